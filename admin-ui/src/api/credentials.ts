@@ -6,8 +6,17 @@ import type {
   SuccessResponse,
   SetDisabledRequest,
   SetPriorityRequest,
+  SetCredentialDailyLimitRequest,
+  SetCanaryRequest,
   AddCredentialRequest,
   AddCredentialResponse,
+  ImportCredentialsRequest,
+  ImportCredentialsResponse,
+  StatsResponse,
+  ArchivedCredentialsResponse,
+  CreatePairingRequest,
+  PairingResponse,
+  RestoreCredentialResponse,
 } from '@/types/api'
 
 // 创建 axios 实例
@@ -57,6 +66,30 @@ export async function setCredentialPriority(
   return data
 }
 
+// 设置凭据级每日请求上限（null 表示不限制）
+export async function setCredentialDailyLimit(
+  id: number,
+  dailyRequestLimit: number | null
+): Promise<SuccessResponse> {
+  const { data } = await api.post<SuccessResponse>(
+    `/credentials/${id}/daily-limit`,
+    { dailyRequestLimit } as SetCredentialDailyLimitRequest
+  )
+  return data
+}
+
+// 设置金丝雀流量百分比（null 表示转正）
+export async function setCredentialCanary(
+  id: number,
+  canaryPercent: number | null
+): Promise<SuccessResponse> {
+  const { data } = await api.post<SuccessResponse>(
+    `/credentials/${id}/canary`,
+    { canaryPercent } as SetCanaryRequest
+  )
+  return data
+}
+
 // 重置失败计数
 export async function resetCredentialFailure(
   id: number
@@ -79,18 +112,62 @@ export async function addCredential(
   return data
 }
 
+// 批量导入凭据（已截断或重复的凭据会被跳过）
+export async function importCredentials(
+  req: ImportCredentialsRequest
+): Promise<ImportCredentialsResponse> {
+  const { data } = await api.post<ImportCredentialsResponse>('/credentials/import', req)
+  return data
+}
+
 // 删除凭据
 export async function deleteCredential(id: number): Promise<SuccessResponse> {
   const { data } = await api.delete<SuccessResponse>(`/credentials/${id}`)
   return data
 }
 
+// 创建一次性配对会话
+export async function createPairing(req: CreatePairingRequest): Promise<PairingResponse> {
+  const { data } = await api.post<PairingResponse>('/credentials/pairing', req)
+  return data
+}
+
+// 查询配对会话状态
+export async function getPairing(token: string): Promise<PairingResponse> {
+  const { data } = await api.get<PairingResponse>(`/credentials/pairing/${token}`)
+  return data
+}
+
+// 获取保留期内的已删除凭据
+export async function getArchivedCredentials(): Promise<ArchivedCredentialsResponse> {
+  const { data } = await api.get<ArchivedCredentialsResponse>('/credentials/archived')
+  return data
+}
+
+// 恢复已删除的凭据（恢复后保持禁用）
+export async function restoreCredential(id: number): Promise<RestoreCredentialResponse> {
+  const { data } = await api.post<RestoreCredentialResponse>(`/credentials/archived/${id}/restore`)
+  return data
+}
+
+// 永久删除已归档的凭据
+export async function purgeArchivedCredential(id: number): Promise<SuccessResponse> {
+  const { data } = await api.delete<SuccessResponse>(`/credentials/archived/${id}`)
+  return data
+}
+
 // 获取负载均衡模式
 export async function getLoadBalancingMode(): Promise<{ mode: 'priority' | 'balanced' }> {
   const { data } = await api.get<{ mode: 'priority' | 'balanced' }>('/config/load-balancing')
   return data
 }
 
+// 获取累计运行指标
+export async function getStats(): Promise<StatsResponse> {
+  const { data } = await api.get<StatsResponse>('/stats')
+  return data
+}
+
 // 设置负载均衡模式
 export async function setLoadBalancingMode(mode: 'priority' | 'balanced'): Promise<{ mode: 'priority' | 'balanced' }> {
   const { data } = await api.put<{ mode: 'priority' | 'balanced' }>('/config/load-balancing', { mode })
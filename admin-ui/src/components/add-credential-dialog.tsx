@@ -28,6 +28,8 @@ export function AddCredentialDialog({ open, onOpenChange }: AddCredentialDialogP
   const [clientSecret, setClientSecret] = useState('')
   const [priority, setPriority] = useState('0')
   const [machineId, setMachineId] = useState('')
+  const [label, setLabel] = useState('')
+  const [notes, setNotes] = useState('')
   const [proxyUrl, setProxyUrl] = useState('')
   const [proxyUsername, setProxyUsername] = useState('')
   const [proxyPassword, setProxyPassword] = useState('')
@@ -43,6 +45,8 @@ export function AddCredentialDialog({ open, onOpenChange }: AddCredentialDialogP
     setClientSecret('')
     setPriority('0')
     setMachineId('')
+    setLabel('')
+    setNotes('')
     setProxyUrl('')
     setProxyUsername('')
     setProxyPassword('')
@@ -73,6 +77,8 @@ export function AddCredentialDialog({ open, onOpenChange }: AddCredentialDialogP
         clientSecret: clientSecret.trim() || undefined,
         priority: parseInt(priority) || 0,
         machineId: machineId.trim() || undefined,
+        label: label.trim() || undefined,
+        notes: notes.trim() || undefined,
         proxyUrl: proxyUrl.trim() || undefined,
         proxyUsername: proxyUsername.trim() || undefined,
         proxyPassword: proxyPassword.trim() || undefined,
@@ -226,6 +232,30 @@ export function AddCredentialDialog({ open, onOpenChange }: AddCredentialDialogP
               </p>
             </div>
 
+            {/* 标签与备注 */}
+            <div className="space-y-2">
+              <label htmlFor="label" className="text-sm font-medium">
+                标签
+              </label>
+              <Input
+                id="label"
+                placeholder="例如：work account, eu-west-1"
+                value={label}
+                onChange={(e) => setLabel(e.target.value)}
+                disabled={isPending}
+              />
+              <Input
+                id="notes"
+                placeholder="备注（可选）"
+                value={notes}
+                onChange={(e) => setNotes(e.target.value)}
+                disabled={isPending}
+              />
+              <p className="text-xs text-muted-foreground">
+                可选，用于在管理面板和日志中识别该凭据对应的上游账号
+              </p>
+            </div>
+
             {/* 代理配置 */}
             <div className="space-y-2">
               <label className="text-sm font-medium">代理配置</label>
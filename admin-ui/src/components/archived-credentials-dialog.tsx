@@ -0,0 +1,124 @@
+import { toast } from 'sonner'
+import { RotateCcw, Trash2 } from 'lucide-react'
+import {
+  Dialog,
+  DialogContent,
+  DialogDescription,
+  DialogHeader,
+  DialogTitle,
+} from '@/components/ui/dialog'
+import { Button } from '@/components/ui/button'
+import {
+  useArchivedCredentials,
+  useRestoreCredential,
+  usePurgeArchivedCredential,
+} from '@/hooks/use-credentials'
+import { extractErrorMessage } from '@/lib/utils'
+
+interface ArchivedCredentialsDialogProps {
+  open: boolean
+  onOpenChange: (open: boolean) => void
+}
+
+export function ArchivedCredentialsDialog({ open, onOpenChange }: ArchivedCredentialsDialogProps) {
+  const { data, isLoading } = useArchivedCredentials(open)
+  const restoreCredential = useRestoreCredential()
+  const purgeCredential = usePurgeArchivedCredential()
+
+  const formatDate = (value: string) => new Date(value).toLocaleString('zh-CN')
+
+  const handleRestore = (id: number) => {
+    restoreCredential.mutate(id, {
+      onSuccess: (res) => {
+        toast.success(res.message)
+      },
+      onError: (err) => {
+        toast.error('恢复失败: ' + extractErrorMessage(err))
+      },
+    })
+  }
+
+  const handlePurge = (id: number) => {
+    if (!confirm(`确定要永久删除凭据 #${id} 吗？此操作无法撤销。`)) {
+      return
+    }
+    purgeCredential.mutate(id, {
+      onSuccess: (res) => {
+        toast.success(res.message)
+      },
+      onError: (err) => {
+        toast.error('删除失败: ' + extractErrorMessage(err))
+      },
+    })
+  }
+
+  const credentials = data?.credentials ?? []
+
+  return (
+    <Dialog open={open} onOpenChange={onOpenChange}>
+      <DialogContent className="sm:max-w-lg">
+        <DialogHeader>
+          <DialogTitle>已删除凭据</DialogTitle>
+          <DialogDescription>
+            {data && data.retentionDays > 0
+              ? `删除的凭据保留 ${data.retentionDays} 天，期间可恢复；恢复后保持禁用，确认无误后手动启用。`
+              : '未启用删除保留（archiveRetentionDays 为 0），删除即永久删除。'}
+          </DialogDescription>
+        </DialogHeader>
+
+        {isLoading && (
+          <div className="flex items-center justify-center py-8">
+            <div className="animate-spin rounded-full h-8 w-8 border-b-2 border-primary"></div>
+          </div>
+        )}
+
+        {!isLoading && credentials.length === 0 && (
+          <div className="py-8 text-center text-sm text-muted-foreground">
+            暂无已删除的凭据
+          </div>
+        )}
+
+        {credentials.length > 0 && (
+          <div className="max-h-96 overflow-y-auto space-y-2">
+            {credentials.map((item) => (
+              <div
+                key={item.id}
+                className="flex items-center justify-between gap-2 rounded-md border p-3 text-sm"
+              >
+                <div className="min-w-0">
+                  <div className="font-medium truncate">
+                    #{item.id} {item.label || item.email || ''}
+                  </div>
+                  <div className="text-xs text-muted-foreground">
+                    删除于 {formatDate(item.deletedAt)}，将于 {formatDate(item.purgeAt)} 自动清除
+                  </div>
+                </div>
+                <div className="flex shrink-0 gap-1">
+                  <Button
+                    size="sm"
+                    variant="outline"
+                    onClick={() => handleRestore(item.id)}
+                    disabled={restoreCredential.isPending}
+                  >
+                    <RotateCcw className="h-4 w-4 mr-1" />
+                    恢复
+                  </Button>
+                  <Button
+                    size="sm"
+                    variant="ghost"
+                    className="text-destructive hover:text-destructive"
+                    onClick={() => handlePurge(item.id)}
+                    disabled={purgeCredential.isPending}
+                    title="永久删除"
+                  >
+                    <Trash2 className="h-4 w-4" />
+                  </Button>
+                </div>
+              </div>
+            ))}
+          </div>
+        )}
+      </DialogContent>
+    </Dialog>
+  )
+}
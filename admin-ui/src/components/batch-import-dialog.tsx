@@ -9,8 +9,9 @@ import {
   DialogFooter,
 } from '@/components/ui/dialog'
 import { Button } from '@/components/ui/button'
-import { useCredentials, useAddCredential, useDeleteCredential } from '@/hooks/use-credentials'
-import { getCredentialBalance, setCredentialDisabled } from '@/api/credentials'
+import { useQueryClient } from '@tanstack/react-query'
+import { useDeleteCredential } from '@/hooks/use-credentials'
+import { getCredentialBalance, importCredentials, setCredentialDisabled } from '@/api/credentials'
 import { extractErrorMessage } from '@/lib/utils'
 
 interface BatchImportDialogProps {
@@ -27,11 +28,13 @@ interface CredentialInput {
   apiRegion?: string
   priority?: number
   machineId?: string
+  label?: string
+  notes?: string
 }
 
 interface VerificationResult {
   index: number
-  status: 'pending' | 'checking' | 'verifying' | 'verified' | 'duplicate' | 'failed'
+  status: 'pending' | 'checking' | 'verifying' | 'verified' | 'duplicate' | 'truncated' | 'failed'
   error?: string
   usage?: string
   email?: string
@@ -54,8 +57,7 @@ export function BatchImportDialog({ open, onOpenChange }: BatchImportDialogProps
   const [currentProcessing, setCurrentProcessing] = useState<string>('')
   const [results, setResults] = useState<VerificationResult[]>([])
 
-  const { data: existingCredentials } = useCredentials()
-  const { mutateAsync: addCredential } = useAddCredential()
+  const queryClient = useQueryClient()
   const { mutateAsync: deleteCredential } = useDeleteCredential()
 
   const rollbackCredential = async (id: number): Promise<{ success: boolean; error?: string }> => {
@@ -107,15 +109,12 @@ export function BatchImportDialog({ open, onOpenChange }: BatchImportDialogProps
       }))
       setResults(initialResults)
 
-      // 3. 检测重复
-      const existingTokenHashes = new Set(
-        existingCredentials?.credentials
-          .map(c => c.refreshTokenHash)
-          .filter((hash): hash is string => Boolean(hash)) || []
-      )
+      // 3. 检测本批次内的重复（与已有凭据的重复由服务端按加盐哈希检测）
+      const existingTokenHashes = new Set<string>()
 
       let successCount = 0
       let duplicateCount = 0
+      let truncatedCount = 0
       let failCount = 0
       let rollbackSuccessCount = 0
       let rollbackFailedCount = 0
@@ -138,14 +137,12 @@ export function BatchImportDialog({ open, onOpenChange }: BatchImportDialogProps
         // 检查重复
         if (existingTokenHashes.has(tokenHash)) {
           duplicateCount++
-          const existingCred = existingCredentials?.credentials.find(c => c.refreshTokenHash === tokenHash)
           setResults(prev => {
             const newResults = [...prev]
             newResults[i] = {
               ...newResults[i],
               status: 'duplicate',
-              error: '该凭据已存在',
-              email: existingCred?.email || undefined
+              error: '该凭据已存在'
             }
             return newResults
           })
@@ -173,24 +170,51 @@ export function BatchImportDialog({ open, onOpenChange }: BatchImportDialogProps
             throw new Error('idc 模式需要同时提供 clientId 和 clientSecret')
           }
 
-          const addedCred = await addCredential({
-            refreshToken: token,
-            authMethod,
-            authRegion: cred.authRegion?.trim() || cred.region?.trim() || undefined,
-            apiRegion: cred.apiRegion?.trim() || undefined,
-            clientId,
-            clientSecret,
-            priority: cred.priority || 0,
-            machineId: cred.machineId?.trim() || undefined,
+          const imported = await importCredentials({
+            credentials: [{
+              refreshToken: token,
+              authMethod,
+              authRegion: cred.authRegion?.trim() || cred.region?.trim() || undefined,
+              apiRegion: cred.apiRegion?.trim() || undefined,
+              clientId,
+              clientSecret,
+              priority: cred.priority || 0,
+              machineId: cred.machineId?.trim() || undefined,
+              label: cred.label?.trim() || undefined,
+              notes: cred.notes?.trim() || undefined,
+            }],
           })
+          queryClient.invalidateQueries({ queryKey: ['credentials'] })
+          const addedCred = imported.results[0]
+
+          // 服务端跳过的凭据（refreshToken 已截断或重复）不计为失败，也无需回滚
+          if (addedCred.status === 'truncated' || addedCred.status === 'duplicate') {
+            const skippedStatus = addedCred.status
+            if (skippedStatus === 'truncated') {
+              truncatedCount++
+            } else {
+              duplicateCount++
+            }
+            setResults(prev => {
+              const newResults = [...prev]
+              newResults[i] = { ...newResults[i], status: skippedStatus, error: addedCred.message }
+              return newResults
+            })
+            setProgress({ current: i + 1, total: credentials.length })
+            continue
+          }
+          if (addedCred.status !== 'imported' || addedCred.credentialId === undefined) {
+            throw new Error(addedCred.message)
+          }
 
-          addedCredId = addedCred.credentialId
+          const credentialId = addedCred.credentialId
+          addedCredId = credentialId
 
           // 延迟 1 秒
           await new Promise(resolve => setTimeout(resolve, 1000))
 
           // 验活
-          const balance = await getCredentialBalance(addedCred.credentialId)
+          const balance = await getCredentialBalance(credentialId)
 
           // 验活成功
           successCount++
@@ -203,7 +227,7 @@ export function BatchImportDialog({ open, onOpenChange }: BatchImportDialogProps
               status: 'verified',
               usage: `${balance.currentUsage}/${balance.usageLimit}`,
               email: addedCred.email || undefined,
-              credentialId: addedCred.credentialId
+              credentialId
             }
             return newResults
           })
@@ -245,13 +269,14 @@ export function BatchImportDialog({ open, onOpenChange }: BatchImportDialogProps
       }
 
       // 显示结果
-      if (failCount === 0 && duplicateCount === 0) {
+      if (failCount === 0 && duplicateCount === 0 && truncatedCount === 0) {
         toast.success(`成功导入并验活 ${successCount} 个凭据`)
       } else {
         const failureSummary = failCount > 0
           ? `，失败 ${failCount} 个（已排除 ${rollbackSuccessCount}，未排除 ${rollbackFailedCount}，无需排除 ${rollbackSkippedCount}）`
           : ''
-        toast.info(`验活完成：成功 ${successCount} 个，重复 ${duplicateCount} 个${failureSummary}`)
+        const truncatedSummary = truncatedCount > 0 ? `，已截断 ${truncatedCount} 个` : ''
+        toast.info(`验活完成：成功 ${successCount} 个，重复 ${duplicateCount} 个${truncatedSummary}${failureSummary}`)
 
         if (rollbackFailedCount > 0) {
           toast.warning(`有 ${rollbackFailedCount} 个失败凭据回滚未完成，请手动禁用并删除`)
@@ -274,6 +299,7 @@ export function BatchImportDialog({ open, onOpenChange }: BatchImportDialogProps
       case 'verified':
         return <CheckCircle2 className="w-5 h-5 text-green-500" />
       case 'duplicate':
+      case 'truncated':
         return <AlertCircle className="w-5 h-5 text-yellow-500" />
       case 'failed':
         return <XCircle className="w-5 h-5 text-red-500" />
@@ -292,6 +318,8 @@ export function BatchImportDialog({ open, onOpenChange }: BatchImportDialogProps
         return '验活成功'
       case 'duplicate':
         return '重复凭据'
+      case 'truncated':
+        return 'refreshToken 已截断（已跳过）'
       case 'failed':
         if (result.rollbackStatus === 'success') return '验活失败（已排除）'
         if (result.rollbackStatus === 'failed') return '验活失败（未排除）'
@@ -361,6 +389,9 @@ export function BatchImportDialog({ open, onOpenChange }: BatchImportDialogProps
                 <span className="text-yellow-600 dark:text-yellow-400">
                   ⚠ 重复: {results.filter(r => r.status === 'duplicate').length}
                 </span>
+                <span className="text-yellow-600 dark:text-yellow-400">
+                  ⚠ 已截断: {results.filter(r => r.status === 'truncated').length}
+                </span>
                 <span className="text-red-600 dark:text-red-400">
                   ✗ 失败: {results.filter(r => r.status === 'failed').length}
                 </span>
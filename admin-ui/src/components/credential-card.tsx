@@ -19,6 +19,8 @@ import type { CredentialStatusItem, BalanceResponse } from '@/types/api'
 import {
   useSetDisabled,
   useSetPriority,
+  useSetDailyLimit,
+  useSetCanary,
   useResetFailure,
   useDeleteCredential,
 } from '@/hooks/use-credentials'
@@ -48,6 +50,22 @@ function formatLastUsed(lastUsedAt: string | null): string {
   return `${days} 天前`
 }
 
+const FAILURE_LABELS: Record<string, string> = {
+  network: '网络错误',
+  throttled: '上游限流',
+  upstream: '上游错误',
+  auth: '凭据错误',
+  quota_exhausted: '额度用尽',
+}
+
+const UNAVAILABLE_LABELS: Record<string, string> = {
+  disabled: '已手动禁用',
+  too_many_failures: '连续失败已自动禁用',
+  quota_exceeded: '额度用尽已禁用',
+  daily_limit: '今日请求已达上限',
+  pool_daily_limit: '所在凭据池今日请求已达上限',
+}
+
 export function CredentialCard({
   credential,
   onViewBalance,
@@ -58,10 +76,20 @@ export function CredentialCard({
 }: CredentialCardProps) {
   const [editingPriority, setEditingPriority] = useState(false)
   const [priorityValue, setPriorityValue] = useState(String(credential.priority))
+  const [editingDailyLimit, setEditingDailyLimit] = useState(false)
+  const [dailyLimitValue, setDailyLimitValue] = useState(
+    credential.dailyRequestLimit == null ? '' : String(credential.dailyRequestLimit)
+  )
+  const [editingCanary, setEditingCanary] = useState(false)
+  const [canaryValue, setCanaryValue] = useState(
+    credential.canaryPercent == null ? '' : String(credential.canaryPercent)
+  )
   const [showDeleteDialog, setShowDeleteDialog] = useState(false)
 
   const setDisabled = useSetDisabled()
   const setPriority = useSetPriority()
+  const setDailyLimit = useSetDailyLimit()
+  const setCanary = useSetCanary()
   const resetFailure = useResetFailure()
   const deleteCredential = useDeleteCredential()
 
@@ -99,6 +127,49 @@ export function CredentialCard({
     )
   }
 
+  const handleDailyLimitChange = () => {
+    const trimmed = dailyLimitValue.trim()
+    const newLimit = trimmed === '' ? null : parseInt(trimmed, 10)
+    if (newLimit !== null && (isNaN(newLimit) || newLimit < 0)) {
+      toast.error('每日上限必须是非负整数，留空表示不限制')
+      return
+    }
+    setDailyLimit.mutate(
+      { id: credential.id, dailyRequestLimit: newLimit },
+      {
+        onSuccess: (res) => {
+          toast.success(res.message)
+          setEditingDailyLimit(false)
+        },
+        onError: (err) => {
+          toast.error('操作失败: ' + (err as Error).message)
+        },
+      }
+    )
+  }
+
+  const handleCanaryChange = (value: string) => {
+    const trimmed = value.trim()
+    const newPercent = trimmed === '' ? null : parseInt(trimmed, 10)
+    if (newPercent !== null && (isNaN(newPercent) || newPercent < 0 || newPercent > 100)) {
+      toast.error('金丝雀流量必须是 0-100 的整数，留空表示转正')
+      return
+    }
+    setCanary.mutate(
+      { id: credential.id, canaryPercent: newPercent },
+      {
+        onSuccess: (res) => {
+          toast.success(res.message)
+          setEditingCanary(false)
+          setCanaryValue(newPercent == null ? '' : String(newPercent))
+        },
+        onError: (err) => {
+          toast.error('操作失败: ' + (err as Error).message)
+        },
+      }
+    )
+  }
+
   const handleReset = () => {
     resetFailure.mutate(credential.id, {
       onSuccess: (res) => {
@@ -139,13 +210,19 @@ export function CredentialCard({
                 onCheckedChange={onToggleSelect}
               />
               <CardTitle className="text-lg flex items-center gap-2">
-                {credential.email || `凭据 #${credential.id}`}
+                {credential.label || credential.email || `凭据 #${credential.id}`}
                 {credential.isCurrent && (
                   <Badge variant="success">当前</Badge>
                 )}
                 {credential.disabled && (
                   <Badge variant="destructive">已禁用</Badge>
                 )}
+                {credential.readOnly && (
+                  <Badge variant="secondary">只读</Badge>
+                )}
+                {credential.canaryPercent != null && (
+                  <Badge variant="secondary">金丝雀 {credential.canaryPercent}%</Badge>
+                )}
               </CardTitle>
             </div>
             <div className="flex items-center gap-2">
@@ -221,6 +298,124 @@ export function CredentialCard({
               <span className="text-muted-foreground">成功次数：</span>
               <span className="font-medium">{credential.successCount}</span>
             </div>
+            <div className="col-span-2">
+              <span className="text-muted-foreground">今日请求：</span>
+              {editingDailyLimit ? (
+                <div className="inline-flex items-center gap-1 ml-1">
+                  <span className="font-medium">{credential.requestsToday} /</span>
+                  <Input
+                    type="number"
+                    value={dailyLimitValue}
+                    onChange={(e) => setDailyLimitValue(e.target.value)}
+                    className="w-20 h-7 text-sm"
+                    min="0"
+                    placeholder="不限制"
+                  />
+                  <Button
+                    size="sm"
+                    variant="ghost"
+                    className="h-7 w-7 p-0"
+                    onClick={handleDailyLimitChange}
+                    disabled={setDailyLimit.isPending}
+                  >
+                    ✓
+                  </Button>
+                  <Button
+                    size="sm"
+                    variant="ghost"
+                    className="h-7 w-7 p-0"
+                    onClick={() => {
+                      setEditingDailyLimit(false)
+                      setDailyLimitValue(
+                        credential.dailyRequestLimit == null
+                          ? ''
+                          : String(credential.dailyRequestLimit)
+                      )
+                    }}
+                  >
+                    ✕
+                  </Button>
+                </div>
+              ) : (
+                <span
+                  className="font-medium cursor-pointer hover:underline ml-1"
+                  onClick={() => setEditingDailyLimit(true)}
+                >
+                  <span
+                    className={
+                      credential.dailyRequestLimit != null &&
+                      credential.requestsToday >= credential.dailyRequestLimit
+                        ? 'text-red-500'
+                        : ''
+                    }
+                  >
+                    {credential.requestsToday} / {credential.dailyRequestLimit ?? '不限'}
+                  </span>
+                  <span className="text-xs text-muted-foreground ml-1">(点击编辑上限)</span>
+                </span>
+              )}
+            </div>
+            <div className="col-span-2">
+              <span className="text-muted-foreground">金丝雀流量：</span>
+              {editingCanary ? (
+                <div className="inline-flex items-center gap-1 ml-1">
+                  <Input
+                    type="number"
+                    value={canaryValue}
+                    onChange={(e) => setCanaryValue(e.target.value)}
+                    className="w-20 h-7 text-sm"
+                    min="0"
+                    max="100"
+                    placeholder="常规轮换"
+                  />
+                  <span className="font-medium">%</span>
+                  <Button
+                    size="sm"
+                    variant="ghost"
+                    className="h-7 w-7 p-0"
+                    onClick={() => handleCanaryChange(canaryValue)}
+                    disabled={setCanary.isPending}
+                  >
+                    ✓
+                  </Button>
+                  <Button
+                    size="sm"
+                    variant="ghost"
+                    className="h-7 w-7 p-0"
+                    onClick={() => {
+                      setEditingCanary(false)
+                      setCanaryValue(
+                        credential.canaryPercent == null ? '' : String(credential.canaryPercent)
+                      )
+                    }}
+                  >
+                    ✕
+                  </Button>
+                </div>
+              ) : (
+                <span className="ml-1">
+                  <span
+                    className="font-medium cursor-pointer hover:underline"
+                    onClick={() => setEditingCanary(true)}
+                  >
+                    {credential.canaryPercent == null
+                      ? '常规轮换'
+                      : `${credential.canaryPercent}%`}
+                  </span>
+                  {credential.canaryPercent != null && (
+                    <Button
+                      size="sm"
+                      variant="ghost"
+                      className="h-6 px-2 ml-1 text-xs"
+                      onClick={() => handleCanaryChange('')}
+                      disabled={setCanary.isPending}
+                    >
+                      转正
+                    </Button>
+                  )}
+                </span>
+              )}
+            </div>
             <div className="col-span-2">
               <span className="text-muted-foreground">最后调用：</span>
               <span className="font-medium">{formatLastUsed(credential.lastUsedAt)}</span>
@@ -242,6 +437,44 @@ export function CredentialCard({
                 <span className="text-sm text-muted-foreground ml-1">未知</span>
               )}
             </div>
+            {credential.label && credential.email && (
+              <div className="col-span-2">
+                <span className="text-muted-foreground">邮箱：</span>
+                <span className="font-medium">{credential.email}</span>
+              </div>
+            )}
+            {credential.notes && (
+              <div className="col-span-2">
+                <span className="text-muted-foreground">备注：</span>
+                <span className="font-medium whitespace-pre-wrap">{credential.notes}</span>
+              </div>
+            )}
+            {credential.unavailableReason && (
+              <div className="col-span-2">
+                <span className="text-muted-foreground">不可用原因：</span>
+                <span className="font-medium text-red-500">
+                  {UNAVAILABLE_LABELS[credential.unavailableReason]}
+                </span>
+                {credential.availableAt && (
+                  <span className="text-xs text-muted-foreground ml-1">
+                    （预计 {new Date(credential.availableAt).toLocaleString('zh-CN')} 恢复）
+                  </span>
+                )}
+              </div>
+            )}
+            {credential.lastFailure && (
+              <div className="col-span-2">
+                <span className="text-muted-foreground">最近失败：</span>
+                <span className="font-medium">
+                  {FAILURE_LABELS[credential.lastFailure]}（{formatLastUsed(credential.lastFailureAt)}）
+                </span>
+                {credential.backoffLevel > 0 && (
+                  <span className="text-xs text-muted-foreground ml-1">
+                    退避级别 {credential.backoffLevel}
+                  </span>
+                )}
+              </div>
+            )}
             {credential.hasProxy && (
               <div className="col-span-2">
                 <span className="text-muted-foreground">代理：</span>
@@ -330,7 +563,7 @@ export function CredentialCard({
           <DialogHeader>
             <DialogTitle>确认删除凭据</DialogTitle>
             <DialogDescription>
-              您确定要删除凭据 #{credential.id} 吗？此操作无法撤销。
+              您确定要删除凭据 #{credential.id} 吗？删除后可在保留期内从「已删除凭据」中恢复。
             </DialogDescription>
           </DialogHeader>
           <DialogFooter>
@@ -1,5 +1,5 @@
 import { useState, useEffect, useRef } from 'react'
-import { RefreshCw, LogOut, Moon, Sun, Server, Plus, Upload, Trash2, RotateCcw, CheckCircle2 } from 'lucide-react'
+import { RefreshCw, LogOut, Moon, Sun, Server, Plus, Upload, Trash2, RotateCcw, CheckCircle2, Archive, Link2 } from 'lucide-react'
 import { useQueryClient } from '@tanstack/react-query'
 import { toast } from 'sonner'
 import { storage } from '@/lib/storage'
@@ -10,8 +10,10 @@ import { CredentialCard } from '@/components/credential-card'
 import { BalanceDialog } from '@/components/balance-dialog'
 import { AddCredentialDialog } from '@/components/add-credential-dialog'
 import { BatchImportDialog } from '@/components/batch-import-dialog'
+import { ArchivedCredentialsDialog } from '@/components/archived-credentials-dialog'
+import { PairingDialog } from '@/components/pairing-dialog'
 import { BatchVerifyDialog, type VerifyResult } from '@/components/batch-verify-dialog'
-import { useCredentials, useDeleteCredential, useResetFailure, useLoadBalancingMode, useSetLoadBalancingMode } from '@/hooks/use-credentials'
+import { useCredentials, useDeleteCredential, useResetFailure, useLoadBalancingMode, useSetLoadBalancingMode, useStats } from '@/hooks/use-credentials'
 import { getCredentialBalance } from '@/api/credentials'
 import { extractErrorMessage } from '@/lib/utils'
 import type { BalanceResponse } from '@/types/api'
@@ -25,6 +27,8 @@ export function Dashboard({ onLogout }: DashboardProps) {
   const [balanceDialogOpen, setBalanceDialogOpen] = useState(false)
   const [addDialogOpen, setAddDialogOpen] = useState(false)
   const [batchImportDialogOpen, setBatchImportDialogOpen] = useState(false)
+  const [archivedDialogOpen, setArchivedDialogOpen] = useState(false)
+  const [pairingDialogOpen, setPairingDialogOpen] = useState(false)
   const [selectedIds, setSelectedIds] = useState<Set<number>>(new Set())
   const [verifyDialogOpen, setVerifyDialogOpen] = useState(false)
   const [verifying, setVerifying] = useState(false)
@@ -49,6 +53,7 @@ export function Dashboard({ onLogout }: DashboardProps) {
   const { mutate: deleteCredential } = useDeleteCredential()
   const { mutate: resetFailure } = useResetFailure()
   const { data: loadBalancingData, isLoading: isLoadingMode } = useLoadBalancingMode()
+  const { data: statsData } = useStats()
   const { mutate: setLoadBalancingMode, isPending: isSettingMode } = useSetLoadBalancingMode()
 
   // 计算分页
@@ -157,7 +162,7 @@ export function Dashboard({ onLogout }: DashboardProps) {
     const skippedCount = selectedIds.size - disabledIds.length
     const skippedText = skippedCount > 0 ? `（将跳过 ${skippedCount} 个未禁用凭据）` : ''
 
-    if (!confirm(`确定要删除 ${disabledIds.length} 个已禁用凭据吗？此操作无法撤销。${skippedText}`)) {
+    if (!confirm(`确定要删除 ${disabledIds.length} 个已禁用凭据吗？删除后可在保留期内从「已删除凭据」中恢复。${skippedText}`)) {
       return
     }
 
@@ -256,7 +261,7 @@ export function Dashboard({ onLogout }: DashboardProps) {
       return
     }
 
-    if (!confirm(`确定要清除所有 ${disabledCredentials.length} 个已禁用凭据吗？此操作无法撤销。`)) {
+    if (!confirm(`确定要清除所有 ${disabledCredentials.length} 个已禁用凭据吗？删除后可在保留期内从「已删除凭据」中恢复。`)) {
       return
     }
 
@@ -521,7 +526,7 @@ export function Dashboard({ onLogout }: DashboardProps) {
       {/* 主内容 */}
       <main className="container mx-auto px-4 md:px-8 py-6">
         {/* 统计卡片 */}
-        <div className="grid gap-4 md:grid-cols-3 mb-6">
+        <div className="grid gap-4 md:grid-cols-4 mb-6">
           <Card>
             <CardHeader className="pb-2">
               <CardTitle className="text-sm font-medium text-muted-foreground">
@@ -555,6 +560,22 @@ export function Dashboard({ onLogout }: DashboardProps) {
               </div>
             </CardContent>
           </Card>
+          <Card>
+            <CardHeader className="pb-2">
+              <CardTitle className="text-sm font-medium text-muted-foreground">
+                累计请求
+              </CardTitle>
+            </CardHeader>
+            <CardContent>
+              <div className="text-2xl font-bold">{statsData?.requestsTotal ?? 0}</div>
+              <p className="text-xs text-muted-foreground">
+                失败 {statsData?.failuresTotal ?? 0} · 输入 {statsData?.inputTokensTotal ?? 0} / 输出 {statsData?.outputTokensTotal ?? 0} tokens
+                {!!statsData?.backpressureEventsTotal && ` · 背压 ${statsData.backpressureEventsTotal} 次`}
+                {!!statsData?.conversion.latencyUs.count &&
+                  ` · 转换 p99 ${(statsData.conversion.latencyUs.p99 / 1000).toFixed(1)} ms`}
+              </p>
+            </CardContent>
+          </Card>
         </div>
 
         {/* 凭据列表 */}
@@ -624,10 +645,18 @@ export function Dashboard({ onLogout }: DashboardProps) {
                   清除已禁用
                 </Button>
               )}
+              <Button onClick={() => setArchivedDialogOpen(true)} size="sm" variant="outline">
+                <Archive className="h-4 w-4 mr-2" />
+                已删除凭据
+              </Button>
               <Button onClick={() => setBatchImportDialogOpen(true)} size="sm" variant="outline">
                 <Upload className="h-4 w-4 mr-2" />
                 批量导入
               </Button>
+              <Button onClick={() => setPairingDialogOpen(true)} size="sm" variant="outline">
+                <Link2 className="h-4 w-4 mr-2" />
+                配对导入
+              </Button>
               <Button onClick={() => setAddDialogOpen(true)} size="sm">
                 <Plus className="h-4 w-4 mr-2" />
                 添加凭据
@@ -704,6 +733,18 @@ export function Dashboard({ onLogout }: DashboardProps) {
         onOpenChange={setBatchImportDialogOpen}
       />
 
+      {/* 配对导入对话框 */}
+      <PairingDialog
+        open={pairingDialogOpen}
+        onOpenChange={setPairingDialogOpen}
+      />
+
+      {/* 已删除凭据对话框 */}
+      <ArchivedCredentialsDialog
+        open={archivedDialogOpen}
+        onOpenChange={setArchivedDialogOpen}
+      />
+
       {/* 批量验活对话框 */}
       <BatchVerifyDialog
         open={verifyDialogOpen}
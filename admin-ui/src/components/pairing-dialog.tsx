@@ -0,0 +1,170 @@
+import { useEffect, useState } from 'react'
+import { toast } from 'sonner'
+import { useQueryClient } from '@tanstack/react-query'
+import { CheckCircle2, Copy } from 'lucide-react'
+import {
+  Dialog,
+  DialogContent,
+  DialogDescription,
+  DialogHeader,
+  DialogTitle,
+  DialogFooter,
+} from '@/components/ui/dialog'
+import { Button } from '@/components/ui/button'
+import { Input } from '@/components/ui/input'
+import { createPairing, getPairing } from '@/api/credentials'
+import { extractErrorMessage } from '@/lib/utils'
+import type { PairingResponse } from '@/types/api'
+
+interface PairingDialogProps {
+  open: boolean
+  onOpenChange: (open: boolean) => void
+}
+
+export function PairingDialog({ open, onOpenChange }: PairingDialogProps) {
+  const queryClient = useQueryClient()
+  const [priority, setPriority] = useState('0')
+  const [label, setLabel] = useState('')
+  const [pairing, setPairing] = useState<PairingResponse | null>(null)
+  const [creating, setCreating] = useState(false)
+
+  // 等待辅助脚本上传，完成后刷新凭据列表
+  useEffect(() => {
+    if (!open || !pairing || pairing.status === 'completed') return
+    const timer = setInterval(async () => {
+      try {
+        const latest = await getPairing(pairing.token)
+        setPairing(latest)
+        if (latest.status === 'completed') {
+          toast.success(`凭据 #${latest.credentialId} 已通过配对导入`)
+          queryClient.invalidateQueries({ queryKey: ['credentials'] })
+        }
+      } catch {
+        // 令牌过期后停止轮询
+        setPairing(null)
+        toast.error('配对已过期，请重新生成')
+      }
+    }, 2000)
+    return () => clearInterval(timer)
+  }, [open, pairing, queryClient])
+
+  const handleCreate = async () => {
+    setCreating(true)
+    try {
+      const response = await createPairing({
+        priority: parseInt(priority) || 0,
+        label: label.trim() || undefined,
+      })
+      setPairing(response)
+    } catch (error) {
+      toast.error('生成配对失败: ' + extractErrorMessage(error))
+    } finally {
+      setCreating(false)
+    }
+  }
+
+  const handleOpenChange = (newOpen: boolean) => {
+    if (!newOpen) {
+      setPairing(null)
+      setPriority('0')
+      setLabel('')
+    }
+    onOpenChange(newOpen)
+  }
+
+  const origin = window.location.origin
+  const command = pairing
+    ? `curl -fsSL ${origin}${pairing.scriptPath} | python3 - ${origin}${pairing.uploadPath}`
+    : ''
+
+  const handleCopy = async () => {
+    try {
+      await navigator.clipboard.writeText(command)
+      toast.success('命令已复制')
+    } catch {
+      toast.error('复制失败，请手动选择命令复制')
+    }
+  }
+
+  return (
+    <Dialog open={open} onOpenChange={handleOpenChange}>
+      <DialogContent className="sm:max-w-lg">
+        <DialogHeader>
+          <DialogTitle>配对导入</DialogTitle>
+          <DialogDescription>
+            生成一次性上传地址，在安装了 Kiro IDE 的机器上运行命令，
+            凭据会直接上传到服务端校验并导入，无需手动复制 refreshToken。
+          </DialogDescription>
+        </DialogHeader>
+
+        {!pairing && (
+          <div className="space-y-4">
+            <div className="space-y-2">
+              <label htmlFor="pairingPriority" className="text-sm font-medium">
+                优先级
+              </label>
+              <Input
+                id="pairingPriority"
+                type="number"
+                min="0"
+                value={priority}
+                onChange={(e) => setPriority(e.target.value)}
+                disabled={creating}
+              />
+            </div>
+            <div className="space-y-2">
+              <label htmlFor="pairingLabel" className="text-sm font-medium">
+                标签
+              </label>
+              <Input
+                id="pairingLabel"
+                placeholder="可选，如 work laptop"
+                value={label}
+                onChange={(e) => setLabel(e.target.value)}
+                disabled={creating}
+              />
+            </div>
+          </div>
+        )}
+
+        {pairing && pairing.status !== 'completed' && (
+          <div className="space-y-3">
+            <div className="text-sm">在 Kiro IDE 所在机器上运行（需要 Python 3）：</div>
+            <div className="rounded-md bg-muted p-3 font-mono text-xs break-all select-all">
+              {command}
+            </div>
+            <div className="flex items-center justify-between text-xs text-muted-foreground">
+              <span>
+                {pairing.status === 'importing' ? '正在校验并导入...' : '等待上传...'}
+                有效期至 {new Date(pairing.expiresAt).toLocaleString('zh-CN')}
+              </span>
+              <Button size="sm" variant="outline" onClick={handleCopy}>
+                <Copy className="h-4 w-4 mr-1" />
+                复制
+              </Button>
+            </div>
+          </div>
+        )}
+
+        {pairing?.status === 'completed' && (
+          <div className="flex items-center justify-center gap-2 py-6 text-green-600">
+            <CheckCircle2 className="h-5 w-5" />
+            <span className="font-medium">凭据 #{pairing.credentialId} 已导入</span>
+          </div>
+        )}
+
+        <DialogFooter>
+          {!pairing ? (
+            <Button onClick={handleCreate} disabled={creating}>
+              {creating ? '生成中...' : '生成配对命令'}
+            </Button>
+          ) : (
+            <Button variant="outline" onClick={() => handleOpenChange(false)}>
+              关闭
+            </Button>
+          )}
+        </DialogFooter>
+      </DialogContent>
+    </Dialog>
+  )
+}
@@ -3,12 +3,18 @@ import {
   getCredentials,
   setCredentialDisabled,
   setCredentialPriority,
+  setCredentialDailyLimit,
+  setCredentialCanary,
   resetCredentialFailure,
   getCredentialBalance,
   addCredential,
   deleteCredential,
+  getArchivedCredentials,
+  restoreCredential,
+  purgeArchivedCredential,
   getLoadBalancingMode,
   setLoadBalancingMode,
+  getStats,
 } from '@/api/credentials'
 import type { AddCredentialRequest } from '@/types/api'
 
@@ -21,6 +27,15 @@ export function useCredentials() {
   })
 }
 
+// 查询累计运行指标
+export function useStats() {
+  return useQuery({
+    queryKey: ['stats'],
+    queryFn: getStats,
+    refetchInterval: 30000,
+  })
+}
+
 // 查询凭据余额
 export function useCredentialBalance(id: number | null) {
   return useQuery({
@@ -55,6 +70,30 @@ export function useSetPriority() {
   })
 }
 
+// 设置凭据级每日请求上限
+export function useSetDailyLimit() {
+  const queryClient = useQueryClient()
+  return useMutation({
+    mutationFn: ({ id, dailyRequestLimit }: { id: number; dailyRequestLimit: number | null }) =>
+      setCredentialDailyLimit(id, dailyRequestLimit),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ['credentials'] })
+    },
+  })
+}
+
+// 设置金丝雀流量百分比
+export function useSetCanary() {
+  const queryClient = useQueryClient()
+  return useMutation({
+    mutationFn: ({ id, canaryPercent }: { id: number; canaryPercent: number | null }) =>
+      setCredentialCanary(id, canaryPercent),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ['credentials'] })
+    },
+  })
+}
+
 // 重置失败计数
 export function useResetFailure() {
   const queryClient = useQueryClient()
@@ -84,6 +123,39 @@ export function useDeleteCredential() {
     mutationFn: (id: number) => deleteCredential(id),
     onSuccess: () => {
       queryClient.invalidateQueries({ queryKey: ['credentials'] })
+      queryClient.invalidateQueries({ queryKey: ['archived-credentials'] })
+    },
+  })
+}
+
+// 查询已删除凭据
+export function useArchivedCredentials(enabled: boolean) {
+  return useQuery({
+    queryKey: ['archived-credentials'],
+    queryFn: getArchivedCredentials,
+    enabled,
+  })
+}
+
+// 恢复已删除的凭据
+export function useRestoreCredential() {
+  const queryClient = useQueryClient()
+  return useMutation({
+    mutationFn: (id: number) => restoreCredential(id),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ['credentials'] })
+      queryClient.invalidateQueries({ queryKey: ['archived-credentials'] })
+    },
+  })
+}
+
+// 永久删除已归档的凭据
+export function usePurgeArchivedCredential() {
+  const queryClient = useQueryClient()
+  return useMutation({
+    mutationFn: (id: number) => purgeArchivedCredential(id),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ['archived-credentials'] })
     },
   })
 }
@@ -17,11 +17,28 @@ export interface CredentialStatusItem {
   authMethod: string | null
   hasProfileArn: boolean
   email?: string
+  label?: string
+  notes?: string
   refreshTokenHash?: string
   successCount: number
   lastUsedAt: string | null
   hasProxy: boolean
   proxyUrl?: string
+  readOnly: boolean
+  requestsToday: number
+  dailyRequestLimit: number | null
+  canaryPercent: number | null
+  backoffLevel: number
+  lastFailure: 'network' | 'throttled' | 'upstream' | 'auth' | 'quota_exhausted' | null
+  lastFailureAt: string | null
+  unavailableReason:
+    | 'disabled'
+    | 'too_many_failures'
+    | 'quota_exceeded'
+    | 'daily_limit'
+    | 'pool_daily_limit'
+    | null
+  availableAt: string | null
 }
 
 // 余额响应
@@ -58,6 +75,14 @@ export interface SetPriorityRequest {
   priority: number
 }
 
+export interface SetCredentialDailyLimitRequest {
+  dailyRequestLimit: number | null
+}
+
+export interface SetCanaryRequest {
+  canaryPercent: number | null
+}
+
 // 添加凭据请求
 export interface AddCredentialRequest {
   refreshToken: string
@@ -68,9 +93,13 @@ export interface AddCredentialRequest {
   authRegion?: string
   apiRegion?: string
   machineId?: string
+  label?: string
+  notes?: string
   proxyUrl?: string
   proxyUsername?: string
   proxyPassword?: string
+  dailyRequestLimit?: number
+  canaryPercent?: number
 }
 
 // 添加凭据响应
@@ -80,3 +109,92 @@ export interface AddCredentialResponse {
   credentialId: number
   email?: string
 }
+
+// 批量导入凭据请求
+export interface ImportCredentialsRequest {
+  credentials: AddCredentialRequest[]
+}
+
+// 单个导入条目的结果
+export interface ImportItemResult {
+  index: number
+  status: 'imported' | 'truncated' | 'duplicate' | 'failed'
+  credentialId?: number
+  email?: string
+  message: string
+}
+
+// 批量导入凭据响应
+export interface ImportCredentialsResponse {
+  imported: number
+  skipped: number
+  failed: number
+  results: ImportItemResult[]
+}
+
+// 创建配对请求
+export interface CreatePairingRequest {
+  priority?: number
+  label?: string
+}
+
+// 配对会话
+export interface PairingResponse {
+  token: string
+  uploadPath: string
+  scriptPath: string
+  expiresAt: string
+  status: 'pending' | 'importing' | 'completed'
+  credentialId?: number
+}
+
+// 已删除（归档）的凭据
+export interface ArchivedCredentialItem {
+  id: number
+  email?: string
+  label?: string
+  authMethod?: string
+  priority: number
+  deletedAt: string
+  purgeAt: string
+}
+
+// 已删除凭据列表响应
+export interface ArchivedCredentialsResponse {
+  retentionDays: number
+  credentials: ArchivedCredentialItem[]
+}
+
+// 恢复凭据响应
+export interface RestoreCredentialResponse {
+  success: boolean
+  message: string
+  credentialId: number
+}
+
+// 直方图快照（buckets 为累计计数，le 为 null 表示 +Inf）
+export interface HistogramSnapshot {
+  count: number
+  sum: number
+  max: number
+  p50: number
+  p99: number
+  buckets: { le: number | null; count: number }[]
+}
+
+// 请求转换指标（仅当前进程）
+export interface ConversionMetrics {
+  latencyUs: HistogramSnapshot
+  passes: Record<string, { latencyUs: HistogramSnapshot; savedBytes: HistogramSnapshot }>
+}
+
+// 累计运行指标（跨重启持续累计）
+export interface StatsResponse {
+  requestsTotal: number
+  failuresTotal: number
+  inputTokensTotal: number
+  outputTokensTotal: number
+  backpressureEventsTotal: number
+  conversion: ConversionMetrics
+  since: string | null
+}
@@ -0,0 +1,7 @@
+//! 构建脚本：记录编译目标，供 `--version` 输出区分各平台的发布二进制
+
+fn main() {
+    let target = std::env::var("TARGET").unwrap_or_default();
+    println!("cargo:rustc-env=KIRO_BUILD_TARGET={}", target);
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -4,6 +4,8 @@ use std::fmt;
 
 use axum::http::StatusCode;
 
+use crate::common::i18n;
+
 use super::types::AdminErrorResponse;
 
 /// Admin 服务错误类型
@@ -20,17 +22,74 @@ pub enum AdminServiceError {
 
     /// 凭据无效（验证失败）
     InvalidCredential(String),
+
+    /// 配对令牌不存在、已过期或已使用
+    PairingNotFound,
+
+    /// 调试转储不存在
+    DumpNotFound(String),
+
+    /// 请求内容无效
+    #[cfg_attr(not(feature = "compression"), allow(dead_code))]
+    InvalidRequest(String),
 }
 
 impl fmt::Display for AdminServiceError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AdminServiceError::NotFound { id } => {
-                write!(f, "凭据不存在: {}", id)
+                write!(
+                    f,
+                    "{}: {}",
+                    i18n::pick("凭据不存在", "Credential not found"),
+                    id
+                )
+            }
+            AdminServiceError::UpstreamError(msg) => {
+                write!(
+                    f,
+                    "{}: {}",
+                    i18n::pick("上游服务错误", "Upstream error"),
+                    msg
+                )
+            }
+            AdminServiceError::InternalError(msg) => {
+                write!(f, "{}: {}", i18n::pick("内部错误", "Internal error"), msg)
+            }
+            AdminServiceError::InvalidCredential(msg) => {
+                write!(
+                    f,
+                    "{}: {}",
+                    i18n::pick("凭据无效", "Invalid credential"),
+                    msg
+                )
+            }
+            AdminServiceError::PairingNotFound => {
+                write!(
+                    f,
+                    "{}",
+                    i18n::pick(
+                        "配对令牌不存在、已过期或已使用",
+                        "Pairing token not found, expired or already used"
+                    )
+                )
+            }
+            AdminServiceError::DumpNotFound(name) => {
+                write!(
+                    f,
+                    "{}: {}",
+                    i18n::pick("调试转储不存在", "Debug dump not found"),
+                    name
+                )
+            }
+            AdminServiceError::InvalidRequest(msg) => {
+                write!(
+                    f,
+                    "{}: {}",
+                    i18n::pick("请求内容无效", "Invalid request"),
+                    msg
+                )
             }
-            AdminServiceError::UpstreamError(msg) => write!(f, "上游服务错误: {}", msg),
-            AdminServiceError::InternalError(msg) => write!(f, "内部错误: {}", msg),
-            AdminServiceError::InvalidCredential(msg) => write!(f, "凭据无效: {}", msg),
         }
     }
 }
@@ -45,18 +104,23 @@ impl AdminServiceError {
             AdminServiceError::UpstreamError(_) => StatusCode::BAD_GATEWAY,
             AdminServiceError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AdminServiceError::InvalidCredential(_) => StatusCode::BAD_REQUEST,
+            AdminServiceError::PairingNotFound => StatusCode::NOT_FOUND,
+            AdminServiceError::DumpNotFound(_) => StatusCode::NOT_FOUND,
+            AdminServiceError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
         }
     }
 
     /// 转换为 API 错误响应
     pub fn into_response(self) -> AdminErrorResponse {
         match &self {
-            AdminServiceError::NotFound { .. } => AdminErrorResponse::not_found(self.to_string()),
+            AdminServiceError::NotFound { .. }
+            | AdminServiceError::PairingNotFound
+            | AdminServiceError::DumpNotFound(_) => AdminErrorResponse::not_found(self.to_string()),
             AdminServiceError::UpstreamError(_) => AdminErrorResponse::api_error(self.to_string()),
             AdminServiceError::InternalError(_) => {
                 AdminErrorResponse::internal_error(self.to_string())
             }
-            AdminServiceError::InvalidCredential(_) => {
+            AdminServiceError::InvalidCredential(_) | AdminServiceError::InvalidRequest(_) => {
                 AdminErrorResponse::invalid_request(self.to_string())
             }
         }
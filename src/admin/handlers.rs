@@ -1,24 +1,52 @@
 //! Admin API HTTP 处理器
 
+use std::convert::Infallible;
+
 use axum::{
     Json,
     extract::{Path, State},
-    response::IntoResponse,
+    http::{HeaderMap, StatusCode, header},
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
 };
+use futures::{Stream, stream};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::common::i18n;
+use crate::model::config::Language;
 
 use super::{
     middleware::AdminState,
     types::{
-        AddCredentialRequest, SetDisabledRequest, SetLoadBalancingModeRequest, SetPriorityRequest,
-        SuccessResponse,
+        AddCredentialRequest, CreatePairingRequest, ImportCredentialsRequest, RefreshTokensRequest,
+        RestoreCredentialResponse, SetCanaryRequest, SetCredentialDailyLimitRequest,
+        SetDailyLimitOverrideRequest, SetDisabledRequest, SetDrainingRequest,
+        SetLoadBalancingModeRequest, SetPriorityRequest, SuccessResponse, UnbindAffinityRequest,
     },
 };
 
 /// GET /api/admin/credentials
 /// 获取所有凭据状态
-pub async fn get_all_credentials(State(state): State<AdminState>) -> impl IntoResponse {
+///
+/// 响应携带 ETag；请求头 `If-None-Match` 与当前 ETag 一致时返回 304，不再生成凭据列表
+pub async fn get_all_credentials(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let etag = state.service.credentials_etag();
+    let not_modified = headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|tag| tag.trim() == etag || tag.trim() == "*");
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
     let response = state.service.get_all_credentials();
-    Json(response)
+    ([(header::ETAG, etag)], Json(response)).into_response()
 }
 
 /// POST /api/admin/credentials/:id/disabled
@@ -30,8 +58,13 @@ pub async fn set_credential_disabled(
 ) -> impl IntoResponse {
     match state.service.set_disabled(id, payload.disabled) {
         Ok(_) => {
-            let action = if payload.disabled { "禁用" } else { "启用" };
-            Json(SuccessResponse::new(format!("凭据 #{} 已{}", id, action))).into_response()
+            let message = match (payload.disabled, i18n::language()) {
+                (true, Language::Zh) => format!("凭据 #{} 已禁用", id),
+                (false, Language::Zh) => format!("凭据 #{} 已启用", id),
+                (true, Language::En) => format!("Credential #{} disabled", id),
+                (false, Language::En) => format!("Credential #{} enabled", id),
+            };
+            Json(SuccessResponse::new(message)).into_response()
         }
         Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
     }
@@ -45,15 +78,66 @@ pub async fn set_credential_priority(
     Json(payload): Json<SetPriorityRequest>,
 ) -> impl IntoResponse {
     match state.service.set_priority(id, payload.priority) {
-        Ok(_) => Json(SuccessResponse::new(format!(
-            "凭据 #{} 优先级已设置为 {}",
-            id, payload.priority
+        Ok(_) => Json(SuccessResponse::new(i18n::pick(
+            format!("凭据 #{} 优先级已设置为 {}", id, payload.priority),
+            format!("Credential #{} priority set to {}", id, payload.priority),
         )))
         .into_response(),
         Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
     }
 }
 
+/// POST /api/admin/credentials/:id/daily-limit
+/// 设置凭据级每日请求上限
+pub async fn set_credential_daily_limit(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Json(payload): Json<SetCredentialDailyLimitRequest>,
+) -> impl IntoResponse {
+    let limit = payload.daily_request_limit;
+    match state.service.set_daily_request_limit(id, limit) {
+        Ok(_) => Json(SuccessResponse::new(match limit {
+            Some(limit) => i18n::pick(
+                format!("凭据 #{} 每日请求上限已设置为 {}", id, limit),
+                format!("Credential #{} daily request limit set to {}", id, limit),
+            ),
+            None => i18n::pick(
+                format!("凭据 #{} 已取消每日请求上限", id),
+                format!("Credential #{} daily request limit removed", id),
+            ),
+        }))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/:id/canary
+/// 设置金丝雀流量百分比（null 表示转正）
+pub async fn set_credential_canary(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Json(payload): Json<SetCanaryRequest>,
+) -> impl IntoResponse {
+    let percent = payload.canary_percent;
+    match state.service.set_canary_percent(id, percent) {
+        Ok(_) => Json(SuccessResponse::new(match percent {
+            Some(percent) => i18n::pick(
+                format!("凭据 #{} 已设为金丝雀凭据（{}% 流量）", id, percent),
+                format!(
+                    "Credential #{} is now a canary ({}% of traffic)",
+                    id, percent
+                ),
+            ),
+            None => i18n::pick(
+                format!("凭据 #{} 已转正，参与常规轮换", id),
+                format!("Credential #{} promoted to full rotation", id),
+            ),
+        }))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
 /// POST /api/admin/credentials/:id/reset
 /// 重置失败计数并重新启用
 pub async fn reset_failure_count(
@@ -61,9 +145,9 @@ pub async fn reset_failure_count(
     Path(id): Path<u64>,
 ) -> impl IntoResponse {
     match state.service.reset_and_enable(id) {
-        Ok(_) => Json(SuccessResponse::new(format!(
-            "凭据 #{} 失败计数已重置并重新启用",
-            id
+        Ok(_) => Json(SuccessResponse::new(i18n::pick(
+            format!("凭据 #{} 失败计数已重置并重新启用", id),
+            format!("Credential #{} failure count reset and re-enabled", id),
         )))
         .into_response(),
         Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
@@ -94,6 +178,64 @@ pub async fn add_credential(
     }
 }
 
+/// POST /api/admin/credentials/import
+/// 批量导入凭据，返回每个条目的结果（已截断或重复的条目会被跳过）
+pub async fn import_credentials(
+    State(state): State<AdminState>,
+    Json(payload): Json<ImportCredentialsRequest>,
+) -> impl IntoResponse {
+    let response = state.service.import_credentials(payload).await;
+    Json(response)
+}
+
+/// POST /api/admin/credentials/pairing
+/// 创建一次性配对会话，返回辅助脚本的上传地址
+pub async fn create_pairing(
+    State(state): State<AdminState>,
+    Json(payload): Json<CreatePairingRequest>,
+) -> impl IntoResponse {
+    let response = state.service.create_pairing(payload);
+    Json(response)
+}
+
+/// GET /api/admin/credentials/pairing/:token
+/// 查询配对会话状态
+pub async fn get_pairing(
+    State(state): State<AdminState>,
+    Path(token): Path<String>,
+) -> impl IntoResponse {
+    match state.service.get_pairing(&token) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/pair/:token
+/// 辅助脚本上传凭据（配对令牌即授权，不需要 Admin API Key）
+pub async fn upload_paired_credential(
+    State(state): State<AdminState>,
+    Path(token): Path<String>,
+    Json(payload): Json<AddCredentialRequest>,
+) -> impl IntoResponse {
+    match state
+        .service
+        .import_paired_credential(&token, payload)
+        .await
+    {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/pair/kiro-pair.py
+/// 下载配对辅助脚本
+pub async fn get_pairing_script() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/x-python; charset=utf-8")],
+        include_str!("../../tools/kiro-pair.py"),
+    )
+}
+
 /// DELETE /api/admin/credentials/:id
 /// 删除凭据
 pub async fn delete_credential(
@@ -101,7 +243,245 @@ pub async fn delete_credential(
     Path(id): Path<u64>,
 ) -> impl IntoResponse {
     match state.service.delete_credential(id) {
-        Ok(_) => Json(SuccessResponse::new(format!("凭据 #{} 已删除", id))).into_response(),
+        Ok(_) => Json(SuccessResponse::new(i18n::pick(
+            format!("凭据 #{} 已删除", id),
+            format!("Credential #{} deleted", id),
+        )))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/credentials/archived
+/// 获取保留期内的已删除凭据
+pub async fn get_archived_credentials(State(state): State<AdminState>) -> impl IntoResponse {
+    let response = state.service.get_archived_credentials();
+    Json(response)
+}
+
+/// POST /api/admin/credentials/archived/:id/restore
+/// 恢复已删除的凭据（恢复后保持禁用状态）
+pub async fn restore_credential(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.restore_credential(id) {
+        Ok(credential_id) => Json(RestoreCredentialResponse {
+            success: true,
+            message: i18n::pick(
+                format!(
+                    "凭据 #{} 已恢复（保持禁用，请确认后手动启用）",
+                    credential_id
+                ),
+                format!(
+                    "Credential #{} restored (still disabled, enable it manually when ready)",
+                    credential_id
+                ),
+            ),
+            credential_id,
+        })
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// DELETE /api/admin/credentials/archived/:id
+/// 永久删除已归档的凭据
+pub async fn purge_archived_credential(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.purge_archived_credential(id) {
+        Ok(_) => Json(SuccessResponse::new(i18n::pick(
+            format!("凭据 #{} 已永久删除", id),
+            format!("Credential #{} permanently deleted", id),
+        )))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/validate
+/// 并发刷新所有凭据的 Token，返回校验结果
+pub async fn validate_credentials(State(state): State<AdminState>) -> impl IntoResponse {
+    let response = state.service.validate_credentials().await;
+    Json(response)
+}
+
+/// POST /api/admin/refresh-tokens
+/// 立即刷新指定凭据（省略请求体或 `ids` 时为全部凭据）的 Token，返回每个凭据的刷新结果
+pub async fn refresh_tokens(
+    State(state): State<AdminState>,
+    payload: Option<Json<RefreshTokensRequest>>,
+) -> impl IntoResponse {
+    let request = payload.map(|Json(r)| r).unwrap_or_default();
+    Json(state.service.refresh_tokens(request).await)
+}
+
+/// GET /api/admin/stats
+/// 获取累计运行指标
+pub async fn get_stats(State(state): State<AdminState>) -> impl IntoResponse {
+    let response = state.service.get_stats();
+    Json(response)
+}
+
+/// GET /api/admin/stats/keys
+/// 获取按客户端 API Key 统计的累计用量
+pub async fn get_key_usage(State(state): State<AdminState>) -> impl IntoResponse {
+    let response = state.service.get_key_usage();
+    Json(response)
+}
+
+/// GET /api/admin/quota
+/// 获取每日请求上限状态
+pub async fn get_daily_limit(State(state): State<AdminState>) -> impl IntoResponse {
+    let response = state.service.get_daily_limit();
+    Json(response)
+}
+
+/// POST /api/admin/quota/override
+/// 解除或恢复今日的每日请求上限
+pub async fn set_daily_limit_override(
+    State(state): State<AdminState>,
+    Json(payload): Json<SetDailyLimitOverrideRequest>,
+) -> impl IntoResponse {
+    let overridden = payload.overridden;
+    state.service.set_daily_limit_override(payload);
+    Json(SuccessResponse::new(if overridden {
+        i18n::pick(
+            "已解除今日的请求上限，次日自动恢复",
+            "Daily request limit lifted for today; it resets tomorrow",
+        )
+    } else {
+        i18n::pick("已恢复每日请求上限", "Daily request limit restored")
+    }))
+}
+
+/// GET /api/admin/runtime
+/// 获取实例运行状态（排空模式、活跃流、排队请求、刷新中的凭据）
+pub async fn get_runtime_status(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_runtime_status())
+}
+
+/// POST /api/admin/runtime/drain
+/// 进入或退出排空模式
+pub async fn set_draining(
+    State(state): State<AdminState>,
+    Json(payload): Json<SetDrainingRequest>,
+) -> impl IntoResponse {
+    Json(state.service.set_draining(payload))
+}
+
+/// GET /api/admin/debug/dumps
+/// 列出上游错误调试转储（最新的在前）
+pub async fn list_debug_dumps(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.list_debug_dumps())
+}
+
+/// GET /api/admin/debug/dumps/:name
+/// 下载调试转储
+pub async fn get_debug_dump(
+    State(state): State<AdminState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    match state.service.read_debug_dump(&name) {
+        Ok(content) => (
+            [
+                (header::CONTENT_TYPE, "application/json".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", name),
+                ),
+            ],
+            content,
+        )
+            .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/debug/compress
+/// 工具压缩试运行：对 Anthropic 请求或已转换的 Kiro 请求执行压缩，返回各步骤的统计与最终请求体大小
+#[cfg(feature = "compression")]
+pub async fn compress_dry_run(
+    State(state): State<AdminState>,
+    Json(payload): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    match state.service.compress_dry_run(payload) {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/doctor
+/// 自检：配置与凭据文件、时钟偏差、上游 DNS/TLS 与代理连通性
+pub async fn get_doctor(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.run_doctor().await)
+}
+
+/// GET /api/admin/rebalance
+/// 获取最近一次凭据池再平衡分析结果
+pub async fn get_rebalance(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_rebalance())
+}
+
+/// POST /api/admin/rebalance/analyze
+/// 立即分析凭据池并生成再平衡建议
+pub async fn analyze_rebalance(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.analyze_rebalance())
+}
+
+/// POST /api/admin/rebalance/apply
+/// 应用最近一次再平衡分析的优先级建议
+pub async fn apply_rebalance(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.apply_rebalance())
+}
+
+/// GET /api/admin/affinity
+/// 获取当前的凭据亲和绑定
+pub async fn get_affinity_bindings(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_affinity_bindings())
+}
+
+/// GET /api/admin/events
+/// 以 SSE 推送凭据状态变化事件（禁用/启用、熔断、额度冷却、余额更新、Token 刷新、新增/删除）
+///
+/// 事件名即事件类型，数据为 JSON；处理过慢丢失事件时推送 `lagged` 事件（数据为丢失数量），
+/// 客户端应重新拉取凭据列表
+pub async fn get_events(
+    State(state): State<AdminState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.service.subscribe_events();
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        let event = loop {
+            match receiver.recv().await {
+                // 请求级事件频率高，不推送给管理面板
+                Ok(event) if !event.is_state_change() => continue,
+                Ok(event) => {
+                    break Event::default()
+                        .event(event.name())
+                        .data(serde_json::to_string(&event).unwrap_or_default());
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Admin 事件订阅者处理过慢，丢失 {} 个事件", skipped);
+                    break Event::default().event("lagged").data(skipped.to_string());
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        };
+        Some((Ok(event), receiver))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// POST /api/admin/affinity/unbind
+/// 按亲和键或凭据解除亲和绑定
+pub async fn unbind_affinity(
+    State(state): State<AdminState>,
+    Json(payload): Json<UnbindAffinityRequest>,
+) -> impl IntoResponse {
+    match state.service.unbind_affinity(payload) {
+        Ok(response) => Json(response).into_response(),
         Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
     }
 }
@@ -19,10 +19,11 @@
 mod error;
 mod handlers;
 mod middleware;
+mod pairing;
 mod router;
 mod service;
 pub mod types;
 
 pub use middleware::AdminState;
-pub use router::create_admin_router;
+pub use router::{create_admin_router, create_pairing_router};
 pub use service::AdminService;
@@ -0,0 +1,137 @@
+//! 凭据配对导入
+//!
+//! Admin 生成一次性的配对令牌，运行在 Kiro IDE 所在机器上的辅助脚本（`tools/kiro-pair.py`）
+//! 将本机凭据 POST 到 `/api/pair/{token}`，服务端校验并导入，避免通过聊天软件手动复制 refreshToken。
+//!
+//! 配对令牌本身即为上传授权（上传端点不需要 Admin API Key），因此：
+//! - 令牌为 256 位随机值，有效期 [`PAIRING_TTL_SECS`]
+//! - 导入成功后令牌立即失效；导入失败（如文件不完整）可在有效期内重试
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+use uuid::Uuid;
+
+/// 配对令牌有效期（秒）
+pub const PAIRING_TTL_SECS: i64 = 600;
+
+/// 配对会话状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairingStatus {
+    /// 等待上传
+    Pending,
+    /// 正在导入（防止同一令牌并发上传）
+    Importing,
+    /// 已导入为指定 ID 的凭据
+    Completed(u64),
+}
+
+/// 配对会话
+#[derive(Debug, Clone)]
+pub struct PairingSession {
+    /// 导入后的优先级
+    pub priority: u32,
+    /// 导入后的标签
+    pub label: Option<String>,
+    /// 过期时间
+    pub expires_at: DateTime<Utc>,
+    /// 当前状态
+    pub status: PairingStatus,
+}
+
+/// 配对会话存储（仅保存在内存中，重启后失效）
+#[derive(Default)]
+pub struct PairingStore {
+    sessions: Mutex<HashMap<String, PairingSession>>,
+}
+
+impl PairingStore {
+    /// 创建配对会话，返回令牌
+    pub fn create(&self, priority: u32, label: Option<String>) -> (String, PairingSession) {
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let session = PairingSession {
+            priority,
+            label,
+            expires_at: Utc::now() + Duration::seconds(PAIRING_TTL_SECS),
+            status: PairingStatus::Pending,
+        };
+
+        let mut sessions = self.sessions.lock();
+        Self::purge_expired(&mut sessions);
+        sessions.insert(token.clone(), session.clone());
+        (token, session)
+    }
+
+    /// 查询未过期的配对会话
+    pub fn get(&self, token: &str) -> Option<PairingSession> {
+        let mut sessions = self.sessions.lock();
+        Self::purge_expired(&mut sessions);
+        sessions.get(token).cloned()
+    }
+
+    /// 开始导入：仅等待上传的会话可以开始，成功时将其标记为导入中
+    pub fn begin_import(&self, token: &str) -> Option<PairingSession> {
+        let mut sessions = self.sessions.lock();
+        Self::purge_expired(&mut sessions);
+        let session = sessions.get_mut(token)?;
+        if session.status != PairingStatus::Pending {
+            return None;
+        }
+        session.status = PairingStatus::Importing;
+        Some(session.clone())
+    }
+
+    /// 结束导入：成功时记录凭据 ID（令牌随之失效），失败时恢复为等待上传
+    pub fn finish_import(&self, token: &str, credential_id: Option<u64>) {
+        if let Some(session) = self.sessions.lock().get_mut(token) {
+            session.status = match credential_id {
+                Some(id) => PairingStatus::Completed(id),
+                None => PairingStatus::Pending,
+            };
+        }
+    }
+
+    fn purge_expired(sessions: &mut HashMap<String, PairingSession>) {
+        let now = Utc::now();
+        sessions.retain(|_, session| session.expires_at > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_is_single_use() {
+        let store = PairingStore::default();
+        let (token, session) = store.create(3, Some("laptop".to_string()));
+        assert_eq!(token.len(), 64);
+        assert_eq!(session.status, PairingStatus::Pending);
+
+        // 导入中不能重复开始；失败后可重试
+        assert!(store.begin_import(&token).is_some());
+        assert!(store.begin_import(&token).is_none());
+        store.finish_import(&token, None);
+        let session = store.begin_import(&token).unwrap();
+        assert_eq!(session.priority, 3);
+
+        // 成功后令牌失效，但仍可查询结果
+        store.finish_import(&token, Some(7));
+        assert!(store.begin_import(&token).is_none());
+        assert_eq!(
+            store.get(&token).unwrap().status,
+            PairingStatus::Completed(7)
+        );
+        assert!(store.get("unknown").is_none());
+    }
+
+    #[test]
+    fn test_expired_sessions_are_purged() {
+        let store = PairingStore::default();
+        let (token, _) = store.create(0, None);
+        store.sessions.lock().get_mut(&token).unwrap().expires_at = Utc::now();
+        assert!(store.get(&token).is_none());
+        assert!(store.begin_import(&token).is_none());
+    }
+}
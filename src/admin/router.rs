@@ -1,29 +1,69 @@
 //! Admin API 路由配置
 
+#[cfg(feature = "compression")]
+use axum::extract::DefaultBodyLimit;
 use axum::{
     Router, middleware,
     routing::{delete, get, post},
 };
 
+#[cfg(feature = "compression")]
+use crate::anthropic::MAX_BODY_SIZE;
+
 use super::{
     handlers::{
-        add_credential, delete_credential, get_all_credentials, get_credential_balance,
-        get_load_balancing_mode, reset_failure_count, set_credential_disabled,
-        set_credential_priority, set_load_balancing_mode,
+        add_credential, analyze_rebalance, apply_rebalance, create_pairing, delete_credential,
+        get_affinity_bindings, get_all_credentials, get_archived_credentials,
+        get_credential_balance, get_daily_limit, get_debug_dump, get_doctor, get_events,
+        get_key_usage, get_load_balancing_mode, get_pairing, get_pairing_script, get_rebalance,
+        get_runtime_status, get_stats, import_credentials, list_debug_dumps,
+        purge_archived_credential, refresh_tokens, reset_failure_count, restore_credential,
+        set_credential_canary, set_credential_daily_limit, set_credential_disabled,
+        set_credential_priority, set_daily_limit_override, set_draining, set_load_balancing_mode,
+        unbind_affinity, upload_paired_credential, validate_credentials,
     },
     middleware::{AdminState, admin_auth_middleware},
 };
 
+#[cfg(feature = "compression")]
+use super::handlers::compress_dry_run;
+
 /// 创建 Admin API 路由
 ///
 /// # 端点
 /// - `GET /credentials` - 获取所有凭据状态
 /// - `POST /credentials` - 添加新凭据
-/// - `DELETE /credentials/:id` - 删除凭据
+/// - `POST /credentials/import` - 批量导入凭据（逐条返回结果，跳过已截断或重复的凭据）
+/// - `POST /credentials/validate` - 并发刷新所有凭据，返回有效/无效/已截断的校验结果
+/// - `DELETE /credentials/:id` - 删除凭据（归档，保留期内可恢复）
+/// - `POST /credentials/pairing` - 创建一次性配对会话（辅助脚本上传凭据）
+/// - `GET /credentials/pairing/:token` - 查询配对会话状态
+/// - `GET /credentials/archived` - 获取保留期内的已删除凭据
+/// - `POST /credentials/archived/:id/restore` - 恢复已删除的凭据
+/// - `DELETE /credentials/archived/:id` - 永久删除已归档的凭据
 /// - `POST /credentials/:id/disabled` - 设置凭据禁用状态
 /// - `POST /credentials/:id/priority` - 设置凭据优先级
+/// - `POST /credentials/:id/daily-limit` - 设置凭据级每日请求上限
+/// - `POST /credentials/:id/canary` - 设置金丝雀流量百分比（null 表示转正）
 /// - `POST /credentials/:id/reset` - 重置失败计数
 /// - `GET /credentials/:id/balance` - 获取凭据余额
+/// - `POST /refresh-tokens` - 立即刷新全部或指定凭据的 Token
+/// - `GET /stats` - 获取累计运行指标
+/// - `GET /stats/keys` - 获取按客户端 API Key 统计的累计用量
+/// - `GET /quota` - 获取每日请求上限状态
+/// - `POST /quota/override` - 解除或恢复今日的每日请求上限
+/// - `GET /runtime` - 获取实例运行状态（排空模式、活跃流、排队请求、刷新中的凭据）
+/// - `POST /runtime/drain` - 进入或退出排空模式（滚动部署）
+/// - `GET /debug/dumps` - 列出上游错误调试转储（需配置 debugDumpDir）
+/// - `GET /debug/dumps/:name` - 下载调试转储
+/// - `POST /debug/compress` - 工具压缩试运行（返回各压缩步骤的统计与最终请求体大小，compression 特性）
+/// - `GET /doctor` - 自检（配置、凭据文件、时钟偏差、上游与代理连通性）
+/// - `GET /rebalance` - 获取最近一次凭据池再平衡分析结果
+/// - `POST /rebalance/analyze` - 立即分析凭据池并生成优先级调整建议
+/// - `POST /rebalance/apply` - 应用最近一次分析的优先级调整建议
+/// - `GET /affinity` - 获取当前的凭据亲和绑定（balanced 模式）
+/// - `POST /affinity/unbind` - 按亲和键或凭据解除亲和绑定
+/// - `GET /events` - 以 SSE 推送凭据状态变化事件
 /// - `GET /config/load-balancing` - 获取负载均衡模式
 /// - `PUT /config/load-balancing` - 设置负载均衡模式
 ///
@@ -32,23 +72,79 @@ use super::{
 /// - `x-api-key` header
 /// - `Authorization: Bearer <token>` header
 pub fn create_admin_router(state: AdminState) -> Router {
-    Router::new()
+    let router = Router::new()
         .route(
             "/credentials",
             get(get_all_credentials).post(add_credential),
         )
+        .route("/credentials/import", post(import_credentials))
+        .route("/credentials/validate", post(validate_credentials))
+        .route("/credentials/pairing", post(create_pairing))
+        .route("/credentials/pairing/{token}", get(get_pairing))
+        .route("/credentials/archived", get(get_archived_credentials))
+        .route(
+            "/credentials/archived/{id}",
+            delete(purge_archived_credential),
+        )
+        .route(
+            "/credentials/archived/{id}/restore",
+            post(restore_credential),
+        )
         .route("/credentials/{id}", delete(delete_credential))
         .route("/credentials/{id}/disabled", post(set_credential_disabled))
         .route("/credentials/{id}/priority", post(set_credential_priority))
+        .route(
+            "/credentials/{id}/daily-limit",
+            post(set_credential_daily_limit),
+        )
+        .route("/credentials/{id}/canary", post(set_credential_canary))
         .route("/credentials/{id}/reset", post(reset_failure_count))
         .route("/credentials/{id}/balance", get(get_credential_balance))
+        .route("/refresh-tokens", post(refresh_tokens))
+        .route("/stats", get(get_stats))
+        .route("/stats/keys", get(get_key_usage))
+        .route("/quota", get(get_daily_limit))
+        .route("/quota/override", post(set_daily_limit_override))
+        .route("/runtime", get(get_runtime_status))
+        .route("/runtime/drain", post(set_draining))
+        .route("/debug/dumps", get(list_debug_dumps))
+        .route("/debug/dumps/{name}", get(get_debug_dump))
+        .route("/doctor", get(get_doctor))
+        .route("/rebalance", get(get_rebalance))
+        .route("/rebalance/analyze", post(analyze_rebalance))
+        .route("/rebalance/apply", post(apply_rebalance))
+        .route("/affinity", get(get_affinity_bindings))
+        .route("/affinity/unbind", post(unbind_affinity))
+        .route("/events", get(get_events))
         .route(
             "/config/load-balancing",
             get(get_load_balancing_mode).put(set_load_balancing_mode),
-        )
+        );
+    // 工具压缩试运行（compression 特性）
+    #[cfg(feature = "compression")]
+    let router = router.route(
+        "/debug/compress",
+        post(compress_dry_run).layer(DefaultBodyLimit::max(MAX_BODY_SIZE)),
+    );
+    router
         .layer(middleware::from_fn_with_state(
             state.clone(),
             admin_auth_middleware,
         ))
         .with_state(state)
 }
+
+/// 创建配对上传路由（挂载于 `/api/pair`）
+///
+/// # 端点
+/// - `GET /kiro-pair.py` - 下载配对辅助脚本
+/// - `POST /:token` - 辅助脚本上传凭据
+///
+/// # 认证
+/// 不需要 Admin API Key，一次性配对令牌即为上传授权
+pub fn create_pairing_router(state: AdminState) -> Router {
+    Router::new()
+        .route("/kiro-pair.py", get(get_pairing_script))
+        .route("/{token}", post(upload_paired_credential))
+        .with_state(state)
+}
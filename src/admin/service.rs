@@ -1,58 +1,71 @@
 //! Admin API 业务逻辑服务
 
-use std::collections::HashMap;
-use std::path::PathBuf;
 use std::sync::Arc;
 
-use chrono::Utc;
-use parking_lot::Mutex;
-use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 
+use crate::anthropic::AdmissionController;
+#[cfg(feature = "compression")]
+use crate::anthropic::{CompressionReport, compression_dry_run};
+use crate::common::lifecycle::Lifecycle;
+use crate::common::{clock, i18n};
+use crate::doctor::{self, DoctorReport};
+use crate::kiro::event_bus::BusEvent;
+use crate::kiro::model::credential_builder::CredentialValidationError;
 use crate::kiro::model::credentials::KiroCredentials;
-use crate::kiro::token_manager::MultiTokenManager;
+use crate::kiro::rebalance::RebalanceReport;
+use crate::kiro::token_manager::{MultiTokenManager, VALIDATION_CONCURRENCY};
+use crate::model::config::Config;
 
 use super::error::AdminServiceError;
+use super::pairing::{PairingSession, PairingStatus, PairingStore};
 use super::types::{
-    AddCredentialRequest, AddCredentialResponse, BalanceResponse, CredentialStatusItem,
-    CredentialsStatusResponse, LoadBalancingModeResponse, SetLoadBalancingModeRequest,
+    AddCredentialRequest, AddCredentialResponse, AffinityBindingsResponse, ArchivedCredentialItem,
+    ArchivedCredentialsResponse, BalanceResponse, CreatePairingRequest, CredentialStatusItem,
+    CredentialValidationItem, CredentialsStatusResponse, DailyLimitResponse, DebugDumpsResponse,
+    ImportCredentialsRequest, ImportCredentialsResponse, ImportItemResult, ImportItemStatus,
+    KeyUsageItem, KeyUsageResponse, LoadBalancingModeResponse, PairingResponse, PoolDailyUsage,
+    RebalanceResponse, RefreshTokenItem, RefreshTokensRequest, RefreshTokensResponse,
+    RuntimeStatusResponse, SetDailyLimitOverrideRequest, SetDrainingRequest,
+    SetLoadBalancingModeRequest, StatsResponse, UnbindAffinityRequest, UnbindAffinityResponse,
+    ValidateCredentialsResponse,
 };
 
-/// 余额缓存过期时间（秒），5 分钟
-const BALANCE_CACHE_TTL_SECS: i64 = 300;
-
-/// 缓存的余额条目（含时间戳）
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct CachedBalance {
-    /// 缓存时间（Unix 秒）
-    cached_at: f64,
-    /// 缓存的余额数据
-    data: BalanceResponse,
-}
-
 /// Admin 服务
 ///
 /// 封装所有 Admin API 的业务逻辑
 pub struct AdminService {
     token_manager: Arc<MultiTokenManager>,
-    balance_cache: Mutex<HashMap<u64, CachedBalance>>,
-    cache_path: Option<PathBuf>,
+    /// 配对导入会话
+    pairing: PairingStore,
+    /// 实例生命周期状态（排空模式）
+    lifecycle: Lifecycle,
+    /// 默认凭据池的请求准入控制器（用于并发与排队统计）
+    admission: AdmissionController,
 }
 
 impl AdminService {
     pub fn new(token_manager: Arc<MultiTokenManager>) -> Self {
-        let cache_path = token_manager
-            .cache_dir()
-            .map(|d| d.join("kiro_balance_cache.json"));
-
-        let balance_cache = Self::load_balance_cache_from(&cache_path);
-
         Self {
             token_manager,
-            balance_cache: Mutex::new(balance_cache),
-            cache_path,
+            pairing: PairingStore::default(),
+            lifecycle: Lifecycle::default(),
+            admission: AdmissionController::unlimited(),
         }
     }
 
+    /// 关联实例生命周期状态与请求准入控制器
+    pub fn with_runtime(mut self, lifecycle: Lifecycle, admission: AdmissionController) -> Self {
+        self.lifecycle = lifecycle;
+        self.admission = admission;
+        self
+    }
+
+    /// 凭据状态的 ETag（凭据状态未变化时不变）
+    pub fn credentials_etag(&self) -> String {
+        self.token_manager.status_etag()
+    }
+
     /// 获取所有凭据状态
     pub fn get_all_credentials(&self) -> CredentialsStatusResponse {
         let snapshot = self.token_manager.snapshot();
@@ -67,14 +80,50 @@ impl AdminService {
                 failure_count: entry.failure_count,
                 is_current: entry.id == snapshot.current_id,
                 expires_at: entry.expires_at,
+                auth_method_warning: entry.detected_auth_method.map(|detected| {
+                    format!(
+                        "{} {}",
+                        i18n::pick(
+                            "配置的认证方式与凭据内容不一致，凭据更像",
+                            "Configured auth method conflicts with the credential, which looks like",
+                        ),
+                        detected
+                    )
+                }),
                 auth_method: entry.auth_method,
                 has_profile_arn: entry.has_profile_arn,
                 refresh_token_hash: entry.refresh_token_hash,
+                refresh_token_warning: entry
+                    .refresh_token_expires_at
+                    .as_ref()
+                    .filter(|_| entry.refresh_token_expiring)
+                    .map(|at| {
+                        i18n::pick(
+                            format!("refreshToken 预计于 {} 过期，请尽快重新登录", at),
+                            format!("Refresh token is expected to expire at {}; log in again soon", at),
+                        )
+                    }),
+                refresh_token_issued_at: entry.refresh_token_issued_at,
+                refresh_token_rotated_at: entry.refresh_token_rotated_at,
+                refresh_token_expires_at: entry.refresh_token_expires_at,
                 email: entry.email,
+                label: entry.label,
+                notes: entry.notes,
                 success_count: entry.success_count,
                 last_used_at: entry.last_used_at.clone(),
                 has_proxy: entry.has_proxy,
                 proxy_url: entry.proxy_url,
+                read_only: entry.read_only,
+                requests_today: entry.requests_today,
+                daily_request_limit: entry.daily_request_limit,
+                canary_percent: entry.canary_percent,
+                backoff_level: entry.backoff_level,
+                last_failure: entry.last_failure.map(|kind| kind.as_str().to_string()),
+                last_failure_at: entry.last_failure_at,
+                unavailable_reason: entry
+                    .unavailable_reason
+                    .map(|reason| reason.as_str().to_string()),
+                available_at: entry.available_at,
             })
             .collect();
 
@@ -113,71 +162,63 @@ impl AdminService {
             .map_err(|e| self.classify_error(e, id))
     }
 
-    /// 重置失败计数并重新启用
-    pub fn reset_and_enable(&self, id: u64) -> Result<(), AdminServiceError> {
+    /// 设置凭据级每日请求上限
+    pub fn set_daily_request_limit(
+        &self,
+        id: u64,
+        limit: Option<u64>,
+    ) -> Result<(), AdminServiceError> {
         self.token_manager
-            .reset_and_enable(id)
+            .set_daily_request_limit(id, limit)
             .map_err(|e| self.classify_error(e, id))
     }
 
-    /// 获取凭据余额（带缓存）
-    pub async fn get_balance(&self, id: u64) -> Result<BalanceResponse, AdminServiceError> {
-        // 先查缓存
-        {
-            let cache = self.balance_cache.lock();
-            if let Some(cached) = cache.get(&id) {
-                let now = Utc::now().timestamp() as f64;
-                if (now - cached.cached_at) < BALANCE_CACHE_TTL_SECS as f64 {
-                    tracing::debug!("凭据 #{} 余额命中缓存", id);
-                    return Ok(cached.data.clone());
-                }
-            }
-        }
-
-        // 缓存未命中或已过期，从上游获取
-        let balance = self.fetch_balance(id).await?;
-
-        // 更新缓存
-        {
-            let mut cache = self.balance_cache.lock();
-            cache.insert(
-                id,
-                CachedBalance {
-                    cached_at: Utc::now().timestamp() as f64,
-                    data: balance.clone(),
-                },
-            );
+    /// 设置凭据的金丝雀流量百分比（None 表示转正）
+    pub fn set_canary_percent(
+        &self,
+        id: u64,
+        percent: Option<u8>,
+    ) -> Result<(), AdminServiceError> {
+        if let Some(percent) = percent.filter(|&p| p > 100) {
+            return Err(AdminServiceError::InvalidCredential(
+                CredentialValidationError::InvalidCanaryPercent(percent).to_string(),
+            ));
         }
-        self.save_balance_cache();
+        self.token_manager
+            .set_canary_percent(id, percent)
+            .map_err(|e| self.classify_error(e, id))
+    }
 
-        Ok(balance)
+    /// 重置失败计数并重新启用
+    pub fn reset_and_enable(&self, id: u64) -> Result<(), AdminServiceError> {
+        self.token_manager
+            .reset_and_enable(id)
+            .map_err(|e| self.classify_error(e, id))
     }
 
-    /// 从上游获取余额（无缓存）
-    async fn fetch_balance(&self, id: u64) -> Result<BalanceResponse, AdminServiceError> {
-        let usage = self
+    /// 获取凭据余额（缓存由 MultiTokenManager 统一维护）
+    pub async fn get_balance(&self, id: u64) -> Result<BalanceResponse, AdminServiceError> {
+        let balance = self
             .token_manager
-            .get_usage_limits_for(id)
+            .get_balance(id)
             .await
             .map_err(|e| self.classify_balance_error(e, id))?;
 
-        let current_usage = usage.current_usage();
-        let usage_limit = usage.usage_limit();
-        let remaining = (usage_limit - current_usage).max(0.0);
-        let usage_percentage = if usage_limit > 0.0 {
-            (current_usage / usage_limit * 100.0).min(100.0)
+        let remaining = (balance.usage_limit - balance.current_usage).max(0.0);
+        let usage_percentage = if balance.usage_limit > 0.0 {
+            (balance.current_usage / balance.usage_limit * 100.0).min(100.0)
         } else {
             0.0
         };
 
         Ok(BalanceResponse {
             id,
-            subscription_title: usage.subscription_title().map(|s| s.to_string()),
-            current_usage,
-            usage_limit,
+            subscription_title: balance.subscription_title,
+            current_usage: balance.current_usage,
+            usage_limit: balance.usage_limit,
             remaining,
             usage_percentage,
-            next_reset_at: usage.next_date_reset,
+            next_reset_at: balance.next_reset_at,
         })
     }
 
@@ -186,28 +227,17 @@ impl AdminService {
         &self,
         req: AddCredentialRequest,
     ) -> Result<AddCredentialResponse, AdminServiceError> {
-        // 构建凭据对象
-        let email = req.email.clone();
-        let new_cred = KiroCredentials {
-            id: None,
-            access_token: None,
-            refresh_token: Some(req.refresh_token),
-            profile_arn: None,
-            expires_at: None,
-            auth_method: Some(req.auth_method),
-            client_id: req.client_id,
-            client_secret: req.client_secret,
-            priority: req.priority,
-            region: req.region,
-            auth_region: req.auth_region,
-            api_region: req.api_region,
-            machine_id: req.machine_id,
-            email: req.email,
-            subscription_title: None, // 将在首次获取使用额度时自动更新
-            proxy_url: req.proxy_url,
-            proxy_username: req.proxy_username,
-            proxy_password: req.proxy_password,
-        };
+        let new_cred = build_credentials(req)
+            .map_err(|e| AdminServiceError::InvalidCredential(e.to_string()))?;
+        self.add_built_credential(new_cred).await
+    }
+
+    /// 添加已通过构建器校验的凭据（刷新 Token 验证有效性后写入凭据池）
+    async fn add_built_credential(
+        &self,
+        new_cred: KiroCredentials,
+    ) -> Result<AddCredentialResponse, AdminServiceError> {
+        let email = new_cred.email.clone();
 
         // 调用 token_manager 添加凭据
         let credential_id = self
@@ -223,26 +253,435 @@ impl AdminService {
 
         Ok(AddCredentialResponse {
             success: true,
-            message: format!("凭据添加成功，ID: {}", credential_id),
+            message: i18n::pick(
+                format!("凭据添加成功，ID: {}", credential_id),
+                format!("Credential added, ID: {}", credential_id),
+            ),
             credential_id,
             email,
         })
     }
 
+    /// 批量导入凭据
+    ///
+    /// 逐个导入并返回每个条目的结果：refreshToken 已被截断的条目在请求上游前直接跳过，
+    /// 避免导入注定无法刷新的凭据；重复的条目同样跳过，其余错误记为失败
+    pub async fn import_credentials(
+        &self,
+        req: ImportCredentialsRequest,
+    ) -> ImportCredentialsResponse {
+        let mut results = Vec::with_capacity(req.credentials.len());
+
+        for (i, item) in req.credentials.into_iter().enumerate() {
+            let index = i + 1;
+            let email = item.email.clone();
+
+            let new_cred = match build_credentials(item) {
+                Ok(new_cred) => new_cred,
+                Err(CredentialValidationError::TruncatedRefreshToken { len }) => {
+                    tracing::warn!(
+                        "导入的第 {} 个凭据 refreshToken 已被截断（长度 {}），已跳过",
+                        index,
+                        len
+                    );
+                    results.push(ImportItemResult {
+                        index,
+                        status: ImportItemStatus::Truncated,
+                        credential_id: None,
+                        email,
+                        message: truncated_token_message(len),
+                    });
+                    continue;
+                }
+                Err(e) => {
+                    results.push(ImportItemResult {
+                        index,
+                        status: ImportItemStatus::Failed,
+                        credential_id: None,
+                        email,
+                        message: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            let result = match self.add_built_credential(new_cred).await {
+                Ok(added) => ImportItemResult {
+                    index,
+                    status: ImportItemStatus::Imported,
+                    credential_id: Some(added.credential_id),
+                    email: added.email,
+                    message: added.message,
+                },
+                Err(e) => {
+                    let message = e.to_string();
+                    let status = if message.contains("凭据已存在") {
+                        ImportItemStatus::Duplicate
+                    } else {
+                        ImportItemStatus::Failed
+                    };
+                    ImportItemResult {
+                        index,
+                        status,
+                        credential_id: None,
+                        email,
+                        message,
+                    }
+                }
+            };
+            results.push(result);
+        }
+
+        let count =
+            |status: ImportItemStatus| results.iter().filter(|r| r.status == status).count();
+        ImportCredentialsResponse {
+            imported: count(ImportItemStatus::Imported),
+            skipped: count(ImportItemStatus::Truncated) + count(ImportItemStatus::Duplicate),
+            failed: count(ImportItemStatus::Failed),
+            results,
+        }
+    }
+
+    /// 创建一次性配对会话
+    pub fn create_pairing(&self, req: CreatePairingRequest) -> PairingResponse {
+        let label = req
+            .label
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty());
+        let (token, session) = self.pairing.create(req.priority, label);
+        tracing::info!("已创建凭据配对会话（有效期至 {}）", session.expires_at);
+        pairing_response(token, &session)
+    }
+
+    /// 查询配对会话状态
+    pub fn get_pairing(&self, token: &str) -> Result<PairingResponse, AdminServiceError> {
+        let session = self
+            .pairing
+            .get(token)
+            .ok_or(AdminServiceError::PairingNotFound)?;
+        Ok(pairing_response(token.to_string(), &session))
+    }
+
+    /// 导入辅助脚本通过配对令牌上传的凭据
+    ///
+    /// 令牌仅在导入成功后失效；校验失败时可在有效期内重新上传
+    pub async fn import_paired_credential(
+        &self,
+        token: &str,
+        mut req: AddCredentialRequest,
+    ) -> Result<AddCredentialResponse, AdminServiceError> {
+        let session = self
+            .pairing
+            .begin_import(token)
+            .ok_or(AdminServiceError::PairingNotFound)?;
+
+        req.priority = session.priority;
+        if req.label.is_none() {
+            req.label = session.label;
+        }
+
+        let result = self.add_credential(req).await;
+        match &result {
+            Ok(response) => {
+                tracing::info!("已通过配对导入凭据 #{}", response.credential_id);
+                self.pairing
+                    .finish_import(token, Some(response.credential_id));
+            }
+            Err(e) => {
+                tracing::warn!("配对上传的凭据导入失败: {}", e);
+                self.pairing.finish_import(token, None);
+            }
+        }
+        result
+    }
+
     /// 删除凭据
     pub fn delete_credential(&self, id: u64) -> Result<(), AdminServiceError> {
         self.token_manager
             .delete_credential(id)
             .map_err(|e| self.classify_delete_error(e, id))?;
 
-        // 清理已删除凭据的余额缓存
-        {
-            let mut cache = self.balance_cache.lock();
-            cache.remove(&id);
+        Ok(())
+    }
+
+    /// 获取保留期内的已删除凭据
+    pub fn get_archived_credentials(&self) -> ArchivedCredentialsResponse {
+        let credentials = self
+            .token_manager
+            .archived_credentials()
+            .into_iter()
+            .map(|(entry, purge_at)| ArchivedCredentialItem {
+                id: entry.id(),
+                email: entry.credentials.email,
+                label: entry.credentials.label,
+                auth_method: entry.credentials.auth_method,
+                priority: entry.credentials.priority,
+                deleted_at: entry.deleted_at.to_rfc3339(),
+                purge_at: purge_at.to_rfc3339(),
+            })
+            .collect();
+
+        ArchivedCredentialsResponse {
+            retention_days: self.token_manager.config().archive_retention_days,
+            credentials,
         }
-        self.save_balance_cache();
+    }
 
-        Ok(())
+    /// 恢复已删除的凭据
+    pub fn restore_credential(&self, id: u64) -> Result<u64, AdminServiceError> {
+        self.token_manager
+            .restore_credential(id)
+            .map_err(|e| self.classify_archive_error(e, id))
+    }
+
+    /// 永久删除已归档的凭据
+    pub fn purge_archived_credential(&self, id: u64) -> Result<(), AdminServiceError> {
+        self.token_manager
+            .purge_archived_credential(id)
+            .map_err(|e| self.classify_archive_error(e, id))
+    }
+
+    /// 校验所有凭据（并发刷新 Token）
+    pub async fn validate_credentials(&self) -> ValidateCredentialsResponse {
+        let results: Vec<CredentialValidationItem> = self
+            .token_manager
+            .validate_credentials(VALIDATION_CONCURRENCY)
+            .await
+            .into_iter()
+            .map(|r| CredentialValidationItem {
+                id: r.id,
+                email: r.email,
+                auth_method: r.auth_method,
+                status: r.status.as_str().to_string(),
+                error: r.status.error().map(str::to_string),
+                expires_at: r.expires_at,
+            })
+            .collect();
+
+        let count = |status: &str| results.iter().filter(|r| r.status == status).count();
+        ValidateCredentialsResponse {
+            total: results.len(),
+            valid: count("valid"),
+            invalid: count("invalid"),
+            truncated: count("truncated"),
+            results,
+        }
+    }
+
+    /// 立即刷新指定凭据（省略时为全部凭据）的 Token
+    pub async fn refresh_tokens(&self, req: RefreshTokensRequest) -> RefreshTokensResponse {
+        let results: Vec<RefreshTokenItem> = self
+            .token_manager
+            .refresh_tokens(req.ids.as_deref())
+            .await
+            .into_iter()
+            .map(|r| RefreshTokenItem {
+                id: r.id,
+                success: r.error.is_none(),
+                expires_at: r.expires_at,
+                error: r.error,
+            })
+            .collect();
+
+        let refreshed = results.iter().filter(|r| r.success).count();
+        RefreshTokensResponse {
+            total: results.len(),
+            refreshed,
+            failed: results.len() - refreshed,
+            results,
+        }
+    }
+
+    /// 获取累计运行指标
+    pub fn get_stats(&self) -> StatsResponse {
+        let snapshot = self.token_manager.metrics().snapshot();
+        StatsResponse {
+            requests_total: snapshot.requests_total,
+            failures_total: snapshot.failures_total,
+            input_tokens_total: snapshot.input_tokens_total,
+            output_tokens_total: snapshot.output_tokens_total,
+            backpressure_events_total: snapshot.backpressure_events_total,
+            since: snapshot.since,
+            conversion: self.token_manager.metrics().conversion().snapshot(),
+            acquire_wait: self.token_manager.metrics().wait().snapshot(),
+            upstream_bytes: self.token_manager.metrics().sizes().snapshot(),
+            unknown_events: self.token_manager.metrics().unknown_events(),
+        }
+    }
+
+    /// 获取按客户端 API Key 统计的累计用量
+    pub fn get_key_usage(&self) -> KeyUsageResponse {
+        let snapshot = self.token_manager.metrics().snapshot();
+        KeyUsageResponse {
+            keys: snapshot
+                .keys
+                .into_iter()
+                .map(|(name, usage)| KeyUsageItem {
+                    name,
+                    requests_total: usage.requests_total,
+                    input_tokens_total: usage.input_tokens_total,
+                    output_tokens_total: usage.output_tokens_total,
+                    last_used_at: usage.last_used_at,
+                })
+                .collect(),
+            since: snapshot.since,
+        }
+    }
+
+    /// 获取每日请求上限状态
+    pub fn get_daily_limit(&self) -> DailyLimitResponse {
+        let status = self.token_manager.daily_limit_status();
+        let paths = self.token_manager.source_paths();
+        DailyLimitResponse {
+            date: status.date,
+            timezone: self.token_manager.config().daily_reset_timezone.clone(),
+            reset_at: status.reset_at.to_rfc3339(),
+            requests_today: status.requests,
+            daily_limit: status.limit,
+            overridden: status.overridden,
+            pools: status
+                .pools
+                .into_iter()
+                .enumerate()
+                .map(|(index, (requests, limit))| PoolDailyUsage {
+                    path: paths
+                        .get(index)
+                        .cloned()
+                        .flatten()
+                        .map(|p| p.display().to_string()),
+                    requests_today: requests,
+                    daily_limit: limit,
+                })
+                .collect(),
+        }
+    }
+
+    /// 解除或恢复今日的每日请求上限
+    pub fn set_daily_limit_override(&self, req: SetDailyLimitOverrideRequest) {
+        self.token_manager
+            .set_daily_limit_overridden(req.overridden);
+    }
+
+    /// 列出上游错误调试转储
+    pub fn list_debug_dumps(&self) -> DebugDumpsResponse {
+        let dumps = self.token_manager.debug_dumps();
+        DebugDumpsResponse {
+            enabled: dumps.is_some(),
+            dumps: dumps.map(|d| d.list()).unwrap_or_default(),
+        }
+    }
+
+    /// 读取调试转储内容
+    pub fn read_debug_dump(&self, name: &str) -> Result<Vec<u8>, AdminServiceError> {
+        self.token_manager
+            .debug_dumps()
+            .and_then(|d| d.read(name))
+            .ok_or_else(|| AdminServiceError::DumpNotFound(name.to_string()))
+    }
+
+    /// 按当前配置对请求执行工具压缩试运行，只返回各压缩步骤的统计
+    #[cfg(feature = "compression")]
+    pub fn compress_dry_run(
+        &self,
+        input: serde_json::Value,
+    ) -> Result<CompressionReport, AdminServiceError> {
+        compression_dry_run(input, self.token_manager.config())
+            .map_err(AdminServiceError::InvalidRequest)
+    }
+
+    /// 执行自检（重新读取配置文件与凭据文件，检查上游连通性）
+    pub async fn run_doctor(&self) -> DoctorReport {
+        let config = self.token_manager.config();
+        let config_path = config.config_path();
+        let config_error = config_path
+            .filter(|path| path.exists())
+            .and_then(|path| Config::load(path).err())
+            .map(|e| format!("{:#}", e));
+        doctor::run(
+            config,
+            config_path,
+            config_error.as_deref(),
+            &self.token_manager.source_files(),
+            self.token_manager.proxy(),
+        )
+        .await
+    }
+
+    /// 获取最近一次再平衡分析结果
+    pub fn get_rebalance(&self) -> RebalanceResponse {
+        self.rebalance_response(self.token_manager.rebalance_report())
+    }
+
+    /// 立即分析凭据池（开启 autopilot 时同时应用建议）
+    pub fn analyze_rebalance(&self) -> RebalanceResponse {
+        self.rebalance_response(Some(self.token_manager.analyze_rebalance()))
+    }
+
+    /// 应用最近一次再平衡分析的建议
+    pub fn apply_rebalance(&self) -> RebalanceResponse {
+        self.rebalance_response(self.token_manager.apply_rebalance())
+    }
+
+    fn rebalance_response(&self, report: Option<RebalanceReport>) -> RebalanceResponse {
+        let config = self.token_manager.config();
+        RebalanceResponse {
+            interval_secs: config.rebalance_interval_secs,
+            autopilot: config.rebalance_autopilot,
+            report,
+        }
+    }
+
+    /// 订阅凭据状态变化事件
+    pub fn subscribe_events(&self) -> broadcast::Receiver<BusEvent> {
+        self.token_manager.subscribe_events()
+    }
+
+    /// 获取当前的亲和绑定
+    pub fn get_affinity_bindings(&self) -> AffinityBindingsResponse {
+        let config = self.token_manager.config();
+        AffinityBindingsResponse {
+            ttl_secs: config.affinity_ttl_secs,
+            max_entries: config.affinity_max_entries,
+            bindings: self.token_manager.affinity_bindings(),
+        }
+    }
+
+    /// 按亲和键或凭据解除亲和绑定
+    pub fn unbind_affinity(
+        &self,
+        req: UnbindAffinityRequest,
+    ) -> Result<UnbindAffinityResponse, AdminServiceError> {
+        if req.key.is_none() && req.credential_id.is_none() {
+            return Err(AdminServiceError::InvalidCredential(
+                i18n::pick(
+                    "key 与 credentialId 至少需要指定一个",
+                    "At least one of key and credentialId is required",
+                )
+                .to_string(),
+            ));
+        }
+        let removed = self
+            .token_manager
+            .unbind_affinity(req.key.as_deref(), req.credential_id);
+        Ok(UnbindAffinityResponse { removed })
+    }
+
+    /// 获取实例运行状态（排空模式与连接统计）
+    pub fn get_runtime_status(&self) -> RuntimeStatusResponse {
+        RuntimeStatusResponse {
+            draining: self.lifecycle.is_draining(),
+            active_streams: self.lifecycle.active_streams(),
+            in_flight_requests: self.admission.in_flight(),
+            queued_requests: self.admission.queued(),
+            refreshing_credentials: self.token_manager.refreshing_credentials(),
+            clock_skew_secs: clock::observed_offset().num_seconds(),
+        }
+    }
+
+    /// 进入或退出排空模式
+    pub fn set_draining(&self, req: SetDrainingRequest) -> RuntimeStatusResponse {
+        self.lifecycle.set_draining(req.draining);
+        self.get_runtime_status()
     }
 
     /// 获取负载均衡模式
@@ -260,7 +699,11 @@ impl AdminService {
         // 验证模式值
         if req.mode != "priority" && req.mode != "balanced" {
             return Err(AdminServiceError::InvalidCredential(
-                "mode 必须是 'priority' 或 'balanced'".to_string(),
+                i18n::pick(
+                    "mode 必须是 'priority' 或 'balanced'",
+                    "mode must be 'priority' or 'balanced'",
+                )
+                .to_string(),
             ));
         }
 
@@ -271,63 +714,6 @@ impl AdminService {
         Ok(LoadBalancingModeResponse { mode: req.mode })
     }
 
-    // ============ 余额缓存持久化 ============
-
-    fn load_balance_cache_from(cache_path: &Option<PathBuf>) -> HashMap<u64, CachedBalance> {
-        let path = match cache_path {
-            Some(p) => p,
-            None => return HashMap::new(),
-        };
-
-        let content = match std::fs::read_to_string(path) {
-            Ok(c) => c,
-            Err(_) => return HashMap::new(),
-        };
-
-        // 文件中使用字符串 key 以兼容 JSON 格式
-        let map: HashMap<String, CachedBalance> = match serde_json::from_str(&content) {
-            Ok(m) => m,
-            Err(e) => {
-                tracing::warn!("解析余额缓存失败，将忽略: {}", e);
-                return HashMap::new();
-            }
-        };
-
-        let now = Utc::now().timestamp() as f64;
-        map.into_iter()
-            .filter_map(|(k, v)| {
-                let id = k.parse::<u64>().ok()?;
-                // 丢弃超过 TTL 的条目
-                if (now - v.cached_at) < BALANCE_CACHE_TTL_SECS as f64 {
-                    Some((id, v))
-                } else {
-                    None
-                }
-            })
-            .collect()
-    }
-
-    fn save_balance_cache(&self) {
-        let path = match &self.cache_path {
-            Some(p) => p,
-            None => return,
-        };
-
-        // 持有锁期间完成序列化和写入，防止并发损坏
-        let cache = self.balance_cache.lock();
-        let map: HashMap<String, &CachedBalance> =
-            cache.iter().map(|(k, v)| (k.to_string(), v)).collect();
-
-        match serde_json::to_string_pretty(&map) {
-            Ok(json) => {
-                if let Err(e) = std::fs::write(path, json) {
-                    tracing::warn!("保存余额缓存失败: {}", e);
-                }
-            }
-            Err(e) => tracing::warn!("序列化余额缓存失败: {}", e),
-        }
-    }
-
     // ============ 错误分类 ============
 
     /// 分类简单操作错误（set_disabled, set_priority, reset_and_enable）
@@ -377,10 +763,8 @@ impl AdminService {
     fn classify_add_error(&self, e: anyhow::Error) -> AdminServiceError {
         let msg = e.to_string();
 
-        // 凭据验证失败（refreshToken 无效、格式错误等）
-        let is_invalid_credential = msg.contains("缺少 refreshToken")
-            || msg.contains("refreshToken 为空")
-            || msg.contains("refreshToken 已被截断")
+        // 凭据验证失败（refreshToken 格式错误、重复、上游拒绝等）
+        let is_invalid_credential = e.downcast_ref::<CredentialValidationError>().is_some()
             || msg.contains("凭据已存在")
             || msg.contains("refreshToken 重复")
             || msg.contains("凭证已过期或无效")
@@ -404,10 +788,162 @@ impl AdminService {
         let msg = e.to_string();
         if msg.contains("不存在") {
             AdminServiceError::NotFound { id }
-        } else if msg.contains("只能删除已禁用的凭据") || msg.contains("请先禁用凭据") {
+        } else if msg.contains("只能删除已禁用的凭据")
+            || msg.contains("请先禁用凭据")
+            || msg.contains("只读凭据文件")
+        {
+            AdminServiceError::InvalidCredential(msg)
+        } else {
+            AdminServiceError::InternalError(msg)
+        }
+    }
+
+    /// 分类恢复 / 永久删除已归档凭据的错误
+    fn classify_archive_error(&self, e: anyhow::Error, id: u64) -> AdminServiceError {
+        let msg = e.to_string();
+        if msg.contains("不存在") {
+            AdminServiceError::NotFound { id }
+        } else if msg.contains("凭据已存在") {
             AdminServiceError::InvalidCredential(msg)
         } else {
             AdminServiceError::InternalError(msg)
         }
     }
 }
+
+/// 构建配对会话响应
+fn pairing_response(token: String, session: &PairingSession) -> PairingResponse {
+    let (status, credential_id) = match session.status {
+        PairingStatus::Pending => ("pending", None),
+        PairingStatus::Importing => ("importing", None),
+        PairingStatus::Completed(id) => ("completed", Some(id)),
+    };
+    PairingResponse {
+        upload_path: format!("/api/pair/{}", token),
+        script_path: "/api/pair/kiro-pair.py".to_string(),
+        token,
+        expires_at: session.expires_at.to_rfc3339(),
+        status: status.to_string(),
+        credential_id,
+    }
+}
+
+/// 由 Admin 添加 / 导入请求构建凭据（规范化并校验）
+fn build_credentials(
+    req: AddCredentialRequest,
+) -> Result<KiroCredentials, CredentialValidationError> {
+    KiroCredentials::builder(req.refresh_token)
+        .auth_method(req.auth_method)
+        .client(req.client_id, req.client_secret)
+        .priority(req.priority)
+        .region(req.region)
+        .auth_region(req.auth_region)
+        .api_region(req.api_region)
+        .machine_id(req.machine_id)
+        .email(req.email)
+        .label(req.label)
+        .notes(req.notes)
+        .proxy(req.proxy_url, req.proxy_username, req.proxy_password)
+        .daily_request_limit(req.daily_request_limit)
+        .canary_percent(req.canary_percent)
+        .build()
+}
+
+/// refreshToken 被截断时的说明
+fn truncated_token_message(len: usize) -> String {
+    i18n::pick(
+        format!(
+            "refreshToken 已被截断（长度 {} 字符），已跳过。Kiro IDE 显示或导出的 refreshToken \
+             会被故意截断（过短或包含 \"...\"），无法用于刷新 Token，导入后只会反复失败；\
+             请从 Kiro 的原始 Token 缓存文件中复制完整的 refreshToken 后重新导入",
+            len
+        ),
+        format!(
+            "refreshToken is truncated ({} chars) and was skipped. Kiro IDE deliberately \
+             truncates refresh tokens it displays or exports (too short or containing \"...\"), \
+             so they can never be refreshed and would only keep failing after import; \
+             copy the full refreshToken from Kiro's original token cache file and import again",
+            len
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::credentials::KiroCredentials as Credentials;
+    use crate::model::config::Config;
+
+    fn import_item(refresh_token: &str) -> AddCredentialRequest {
+        serde_json::from_value(serde_json::json!({ "refreshToken": refresh_token })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_import_skips_truncated_tokens() {
+        let existing = Credentials {
+            id: Some(1),
+            refresh_token: Some("x".repeat(120)),
+            ..Default::default()
+        };
+        let manager =
+            MultiTokenManager::new(Config::default(), vec![existing], None, None, false).unwrap();
+        let service = AdminService::new(Arc::new(manager));
+
+        let response = service
+            .import_credentials(ImportCredentialsRequest {
+                credentials: vec![
+                    import_item("short"),
+                    import_item(&format!("{}...", "y".repeat(120))),
+                    import_item(&format!("  {}  ", "x".repeat(120))),
+                    serde_json::from_value(serde_json::json!({
+                        "refreshToken": "z".repeat(120),
+                        "apiRegion": "us_east_1",
+                    }))
+                    .unwrap(),
+                ],
+            })
+            .await;
+
+        let statuses: Vec<ImportItemStatus> = response.results.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ImportItemStatus::Truncated,
+                ImportItemStatus::Truncated,
+                ImportItemStatus::Duplicate,
+                ImportItemStatus::Failed
+            ]
+        );
+        assert_eq!(response.skipped, 3);
+        assert_eq!(response.imported, 0);
+        // 格式校验失败的条目不会请求上游
+        assert!(response.results[3].message.contains("apiRegion"));
+        assert!(response.results[0].message.contains("Kiro IDE"));
+    }
+
+    #[tokio::test]
+    async fn test_paired_upload_requires_valid_token() {
+        let manager = MultiTokenManager::new(Config::default(), vec![], None, None, false).unwrap();
+        let service = AdminService::new(Arc::new(manager));
+
+        let result = service
+            .import_paired_credential("unknown", import_item(&"x".repeat(120)))
+            .await;
+        assert!(matches!(result, Err(AdminServiceError::PairingNotFound)));
+
+        // 导入失败（refreshToken 已截断）时令牌保持可用
+        let pairing = service.create_pairing(CreatePairingRequest::default());
+        assert_eq!(pairing.upload_path, format!("/api/pair/{}", pairing.token));
+        let result = service
+            .import_paired_credential(&pairing.token, import_item("short"))
+            .await;
+        assert!(matches!(
+            result,
+            Err(AdminServiceError::InvalidCredential(_))
+        ));
+        assert_eq!(
+            service.get_pairing(&pairing.token).unwrap().status,
+            "pending"
+        );
+    }
+}
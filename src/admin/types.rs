@@ -1,7 +1,14 @@
 //! Admin API 类型定义
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
+use crate::kiro::debug_dump::DumpInfo;
+use crate::kiro::metrics::{ConversionMetricsSnapshot, SizeMetricsSnapshot, WaitMetricsSnapshot};
+use crate::kiro::rebalance::RebalanceReport;
+use crate::kiro::token_manager::AffinityBindingSnapshot;
+
 // ============ 凭据状态 ============
 
 /// 所有凭据状态响应
@@ -36,12 +43,30 @@ pub struct CredentialStatusItem {
     pub expires_at: Option<String>,
     /// 认证方式
     pub auth_method: Option<String>,
+    /// 配置的认证方式与按凭据内容推断的结果不一致时的警告
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_method_warning: Option<String>,
     /// 是否有 Profile ARN
     pub has_profile_arn: bool,
-    /// refreshToken 的 SHA-256 哈希（用于前端重复检测）
+    /// 加盐的 refreshToken SHA-256 哈希前缀（用于跨系统匹配账号）
     pub refresh_token_hash: Option<String>,
+    /// refreshToken 的签发时间（RFC3339 格式）
+    pub refresh_token_issued_at: Option<String>,
+    /// refreshToken 最近一次被上游轮换的时间（RFC3339 格式）
+    pub refresh_token_rotated_at: Option<String>,
+    /// 估算的 refreshToken 过期时间（RFC3339 格式，仅 IdC 凭据）
+    pub refresh_token_expires_at: Option<String>,
+    /// refreshToken 即将过期时的警告（提醒重新登录）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token_warning: Option<String>,
     /// 用户邮箱（用于前端显示）
     pub email: Option<String>,
+    /// 标签（运营方自定义的显示名称）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// 备注
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
     /// API 调用成功次数
     pub success_count: u64,
     /// 最后一次 API 调用时间（RFC3339 格式）
@@ -51,6 +76,24 @@ pub struct CredentialStatusItem {
     /// 代理 URL（用于前端展示）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_url: Option<String>,
+    /// 是否来自只读凭据文件
+    pub read_only: bool,
+    /// 今日已计入的请求数
+    pub requests_today: u64,
+    /// 凭据级每日请求上限（null 表示不限制）
+    pub daily_request_limit: Option<u64>,
+    /// 金丝雀流量百分比（null 表示正常参与轮换）
+    pub canary_percent: Option<u8>,
+    /// 退避级别（自上次成功以来连续的瞬态失败次数）
+    pub backoff_level: u32,
+    /// 最近一次失败的分类（network / throttled / upstream / auth / quota_exhausted）
+    pub last_failure: Option<String>,
+    /// 最近一次失败的时间（RFC3339 格式）
+    pub last_failure_at: Option<String>,
+    /// 当前不会被选中的原因（disabled / too_many_failures / quota_exceeded / daily_limit / pool_daily_limit，null 表示可被选中）
+    pub unavailable_reason: Option<String>,
+    /// 预计恢复可选的时间（RFC3339 格式，null 表示需手动处理或当前可用）
+    pub available_at: Option<String>,
 }
 
 // ============ 操作请求 ============
@@ -71,6 +114,22 @@ pub struct SetPriorityRequest {
     pub priority: u32,
 }
 
+/// 修改凭据级每日请求上限请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetCredentialDailyLimitRequest {
+    /// 新上限（null 表示不限制）
+    pub daily_request_limit: Option<u64>,
+}
+
+/// 设置金丝雀流量百分比请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetCanaryRequest {
+    /// 流量百分比（0-100，null 表示转正）
+    pub canary_percent: Option<u8>,
+}
+
 /// 添加凭据请求
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -109,6 +168,12 @@ pub struct AddCredentialRequest {
     /// 用户邮箱（可选，用于前端显示）
     pub email: Option<String>,
 
+    /// 标签（可选，运营方自定义的显示名称，如 "work account, eu-west-1"）
+    pub label: Option<String>,
+
+    /// 备注（可选）
+    pub notes: Option<String>,
+
     /// 凭据级代理 URL（可选，特殊值 "direct" 表示不使用代理）
     pub proxy_url: Option<String>,
 
@@ -117,12 +182,71 @@ pub struct AddCredentialRequest {
 
     /// 凭据级代理认证密码（可选）
     pub proxy_password: Option<String>,
+
+    /// 凭据级每日请求上限（可选，不填表示不限制）
+    pub daily_request_limit: Option<u64>,
+
+    /// 金丝雀流量百分比（可选，不填时使用配置 newCredentialCanaryPercent）
+    pub canary_percent: Option<u8>,
 }
 
 fn default_auth_method() -> String {
     "social".to_string()
 }
 
+/// 批量导入凭据请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCredentialsRequest {
+    /// 待导入的凭据（按顺序逐个导入）
+    pub credentials: Vec<AddCredentialRequest>,
+}
+
+/// 导入条目状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportItemStatus {
+    /// 导入成功
+    Imported,
+    /// refreshToken 已被截断，已跳过
+    Truncated,
+    /// 凭据已存在，已跳过
+    Duplicate,
+    /// 刷新验证失败等其他错误
+    Failed,
+}
+
+/// 单个导入条目的结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportItemResult {
+    /// 条目序号（从 1 开始，对应请求中的顺序）
+    pub index: usize,
+    pub status: ImportItemStatus,
+    /// 新凭据 ID（仅导入成功时有值）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_id: Option<u64>,
+    /// 用户邮箱
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// 结果说明（失败或跳过的原因）
+    pub message: String,
+}
+
+/// 批量导入凭据响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCredentialsResponse {
+    /// 导入成功数
+    pub imported: usize,
+    /// 跳过数（已截断或重复）
+    pub skipped: usize,
+    /// 失败数
+    pub failed: usize,
+    /// 各条目的结果
+    pub results: Vec<ImportItemResult>,
+}
+
 /// 添加凭据成功响应
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -136,6 +260,84 @@ pub struct AddCredentialResponse {
     pub email: Option<String>,
 }
 
+// ============ 配对导入 ============
+
+/// 创建配对请求
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatePairingRequest {
+    /// 导入后的优先级（可选，默认 0）
+    #[serde(default)]
+    pub priority: u32,
+
+    /// 导入后的标签（可选，上传内容中带有标签时以上传内容为准）
+    pub label: Option<String>,
+}
+
+/// 配对会话响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PairingResponse {
+    /// 一次性配对令牌
+    pub token: String,
+    /// 上传地址路径（辅助脚本 POST 凭据 JSON 到该路径）
+    pub upload_path: String,
+    /// 辅助脚本下载路径
+    pub script_path: String,
+    /// 过期时间（RFC3339）
+    pub expires_at: String,
+    /// 状态（pending / importing / completed）
+    pub status: String,
+    /// 导入后的凭据 ID（仅 completed 时有值）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_id: Option<u64>,
+}
+
+// ============ 已删除凭据 ============
+
+/// 已删除（归档）凭据列表响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedCredentialsResponse {
+    /// 保留天数（0 表示未启用归档）
+    pub retention_days: u64,
+    /// 已删除的凭据（按删除时间倒序）
+    pub credentials: Vec<ArchivedCredentialItem>,
+}
+
+/// 单个已删除凭据
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedCredentialItem {
+    /// 删除前的凭据 ID
+    pub id: u64,
+    /// 用户邮箱
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// 标签
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// 认证方式
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_method: Option<String>,
+    /// 优先级
+    pub priority: u32,
+    /// 删除时间（RFC3339）
+    pub deleted_at: String,
+    /// 自动清除时间（RFC3339）
+    pub purge_at: String,
+}
+
+/// 恢复凭据响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreCredentialResponse {
+    pub success: bool,
+    pub message: String,
+    /// 恢复后的凭据 ID（原 ID 被占用时为新分配的 ID）
+    pub credential_id: u64,
+}
+
 // ============ 余额查询 ============
 
 /// 余额查询响应
@@ -168,6 +370,257 @@ pub struct LoadBalancingModeResponse {
     pub mode: String,
 }
 
+// ============ 累计指标 ============
+
+/// 累计运行指标响应（跨重启持续累计）
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsResponse {
+    /// 累计成功请求数
+    pub requests_total: u64,
+    /// 累计失败请求数
+    pub failures_total: u64,
+    /// 累计输入 token 数
+    pub input_tokens_total: u64,
+    /// 累计输出 token 数
+    pub output_tokens_total: u64,
+    /// 累计背压事件数（客户端读取过慢）
+    pub backpressure_events_total: u64,
+    /// 开始累计的时间（RFC3339 格式）
+    pub since: Option<String>,
+    /// 请求转换耗时与各压缩步骤的耗时、节省字节数直方图（仅当前进程）
+    pub conversion: ConversionMetricsSnapshot,
+    /// 选择凭据的等待耗时直方图（总体与各凭据，仅当前进程）
+    pub acquire_wait: WaitMetricsSnapshot,
+    /// 上游请求体与响应体字节数直方图（总体、各模型与各凭据，仅当前进程）
+    pub upstream_bytes: SizeMetricsSnapshot,
+    /// 响应流中各未知事件类型的出现次数（仅当前进程）
+    pub unknown_events: BTreeMap<String, u64>,
+}
+
+/// 单个客户端 API Key 的累计用量
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyUsageItem {
+    /// API Key 名称（主 apiKey 为 "default"）
+    pub name: String,
+    /// 累计完成的请求数
+    pub requests_total: u64,
+    /// 累计输入 token 数（估算值）
+    pub input_tokens_total: u64,
+    /// 累计输出 token 数（估算值）
+    pub output_tokens_total: u64,
+    /// 最后一次请求时间（RFC3339 格式）
+    pub last_used_at: Option<String>,
+}
+
+/// 按客户端 API Key 统计的用量响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyUsageResponse {
+    /// 各 API Key 的用量（按名称排序）
+    pub keys: Vec<KeyUsageItem>,
+    /// 开始累计的时间（RFC3339 格式）
+    pub since: Option<String>,
+}
+
+// ============ 凭据校验 ============
+
+/// 单个凭据的校验结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialValidationItem {
+    /// 凭据 ID
+    pub id: u64,
+    /// 用户邮箱
+    pub email: Option<String>,
+    /// 认证方式
+    pub auth_method: Option<String>,
+    /// 校验状态（valid / invalid / truncated）
+    pub status: String,
+    /// 失败原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 刷新后的 Token 过期时间
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
+
+/// 凭据校验响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidateCredentialsResponse {
+    /// 凭据总数
+    pub total: usize,
+    /// 有效凭据数
+    pub valid: usize,
+    /// 无效凭据数
+    pub invalid: usize,
+    /// refreshToken 已截断的凭据数
+    pub truncated: usize,
+    /// 各凭据的校验结果（按 ID 排序）
+    pub results: Vec<CredentialValidationItem>,
+}
+
+/// 立即刷新 Token 请求
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshTokensRequest {
+    /// 要刷新的凭据 ID（省略表示全部凭据）
+    #[serde(default)]
+    pub ids: Option<Vec<u64>>,
+}
+
+/// 单个凭据的刷新结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshTokenItem {
+    /// 凭据 ID
+    pub id: u64,
+    /// 是否刷新成功
+    pub success: bool,
+    /// 刷新后的 Token 过期时间
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    /// 失败原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 立即刷新 Token 响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshTokensResponse {
+    /// 刷新的凭据数
+    pub total: usize,
+    /// 刷新成功数
+    pub refreshed: usize,
+    /// 刷新失败数
+    pub failed: usize,
+    /// 各凭据的刷新结果
+    pub results: Vec<RefreshTokenItem>,
+}
+
+// ============ 每日请求上限 ============
+
+/// 凭据池（凭据文件）的每日请求计数
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolDailyUsage {
+    /// 凭据文件路径
+    pub path: Option<String>,
+    /// 今日上游请求数
+    pub requests_today: u64,
+    /// 每日上限（null 表示不限制）
+    pub daily_limit: Option<u64>,
+}
+
+/// 每日请求上限状态响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyLimitResponse {
+    /// 计数所属日期（YYYY-MM-DD，按 dailyResetTimezone 计算）
+    pub date: String,
+    /// 计数重置时区（dailyResetTimezone）
+    pub timezone: String,
+    /// 下次重置时间（RFC3339 格式）
+    pub reset_at: String,
+    /// 今日请求数
+    pub requests_today: u64,
+    /// 全局每日上限（null 表示不限制）
+    pub daily_limit: Option<u64>,
+    /// 今日是否已临时解除限制
+    pub overridden: bool,
+    /// 各凭据池的计数
+    pub pools: Vec<PoolDailyUsage>,
+}
+
+/// 凭据池再平衡状态响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RebalanceResponse {
+    /// 定期分析间隔（秒，0 表示未启用定期分析）
+    pub interval_secs: u64,
+    /// 是否自动应用建议
+    pub autopilot: bool,
+    /// 最近一次分析结果（尚未分析时为 null）
+    pub report: Option<RebalanceReport>,
+}
+
+/// 亲和绑定列表响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AffinityBindingsResponse {
+    /// 空闲过期时间（秒，0 表示不过期）
+    pub ttl_secs: u64,
+    /// 最大绑定数量（0 表示不限制）
+    pub max_entries: usize,
+    /// 当前绑定（最近使用的在前）
+    pub bindings: Vec<AffinityBindingSnapshot>,
+}
+
+/// 解除亲和绑定请求（key 与 credentialId 至少指定一个，同时指定时需同时匹配）
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnbindAffinityRequest {
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub credential_id: Option<u64>,
+}
+
+/// 解除亲和绑定响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnbindAffinityResponse {
+    /// 解除的绑定数量
+    pub removed: usize,
+}
+
+/// 上游错误调试转储列表响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugDumpsResponse {
+    /// 是否已配置 debugDumpDir
+    pub enabled: bool,
+    /// 转储文件（最新的在前）
+    pub dumps: Vec<DumpInfo>,
+}
+
+/// 实例运行状态响应（滚动部署时的连接排空）
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeStatusResponse {
+    /// 是否处于排空模式（拒绝新请求，就绪检查返回 503）
+    pub draining: bool,
+    /// 活跃的流式响应数量（所有租户）
+    pub active_streams: usize,
+    /// 执行中的请求数量（仅在启用 maxConcurrentPerCredential 时统计）
+    pub in_flight_requests: usize,
+    /// 排队等待的请求数量
+    pub queued_requests: usize,
+    /// 正在刷新 Token 的凭据 ID
+    pub refreshing_credentials: Vec<u64>,
+    /// 按上游 Date 响应头估算的时钟偏差（上游时间 - 本机时间，秒）
+    pub clock_skew_secs: i64,
+}
+
+/// 进入/退出排空模式请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetDrainingRequest {
+    /// true = 进入排空模式，false = 恢复接收请求
+    pub draining: bool,
+}
+
+/// 解除/恢复每日请求上限请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetDailyLimitOverrideRequest {
+    /// true = 解除今日的限制（次日自动恢复），false = 立即恢复限制
+    pub overridden: bool,
+}
+
 /// 设置负载均衡模式请求
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
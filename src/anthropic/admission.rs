@@ -0,0 +1,405 @@
+//! 请求准入控制与优先级队列
+//!
+//! 客户端可通过 `X-Kiro-Priority: interactive|batch` 声明请求优先级（默认 interactive）。
+//! 当并发请求数达到上限（每个可用凭据的并发数 × 可用凭据数）时：
+//! - 新请求进入等待队列，interactive 请求总是先于 batch 请求被放行
+//! - 队列已满时，batch 请求直接被拒绝；interactive 请求会挤掉队尾的 batch 请求
+//! - 等待超时的请求被拒绝
+//!
+//! 被拒绝的请求由调用方返回 429。
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{body::Body, http::HeaderMap, response::Response};
+use futures::StreamExt;
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+use crate::common::i18n;
+
+/// 请求优先级 Header
+pub const PRIORITY_HEADER: &str = "x-kiro-priority";
+
+/// 请求优先级
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestPriority {
+    /// 交互式请求（默认），凭据紧张时优先放行
+    Interactive,
+    /// 批处理请求，凭据紧张时最先被拒绝
+    Batch,
+}
+
+impl RequestPriority {
+    /// 从请求头解析优先级，缺失或无法识别时视为 interactive
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        match headers
+            .get(PRIORITY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("batch") => Self::Batch,
+            _ => Self::Interactive,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Interactive => "interactive",
+            Self::Batch => "batch",
+        }
+    }
+}
+
+/// 准入失败原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdmissionError {
+    /// 等待队列已满
+    QueueFull,
+    /// 排队中被更高优先级的请求挤出
+    Preempted,
+    /// 排队等待超时
+    Timeout,
+}
+
+impl std::fmt::Display for AdmissionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdmissionError::QueueFull => f.write_str(i18n::pick(
+                "凭据并发已满且等待队列已满",
+                "All credentials are busy and the wait queue is full",
+            )),
+            AdmissionError::Preempted => f.write_str(i18n::pick(
+                "排队中被更高优先级的请求抢占",
+                "Preempted by a higher priority request while queued",
+            )),
+            AdmissionError::Timeout => f.write_str(i18n::pick(
+                "排队等待可用凭据超时",
+                "Timed out waiting for an available credential",
+            )),
+        }
+    }
+}
+
+impl std::error::Error for AdmissionError {}
+
+/// 排队中的请求
+struct Waiter {
+    id: u64,
+    /// true = 已放行（in_flight 已计入），false = 被抢占
+    tx: oneshot::Sender<bool>,
+}
+
+#[derive(Default)]
+struct AdmissionState {
+    in_flight: usize,
+    /// 最近一次计算的并发上限
+    capacity: usize,
+    interactive: VecDeque<Waiter>,
+    batch: VecDeque<Waiter>,
+    next_waiter_id: u64,
+}
+
+impl AdmissionState {
+    fn queued(&self) -> usize {
+        self.interactive.len() + self.batch.len()
+    }
+
+    /// 清理已断开（客户端取消）的等待者
+    fn prune_closed(&mut self) {
+        self.interactive.retain(|w| !w.tx.is_closed());
+        self.batch.retain(|w| !w.tx.is_closed());
+    }
+
+    fn remove_waiter(&mut self, id: u64) -> bool {
+        for queue in [&mut self.interactive, &mut self.batch] {
+            if let Some(pos) = queue.iter().position(|w| w.id == id) {
+                queue.remove(pos);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 按优先级放行等待者，直到达到并发上限
+    fn dispatch(&mut self) {
+        while self.in_flight < self.capacity {
+            let Some(waiter) = self
+                .interactive
+                .pop_front()
+                .or_else(|| self.batch.pop_front())
+            else {
+                break;
+            };
+            if waiter.tx.send(true).is_ok() {
+                self.in_flight += 1;
+            }
+        }
+    }
+}
+
+struct AdmissionInner {
+    max_per_credential: usize,
+    max_queued: usize,
+    queue_timeout: Duration,
+    state: Mutex<AdmissionState>,
+}
+
+/// 请求准入控制器
+#[derive(Clone)]
+pub struct AdmissionController {
+    inner: Arc<AdmissionInner>,
+}
+
+impl AdmissionController {
+    /// 创建准入控制器
+    ///
+    /// # Arguments
+    /// * `max_per_credential` - 每个可用凭据允许的并发请求数，0 表示不限制
+    /// * `max_queued` - 等待队列最大长度
+    /// * `queue_timeout` - 排队最长等待时间
+    pub fn new(max_per_credential: usize, max_queued: usize, queue_timeout: Duration) -> Self {
+        Self {
+            inner: Arc::new(AdmissionInner {
+                max_per_credential,
+                max_queued,
+                queue_timeout,
+                state: Mutex::new(AdmissionState::default()),
+            }),
+        }
+    }
+
+    /// 不限制并发的控制器
+    pub fn unlimited() -> Self {
+        Self::new(0, 0, Duration::ZERO)
+    }
+
+    /// 申请执行许可
+    ///
+    /// # Arguments
+    /// * `priority` - 请求优先级
+    /// * `available_credentials` - 当前可用凭据数量（用于计算并发上限）
+    pub async fn acquire(
+        &self,
+        priority: RequestPriority,
+        available_credentials: usize,
+    ) -> Result<AdmissionPermit, AdmissionError> {
+        if self.inner.max_per_credential == 0 {
+            return Ok(AdmissionPermit { inner: None });
+        }
+
+        let (mut rx, waiter_id) = {
+            let mut state = self.inner.state.lock();
+            state.capacity = self.inner.max_per_credential * available_credentials.max(1);
+            state.prune_closed();
+
+            // 有空闲并发且没有更高（或同级更早）的等待者时直接放行
+            let can_pass = match priority {
+                RequestPriority::Interactive => state.interactive.is_empty(),
+                RequestPriority::Batch => state.queued() == 0,
+            };
+            if can_pass && state.in_flight < state.capacity {
+                state.in_flight += 1;
+                return Ok(self.permit());
+            }
+
+            if state.queued() >= self.inner.max_queued {
+                match priority {
+                    RequestPriority::Batch => return Err(AdmissionError::QueueFull),
+                    RequestPriority::Interactive => match state.batch.pop_back() {
+                        Some(evicted) => {
+                            let _ = evicted.tx.send(false);
+                        }
+                        None => return Err(AdmissionError::QueueFull),
+                    },
+                }
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let id = state.next_waiter_id;
+            state.next_waiter_id += 1;
+            let waiter = Waiter { id, tx };
+            match priority {
+                RequestPriority::Interactive => state.interactive.push_back(waiter),
+                RequestPriority::Batch => state.batch.push_back(waiter),
+            }
+            (rx, id)
+        };
+
+        match tokio::time::timeout(self.inner.queue_timeout, &mut rx).await {
+            Ok(Ok(true)) => Ok(self.permit()),
+            Ok(Ok(false)) | Ok(Err(_)) => Err(AdmissionError::Preempted),
+            Err(_) => {
+                let removed = self.inner.state.lock().remove_waiter(waiter_id);
+                if removed {
+                    return Err(AdmissionError::Timeout);
+                }
+                // 超时与放行同时发生：以实际收到的结果为准
+                match rx.try_recv() {
+                    Ok(true) => Ok(self.permit()),
+                    _ => Err(AdmissionError::Preempted),
+                }
+            }
+        }
+    }
+
+    fn permit(&self) -> AdmissionPermit {
+        AdmissionPermit {
+            inner: Some(self.inner.clone()),
+        }
+    }
+
+    /// 当前执行中的请求数量
+    pub fn in_flight(&self) -> usize {
+        self.inner.state.lock().in_flight
+    }
+
+    /// 当前排队等待的请求数量
+    pub fn queued(&self) -> usize {
+        let mut state = self.inner.state.lock();
+        state.prune_closed();
+        state.queued()
+    }
+}
+
+/// 执行许可，Drop 时释放并发名额并放行下一个等待者
+pub struct AdmissionPermit {
+    inner: Option<Arc<AdmissionInner>>,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            let mut state = inner.state.lock();
+            state.in_flight = state.in_flight.saturating_sub(1);
+            state.dispatch();
+        }
+    }
+}
+
+/// 将执行许可（及其他需随响应释放的许可）绑定到响应体上，流式响应结束（或客户端断开）时才释放
+pub fn hold_permit<P: Send + 'static>(response: Response, permit: P) -> Response {
+    response.map(|body| {
+        let stream = body.into_data_stream().map(move |chunk| {
+            let _permit = &permit;
+            chunk
+        });
+        Body::from_stream(stream)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            RequestPriority::from_headers(&headers),
+            RequestPriority::Interactive
+        );
+
+        headers.insert(PRIORITY_HEADER, "Batch".parse().unwrap());
+        assert_eq!(
+            RequestPriority::from_headers(&headers),
+            RequestPriority::Batch
+        );
+
+        headers.insert(PRIORITY_HEADER, "unknown".parse().unwrap());
+        assert_eq!(
+            RequestPriority::from_headers(&headers),
+            RequestPriority::Interactive
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_always_admits() {
+        let controller = AdmissionController::unlimited();
+        let _a = controller.acquire(RequestPriority::Batch, 1).await.unwrap();
+        let _b = controller.acquire(RequestPriority::Batch, 1).await.unwrap();
+        assert_eq!(controller.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_interactive_jumps_ahead_of_batch() {
+        let controller = AdmissionController::new(1, 8, Duration::from_secs(5));
+        let first = controller
+            .acquire(RequestPriority::Interactive, 1)
+            .await
+            .unwrap();
+
+        let batch = tokio::spawn({
+            let controller = controller.clone();
+            async move { controller.acquire(RequestPriority::Batch, 1).await }
+        });
+        tokio::task::yield_now().await;
+        let interactive = tokio::spawn({
+            let controller = controller.clone();
+            async move { controller.acquire(RequestPriority::Interactive, 1).await }
+        });
+        tokio::task::yield_now().await;
+
+        // 释放后应先放行 interactive
+        drop(first);
+        let interactive_permit = interactive.await.unwrap().unwrap();
+        assert!(!batch.is_finished());
+
+        drop(interactive_permit);
+        assert!(batch.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_batch_rejected_first_when_queue_full() {
+        let controller = AdmissionController::new(1, 1, Duration::from_secs(5));
+        let _running = controller
+            .acquire(RequestPriority::Interactive, 1)
+            .await
+            .unwrap();
+
+        let queued_batch = tokio::spawn({
+            let controller = controller.clone();
+            async move { controller.acquire(RequestPriority::Batch, 1).await }
+        });
+        tokio::task::yield_now().await;
+
+        // 队列已满：新的 batch 请求直接被拒绝
+        assert_eq!(
+            controller
+                .acquire(RequestPriority::Batch, 1)
+                .await
+                .err()
+                .unwrap(),
+            AdmissionError::QueueFull
+        );
+
+        // 新的 interactive 请求挤掉排队中的 batch 请求
+        let interactive = tokio::spawn({
+            let controller = controller.clone();
+            async move { controller.acquire(RequestPriority::Interactive, 1).await }
+        });
+        assert_eq!(
+            queued_batch.await.unwrap().err().unwrap(),
+            AdmissionError::Preempted
+        );
+        interactive.abort();
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout() {
+        let controller = AdmissionController::new(1, 4, Duration::from_millis(20));
+        let _running = controller
+            .acquire(RequestPriority::Interactive, 1)
+            .await
+            .unwrap();
+
+        let err = controller
+            .acquire(RequestPriority::Interactive, 1)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err, AdmissionError::Timeout);
+    }
+}
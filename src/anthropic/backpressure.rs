@@ -0,0 +1,134 @@
+//! 流式响应背压
+//!
+//! 后台任务读取上游并生成 SSE 字节，通过有界缓冲交给客户端连接写出。
+//! 等待客户端读取的字节数达到高水位（`streamHighWaterBytes`）时按 `streamBackpressurePolicy` 处理：
+//! - `pause`：暂停读取上游，待客户端读取腾出空间后继续
+//! - `disconnect`：断开客户端连接并停止读取上游，尽快释放凭据与准入名额
+//!
+//! 每次达到高水位都计入累计指标的背压事件数。
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::body::Body;
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+
+use crate::kiro::metrics::Metrics;
+use crate::model::config::{BackpressurePolicy, Config};
+
+type BufferedChunk = Result<(Bytes, OwnedSemaphorePermit), std::io::Error>;
+
+/// 将 SSE 字节流包装为带背压的响应体（高水位为 0 时直接透传）
+pub fn bounded_body<S>(stream: S, config: &Config, metrics: Arc<Metrics>) -> Body
+where
+    S: Stream<Item = Result<Bytes, Infallible>> + Send + 'static,
+{
+    let high_water = config.stream_high_water_bytes.min(u32::MAX as usize);
+    if high_water == 0 {
+        return Body::from_stream(stream);
+    }
+    let policy = config.stream_backpressure_policy;
+
+    let semaphore = Arc::new(Semaphore::new(high_water));
+    let (tx, rx) = mpsc::unbounded_channel::<BufferedChunk>();
+    tokio::spawn(async move {
+        let mut stream = Box::pin(stream);
+        while let Some(Ok(chunk)) = stream.next().await {
+            let size = chunk.len().min(high_water) as u32;
+            let permit = match semaphore.clone().try_acquire_many_owned(size) {
+                Ok(permit) => permit,
+                Err(_) => {
+                    metrics.record_backpressure();
+                    match policy {
+                        BackpressurePolicy::Disconnect => {
+                            tracing::warn!(
+                                "客户端读取过慢，缓冲达到 {} 字节，断开连接",
+                                high_water
+                            );
+                            let _ = tx.send(Err(std::io::Error::other("client too slow")));
+                            return;
+                        }
+                        BackpressurePolicy::Pause => {
+                            tracing::debug!("客户端读取过慢，暂停读取上游");
+                            tokio::select! {
+                                permit = semaphore.clone().acquire_many_owned(size) => match permit {
+                                    Ok(permit) => permit,
+                                    Err(_) => return,
+                                },
+                                // 客户端已断开
+                                _ = tx.closed() => return,
+                            }
+                        }
+                    }
+                }
+            };
+            if tx.send(Ok((chunk, permit))).is_err() {
+                return;
+            }
+        }
+    });
+
+    // 客户端连接取走数据后释放占用的缓冲额度
+    Body::from_stream(stream::unfold(rx, |mut rx| async move {
+        let chunk = rx.recv().await?;
+        Some((chunk.map(|(bytes, _permit)| bytes), rx))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks() -> impl Stream<Item = Result<Bytes, Infallible>> {
+        stream::iter((0..3).map(|_| Ok(Bytes::from(vec![b'x'; 10]))))
+    }
+
+    fn config(high_water: usize, policy: BackpressurePolicy) -> Config {
+        let mut config = Config::default();
+        config.stream_high_water_bytes = high_water;
+        config.stream_backpressure_policy = policy;
+        config
+    }
+
+    #[tokio::test]
+    async fn test_pause_delivers_everything() {
+        let metrics = Arc::new(Metrics::new());
+        let body = bounded_body(
+            chunks(),
+            &config(15, BackpressurePolicy::Pause),
+            metrics.clone(),
+        );
+        // 等待后台任务填满缓冲
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(bytes.len(), 30);
+        assert!(metrics.snapshot().backpressure_events_total >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_aborts_slow_client() {
+        let metrics = Arc::new(Metrics::new());
+        let body = bounded_body(
+            chunks(),
+            &config(15, BackpressurePolicy::Disconnect),
+            metrics.clone(),
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        assert!(axum::body::to_bytes(body, usize::MAX).await.is_err());
+        assert_eq!(metrics.snapshot().backpressure_events_total, 1);
+
+        // 高水位为 0 时不限制
+        let body = bounded_body(
+            chunks(),
+            &config(0, BackpressurePolicy::Disconnect),
+            metrics.clone(),
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(bytes.len(), 30);
+    }
+}
@@ -0,0 +1,321 @@
+//! 客户端 IP 识别与按 IP 限流
+//!
+//! 部署在 nginx 等反向代理之后时，所有连接的对端地址都是代理本身。
+//! 对端地址属于 `trustedProxies` 时，按以下顺序识别真实客户端 IP：
+//! - `X-Forwarded-For`：从右往左跳过可信代理，第一个不可信（或无法解析）的条目即为客户端
+//! - `X-Real-IP`：仅在没有 `X-Forwarded-For` 时使用
+//!
+//! 对端不可信时忽略这两个请求头，防止客户端伪造 IP 绕过限流。
+//! 识别出的 IP 写入请求扩展（[`ClientIp`]），并用于日志字段、按 IP 限流（`perIpRequestsPerMinute`）
+//! 与可选的凭据亲和（`clientIpAffinity`）。
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderValue, Request, StatusCode, header::RETRY_AFTER},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use parking_lot::Mutex;
+use tracing::Instrument;
+
+use crate::common::i18n;
+use crate::model::config::Config;
+
+use super::middleware::AppState;
+use super::types::ErrorResponse;
+
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+const REAL_IP_HEADER: &str = "x-real-ip";
+
+/// 按 IP 限流的统计窗口
+const LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// 识别出的客户端 IP（由客户端 IP 中间件写入请求扩展）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// 可信代理地址段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    /// 解析 IP 或 CIDR（如 `10.0.0.0/8`、`::1`）
+    fn parse(s: &str) -> Option<Self> {
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix.parse::<u8>().ok()?)),
+            None => (s.trim(), None),
+        };
+        let network: IpAddr = addr.parse().ok()?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(Self { network, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, canonical(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_eq(u32::from(net).into(), u32::from(ip).into(), 32, self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_eq(u128::from(net), u128::from(ip), 128, self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_eq(a: u128, b: u128, bits: u8, prefix: u8) -> bool {
+    let shift = u32::from(bits - prefix);
+    shift >= u32::from(bits) || (a >> shift) == (b >> shift)
+}
+
+/// IPv4 映射的 IPv6 地址（`::ffff:a.b.c.d`）按 IPv4 处理
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        v4 => v4,
+    }
+}
+
+/// 可信代理列表
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    ranges: Vec<IpRange>,
+}
+
+impl TrustedProxies {
+    /// 解析 `trustedProxies` 配置，返回第一个无法解析的条目作为错误
+    pub fn parse(entries: &[String]) -> Result<Self, String> {
+        let ranges = entries
+            .iter()
+            .map(|entry| IpRange::parse(entry).ok_or_else(|| entry.clone()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { ranges })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+
+    /// 根据连接对端地址与转发请求头识别客户端 IP
+    pub fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = canonical(peer);
+        if !self.contains(peer) {
+            return peer;
+        }
+
+        let forwarded: Vec<&str> = headers
+            .get_all(FORWARDED_FOR_HEADER)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .collect();
+        if forwarded.is_empty() {
+            return headers
+                .get(REAL_IP_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<IpAddr>().ok())
+                .map_or(peer, canonical);
+        }
+
+        // 从右往左跳过可信代理；左侧内容可由客户端任意填写，只信任可信代理追加的部分
+        let mut client = peer;
+        for part in forwarded.iter().rev() {
+            let Ok(ip) = part.parse::<IpAddr>().map(canonical) else {
+                // 无法解析的条目视为客户端，只能以追加它的代理地址识别
+                return client;
+            };
+            client = ip;
+            if !self.contains(ip) {
+                return ip;
+            }
+        }
+        // 转发链全部可信时取最左侧地址
+        client
+    }
+}
+
+/// 按 IP 的固定窗口请求计数
+#[derive(Debug)]
+pub struct PerIpLimiter {
+    limit: u64,
+    windows: Mutex<HashMap<IpAddr, (Instant, u64)>>,
+}
+
+impl PerIpLimiter {
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 记录一次请求；超过上限时返回当前窗口剩余时长
+    fn try_acquire(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut windows = self.windows.lock();
+        if windows.len() > 4096 {
+            windows.retain(|_, (started, _)| now.duration_since(*started) < LIMIT_WINDOW);
+        }
+        let (started, count) = windows.entry(ip).or_insert((now, 0));
+        if now.duration_since(*started) >= LIMIT_WINDOW {
+            *started = now;
+            *count = 0;
+        }
+        if *count >= self.limit {
+            return Err(LIMIT_WINDOW.saturating_sub(now.duration_since(*started)));
+        }
+        *count += 1;
+        Ok(())
+    }
+}
+
+/// 客户端 IP 识别配置
+#[derive(Debug, Clone, Default)]
+pub struct ClientIpPolicy {
+    trusted_proxies: TrustedProxies,
+    limiter: Option<Arc<PerIpLimiter>>,
+    /// 请求没有 metadata.user_id 时以客户端 IP 作为凭据亲和键
+    pub affinity: bool,
+}
+
+impl ClientIpPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            // 配置已在启动时校验，这里不会失败
+            trusted_proxies: TrustedProxies::parse(&config.trusted_proxies).unwrap_or_default(),
+            limiter: (config.per_ip_requests_per_minute > 0)
+                .then(|| Arc::new(PerIpLimiter::new(config.per_ip_requests_per_minute))),
+            affinity: config.client_ip_affinity,
+        }
+    }
+}
+
+/// 客户端 IP 中间件：识别 IP、按 IP 限流，并为请求日志附加 `client_ip` 字段
+///
+/// 需要以 `into_make_service_with_connect_info::<SocketAddr>()` 启动服务，否则只能使用转发请求头之外的默认地址
+pub async fn client_ip_middleware(
+    State(state): State<AppState>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or(IpAddr::from([127, 0, 0, 1]), |info| info.0.ip());
+    let policy = &state.client_ip;
+    let ip = policy.trusted_proxies.resolve(peer, request.headers());
+
+    if let Some(limiter) = &policy.limiter
+        && let Err(retry_after) = limiter.try_acquire(ip, Instant::now())
+    {
+        tracing::warn!(client_ip = %ip, "请求被按 IP 限流拒绝");
+        let error = ErrorResponse::new(
+            "rate_limit_error",
+            i18n::pick(
+                format!("该 IP 每分钟请求数超过上限（{}）", limiter.limit),
+                format!(
+                    "Too many requests from this IP (limit {} per minute)",
+                    limiter.limit
+                ),
+            ),
+        );
+        let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(error)).into_response();
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(retry_after.as_secs().max(1)));
+        return response;
+    }
+
+    request.extensions_mut().insert(ClientIp(ip));
+    let span = tracing::info_span!("client", client_ip = %ip);
+    next.run(request).instrument(span).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trusted(entries: &[&str]) -> TrustedProxies {
+        let entries: Vec<String> = entries.iter().map(|s| s.to_string()).collect();
+        TrustedProxies::parse(&entries).unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_resolve_client_ip() {
+        let proxies = trusted(&["127.0.0.1", "10.0.0.0/8"]);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            FORWARDED_FOR_HEADER,
+            "198.51.100.7, 203.0.113.9, 10.1.2.3".parse().unwrap(),
+        );
+
+        // 从右往左跳过可信代理
+        assert_eq!(
+            proxies.resolve(ip("127.0.0.1"), &headers),
+            ip("203.0.113.9")
+        );
+        // 对端不可信时忽略转发请求头
+        assert_eq!(proxies.resolve(ip("192.0.2.1"), &headers), ip("192.0.2.1"));
+        // IPv4 映射的 IPv6 对端地址按 IPv4 匹配
+        assert_eq!(
+            proxies.resolve(ip("::ffff:127.0.0.1"), &headers),
+            ip("203.0.113.9")
+        );
+
+        // 客户端在转发链左侧填入无法解析的内容时仍按代理追加的地址识别，且不回退到 X-Real-IP
+        let mut headers = HeaderMap::new();
+        headers.insert(FORWARDED_FOR_HEADER, "x, 203.0.113.9".parse().unwrap());
+        headers.insert(REAL_IP_HEADER, "198.51.100.7".parse().unwrap());
+        assert_eq!(
+            proxies.resolve(ip("127.0.0.1"), &headers),
+            ip("203.0.113.9")
+        );
+        // 可信代理之后的条目无法解析时以追加它的代理地址识别
+        headers.insert(FORWARDED_FOR_HEADER, "x, 10.1.2.3".parse().unwrap());
+        assert_eq!(proxies.resolve(ip("127.0.0.1"), &headers), ip("10.1.2.3"));
+        headers.insert(FORWARDED_FOR_HEADER, "x".parse().unwrap());
+        assert_eq!(proxies.resolve(ip("127.0.0.1"), &headers), ip("127.0.0.1"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(REAL_IP_HEADER, "198.51.100.7".parse().unwrap());
+        assert_eq!(
+            proxies.resolve(ip("10.9.9.9"), &headers),
+            ip("198.51.100.7")
+        );
+        assert_eq!(
+            TrustedProxies::default().resolve(ip("10.9.9.9"), &headers),
+            ip("10.9.9.9")
+        );
+
+        let invalid = vec!["10.0.0.0/33".to_string()];
+        assert_eq!(TrustedProxies::parse(&invalid).unwrap_err(), "10.0.0.0/33");
+    }
+
+    #[test]
+    fn test_per_ip_limiter() {
+        let limiter = PerIpLimiter::new(2);
+        let now = Instant::now();
+        let a = ip("198.51.100.7");
+        assert!(limiter.try_acquire(a, now).is_ok());
+        assert!(limiter.try_acquire(a, now).is_ok());
+        let retry_after = limiter.try_acquire(a, now).unwrap_err();
+        assert_eq!(retry_after, LIMIT_WINDOW);
+        // 其他 IP 不受影响，窗口结束后重新计数
+        assert!(limiter.try_acquire(ip("198.51.100.8"), now).is_ok());
+        assert!(limiter.try_acquire(a, now + LIMIT_WINDOW).is_ok());
+    }
+}
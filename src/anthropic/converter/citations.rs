@@ -0,0 +1,91 @@
+//! 引用转换：Kiro 引用事件 → Anthropic citations
+//!
+//! 上游在联网搜索或文档问答时返回 citationEvent / supplementaryWebLinksEvent，
+//! 转换为文本块上的 `web_search_result_location` 引用（流式响应中为 `citations_delta`），
+//! 而不是丢弃或混入正文，便于客户端展示来源链接。
+//! 上游不提供 `encrypted_index`，固定为空字符串。
+
+use serde_json::{Value, json};
+
+use crate::kiro::model::events::Event;
+
+/// 从上游事件中提取 Anthropic 引用（非引用事件返回空列表）
+pub fn citations_from_event(event: &Event) -> Vec<Value> {
+    match event {
+        Event::Citation(citation) => citation
+            .citation_link
+            .as_deref()
+            .map(|url| {
+                web_search_citation(url, None, citation.citation_text.as_deref().unwrap_or(""))
+            })
+            .into_iter()
+            .collect(),
+        Event::SupplementaryWebLinks(links) => links
+            .supplementary_web_links
+            .iter()
+            .map(|link| {
+                web_search_citation(
+                    &link.url,
+                    link.title.as_deref(),
+                    link.snippet.as_deref().unwrap_or(""),
+                )
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn web_search_citation(url: &str, title: Option<&str>, cited_text: &str) -> Value {
+    json!({
+        "type": "web_search_result_location",
+        "url": url,
+        "title": title,
+        "cited_text": cited_text,
+        "encrypted_index": ""
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_citations_from_event() {
+        let citation = Event::Citation(
+            serde_json::from_value(json!({
+                "citationText": "Rust 1.0 was released in 2015",
+                "citationLink": "https://blog.rust-lang.org"
+            }))
+            .unwrap(),
+        );
+        assert_eq!(
+            citations_from_event(&citation),
+            vec![json!({
+                "type": "web_search_result_location",
+                "url": "https://blog.rust-lang.org",
+                "title": null,
+                "cited_text": "Rust 1.0 was released in 2015",
+                "encrypted_index": ""
+            })]
+        );
+
+        // 没有链接的引用无法展示来源，直接忽略
+        let no_link =
+            Event::Citation(serde_json::from_value(json!({"citationText": "x"})).unwrap());
+        assert!(citations_from_event(&no_link).is_empty());
+
+        let links = Event::SupplementaryWebLinks(
+            serde_json::from_value(json!({
+                "supplementaryWebLinks": [
+                    {"url": "https://a.example", "title": "A"},
+                    {"url": "https://b.example", "snippet": "B"}
+                ]
+            }))
+            .unwrap(),
+        );
+        let citations = citations_from_event(&links);
+        assert_eq!(citations.len(), 2);
+        assert_eq!(citations[0]["title"], "A");
+        assert_eq!(citations[1]["cited_text"], "B");
+    }
+}
@@ -0,0 +1,89 @@
+//! 请求转换的 golden 测试
+//!
+//! `testdata/<name>.request.json` 为客户端发来的 Anthropic 请求，
+//! `testdata/<name>.kiro.json` 为期望的 Kiro `conversationState`。
+//! 转换使用固定种子的 [`IdGenerator`]，生成的 ID 每次运行都相同，可直接逐字节比较。
+//!
+//! 有意修改转换逻辑后，使用 `UPDATE_GOLDEN=1 cargo test golden` 重新生成期望文件，并检查 diff。
+
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use crate::anthropic::types::MessagesRequest;
+
+use super::{ConversionOptions, IdGenerator, convert_request};
+
+/// 覆盖的请求形态
+const CASES: &[&str] = &[
+    // Claude Code：system 数组、thinking、session metadata、tool_use/tool_result 往返
+    "claude_code",
+    // new-api 等中转：system 字符串、纯文本历史、图片
+    "new_api",
+    // LangChain：连续 user 消息、并行工具调用、孤立的 tool_use/tool_result
+    "langchain",
+];
+
+fn testdata_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/anthropic/converter/testdata")
+}
+
+/// golden 测试使用的 ID 种子
+const ID_SEED: u64 = 0x6b69726f;
+
+fn convert_case(name: &str) -> Value {
+    let path = testdata_dir().join(format!("{}.request.json", name));
+    let content = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{:?}: {}", path, e));
+    let request: MessagesRequest = serde_json::from_str(&content).unwrap();
+
+    let options = ConversionOptions {
+        id_generator: IdGenerator::Seeded(ID_SEED),
+        ..ConversionOptions::default()
+    };
+    let result = convert_request(&request, &options).unwrap_or_else(|e| panic!("{}: {}", name, e));
+    serde_json::to_value(&result.conversation_state).unwrap()
+}
+
+#[test]
+fn test_golden_conversions() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut mismatches = Vec::new();
+
+    for name in CASES {
+        let actual = convert_case(name);
+        let path = testdata_dir().join(format!("{}.kiro.json", name));
+
+        if update {
+            let json = serde_json::to_string_pretty(&actual).unwrap();
+            std::fs::write(&path, json + "\n").unwrap();
+            continue;
+        }
+
+        let expected: Value = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_else(|| panic!("缺少期望文件 {:?}，请使用 UPDATE_GOLDEN=1 生成", path));
+        if actual != expected {
+            mismatches.push(format!(
+                "{}:\n{}",
+                name,
+                serde_json::to_string_pretty(&actual).unwrap()
+            ));
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "转换结果与 golden 文件不一致（有意修改时使用 UPDATE_GOLDEN=1 重新生成）:\n{}",
+        mismatches.join("\n\n")
+    );
+}
+
+#[test]
+fn test_seeded_conversion_is_byte_identical() {
+    for name in CASES {
+        let first = serde_json::to_string(&convert_case(name)).unwrap();
+        let second = serde_json::to_string(&convert_case(name)).unwrap();
+        assert_eq!(first, second, "{}", name);
+    }
+}
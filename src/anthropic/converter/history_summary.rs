@@ -0,0 +1,183 @@
+//! 历史摘要压缩
+//!
+//! 会话累计 token 超过 `conversationTokenBudget` 时，较早的历史消息被替换为一段摘要：
+//! 每条消息保留开头的文本以及调用的工具名称，工具结果与图片只保留数量。
+//! 摘要以提示语开头，告知模型较早的上下文已被压缩。
+
+use serde_json::Value;
+
+use crate::anthropic::types::Message;
+
+/// 摘要开头的提示语（发送给模型）
+pub(super) const SUMMARY_NOTICE: &str = "\
+[Context notice] The earlier part of this conversation exceeded the conversation token budget \
+and was automatically summarized below. Full file contents, tool outputs and images were omitted; \
+re-read files or re-run tools if you need those details.";
+
+/// 每条消息在摘要中保留的最大字符数
+const MAX_LINE_CHARS: usize = 300;
+
+/// 摘要的最大字符数（超出时省略最早的消息）
+const MAX_SUMMARY_CHARS: usize = 12_000;
+
+/// 选择摘要的截止位置：`messages[..cut]` 被压缩为摘要
+///
+/// 在 `history_end` 之前至少保留 `keep` 条消息；保留部分从一条紧跟 assistant、
+/// 且不含 tool_result 的 user 消息开始，避免拆开 tool_use/tool_result 配对。找不到时返回 None
+pub(super) fn summary_cut(messages: &[Message], history_end: usize, keep: usize) -> Option<usize> {
+    let start = history_end.saturating_sub(keep.max(1));
+    (1..=start).rev().find(|&i| {
+        messages[i].role == "user"
+            && messages[i - 1].role == "assistant"
+            && !has_block(&messages[i].content, "tool_result")
+    })
+}
+
+/// 生成摘要文本（以提示语开头）
+pub(super) fn summarize(messages: &[Message]) -> String {
+    let lines: Vec<String> = messages.iter().map(summarize_message).collect();
+
+    // 从最近的消息向前累计，超出上限时省略更早的消息
+    let mut total = 0;
+    let kept = lines
+        .iter()
+        .rev()
+        .take_while(|line| {
+            total += line.chars().count() + 1;
+            total <= MAX_SUMMARY_CHARS
+        })
+        .count();
+    let omitted = lines.len() - kept;
+
+    let mut summary = format!("{}\n\n", SUMMARY_NOTICE);
+    if omitted > 0 {
+        summary.push_str(&format!("- ({} earlier messages omitted)\n", omitted));
+    }
+    summary.push_str(&lines[omitted..].join("\n"));
+    summary
+}
+
+/// 单条消息的摘要行
+fn summarize_message(message: &Message) -> String {
+    let role = if message.role == "assistant" {
+        "Assistant"
+    } else {
+        "User"
+    };
+    let mut text = Vec::new();
+    let mut tools = Vec::new();
+    let (mut results, mut images) = (0, 0);
+    match &message.content {
+        Value::String(s) => text.push(s.as_str()),
+        Value::Array(blocks) => {
+            for block in blocks {
+                match block.get("type").and_then(Value::as_str) {
+                    Some("text") => text.extend(block.get("text").and_then(Value::as_str)),
+                    Some("tool_use") => tools.extend(block.get("name").and_then(Value::as_str)),
+                    Some("tool_result") => results += 1,
+                    Some("image") => images += 1,
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+
+    let mut line = format!("- {}: {}", role, truncate(&text.join(" ")));
+    if !tools.is_empty() {
+        line.push_str(&format!(" [called tools: {}]", tools.join(", ")));
+    }
+    if results > 0 {
+        line.push_str(&format!(" [{} tool result(s)]", results));
+    }
+    if images > 0 {
+        line.push_str(&format!(" [{} image(s)]", images));
+    }
+    line
+}
+
+/// 合并空白并截断到 `MAX_LINE_CHARS` 个字符
+fn truncate(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(MAX_LINE_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+fn has_block(content: &Value, block_type: &str) -> bool {
+    content.as_array().is_some_and(|blocks| {
+        blocks
+            .iter()
+            .any(|b| b.get("type").and_then(Value::as_str) == Some(block_type))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(role: &str, content: Value) -> Message {
+        Message {
+            role: role.to_string(),
+            content,
+        }
+    }
+
+    #[test]
+    fn test_summary_cut_keeps_tool_pairs() {
+        let messages = vec![
+            message("user", json!("hello")),
+            message("assistant", json!("hi")),
+            message("user", json!("read the file")),
+            message(
+                "assistant",
+                json!([{"type": "tool_use", "id": "t1", "name": "Read", "input": {}}]),
+            ),
+            message(
+                "user",
+                json!([{"type": "tool_result", "tool_use_id": "t1", "content": "data"}]),
+            ),
+            message("assistant", json!("done")),
+            message("user", json!("thanks")),
+        ];
+        // 保留 2 条时会从 tool_result 开始，向前找到不含 tool_result 的 user 消息
+        assert_eq!(summary_cut(&messages, 6, 2), Some(2));
+        assert_eq!(summary_cut(&messages, 6, 4), Some(2));
+        // 没有可用的截止位置
+        assert_eq!(summary_cut(&messages, 6, 6), None);
+        assert_eq!(summary_cut(&messages[..1], 1, 0), None);
+    }
+
+    #[test]
+    fn test_summarize() {
+        let messages = vec![
+            message("user", json!("please   fix\nthe bug")),
+            message(
+                "assistant",
+                json!([
+                    {"type": "text", "text": "Looking"},
+                    {"type": "tool_use", "id": "t1", "name": "Read", "input": {}}
+                ]),
+            ),
+            message(
+                "user",
+                json!([
+                    {"type": "tool_result", "tool_use_id": "t1", "content": "x"},
+                    {"type": "image", "source": {}}
+                ]),
+            ),
+        ];
+        let summary = summarize(&messages);
+        assert!(summary.starts_with(SUMMARY_NOTICE));
+        assert!(summary.contains("- User: please fix the bug"));
+        assert!(summary.contains("- Assistant: Looking [called tools: Read]"));
+        assert!(summary.contains("[1 tool result(s)] [1 image(s)]"));
+
+        let long = "a".repeat(MAX_LINE_CHARS + 10);
+        let line = summarize_message(&message("user", json!(long)));
+        assert!(line.ends_with('…'));
+        assert_eq!(line.chars().count(), "- User: ".len() + MAX_LINE_CHARS + 1);
+    }
+}
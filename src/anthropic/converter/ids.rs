@@ -0,0 +1,68 @@
+//! 转换时生成的 ID
+//!
+//! conversationId（没有会话标识时）与 agentContinuationId 默认随机生成，
+//! 同一请求每次转换的结果都不同。测试与按字节缓存转换结果时使用带种子的生成器，
+//! 同一种子下相同请求的转换结果完全一致。
+
+use uuid::Uuid;
+
+/// ID 生成器
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdGenerator {
+    /// 随机 UUID v4
+    #[default]
+    Random,
+    /// 由种子决定的 UUID v4 序列（每次转换都从同一位置开始）
+    #[allow(dead_code)]
+    Seeded(u64),
+}
+
+impl IdGenerator {
+    /// 开始一次转换使用的 ID 序列
+    pub(super) fn sequence(self) -> IdSequence {
+        IdSequence {
+            rng: match self {
+                Self::Random => None,
+                Self::Seeded(seed) => Some(fastrand::Rng::with_seed(seed)),
+            },
+        }
+    }
+}
+
+/// 单次转换内的 ID 序列
+pub(super) struct IdSequence {
+    rng: Option<fastrand::Rng>,
+}
+
+impl IdSequence {
+    /// 下一个 UUID 字符串
+    pub(super) fn next_uuid(&mut self) -> String {
+        match &mut self.rng {
+            None => Uuid::new_v4().to_string(),
+            Some(rng) => {
+                let bytes = rng.u128(..).to_le_bytes();
+                uuid::Builder::from_random_bytes(bytes)
+                    .into_uuid()
+                    .to_string()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_sequence_is_deterministic() {
+        let mut a = IdGenerator::Seeded(42).sequence();
+        let mut b = IdGenerator::Seeded(42).sequence();
+        let first = a.next_uuid();
+        assert_eq!(first, b.next_uuid());
+        assert_ne!(first, a.next_uuid());
+        assert_eq!(Uuid::parse_str(&first).unwrap().get_version_num(), 4);
+
+        let mut random = IdGenerator::Random.sequence();
+        assert_ne!(random.next_uuid(), random.next_uuid());
+    }
+}
@@ -0,0 +1,202 @@
+//! 图片数量与尺寸限制
+//!
+//! 与 Anthropic API 的限制对齐，在提交上游前检查，
+//! 避免超限的请求被上游以不明确的 400 拒绝。
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use crate::kiro::model::requests::conversation::KiroImage;
+
+use super::{ConversionError, ConversionOptions};
+
+/// 检查请求中所有图片（历史与当前消息）是否超出限制，限制为 0 表示不检查
+pub(super) fn check_image_limits<'a>(
+    images: impl IntoIterator<Item = &'a KiroImage>,
+    options: &ConversionOptions,
+) -> Result<(), ConversionError> {
+    let images: Vec<&KiroImage> = images.into_iter().collect();
+    if options.max_images_per_request > 0 && images.len() > options.max_images_per_request {
+        return Err(ConversionError::TooManyImages {
+            count: images.len(),
+            limit: options.max_images_per_request,
+        });
+    }
+
+    if options.max_image_bytes == 0 && options.max_image_dimension == 0 {
+        return Ok(());
+    }
+
+    for (i, image) in images.iter().enumerate() {
+        let index = i + 1;
+        let data = &image.source.bytes;
+
+        // base64 每 4 个字符对应 3 个字节
+        let bytes = data.len() / 4 * 3;
+        if options.max_image_bytes > 0 && bytes > options.max_image_bytes {
+            return Err(ConversionError::ImageTooLarge {
+                index,
+                bytes,
+                limit: options.max_image_bytes,
+            });
+        }
+
+        if options.max_image_dimension == 0 {
+            continue;
+        }
+        // 无法解码或识别的图片交给上游判断
+        let Some((width, height)) = STANDARD
+            .decode(data)
+            .ok()
+            .and_then(|decoded| image_dimensions(&decoded))
+        else {
+            continue;
+        };
+        if width > options.max_image_dimension || height > options.max_image_dimension {
+            return Err(ConversionError::ImageDimensionsTooLarge {
+                index,
+                width,
+                height,
+                limit: options.max_image_dimension,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// 从图片文件头读取宽高（支持 PNG、GIF、JPEG、WebP）
+pub(crate) fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let u16_be =
+        |at: usize| Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?) as u32);
+    let u16_le =
+        |at: usize| Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?) as u32);
+    let u24_le = |at: usize| {
+        let b = data.get(at..at + 3)?;
+        Some(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16)
+    };
+    let u32_be = |at: usize| Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?));
+
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some((u32_be(16)?, u32_be(20)?));
+    }
+    if data.starts_with(b"GIF8") {
+        return Some((u16_le(6)?, u16_le(8)?));
+    }
+    if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        return match data.get(12..16)? {
+            b"VP8 " => Some((u16_le(26)? & 0x3fff, u16_le(28)? & 0x3fff)),
+            b"VP8L" => {
+                let b = data.get(21..25)?;
+                let width = 1 + (b[0] as u32 | (b[1] as u32 & 0x3f) << 8);
+                let height =
+                    1 + (b[1] as u32 >> 6 | (b[2] as u32) << 2 | (b[3] as u32 & 0x0f) << 10);
+                Some((width, height))
+            }
+            b"VP8X" => Some((1 + u24_le(24)?, 1 + u24_le(27)?)),
+            _ => None,
+        };
+    }
+    if data.starts_with(&[0xff, 0xd8]) {
+        // 遍历 JPEG 段，找到 SOF（帧头）段
+        let mut pos = 2;
+        while pos + 4 <= data.len() {
+            if data[pos] != 0xff {
+                return None;
+            }
+            let marker = data[pos + 1];
+            match marker {
+                // 填充字节
+                0xff => pos += 1,
+                // 无长度的独立标记
+                0x01 | 0xd0..=0xd7 => pos += 2,
+                // SOF0-SOF15（排除 DHT、JPG、DAC）
+                0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                    return Some((u16_be(pos + 7)?, u16_be(pos + 5)?));
+                }
+                _ => pos += 2 + u16_be(pos + 2)? as usize,
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        data.extend(width.to_be_bytes());
+        data.extend(height.to_be_bytes());
+        data.extend([8, 6, 0, 0, 0]);
+        data
+    }
+
+    fn image(data: &[u8]) -> KiroImage {
+        KiroImage::from_base64("png", STANDARD.encode(data))
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        assert_eq!(image_dimensions(&png(640, 480)), Some((640, 480)));
+        assert_eq!(
+            image_dimensions(b"GIF89a\x20\x03\x58\x02\0\0"),
+            Some((800, 600))
+        );
+
+        // JPEG：APP0 段之后是 SOF0
+        let jpeg = [
+            0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, 0xff, 0xc0, 0x00, 0x11, 0x08, 0x02,
+            0x58, 0x03, 0x20,
+        ];
+        assert_eq!(image_dimensions(&jpeg), Some((800, 600)));
+
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0\0\0\0\0".to_vec();
+        webp.extend([0x1f, 0x03, 0x00, 0x57, 0x02, 0x00]);
+        assert_eq!(image_dimensions(&webp), Some((800, 600)));
+
+        assert_eq!(image_dimensions(b"not an image"), None);
+    }
+
+    #[test]
+    fn test_rejects_too_many_images() {
+        let options = ConversionOptions {
+            max_images_per_request: 2,
+            ..ConversionOptions::default()
+        };
+        let images = vec![image(&png(10, 10)); 3];
+        assert!(matches!(
+            check_image_limits(&images, &options),
+            Err(ConversionError::TooManyImages { count: 3, limit: 2 })
+        ));
+        assert!(check_image_limits(&images[..2], &options).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_oversized_dimensions() {
+        let options = ConversionOptions::default();
+        let images = vec![image(&png(100, 100)), image(&png(9000, 100))];
+        assert!(matches!(
+            check_image_limits(&images, &options),
+            Err(ConversionError::ImageDimensionsTooLarge {
+                index: 2,
+                width: 9000,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_rejects_oversized_bytes() {
+        let options = ConversionOptions {
+            max_image_bytes: 16,
+            ..ConversionOptions::default()
+        };
+        let images = vec![image(&png(10, 10))];
+        assert!(matches!(
+            check_image_limits(&images, &options),
+            Err(ConversionError::ImageTooLarge { index: 1, .. })
+        ));
+    }
+}
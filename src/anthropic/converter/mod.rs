@@ -0,0 +1,398 @@
+//! Anthropic → Kiro 协议转换器
+//!
+//! - `request`：Anthropic 请求 → Kiro 请求（消息历史、tool_use/tool_result 配对、thinking 注入）
+//! - `schema`：工具定义转换（描述补充、占位符定义、超长工具压缩）
+//! - `response`：Kiro 事件流 → Anthropic 非流式响应
+//! - `citations`：Kiro 引用事件 → Anthropic citations
+//! - `tool_ids`：tool_use id 规范化与还原
+//! - `history_summary`：会话超过 token 预算时把较早的历史压缩为摘要
+//!
+//! 流式响应的转换见 `stream` 模块；`golden` 测试用固定的请求样例防止转换结果回归。
+
+mod citations;
+#[cfg(test)]
+mod golden;
+mod history_summary;
+mod ids;
+mod image;
+mod request;
+mod response;
+mod schema;
+mod tool_ids;
+
+pub use citations::citations_from_event;
+pub use ids::IdGenerator;
+pub(crate) use image::image_dimensions;
+pub use request::{convert_request, derive_affinity_key};
+pub use response::{AggregatedMessage, NonStreamAggregator};
+pub use tool_ids::ToolUseIdMap;
+
+use std::time::Duration;
+
+use crate::common::i18n;
+use crate::kiro::debug_dump::CompressionPassStats;
+use crate::kiro::model::requests::conversation::ConversationState;
+use crate::model::config::{Config, SystemBlockMode, SystemPromptMode, TrailingUserMode};
+
+/// 请求转换选项（来自配置）
+#[derive(Debug, Clone)]
+pub struct ConversionOptions {
+    /// system 数组中多个文本块的发送方式
+    pub system_block_mode: SystemBlockMode,
+    /// merge 模式下拼接 system 文本块的分隔符
+    pub system_block_separator: String,
+    /// 是否去掉内容重复的 system 文本块
+    pub dedupe_system_blocks: bool,
+    /// 系统提示的发送方式
+    pub system_prompt_mode: SystemPromptMode,
+    /// 结尾连续 user 消息的处理方式
+    pub trailing_user_mode: TrailingUserMode,
+    /// 与系统提示配对的 assistant 回复
+    pub system_ack_text: String,
+    /// 与结尾 user 消息自动配对的 assistant 回复
+    pub paired_assistant_text: String,
+    /// 只有 tool_use 的 assistant 消息的占位文本
+    pub empty_content_placeholder: String,
+    /// 单个请求的图片数量上限（0 表示不限制）
+    pub max_images_per_request: usize,
+    /// 单张图片的字节上限（0 表示不限制）
+    pub max_image_bytes: usize,
+    /// 图片宽高的像素上限（0 表示不限制）
+    pub max_image_dimension: u32,
+    /// 历史消息中最多保留的图片数量（None 表示全部保留）
+    pub max_history_images: Option<usize>,
+    /// 会话累计 token 预算，超过后较早的历史被压缩为摘要（0 表示不启用）
+    pub conversation_token_budget: u64,
+    /// 历史摘要压缩时至少保留的最近历史消息数
+    pub compaction_keep_messages: usize,
+    /// 由请求头 x-kiro-session 映射得到的 conversationId（按请求设置）
+    ///
+    /// metadata.user_id 中没有 session 时使用，仍没有时由 `id_generator` 生成
+    pub conversation_id: Option<String>,
+    /// 生成 conversationId 与 agentContinuationId（测试中使用带种子的生成器）
+    pub id_generator: IdGenerator,
+}
+
+impl ConversionOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            system_block_mode: config.system_block_mode,
+            system_block_separator: config.system_block_separator.clone(),
+            dedupe_system_blocks: config.dedupe_system_blocks,
+            system_prompt_mode: config.system_prompt_mode,
+            trailing_user_mode: config.trailing_user_mode,
+            system_ack_text: config.system_ack_text.clone(),
+            paired_assistant_text: config.paired_assistant_text.clone(),
+            empty_content_placeholder: config.empty_content_placeholder.clone(),
+            max_images_per_request: config.max_images_per_request,
+            max_image_bytes: config.max_image_bytes,
+            max_image_dimension: config.max_image_dimension,
+            max_history_images: config.max_history_images,
+            conversation_token_budget: config.conversation_token_budget,
+            compaction_keep_messages: config.compaction_keep_messages,
+            conversation_id: None,
+            id_generator: IdGenerator::Random,
+        }
+    }
+}
+
+impl Default for ConversionOptions {
+    fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
+
+/// 模型映射：将 Anthropic 模型名映射到 Kiro 模型 ID
+///
+/// 按照用户要求：
+/// - 所有 sonnet → claude-sonnet-4.5
+/// - 所有 opus → claude-opus-4.5
+/// - 所有 haiku → claude-haiku-4.5
+/// - -agentic 后缀会被剥离，映射到同一底层模型
+pub fn map_model(model: &str) -> Option<String> {
+    let model_lower = model.to_lowercase();
+
+    if model_lower.contains("sonnet") {
+        Some("claude-sonnet-4.5".to_string())
+    } else if model_lower.contains("opus") {
+        if model_lower.contains("4-5") || model_lower.contains("4.5") {
+            Some("claude-opus-4.5".to_string())
+        } else {
+            Some("claude-opus-4.6".to_string())
+        }
+    } else if model_lower.contains("haiku") {
+        Some("claude-haiku-4.5".to_string())
+    } else {
+        None
+    }
+}
+
+/// 检查模型名是否为 agentic 变体
+pub fn is_agentic_model(model: &str) -> bool {
+    model.to_lowercase().contains("agentic")
+}
+
+/// 转换结果
+#[derive(Debug)]
+pub struct ConversionResult {
+    /// 转换后的 Kiro 请求
+    pub conversation_state: ConversationState,
+    /// 转换过程中对请求内容所做的修正（严格模式下会拒绝请求）
+    pub fixups: Vec<Fixup>,
+    /// assistant 预填充文本（最后一条消息为 assistant 时），需要拼接到返回内容的开头
+    pub prefill: Option<String>,
+    /// 规范化后的 tool_use id → 客户端原始 id，返回内容中的 tool_use id 需要按它还原
+    pub tool_use_ids: ToolUseIdMap,
+    /// 工具定义压缩实际执行的步骤（用于指标统计）
+    pub compression_passes: Vec<CompressionPass>,
+}
+
+/// 单个压缩步骤的统计
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionPass {
+    /// 步骤名称（`schema` / `description`）
+    pub name: &'static str,
+    /// 耗时
+    pub elapsed: Duration,
+    /// 压缩前的序列化大小（字节）
+    pub bytes_before: usize,
+    /// 压缩后的序列化大小（字节）
+    pub bytes_after: usize,
+}
+
+impl CompressionPass {
+    /// 节省的字节数
+    pub fn saved_bytes(&self) -> usize {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+impl From<&CompressionPass> for CompressionPassStats {
+    fn from(pass: &CompressionPass) -> Self {
+        Self {
+            name: pass.name.to_string(),
+            elapsed_ms: pass.elapsed.as_secs_f64() * 1000.0,
+            bytes_before: pass.bytes_before,
+            bytes_after: pass.bytes_after,
+        }
+    }
+}
+
+/// 转换时为满足 Kiro API 要求而对请求内容所做的修正
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fixup {
+    /// 丢弃找不到可配对 tool_use 的 tool_result（孤立或重复）
+    DroppedToolResult(String),
+    /// 从历史中移除没有 tool_result 的 tool_use
+    DroppedToolUse(String),
+    /// 为历史中引用但未在 tools 中定义的工具补充定义
+    InjectedToolDefinition(String),
+    /// 丢弃不支持格式的图片
+    DroppedImage(String),
+    /// 按 `maxHistoryImages` 丢弃较早的历史图片（数量）
+    DroppedHistoryImages(usize),
+    /// 会话超过 `conversationTokenBudget`，较早的历史消息被压缩为摘要（消息数）
+    SummarizedHistory(usize),
+    /// 工具描述超过长度上限被截断
+    TruncatedToolDescription(String),
+    /// 工具定义总大小超过上限，描述与 schema 被压缩
+    #[cfg_attr(not(feature = "compression"), allow(dead_code))]
+    CompressedTools {
+        /// 压缩前的序列化大小（字节）
+        original_bytes: usize,
+        /// 压缩后的序列化大小（字节）
+        compressed_bytes: usize,
+    },
+}
+
+impl Fixup {
+    /// 面向客户端的说明（按配置语言）
+    pub fn describe(&self) -> String {
+        match self {
+            Fixup::DroppedToolResult(id) => i18n::pick(
+                format!("tool_result {} 没有可配对的 tool_use（孤立或重复），将被丢弃", id),
+                format!("tool_result {} has no matching tool_use (orphaned or duplicate) and would be dropped", id),
+            ),
+            Fixup::DroppedToolUse(id) => i18n::pick(
+                format!("tool_use {} 没有对应的 tool_result，将从历史中移除", id),
+                format!("tool_use {} has no matching tool_result and would be removed from history", id),
+            ),
+            Fixup::InjectedToolDefinition(name) => i18n::pick(
+                format!("历史中引用的工具 {} 未在 tools 中定义，将补充定义", name),
+                format!("tool {} is used in history but missing from tools; a definition would be injected", name),
+            ),
+            Fixup::DroppedImage(media_type) => i18n::pick(
+                format!("不支持的图片格式 {}，图片将被丢弃", media_type),
+                format!("unsupported image media type {}; the image would be dropped", media_type),
+            ),
+            Fixup::DroppedHistoryImages(count) => i18n::pick(
+                format!("历史消息中较早的 {} 张图片将被丢弃", count),
+                format!("{} earlier image(s) in history would be dropped", count),
+            ),
+            Fixup::SummarizedHistory(count) => i18n::pick(
+                format!("会话累计 token 超过预算，较早的 {} 条历史消息将被压缩为摘要", count),
+                format!("conversation token budget exceeded; {} earlier history message(s) would be summarized", count),
+            ),
+            Fixup::TruncatedToolDescription(name) => i18n::pick(
+                format!("工具 {} 的描述过长，将被截断", name),
+                format!("description of tool {} is too long and would be truncated", name),
+            ),
+            Fixup::CompressedTools { .. } => i18n::pick(
+                "工具定义总大小超过上限，描述与 schema 将被压缩".to_string(),
+                "total size of tool definitions exceeds the limit; descriptions and schemas would be compressed".to_string(),
+            ),
+        }
+    }
+
+    /// 机器可读的简短标识（`类型:参数`），用于 `x-kiro-fixups` 响应头
+    pub fn code(&self) -> String {
+        match self {
+            Fixup::DroppedToolResult(id) => format!("dropped_tool_result:{}", id),
+            Fixup::DroppedToolUse(id) => format!("dropped_tool_use:{}", id),
+            Fixup::InjectedToolDefinition(name) => format!("injected_tool_definition:{}", name),
+            Fixup::DroppedImage(media_type) => format!("dropped_image:{}", media_type),
+            Fixup::DroppedHistoryImages(count) => format!("dropped_history_images:{}", count),
+            Fixup::SummarizedHistory(count) => format!("summarized_history:{}", count),
+            Fixup::TruncatedToolDescription(name) => {
+                format!("truncated_tool_description:{}", name)
+            }
+            Fixup::CompressedTools {
+                original_bytes,
+                compressed_bytes,
+            } => format!("compressed_tools:{}->{}", original_bytes, compressed_bytes),
+        }
+    }
+}
+
+/// 转换错误
+#[derive(Debug)]
+pub enum ConversionError {
+    UnsupportedModel(String),
+    EmptyMessages,
+    /// 启用 thinking 时不支持 assistant 预填充
+    PrefillWithThinking,
+    /// 图片数量超过上限
+    TooManyImages {
+        count: usize,
+        limit: usize,
+    },
+    /// 第 index 张图片（从 1 开始）超过字节上限
+    ImageTooLarge {
+        index: usize,
+        bytes: usize,
+        limit: usize,
+    },
+    /// 第 index 张图片（从 1 开始）宽或高超过像素上限
+    ImageDimensionsTooLarge {
+        index: usize,
+        width: u32,
+        height: u32,
+        limit: u32,
+    },
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::UnsupportedModel(model) => write!(f, "模型不支持: {}", model),
+            ConversionError::EmptyMessages => write!(f, "消息列表为空"),
+            ConversionError::PrefillWithThinking => {
+                write!(f, "启用 thinking 时不支持 assistant 预填充")
+            }
+            ConversionError::TooManyImages { count, limit } => {
+                write!(f, "图片数量 {} 超过上限 {}", count, limit)
+            }
+            ConversionError::ImageTooLarge {
+                index,
+                bytes,
+                limit,
+            } => write!(
+                f,
+                "第 {} 张图片大小 {} 字节超过上限 {}",
+                index, bytes, limit
+            ),
+            ConversionError::ImageDimensionsTooLarge {
+                index,
+                width,
+                height,
+                limit,
+            } => write!(
+                f,
+                "第 {} 张图片尺寸 {}x{} 超过上限 {}",
+                index, width, height, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_model_sonnet() {
+        assert!(
+            map_model("claude-sonnet-4-20250514")
+                .unwrap()
+                .contains("sonnet")
+        );
+        assert!(
+            map_model("claude-3-5-sonnet-20241022")
+                .unwrap()
+                .contains("sonnet")
+        );
+    }
+
+    #[test]
+    fn test_map_model_opus() {
+        assert!(
+            map_model("claude-opus-4-20250514")
+                .unwrap()
+                .contains("opus")
+        );
+    }
+
+    #[test]
+    fn test_map_model_haiku() {
+        assert!(
+            map_model("claude-haiku-4-20250514")
+                .unwrap()
+                .contains("haiku")
+        );
+    }
+
+    #[test]
+    fn test_map_model_unsupported() {
+        assert!(map_model("gpt-4").is_none());
+    }
+
+    #[test]
+    fn test_map_model_thinking_suffix_sonnet() {
+        // thinking 后缀不应影响 sonnet 模型映射
+        let result = map_model("claude-sonnet-4-5-20250929-thinking");
+        assert_eq!(result, Some("claude-sonnet-4.5".to_string()));
+    }
+
+    #[test]
+    fn test_map_model_thinking_suffix_opus_4_5() {
+        // thinking 后缀不应影响 opus 4.5 模型映射
+        let result = map_model("claude-opus-4-5-20251101-thinking");
+        assert_eq!(result, Some("claude-opus-4.5".to_string()));
+    }
+
+    #[test]
+    fn test_map_model_thinking_suffix_opus_4_6() {
+        // thinking 后缀不应影响 opus 4.6 模型映射
+        let result = map_model("claude-opus-4-6-thinking");
+        assert_eq!(result, Some("claude-opus-4.6".to_string()));
+    }
+
+    #[test]
+    fn test_map_model_thinking_suffix_haiku() {
+        // thinking 后缀不应影响 haiku 模型映射
+        let result = map_model("claude-haiku-4-5-20251001-thinking");
+        assert_eq!(result, Some("claude-haiku-4.5".to_string()));
+    }
+}
@@ -1,25 +1,25 @@
-//! Anthropic → Kiro 协议转换器
+//! 请求转换
 //!
-//! 负责将 Anthropic API 请求格式转换为 Kiro API 请求格式
-
-use uuid::Uuid;
+//! 将 Anthropic Messages 请求转换为 Kiro ConversationState
 
+use crate::anthropic::tool_schema_cache;
+use crate::anthropic::types::{self, ContentBlock, MessagesRequest};
+use crate::kiro::conversation_budget;
 use crate::kiro::model::requests::conversation::{
     AssistantMessage, ConversationState, CurrentMessage, HistoryAssistantMessage,
     HistoryUserMessage, KiroImage, Message, UserInputMessage, UserInputMessageContext, UserMessage,
 };
-use crate::kiro::model::requests::tool::{
-    InputSchema, Tool, ToolResult, ToolSpecification, ToolUseEntry,
+use crate::kiro::model::requests::tool::{ToolResult, ToolUseEntry};
+use crate::model::config::{SystemBlockMode, SystemPromptMode, TrailingUserMode};
+
+use super::history_summary;
+use super::image::check_image_limits;
+use super::schema::{convert_tools, create_placeholder_tool};
+use super::tool_ids::normalize_tool_use_ids;
+use super::{
+    ConversionError, ConversionOptions, ConversionResult, Fixup, is_agentic_model, map_model,
 };
 
-use super::types::{ContentBlock, MessagesRequest};
-
-/// 追加到 Write 工具 description 末尾的内容
-const WRITE_TOOL_DESCRIPTION_SUFFIX: &str = "- IMPORTANT: If the content to write exceeds 150 lines, you MUST only write the first 50 lines using this tool, then use `Edit` tool to append the remaining content in chunks of no more than 50 lines each. If needed, leave a unique placeholder to help append content. Do NOT attempt to write all content at once.";
-
-/// 追加到 Edit 工具 description 末尾的内容
-const EDIT_TOOL_DESCRIPTION_SUFFIX: &str = "- IMPORTANT: If the `new_string` content exceeds 50 lines, you MUST split it into multiple Edit calls, each replacing no more than 50 lines at a time. If used to append content, leave a unique placeholder to help append content. On the final chunk, do NOT include the placeholder.";
-
 /// 追加到系统提示词的分块写入策略
 const SYSTEM_CHUNKED_POLICY: &str = "\
 When the Write or Edit tool has content size limits, always comply silently. \
@@ -52,60 +52,10 @@ You MUST follow these rules for ALL file operations. Violation causes server tim
 \n\
 REMEMBER: When in doubt, write LESS per operation. Multiple small operations > one large operation.";
 
-/// 模型映射：将 Anthropic 模型名映射到 Kiro 模型 ID
-///
-/// 按照用户要求：
-/// - 所有 sonnet → claude-sonnet-4.5
-/// - 所有 opus → claude-opus-4.5
-/// - 所有 haiku → claude-haiku-4.5
-/// - -agentic 后缀会被剥离，映射到同一底层模型
-pub fn map_model(model: &str) -> Option<String> {
-    let model_lower = model.to_lowercase();
-
-    if model_lower.contains("sonnet") {
-        Some("claude-sonnet-4.5".to_string())
-    } else if model_lower.contains("opus") {
-        if model_lower.contains("4-5") || model_lower.contains("4.5") {
-            Some("claude-opus-4.5".to_string())
-        } else {
-            Some("claude-opus-4.6".to_string())
-        }
-    } else if model_lower.contains("haiku") {
-        Some("claude-haiku-4.5".to_string())
-    } else {
-        None
-    }
-}
-
-/// 检查模型名是否为 agentic 变体
-pub fn is_agentic_model(model: &str) -> bool {
-    model.to_lowercase().contains("agentic")
-}
-
-/// 转换结果
-#[derive(Debug)]
-pub struct ConversionResult {
-    /// 转换后的 Kiro 请求
-    pub conversation_state: ConversationState,
-}
-
-/// 转换错误
-#[derive(Debug)]
-pub enum ConversionError {
-    UnsupportedModel(String),
-    EmptyMessages,
-}
-
-impl std::fmt::Display for ConversionError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ConversionError::UnsupportedModel(model) => write!(f, "模型不支持: {}", model),
-            ConversionError::EmptyMessages => write!(f, "消息列表为空"),
-        }
-    }
-}
-
-impl std::error::Error for ConversionError {}
+/// 预填充请求中追加的 user 消息，要求模型从预填充内容末尾继续输出
+const PREFILL_CONTINUATION_PROMPT: &str = "\
+Continue your previous response exactly where it ends. \
+Do not repeat any part of it and do not add any preamble.";
 
 /// 从 metadata.user_id 中提取 session UUID
 ///
@@ -115,8 +65,8 @@ fn extract_session_id(user_id: &str) -> Option<String> {
     // 查找 "session_" 后面的内容
     if let Some(pos) = user_id.find("session_") {
         let session_part = &user_id[pos + 8..]; // "session_" 长度为 8
-                                                // session_part 应该是 UUID 格式: xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx
-                                                // 验证是否是有效的 UUID 格式（36 字符，包含 4 个连字符）
+        // session_part 应该是 UUID 格式: xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx
+        // 验证是否是有效的 UUID 格式（36 字符，包含 4 个连字符）
         if session_part.len() >= 36 {
             let uuid_str = &session_part[..36];
             // 简单验证 UUID 格式
@@ -128,6 +78,63 @@ fn extract_session_id(user_id: &str) -> Option<String> {
     None
 }
 
+/// Claude Code 子代理（Task 工具启动的 agent）系统提示词中的特征文本
+const SUBAGENT_SYSTEM_MARKERS: &[&str] = &["You are an agent for Claude Code"];
+
+/// 判断请求是否来自 Claude Code 子代理
+fn is_subagent_request(req: &MessagesRequest) -> bool {
+    req.system.as_ref().is_some_and(|system| {
+        system
+            .iter()
+            .any(|s| SUBAGENT_SYSTEM_MARKERS.iter().any(|m| s.text.contains(m)))
+    })
+}
+
+/// 提取第一条用户消息的文本内容（子代理的任务描述）
+fn first_user_text(req: &MessagesRequest) -> String {
+    let Some(first) = req.messages.iter().find(|m| m.role == "user") else {
+        return String::new();
+    };
+
+    match &first.content {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(blocks) => blocks
+            .iter()
+            .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some("text"))
+            .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// 推导凭据亲和键
+///
+/// 以 metadata.user_id 为基础；Claude Code 子代理与主会话共享 user_id，
+/// 但逻辑上相互独立，因此额外拼接任务描述的哈希，
+/// 使并行的子代理分散到不同凭据上，而不是全部挤在同一账号的速率限制上。
+///
+/// 没有 user_id 时返回 None（不做亲和）
+pub fn derive_affinity_key(req: &MessagesRequest) -> Option<String> {
+    use sha2::{Digest, Sha256};
+
+    let user_id = req.metadata.as_ref()?.user_id.as_ref()?;
+    if user_id.is_empty() {
+        return None;
+    }
+
+    if !is_subagent_request(req) {
+        return Some(user_id.clone());
+    }
+
+    let digest = Sha256::digest(first_user_text(req).as_bytes());
+    Some(format!(
+        "{}#subagent-{}",
+        user_id,
+        &hex::encode(digest)[..16]
+    ))
+}
+
 /// 收集历史消息中使用的所有工具名称
 fn collect_history_tool_names(history: &[Message]) -> Vec<String> {
     let mut tool_names = Vec::new();
@@ -147,26 +154,11 @@ fn collect_history_tool_names(history: &[Message]) -> Vec<String> {
     tool_names
 }
 
-/// 为历史中使用但不在 tools 列表中的工具创建占位符定义
-/// Kiro API 要求：历史消息中引用的工具必须在 currentMessage.tools 中有定义
-fn create_placeholder_tool(name: &str) -> Tool {
-    Tool {
-        tool_specification: ToolSpecification {
-            name: name.to_string(),
-            description: "Tool used in conversation history".to_string(),
-            input_schema: InputSchema::from_json(serde_json::json!({
-                "$schema": "http://json-schema.org/draft-07/schema#",
-                "type": "object",
-                "properties": {},
-                "required": [],
-                "additionalProperties": true
-            })),
-        },
-    }
-}
-
 /// 将 Anthropic 请求转换为 Kiro 请求
-pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, ConversionError> {
+pub fn convert_request(
+    req: &MessagesRequest,
+    options: &ConversionOptions,
+) -> Result<ConversionResult, ConversionError> {
     // 1. 映射模型
     let model_id = map_model(&req.model)
         .ok_or_else(|| ConversionError::UnsupportedModel(req.model.clone()))?;
@@ -177,40 +169,133 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
     }
 
     // 3. 生成会话 ID 和代理 ID
-    // 优先从 metadata.user_id 中提取 session UUID 作为 conversationId
-    let conversation_id = req
+    // 优先从 metadata.user_id 中提取 session UUID 作为 conversationId，其次使用会话映射
+    let mut ids = options.id_generator.sequence();
+    let session_conversation_id = req
         .metadata
         .as_ref()
         .and_then(|m| m.user_id.as_ref())
         .and_then(|user_id| extract_session_id(user_id))
-        .unwrap_or_else(|| Uuid::new_v4().to_string());
-    let agent_continuation_id = Uuid::new_v4().to_string();
+        .or_else(|| options.conversation_id.clone());
+    let conversation_id = session_conversation_id
+        .clone()
+        .unwrap_or_else(|| ids.next_uuid());
+    let agent_continuation_id = ids.next_uuid();
 
     // 4. 确定触发类型
     let chat_trigger_type = determine_chat_trigger_type(req);
 
     // 5. 处理最后一条消息作为 current_message
+    // 最后一条为 assistant 时视为预填充：它已包含在历史中，current_message 改为要求继续输出
     let last_message = req.messages.last().unwrap();
-    let (text_content, images, tool_results) = process_message_content(&last_message.content)?;
+    let prefill = (last_message.role == "assistant").then(|| prefill_text(last_message));
+    if prefill.is_some() && req.thinking.as_ref().is_some_and(|t| t.is_enabled()) {
+        return Err(ConversionError::PrefillWithThinking);
+    }
+    let mut fixups = Vec::new();
+    let current_start = current_message_start(req, options);
+    let (mut text_content, images, mut tool_results) = if prefill.is_some() {
+        (
+            PREFILL_CONTINUATION_PROMPT.to_string(),
+            Vec::new(),
+            Vec::new(),
+        )
+    } else {
+        // trailingUserMode = merge 时，最后一条 assistant 之后的所有 user 消息合并为 current_message
+        let mut parts = Vec::new();
+        let mut images = Vec::new();
+        let mut tool_results = Vec::new();
+        for msg in &req.messages[current_start..] {
+            let (text, msg_images, msg_results) =
+                process_message_content(&msg.content, &mut fixups)?;
+            if !text.is_empty() {
+                parts.push(text);
+            }
+            images.extend(msg_images);
+            tool_results.extend(msg_results);
+        }
+        (parts.join("\n"), images, tool_results)
+    };
 
-    // 6. 转换工具定义
-    let mut tools = convert_tools(&req.tools);
+    // 6. 转换工具定义，并记录到会话的工具 schema 缓存中
+    // 只缓存来自真实会话的 conversationId；随机生成的 ID 每次请求都不同，缓存后不会再被读取
+    let mut compression_passes = Vec::new();
+    let mut tools = convert_tools(&req.tools, &mut fixups, &mut compression_passes);
+    if let Some(id) = &session_conversation_id {
+        tool_schema_cache::remember_tools(id, &tools);
+    }
 
     // 7. 构建历史消息（需要先构建，以便收集历史中使用的工具）
-    let mut history = build_history(req, &model_id)?;
+    // 会话累计 token 超过预算时，较早的历史压缩为一段摘要
+    let summary_cut = (options.conversation_token_budget > 0
+        && conversation_budget::used(&conversation_id) > options.conversation_token_budget)
+        .then(|| {
+            history_summary::summary_cut(
+                &req.messages,
+                current_start,
+                options.compaction_keep_messages,
+            )
+        })
+        .flatten();
+    if let Some(cut) = summary_cut {
+        tracing::info!(
+            "会话 {} 累计 token 超过预算，较早的 {} 条历史消息压缩为摘要",
+            conversation_id,
+            cut
+        );
+        fixups.push(Fixup::SummarizedHistory(cut));
+    }
+    let mut history = build_history(
+        req,
+        &model_id,
+        summary_cut.unwrap_or(0)..current_start,
+        options,
+        &mut fixups,
+    )?;
+
+    // systemPromptMode = prepend：系统提示拼接到第一条 user 消息开头，而不是单独一轮伪造的对话
+    if options.system_prompt_mode == SystemPromptMode::Prepend {
+        let system = system_prompts(req, options).join("\n\n");
+        if !system.is_empty() {
+            let first_user = history.iter_mut().find_map(|msg| match msg {
+                Message::User(user) => Some(&mut user.user_input_message.content),
+                Message::Assistant(_) => None,
+            });
+            let target = first_user.unwrap_or(&mut text_content);
+            *target = format!("{}\n\n{}", system, target);
+        }
+    }
 
-    // 8. 验证并过滤 tool_use/tool_result 配对
+    // 8. 规范化上游不接受或跨轮次重复的 tool_use id，再验证并过滤 tool_use/tool_result 配对
     // 移除孤立的 tool_result（没有对应的 tool_use）
     // 同时返回孤立的 tool_use_id 集合，用于后续清理
+    let tool_use_ids = normalize_tool_use_ids(&mut history, &mut tool_results);
     let (validated_tool_results, orphaned_tool_use_ids) =
         validate_tool_pairing(&history, &tool_results);
 
     // 9. 从历史中移除孤立的 tool_use（Kiro API 要求 tool_use 必须有对应的 tool_result）
     remove_orphaned_tool_uses(&mut history, &orphaned_tool_use_ids);
+    let mut remaining = validated_tool_results.iter().peekable();
+    for result in &tool_results {
+        if remaining
+            .next_if(|v| v.tool_use_id == result.tool_use_id)
+            .is_none()
+        {
+            let id = tool_use_ids.restore(&result.tool_use_id);
+            fixups.push(Fixup::DroppedToolResult(id.to_string()));
+        }
+    }
+    let mut orphaned_ids: Vec<_> = orphaned_tool_use_ids
+        .iter()
+        .map(|id| tool_use_ids.restore(id).to_string())
+        .collect();
+    orphaned_ids.sort();
+    fixups.extend(orphaned_ids.into_iter().map(Fixup::DroppedToolUse));
 
     // 10. 收集历史中使用的工具名称，为缺失的工具生成占位符定义
     // Kiro API 要求：历史消息中引用的工具必须在 tools 列表中有定义
     // 注意：Kiro 匹配工具名称时忽略大小写，所以这里也需要忽略大小写比较
+    // 优先使用同一会话先前请求中出现过的真实定义，找不到时才使用空 schema 占位符
     let history_tool_names = collect_history_tool_names(&history);
     let existing_tool_names: std::collections::HashSet<_> = tools
         .iter()
@@ -219,10 +304,31 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
 
     for tool_name in history_tool_names {
         if !existing_tool_names.contains(&tool_name.to_lowercase()) {
-            tools.push(create_placeholder_tool(&tool_name));
+            let tool = session_conversation_id
+                .as_deref()
+                .and_then(|id| tool_schema_cache::lookup_tool(id, &tool_name))
+                .unwrap_or_else(|| create_placeholder_tool(&tool_name));
+            tools.push(tool);
+            fixups.push(Fixup::InjectedToolDefinition(tool_name));
+        }
+    }
+
+    // 按配置丢弃较早的历史图片（当前消息中的图片始终保留）
+    if let Some(keep) = options.max_history_images {
+        let dropped = limit_history_images(&mut history, keep);
+        if dropped > 0 {
+            tracing::debug!("丢弃了 {} 张较早的历史图片", dropped);
+            fixups.push(Fixup::DroppedHistoryImages(dropped));
         }
     }
 
+    // 检查图片数量与尺寸限制（历史与当前消息中的所有图片）
+    let history_images = history.iter().flat_map(|msg| match msg {
+        Message::User(user) => user.user_input_message.images.as_slice(),
+        Message::Assistant(_) => &[],
+    });
+    check_image_limits(history_images.chain(&images), options)?;
+
     // 11. 构建 UserInputMessageContext
     let mut context = UserInputMessageContext::new();
     if !tools.is_empty() {
@@ -254,7 +360,30 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
         .with_current_message(current_message)
         .with_history(history);
 
-    Ok(ConversionResult { conversation_state })
+    Ok(ConversionResult {
+        conversation_state,
+        fixups,
+        prefill: prefill.filter(|p| !p.is_empty()),
+        tool_use_ids,
+        compression_passes,
+    })
+}
+
+/// 提取 assistant 预填充消息的文本
+///
+/// 去掉末尾空白（Anthropic 同样不允许预填充以空白结尾），
+/// 否则模型续写时补上的空白会与预填充重复
+fn prefill_text(msg: &types::Message) -> String {
+    let text = match &msg.content {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(blocks) => blocks
+            .iter()
+            .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some("text"))
+            .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+            .collect(),
+        _ => String::new(),
+    };
+    text.trim_end().to_string()
 }
 
 /// 确定聊天触发类型
@@ -266,6 +395,7 @@ fn determine_chat_trigger_type(_req: &MessagesRequest) -> String {
 /// 处理消息内容，提取文本、图片和工具结果
 fn process_message_content(
     content: &serde_json::Value,
+    fixups: &mut Vec<Fixup>,
 ) -> Result<(String, Vec<KiroImage>, Vec<ToolResult>), ConversionError> {
     let mut text_parts = Vec::new();
     let mut images = Vec::new();
@@ -286,14 +416,14 @@ fn process_message_content(
                         }
                         "image" => {
                             if let Some(source) = block.source {
-                                if let Some(format) = get_image_format(&source.media_type) {
-                                    images.push(KiroImage::from_base64(format, source.data));
-                                }
+                                push_image(source, &mut images, fixups);
                             }
                         }
                         "tool_result" => {
                             if let Some(tool_use_id) = block.tool_use_id {
                                 let result_content = extract_tool_result_content(&block.content);
+                                // 工具返回的图片（如截图）随所在的 user 消息一起发送
+                                extract_tool_result_images(&block.content, &mut images, fixups);
                                 let is_error = block.is_error.unwrap_or(false);
 
                                 let mut result = if is_error {
@@ -321,6 +451,56 @@ fn process_message_content(
     Ok((text_parts.join("\n"), images, tool_results))
 }
 
+/// 转换一张图片，不支持的格式记录为修正并丢弃
+fn push_image(source: types::ImageSource, images: &mut Vec<KiroImage>, fixups: &mut Vec<Fixup>) {
+    match get_image_format(&source.media_type) {
+        Some(format) => images.push(KiroImage::from_base64(format, source.data)),
+        None => fixups.push(Fixup::DroppedImage(source.media_type)),
+    }
+}
+
+/// 提取 tool_result 内容中的图片块
+fn extract_tool_result_images(
+    content: &Option<serde_json::Value>,
+    images: &mut Vec<KiroImage>,
+    fixups: &mut Vec<Fixup>,
+) {
+    let Some(serde_json::Value::Array(arr)) = content else {
+        return;
+    };
+    for item in arr {
+        if item.get("type").and_then(|t| t.as_str()) != Some("image") {
+            continue;
+        }
+        if let Some(source) = item
+            .get("source")
+            .and_then(|s| serde_json::from_value::<types::ImageSource>(s.clone()).ok())
+        {
+            push_image(source, images, fixups);
+        }
+    }
+}
+
+/// 只保留历史中最近的 `keep` 张图片，返回丢弃的数量
+fn limit_history_images(history: &mut [Message], keep: usize) -> usize {
+    let mut kept = 0;
+    let mut dropped = 0;
+    for msg in history.iter_mut().rev() {
+        let Message::User(user) = msg else {
+            continue;
+        };
+        let images = &mut user.user_input_message.images;
+        let remaining = keep - kept;
+        if images.len() > remaining {
+            // 同一条消息中保留靠后的图片
+            dropped += images.len() - remaining;
+            images.drain(..images.len() - remaining);
+        }
+        kept += images.len();
+    }
+    dropped
+}
+
 /// 从 media_type 获取图片格式
 fn get_image_format(media_type: &str) -> Option<String> {
     match media_type {
@@ -470,48 +650,6 @@ fn remove_orphaned_tool_uses(
     }
 }
 
-/// 转换工具定义
-fn convert_tools(tools: &Option<Vec<super::types::Tool>>) -> Vec<Tool> {
-    let Some(tools) = tools else {
-        return Vec::new();
-    };
-
-    let converted: Vec<Tool> = tools
-        .iter()
-        .map(|t| {
-            let mut description = t.description.clone();
-
-            // 对 Write/Edit 工具追加自定义描述后缀
-            let suffix = match t.name.as_str() {
-                "Write" => WRITE_TOOL_DESCRIPTION_SUFFIX,
-                "Edit" => EDIT_TOOL_DESCRIPTION_SUFFIX,
-                _ => "",
-            };
-            if !suffix.is_empty() {
-                description.push('\n');
-                description.push_str(suffix);
-            }
-
-            // 限制描述长度为 10000 字符（安全截断 UTF-8，单次遍历）
-            let description = match description.char_indices().nth(10000) {
-                Some((idx, _)) => description[..idx].to_string(),
-                None => description,
-            };
-
-            Tool {
-                tool_specification: ToolSpecification {
-                    name: t.name.clone(),
-                    description,
-                    input_schema: InputSchema::from_json(serde_json::json!(t.input_schema)),
-                },
-            }
-        })
-        .collect();
-
-    // 如果工具总大小超过阈值，进行压缩
-    super::tool_compression::compress_tools_if_needed(&converted)
-}
-
 /// 生成thinking标签前缀
 fn generate_thinking_prefix(req: &MessagesRequest) -> Option<String> {
     if let Some(t) = &req.thinking {
@@ -541,90 +679,64 @@ fn has_thinking_tags(content: &str) -> bool {
 }
 
 /// 构建历史消息
-fn build_history(req: &MessagesRequest, model_id: &str) -> Result<Vec<Message>, ConversionError> {
+///
+/// `range` 内的消息加入历史，之前的消息压缩为摘要，之后的消息由调用方作为 current_message 处理
+fn build_history(
+    req: &MessagesRequest,
+    model_id: &str,
+    range: std::ops::Range<usize>,
+    options: &ConversionOptions,
+    fixups: &mut Vec<Fixup>,
+) -> Result<Vec<Message>, ConversionError> {
     let mut history = Vec::new();
 
-    // 生成thinking前缀（如果需要）
-    let thinking_prefix = generate_thinking_prefix(req);
-
-    // 1. 处理系统消息
-    if let Some(ref system) = req.system {
-        let system_content: String = system
-            .iter()
-            .map(|s| s.text.clone())
-            .collect::<Vec<_>>()
-            .join("\n");
-
-        if !system_content.is_empty() {
-            // 追加分块写入策略到系统消息
-            let mut system_content = format!("{}\n{}", system_content, SYSTEM_CHUNKED_POLICY);
-
-            // 如果是 agentic 模型，追加专用分块写入系统提示
-            if is_agentic_model(&req.model) {
-                system_content = format!("{}\n{}", system_content, KIRO_AGENTIC_SYSTEM_PROMPT);
-            }
-
-            // 注入thinking标签到系统消息最前面（如果需要且不存在）
-            let final_content = if let Some(ref prefix) = thinking_prefix {
-                if !has_thinking_tags(&system_content) {
-                    format!("{}\n{}", prefix, system_content)
-                } else {
-                    system_content
-                }
-            } else {
-                system_content
-            };
-
-            // 系统消息作为 user + assistant 配对
-            let user_msg = HistoryUserMessage::new(final_content, model_id);
+    // 1. 处理系统消息：作为 user + assistant 配对（prepend 模式由调用方处理）
+    if options.system_prompt_mode == SystemPromptMode::Pair {
+        for content in system_prompts(req, options) {
+            let user_msg = HistoryUserMessage::new(content, model_id);
             history.push(Message::User(user_msg));
 
-            let assistant_msg = HistoryAssistantMessage::new("I will follow these instructions.");
+            let assistant_msg = HistoryAssistantMessage::new(options.system_ack_text.as_str());
             history.push(Message::Assistant(assistant_msg));
         }
-    } else if let Some(ref prefix) = thinking_prefix {
-        // 没有系统消息但有thinking配置，插入新的系统消息
-        let user_msg = HistoryUserMessage::new(prefix.clone(), model_id);
-        history.push(Message::User(user_msg));
+    }
 
-        let assistant_msg = HistoryAssistantMessage::new("I will follow these instructions.");
-        history.push(Message::Assistant(assistant_msg));
+    // 2. range 之前的消息压缩为摘要，与 assistant 回复配对
+    if range.start > 0 {
+        let summary = history_summary::summarize(&req.messages[..range.start]);
+        history.push(Message::User(HistoryUserMessage::new(summary, model_id)));
+        history.push(Message::Assistant(HistoryAssistantMessage::new(
+            options.paired_assistant_text.as_str(),
+        )));
     }
 
-    // 2. 处理常规消息历史
-    // 最后一条消息作为 currentMessage，不加入历史
-    let history_end_index = req.messages.len().saturating_sub(1);
+    // 3. 处理常规消息历史：收集并配对消息
+    let mut user_buffer: Vec<&types::Message> = Vec::new();
 
-    // 如果最后一条是 assistant，则包含在历史中
-    let last_is_assistant = req
+    for (i, msg) in req
         .messages
-        .last()
-        .map(|m| m.role == "assistant")
-        .unwrap_or(false);
-
-    let history_end_index = if last_is_assistant {
-        req.messages.len()
-    } else {
-        history_end_index
-    };
-
-    // 收集并配对消息
-    let mut user_buffer: Vec<&super::types::Message> = Vec::new();
-
-    for i in 0..history_end_index {
-        let msg = &req.messages[i];
-
+        .iter()
+        .enumerate()
+        .take(range.end)
+        .skip(range.start)
+    {
         if msg.role == "user" {
             user_buffer.push(msg);
         } else if msg.role == "assistant" {
             // 遇到 assistant，处理累积的 user 消息
             if !user_buffer.is_empty() {
-                let merged_user = merge_user_messages(&user_buffer, model_id)?;
+                let merged_user = merge_user_messages(&user_buffer, model_id, fixups)?;
                 history.push(Message::User(merged_user));
                 user_buffer.clear();
 
                 // 添加 assistant 消息
-                let assistant = convert_assistant_message(msg)?;
+                let mut assistant =
+                    convert_assistant_message(msg, &options.empty_content_placeholder)?;
+                if i + 1 == req.messages.len() {
+                    // 预填充消息：与返回内容拼接的文本保持一致
+                    let content = &mut assistant.assistant_response_message.content;
+                    content.truncate(content.trim_end().len());
+                }
                 history.push(Message::Assistant(assistant));
             }
         }
@@ -632,28 +744,118 @@ fn build_history(req: &MessagesRequest, model_id: &str) -> Result<Vec<Message>,
 
     // 处理结尾的孤立 user 消息
     if !user_buffer.is_empty() {
-        let merged_user = merge_user_messages(&user_buffer, model_id)?;
+        let merged_user = merge_user_messages(&user_buffer, model_id, fixups)?;
         history.push(Message::User(merged_user));
 
-        // 自动配对一个 "OK" 的 assistant 响应
-        let auto_assistant = HistoryAssistantMessage::new("OK");
+        // 自动配对一个 assistant 响应（pairedAssistantText，默认 "OK"）
+        let auto_assistant = HistoryAssistantMessage::new(options.paired_assistant_text.as_str());
         history.push(Message::Assistant(auto_assistant));
     }
 
     Ok(history)
 }
 
+/// current_message 对应的消息起始位置（之前的消息进入历史）
+///
+/// - 最后一条为 assistant（预填充）：所有消息进入历史
+/// - trailingUserMode = pair：只有最后一条消息作为 current_message，
+///   之前结尾的连续 user 消息在历史中与 `paired_assistant_text` 配对
+/// - trailingUserMode = merge：最后一条 assistant 之后的连续 user 消息都作为 current_message
+fn current_message_start(req: &MessagesRequest, options: &ConversionOptions) -> usize {
+    let len = req.messages.len();
+    if req.messages.last().is_some_and(|m| m.role == "assistant") {
+        return len;
+    }
+    match options.trailing_user_mode {
+        TrailingUserMode::Pair => len.saturating_sub(1),
+        TrailingUserMode::Merge => req
+            .messages
+            .iter()
+            .rposition(|m| m.role == "assistant")
+            .map_or(0, |i| i + 1),
+    }
+}
+
+/// 生成要发送的系统提示（已追加分块写入策略与 thinking 标签）
+///
+/// 没有 system 但启用了 thinking 时，返回仅包含 thinking 标签的一条
+fn system_prompts(req: &MessagesRequest, options: &ConversionOptions) -> Vec<String> {
+    // 生成thinking前缀（如果需要）
+    let thinking_prefix = generate_thinking_prefix(req);
+
+    let Some(ref system) = req.system else {
+        // 没有系统消息但有thinking配置，插入新的系统消息
+        return thinking_prefix.into_iter().collect();
+    };
+
+    let mut system_messages = system_message_texts(system, options);
+
+    // 追加分块写入策略到（最后一条）系统消息
+    if let Some(last) = system_messages.last_mut() {
+        last.push('\n');
+        last.push_str(SYSTEM_CHUNKED_POLICY);
+
+        // 如果是 agentic 模型，追加专用分块写入系统提示
+        if is_agentic_model(&req.model) {
+            last.push('\n');
+            last.push_str(KIRO_AGENTIC_SYSTEM_PROMPT);
+        }
+    }
+
+    // 注入thinking标签到第一条系统消息最前面（如果需要且不存在）
+    if let Some(ref prefix) = thinking_prefix
+        && !system_messages.iter().any(|m| has_thinking_tags(m))
+        && let Some(first) = system_messages.first_mut()
+    {
+        *first = format!("{}\n{}", prefix, first);
+    }
+
+    system_messages
+}
+
+/// 按配置整理 system 文本块，返回要发送的系统消息（内容为空时不发送）
+///
+/// - merge：所有文本块按分隔符拼接为一条
+/// - separate：每个非空文本块单独一条，保留块边界
+fn system_message_texts(
+    system: &[types::SystemMessage],
+    options: &ConversionOptions,
+) -> Vec<String> {
+    let mut blocks: Vec<&str> = system.iter().map(|s| s.text.as_str()).collect();
+    if options.dedupe_system_blocks {
+        let mut seen = std::collections::HashSet::new();
+        blocks.retain(|text| text.trim().is_empty() || seen.insert(text.trim()));
+    }
+
+    match options.system_block_mode {
+        SystemBlockMode::Merge => {
+            let merged = blocks.join(&options.system_block_separator);
+            if merged.is_empty() {
+                Vec::new()
+            } else {
+                vec![merged]
+            }
+        }
+        SystemBlockMode::Separate => blocks
+            .into_iter()
+            .filter(|text| !text.trim().is_empty())
+            .map(str::to_string)
+            .collect(),
+    }
+}
+
 /// 合并多个 user 消息
 fn merge_user_messages(
-    messages: &[&super::types::Message],
+    messages: &[&types::Message],
     model_id: &str,
+    fixups: &mut Vec<Fixup>,
 ) -> Result<HistoryUserMessage, ConversionError> {
     let mut content_parts = Vec::new();
     let mut all_images = Vec::new();
     let mut all_tool_results = Vec::new();
 
     for msg in messages {
-        let (text, images, tool_results) = process_message_content(&msg.content)?;
+        let (text, images, tool_results) = process_message_content(&msg.content, fixups)?;
         if !text.is_empty() {
             content_parts.push(text);
         }
@@ -681,8 +883,11 @@ fn merge_user_messages(
 }
 
 /// 转换 assistant 消息
+///
+/// `placeholder`：只有 tool_use 没有文本时使用的占位文本
 fn convert_assistant_message(
-    msg: &super::types::Message,
+    msg: &types::Message,
+    placeholder: &str,
 ) -> Result<HistoryAssistantMessage, ConversionError> {
     let mut thinking_content = String::new();
     let mut text_content = String::new();
@@ -733,7 +938,7 @@ fn convert_assistant_message(
             format!("<thinking>{}</thinking>", thinking_content)
         }
     } else if text_content.is_empty() && !tool_uses.is_empty() {
-        ".".to_string()
+        placeholder.to_string()
     } else {
         text_content
     };
@@ -750,64 +955,9 @@ fn convert_assistant_message(
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
-    #[test]
-    fn test_map_model_sonnet() {
-        assert!(map_model("claude-sonnet-4-20250514")
-            .unwrap()
-            .contains("sonnet"));
-        assert!(map_model("claude-3-5-sonnet-20241022")
-            .unwrap()
-            .contains("sonnet"));
-    }
-
-    #[test]
-    fn test_map_model_opus() {
-        assert!(map_model("claude-opus-4-20250514")
-            .unwrap()
-            .contains("opus"));
-    }
-
-    #[test]
-    fn test_map_model_haiku() {
-        assert!(map_model("claude-haiku-4-20250514")
-            .unwrap()
-            .contains("haiku"));
-    }
-
-    #[test]
-    fn test_map_model_unsupported() {
-        assert!(map_model("gpt-4").is_none());
-    }
+    use uuid::Uuid;
 
-    #[test]
-    fn test_map_model_thinking_suffix_sonnet() {
-        // thinking 后缀不应影响 sonnet 模型映射
-        let result = map_model("claude-sonnet-4-5-20250929-thinking");
-        assert_eq!(result, Some("claude-sonnet-4.5".to_string()));
-    }
-
-    #[test]
-    fn test_map_model_thinking_suffix_opus_4_5() {
-        // thinking 后缀不应影响 opus 4.5 模型映射
-        let result = map_model("claude-opus-4-5-20251101-thinking");
-        assert_eq!(result, Some("claude-opus-4.5".to_string()));
-    }
-
-    #[test]
-    fn test_map_model_thinking_suffix_opus_4_6() {
-        // thinking 后缀不应影响 opus 4.6 模型映射
-        let result = map_model("claude-opus-4-6-thinking");
-        assert_eq!(result, Some("claude-opus-4.6".to_string()));
-    }
-
-    #[test]
-    fn test_map_model_thinking_suffix_haiku() {
-        // thinking 后缀不应影响 haiku 模型映射
-        let result = map_model("claude-haiku-4-5-20251001-thinking");
-        assert_eq!(result, Some("claude-haiku-4.5".to_string()));
-    }
+    use super::*;
 
     #[test]
     fn test_determine_chat_trigger_type() {
@@ -823,6 +973,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            extra: Default::default(),
         };
         assert_eq!(determine_chat_trigger_type(&req), "MANUAL");
     }
@@ -857,20 +1008,371 @@ mod tests {
     }
 
     #[test]
-    fn test_create_placeholder_tool() {
-        let tool = create_placeholder_tool("my_custom_tool");
+    fn test_convert_request_records_fixups() {
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": [
+                {"role": "user", "content": "Check the weather"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "call_a", "name": "weather", "input": {}},
+                    {"type": "tool_use", "id": "call_b", "name": "weather", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "call_a", "content": "sunny"},
+                    {"type": "tool_result", "tool_use_id": "call_x", "content": "stale"},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/bmp", "data": "AA=="}}
+                ]}
+            ]
+        }))
+        .unwrap();
+
+        let result = convert_request(&req, &ConversionOptions::default()).unwrap();
+        assert_eq!(
+            result.fixups,
+            vec![
+                Fixup::DroppedImage("image/bmp".to_string()),
+                Fixup::DroppedToolResult("call_x".to_string()),
+                Fixup::DroppedToolUse("call_b".to_string()),
+                Fixup::InjectedToolDefinition("weather".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_convert_request_without_fixups() {
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": [{"role": "user", "content": "Hello"}]
+        }))
+        .unwrap();
+
+        assert!(
+            convert_request(&req, &ConversionOptions::default())
+                .unwrap()
+                .fixups
+                .is_empty()
+        );
+    }
+
+    fn system_blocks(texts: &[&str]) -> Vec<types::SystemMessage> {
+        texts
+            .iter()
+            .map(|t| types::SystemMessage {
+                text: t.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_system_message_texts_merge_with_separator() {
+        let system = system_blocks(&["You are Claude Code.", "Be concise."]);
+        let options = ConversionOptions {
+            system_block_separator: "\n\n".to_string(),
+            ..ConversionOptions::default()
+        };
+        assert_eq!(
+            system_message_texts(&system, &options),
+            vec!["You are Claude Code.\n\nBe concise.".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_system_message_texts_separate_and_dedupe() {
+        let system = system_blocks(&["Boilerplate", "", "Project rules", "Boilerplate "]);
+        let options = ConversionOptions {
+            system_block_mode: SystemBlockMode::Separate,
+            dedupe_system_blocks: true,
+            ..ConversionOptions::default()
+        };
+        assert_eq!(
+            system_message_texts(&system, &options),
+            vec!["Boilerplate".to_string(), "Project rules".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_separate_system_blocks_become_history_pairs() {
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "system": [{"type": "text", "text": "First"}, {"type": "text", "text": "Second"}],
+            "messages": [{"role": "user", "content": "Hello"}]
+        }))
+        .unwrap();
+        let options = ConversionOptions {
+            system_block_mode: SystemBlockMode::Separate,
+            ..ConversionOptions::default()
+        };
+
+        let history = convert_request(&req, &options)
+            .unwrap()
+            .conversation_state
+            .history;
+        assert_eq!(history.len(), 4);
+        match (&history[0], &history[2]) {
+            (Message::User(first), Message::User(second)) => {
+                assert_eq!(first.user_input_message.content, "First");
+                assert!(second.user_input_message.content.starts_with("Second\n"));
+                assert!(
+                    second
+                        .user_input_message
+                        .content
+                        .contains(SYSTEM_CHUNKED_POLICY)
+                );
+            }
+            _ => panic!("system 文本块应作为独立的 user 消息"),
+        }
+    }
+
+    #[test]
+    fn test_prepend_system_prompt_without_fake_turn() {
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "system": "Answer in French.",
+            "messages": [{"role": "user", "content": "Hello"}]
+        }))
+        .unwrap();
+        let options = ConversionOptions {
+            system_prompt_mode: SystemPromptMode::Prepend,
+            ..ConversionOptions::default()
+        };
+
+        let state = convert_request(&req, &options).unwrap().conversation_state;
+        assert!(state.history.is_empty());
+        let content = &state.current_message.user_input_message.content;
+        assert!(content.starts_with("Answer in French.\n"));
+        assert!(content.ends_with("\n\nHello"));
+    }
+
+    #[test]
+    fn test_merge_trailing_user_messages_into_current() {
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": [
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hello!"},
+                {"role": "user", "content": "First question"},
+                {"role": "user", "content": "Second question"}
+            ]
+        }))
+        .unwrap();
+
+        let paired = convert_request(&req, &ConversionOptions::default())
+            .unwrap()
+            .conversation_state;
+        assert_eq!(paired.history.len(), 4);
+
+        let options = ConversionOptions {
+            trailing_user_mode: TrailingUserMode::Merge,
+            ..ConversionOptions::default()
+        };
+        let merged = convert_request(&req, &options).unwrap().conversation_state;
+        assert_eq!(merged.history.len(), 2);
+        assert_eq!(
+            merged.current_message.user_input_message.content,
+            "First question\nSecond question"
+        );
+    }
+
+    #[test]
+    fn test_synthetic_assistant_texts_from_options() {
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "system": "Be brief.",
+            "tools": [{"name": "read", "description": "Read a file", "input_schema": {"type": "object"}}],
+            "messages": [
+                {"role": "user", "content": "Read it"},
+                {"role": "assistant", "content": [{"type": "tool_use", "id": "toolu_1", "name": "read", "input": {}}]},
+                {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "toolu_1", "content": "data"}]},
+                {"role": "user", "content": "Thanks"}
+            ]
+        }))
+        .unwrap();
+        let options = ConversionOptions {
+            system_ack_text: "Understood.".to_string(),
+            paired_assistant_text: "Continue.".to_string(),
+            empty_content_placeholder: "Calling tool.".to_string(),
+            ..ConversionOptions::default()
+        };
+
+        let history = convert_request(&req, &options)
+            .unwrap()
+            .conversation_state
+            .history;
+        let assistant_texts: Vec<_> = history
+            .iter()
+            .filter_map(|msg| match msg {
+                Message::Assistant(a) => Some(a.assistant_response_message.content.as_str()),
+                Message::User(_) => None,
+            })
+            .collect();
+        // 每条合成的 assistant 消息都使用配置的非空文本
+        assert_eq!(
+            assistant_texts,
+            ["Understood.", "Calling tool.", "Continue."]
+        );
+    }
+
+    fn vision_request() -> MessagesRequest {
+        let image = |data: &str| serde_json::json!({"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": data}});
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "tools": [{"name": "screenshot", "description": "Take a screenshot", "input_schema": {"type": "object"}}],
+            "messages": [
+                {"role": "user", "content": [image("AAAA"), {"type": "text", "text": "What is this?"}]},
+                {"role": "assistant", "content": [
+                    {"type": "text", "text": "A chart."},
+                    {"type": "tool_use", "id": "shot_1", "name": "screenshot", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "shot_1", "content": [image("BBBB"), {"type": "text", "text": "saved"}]}
+                ]},
+                {"role": "assistant", "content": "Captured."},
+                {"role": "user", "content": "Compare with the previous image"}
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn history_images(history: &[Message]) -> Vec<String> {
+        history
+            .iter()
+            .filter_map(|msg| match msg {
+                Message::User(user) => Some(&user.user_input_message.images),
+                Message::Assistant(_) => None,
+            })
+            .flatten()
+            .map(|image| image.source.bytes.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_history_keeps_images_including_tool_results() {
+        let result = convert_request(&vision_request(), &ConversionOptions::default()).unwrap();
+        assert_eq!(
+            history_images(&result.conversation_state.history),
+            vec!["AAAA", "BBBB"]
+        );
+        assert!(result.fixups.is_empty());
+    }
+
+    #[test]
+    fn test_max_history_images_drops_oldest() {
+        let options = ConversionOptions {
+            max_history_images: Some(1),
+            ..ConversionOptions::default()
+        };
+        let result = convert_request(&vision_request(), &options).unwrap();
+        assert_eq!(
+            history_images(&result.conversation_state.history),
+            vec!["BBBB"]
+        );
+        assert_eq!(result.fixups, vec![Fixup::DroppedHistoryImages(1)]);
+    }
+
+    #[test]
+    fn test_conversation_token_budget_summarizes_history() {
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": [
+                {"role": "user", "content": "first question"},
+                {"role": "assistant", "content": "first answer"},
+                {"role": "user", "content": "second question"},
+                {"role": "assistant", "content": "second answer"},
+                {"role": "user", "content": "third question"}
+            ]
+        }))
+        .unwrap();
+        let conversation_id = uuid::Uuid::new_v4().to_string();
+        let options = ConversionOptions {
+            conversation_id: Some(conversation_id.clone()),
+            conversation_token_budget: 500,
+            compaction_keep_messages: 2,
+            ..ConversionOptions::default()
+        };
 
-        assert_eq!(tool.tool_specification.name, "my_custom_tool");
-        assert!(!tool.tool_specification.description.is_empty());
+        // 未超过预算时不压缩
+        let result = convert_request(&req, &options).unwrap();
+        assert!(result.fixups.is_empty());
 
-        // 验证 JSON 序列化正确
-        let json = serde_json::to_string(&tool).unwrap();
-        assert!(json.contains("\"name\":\"my_custom_tool\""));
+        conversation_budget::record(&conversation_id, 1000);
+        let result = convert_request(&req, &options).unwrap();
+        assert_eq!(result.fixups, vec![Fixup::SummarizedHistory(2)]);
+        let history = &result.conversation_state.history;
+        let contents: Vec<&str> = history
+            .iter()
+            .map(|msg| match msg {
+                Message::User(user) => user.user_input_message.content.as_str(),
+                Message::Assistant(assistant) => {
+                    assistant.assistant_response_message.content.as_str()
+                }
+            })
+            .collect();
+        assert_eq!(contents.len(), 4);
+        assert!(contents[0].starts_with(history_summary::SUMMARY_NOTICE));
+        assert!(contents[0].contains("- User: first question"));
+        assert!(contents[0].contains("- Assistant: first answer"));
+        assert_eq!(&contents[2..], ["second question", "second answer"]);
+    }
+
+    #[test]
+    fn test_convert_request_with_prefill() {
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": [
+                {"role": "user", "content": "Describe Paris as JSON"},
+                {"role": "assistant", "content": [{"type": "text", "text": "{\"city\": "}]}
+            ]
+        }))
+        .unwrap();
+
+        let result = convert_request(&req, &ConversionOptions::default()).unwrap();
+        assert_eq!(result.prefill.as_deref(), Some("{\"city\":"));
+
+        // 预填充作为历史中的 assistant 消息发送，末尾空白被去掉
+        let state = &result.conversation_state;
+        match state.history.last() {
+            Some(Message::Assistant(msg)) => {
+                assert_eq!(msg.assistant_response_message.content, "{\"city\":")
+            }
+            _ => panic!("预填充应作为最后一条历史消息"),
+        }
+        assert_eq!(
+            state.current_message.user_input_message.content,
+            PREFILL_CONTINUATION_PROMPT
+        );
+    }
+
+    #[test]
+    fn test_convert_request_rejects_prefill_with_thinking() {
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "thinking": {"type": "enabled", "budget_tokens": 2048},
+            "messages": [
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hello"}
+            ]
+        }))
+        .unwrap();
+
+        assert!(matches!(
+            convert_request(&req, &ConversionOptions::default()),
+            Err(ConversionError::PrefillWithThinking)
+        ));
     }
 
     #[test]
     fn test_history_tools_added_to_tools_list() {
-        use super::super::types::Message as AnthropicMessage;
+        use crate::anthropic::types::Message as AnthropicMessage;
 
         // 创建一个请求，历史中有工具使用，但 tools 列表为空
         let req = MessagesRequest {
@@ -902,9 +1404,10 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            extra: Default::default(),
         };
 
-        let result = convert_request(&req).unwrap();
+        let result = convert_request(&req, &ConversionOptions::default()).unwrap();
 
         // 验证 tools 列表中包含了历史中使用的工具的占位符定义
         let tools = &result
@@ -921,6 +1424,134 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_history_tools_use_learned_schema() {
+        use crate::anthropic::types::{
+            Message as AnthropicMessage, Metadata, Tool as AnthropicTool,
+        };
+
+        let session_id = Uuid::new_v4().to_string();
+        let metadata = Some(Metadata {
+            user_id: Some(format!("user_abc_account__session_{}", session_id)),
+        });
+
+        let mut input_schema = std::collections::HashMap::new();
+        input_schema.insert("type".to_string(), serde_json::json!("object"));
+        input_schema.insert(
+            "properties".to_string(),
+            serde_json::json!({"path": {"type": "string"}}),
+        );
+        input_schema.insert("required".to_string(), serde_json::json!(["path"]));
+
+        // 第一次请求携带 read 工具定义
+        let first = MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            messages: vec![AnthropicMessage {
+                role: "user".to_string(),
+                content: serde_json::json!("Read the file"),
+            }],
+            stream: false,
+            system: None,
+            tools: Some(vec![AnthropicTool {
+                tool_type: None,
+                name: "read".to_string(),
+                description: "Read a file".to_string(),
+                input_schema,
+                max_uses: None,
+            }]),
+            tool_choice: None,
+            thinking: None,
+            output_config: None,
+            metadata: metadata.clone(),
+            extra: Default::default(),
+        };
+        convert_request(&first, &ConversionOptions::default()).unwrap();
+
+        // 第二次请求移除了工具定义，但历史中仍引用 read
+        let second = MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            messages: vec![
+                AnthropicMessage {
+                    role: "user".to_string(),
+                    content: serde_json::json!("Read the file"),
+                },
+                AnthropicMessage {
+                    role: "assistant".to_string(),
+                    content: serde_json::json!([
+                        {"type": "tool_use", "id": "tool-1", "name": "read", "input": {"path": "/test.txt"}}
+                    ]),
+                },
+                AnthropicMessage {
+                    role: "user".to_string(),
+                    content: serde_json::json!([
+                        {"type": "tool_result", "tool_use_id": "tool-1", "content": "file content"}
+                    ]),
+                },
+            ],
+            stream: false,
+            system: None,
+            tools: None,
+            tool_choice: None,
+            thinking: None,
+            output_config: None,
+            metadata,
+            extra: Default::default(),
+        };
+        let result = convert_request(&second, &ConversionOptions::default()).unwrap();
+
+        let tool = result
+            .conversation_state
+            .current_message
+            .user_input_message
+            .user_input_message_context
+            .tools
+            .iter()
+            .find(|t| t.tool_specification.name == "read")
+            .expect("tools 列表应包含 'read'");
+        assert_eq!(tool.tool_specification.description, "Read a file");
+        assert_eq!(
+            tool.tool_specification.input_schema.json["required"][0],
+            "path"
+        );
+    }
+
+    #[test]
+    fn test_random_conversation_id_skips_tool_schema_cache() {
+        use crate::anthropic::types::{Message as AnthropicMessage, Tool as AnthropicTool};
+
+        // 没有会话信息时 conversationId 每次随机生成，不应写入工具 schema 缓存
+        let req = MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            messages: vec![AnthropicMessage {
+                role: "user".to_string(),
+                content: serde_json::json!("Read the file"),
+            }],
+            stream: false,
+            system: None,
+            tools: Some(vec![AnthropicTool {
+                tool_type: None,
+                name: "read".to_string(),
+                description: "Read a file".to_string(),
+                input_schema: Default::default(),
+                max_uses: None,
+            }]),
+            tool_choice: None,
+            thinking: None,
+            output_config: None,
+            metadata: None,
+            extra: Default::default(),
+        };
+        let result = convert_request(&req, &ConversionOptions::default()).unwrap();
+
+        assert!(
+            tool_schema_cache::lookup_tool(&result.conversation_state.conversation_id, "read")
+                .is_none()
+        );
+    }
+
     #[test]
     fn test_extract_session_id_valid() {
         // 测试有效的 user_id 格式
@@ -950,7 +1581,7 @@ mod tests {
 
     #[test]
     fn test_convert_request_with_session_metadata() {
-        use super::super::types::{Message as AnthropicMessage, Metadata};
+        use crate::anthropic::types::{Message as AnthropicMessage, Metadata};
 
         // 测试带有 metadata 的请求，应该使用 session UUID 作为 conversationId
         let req = MessagesRequest {
@@ -971,18 +1602,67 @@ mod tests {
                     "user_0dede55c6dcc4a11a30bbb5e7f22e6fdf86cdeba3820019cc27612af4e1243cd_account__session_a0662283-7fd3-4399-a7eb-52b9a717ae88".to_string(),
                 ),
             }),
+            extra: Default::default(),
         };
 
-        let result = convert_request(&req).unwrap();
+        let result = convert_request(&req, &ConversionOptions::default()).unwrap();
         assert_eq!(
             result.conversation_state.conversation_id,
             "a0662283-7fd3-4399-a7eb-52b9a717ae88"
         );
     }
 
+    #[test]
+    fn test_derive_affinity_key_distinguishes_subagents() {
+        use crate::anthropic::types::{Message as AnthropicMessage, Metadata, SystemMessage};
+
+        let make_req = |system: &str, task: &str| MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            messages: vec![AnthropicMessage {
+                role: "user".to_string(),
+                content: serde_json::json!(task),
+            }],
+            stream: false,
+            system: Some(vec![SystemMessage {
+                text: system.to_string(),
+            }]),
+            tools: None,
+            tool_choice: None,
+            thinking: None,
+            output_config: None,
+            metadata: Some(Metadata {
+                user_id: Some(
+                    "user_abc_account__session_a0662283-7fd3-4399-a7eb-52b9a717ae88".to_string(),
+                ),
+            }),
+            extra: Default::default(),
+        };
+
+        // 主会话：直接使用 user_id
+        let main = derive_affinity_key(&make_req("You are Claude Code", "fix the bug")).unwrap();
+        assert_eq!(
+            main,
+            "user_abc_account__session_a0662283-7fd3-4399-a7eb-52b9a717ae88"
+        );
+
+        // 子代理：不同任务得到不同的亲和键，相同任务保持稳定
+        let subagent_system =
+            "You are an agent for Claude Code, Anthropic's official CLI for Claude.";
+        let sub_a = derive_affinity_key(&make_req(subagent_system, "search for usages")).unwrap();
+        let sub_b = derive_affinity_key(&make_req(subagent_system, "review the tests")).unwrap();
+        let sub_a_again =
+            derive_affinity_key(&make_req(subagent_system, "search for usages")).unwrap();
+
+        assert!(sub_a.starts_with(&main));
+        assert_ne!(sub_a, main);
+        assert_ne!(sub_a, sub_b);
+        assert_eq!(sub_a, sub_a_again);
+    }
+
     #[test]
     fn test_convert_request_without_metadata() {
-        use super::super::types::Message as AnthropicMessage;
+        use crate::anthropic::types::Message as AnthropicMessage;
 
         // 测试没有 metadata 的请求，应该生成新的 UUID
         let req = MessagesRequest {
@@ -999,9 +1679,10 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            extra: Default::default(),
         };
 
-        let result = convert_request(&req).unwrap();
+        let result = convert_request(&req, &ConversionOptions::default()).unwrap();
         // 验证生成的是有效的 UUID 格式
         assert_eq!(result.conversation_state.conversation_id.len(), 36);
         assert_eq!(
@@ -1210,7 +1891,7 @@ mod tests {
 
     #[test]
     fn test_convert_assistant_message_tool_use_only() {
-        use super::super::types::Message as AnthropicMessage;
+        use crate::anthropic::types::Message as AnthropicMessage;
 
         // 测试仅包含 tool_use 的 assistant 消息（无 text 块）
         // Kiro API 要求 content 字段不能为空
@@ -1221,7 +1902,7 @@ mod tests {
             ]),
         };
 
-        let result = convert_assistant_message(&msg).expect("应该成功转换");
+        let result = convert_assistant_message(&msg, ".").expect("应该成功转换");
 
         // 验证 content 不为空（使用占位符）
         assert!(
@@ -1245,7 +1926,7 @@ mod tests {
 
     #[test]
     fn test_convert_assistant_message_with_text_and_tool_use() {
-        use super::super::types::Message as AnthropicMessage;
+        use crate::anthropic::types::Message as AnthropicMessage;
 
         // 测试同时包含 text 和 tool_use 的 assistant 消息
         let msg = AnthropicMessage {
@@ -1256,7 +1937,7 @@ mod tests {
             ]),
         };
 
-        let result = convert_assistant_message(&msg).expect("应该成功转换");
+        let result = convert_assistant_message(&msg, ".").expect("应该成功转换");
 
         // 验证 content 使用原始文本（不是占位符）
         assert_eq!(
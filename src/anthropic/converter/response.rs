@@ -0,0 +1,343 @@
+//! 响应转换：非流式响应聚合
+//!
+//! stream=false 时需要把上游事件流聚合为一条完整的 Message。
+//! 聚合内容（文本与工具输入）设有字节上限（`maxNonStreamResponseBytes`）：
+//! 超过上限时停止读取上游，已有内容截断到上限以内并以 `stop_reason = "max_tokens"` 结束，
+//! 避免异常的超大输出让单个请求占用无限内存。
+//! 上游返回的引用附加到文本块的 `citations` 字段。
+
+use std::collections::HashMap;
+
+use serde_json::{Value, json};
+
+use crate::kiro::model::events::Event;
+
+use crate::anthropic::prefill::PrefillEchoFilter;
+use crate::anthropic::truncation;
+use crate::anthropic::types::get_context_window_size;
+
+use super::ToolUseIdMap;
+use super::citations::citations_from_event;
+
+/// 聚合完成的消息内容
+pub struct AggregatedMessage {
+    /// Anthropic content blocks
+    pub content: Vec<Value>,
+    /// 停止原因
+    pub stop_reason: String,
+    /// 从 contextUsageEvent 计算的实际输入 tokens
+    pub context_input_tokens: Option<i32>,
+}
+
+/// 非流式响应聚合器
+pub struct NonStreamAggregator {
+    model: String,
+    /// 聚合内容字节上限
+    max_bytes: usize,
+    /// 已聚合的字节数（文本 + 工具输入）
+    aggregated_bytes: usize,
+    /// 是否因超过上限而截断
+    truncated: bool,
+    text_content: String,
+    /// 文本块的引用
+    citations: Vec<Value>,
+    tool_uses: Vec<Value>,
+    has_tool_use: bool,
+    stop_reason: String,
+    context_input_tokens: Option<i32>,
+    /// 收集工具调用的增量 JSON
+    tool_json_buffers: HashMap<String, String>,
+    /// 预填充请求中剥离上游复述的过滤器
+    prefill_filter: Option<PrefillEchoFilter>,
+    /// 规范化后的 tool_use id → 客户端原始 id
+    tool_use_ids: ToolUseIdMap,
+}
+
+impl NonStreamAggregator {
+    pub fn new(model: &str, max_bytes: usize) -> Self {
+        Self {
+            model: model.to_string(),
+            max_bytes,
+            aggregated_bytes: 0,
+            truncated: false,
+            text_content: String::new(),
+            citations: Vec::new(),
+            tool_uses: Vec::new(),
+            has_tool_use: false,
+            stop_reason: "end_turn".to_string(),
+            context_input_tokens: None,
+            tool_json_buffers: HashMap::new(),
+            prefill_filter: None,
+            tool_use_ids: ToolUseIdMap::default(),
+        }
+    }
+
+    /// 设置 assistant 预填充文本：返回的文本以它开头（不计入字节上限）
+    pub fn with_prefill(mut self, prefill: Option<String>) -> Self {
+        if let Some(prefill) = prefill {
+            self.text_content = prefill.clone();
+            self.prefill_filter = Some(PrefillEchoFilter::new(prefill));
+        }
+        self
+    }
+
+    /// 设置 tool_use id 对照表：返回的 tool_use id 还原为客户端原始 id
+    pub fn with_tool_use_ids(mut self, tool_use_ids: ToolUseIdMap) -> Self {
+        self.tool_use_ids = tool_use_ids;
+        self
+    }
+
+    /// 处理一个上游事件
+    ///
+    /// 返回 false 表示已达到字节上限，调用方应停止读取上游
+    pub fn handle_event(&mut self, event: Event) -> bool {
+        if self.truncated {
+            return false;
+        }
+
+        match event {
+            Event::AssistantResponse(resp) => {
+                let content = match &mut self.prefill_filter {
+                    Some(filter) => filter.push(&resp.content),
+                    None => resp.content,
+                };
+                let remaining = self.remaining_bytes();
+                if content.len() > remaining {
+                    self.text_content
+                        .push_str(&content[..floor_char_boundary(&content, remaining)]);
+                    self.truncate();
+                    return false;
+                }
+                self.aggregated_bytes += content.len();
+                self.text_content.push_str(&content);
+            }
+            Event::ToolUse(tool_use) => {
+                self.has_tool_use = true;
+
+                // 未完成的工具调用在截断时被丢弃
+                if tool_use.input.len() > self.remaining_bytes() {
+                    self.truncate();
+                    return false;
+                }
+                self.aggregated_bytes += tool_use.input.len();
+
+                // 累积工具的 JSON 输入
+                let buffer = self
+                    .tool_json_buffers
+                    .entry(tool_use.tool_use_id.clone())
+                    .or_default();
+                buffer.push_str(&tool_use.input);
+
+                // 如果是完整的工具调用，添加到列表
+                if tool_use.stop {
+                    let buffer = self
+                        .tool_json_buffers
+                        .remove(&tool_use.tool_use_id)
+                        .unwrap_or_default();
+                    let input = match serde_json::from_str::<Value>(&buffer) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            // 检测是否为截断
+                            let truncation_info = truncation::detect_truncation(
+                                &tool_use.name,
+                                &tool_use.tool_use_id,
+                                &buffer,
+                                None,
+                            );
+                            if truncation_info.is_truncated {
+                                tracing::warn!(
+                                    "工具输入被截断: tool={}, id={}, type={:?}",
+                                    tool_use.name,
+                                    tool_use.tool_use_id,
+                                    truncation_info.truncation_type
+                                );
+                            } else {
+                                tracing::warn!(
+                                    "工具输入 JSON 解析失败: {}, tool_use_id: {}, 原始内容: {}",
+                                    e,
+                                    tool_use.tool_use_id,
+                                    buffer
+                                );
+                            }
+                            json!({})
+                        }
+                    };
+
+                    self.tool_uses.push(json!({
+                        "type": "tool_use",
+                        "id": self.tool_use_ids.restore(&tool_use.tool_use_id),
+                        "name": tool_use.name,
+                        "input": input
+                    }));
+                }
+            }
+            Event::Citation(_) | Event::SupplementaryWebLinks(_) => {
+                self.citations.extend(citations_from_event(&event));
+            }
+            Event::ContextUsage(context_usage) => {
+                // 从上下文使用百分比计算实际的 input_tokens
+                let context_window = get_context_window_size(&self.model);
+                let actual_input_tokens = (context_usage.context_usage_percentage
+                    * (context_window as f64)
+                    / 100.0) as i32;
+                self.context_input_tokens = Some(actual_input_tokens);
+                // 上下文使用量达到 100% 时，设置 stop_reason 为 model_context_window_exceeded
+                if context_usage.context_usage_percentage >= 100.0 {
+                    self.stop_reason = "model_context_window_exceeded".to_string();
+                }
+                tracing::debug!(
+                    "收到 contextUsageEvent: {}%, 计算 input_tokens: {} (context_window: {})",
+                    context_usage.context_usage_percentage,
+                    actual_input_tokens,
+                    context_window
+                );
+            }
+            Event::Exception { exception_type, .. }
+                if exception_type == "ContentLengthExceededException" =>
+            {
+                self.stop_reason = "max_tokens".to_string();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// 结束聚合，生成 content blocks 与 stop_reason
+    pub fn finish(self) -> AggregatedMessage {
+        let mut stop_reason = self.stop_reason;
+        if self.truncated {
+            stop_reason = "max_tokens".to_string();
+        } else if self.has_tool_use && stop_reason == "end_turn" {
+            stop_reason = "tool_use".to_string();
+        }
+
+        let mut content: Vec<Value> = Vec::new();
+        if !self.text_content.is_empty() || !self.citations.is_empty() {
+            let mut block = json!({
+                "type": "text",
+                "text": self.text_content
+            });
+            if !self.citations.is_empty() {
+                block["citations"] = Value::Array(self.citations);
+            }
+            content.push(block);
+        }
+        content.extend(self.tool_uses);
+
+        AggregatedMessage {
+            content,
+            stop_reason,
+            context_input_tokens: self.context_input_tokens,
+        }
+    }
+
+    fn remaining_bytes(&self) -> usize {
+        self.max_bytes.saturating_sub(self.aggregated_bytes)
+    }
+
+    fn truncate(&mut self) {
+        self.truncated = true;
+        self.aggregated_bytes = self.max_bytes;
+        self.tool_json_buffers.clear();
+    }
+}
+
+/// 不超过 `index` 的最大字符边界
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    let mut index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(content: &str) -> Event {
+        Event::AssistantResponse(serde_json::from_value(json!({ "content": content })).unwrap())
+    }
+
+    fn tool(id: &str, input: &str, stop: bool) -> Event {
+        Event::ToolUse(
+            serde_json::from_value(json!({
+                "name": "Write",
+                "toolUseId": id,
+                "input": input,
+                "stop": stop
+            }))
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_aggregates_text_and_tools() {
+        let mut aggregator = NonStreamAggregator::new("claude-sonnet-4", 1024);
+        assert!(aggregator.handle_event(text("Hello, ")));
+        assert!(aggregator.handle_event(text("world")));
+        assert!(aggregator.handle_event(tool("t1", "{\"path\":", false)));
+        assert!(aggregator.handle_event(tool("t1", "\"a.txt\"}", true)));
+
+        let message = aggregator.finish();
+        assert_eq!(message.stop_reason, "tool_use");
+        assert_eq!(message.content[0]["text"], "Hello, world");
+        assert_eq!(message.content[1]["input"]["path"], "a.txt");
+    }
+
+    #[test]
+    fn test_truncates_text_at_limit() {
+        let mut aggregator = NonStreamAggregator::new("claude-sonnet-4", 8);
+        assert!(aggregator.handle_event(text("12345")));
+        // 多字节字符不会被截成半个
+        assert!(!aggregator.handle_event(text("67你好")));
+        assert!(!aggregator.handle_event(text("ignored")));
+
+        let message = aggregator.finish();
+        assert_eq!(message.stop_reason, "max_tokens");
+        assert_eq!(message.content[0]["text"], "1234567");
+    }
+
+    #[test]
+    fn test_drops_incomplete_tool_on_truncation() {
+        let mut aggregator = NonStreamAggregator::new("claude-sonnet-4", 16);
+        assert!(aggregator.handle_event(text("done")));
+        assert!(aggregator.handle_event(tool("t1", "{\"content\":", false)));
+        assert!(!aggregator.handle_event(tool("t1", "\"very long content\"}", true)));
+
+        let message = aggregator.finish();
+        assert_eq!(message.stop_reason, "max_tokens");
+        assert_eq!(message.content.len(), 1);
+    }
+
+    #[test]
+    fn test_attaches_citations_to_text_block() {
+        let mut aggregator = NonStreamAggregator::new("claude-sonnet-4", 1024);
+        assert!(aggregator.handle_event(text("Rust 1.0 was released in 2015.")));
+        let citation = Event::Citation(
+            serde_json::from_value(json!({
+                "citationText": "Rust 1.0 was released in 2015",
+                "citationLink": "https://blog.rust-lang.org"
+            }))
+            .unwrap(),
+        );
+        assert!(aggregator.handle_event(citation));
+
+        let message = aggregator.finish();
+        assert_eq!(message.content.len(), 1);
+        let citations = message.content[0]["citations"].as_array().unwrap();
+        assert_eq!(citations[0]["type"], "web_search_result_location");
+        assert_eq!(citations[0]["url"], "https://blog.rust-lang.org");
+    }
+
+    #[test]
+    fn test_prepends_prefill_without_echo() {
+        let mut aggregator = NonStreamAggregator::new("claude-sonnet-4", 1024)
+            .with_prefill(Some("{\"city\":".to_string()));
+        assert!(aggregator.handle_event(text("{\"ci")));
+        assert!(aggregator.handle_event(text("ty\": \"Paris\"}")));
+
+        let message = aggregator.finish();
+        assert_eq!(message.content[0]["text"], "{\"city\": \"Paris\"}");
+    }
+}
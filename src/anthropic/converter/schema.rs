@@ -0,0 +1,131 @@
+//! 工具定义转换
+//!
+//! 将 Anthropic 工具定义转换为 Kiro 的 ToolSpecification，
+//! 并为历史中引用但未定义的工具生成占位符定义
+
+#[cfg(feature = "compression")]
+use crate::anthropic::tool_compression;
+use crate::anthropic::types;
+use crate::kiro::model::requests::tool::{InputSchema, Tool, ToolSpecification};
+
+use super::{CompressionPass, Fixup};
+
+/// 追加到 Write 工具 description 末尾的内容
+const WRITE_TOOL_DESCRIPTION_SUFFIX: &str = "- IMPORTANT: If the content to write exceeds 150 lines, you MUST only write the first 50 lines using this tool, then use `Edit` tool to append the remaining content in chunks of no more than 50 lines each. If needed, leave a unique placeholder to help append content. Do NOT attempt to write all content at once.";
+
+/// 追加到 Edit 工具 description 末尾的内容
+const EDIT_TOOL_DESCRIPTION_SUFFIX: &str = "- IMPORTANT: If the `new_string` content exceeds 50 lines, you MUST split it into multiple Edit calls, each replacing no more than 50 lines at a time. If used to append content, leave a unique placeholder to help append content. On the final chunk, do NOT include the placeholder.";
+
+/// 为历史中使用但不在 tools 列表中的工具创建占位符定义
+/// Kiro API 要求：历史消息中引用的工具必须在 currentMessage.tools 中有定义
+pub(super) fn create_placeholder_tool(name: &str) -> Tool {
+    Tool {
+        tool_specification: ToolSpecification {
+            name: name.to_string(),
+            description: "Tool used in conversation history".to_string(),
+            input_schema: InputSchema::from_json(serde_json::json!({
+                "$schema": "http://json-schema.org/draft-07/schema#",
+                "type": "object",
+                "properties": {},
+                "required": [],
+                "additionalProperties": true
+            })),
+        },
+    }
+}
+
+/// 转换工具定义
+///
+/// 截断或压缩工具定义时记录到 `fixups`，实际执行的压缩步骤记录到 `passes`
+pub(super) fn convert_tools(
+    tools: &Option<Vec<types::Tool>>,
+    fixups: &mut Vec<Fixup>,
+    passes: &mut Vec<CompressionPass>,
+) -> Vec<Tool> {
+    let Some(tools) = tools else {
+        return Vec::new();
+    };
+
+    let converted: Vec<Tool> = tools
+        .iter()
+        .map(|t| {
+            let mut description = t.description.clone();
+
+            // 对 Write/Edit 工具追加自定义描述后缀
+            let suffix = match t.name.as_str() {
+                "Write" => WRITE_TOOL_DESCRIPTION_SUFFIX,
+                "Edit" => EDIT_TOOL_DESCRIPTION_SUFFIX,
+                _ => "",
+            };
+            if !suffix.is_empty() {
+                description.push('\n');
+                description.push_str(suffix);
+            }
+
+            // 限制描述长度为 10000 字符（安全截断 UTF-8，单次遍历）
+            let description = match description.char_indices().nth(10000) {
+                Some((idx, _)) => {
+                    fixups.push(Fixup::TruncatedToolDescription(t.name.clone()));
+                    description[..idx].to_string()
+                }
+                None => description,
+            };
+
+            Tool {
+                tool_specification: ToolSpecification {
+                    name: t.name.clone(),
+                    description,
+                    input_schema: InputSchema::from_json(serde_json::json!(t.input_schema)),
+                },
+            }
+        })
+        .collect();
+
+    compress_tools(converted, fixups, passes)
+}
+
+/// 工具总大小超过阈值时压缩工具定义
+#[cfg(feature = "compression")]
+fn compress_tools(
+    converted: Vec<Tool>,
+    fixups: &mut Vec<Fixup>,
+    passes: &mut Vec<CompressionPass>,
+) -> Vec<Tool> {
+    if !tool_compression::needs_compression(&converted) {
+        return converted;
+    }
+    let (compressed, compression_passes) = tool_compression::compress_tools_if_needed(&converted);
+    fixups.push(Fixup::CompressedTools {
+        original_bytes: tool_compression::calculate_tools_size(&converted),
+        compressed_bytes: tool_compression::calculate_tools_size(&compressed),
+    });
+    passes.extend(compression_passes);
+    compressed
+}
+
+/// 未启用 compression 特性时原样发送工具定义（超出上游大小限制时由上游拒绝）
+#[cfg(not(feature = "compression"))]
+fn compress_tools(
+    converted: Vec<Tool>,
+    _fixups: &mut Vec<Fixup>,
+    _passes: &mut Vec<CompressionPass>,
+) -> Vec<Tool> {
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_placeholder_tool() {
+        let tool = create_placeholder_tool("my_custom_tool");
+
+        assert_eq!(tool.tool_specification.name, "my_custom_tool");
+        assert!(!tool.tool_specification.description.is_empty());
+
+        // 验证 JSON 序列化正确
+        let json = serde_json::to_string(&tool).unwrap();
+        assert!(json.contains("\"name\":\"my_custom_tool\""));
+    }
+}
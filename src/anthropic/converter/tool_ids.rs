@@ -0,0 +1,244 @@
+//! tool_use id 规范化
+//!
+//! 部分客户端发送的 tool_use id 含有上游不接受的字符，或在不同轮次中重复使用同一个 id
+//! （例如每轮都从 `call_0` 开始编号），上游会拒绝请求或把 tool_result 配对到错误的 tool_use。
+//!
+//! 转换时按消息顺序为这些 id 分配稳定的新 id（由原始 id 与出现次序决定，同一会话的后续请求
+//! 得到相同结果），tool_result 跟随最近一次出现的同名 tool_use；
+//! 同时保留新 id → 原始 id 的对照表，返回内容中的 tool_use id 按对照表还原。
+
+use std::collections::{HashMap, VecDeque};
+
+use sha2::{Digest, Sha256};
+
+use crate::kiro::model::requests::conversation::Message;
+use crate::kiro::model::requests::tool::ToolResult;
+
+/// 上游接受的 tool_use id 最大长度
+const MAX_TOOL_USE_ID_LEN: usize = 64;
+
+/// 生成的 tool_use id 前缀
+const GENERATED_ID_PREFIX: &str = "tooluse_";
+
+/// 规范化后的 tool_use id → 客户端原始 id
+#[derive(Debug, Clone, Default)]
+pub struct ToolUseIdMap {
+    original: HashMap<String, String>,
+}
+
+impl ToolUseIdMap {
+    /// 还原为客户端原始 id（未被规范化的 id 原样返回）
+    pub fn restore<'a>(&'a self, id: &'a str) -> &'a str {
+        self.original.get(id).map_or(id, String::as_str)
+    }
+
+    /// 被规范化的 id 数量
+    pub fn len(&self) -> usize {
+        self.original.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.original.is_empty()
+    }
+}
+
+/// 规范化历史与当前消息中的 tool_use / tool_result id
+pub(super) fn normalize_tool_use_ids(
+    history: &mut [Message],
+    tool_results: &mut [ToolResult],
+) -> ToolUseIdMap {
+    let mut normalizer = Normalizer::default();
+    for msg in history.iter_mut() {
+        match msg {
+            Message::Assistant(assistant_msg) => {
+                let tool_uses = assistant_msg
+                    .assistant_response_message
+                    .tool_uses
+                    .iter_mut();
+                for tool_use in tool_uses.flatten() {
+                    tool_use.tool_use_id = normalizer.tool_use(&tool_use.tool_use_id);
+                }
+            }
+            Message::User(user_msg) => {
+                let context = &mut user_msg.user_input_message.user_input_message_context;
+                for result in &mut context.tool_results {
+                    result.tool_use_id = normalizer.tool_result(&result.tool_use_id);
+                }
+            }
+        }
+    }
+    for result in tool_results {
+        result.tool_use_id = normalizer.tool_result(&result.tool_use_id);
+    }
+
+    if !normalizer.map.is_empty() {
+        tracing::debug!("规范化了 {} 个 tool_use id", normalizer.map.len());
+    }
+    normalizer.map
+}
+
+#[derive(Default)]
+struct Normalizer {
+    map: ToolUseIdMap,
+    /// 原始 id 作为 tool_use 出现的次数
+    occurrences: HashMap<String, usize>,
+    /// 尚未被 tool_result 引用的 tool_use（原始 id → 规范化 id，按出现顺序）
+    pending: HashMap<String, VecDeque<String>>,
+    /// 原始 id 最近一次对应的规范化 id
+    latest: HashMap<String, String>,
+}
+
+impl Normalizer {
+    fn tool_use(&mut self, original: &str) -> String {
+        let occurrence = self.occurrences.entry(original.to_string()).or_insert(0);
+        let normalized = if *occurrence == 0 && is_valid_id(original) {
+            original.to_string()
+        } else {
+            generated_id(original, *occurrence)
+        };
+        *occurrence += 1;
+
+        if normalized != original {
+            self.map
+                .original
+                .insert(normalized.clone(), original.to_string());
+        }
+        self.pending
+            .entry(original.to_string())
+            .or_default()
+            .push_back(normalized.clone());
+        self.latest.insert(original.to_string(), normalized.clone());
+        normalized
+    }
+
+    fn tool_result(&mut self, original: &str) -> String {
+        self.pending
+            .get_mut(original)
+            .and_then(VecDeque::pop_front)
+            .or_else(|| self.latest.get(original).cloned())
+            .unwrap_or_else(|| original.to_string())
+    }
+}
+
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_TOOL_USE_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// 由原始 id 与出现次序生成稳定的 id
+fn generated_id(original: &str, occurrence: usize) -> String {
+    let digest = Sha256::digest(format!("{}#{}", original, occurrence).as_bytes());
+    format!("{}{}", GENERATED_ID_PREFIX, &hex::encode(digest)[..24])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::requests::conversation::{
+        AssistantMessage, HistoryAssistantMessage, HistoryUserMessage, UserInputMessageContext,
+    };
+    use crate::kiro::model::requests::tool::ToolUseEntry;
+
+    fn assistant(ids: &[&str]) -> Message {
+        let tool_uses = ids
+            .iter()
+            .map(|id| ToolUseEntry::new(*id, "read").with_input(serde_json::json!({})))
+            .collect();
+        Message::Assistant(HistoryAssistantMessage {
+            assistant_response_message: AssistantMessage::new("ok").with_tool_uses(tool_uses),
+        })
+    }
+
+    fn user(ids: &[&str]) -> Message {
+        let mut msg = HistoryUserMessage::new("", "claude-sonnet-4");
+        let results = ids
+            .iter()
+            .map(|id| ToolResult::success(*id, "done"))
+            .collect();
+        msg.user_input_message.user_input_message_context =
+            UserInputMessageContext::new().with_tool_results(results);
+        Message::User(msg)
+    }
+
+    fn tool_use_ids(msg: &Message) -> Vec<String> {
+        match msg {
+            Message::Assistant(m) => m
+                .assistant_response_message
+                .tool_uses
+                .iter()
+                .flatten()
+                .map(|t| t.tool_use_id.clone())
+                .collect(),
+            Message::User(m) => m
+                .user_input_message
+                .user_input_message_context
+                .tool_results
+                .iter()
+                .map(|r| r.tool_use_id.clone())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_valid_unique_ids_are_kept() {
+        let mut history = vec![user(&[]), assistant(&["toolu_01", "toolu_02"])];
+        let mut results = vec![ToolResult::success("toolu_02", "a")];
+        let map = normalize_tool_use_ids(&mut history, &mut results);
+        assert!(map.is_empty());
+        assert_eq!(tool_use_ids(&history[1]), ["toolu_01", "toolu_02"]);
+        assert_eq!(results[0].tool_use_id, "toolu_02");
+    }
+
+    #[test]
+    fn test_colliding_ids_across_turns() {
+        let mut history = vec![
+            user(&[]),
+            assistant(&["call_0"]),
+            user(&["call_0"]),
+            assistant(&["call_0"]),
+        ];
+        let mut results = vec![ToolResult::success("call_0", "second")];
+        let map = normalize_tool_use_ids(&mut history, &mut results);
+
+        assert_eq!(tool_use_ids(&history[1]), ["call_0"]);
+        assert_eq!(tool_use_ids(&history[2]), ["call_0"]);
+        let second = tool_use_ids(&history[3]).remove(0);
+        assert!(second.starts_with(GENERATED_ID_PREFIX));
+        assert_eq!(results[0].tool_use_id, second);
+        assert_eq!(map.restore(&second), "call_0");
+        assert_eq!(map.restore("toolu_other"), "toolu_other");
+
+        // 同一会话的后续请求得到相同的 id
+        let mut again = vec![
+            user(&[]),
+            assistant(&["call_0"]),
+            user(&["call_0"]),
+            assistant(&["call_0"]),
+        ];
+        normalize_tool_use_ids(&mut again, &mut []);
+        assert_eq!(tool_use_ids(&again[3]), [second]);
+    }
+
+    #[test]
+    fn test_invalid_characters_are_replaced() {
+        let mut history = vec![
+            user(&[]),
+            assistant(&["functions.read:0", "functions.read:1"]),
+        ];
+        let mut results = vec![
+            ToolResult::success("functions.read:1", "b"),
+            ToolResult::success("functions.read:0", "a"),
+        ];
+        let map = normalize_tool_use_ids(&mut history, &mut results);
+
+        let ids = tool_use_ids(&history[1]);
+        assert_eq!(map.len(), 2);
+        assert!(ids.iter().all(|id| is_valid_id(id)));
+        assert_eq!(results[0].tool_use_id, ids[1]);
+        assert_eq!(results[1].tool_use_id, ids[0]);
+        assert_eq!(map.restore(&ids[0]), "functions.read:0");
+    }
+}
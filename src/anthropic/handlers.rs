@@ -1,31 +1,72 @@
 //! Anthropic API Handler 函数
 
 use std::convert::Infallible;
+use std::sync::Arc;
 
+use crate::common::i18n;
+use crate::kiro::debug_dump::{
+    CompressionPassStats, ConversionStats, UnknownEventDump, UpstreamDump,
+};
+use crate::kiro::log_sanitizer;
+use crate::kiro::metrics::{ResponseBytes, UsageRecorder};
 use crate::kiro::model::events::Event;
 use crate::kiro::model::requests::kiro::KiroRequest;
 use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::provider::{
+    AttemptTrace, KiroProvider, UpstreamResponse, UpstreamStatusError, UpstreamTimeout,
+};
+use crate::kiro::token_manager::{FailureKind, PinnedCredentialUnavailable};
 use crate::token;
 use axum::{
     Json as JsonExtractor,
-    body::Body,
-    extract::State,
-    http::{StatusCode, header},
+    extract::{Extension, Query, State},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Json, Response},
 };
 use bytes::Bytes;
 use futures::{Stream, StreamExt, stream};
 use serde_json::json;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::time::interval;
 use uuid::Uuid;
 
-use super::converter::{ConversionError, convert_request};
-use super::middleware::AppState;
-use super::stream::{BufferedStreamContext, SseEvent, StreamContext};
-use super::truncation;
-use super::types::{CountTokensRequest, CountTokensResponse, ErrorResponse, MessagesRequest, Model, ModelsResponse, OutputConfig, Thinking, get_context_window_size};
-use super::websearch;
+use super::admission::{self, RequestPriority};
+use super::backpressure;
+use super::client_ip::ClientIp;
+use super::converter::{
+    AggregatedMessage, ConversionError, ConversionResult, Fixup, NonStreamAggregator, ToolUseIdMap,
+    convert_request, derive_affinity_key,
+};
+use super::keep_alive;
+use super::middleware::{AppState, ClientKey, CredentialPinError};
+use super::stream::{BufferedStreamContext, SseEvent, StreamContext, UpstreamStreamError};
+use super::types::{
+    CountTokensQuery, CountTokensRequest, CountTokensResponse, ErrorResponse, MessagesRequest,
+    Model, ModelsResponse, OutputConfig, Thinking,
+};
+#[cfg(feature = "mcp")]
+use super::websearch::{self, WebSearchToolRequest, WebSearchToolResponse};
+
+/// GET /ready
+///
+/// 就绪检查（无需认证）：排空模式下返回 503，供负载均衡摘除实例
+pub async fn get_ready(State(state): State<AppState>) -> Response {
+    let draining = state.lifecycle.is_draining();
+    let status = if draining {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (
+        status,
+        Json(json!({
+            "ready": !draining,
+            "draining": draining,
+            "activeStreams": state.lifecycle.active_streams(),
+        })),
+    )
+        .into_response()
+}
 
 /// GET /v1/models
 ///
@@ -187,11 +228,442 @@ pub async fn get_models() -> impl IntoResponse {
     })
 }
 
+/// 一次上游调用的参数
+struct UpstreamCall<'a> {
+    /// Kiro 请求体
+    body: &'a str,
+    /// 凭据亲和键
+    affinity_key: Option<&'a str>,
+    /// 固定使用的凭据 ID（x-kiro-credential-id）
+    pinned_id: Option<u64>,
+    /// 本次请求的超时（None 使用 requestTimeoutSecs）
+    timeout: Option<Duration>,
+    /// 本次请求的重试次数上限（None 使用 maxRetries）
+    max_retries: Option<usize>,
+    /// 客户端请求头（写入调试转储时脱敏）
+    headers: &'a HeaderMap,
+    /// 请求转换统计（写入调试转储）
+    conversion: &'a ConversionStats,
+}
+
+/// 上游返回 400 或 5xx 时写入调试转储（未配置 debugDumpDir 时跳过）
+fn dump_upstream_error(provider: &KiroProvider, call: &UpstreamCall<'_>, e: &anyhow::Error) {
+    let token_manager = provider.token_manager();
+    let (Some(dumps), Some(upstream)) = (
+        token_manager.debug_dumps(),
+        e.downcast_ref::<UpstreamStatusError>(),
+    ) else {
+        return;
+    };
+    let dump = UpstreamDump::new(
+        upstream.status.as_u16(),
+        call.headers,
+        call.body,
+        &upstream.body,
+        call.conversion.clone(),
+    );
+    match dumps.write(&dump) {
+        Ok(path) => tracing::info!("上游错误调试转储已写入 {}", path.display()),
+        Err(e) => tracing::warn!("写入调试转储失败: {}", e),
+    }
+}
+
+/// 记录响应流中的未知事件：按类型计数，每种类型首次出现时写入调试转储（未配置 debugDumpDir 时跳过）
+fn capture_unknown_event(provider: &KiroProvider, event: &Event) {
+    let Event::Unknown {
+        event_type,
+        payload,
+    } = event
+    else {
+        return;
+    };
+    let token_manager = provider.token_manager();
+    if token_manager.metrics().record_unknown_event(event_type) > 1 {
+        return;
+    }
+    let Some(dumps) = token_manager.debug_dumps() else {
+        return;
+    };
+    match dumps.write_unknown_event(&UnknownEventDump::new(event_type, payload)) {
+        Ok(path) => tracing::info!("未知事件 {} 已写入 {}", event_type, path.display()),
+        Err(e) => tracing::warn!("写入未知事件转储失败: {}", e),
+    }
+}
+
+/// 逐次尝试记录的响应头
+const ATTEMPTS_HEADER: &str = "x-kiro-attempts";
+
+/// 上游调用失败时的响应
+///
+/// 超时返回 504 timeout_error，其他错误返回 502 api_error；
+/// 错误附带尝试记录时同时返回 `x-kiro-attempts` 头
+fn upstream_error_response(e: &anyhow::Error) -> Response {
+    let mut response = if let Some(timeout) = e.downcast_ref::<UpstreamTimeout>() {
+        upstream_timeout_response(timeout.timeout)
+    } else if let Some(pinned) = e.downcast_ref::<PinnedCredentialUnavailable>() {
+        pinned_credential_unavailable_response(pinned)
+    } else {
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(ErrorResponse::new(
+                "api_error",
+                i18n::pick(
+                    format!("上游 API 调用失败: {}", e),
+                    format!("Upstream API call failed: {}", e),
+                ),
+            )),
+        )
+            .into_response()
+    };
+    if let Some(value) = e
+        .downcast_ref::<AttemptTrace>()
+        .and_then(|trace| header::HeaderValue::from_str(&trace.header_value()).ok())
+    {
+        response.headers_mut().insert(ATTEMPTS_HEADER, value);
+    }
+    response
+}
+
+/// 上游请求超时的响应（504 timeout_error）
+fn upstream_timeout_response(timeout: Duration) -> Response {
+    (
+        StatusCode::GATEWAY_TIMEOUT,
+        Json(ErrorResponse::new(
+            "timeout_error",
+            i18n::pick(
+                format!("上游请求超时（{}秒）", timeout.as_secs()),
+                format!("Upstream request timed out after {}s", timeout.as_secs()),
+            ),
+        )),
+    )
+        .into_response()
+}
+
+/// 请求转换失败的响应（400 invalid_request_error）
+fn conversion_error_response(e: &ConversionError) -> Response {
+    let message = match e {
+        ConversionError::UnsupportedModel(model) => i18n::pick(
+            format!("模型不支持: {}", model),
+            format!("Unsupported model: {}", model),
+        ),
+        ConversionError::EmptyMessages => {
+            i18n::pick("消息列表为空", "messages must not be empty").to_string()
+        }
+        ConversionError::PrefillWithThinking => i18n::pick(
+            "启用 thinking 时不支持 assistant 预填充",
+            "assistant message prefill is not supported when thinking is enabled",
+        )
+        .to_string(),
+        ConversionError::TooManyImages { count, limit } => i18n::pick(
+            format!("请求包含 {} 张图片，超过上限 {} 张", count, limit),
+            format!(
+                "Too many images: the request contains {} images, the maximum is {}",
+                count, limit
+            ),
+        ),
+        ConversionError::ImageTooLarge {
+            index,
+            bytes,
+            limit,
+        } => i18n::pick(
+            format!(
+                "第 {} 张图片大小为 {} 字节，超过上限 {} 字节",
+                index, bytes, limit
+            ),
+            format!(
+                "Image {} is {} bytes, which exceeds the maximum of {} bytes",
+                index, bytes, limit
+            ),
+        ),
+        ConversionError::ImageDimensionsTooLarge {
+            index,
+            width,
+            height,
+            limit,
+        } => i18n::pick(
+            format!(
+                "第 {} 张图片尺寸为 {}x{}，宽高不能超过 {} 像素",
+                index, width, height, limit
+            ),
+            format!(
+                "Image {} is {}x{} pixels; width and height must not exceed {} pixels",
+                index, width, height, limit
+            ),
+        ),
+    };
+    tracing::warn!("请求转换失败: {}", e);
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse::new("invalid_request_error", message)),
+    )
+        .into_response()
+}
+
+/// 固定凭据请求头无法使用时的响应（403 permission_error / 400 invalid_request_error）
+fn credential_pin_error_response(e: CredentialPinError) -> Response {
+    let (status, error_type, message) = match e {
+        CredentialPinError::Forbidden => (
+            StatusCode::FORBIDDEN,
+            "permission_error",
+            i18n::pick(
+                "固定凭据需要携带有效的 x-kiro-admin-key",
+                "Pinning a credential requires a valid x-kiro-admin-key",
+            ),
+        ),
+        CredentialPinError::InvalidId => (
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            i18n::pick(
+                "x-kiro-credential-id 必须是凭据 ID（数字）",
+                "x-kiro-credential-id must be a numeric credential ID",
+            ),
+        ),
+    };
+    (status, Json(ErrorResponse::new(error_type, message))).into_response()
+}
+
+/// 固定的凭据不存在（404 not_found_error）或当前不可用（503 service_unavailable）时的响应
+fn pinned_credential_unavailable_response(e: &PinnedCredentialUnavailable) -> Response {
+    tracing::warn!("固定凭据请求被拒绝: {}", e);
+    let (status, error_type, message) = match e.reason {
+        Some(reason) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "service_unavailable",
+            i18n::pick(
+                format!("凭据 #{} 当前不可用（{}）", e.id, reason.as_str()),
+                format!("Credential #{} is unavailable ({})", e.id, reason.as_str()),
+            ),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            "not_found_error",
+            i18n::pick(
+                format!("凭据 #{} 不存在", e.id),
+                format!("Credential #{} not found", e.id),
+            ),
+        ),
+    };
+    (status, Json(ErrorResponse::new(error_type, message))).into_response()
+}
+
+/// 记录请求转换耗时与各压缩步骤的耗时、节省字节数，返回供调试转储使用的转换统计
+fn record_conversion_metrics(
+    provider: &KiroProvider,
+    elapsed: Duration,
+    result: &ConversionResult,
+) -> ConversionStats {
+    let metrics = provider.token_manager().metrics();
+    let conversion = metrics.conversion();
+    conversion.record_conversion(elapsed);
+    for pass in &result.compression_passes {
+        conversion.record_pass(pass.name, pass.elapsed, pass.saved_bytes());
+    }
+    ConversionStats {
+        elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+        fixups: result.fixups.iter().map(Fixup::code).collect(),
+        compression_passes: result
+            .compression_passes
+            .iter()
+            .map(CompressionPassStats::from)
+            .collect(),
+    }
+}
+
+/// 严格模式下转换需要修正请求内容时的响应（400 invalid_request_error）
+fn strict_conversion_response(fixups: &[Fixup]) -> Response {
+    let details: Vec<String> = fixups.iter().map(Fixup::describe).collect();
+    tracing::warn!(
+        "严格模式拒绝请求，需要 {} 处修正: {:?}",
+        fixups.len(),
+        fixups
+    );
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse::new(
+            "invalid_request_error",
+            i18n::pick(
+                format!("严格模式：请求需要以下修正才能转发: {}", details.join("; ")),
+                format!(
+                    "Strict mode: the request would need these modifications: {}",
+                    details.join("; ")
+                ),
+            ),
+        )),
+    )
+        .into_response()
+}
+
+/// 非严格模式下报告转换修正的响应头
+///
+/// 值为逗号分隔的 [`Fixup::code`]，便于客户端发现被静默修改或丢弃的内容
+const FIXUPS_HEADER: &str = "x-kiro-fixups";
+
+/// `x-kiro-fixups` 响应头的最大长度，超出部分以 `+N more` 结尾
+const FIXUPS_HEADER_MAX_LEN: usize = 2048;
+
+/// 生成 `x-kiro-fixups` 响应头的值
+///
+/// 工具名、ID 等来自客户端，非可见 ASCII 字符替换为 `?`，逗号替换为 `_`
+fn fixups_header_value(fixups: &[Fixup]) -> String {
+    let mut value = String::new();
+    for (i, fixup) in fixups.iter().enumerate() {
+        let code: String = fixup
+            .code()
+            .chars()
+            .map(|c| match c {
+                ',' => '_',
+                c if c.is_ascii_graphic() => c,
+                _ => '?',
+            })
+            .collect();
+        let separator = if value.is_empty() { "" } else { "," };
+        let rest = fixups.len() - i;
+        // 非最后一项时预留 ",+N more" 后缀的空间
+        let reserve = if rest > 1 {
+            format!(",+{} more", rest - 1).len()
+        } else {
+            0
+        };
+        if value.len() + separator.len() + code.len() + reserve > FIXUPS_HEADER_MAX_LEN {
+            value.push_str(&format!("{}+{} more", separator, rest));
+            break;
+        }
+        value.push_str(separator);
+        value.push_str(&code);
+    }
+    value
+}
+
+/// 透传上游响应头时使用的前缀
+const UPSTREAM_HEADER_PREFIX: &str = "x-kiro-upstream-";
+
+/// 本次请求实际重试次数的响应头
+const RETRIES_HEADER: &str = "x-kiro-retries";
+
+/// 按白名单挑选上游响应头，改名为 `x-kiro-upstream-*` 返回给客户端，并附加 `x-kiro-retries`
+///
+/// 名称去掉 `x-` 前缀后拼接（如 `x-amzn-requestid` -> `x-kiro-upstream-amzn-requestid`），
+/// 便于向上游服务提交支持工单时定位请求
+fn upstream_headers(upstream: &HeaderMap, allowlist: &[String], retries: usize) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(RETRIES_HEADER, header::HeaderValue::from(retries));
+    for name in allowlist {
+        let name = name.to_ascii_lowercase();
+        let suffix = name.strip_prefix("x-").unwrap_or(&name);
+        let Ok(renamed) =
+            header::HeaderName::try_from(format!("{}{}", UPSTREAM_HEADER_PREFIX, suffix))
+        else {
+            continue;
+        };
+        // 同名头可能出现多次，逐个保留
+        for value in upstream.get_all(name.as_str()) {
+            headers.append(renamed.clone(), value.clone());
+        }
+    }
+    headers
+}
+
+/// 为响应附加透传的上游响应头与重试次数
+fn with_upstream_headers(mut response: Response, headers: HeaderMap) -> Response {
+    response.headers_mut().extend(headers);
+    response
+}
+
+/// 为响应附加 `x-kiro-fixups` 头（没有修正时不附加）
+fn with_fixups_header(mut response: Response, fixups: &[Fixup]) -> Response {
+    if fixups.is_empty() {
+        return response;
+    }
+    tracing::info!("请求转换应用了 {} 处修正: {:?}", fixups.len(), fixups);
+    if let Ok(value) = header::HeaderValue::from_str(&fixups_header_value(fixups)) {
+        response.headers_mut().insert(FIXUPS_HEADER, value);
+    }
+    response
+}
+
+/// 发送给上游的请求体字节数
+const REQUEST_BYTES_HEADER: &str = "x-kiro-request-bytes";
+
+/// 请求体占 `upstreamRequestLimitBytes` 的百分比
+const REQUEST_PERCENT_HEADER: &str = "x-kiro-request-percent";
+
+/// 为响应附加请求体大小提示头（未配置参考上限时不附加）
+///
+/// 历史不断增长的客户端（如 Claude Code）可据此在触发上游硬性失败前主动压缩上下文
+fn with_request_size_headers(
+    mut response: Response,
+    request_bytes: usize,
+    limit: usize,
+) -> Response {
+    if limit == 0 {
+        return response;
+    }
+    let percent = request_bytes.saturating_mul(100) / limit;
+    let headers = response.headers_mut();
+    headers.insert(
+        REQUEST_BYTES_HEADER,
+        header::HeaderValue::from(request_bytes),
+    );
+    headers.insert(REQUEST_PERCENT_HEADER, header::HeaderValue::from(percent));
+    response
+}
+
+/// 每日请求上限：达到后直接拒绝，不再消耗上游额度
+///
+/// 只在请求即将发往上游时计数，被准入控制拒绝或转换失败的请求不占用每日额度
+fn check_daily_limit(provider: &KiroProvider) -> Option<Response> {
+    let e = provider.token_manager().try_acquire_daily().err()?;
+    tracing::warn!("请求被每日请求上限拒绝: {}", e);
+    Some(daily_limit_exceeded_response(e.limit))
+}
+
+/// 达到每日请求上限时的响应（529 overloaded_error）
+fn daily_limit_exceeded_response(limit: u64) -> Response {
+    (
+        StatusCode::from_u16(529).unwrap_or(StatusCode::SERVICE_UNAVAILABLE),
+        Json(ErrorResponse::new(
+            "overloaded_error",
+            i18n::pick(
+                format!("已达到今日请求上限（{}），请明天再试", limit),
+                format!(
+                    "Daily request limit ({}) reached, please try again tomorrow",
+                    limit
+                ),
+            ),
+        )),
+    )
+        .into_response()
+}
+
+/// API Key 并发流数量达到上限时的响应（429 overloaded_error）
+fn concurrent_streams_exceeded_response(limit: usize) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(ErrorResponse::new(
+            "overloaded_error",
+            i18n::pick(
+                format!(
+                    "该 API Key 的并发流式请求数已达上限（{}），请稍后重试",
+                    limit
+                ),
+                format!(
+                    "Too many concurrent streams for this API key (limit {}), please retry later",
+                    limit
+                ),
+            ),
+        )),
+    )
+        .into_response()
+}
+
 /// POST /v1/messages
 ///
 /// 创建消息（对话）
 pub async fn post_messages(
     State(state): State<AppState>,
+    Extension(client_key): Extension<ClientKey>,
+    client_ip: Option<Extension<ClientIp>>,
+    headers: HeaderMap,
     JsonExtractor(mut payload): JsonExtractor<MessagesRequest>,
 ) -> Response {
     tracing::info!(
@@ -201,6 +673,7 @@ pub async fn post_messages(
         message_count = %payload.messages.len(),
         "Received POST /v1/messages request"
     );
+    payload.log_unknown_fields();
     // 检查 KiroProvider 是否可用
     let provider = match &state.kiro_provider {
         Some(p) => p.clone(),
@@ -216,44 +689,92 @@ pub async fn post_messages(
                 .into_response();
         }
     };
+    let timeout = state.request_timeout(&client_key.0, &headers);
+    let max_retries = state.max_retries(&client_key.0, &headers);
+    let session_conversation_id = state.session_conversation_id(&client_key.0, &headers);
+    let pinned_id = match state.pinned_credential(&headers) {
+        Ok(pinned_id) => pinned_id,
+        Err(e) => return credential_pin_error_response(e),
+    };
+    if let Some(id) = pinned_id {
+        tracing::info!("请求固定使用凭据 #{}", id);
+    }
+
+    // 每个 API Key 的并发流上限：超出时直接拒绝，避免单个用户占满凭据池
+    let stream_permit = if payload.stream {
+        match state.acquire_key_stream(&client_key.0) {
+            Ok(permit) => Some(permit),
+            Err(limit) => {
+                tracing::warn!("API Key {} 的并发流数量已达上限（{}）", client_key.0, limit);
+                return concurrent_streams_exceeded_response(limit);
+            }
+        }
+    } else {
+        None
+    };
+    let usage = UsageRecorder::new(provider.token_manager().metrics(), client_key.0);
+
+    // 准入控制：凭据紧张时按优先级排队，batch 请求最先被拒绝
+    let priority = RequestPriority::from_headers(&headers);
+    let queued_at = Instant::now();
+    let admitted = state
+        .admission
+        .acquire(priority, provider.token_manager().available_count())
+        .await;
+    provider
+        .token_manager()
+        .record_queue_delay(queued_at.elapsed());
+    let permit = match admitted {
+        Ok(permit) => permit,
+        Err(e) => {
+            tracing::warn!(priority = priority.as_str(), "请求被准入控制拒绝: {}", e);
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(ErrorResponse::new("rate_limit_error", e.to_string())),
+            )
+                .into_response();
+        }
+    };
 
     // 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
     override_thinking_from_model_name(&mut payload);
 
     // 检查是否为 WebSearch 请求
-    if websearch::has_web_search_tool(&payload) {
-        tracing::info!("检测到 WebSearch 工具，路由到 WebSearch 处理");
-
-        // 估算输入 tokens
-        let input_tokens = token::count_all_tokens(
-            payload.model.clone(),
-            payload.system.clone(),
-            payload.messages.clone(),
-            payload.tools.clone(),
-        ) as i32;
-
-        return websearch::handle_websearch_request(provider, &payload, input_tokens).await;
+    if let Some(response) = handle_web_search(&provider, &payload).await {
+        return admission::hold_permit(response, (permit, stream_permit));
     }
 
+    // 推导凭据亲和键（区分主会话与 Claude Code 子代理，可选回退到客户端 IP）
+    let affinity_key = derive_affinity_key(&payload)
+        .or_else(|| state.client_ip_affinity_key(client_ip.as_deref()));
+
     // 转换请求
-    let conversion_result = match convert_request(&payload) {
+    let mut options = state.conversion_options();
+    options.conversation_id = session_conversation_id;
+    let conversion_started = Instant::now();
+    let conversion_result = match convert_request(&payload, &options) {
         Ok(result) => result,
-        Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
-            tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
-        }
+        Err(e) => return conversion_error_response(&e),
+    };
+    let conversion_stats =
+        record_conversion_metrics(&provider, conversion_started.elapsed(), &conversion_result);
+
+    // 严格模式：转换需要修正请求内容时直接拒绝，便于调试客户端集成
+    if !conversion_result.fixups.is_empty() && state.strict_conversion(&headers) {
+        return strict_conversion_response(&conversion_result.fixups);
+    }
+    if let Some(response) = check_daily_limit(&provider) {
+        return response;
+    }
+    let fixups = conversion_result.fixups;
+    let restore = ResponseRestore {
+        prefill: conversion_result.prefill,
+        tool_use_ids: conversion_result.tool_use_ids,
+    };
+    let usage = if options.conversation_token_budget > 0 {
+        usage.with_conversation(&conversion_result.conversation_state.conversation_id)
+    } else {
+        usage
     };
 
     // 构建 Kiro 请求
@@ -270,14 +791,23 @@ pub async fn post_messages(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ErrorResponse::new(
                     "internal_error",
-                    format!("序列化请求失败: {}", e),
+                    i18n::pick(
+                        format!("序列化请求失败: {}", e),
+                        format!("Failed to serialize request: {}", e),
+                    ),
                 )),
             )
                 .into_response();
         }
     };
 
-    tracing::debug!("Kiro request body: {}", request_body);
+    tracing::debug!(
+        "Kiro request body: {}",
+        log_sanitizer::sanitize_body(
+            &request_body,
+            provider.token_manager().config().log_body_max_bytes
+        )
+    );
 
     // 估算输入 tokens
     let input_tokens = token::count_all_tokens(
@@ -294,63 +824,283 @@ pub async fn post_messages(
         .map(|t| t.is_enabled())
         .unwrap_or(false);
 
-    if payload.stream {
+    let request_limit = provider
+        .token_manager()
+        .config()
+        .upstream_request_limit_bytes;
+    let request_bytes = request_body.len();
+    if request_limit > 0 && request_bytes > request_limit {
+        tracing::warn!(
+            "请求体 {} 字节，超过参考上限 upstreamRequestLimitBytes ({})",
+            request_bytes,
+            request_limit
+        );
+    }
+
+    let response = if payload.stream {
         // 流式响应
         handle_stream_request(
             provider,
-            &request_body,
+            UpstreamCall {
+                body: &request_body,
+                affinity_key: affinity_key.as_deref(),
+                pinned_id,
+                timeout,
+                max_retries,
+                headers: &headers,
+                conversion: &conversion_stats,
+            },
             &payload.model,
             input_tokens,
             thinking_enabled,
+            restore,
+            usage,
         )
         .await
     } else {
         // 非流式响应
-        handle_non_stream_request(provider, &request_body, &payload.model, input_tokens).await
-    }
+        respond_non_stream(
+            provider,
+            UpstreamCall {
+                body: &request_body,
+                affinity_key: affinity_key.as_deref(),
+                pinned_id,
+                timeout,
+                max_retries,
+                headers: &headers,
+                conversion: &conversion_stats,
+            },
+            &payload.model,
+            payload.max_tokens,
+            input_tokens,
+            restore,
+            usage,
+        )
+        .await
+    };
+
+    let response = with_request_size_headers(response, request_bytes, request_limit);
+    admission::hold_permit(
+        with_fixups_header(response, &fixups),
+        (permit, stream_permit),
+    )
 }
 
 /// 处理流式请求
 async fn handle_stream_request(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
-    request_body: &str,
+    call: UpstreamCall<'_>,
     model: &str,
     input_tokens: i32,
     thinking_enabled: bool,
+    restore: ResponseRestore,
+    usage: UsageRecorder,
 ) -> Response {
     // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api_stream(request_body).await {
+    let upstream = match provider
+        .call_api_stream(
+            call.body,
+            call.affinity_key,
+            call.pinned_id,
+            call.timeout,
+            call.max_retries,
+        )
+        .await
+    {
         Ok(resp) => resp,
         Err(e) => {
             tracing::error!("Kiro API 调用失败: {}", e);
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("上游 API 调用失败: {}", e),
-                )),
-            )
-                .into_response();
+            dump_upstream_error(&provider, &call, &e);
+            return upstream_error_response(&e);
         }
     };
+    let passthrough = upstream_headers(
+        upstream.response.headers(),
+        &provider.token_manager().config().upstream_response_headers,
+        upstream.retries,
+    );
+    let failover = StreamFailover::new(provider.clone(), &call, &upstream);
 
     // 创建流处理上下文
-    let mut ctx = StreamContext::new_with_thinking(model, input_tokens, thinking_enabled);
+    let mut ctx = StreamContext::new_with_thinking(model, input_tokens, thinking_enabled)
+        .with_prefill(restore.prefill)
+        .with_tool_use_ids(restore.tool_use_ids);
 
     // 生成初始事件
     let initial_events = ctx.generate_initial_events();
 
     // 创建 SSE 流
-    let stream = create_sse_stream(response, ctx, initial_events);
+    let stream = create_sse_stream(upstream.response, ctx, initial_events, usage, failover);
+    let token_manager = provider.token_manager();
+    let body = backpressure::bounded_body(stream, token_manager.config(), token_manager.metrics());
 
     // 返回 SSE 响应
-    Response::builder()
+    let response = Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "text/event-stream")
         .header(header::CACHE_CONTROL, "no-cache")
         .header(header::CONNECTION, "keep-alive")
-        .body(Body::from_stream(stream))
-        .unwrap()
+        .body(body)
+        .unwrap();
+    with_upstream_headers(response, passthrough)
+}
+
+/// 流式响应中途失败时的故障转移状态
+///
+/// 上游内容尚未发送给客户端时，换用其他凭据重新发起请求（消耗本次请求剩余的重试次数）；
+/// 已发送内容后再重试会导致客户端收到重复内容，此时只返回 error 事件
+struct StreamFailover {
+    provider: Arc<KiroProvider>,
+    body: String,
+    affinity_key: Option<String>,
+    pinned_id: Option<u64>,
+    timeout: Option<Duration>,
+    /// 剩余可用的重试次数
+    retries_left: usize,
+    /// 当前响应使用的凭据
+    credential_id: u64,
+    /// 是否已向客户端发送上游内容（之后不再故障转移）
+    committed: bool,
+    /// 已读取的上游响应字节数
+    response_bytes: ResponseBytes,
+}
+
+impl StreamFailover {
+    fn new(
+        provider: Arc<KiroProvider>,
+        call: &UpstreamCall<'_>,
+        upstream: &UpstreamResponse,
+    ) -> Self {
+        let max_retries = call
+            .max_retries
+            .unwrap_or(provider.token_manager().config().max_retries);
+        let response_bytes = ResponseBytes::new(
+            provider.token_manager().metrics(),
+            upstream.model.clone(),
+            upstream.credential_id,
+        );
+        Self {
+            provider,
+            body: call.body.to_string(),
+            affinity_key: call.affinity_key.map(str::to_string),
+            pinned_id: call.pinned_id,
+            timeout: call.timeout,
+            retries_left: max_retries.saturating_sub(upstream.retries),
+            credential_id: upstream.credential_id,
+            committed: false,
+            response_bytes,
+        }
+    }
+
+    /// 上游流中断：按失败分类计入当前凭据，尚未发送内容时换用其他凭据重新请求
+    ///
+    /// `kind` 为 None 表示错误由请求本身导致（不计入凭据，也不重试）。
+    /// 返回新的上游响应；已发送内容、重试次数用尽或重新请求失败时返回 None
+    async fn interrupted(&mut self, kind: Option<FailureKind>) -> Option<reqwest::Response> {
+        let token_manager = self.provider.token_manager();
+        match kind {
+            None => return None,
+            Some(FailureKind::Auth) => {
+                token_manager.report_failure(self.credential_id);
+            }
+            Some(FailureKind::QuotaExhausted) => {
+                token_manager.report_quota_exhausted(self.credential_id);
+            }
+            Some(kind) => token_manager.report_transient_failure(self.credential_id, kind),
+        }
+        if self.committed || self.retries_left == 0 {
+            return None;
+        }
+
+        tracing::warn!(
+            "上游流在发送内容前中断，换用其他凭据重试（剩余重试 {} 次）",
+            self.retries_left
+        );
+        match self
+            .provider
+            .call_api_stream_failover(
+                &self.body,
+                self.affinity_key.as_deref(),
+                self.pinned_id,
+                self.credential_id,
+                self.timeout,
+                Some(self.retries_left - 1),
+            )
+            .await
+        {
+            Ok(upstream) => {
+                self.retries_left = self.retries_left.saturating_sub(upstream.retries + 1);
+                self.credential_id = upstream.credential_id;
+                self.response_bytes
+                    .switch_credential(upstream.credential_id);
+                Some(upstream.response)
+            }
+            Err(e) => {
+                tracing::error!("流式响应故障转移失败: {}", e);
+                self.retries_left = 0;
+                None
+            }
+        }
+    }
+}
+
+/// 需要在返回内容上还原的请求信息（来自转换结果）
+struct ResponseRestore {
+    /// assistant 预填充文本，拼接到返回内容的开头
+    prefill: Option<String>,
+    /// 规范化后的 tool_use id → 客户端原始 id
+    tool_use_ids: ToolUseIdMap,
+}
+
+/// 上游流中途失败的原因
+enum StreamFailure {
+    /// 读取响应流失败
+    Read(reqwest::Error),
+    /// 响应流在帧中间结束
+    Truncated,
+    /// 上游注入的 error / exception 事件
+    Upstream(UpstreamStreamError),
+}
+
+impl StreamFailure {
+    /// 返回给客户端的 Anthropic 错误类型
+    fn error_type(&self) -> &'static str {
+        match self {
+            Self::Read(_) | Self::Truncated => "api_error",
+            Self::Upstream(e) => e.error_type(),
+        }
+    }
+
+    /// 返回给客户端的错误消息
+    fn message(&self) -> String {
+        match self {
+            Self::Read(e) => i18n::pick(
+                format!("读取上游响应流失败: {}", e),
+                format!("Upstream stream interrupted: {}", e),
+            ),
+            Self::Truncated => i18n::pick(
+                "上游响应流异常结束".to_string(),
+                "Upstream stream closed unexpectedly".to_string(),
+            ),
+            Self::Upstream(e) => i18n::pick(
+                format!("上游返回错误 {}: {}", e.code, e.message),
+                format!("Upstream error {}: {}", e.code, e.message),
+            ),
+        }
+    }
+
+    /// 凭据失败分类（None：由请求本身导致，不计入凭据）
+    fn failure_kind(&self) -> Option<FailureKind> {
+        let Self::Upstream(e) = self else {
+            return Some(FailureKind::Network);
+        };
+        match e.error_type() {
+            "rate_limit_error" => Some(FailureKind::Throttled),
+            "authentication_error" | "permission_error" => Some(FailureKind::Auth),
+            "invalid_request_error" => None,
+            _ => Some(FailureKind::Upstream),
+        }
+    }
 }
 
 /// Ping 事件间隔（25秒）
@@ -366,6 +1116,8 @@ fn create_sse_stream(
     response: reqwest::Response,
     ctx: StreamContext,
     initial_events: Vec<SseEvent>,
+    usage: UsageRecorder,
+    failover: StreamFailover,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
     // 先发送初始事件
     let initial_stream = stream::iter(
@@ -378,8 +1130,8 @@ fn create_sse_stream(
     let body_stream = response.bytes_stream();
 
     let processing_stream = stream::unfold(
-        (body_stream, ctx, EventStreamDecoder::new(), false, interval(Duration::from_secs(PING_INTERVAL_SECS))),
-        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval)| async move {
+        (body_stream, ctx, EventStreamDecoder::new(), false, interval(Duration::from_secs(PING_INTERVAL_SECS)), usage, failover),
+        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval, usage, mut failover)| async move {
             if finished {
                 return None;
             }
@@ -390,6 +1142,7 @@ fn create_sse_stream(
                 chunk_result = body_stream.next() => {
                     match chunk_result {
                         Some(Ok(chunk)) => {
+                            failover.response_bytes.add(chunk.len());
                             // 解码事件
                             if let Err(e) = decoder.feed(&chunk) {
                                 tracing::warn!("缓冲区溢出: {}", e);
@@ -400,6 +1153,7 @@ fn create_sse_stream(
                                 match result {
                                     Ok(frame) => {
                                         if let Ok(event) = Event::from_frame(frame) {
+                                            capture_unknown_event(&failover.provider, &event);
                                             let sse_events = ctx.process_kiro_event(&event);
                                             events.extend(sse_events);
                                         }
@@ -410,32 +1164,71 @@ fn create_sse_stream(
                                 }
                             }
 
+                            if !events.is_empty() {
+                                failover.committed = true;
+                            }
+
+                            if let Some(error) = ctx.take_upstream_error() {
+                                let failure = StreamFailure::Upstream(error);
+                                // 尚未发送上游内容时换用其他凭据继续
+                                if let Some(response) = failover.interrupted(failure.failure_kind()).await {
+                                    let bytes: Vec<Result<Bytes, Infallible>> = Vec::new();
+                                    return Some((stream::iter(bytes), (response.bytes_stream(), ctx, EventStreamDecoder::new(), false, ping_interval, usage, failover)));
+                                }
+                                events.extend(stream_failure_events(&mut ctx, &usage, &failure));
+                                let bytes: Vec<Result<Bytes, Infallible>> = events
+                                    .into_iter()
+                                    .map(|e| Ok(Bytes::from(e.to_sse_string())))
+                                    .collect();
+                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage, failover)));
+                            }
+
                             // 转换为 SSE 字节流
                             let bytes: Vec<Result<Bytes, Infallible>> = events
                                 .into_iter()
                                 .map(|e| Ok(Bytes::from(e.to_sse_string())))
                                 .collect();
 
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)))
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, usage, failover)))
                         }
                         Some(Err(e)) => {
                             tracing::error!("读取响应流失败: {}", e);
-                            // 发送最终事件并结束
-                            let final_events = ctx.generate_final_events();
-                            let bytes: Vec<Result<Bytes, Infallible>> = final_events
+                            let failure = StreamFailure::Read(e);
+                            // 尚未发送上游内容时换用其他凭据继续
+                            if let Some(response) = failover.interrupted(failure.failure_kind()).await {
+                                let bytes: Vec<Result<Bytes, Infallible>> = Vec::new();
+                                return Some((stream::iter(bytes), (response.bytes_stream(), ctx, EventStreamDecoder::new(), false, ping_interval, usage, failover)));
+                            }
+                            // 已发送部分内容：返回 error 事件并结束，避免客户端把截断的响应当作完整结果
+                            let bytes: Vec<Result<Bytes, Infallible>> = stream_failure_events(&mut ctx, &usage, &failure)
                                 .into_iter()
                                 .map(|e| Ok(Bytes::from(e.to_sse_string())))
                                 .collect();
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)))
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage, failover)))
+                        }
+                        None if decoder.buffer_len() > 0 => {
+                            tracing::error!("上游响应流在帧中间结束（残留 {} 字节）", decoder.buffer_len());
+                            let failure = StreamFailure::Truncated;
+                            if let Some(response) = failover.interrupted(failure.failure_kind()).await {
+                                let bytes: Vec<Result<Bytes, Infallible>> = Vec::new();
+                                return Some((stream::iter(bytes), (response.bytes_stream(), ctx, EventStreamDecoder::new(), false, ping_interval, usage, failover)));
+                            }
+                            let bytes: Vec<Result<Bytes, Infallible>> = stream_failure_events(&mut ctx, &usage, &failure)
+                                .into_iter()
+                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
+                                .collect();
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage, failover)))
                         }
                         None => {
                             // 流结束，发送最终事件
                             let final_events = ctx.generate_final_events();
+                            let (input_tokens, output_tokens) = ctx.usage();
+                            usage.record_tokens(input_tokens, output_tokens);
                             let bytes: Vec<Result<Bytes, Infallible>> = final_events
                                 .into_iter()
                                 .map(|e| Ok(Bytes::from(e.to_sse_string())))
                                 .collect();
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)))
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage, failover)))
                         }
                     }
                 }
@@ -443,7 +1236,7 @@ fn create_sse_stream(
                 _ = ping_interval.tick() => {
                     tracing::trace!("发送 ping 保活事件");
                     let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
-                    Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)))
+                    Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, usage, failover)))
                 }
             }
         },
@@ -453,175 +1246,186 @@ fn create_sse_stream(
     initial_stream.chain(processing_stream)
 }
 
+/// 流中途失败：记录用量，生成 error 与 message_stop 事件
+fn stream_failure_events(
+    ctx: &mut StreamContext,
+    usage: &UsageRecorder,
+    failure: &StreamFailure,
+) -> Vec<SseEvent> {
+    let (input_tokens, output_tokens) = ctx.usage();
+    usage.record_tokens(input_tokens, output_tokens);
+    ctx.generate_error_events(failure.error_type(), &failure.message())
+}
+
+/// 处理非流式请求：启用 nonStreamKeepAliveSecs 且 max_tokens 达到阈值时在后台执行并发送保活空白字符
+async fn respond_non_stream(
+    provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
+    call: UpstreamCall<'_>,
+    model: &str,
+    max_tokens: i32,
+    input_tokens: i32,
+    restore: ResponseRestore,
+    usage: UsageRecorder,
+) -> Response {
+    let (interval, min_tokens) = {
+        let config = provider.token_manager().config();
+        (
+            config.non_stream_keep_alive_secs,
+            config.non_stream_keep_alive_min_tokens,
+        )
+    };
+    if interval == 0 || i64::from(max_tokens) < i64::from(min_tokens) {
+        return handle_non_stream_request(provider, call, model, input_tokens, restore, usage)
+            .await;
+    }
+
+    let body = call.body.to_string();
+    let affinity_key = call.affinity_key.map(str::to_string);
+    let (pinned_id, timeout, max_retries) = (call.pinned_id, call.timeout, call.max_retries);
+    let headers = call.headers.clone();
+    let conversion = call.conversion.clone();
+    let model = model.to_string();
+    let task = tokio::spawn(async move {
+        handle_non_stream_request(
+            provider,
+            UpstreamCall {
+                body: &body,
+                affinity_key: affinity_key.as_deref(),
+                pinned_id,
+                timeout,
+                max_retries,
+                headers: &headers,
+                conversion: &conversion,
+            },
+            &model,
+            input_tokens,
+            restore,
+            usage,
+        )
+        .await
+    });
+    keep_alive::keep_alive_response(task, Duration::from_secs(interval)).await
+}
+
 /// 处理非流式请求
 async fn handle_non_stream_request(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
-    request_body: &str,
+    call: UpstreamCall<'_>,
     model: &str,
     input_tokens: i32,
+    restore: ResponseRestore,
+    usage: UsageRecorder,
 ) -> Response {
     // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api(request_body).await {
+    let UpstreamResponse {
+        response,
+        retries,
+        credential_id,
+        model: upstream_model,
+    } = match provider
+        .call_api(
+            call.body,
+            call.affinity_key,
+            call.pinned_id,
+            call.timeout,
+            call.max_retries,
+        )
+        .await
+    {
         Ok(resp) => resp,
         Err(e) => {
             tracing::error!("Kiro API 调用失败: {}", e);
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("上游 API 调用失败: {}", e),
-                )),
-            )
-                .into_response();
-        }
-    };
-
-    // 读取响应体
-    let body_bytes = match response.bytes().await {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            tracing::error!("读取响应体失败: {}", e);
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("读取响应失败: {}", e),
-                )),
-            )
-                .into_response();
+            dump_upstream_error(&provider, &call, &e);
+            return upstream_error_response(&e);
         }
     };
+    let passthrough = upstream_headers(
+        response.headers(),
+        &provider.token_manager().config().upstream_response_headers,
+        retries,
+    );
 
-    // 解析事件流
+    // 边读取边解析事件流，聚合内容超过上限时停止读取上游
+    let max_bytes = provider
+        .token_manager()
+        .config()
+        .max_non_stream_response_bytes;
+    let mut aggregator = NonStreamAggregator::new(model, max_bytes)
+        .with_prefill(restore.prefill)
+        .with_tool_use_ids(restore.tool_use_ids);
     let mut decoder = EventStreamDecoder::new();
-    if let Err(e) = decoder.feed(&body_bytes) {
-        tracing::warn!("缓冲区溢出: {}", e);
-    }
-
-    let mut text_content = String::new();
-    let mut tool_uses: Vec<serde_json::Value> = Vec::new();
-    let mut has_tool_use = false;
-    let mut stop_reason = "end_turn".to_string();
-    // 从 contextUsageEvent 计算的实际输入 tokens
-    let mut context_input_tokens: Option<i32> = None;
-
-    // 收集工具调用的增量 JSON
-    let mut tool_json_buffers: std::collections::HashMap<String, String> =
-        std::collections::HashMap::new();
-
-    for result in decoder.decode_iter() {
-        match result {
-            Ok(frame) => {
-                if let Ok(event) = Event::from_frame(frame) {
-                    match event {
-                        Event::AssistantResponse(resp) => {
-                            text_content.push_str(&resp.content);
-                        }
-                        Event::ToolUse(tool_use) => {
-                            has_tool_use = true;
-
-                            // 累积工具的 JSON 输入
-                            let buffer = tool_json_buffers
-                                .entry(tool_use.tool_use_id.clone())
-                                .or_insert_with(String::new);
-                            buffer.push_str(&tool_use.input);
-
-                            // 如果是完整的工具调用，添加到列表
-                            if tool_use.stop {
-                                let parse_result: Result<serde_json::Value, _> =
-                                    serde_json::from_str(buffer);
-
-                                let input = match parse_result {
-                                    Ok(v) => v,
-                                    Err(e) => {
-                                        // 检测是否为截断
-                                        let truncation_info = truncation::detect_truncation(
-                                            &tool_use.name,
-                                            &tool_use.tool_use_id,
-                                            buffer,
-                                            None,
-                                        );
-                                        if truncation_info.is_truncated {
-                                            tracing::warn!(
-                                                "工具输入被截断: tool={}, id={}, type={:?}",
-                                                tool_use.name,
-                                                tool_use.tool_use_id,
-                                                truncation_info.truncation_type
-                                            );
-                                        } else {
-                                            tracing::warn!(
-                                                "工具输入 JSON 解析失败: {}, tool_use_id: {}, 原始内容: {}",
-                                                e, tool_use.tool_use_id, buffer
-                                            );
-                                        }
-                                        serde_json::json!({})
-                                    }
-                                };
-
-                                tool_uses.push(json!({
-                                    "type": "tool_use",
-                                    "id": tool_use.tool_use_id,
-                                    "name": tool_use.name,
-                                    "input": input
-                                }));
-                            }
-                        }
-                        Event::ContextUsage(context_usage) => {
-                            // 从上下文使用百分比计算实际的 input_tokens
-                            let context_window = get_context_window_size(model);
-                            let actual_input_tokens = (context_usage.context_usage_percentage
-                                * (context_window as f64)
-                                / 100.0)
-                                as i32;
-                            context_input_tokens = Some(actual_input_tokens);
-                            // 上下文使用量达到 100% 时，设置 stop_reason 为 model_context_window_exceeded
-                            if context_usage.context_usage_percentage >= 100.0 {
-                                stop_reason = "model_context_window_exceeded".to_string();
-                            }
-                            tracing::debug!(
-                                "收到 contextUsageEvent: {}%, 计算 input_tokens: {} (context_window: {})",
-                                context_usage.context_usage_percentage,
-                                actual_input_tokens,
-                                context_window
-                            );
-                        }
-                        Event::Exception { exception_type, .. } => {
-                            if exception_type == "ContentLengthExceededException" {
-                                stop_reason = "max_tokens".to_string();
-                            }
-                        }
-                        _ => {}
-                    }
-                }
+    let mut response_bytes = ResponseBytes::new(
+        provider.token_manager().metrics(),
+        upstream_model,
+        credential_id,
+    );
+    let mut body_stream = response.bytes_stream();
+    'read: while let Some(chunk) = body_stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => {
+                response_bytes.add(chunk.len());
+                chunk
             }
             Err(e) => {
-                tracing::warn!("解码事件失败: {}", e);
+                tracing::error!("读取响应体失败: {}", e);
+                if e.is_timeout() {
+                    let timeout = call.timeout.unwrap_or_else(|| {
+                        Duration::from_secs(provider.token_manager().config().request_timeout_secs)
+                    });
+                    return upstream_timeout_response(timeout);
+                }
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    Json(ErrorResponse::new(
+                        "api_error",
+                        i18n::pick(
+                            format!("读取响应失败: {}", e),
+                            format!("Failed to read upstream response: {}", e),
+                        ),
+                    )),
+                )
+                    .into_response();
             }
-        }
-    }
+        };
 
-    // 确定 stop_reason
-    if has_tool_use && stop_reason == "end_turn" {
-        stop_reason = "tool_use".to_string();
-    }
-
-    // 构建响应内容
-    let mut content: Vec<serde_json::Value> = Vec::new();
+        if let Err(e) = decoder.feed(&chunk) {
+            tracing::warn!("缓冲区溢出: {}", e);
+        }
 
-    if !text_content.is_empty() {
-        content.push(json!({
-            "type": "text",
-            "text": text_content
-        }));
+        for result in decoder.decode_iter() {
+            match result {
+                Ok(frame) => {
+                    let Ok(event) = Event::from_frame(frame) else {
+                        continue;
+                    };
+                    capture_unknown_event(&provider, &event);
+                    if !aggregator.handle_event(event) {
+                        tracing::warn!(
+                            "非流式响应内容超过 {} 字节上限，已截断并停止读取上游",
+                            max_bytes
+                        );
+                        break 'read;
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("解码事件失败: {}", e);
+                }
+            }
+        }
     }
 
-    content.extend(tool_uses);
+    let AggregatedMessage {
+        content,
+        stop_reason,
+        context_input_tokens,
+    } = aggregator.finish();
 
     // 估算输出 tokens
     let output_tokens = token::estimate_output_tokens(&content);
 
     // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则使用估算值
     let final_input_tokens = context_input_tokens.unwrap_or(input_tokens);
+    usage.record_tokens(final_input_tokens, output_tokens);
 
     // 构建 Anthropic 响应
     let response_body = json!({
@@ -638,7 +1442,10 @@ async fn handle_non_stream_request(
         }
     });
 
-    (StatusCode::OK, Json(response_body)).into_response()
+    with_upstream_headers(
+        (StatusCode::OK, Json(response_body)).into_response(),
+        passthrough,
+    )
 }
 
 /// 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
@@ -707,7 +1514,11 @@ fn override_thinking_from_model_name(payload: &mut MessagesRequest) {
 /// POST /v1/messages/count_tokens
 ///
 /// 计算消息的 token 数量
+///
+/// `?breakdown=true` 或 `anthropic-beta` 头含 `token-breakdown` 时，额外返回按来源拆分的本地估算
 pub async fn count_tokens(
+    Query(query): Query<CountTokensQuery>,
+    headers: HeaderMap,
     JsonExtractor(payload): JsonExtractor<CountTokensRequest>,
 ) -> impl IntoResponse {
     tracing::info!(
@@ -716,6 +1527,13 @@ pub async fn count_tokens(
         "Received POST /v1/messages/count_tokens request"
     );
 
+    let breakdown = (query.breakdown || wants_token_breakdown(&headers)).then(|| {
+        token::count_tokens_breakdown(
+            payload.system.as_deref(),
+            &payload.messages,
+            payload.tools.as_deref(),
+        )
+    });
     let total_tokens = token::count_all_tokens(
         payload.model,
         payload.system,
@@ -725,9 +1543,204 @@ pub async fn count_tokens(
 
     Json(CountTokensResponse {
         input_tokens: total_tokens.max(1) as i32,
+        breakdown,
     })
 }
 
+/// 请求 count_tokens 返回拆分结果的 beta 标识
+const TOKEN_BREAKDOWN_BETA: &str = "token-breakdown";
+
+/// anthropic-beta 头是否请求了 token 拆分
+fn wants_token_breakdown(headers: &HeaderMap) -> bool {
+    headers
+        .get_all("anthropic-beta")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|beta| beta.trim().starts_with(TOKEN_BREAKDOWN_BETA))
+}
+
+/// 工具列表只有 web_search 时路由到 WebSearch 处理，其他请求返回 None
+#[cfg(feature = "mcp")]
+async fn handle_web_search(
+    provider: &Arc<KiroProvider>,
+    payload: &MessagesRequest,
+) -> Option<Response> {
+    if !websearch::has_web_search_tool(payload) {
+        return None;
+    }
+    tracing::info!("检测到 WebSearch 工具，路由到 WebSearch 处理");
+    if let Some(response) = check_daily_limit(provider) {
+        return Some(response);
+    }
+
+    // 估算输入 tokens
+    let input_tokens = token::count_all_tokens(
+        payload.model.clone(),
+        payload.system.clone(),
+        payload.messages.clone(),
+        payload.tools.clone(),
+    ) as i32;
+
+    Some(websearch::handle_websearch_request(provider.clone(), payload, input_tokens).await)
+}
+
+/// 未启用 mcp 特性时 WebSearch 依赖的 MCP 调用不可用，直接拒绝 WebSearch 请求
+#[cfg(not(feature = "mcp"))]
+async fn handle_web_search(
+    _provider: &Arc<KiroProvider>,
+    payload: &MessagesRequest,
+) -> Option<Response> {
+    let web_search_only = payload
+        .tools
+        .as_ref()
+        .is_some_and(|tools| tools.len() == 1 && tools[0].name == "web_search");
+    web_search_only.then(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(
+                "invalid_request_error",
+                i18n::pick(
+                    "当前构建未启用 WebSearch（mcp 特性）",
+                    "web search is not available in this build (mcp feature disabled)",
+                ),
+            )),
+        )
+            .into_response()
+    })
+}
+
+/// 工具类端点（MCP 透传、直接搜索）的前置检查
+///
+/// 与消息端点一致：需要已配置上游，计入每日请求上限，并经过准入控制
+#[cfg(feature = "mcp")]
+async fn acquire_tool_call(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<(Arc<KiroProvider>, admission::AdmissionPermit), Response> {
+    let Some(provider) = state.kiro_provider.clone() else {
+        tracing::error!("KiroProvider 未配置");
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorResponse::new(
+                "service_unavailable",
+                "Kiro API provider not configured",
+            )),
+        )
+            .into_response());
+    };
+
+    let priority = RequestPriority::from_headers(headers);
+    let queued_at = Instant::now();
+    let admitted = state
+        .admission
+        .acquire(priority, provider.token_manager().available_count())
+        .await;
+    provider
+        .token_manager()
+        .record_queue_delay(queued_at.elapsed());
+    match admitted {
+        Ok(permit) => match check_daily_limit(&provider) {
+            Some(response) => Err(response),
+            None => Ok((provider, permit)),
+        },
+        Err(e) => {
+            tracing::warn!(priority = priority.as_str(), "请求被准入控制拒绝: {}", e);
+            Err((
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(ErrorResponse::new("rate_limit_error", e.to_string())),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// POST /v1/mcp
+///
+/// MCP 透传：将 JSON-RPC 请求原样转发到 Kiro MCP 端点（使用凭据池，计入每日请求上限与准入控制），
+/// 供本地支持 MCP 的工具调用 Kiro 托管的工具（如 `tools/list`、`tools/call`）
+#[cfg(feature = "mcp")]
+pub async fn post_mcp(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    JsonExtractor(payload): JsonExtractor<serde_json::Value>,
+) -> Response {
+    let Some(method) = payload.get("method").and_then(|m| m.as_str()) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(
+                "invalid_request_error",
+                i18n::pick(
+                    "请求体必须是包含 method 字段的 JSON-RPC 对象",
+                    "request body must be a JSON-RPC object with a method field",
+                ),
+            )),
+        )
+            .into_response();
+    };
+    tracing::info!(method = %method, "Received POST /v1/mcp request");
+
+    let (provider, _permit) = match acquire_tool_call(&state, &headers).await {
+        Ok(acquired) => acquired,
+        Err(response) => return response,
+    };
+
+    let response = match provider.call_mcp(&payload.to_string()).await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::error!("MCP 透传请求失败: {}", e);
+            return upstream_error_response(&e);
+        }
+    };
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::OK);
+    let body = match response.bytes().await {
+        Ok(body) => body,
+        Err(e) => return upstream_error_response(&e.into()),
+    };
+
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(axum::body::Body::from(body))
+        .unwrap()
+}
+
+/// POST /v1/tools/web_search
+///
+/// 直接执行 WebSearch（不经过模型），返回规范化的搜索结果
+#[cfg(feature = "mcp")]
+pub async fn post_web_search(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    JsonExtractor(payload): JsonExtractor<WebSearchToolRequest>,
+) -> Response {
+    let query = payload.query.trim().to_string();
+    if query.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(
+                "invalid_request_error",
+                i18n::pick("query 不能为空", "query must not be empty"),
+            )),
+        )
+            .into_response();
+    }
+    tracing::info!(query = %query, "Received POST /v1/tools/web_search request");
+
+    let (provider, _permit) = match acquire_tool_call(&state, &headers).await {
+        Ok(acquired) => acquired,
+        Err(response) => return response,
+    };
+
+    match websearch::search(&provider, &query).await {
+        Ok(results) => Json(WebSearchToolResponse::new(query, &results)).into_response(),
+        Err(e) => {
+            tracing::warn!("WebSearch 调用失败: {}", e);
+            upstream_error_response(&e)
+        }
+    }
+}
+
 /// POST /cc/v1/messages
 ///
 /// Claude Code 兼容端点，与 /v1/messages 的区别在于：
@@ -735,6 +1748,9 @@ pub async fn count_tokens(
 /// - message_start 中的 input_tokens 是从 contextUsageEvent 计算的准确值
 pub async fn post_messages_cc(
     State(state): State<AppState>,
+    Extension(client_key): Extension<ClientKey>,
+    client_ip: Option<Extension<ClientIp>>,
+    headers: HeaderMap,
     JsonExtractor(mut payload): JsonExtractor<MessagesRequest>,
 ) -> Response {
     tracing::info!(
@@ -744,6 +1760,7 @@ pub async fn post_messages_cc(
         message_count = %payload.messages.len(),
         "Received POST /cc/v1/messages request"
     );
+    payload.log_unknown_fields();
 
     // 检查 KiroProvider 是否可用
     let provider = match &state.kiro_provider {
@@ -760,44 +1777,92 @@ pub async fn post_messages_cc(
                 .into_response();
         }
     };
+    let timeout = state.request_timeout(&client_key.0, &headers);
+    let max_retries = state.max_retries(&client_key.0, &headers);
+    let session_conversation_id = state.session_conversation_id(&client_key.0, &headers);
+    let pinned_id = match state.pinned_credential(&headers) {
+        Ok(pinned_id) => pinned_id,
+        Err(e) => return credential_pin_error_response(e),
+    };
+    if let Some(id) = pinned_id {
+        tracing::info!("请求固定使用凭据 #{}", id);
+    }
+
+    // 每个 API Key 的并发流上限：超出时直接拒绝，避免单个用户占满凭据池
+    let stream_permit = if payload.stream {
+        match state.acquire_key_stream(&client_key.0) {
+            Ok(permit) => Some(permit),
+            Err(limit) => {
+                tracing::warn!("API Key {} 的并发流数量已达上限（{}）", client_key.0, limit);
+                return concurrent_streams_exceeded_response(limit);
+            }
+        }
+    } else {
+        None
+    };
+    let usage = UsageRecorder::new(provider.token_manager().metrics(), client_key.0);
+
+    // 准入控制：凭据紧张时按优先级排队，batch 请求最先被拒绝
+    let priority = RequestPriority::from_headers(&headers);
+    let queued_at = Instant::now();
+    let admitted = state
+        .admission
+        .acquire(priority, provider.token_manager().available_count())
+        .await;
+    provider
+        .token_manager()
+        .record_queue_delay(queued_at.elapsed());
+    let permit = match admitted {
+        Ok(permit) => permit,
+        Err(e) => {
+            tracing::warn!(priority = priority.as_str(), "请求被准入控制拒绝: {}", e);
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(ErrorResponse::new("rate_limit_error", e.to_string())),
+            )
+                .into_response();
+        }
+    };
 
     // 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
     override_thinking_from_model_name(&mut payload);
 
     // 检查是否为 WebSearch 请求
-    if websearch::has_web_search_tool(&payload) {
-        tracing::info!("检测到 WebSearch 工具，路由到 WebSearch 处理");
-
-        // 估算输入 tokens
-        let input_tokens = token::count_all_tokens(
-            payload.model.clone(),
-            payload.system.clone(),
-            payload.messages.clone(),
-            payload.tools.clone(),
-        ) as i32;
-
-        return websearch::handle_websearch_request(provider, &payload, input_tokens).await;
+    if let Some(response) = handle_web_search(&provider, &payload).await {
+        return admission::hold_permit(response, (permit, stream_permit));
     }
 
+    // 推导凭据亲和键（区分主会话与 Claude Code 子代理，可选回退到客户端 IP）
+    let affinity_key = derive_affinity_key(&payload)
+        .or_else(|| state.client_ip_affinity_key(client_ip.as_deref()));
+
     // 转换请求
-    let conversion_result = match convert_request(&payload) {
+    let mut options = state.conversion_options();
+    options.conversation_id = session_conversation_id;
+    let conversion_started = Instant::now();
+    let conversion_result = match convert_request(&payload, &options) {
         Ok(result) => result,
-        Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
-            tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
-        }
+        Err(e) => return conversion_error_response(&e),
+    };
+    let conversion_stats =
+        record_conversion_metrics(&provider, conversion_started.elapsed(), &conversion_result);
+
+    // 严格模式：转换需要修正请求内容时直接拒绝，便于调试客户端集成
+    if !conversion_result.fixups.is_empty() && state.strict_conversion(&headers) {
+        return strict_conversion_response(&conversion_result.fixups);
+    }
+    if let Some(response) = check_daily_limit(&provider) {
+        return response;
+    }
+    let fixups = conversion_result.fixups;
+    let restore = ResponseRestore {
+        prefill: conversion_result.prefill,
+        tool_use_ids: conversion_result.tool_use_ids,
+    };
+    let usage = if options.conversation_token_budget > 0 {
+        usage.with_conversation(&conversion_result.conversation_state.conversation_id)
+    } else {
+        usage
     };
 
     // 构建 Kiro 请求
@@ -814,14 +1879,23 @@ pub async fn post_messages_cc(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ErrorResponse::new(
                     "internal_error",
-                    format!("序列化请求失败: {}", e),
+                    i18n::pick(
+                        format!("序列化请求失败: {}", e),
+                        format!("Failed to serialize request: {}", e),
+                    ),
                 )),
             )
                 .into_response();
         }
     };
 
-    tracing::debug!("Kiro request body: {}", request_body);
+    tracing::debug!(
+        "Kiro request body: {}",
+        log_sanitizer::sanitize_body(
+            &request_body,
+            provider.token_manager().config().log_body_max_bytes
+        )
+    );
 
     // 估算输入 tokens
     let input_tokens = token::count_all_tokens(
@@ -838,20 +1912,66 @@ pub async fn post_messages_cc(
         .map(|t| t.is_enabled())
         .unwrap_or(false);
 
-    if payload.stream {
+    let request_limit = provider
+        .token_manager()
+        .config()
+        .upstream_request_limit_bytes;
+    let request_bytes = request_body.len();
+    if request_limit > 0 && request_bytes > request_limit {
+        tracing::warn!(
+            "请求体 {} 字节，超过参考上限 upstreamRequestLimitBytes ({})",
+            request_bytes,
+            request_limit
+        );
+    }
+
+    let response = if payload.stream {
         // 流式响应（缓冲模式）
         handle_stream_request_buffered(
             provider,
-            &request_body,
+            UpstreamCall {
+                body: &request_body,
+                affinity_key: affinity_key.as_deref(),
+                pinned_id,
+                timeout,
+                max_retries,
+                headers: &headers,
+                conversion: &conversion_stats,
+            },
             &payload.model,
             input_tokens,
             thinking_enabled,
+            restore,
+            usage,
         )
         .await
     } else {
         // 非流式响应（复用现有逻辑，已经使用正确的 input_tokens）
-        handle_non_stream_request(provider, &request_body, &payload.model, input_tokens).await
-    }
+        respond_non_stream(
+            provider,
+            UpstreamCall {
+                body: &request_body,
+                affinity_key: affinity_key.as_deref(),
+                pinned_id,
+                timeout,
+                max_retries,
+                headers: &headers,
+                conversion: &conversion_stats,
+            },
+            &payload.model,
+            payload.max_tokens,
+            input_tokens,
+            restore,
+            usage,
+        )
+        .await
+    };
+
+    let response = with_request_size_headers(response, request_bytes, request_limit);
+    admission::hold_permit(
+        with_fixups_header(response, &fixups),
+        (permit, stream_permit),
+    )
 }
 
 /// 处理流式请求（缓冲版本）
@@ -860,41 +1980,60 @@ pub async fn post_messages_cc(
 /// 然后用从 contextUsageEvent 计算的正确 input_tokens 生成 message_start 事件。
 async fn handle_stream_request_buffered(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
-    request_body: &str,
+    call: UpstreamCall<'_>,
     model: &str,
     estimated_input_tokens: i32,
     thinking_enabled: bool,
+    restore: ResponseRestore,
+    usage: UsageRecorder,
 ) -> Response {
     // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api_stream(request_body).await {
+    let upstream = match provider
+        .call_api_stream(
+            call.body,
+            call.affinity_key,
+            call.pinned_id,
+            call.timeout,
+            call.max_retries,
+        )
+        .await
+    {
         Ok(resp) => resp,
         Err(e) => {
             tracing::error!("Kiro API 调用失败: {}", e);
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("上游 API 调用失败: {}", e),
-                )),
-            )
-                .into_response();
+            dump_upstream_error(&provider, &call, &e);
+            return upstream_error_response(&e);
         }
     };
-
-    // 创建缓冲流处理上下文
-    let ctx = BufferedStreamContext::new(model, estimated_input_tokens, thinking_enabled);
+    let passthrough = upstream_headers(
+        upstream.response.headers(),
+        &provider.token_manager().config().upstream_response_headers,
+        upstream.retries,
+    );
+    let failover = StreamFailover::new(provider.clone(), &call, &upstream);
+
+    // 创建缓冲流处理上下文（故障转移时重新创建）
+    let model = model.to_string();
+    let new_ctx = move || {
+        BufferedStreamContext::new(model.clone(), estimated_input_tokens, thinking_enabled)
+            .with_prefill(restore.prefill.clone())
+            .with_tool_use_ids(restore.tool_use_ids.clone())
+    };
 
     // 创建缓冲 SSE 流
-    let stream = create_buffered_sse_stream(response, ctx);
+    let stream = create_buffered_sse_stream(upstream.response, new_ctx, usage, failover);
+    let token_manager = provider.token_manager();
+    let body = backpressure::bounded_body(stream, token_manager.config(), token_manager.metrics());
 
     // 返回 SSE 响应
-    Response::builder()
+    let response = Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "text/event-stream")
         .header(header::CACHE_CONTROL, "no-cache")
         .header(header::CONNECTION, "keep-alive")
-        .body(Body::from_stream(stream))
-        .unwrap()
+        .body(body)
+        .unwrap();
+    with_upstream_headers(response, passthrough)
 }
 
 /// 创建缓冲 SSE 事件流
@@ -906,9 +2045,12 @@ async fn handle_stream_request_buffered(
 /// 4. 一次性发送所有事件
 fn create_buffered_sse_stream(
     response: reqwest::Response,
-    ctx: BufferedStreamContext,
+    new_ctx: impl Fn() -> BufferedStreamContext + Send + 'static,
+    usage: UsageRecorder,
+    failover: StreamFailover,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
     let body_stream = response.bytes_stream();
+    let ctx = new_ctx();
 
     stream::unfold(
         (
@@ -917,8 +2059,10 @@ fn create_buffered_sse_stream(
             EventStreamDecoder::new(),
             false,
             interval(Duration::from_secs(PING_INTERVAL_SECS)),
+            usage,
+            (failover, new_ctx),
         ),
-        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval)| async move {
+        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval, usage, (mut failover, new_ctx))| async move {
             if finished {
                 return None;
             }
@@ -933,13 +2077,14 @@ fn create_buffered_sse_stream(
                     _ = ping_interval.tick() => {
                         tracing::trace!("发送 ping 保活事件（缓冲模式）");
                         let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
-                        return Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)));
+                        return Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, usage, (failover, new_ctx))));
                     }
 
                     // 然后处理数据流
                     chunk_result = body_stream.next() => {
-                        match chunk_result {
+                        let failure = match chunk_result {
                             Some(Ok(chunk)) => {
+                                failover.response_bytes.add(chunk.len());
                                 // 解码事件
                                 if let Err(e) = decoder.feed(&chunk) {
                                     tracing::warn!("缓冲区溢出: {}", e);
@@ -949,6 +2094,7 @@ fn create_buffered_sse_stream(
                                     match result {
                                         Ok(frame) => {
                                             if let Ok(event) = Event::from_frame(frame) {
+                                                capture_unknown_event(&failover.provider, &event);
                                                 // 缓冲事件（复用 StreamContext 的处理逻辑）
                                                 ctx.process_and_buffer(&event);
                                             }
@@ -958,28 +2104,48 @@ fn create_buffered_sse_stream(
                                         }
                                     }
                                 }
-                                // 继续读取下一个 chunk，不发送任何数据
+                                match ctx.take_upstream_error() {
+                                    Some(error) => StreamFailure::Upstream(error),
+                                    // 继续读取下一个 chunk，不发送任何数据
+                                    None => continue,
+                                }
                             }
                             Some(Err(e)) => {
                                 tracing::error!("读取响应流失败: {}", e);
-                                // 发生错误，完成处理并返回所有事件
-                                let all_events = ctx.finish_and_get_all_events();
-                                let bytes: Vec<Result<Bytes, Infallible>> = all_events
-                                    .into_iter()
-                                    .map(|e| Ok(Bytes::from(e.to_sse_string())))
-                                    .collect();
-                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)));
+                                StreamFailure::Read(e)
+                            }
+                            None if decoder.buffer_len() > 0 => {
+                                tracing::error!("上游响应流在帧中间结束（残留 {} 字节）", decoder.buffer_len());
+                                StreamFailure::Truncated
                             }
                             None => {
                                 // 流结束，完成处理并返回所有事件（已更正 input_tokens）
                                 let all_events = ctx.finish_and_get_all_events();
+                                let (input_tokens, output_tokens) = ctx.usage();
+                                usage.record_tokens(input_tokens, output_tokens);
                                 let bytes: Vec<Result<Bytes, Infallible>> = all_events
                                     .into_iter()
                                     .map(|e| Ok(Bytes::from(e.to_sse_string())))
                                     .collect();
-                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)));
+                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage, (failover, new_ctx))));
                             }
+                        };
+
+                        // 缓冲模式下尚未发送任何内容：丢弃已缓冲的事件，换用其他凭据重新请求
+                        if let Some(response) = failover.interrupted(failure.failure_kind()).await {
+                            body_stream = response.bytes_stream();
+                            ctx = new_ctx();
+                            decoder = EventStreamDecoder::new();
+                            continue;
                         }
+                        let (input_tokens, output_tokens) = ctx.usage();
+                        usage.record_tokens(input_tokens, output_tokens);
+                        let bytes: Vec<Result<Bytes, Infallible>> = ctx
+                            .finish_with_error(failure.error_type(), &failure.message())
+                            .into_iter()
+                            .map(|e| Ok(Bytes::from(e.to_sse_string())))
+                            .collect();
+                        return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage, (failover, new_ctx))));
                     }
                 }
             }
@@ -987,3 +2153,241 @@ fn create_buffered_sse_stream(
     )
     .flatten()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::provider::Attempt;
+
+    #[test]
+    fn test_fixups_header_value_joins_codes() {
+        let fixups = vec![
+            Fixup::DroppedToolResult("call_x".to_string()),
+            Fixup::InjectedToolDefinition("天气,查询".to_string()),
+            Fixup::CompressedTools {
+                original_bytes: 30000,
+                compressed_bytes: 20000,
+            },
+        ];
+        assert_eq!(
+            fixups_header_value(&fixups),
+            "dropped_tool_result:call_x,injected_tool_definition:??_??,compressed_tools:30000->20000"
+        );
+    }
+
+    #[test]
+    fn test_fixups_header_value_caps_length() {
+        let fixups: Vec<Fixup> = (0..500)
+            .map(|i| Fixup::DroppedToolUse(format!("toolu_{:020}", i)))
+            .collect();
+        let value = fixups_header_value(&fixups);
+        assert!(value.len() <= FIXUPS_HEADER_MAX_LEN);
+        assert!(value.ends_with(" more"));
+        assert!(header::HeaderValue::from_str(&value).is_ok());
+    }
+
+    #[test]
+    fn test_stream_failure_classification() {
+        let upstream = |code: &str| {
+            StreamFailure::Upstream(UpstreamStreamError {
+                code: code.to_string(),
+                message: "boom".to_string(),
+            })
+        };
+        assert_eq!(
+            StreamFailure::Truncated.failure_kind(),
+            Some(FailureKind::Network)
+        );
+        assert_eq!(
+            upstream("ThrottlingException").failure_kind(),
+            Some(FailureKind::Throttled)
+        );
+        assert_eq!(
+            upstream("AccessDeniedException").failure_kind(),
+            Some(FailureKind::Auth)
+        );
+        assert_eq!(upstream("ValidationException").failure_kind(), None);
+        assert_eq!(
+            upstream("InternalServerException").failure_kind(),
+            Some(FailureKind::Upstream)
+        );
+        assert!(
+            upstream("InternalServerException")
+                .message()
+                .contains("boom")
+        );
+    }
+
+    #[test]
+    fn test_upstream_headers_allowlist() {
+        let mut upstream = HeaderMap::new();
+        upstream.insert("x-amzn-requestid", "req-1".parse().unwrap());
+        upstream.insert("x-amzn-trace-id", "Root=1-abc".parse().unwrap());
+        upstream.append("x-amz-cf-pop", "NRT57-C1".parse().unwrap());
+        upstream.append("x-amz-cf-pop", "NRT20-P3".parse().unwrap());
+        let allowlist = vec![
+            "X-Amzn-RequestId".to_string(),
+            "x-amz-cf-pop".to_string(),
+            "x-missing".to_string(),
+        ];
+
+        let response = with_upstream_headers(
+            StatusCode::OK.into_response(),
+            upstream_headers(&upstream, &allowlist, 2),
+        );
+        let headers = response.headers();
+        assert_eq!(
+            headers.get("x-kiro-upstream-amzn-requestid").unwrap(),
+            "req-1"
+        );
+        let pops: Vec<_> = headers
+            .get_all("x-kiro-upstream-amz-cf-pop")
+            .iter()
+            .collect();
+        assert_eq!(pops, ["NRT57-C1", "NRT20-P3"]);
+        assert!(headers.get("x-kiro-upstream-amzn-trace-id").is_none());
+        assert_eq!(headers.get(RETRIES_HEADER).unwrap(), "2");
+        assert_eq!(headers.len(), 4);
+    }
+
+    #[test]
+    fn test_upstream_error_attempts_header() {
+        let e = AttemptTrace::attach(
+            UpstreamTimeout {
+                timeout: Duration::from_secs(5),
+            }
+            .into(),
+            vec![Attempt {
+                credential_id: Some(3),
+                status: Some(503),
+                error: "upstream",
+                delay: Duration::ZERO,
+            }],
+        );
+        let response = upstream_error_response(&e);
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(
+            response.headers()[ATTEMPTS_HEADER],
+            "cred=3;status=503;error=upstream;delay_ms=0"
+        );
+
+        let response = upstream_error_response(&anyhow::anyhow!("boom"));
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert!(response.headers().get(ATTEMPTS_HEADER).is_none());
+    }
+
+    #[test]
+    fn test_request_size_headers() {
+        let response = with_request_size_headers(StatusCode::OK.into_response(), 750, 1000);
+        assert_eq!(response.headers()[REQUEST_BYTES_HEADER], "750");
+        assert_eq!(response.headers()[REQUEST_PERCENT_HEADER], "75");
+
+        let response = with_request_size_headers(StatusCode::OK.into_response(), 750, 0);
+        assert!(response.headers().get(REQUEST_BYTES_HEADER).is_none());
+    }
+
+    #[test]
+    fn test_with_fixups_header_skips_empty() {
+        let response = with_fixups_header(StatusCode::OK.into_response(), &[]);
+        assert!(response.headers().get(FIXUPS_HEADER).is_none());
+
+        let fixups = vec![Fixup::DroppedImage("image/bmp".to_string())];
+        let response = with_fixups_header(StatusCode::OK.into_response(), &fixups);
+        assert_eq!(
+            response.headers().get(FIXUPS_HEADER).unwrap(),
+            "dropped_image:image/bmp"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_requests_do_not_consume_daily_limit() {
+        use crate::kiro::model::credentials::KiroCredentials;
+        use crate::kiro::token_manager::MultiTokenManager;
+        use crate::model::config::Config;
+
+        let mut config = Config::default();
+        config.daily_request_limit = 5;
+        let manager =
+            MultiTokenManager::new(config, vec![KiroCredentials::default()], None, None, false)
+                .unwrap();
+        let mut state =
+            AppState::new("key").with_kiro_provider(KiroProvider::new(Arc::new(manager)));
+        state.admission = admission::AdmissionController::new(1, 0, Duration::ZERO);
+        let request = |messages: serde_json::Value| {
+            JsonExtractor(
+                serde_json::from_value::<MessagesRequest>(json!({
+                    "model": "claude-sonnet-4",
+                    "max_tokens": 16,
+                    "messages": messages,
+                }))
+                .unwrap(),
+            )
+        };
+
+        // 转换失败（消息列表为空）
+        let response = post_messages(
+            State(state.clone()),
+            Extension(ClientKey("default".to_string())),
+            None,
+            HeaderMap::new(),
+            request(json!([])),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // 准入控制拒绝（并发已满且不允许排队）
+        let _held = state
+            .admission
+            .acquire(RequestPriority::Interactive, 1)
+            .await
+            .unwrap();
+        let response = post_messages(
+            State(state.clone()),
+            Extension(ClientKey("default".to_string())),
+            None,
+            HeaderMap::new(),
+            request(json!([{"role": "user", "content": "hi"}])),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let provider = state.kiro_provider.as_ref().unwrap();
+        assert_eq!(provider.token_manager().daily_limit_status().requests, 0);
+    }
+
+    #[cfg(feature = "mcp")]
+    #[tokio::test]
+    async fn test_post_mcp_rejects_non_jsonrpc_body() {
+        let state = AppState::new("key");
+        let response = post_mcp(
+            State(state.clone()),
+            HeaderMap::new(),
+            JsonExtractor(json!({"params": {}})),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // 未配置上游时返回 503
+        let response = post_mcp(
+            State(state),
+            HeaderMap::new(),
+            JsonExtractor(json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"})),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[cfg(feature = "mcp")]
+    #[tokio::test]
+    async fn test_post_web_search_rejects_empty_query() {
+        let response = post_web_search(
+            State(AppState::new("key")),
+            HeaderMap::new(),
+            JsonExtractor(WebSearchToolRequest {
+                query: "  ".to_string(),
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,427 @@
+//! 非流式请求幂等
+//!
+//! 客户端在网络超时后重试非流式请求时，上游可能已经完成并扣除了额度。
+//! 请求携带 `Idempotency-Key` 头时，按 API Key 名称 + 该值保存成功的最终响应，
+//! `idempotencyTtlSecs` 内相同 key 的重试直接返回保存的响应（附带 `x-kiro-idempotent-replay: true`），
+//! 不再调用上游。
+//!
+//! - 同一 key 对应的请求体不同（按 SHA-256 比较）时返回 422
+//! - 相同 key 的请求仍在处理中时返回 409
+//! - 只保存成功响应；失败或客户端中途断开时释放 key，允许重试
+//! - 保存的响应体总大小受 `idempotencyMaxBytes` 限制，超出时淘汰最早的响应
+//! - 流式请求不受影响
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::State,
+    http::{HeaderMap, HeaderValue, Request, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use futures::{StreamExt, stream};
+use parking_lot::Mutex;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::common::i18n;
+
+use super::middleware::{AppState, ClientKey};
+use super::router::MAX_BODY_SIZE;
+use super::types::ErrorResponse;
+
+/// 客户端声明幂等键的请求头
+const IDEMPOTENCY_HEADER: &str = "idempotency-key";
+
+/// 返回已保存响应时附加的响应头
+const REPLAY_HEADER: &str = "x-kiro-idempotent-replay";
+
+/// 幂等键的最大长度
+const MAX_KEY_LEN: usize = 256;
+
+/// 请求体摘要
+type BodyHash = [u8; 32];
+
+/// 已保存的响应
+#[derive(Debug, Clone)]
+struct StoredResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl StoredResponse {
+    fn to_response(&self) -> Response {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+            .headers_mut()
+            .insert(REPLAY_HEADER, HeaderValue::from_static("true"));
+        response
+    }
+}
+
+#[derive(Debug)]
+enum Entry {
+    /// 请求处理中
+    Pending { hash: BodyHash },
+    /// 已完成（只保存成功响应）
+    Done {
+        hash: BodyHash,
+        response: StoredResponse,
+        stored_at: Instant,
+    },
+}
+
+/// 查询幂等键的结果
+enum Lookup {
+    /// 首次请求：继续处理，完成后保存响应
+    Proceed(PendingGuard),
+    /// 重试：返回已保存的响应
+    Replay(Response),
+    /// 相同 key 的请求仍在处理中
+    InProgress,
+    /// 相同 key 对应不同的请求体
+    Mismatch,
+}
+
+/// 幂等响应存储
+pub struct IdempotencyStore {
+    ttl: Duration,
+    max_bytes: usize,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl IdempotencyStore {
+    /// 创建存储
+    ///
+    /// # Arguments
+    /// * `ttl_secs` - 响应保存时间（秒）
+    /// * `max_bytes` - 保存的响应体总大小上限
+    pub fn new(ttl_secs: u64, max_bytes: usize) -> Self {
+        Self {
+            ttl: Duration::from_secs(ttl_secs),
+            max_bytes,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn begin(self: &Arc<Self>, key: String, hash: BodyHash) -> Lookup {
+        let mut entries = self.entries.lock();
+        let now = Instant::now();
+        entries.retain(|_, entry| match entry {
+            Entry::Pending { .. } => true,
+            Entry::Done { stored_at, .. } => now.duration_since(*stored_at) <= self.ttl,
+        });
+        match entries.get(&key) {
+            Some(Entry::Pending { hash: existing } | Entry::Done { hash: existing, .. })
+                if *existing != hash =>
+            {
+                Lookup::Mismatch
+            }
+            Some(Entry::Pending { .. }) => Lookup::InProgress,
+            Some(Entry::Done { response, .. }) => Lookup::Replay(response.to_response()),
+            None => {
+                entries.insert(key.clone(), Entry::Pending { hash });
+                Lookup::Proceed(PendingGuard {
+                    store: self.clone(),
+                    key: Some(key),
+                    hash,
+                })
+            }
+        }
+    }
+
+    /// 保存响应，超出总大小上限时淘汰最早保存的响应（单个响应超过上限时不保存）
+    fn complete(&self, key: String, hash: BodyHash, response: StoredResponse) {
+        let mut entries = self.entries.lock();
+        if response.body.len() > self.max_bytes {
+            entries.remove(&key);
+            return;
+        }
+        let mut used: usize = entries.values().map(Self::stored_bytes).sum();
+        while used + response.body.len() > self.max_bytes {
+            let Some(oldest) = entries
+                .iter()
+                .filter_map(|(key, entry)| match entry {
+                    Entry::Done { stored_at, .. } => Some((key, *stored_at)),
+                    Entry::Pending { .. } => None,
+                })
+                .min_by_key(|(_, stored_at)| *stored_at)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = entries.remove(&oldest) {
+                used -= Self::stored_bytes(&entry);
+            }
+        }
+        entries.insert(
+            key,
+            Entry::Done {
+                hash,
+                response,
+                stored_at: Instant::now(),
+            },
+        );
+    }
+
+    fn stored_bytes(entry: &Entry) -> usize {
+        match entry {
+            Entry::Done { response, .. } => response.body.len(),
+            Entry::Pending { .. } => 0,
+        }
+    }
+}
+
+/// 处理中的幂等键：未保存响应就被丢弃（请求失败或客户端断开）时释放 key
+struct PendingGuard {
+    store: Arc<IdempotencyStore>,
+    key: Option<String>,
+    hash: BodyHash,
+}
+
+impl PendingGuard {
+    fn complete(mut self, response: StoredResponse) {
+        if let Some(key) = self.key.take() {
+            self.store.complete(key, self.hash, response);
+        }
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.store.entries.lock().remove(&key);
+        }
+    }
+}
+
+/// 幂等中间件（仅作用于 Messages 路由）
+pub async fn idempotency_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(store) = state.idempotency.clone() else {
+        return next.run(request).await;
+    };
+    let Some(idempotency_key) = request
+        .headers()
+        .get(IDEMPOTENCY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && v.len() <= MAX_KEY_LEN)
+        .map(str::to_string)
+    else {
+        return next.run(request).await;
+    };
+    let key_name = request
+        .extensions()
+        .get::<ClientKey>()
+        .map(|k| k.0.clone())
+        .unwrap_or_default();
+
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::warn!("读取请求体失败: {}", e);
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+    let is_stream = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|v| v.get("stream").and_then(Value::as_bool))
+        .unwrap_or(false);
+    let request = Request::from_parts(parts, Body::from(body.clone()));
+    if is_stream {
+        return next.run(request).await;
+    }
+
+    let hash: BodyHash = Sha256::digest(&body).into();
+    let guard = match store.begin(format!("{}:{}", key_name, idempotency_key), hash) {
+        Lookup::Proceed(guard) => guard,
+        Lookup::Replay(response) => {
+            tracing::info!("Idempotency-Key 命中，返回已保存的响应");
+            return response;
+        }
+        Lookup::InProgress => return in_progress_response(),
+        Lookup::Mismatch => return mismatch_response(),
+    };
+
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        return response;
+    }
+    record_response(response, guard)
+}
+
+/// 边转发边缓冲响应体（不影响非流式保活的空白字符），完整读取后保存
+///
+/// 保活开始后状态码固定为 200，错误只能从响应体判断：响应体不是 JSON 或为 `error` 类型时不保存
+fn record_response(response: Response, guard: PendingGuard) -> Response {
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let status = parts.status;
+    let pending = Some((guard, parts.headers.clone()));
+
+    let state = (body.into_data_stream(), Vec::new(), pending);
+    let body = stream::unfold(
+        state,
+        move |(mut data, mut buffer, mut pending)| async move {
+            match data.next().await {
+                Some(Ok(chunk)) => {
+                    buffer.extend_from_slice(&chunk);
+                    Some((Ok(chunk), (data, buffer, pending)))
+                }
+                // 读取失败时丢弃 guard，释放 key
+                Some(Err(e)) => Some((Err(e), (data, buffer, None))),
+                None => {
+                    if let Some((guard, headers)) = pending.take() {
+                        let body = Bytes::from(buffer.trim_ascii_start().to_vec());
+                        let is_error = serde_json::from_slice::<Value>(&body)
+                            .map(|v| v.get("type").and_then(Value::as_str) == Some("error"))
+                            .unwrap_or(true);
+                        if !is_error {
+                            guard.complete(StoredResponse {
+                                status,
+                                headers,
+                                body,
+                            });
+                        }
+                    }
+                    None
+                }
+            }
+        },
+    );
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// 相同 key 的请求仍在处理中（409）
+fn in_progress_response() -> Response {
+    (
+        StatusCode::CONFLICT,
+        Json(ErrorResponse::new(
+            "invalid_request_error",
+            i18n::pick(
+                "使用相同 Idempotency-Key 的请求仍在处理中，请稍后重试",
+                "A request with the same Idempotency-Key is still in progress, please retry later",
+            ),
+        )),
+    )
+        .into_response()
+}
+
+/// 相同 key 对应不同的请求体（422）
+fn mismatch_response() -> Response {
+    (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Json(ErrorResponse::new(
+            "invalid_request_error",
+            i18n::pick(
+                "Idempotency-Key 已用于不同的请求体",
+                "Idempotency-Key has already been used with a different request body",
+            ),
+        )),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(body: &str) -> StoredResponse {
+        StoredResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Bytes::from(body.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_begin_replay_and_mismatch() {
+        let store = Arc::new(IdempotencyStore::new(60, 1024));
+        let Lookup::Proceed(guard) = store.begin("k:1".to_string(), [1; 32]) else {
+            panic!("首次请求应继续处理");
+        };
+        assert!(matches!(
+            store.begin("k:1".to_string(), [1; 32]),
+            Lookup::InProgress
+        ));
+        guard.complete(stored(r#"{"type":"message"}"#));
+
+        let Lookup::Replay(response) = store.begin("k:1".to_string(), [1; 32]) else {
+            panic!("重试应返回已保存的响应");
+        };
+        assert_eq!(response.headers()[REPLAY_HEADER], "true");
+        assert!(matches!(
+            store.begin("k:1".to_string(), [2; 32]),
+            Lookup::Mismatch
+        ));
+    }
+
+    #[test]
+    fn test_dropped_guard_releases_key() {
+        let store = Arc::new(IdempotencyStore::new(60, 1024));
+        let Lookup::Proceed(guard) = store.begin("k:1".to_string(), [1; 32]) else {
+            panic!("首次请求应继续处理");
+        };
+        drop(guard);
+        assert!(matches!(
+            store.begin("k:1".to_string(), [1; 32]),
+            Lookup::Proceed(_)
+        ));
+    }
+
+    #[test]
+    fn test_evicts_oldest_over_max_bytes() {
+        let store = Arc::new(IdempotencyStore::new(60, 10));
+        for key in ["a", "b"] {
+            let Lookup::Proceed(guard) = store.begin(key.to_string(), [0; 32]) else {
+                panic!("首次请求应继续处理");
+            };
+            guard.complete(stored("123456"));
+        }
+        assert!(matches!(
+            store.begin("a".to_string(), [0; 32]),
+            Lookup::Proceed(_)
+        ));
+        assert!(matches!(
+            store.begin("b".to_string(), [0; 32]),
+            Lookup::Replay(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_record_response_skips_errors() {
+        let store = Arc::new(IdempotencyStore::new(60, 1024));
+        for (key, body) in [
+            ("ok", r#"  {"type":"message"}"#),
+            ("err", r#"{"type":"error"}"#),
+        ] {
+            let Lookup::Proceed(guard) = store.begin(key.to_string(), [0; 32]) else {
+                panic!("首次请求应继续处理");
+            };
+            let response = record_response((StatusCode::OK, body).into_response(), guard);
+            let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            assert_eq!(bytes, body.as_bytes());
+        }
+
+        let Lookup::Replay(response) = store.begin("ok".to_string(), [0; 32]) else {
+            panic!("成功响应应被保存");
+        };
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(bytes, r#"{"type":"message"}"#.as_bytes());
+        assert!(matches!(
+            store.begin("err".to_string(), [0; 32]),
+            Lookup::Proceed(_)
+        ));
+    }
+}
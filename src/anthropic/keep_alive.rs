@@ -0,0 +1,116 @@
+//! 非流式请求保活
+//!
+//! 客户端以 `stream: false` 请求较大的 `max_tokens` 时，上游生成完整响应可能超过负载均衡器的空闲超时，
+//! 导致网关返回 504。启用 `nonStreamKeepAliveSecs` 后，这类请求在后台执行：
+//! 超过一个保活间隔仍未完成时先返回 200，之后每个间隔发送一个空格，完成后发送响应 JSON
+//! （JSON 前的空白字符不影响解析）。
+//!
+//! 已开始保活后无法再修改状态码与响应头：上游出错时仍以 200 返回错误 JSON，透传的上游响应头也会丢失。
+//! 客户端断开时中止后台请求。
+
+use std::time::Duration;
+
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use futures::stream;
+use tokio::task::{JoinError, JoinHandle};
+
+use crate::common::i18n;
+
+use super::types::ErrorResponse;
+
+/// 保活时发送的空白字符
+const KEEP_ALIVE_BYTES: &[u8] = b" ";
+
+/// 客户端断开（响应体被丢弃）时中止后台请求
+struct AbortOnDrop(JoinHandle<Response>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// 等待后台请求完成；超过 `interval` 未完成时改为发送保活空白字符
+pub async fn keep_alive_response(task: JoinHandle<Response>, interval: Duration) -> Response {
+    let mut task = AbortOnDrop(task);
+    if let Ok(result) = tokio::time::timeout(interval, &mut task.0).await {
+        return task_response(result);
+    }
+    tracing::info!(
+        "非流式请求 {} 秒内未完成，开始发送保活空白字符",
+        interval.as_secs()
+    );
+
+    let body = stream::unfold(Some(task), move |task| async move {
+        let mut task = task?;
+        tokio::select! {
+            result = &mut task.0 => Some((Ok::<_, axum::Error>(final_body(result).await), None)),
+            _ = tokio::time::sleep(interval) => {
+                Some((Ok(Bytes::from_static(KEEP_ALIVE_BYTES)), Some(task)))
+            }
+        }
+    });
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from_stream(body))
+        .unwrap()
+}
+
+fn task_response(result: Result<Response, JoinError>) -> Response {
+    result.unwrap_or_else(|e| {
+        tracing::error!("非流式请求后台任务失败: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(
+                "api_error",
+                i18n::pick("处理请求失败", "Failed to process request"),
+            )),
+        )
+            .into_response()
+    })
+}
+
+/// 读取最终响应体（状态码已无法修改，非 200 时记录警告）
+async fn final_body(result: Result<Response, JoinError>) -> Bytes {
+    let response = task_response(result);
+    if !response.status().is_success() {
+        tracing::warn!(
+            "非流式请求在保活期间失败（{}），以 200 返回错误响应体",
+            response.status()
+        );
+    }
+    to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keep_alive_response() {
+        // 一个间隔内完成时原样返回
+        let task = tokio::spawn(async { (StatusCode::BAD_REQUEST, "{}").into_response() });
+        let response = keep_alive_response(task, Duration::from_millis(50)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // 超过间隔时先返回 200，发送空白字符后再发送响应体
+        let task = tokio::spawn(async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            (StatusCode::OK, r#"{"type":"message"}"#).into_response()
+        });
+        let response = keep_alive_response(task, Duration::from_millis(20)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.starts_with(' '));
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["type"], "message");
+    }
+}
@@ -0,0 +1,77 @@
+//! 每个 API Key 的并发流上限
+//!
+//! 防止单个用户同时打开大量流式会话（如并行运行几十个 Claude Code 实例）占满凭据池。
+//! 上限取 API Key 配置的 `maxConcurrentStreams`，未配置时使用全局 `maxConcurrentStreamsPerKey`（0 表示不限制）。
+//! 流式请求在消息处理器中登记，响应流结束（或客户端断开）时释放；超出上限的请求返回 429 overloaded_error。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+/// 各 API Key 当前的活跃流数量（克隆后共享同一份计数）
+#[derive(Debug, Clone, Default)]
+pub struct KeyStreamLimiter {
+    active: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl KeyStreamLimiter {
+    /// 登记一个活跃流；已达到上限时返回 None（limit 为 0 表示不限制）
+    pub fn try_acquire(&self, key_name: &str, limit: usize) -> Option<KeyStreamPermit> {
+        let mut active = self.active.lock();
+        let count = active.entry(key_name.to_string()).or_insert(0);
+        if limit > 0 && *count >= limit {
+            return None;
+        }
+        *count += 1;
+        Some(KeyStreamPermit {
+            active: self.active.clone(),
+            key_name: key_name.to_string(),
+        })
+    }
+}
+
+/// 活跃流许可，流结束（或客户端断开）时 Drop
+#[derive(Debug)]
+pub struct KeyStreamPermit {
+    active: Arc<Mutex<HashMap<String, usize>>>,
+    key_name: String,
+}
+
+impl Drop for KeyStreamPermit {
+    fn drop(&mut self) {
+        let mut active = self.active.lock();
+        if let Some(count) = active.get_mut(&self.key_name) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                active.remove(&self.key_name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_key_stream_limit() {
+        let limiter = KeyStreamLimiter::default();
+        let first = limiter.try_acquire("team-a", 2).unwrap();
+        let _second = limiter.try_acquire("team-a", 2).unwrap();
+        assert!(limiter.try_acquire("team-a", 2).is_none());
+
+        // 其他 Key 不受影响，0 表示不限制
+        assert!(limiter.try_acquire("team-b", 2).is_some());
+        let unlimited: Vec<_> = (0..5)
+            .map(|_| limiter.try_acquire("default", 0).unwrap())
+            .collect();
+        assert_eq!(limiter.active.lock().get("default"), Some(&5));
+        drop(unlimited);
+        assert!(limiter.active.lock().get("default").is_none());
+
+        // 流结束后释放名额
+        drop(first);
+        assert!(limiter.try_acquire("team-a", 2).is_some());
+    }
+}
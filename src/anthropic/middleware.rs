@@ -1,30 +1,84 @@
 //! Anthropic API 中间件
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
     body::Body,
     extract::State,
-    http::{Request, StatusCode},
+    http::{HeaderMap, Request, StatusCode, header},
     middleware::Next,
     response::{IntoResponse, Json, Response},
 };
+use futures::StreamExt;
 
-use crate::common::auth;
+use crate::common::lifecycle::Lifecycle;
+use crate::common::{auth, i18n};
 use crate::kiro::provider::KiroProvider;
+use crate::model::config::{ClientApiKeyConfig, DEFAULT_API_KEY_NAME};
 
+use super::admission::AdmissionController;
+use super::client_ip::{ClientIp, ClientIpPolicy};
+use super::converter::ConversionOptions;
+use super::idempotency::IdempotencyStore;
+use super::key_streams::{KeyStreamLimiter, KeyStreamPermit};
+use super::session_store::SessionStore;
+use super::shadow::ShadowTarget;
 use super::types::ErrorResponse;
 
+/// 请求超时请求头（秒），只能缩短而不能延长 API Key 或全局配置的超时
+pub const TIMEOUT_HEADER: &str = "x-kiro-timeout";
+
+/// 重试次数上限请求头，只能缩小 API Key 或全局配置的重试次数（0 表示失败立即返回）
+pub const MAX_RETRIES_HEADER: &str = "x-kiro-max-retries";
+
+/// 严格转换模式请求头（true/false），覆盖配置 `strictConversion`
+pub const STRICT_HEADER: &str = "x-kiro-strict";
+
+/// 客户端会话标识请求头，同一 API Key 下相同的会话标识映射到同一个 conversationId
+pub const SESSION_HEADER: &str = "x-kiro-session";
+
+/// 固定凭据请求头（凭据 ID），跳过负载均衡直接使用该凭据，用于复现特定账号的问题
+pub const CREDENTIAL_ID_HEADER: &str = "x-kiro-credential-id";
+
+/// 固定凭据所需的 Admin Key 请求头（值需与 `adminApiKey` 一致）
+pub const ADMIN_KEY_HEADER: &str = "x-kiro-admin-key";
+
+/// 固定凭据请求头无法使用的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialPinError {
+    /// 未启用 Admin API，或 Admin Key 缺失/不匹配
+    Forbidden,
+    /// 凭据 ID 不是有效的数字
+    InvalidId,
+}
+
 /// 应用共享状态
 #[derive(Clone)]
 pub struct AppState {
     /// API 密钥
     pub api_key: String,
+    /// 额外的客户端 API Key（按名称分别统计用量）
+    pub client_api_keys: Arc<Vec<ClientApiKeyConfig>>,
     /// Kiro Provider（可选，用于实际 API 调用）
     /// 内部使用 MultiTokenManager，已支持线程安全的多凭据管理
     pub kiro_provider: Option<Arc<KiroProvider>>,
     /// Profile ARN（可选，用于请求）
     pub profile_arn: Option<String>,
+    /// 请求准入控制（并发上限与优先级队列）
+    pub admission: AdmissionController,
+    /// 客户端会话 → conversationId 映射（可选）
+    pub sessions: Option<Arc<SessionStore>>,
+    /// 非流式请求的幂等响应存储（可选）
+    pub idempotency: Option<Arc<IdempotencyStore>>,
+    /// 实例生命周期状态（排空模式与活跃流数量）
+    pub lifecycle: Lifecycle,
+    /// 客户端 IP 识别、按 IP 限流与 IP 亲和配置
+    pub client_ip: ClientIpPolicy,
+    /// 影子复制端点（可选）
+    pub shadow: Option<Arc<ShadowTarget>>,
+    /// 各 API Key 的活跃流数量
+    pub key_streams: KeyStreamLimiter,
 }
 
 impl AppState {
@@ -32,8 +86,16 @@ impl AppState {
     pub fn new(api_key: impl Into<String>) -> Self {
         Self {
             api_key: api_key.into(),
+            client_api_keys: Arc::new(Vec::new()),
             kiro_provider: None,
             profile_arn: None,
+            admission: AdmissionController::unlimited(),
+            sessions: None,
+            idempotency: None,
+            lifecycle: Lifecycle::default(),
+            client_ip: ClientIpPolicy::default(),
+            shadow: None,
+            key_streams: KeyStreamLimiter::default(),
         }
     }
 
@@ -48,23 +110,268 @@ impl AppState {
         self.profile_arn = Some(arn.into());
         self
     }
+
+    /// 设置额外的客户端 API Key
+    pub fn with_client_api_keys(mut self, keys: Vec<ClientApiKeyConfig>) -> Self {
+        self.client_api_keys = Arc::new(keys);
+        self
+    }
+
+    /// 设置客户端 IP 识别配置
+    pub fn with_client_ip(mut self, policy: ClientIpPolicy) -> Self {
+        self.client_ip = policy;
+        self
+    }
+
+    /// 设置影子复制端点
+    pub fn with_shadow(mut self, target: Option<ShadowTarget>) -> Self {
+        self.shadow = target.map(Arc::new);
+        self
+    }
+
+    /// 以客户端 IP 作为凭据亲和键（未启用 clientIpAffinity 时返回 None）
+    pub fn client_ip_affinity_key(&self, client_ip: Option<&ClientIp>) -> Option<String> {
+        client_ip
+            .filter(|_| self.client_ip.affinity)
+            .map(|ip| format!("ip:{}", ip.0))
+    }
+
+    /// 设置会话映射存储
+    pub fn with_session_store(mut self, sessions: SessionStore) -> Self {
+        self.sessions = Some(Arc::new(sessions));
+        self
+    }
+
+    /// 设置幂等响应存储
+    pub fn with_idempotency_store(mut self, store: IdempotencyStore) -> Self {
+        self.idempotency = Some(Arc::new(store));
+        self
+    }
+
+    /// 识别请求使用的 API Key，返回其名称
+    ///
+    /// 逐个进行常量时间比较，不因匹配位置提前返回
+    fn identify_key(&self, key: &str) -> Option<String> {
+        let mut matched =
+            auth::constant_time_eq(key, &self.api_key).then(|| DEFAULT_API_KEY_NAME.to_string());
+        for client in self.client_api_keys.iter() {
+            if auth::constant_time_eq(key, &client.key) && matched.is_none() {
+                matched = Some(client.name.clone());
+            }
+        }
+        matched
+    }
+
+    /// 计算本次请求的上游超时
+    ///
+    /// 优先使用 API Key 配置的 `timeoutSecs`，否则使用全局 `requestTimeoutSecs`；
+    /// 请求头 `x-kiro-timeout` 只能在此基础上缩短。返回 None 表示使用 Provider 默认值
+    pub fn request_timeout(&self, key_name: &str, headers: &HeaderMap) -> Option<Duration> {
+        let configured = self
+            .client_api_keys
+            .iter()
+            .find(|client| client.name == key_name)
+            .and_then(|client| client.timeout_secs)
+            .or_else(|| {
+                self.kiro_provider
+                    .as_ref()
+                    .map(|p| p.token_manager().config().request_timeout_secs)
+            });
+        let requested = headers
+            .get(TIMEOUT_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .filter(|&secs| secs > 0);
+
+        let secs = match (configured, requested) {
+            (Some(configured), Some(requested)) => Some(configured.min(requested)),
+            (configured, requested) => configured.or(requested),
+        };
+        secs.map(Duration::from_secs)
+    }
+
+    /// 计算本次请求的上游重试次数上限
+    ///
+    /// 优先使用 API Key 配置的 `maxRetries`，否则使用全局 `maxRetries`，两者均不超过全局值；
+    /// 请求头 `x-kiro-max-retries` 只能在此基础上缩小。返回 None 表示使用 Provider 默认值
+    pub fn max_retries(&self, key_name: &str, headers: &HeaderMap) -> Option<usize> {
+        let global = self
+            .kiro_provider
+            .as_ref()
+            .map(|p| p.token_manager().config().max_retries);
+        let configured = self
+            .client_api_keys
+            .iter()
+            .find(|client| client.name == key_name)
+            .and_then(|client| client.max_retries)
+            .map(|retries| global.map_or(retries, |global| retries.min(global)))
+            .or(global);
+        let requested = headers
+            .get(MAX_RETRIES_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<usize>().ok());
+
+        match (configured, requested) {
+            (Some(configured), Some(requested)) => Some(configured.min(requested)),
+            (configured, requested) => configured.or(requested),
+        }
+    }
+
+    /// API Key 允许的并发流式请求数（0 表示不限制）
+    ///
+    /// 优先使用 API Key 配置的 `maxConcurrentStreams`，否则使用全局 `maxConcurrentStreamsPerKey`
+    pub fn max_concurrent_streams(&self, key_name: &str) -> usize {
+        self.client_api_keys
+            .iter()
+            .find(|client| client.name == key_name)
+            .and_then(|client| client.max_concurrent_streams)
+            .or_else(|| {
+                self.kiro_provider
+                    .as_ref()
+                    .map(|p| p.token_manager().config().max_concurrent_streams_per_key)
+            })
+            .unwrap_or(0)
+    }
+
+    /// 为流式请求登记一个活跃流；超出该 API Key 的并发流上限时返回上限值
+    pub fn acquire_key_stream(&self, key_name: &str) -> Result<KeyStreamPermit, usize> {
+        let limit = self.max_concurrent_streams(key_name);
+        self.key_streams.try_acquire(key_name, limit).ok_or(limit)
+    }
+
+    /// 请求转换选项（未配置 KiroProvider 时使用默认值）
+    pub fn conversion_options(&self) -> ConversionOptions {
+        self.kiro_provider
+            .as_ref()
+            .map(|p| ConversionOptions::from_config(p.token_manager().config()))
+            .unwrap_or_default()
+    }
+
+    /// 根据请求头 `x-kiro-session` 获取映射的 conversationId（未启用或未携带时为 None）
+    pub fn session_conversation_id(&self, key_name: &str, headers: &HeaderMap) -> Option<String> {
+        let session = headers.get(SESSION_HEADER)?.to_str().ok()?;
+        self.sessions.as_ref()?.conversation_id(key_name, session)
+    }
+
+    /// 本次请求是否启用严格转换模式
+    ///
+    /// 请求头 `x-kiro-strict` 优先，未设置或无法识别时使用配置 `strictConversion`
+    pub fn strict_conversion(&self, headers: &HeaderMap) -> bool {
+        let requested = headers
+            .get(STRICT_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| match v.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" => Some(true),
+                "0" | "false" => Some(false),
+                _ => None,
+            });
+        requested.unwrap_or_else(|| {
+            self.kiro_provider
+                .as_ref()
+                .is_some_and(|p| p.token_manager().config().strict_conversion)
+        })
+    }
+
+    /// 解析请求头 `x-kiro-credential-id` 指定的固定凭据
+    ///
+    /// 仅当请求同时携带与 `adminApiKey` 一致的 `x-kiro-admin-key` 时生效；未携带时返回 None
+    pub fn pinned_credential(
+        &self,
+        headers: &HeaderMap,
+    ) -> Result<Option<u64>, CredentialPinError> {
+        let Some(value) = headers.get(CREDENTIAL_ID_HEADER) else {
+            return Ok(None);
+        };
+        let admin_key = self
+            .kiro_provider
+            .as_ref()
+            .and_then(|p| p.token_manager().config().admin_api_key.clone())
+            .filter(|key| !key.trim().is_empty());
+        let provided = headers.get(ADMIN_KEY_HEADER).and_then(|v| v.to_str().ok());
+        match (admin_key, provided) {
+            (Some(admin_key), Some(provided)) if auth::constant_time_eq(provided, &admin_key) => {}
+            _ => return Err(CredentialPinError::Forbidden),
+        }
+        value
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Some)
+            .ok_or(CredentialPinError::InvalidId)
+    }
+
+    /// 设置请求准入控制器
+    pub fn with_admission(mut self, admission: AdmissionController) -> Self {
+        self.admission = admission;
+        self
+    }
+
+    /// 设置实例生命周期状态
+    pub fn with_lifecycle(mut self, lifecycle: Lifecycle) -> Self {
+        self.lifecycle = lifecycle;
+        self
+    }
 }
 
+/// 已通过认证的客户端 API Key 名称（由认证中间件写入请求扩展）
+#[derive(Debug, Clone)]
+pub struct ClientKey(pub String);
+
 /// API Key 认证中间件
 pub async fn auth_middleware(
     State(state): State<AppState>,
-    request: Request<Body>,
+    mut request: Request<Body>,
     next: Next,
 ) -> Response {
-    match auth::extract_api_key(&request) {
-        Some(key) if auth::constant_time_eq(&key, &state.api_key) => next.run(request).await,
-        _ => {
+    match auth::extract_api_key(&request).and_then(|key| state.identify_key(&key)) {
+        Some(name) => {
+            request.extensions_mut().insert(ClientKey(name));
+            next.run(request).await
+        }
+        None => {
             let error = ErrorResponse::authentication_error();
             (StatusCode::UNAUTHORIZED, Json(error)).into_response()
         }
     }
 }
 
+/// 排空模式中间件
+///
+/// 排空模式下拒绝新请求（503）；流式响应在结束（或客户端断开）前计入活跃流数量
+pub async fn lifecycle_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    if state.lifecycle.is_draining() {
+        let error = ErrorResponse::new(
+            "service_unavailable",
+            i18n::pick(
+                "实例正在排空，请重试其他实例",
+                "Instance is draining, please retry on another instance",
+            ),
+        );
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(error)).into_response();
+    }
+
+    let response = next.run(request).await;
+    let is_stream = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"text/event-stream"));
+    if !is_stream {
+        return response;
+    }
+    let guard = state.lifecycle.track_stream();
+    response.map(|body| {
+        let stream = body.into_data_stream().map(move |chunk| {
+            let _guard = &guard;
+            chunk
+        });
+        Body::from_stream(stream)
+    })
+}
+
 /// CORS 中间件层
 ///
 /// **安全说明**：当前配置允许所有来源（Any），这是为了支持公开 API 服务。
@@ -82,3 +389,161 @@ pub fn cors_layer() -> tower_http::cors::CorsLayer {
         .allow_methods(Any)
         .allow_headers(Any)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identify_key_by_name() {
+        let state = AppState::new("sk-main").with_client_api_keys(vec![ClientApiKeyConfig {
+            name: "team-a".to_string(),
+            key: "sk-team-a".to_string(),
+            timeout_secs: None,
+            max_retries: None,
+            max_concurrent_streams: None,
+        }]);
+
+        assert_eq!(
+            state.identify_key("sk-main").as_deref(),
+            Some(DEFAULT_API_KEY_NAME)
+        );
+        assert_eq!(state.identify_key("sk-team-a").as_deref(), Some("team-a"));
+        assert_eq!(state.identify_key("sk-unknown"), None);
+    }
+
+    #[test]
+    fn test_request_timeout_header_only_shortens() {
+        let state = AppState::new("sk-main").with_client_api_keys(vec![ClientApiKeyConfig {
+            name: "batch".to_string(),
+            key: "sk-batch".to_string(),
+            timeout_secs: Some(1800),
+            max_retries: None,
+            max_concurrent_streams: None,
+        }]);
+        let mut headers = HeaderMap::new();
+
+        assert_eq!(
+            state.request_timeout("batch", &headers),
+            Some(Duration::from_secs(1800))
+        );
+        assert_eq!(state.request_timeout(DEFAULT_API_KEY_NAME, &headers), None);
+
+        headers.insert(TIMEOUT_HEADER, "60".parse().unwrap());
+        assert_eq!(
+            state.request_timeout("batch", &headers),
+            Some(Duration::from_secs(60))
+        );
+
+        headers.insert(TIMEOUT_HEADER, "3600".parse().unwrap());
+        assert_eq!(
+            state.request_timeout("batch", &headers),
+            Some(Duration::from_secs(1800))
+        );
+
+        // 非法值被忽略
+        headers.insert(TIMEOUT_HEADER, "abc".parse().unwrap());
+        assert_eq!(
+            state.request_timeout("batch", &headers),
+            Some(Duration::from_secs(1800))
+        );
+    }
+
+    #[test]
+    fn test_max_retries_header_only_lowers() {
+        let state = AppState::new("sk-main").with_client_api_keys(vec![ClientApiKeyConfig {
+            name: "interactive".to_string(),
+            key: "sk-interactive".to_string(),
+            timeout_secs: None,
+            max_retries: Some(2),
+            max_concurrent_streams: None,
+        }]);
+        let mut headers = HeaderMap::new();
+
+        assert_eq!(state.max_retries("interactive", &headers), Some(2));
+        assert_eq!(state.max_retries(DEFAULT_API_KEY_NAME, &headers), None);
+
+        headers.insert(MAX_RETRIES_HEADER, "0".parse().unwrap());
+        assert_eq!(state.max_retries("interactive", &headers), Some(0));
+        assert_eq!(state.max_retries(DEFAULT_API_KEY_NAME, &headers), Some(0));
+
+        headers.insert(MAX_RETRIES_HEADER, "5".parse().unwrap());
+        assert_eq!(state.max_retries("interactive", &headers), Some(2));
+
+        // 非法值被忽略
+        headers.insert(MAX_RETRIES_HEADER, "-1".parse().unwrap());
+        assert_eq!(state.max_retries("interactive", &headers), Some(2));
+    }
+
+    #[test]
+    fn test_key_stream_limit() {
+        let state = AppState::new("sk-main").with_client_api_keys(vec![ClientApiKeyConfig {
+            name: "ci".to_string(),
+            key: "sk-ci".to_string(),
+            timeout_secs: None,
+            max_retries: None,
+            max_concurrent_streams: Some(1),
+        }]);
+        assert_eq!(state.max_concurrent_streams("ci"), 1);
+        assert_eq!(state.max_concurrent_streams(DEFAULT_API_KEY_NAME), 0);
+
+        let permit = state.acquire_key_stream("ci").unwrap();
+        assert_eq!(state.acquire_key_stream("ci").unwrap_err(), 1);
+        assert!(state.acquire_key_stream(DEFAULT_API_KEY_NAME).is_ok());
+        drop(permit);
+        assert!(state.acquire_key_stream("ci").is_ok());
+    }
+
+    #[test]
+    fn test_strict_conversion_header() {
+        let state = AppState::new("sk-main");
+        let mut headers = HeaderMap::new();
+        assert!(!state.strict_conversion(&headers));
+
+        headers.insert(STRICT_HEADER, "TRUE".parse().unwrap());
+        assert!(state.strict_conversion(&headers));
+
+        headers.insert(STRICT_HEADER, "0".parse().unwrap());
+        assert!(!state.strict_conversion(&headers));
+    }
+
+    #[test]
+    fn test_pinned_credential_requires_admin_key() {
+        use crate::kiro::model::credentials::KiroCredentials;
+        use crate::kiro::token_manager::MultiTokenManager;
+        use crate::model::config::Config;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CREDENTIAL_ID_HEADER, "7".parse().unwrap());
+        headers.insert(ADMIN_KEY_HEADER, "sk-admin".parse().unwrap());
+
+        // 未启用 Admin API 时不允许固定凭据
+        let state = AppState::new("sk-main");
+        assert_eq!(
+            state.pinned_credential(&headers),
+            Err(CredentialPinError::Forbidden)
+        );
+
+        let mut config = Config::default();
+        config.admin_api_key = Some("sk-admin".to_string());
+        let manager =
+            MultiTokenManager::new(config, vec![KiroCredentials::default()], None, None, false)
+                .unwrap();
+        let state =
+            AppState::new("sk-main").with_kiro_provider(KiroProvider::new(Arc::new(manager)));
+        assert_eq!(state.pinned_credential(&headers), Ok(Some(7)));
+        assert_eq!(state.pinned_credential(&HeaderMap::new()), Ok(None));
+
+        headers.insert(CREDENTIAL_ID_HEADER, "abc".parse().unwrap());
+        assert_eq!(
+            state.pinned_credential(&headers),
+            Err(CredentialPinError::InvalidId)
+        );
+        headers.insert(CREDENTIAL_ID_HEADER, "7".parse().unwrap());
+        headers.insert(ADMIN_KEY_HEADER, "sk-main".parse().unwrap());
+        assert_eq!(
+            state.pinned_credential(&headers),
+            Err(CredentialPinError::Forbidden)
+        );
+    }
+}
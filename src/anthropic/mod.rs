@@ -22,14 +22,34 @@
 //! axum::serve(listener, app).await?;
 //! ```
 
+mod admission;
+mod backpressure;
+mod client_ip;
 mod converter;
 mod handlers;
+mod idempotency;
+mod keep_alive;
+mod key_streams;
 mod middleware;
+mod prefill;
+mod rate_limit;
 mod router;
+mod session_store;
+mod shadow;
 mod stream;
+#[cfg(feature = "compression")]
 mod tool_compression;
+mod tool_schema_cache;
 mod truncation;
 pub mod types;
+#[cfg(feature = "mcp")]
 mod websearch;
 
+pub use admission::AdmissionController;
+pub use client_ip::TrustedProxies;
+pub(crate) use converter::image_dimensions;
+#[cfg(feature = "compression")]
+pub(crate) use router::MAX_BODY_SIZE;
 pub use router::create_router_with_provider;
+#[cfg(feature = "compression")]
+pub use tool_compression::{CompressionReport, dry_run as compression_dry_run};
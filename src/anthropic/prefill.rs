@@ -0,0 +1,90 @@
+//! Assistant 预填充（prefill）处理
+//!
+//! Anthropic 允许最后一条消息为 assistant，用于预先给出回复的开头。
+//! Kiro 的 currentMessage 只能是 user 消息，因此预填充内容作为历史中的 assistant 消息发送，
+//! 再追加一条要求继续输出的 user 消息（见 `converter`）。
+//!
+//! 返回给客户端的内容以预填充文本开头。模型续写时可能先复述预填充内容，
+//! [`PrefillEchoFilter`] 负责剥离这段复述，避免重复。
+
+/// 剥离上游对预填充内容的复述
+///
+/// 上游输出的开头如果与预填充文本一致（可能跨多个事件），这部分会被丢弃；
+/// 一旦出现不一致的内容，之后的文本原样放行。
+pub struct PrefillEchoFilter {
+    prefill: String,
+    /// 尚无法判断是否为复述的开头文本
+    pending: String,
+    done: bool,
+}
+
+impl PrefillEchoFilter {
+    pub fn new(prefill: impl Into<String>) -> Self {
+        Self {
+            prefill: prefill.into(),
+            pending: String::new(),
+            done: false,
+        }
+    }
+
+    /// 处理一段上游文本，返回可以输出的部分
+    ///
+    /// 可能是复述的开头会被暂存，流在复述中途结束时暂存内容直接丢弃
+    pub fn push(&mut self, text: &str) -> String {
+        if self.done {
+            return text.to_string();
+        }
+
+        self.pending.push_str(text);
+        if let Some(rest) = self.pending.strip_prefix(self.prefill.as_str()) {
+            tracing::debug!(
+                "上游复述了预填充内容（{} 字节），已剥离",
+                self.prefill.len()
+            );
+            let rest = rest.to_string();
+            self.done = true;
+            self.pending.clear();
+            rest
+        } else if self.prefill.starts_with(self.pending.as_str()) {
+            String::new()
+        } else {
+            self.done = true;
+            std::mem::take(&mut self.pending)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(prefill: &str, chunks: &[&str]) -> String {
+        let mut filter = PrefillEchoFilter::new(prefill);
+        chunks.iter().map(|c| filter.push(c)).collect()
+    }
+
+    #[test]
+    fn test_passes_through_continuation() {
+        assert_eq!(run("{\"name\":", &[" \"kiro\"", "}"]), " \"kiro\"}");
+    }
+
+    #[test]
+    fn test_strips_echo_across_chunks() {
+        assert_eq!(
+            run("{\"name\":", &["{\"na", "me\":", " \"kiro\"}"]),
+            " \"kiro\"}"
+        );
+    }
+
+    #[test]
+    fn test_releases_partial_match_on_divergence() {
+        assert_eq!(run("Hello world", &["Hel", "p me"]), "Help me");
+    }
+
+    #[test]
+    fn test_drops_unfinished_echo() {
+        let mut filter = PrefillEchoFilter::new("Hello world");
+        assert_eq!(filter.push("Hello"), "");
+        assert_eq!(filter.pending, "Hello");
+    }
+}
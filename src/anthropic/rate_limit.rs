@@ -0,0 +1,208 @@
+//! Anthropic 兼容的限流响应头
+//!
+//! 官方 SDK 会读取 `anthropic-ratelimit-requests-*` 与 `retry-after` 来决定是否以及何时重试。
+//! 本模块根据本地每日请求上限和凭据池状态生成这些响应头：
+//! - 配置了全局每日上限时，输出 limit / remaining / reset（reset 为按 `dailyResetTimezone` 计算的下次清零时间）
+//! - 没有可用凭据时 remaining 为 0
+//! - 429 / 529 响应附带 `retry-after`；每日上限耗尽时同时返回 `x-should-retry: false`，
+//!   避免客户端在当天剩余时间内反复重试
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderName, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::kiro::daily_limit::DailyLimitStatus;
+
+use super::middleware::AppState;
+
+const LIMIT_HEADER: &str = "anthropic-ratelimit-requests-limit";
+const REMAINING_HEADER: &str = "anthropic-ratelimit-requests-remaining";
+const RESET_HEADER: &str = "anthropic-ratelimit-requests-reset";
+const RETRY_AFTER_HEADER: &str = "retry-after";
+const SHOULD_RETRY_HEADER: &str = "x-should-retry";
+
+/// 并发排队被拒绝（或没有可用凭据）时建议的重试间隔（秒）
+const BUSY_RETRY_AFTER_SECS: u64 = 5;
+
+/// 本地限流状态快照
+#[derive(Debug, Clone, PartialEq)]
+struct RateLimitState {
+    /// 全局每日上限（未配置或当天已解除时为 None）
+    limit: Option<u64>,
+    /// 当天已使用的请求数
+    used: u64,
+    /// 可用凭据数量
+    available_credentials: usize,
+    /// 每日计数的下一次清零时间
+    reset_at: DateTime<Utc>,
+}
+
+impl RateLimitState {
+    fn new(status: &DailyLimitStatus, available_credentials: usize) -> Self {
+        Self {
+            limit: status.limit.filter(|_| !status.overridden),
+            used: status.requests,
+            available_credentials,
+            reset_at: status.reset_at,
+        }
+    }
+
+    /// 剩余请求数（None 表示不限制且有可用凭据）
+    fn remaining(&self) -> Option<u64> {
+        if self.available_credentials == 0 {
+            return Some(0);
+        }
+        self.limit.map(|limit| limit.saturating_sub(self.used))
+    }
+
+    /// 每日上限是否已耗尽
+    fn daily_exhausted(&self) -> bool {
+        self.limit.is_some_and(|limit| self.used >= limit)
+    }
+
+    /// 生成响应头
+    fn headers(&self, status: StatusCode, now: DateTime<Utc>) -> Vec<(&'static str, String)> {
+        let reset = self.reset_at;
+        let mut headers = Vec::new();
+
+        if let Some(limit) = self.limit {
+            headers.push((LIMIT_HEADER, limit.to_string()));
+        }
+        if let Some(remaining) = self.remaining() {
+            headers.push((REMAINING_HEADER, remaining.to_string()));
+        }
+        if self.limit.is_some() {
+            headers.push((
+                RESET_HEADER,
+                reset.to_rfc3339_opts(SecondsFormat::Secs, false),
+            ));
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS || status.as_u16() == 529 {
+            if self.daily_exhausted() {
+                let secs = (reset - now).num_seconds().max(1);
+                headers.push((RETRY_AFTER_HEADER, secs.to_string()));
+                headers.push((SHOULD_RETRY_HEADER, "false".to_string()));
+            } else {
+                headers.push((RETRY_AFTER_HEADER, BUSY_RETRY_AFTER_SECS.to_string()));
+            }
+        }
+
+        headers
+    }
+}
+
+/// 为响应附加限流响应头的中间件
+///
+/// 已由处理函数设置的同名响应头不会被覆盖
+pub async fn rate_limit_headers_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    let Some(provider) = &state.kiro_provider else {
+        return response;
+    };
+
+    let token_manager = provider.token_manager();
+    let rate_limit = RateLimitState::new(
+        &token_manager.daily_limit_status(),
+        token_manager.available_count(),
+    );
+    for (name, value) in rate_limit.headers(response.status(), Utc::now()) {
+        let name = HeaderName::from_static(name);
+        if response.headers().contains_key(&name) {
+            continue;
+        }
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn status(requests: u64, limit: Option<u64>) -> DailyLimitStatus {
+        DailyLimitStatus {
+            date: "2026-01-01".to_string(),
+            requests,
+            limit,
+            pools: Vec::new(),
+            overridden: false,
+            reset_at: Utc.with_ymd_and_hms(2026, 1, 2, 0, 0, 0).unwrap(),
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 1, 23, 0, 0).unwrap()
+    }
+
+    fn header<'a>(headers: &'a [(&'static str, String)], name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_headers_with_daily_limit() {
+        let state = RateLimitState::new(&status(40, Some(100)), 2);
+        let headers = state.headers(StatusCode::OK, now());
+        assert_eq!(header(&headers, LIMIT_HEADER), Some("100"));
+        assert_eq!(header(&headers, REMAINING_HEADER), Some("60"));
+        assert!(
+            header(&headers, RESET_HEADER)
+                .unwrap()
+                .starts_with("2026-01-02T00:00:00")
+        );
+        assert_eq!(header(&headers, RETRY_AFTER_HEADER), None);
+    }
+
+    #[test]
+    fn test_headers_without_limit() {
+        let state = RateLimitState::new(&status(40, None), 2);
+        assert!(state.headers(StatusCode::OK, now()).is_empty());
+
+        // 没有可用凭据时 remaining 为 0
+        let state = RateLimitState::new(&status(40, None), 0);
+        let headers = state.headers(StatusCode::OK, now());
+        assert_eq!(header(&headers, REMAINING_HEADER), Some("0"));
+        assert_eq!(header(&headers, LIMIT_HEADER), None);
+    }
+
+    #[test]
+    fn test_overridden_limit_is_ignored() {
+        let mut status = status(200, Some(100));
+        status.overridden = true;
+        let state = RateLimitState::new(&status, 2);
+        assert!(state.headers(StatusCode::OK, now()).is_empty());
+    }
+
+    #[test]
+    fn test_retry_after_when_daily_limit_exhausted() {
+        let state = RateLimitState::new(&status(100, Some(100)), 2);
+        let overloaded = StatusCode::from_u16(529).unwrap();
+        let headers = state.headers(overloaded, now());
+        assert_eq!(header(&headers, REMAINING_HEADER), Some("0"));
+        assert_eq!(header(&headers, RETRY_AFTER_HEADER), Some("3600"));
+        assert_eq!(header(&headers, SHOULD_RETRY_HEADER), Some("false"));
+    }
+
+    #[test]
+    fn test_retry_after_when_busy() {
+        let state = RateLimitState::new(&status(10, Some(100)), 2);
+        let headers = state.headers(StatusCode::TOO_MANY_REQUESTS, now());
+        assert_eq!(header(&headers, RETRY_AFTER_HEADER), Some("5"));
+        assert_eq!(header(&headers, SHOULD_RETRY_HEADER), None);
+    }
+}
@@ -7,15 +7,25 @@ use axum::{
     routing::{get, post},
 };
 
+use crate::common::lifecycle::Lifecycle;
 use crate::kiro::provider::KiroProvider;
+use crate::model::config::ClientApiKeyConfig;
 
+#[cfg(feature = "mcp")]
+use super::handlers::{post_mcp, post_web_search};
 use super::{
-    handlers::{count_tokens, get_models, post_messages, post_messages_cc},
-    middleware::{AppState, auth_middleware, cors_layer},
+    admission::AdmissionController,
+    client_ip::{ClientIpPolicy, client_ip_middleware},
+    handlers::{count_tokens, get_models, get_ready, post_messages, post_messages_cc},
+    idempotency::{IdempotencyStore, idempotency_middleware},
+    middleware::{AppState, auth_middleware, cors_layer, lifecycle_middleware},
+    rate_limit::rate_limit_headers_middleware,
+    session_store::{SESSIONS_FILE, SessionStore},
+    shadow::{ShadowTarget, shadow_middleware},
 };
 
 /// 请求体最大大小限制 (50MB)
-const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
+pub(crate) const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
 
 /// 创建 Anthropic API 路由
 ///
@@ -23,25 +33,73 @@ const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
 /// - `GET /v1/models` - 获取可用模型列表
 /// - `POST /v1/messages` - 创建消息（对话）
 /// - `POST /v1/messages/count_tokens` - 计算 token 数量
+/// - `POST /v1/mcp` - MCP JSON-RPC 透传（转发到 Kiro MCP 端点，mcp 特性）
+/// - `POST /v1/tools/web_search` - 直接执行 WebSearch，返回规范化结果（mcp 特性）
+/// - `GET /ready` - 就绪检查（无需认证，排空模式下返回 503）
 ///
 /// # 认证
 /// 所有 `/v1` 路径需要 API Key 认证，支持：
 /// - `x-api-key` header
 /// - `Authorization: Bearer <token>` header
 ///
+/// # 限流响应头
+/// 所有响应附带 `anthropic-ratelimit-requests-*` / `retry-after`（见 `rate_limit` 模块）
+///
+/// # 幂等
+/// 非流式 `/messages` 请求携带 `Idempotency-Key` 时保存成功响应，重试直接返回（见 `idempotency` 模块）
+///
+/// # 影子复制
+/// 配置 `shadow` 后按比例将非流式 `/messages` 请求复制到另一个后端并记录响应差异（见 `shadow` 模块）
+///
+/// # 客户端 IP
+/// 按 `trustedProxies` 识别客户端 IP，用于日志、按 IP 限流与 IP 亲和（见 `client_ip` 模块）
+///
 /// # 参数
 /// - `api_key`: API 密钥，用于验证客户端请求
+/// - `client_api_keys`: 额外的客户端 API Key（按名称分别统计用量）
 /// - `kiro_provider`: 可选的 KiroProvider，用于调用上游 API
+/// - `admission`: 请求准入控制器（并发上限与优先级队列）
+/// - `lifecycle`: 实例生命周期状态（排空模式下拒绝新请求）
 
 /// 创建带有 KiroProvider 的 Anthropic API 路由
 pub fn create_router_with_provider(
     api_key: impl Into<String>,
+    client_api_keys: Vec<ClientApiKeyConfig>,
     kiro_provider: Option<KiroProvider>,
     profile_arn: Option<String>,
+    admission: AdmissionController,
+    lifecycle: Lifecycle,
 ) -> Router {
-    let mut state = AppState::new(api_key);
+    let mut state = AppState::new(api_key)
+        .with_client_api_keys(client_api_keys)
+        .with_admission(admission)
+        .with_lifecycle(lifecycle);
     if let Some(provider) = kiro_provider {
-        state = state.with_kiro_provider(provider);
+        let token_manager = provider.token_manager();
+        let ttl_secs = token_manager.config().session_ttl_secs;
+        let sessions = (ttl_secs > 0).then(|| {
+            SessionStore::new(
+                token_manager.cache_dir().map(|d| d.join(SESSIONS_FILE)),
+                ttl_secs,
+            )
+        });
+        let idempotency = (token_manager.config().idempotency_ttl_secs > 0).then(|| {
+            IdempotencyStore::new(
+                token_manager.config().idempotency_ttl_secs,
+                token_manager.config().idempotency_max_bytes,
+            )
+        });
+        let shadow = ShadowTarget::from_config(token_manager.config(), provider.global_proxy());
+        state = state
+            .with_client_ip(ClientIpPolicy::from_config(token_manager.config()))
+            .with_shadow(shadow)
+            .with_kiro_provider(provider);
+        if let Some(sessions) = sessions {
+            state = state.with_session_store(sessions);
+        }
+        if let Some(idempotency) = idempotency {
+            state = state.with_idempotency_store(idempotency);
+        }
     }
     if let Some(arn) = profile_arn {
         state = state.with_profile_arn(arn);
@@ -50,24 +108,77 @@ pub fn create_router_with_provider(
     // 需要认证的 /v1 路由
     let v1_routes = Router::new()
         .route("/models", get(get_models))
-        .route("/messages", post(post_messages))
-        .route("/messages/count_tokens", post(count_tokens))
+        .route(
+            "/messages",
+            post(post_messages)
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    shadow_middleware,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    idempotency_middleware,
+                )),
+        )
+        .route("/messages/count_tokens", post(count_tokens));
+    // MCP 透传与直接搜索（mcp 特性）
+    #[cfg(feature = "mcp")]
+    let v1_routes = v1_routes
+        .route("/mcp", post(post_mcp))
+        .route("/tools/web_search", post(post_web_search));
+    let v1_routes = v1_routes
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            lifecycle_middleware,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit_headers_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            client_ip_middleware,
         ));
 
     // 需要认证的 /cc/v1 路由（Claude Code 兼容端点）
     // 与 /v1 的区别：流式响应会等待 contextUsageEvent 后再发送 message_start
     let cc_v1_routes = Router::new()
-        .route("/messages", post(post_messages_cc))
+        .route(
+            "/messages",
+            post(post_messages_cc)
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    shadow_middleware,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    idempotency_middleware,
+                )),
+        )
         .route("/messages/count_tokens", post(count_tokens))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            lifecycle_middleware,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit_headers_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            client_ip_middleware,
         ));
 
     Router::new()
+        .route("/ready", get(get_ready))
         .nest("/v1", v1_routes)
         .nest("/cc/v1", cc_v1_routes)
         .layer(cors_layer())
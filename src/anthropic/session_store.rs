@@ -0,0 +1,188 @@
+//! 客户端会话 → Kiro conversationId 映射
+//!
+//! Claude Code 在 metadata.user_id 中携带 session UUID，可直接作为 conversationId；
+//! 其他客户端每个请求都会得到随机的 conversationId，上游无法把它们识别为同一会话。
+//!
+//! 客户端可通过请求头 `x-kiro-session` 声明会话标识，本模块按 API Key 名称 + 会话标识
+//! 分配稳定的 conversationId，持久化到缓存目录，超过 `sessionTtlSecs` 未使用的映射过期。
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// 持久化文件名（位于缓存目录）
+pub const SESSIONS_FILE: &str = "kiro_sessions.json";
+
+/// 最多保存的会话数量，超过时淘汰最久未使用的
+const MAX_SESSIONS: usize = 10_000;
+
+/// 会话标识的最大长度
+const MAX_SESSION_ID_LEN: usize = 256;
+
+/// 单个会话映射
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionEntry {
+    conversation_id: String,
+    /// 最近一次使用时间（Unix 秒）
+    last_used_at: i64,
+}
+
+/// 会话映射存储
+pub struct SessionStore {
+    path: Option<PathBuf>,
+    ttl_secs: u64,
+    entries: Mutex<HashMap<String, SessionEntry>>,
+}
+
+impl SessionStore {
+    /// 创建存储并加载未过期的映射
+    ///
+    /// # Arguments
+    /// * `path` - 持久化文件路径（None 时仅保存在内存中）
+    /// * `ttl_secs` - 映射过期时间（秒）
+    pub fn new(path: Option<PathBuf>, ttl_secs: u64) -> Self {
+        let entries = Self::load_from(&path, ttl_secs);
+        if !entries.is_empty() {
+            tracing::info!("已加载 {} 个会话映射", entries.len());
+        }
+        Self {
+            path,
+            ttl_secs,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// 获取会话对应的 conversationId，不存在或已过期时分配新的
+    ///
+    /// 会话标识为空或过长时返回 None
+    pub fn conversation_id(&self, key_name: &str, session: &str) -> Option<String> {
+        let session = session.trim();
+        if session.is_empty() || session.len() > MAX_SESSION_ID_LEN {
+            return None;
+        }
+
+        let key = format!("{}:{}", key_name, session);
+        let now = Utc::now().timestamp();
+        let mut entries = self.entries.lock();
+
+        if let Some(entry) = entries.get_mut(&key)
+            && !self.is_expired(entry, now)
+        {
+            entry.last_used_at = now;
+            return Some(entry.conversation_id.clone());
+        }
+
+        entries.retain(|_, entry| !self.is_expired(entry, now));
+        if entries.len() >= MAX_SESSIONS
+            && let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used_at)
+                .map(|(key, _)| key.clone())
+        {
+            entries.remove(&oldest);
+        }
+
+        let conversation_id = Uuid::new_v4().to_string();
+        entries.insert(
+            key,
+            SessionEntry {
+                conversation_id: conversation_id.clone(),
+                last_used_at: now,
+            },
+        );
+        self.save(&entries);
+        Some(conversation_id)
+    }
+
+    fn is_expired(&self, entry: &SessionEntry, now: i64) -> bool {
+        now.saturating_sub(entry.last_used_at) > self.ttl_secs as i64
+    }
+
+    fn load_from(path: &Option<PathBuf>, ttl_secs: u64) -> HashMap<String, SessionEntry> {
+        let Some(path) = path else {
+            return HashMap::new();
+        };
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return HashMap::new();
+        };
+
+        let mut entries: HashMap<String, SessionEntry> = match serde_json::from_str(&content) {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::warn!("解析会话映射失败，将忽略: {}", e);
+                return HashMap::new();
+            }
+        };
+        let now = Utc::now().timestamp();
+        entries.retain(|_, entry| now.saturating_sub(entry.last_used_at) <= ttl_secs as i64);
+        entries
+    }
+
+    /// 持久化（新增映射时调用，已有映射的使用时间随下一次写入保存）
+    fn save(&self, entries: &HashMap<String, SessionEntry>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        match serde_json::to_string_pretty(entries) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(path, json) {
+                    tracing::warn!("保存会话映射失败: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("序列化会话映射失败: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversation_id_is_stable_per_key_and_session() {
+        let store = SessionStore::new(None, 3600);
+        let first = store.conversation_id("default", "chat-1").unwrap();
+        assert_eq!(
+            store.conversation_id("default", "chat-1"),
+            Some(first.clone())
+        );
+        assert_ne!(
+            store.conversation_id("default", "chat-2"),
+            Some(first.clone())
+        );
+        assert_ne!(store.conversation_id("team-a", "chat-1"), Some(first));
+        assert_eq!(store.conversation_id("default", "  "), None);
+    }
+
+    #[test]
+    fn test_persists_and_expires() {
+        let path = std::env::temp_dir().join(format!("kiro-sessions-{}.json", Uuid::new_v4()));
+
+        let store = SessionStore::new(Some(path.clone()), 3600);
+        let id = store.conversation_id("default", "chat-1").unwrap();
+        let reloaded = SessionStore::new(Some(path.clone()), 3600);
+        assert_eq!(
+            reloaded.conversation_id("default", "chat-1"),
+            Some(id.clone())
+        );
+
+        // 使用时间早于 TTL 的映射在加载时被丢弃
+        reloaded
+            .entries
+            .lock()
+            .get_mut("default:chat-1")
+            .unwrap()
+            .last_used_at -= 7200;
+        reloaded.save(&reloaded.entries.lock());
+        let expired = SessionStore::new(Some(path.clone()), 3600);
+        assert_ne!(expired.conversation_id("default", "chat-1"), Some(id));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,401 @@
+//! 请求影子复制
+//!
+//! 按 `shadow.percent` 抽样非流式 Messages 请求，在主请求完成后把原始请求体异步发送到
+//! `shadow.url`（mock、预发实例或 Anthropic 官方 API 等 Anthropic 兼容端点），
+//! 比较两边响应的摘要（状态码、stop_reason、内容块类型、工具调用名称、文本长度）并记录差异日志。
+//! 影子请求不影响返回给客户端的响应，用于在上线转换逻辑改动前验证行为是否一致：
+//! 主响应边转发边复制，非流式保活（`nonStreamKeepAliveSecs`）的空白字符照常实时发给客户端。
+
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::State,
+    http::{HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::{StreamExt, stream};
+use serde_json::Value;
+
+use crate::http_client::{ProxyConfig, TransportOptions, build_client};
+use crate::model::config::{Config, ShadowConfig};
+
+use super::middleware::AppState;
+use super::router::MAX_BODY_SIZE;
+
+/// 转发给影子端点的客户端请求头（API Key 等认证头不转发）
+const FORWARDED_HEADERS: &[&str] = &["anthropic-version", "anthropic-beta"];
+
+/// 未指定 anthropic-version 时使用的版本
+const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// 复制主响应体的上限，超出时不做比较
+const MAX_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+
+/// 影子端点
+pub struct ShadowTarget {
+    config: ShadowConfig,
+    client: reqwest::Client,
+}
+
+impl ShadowTarget {
+    /// 按配置创建（未配置 shadow 或抽样比例为 0 时返回 None）
+    pub fn from_config(config: &Config, proxy: Option<&ProxyConfig>) -> Option<Self> {
+        let shadow = config.shadow.as_ref().filter(|s| s.percent > 0)?;
+        let client = build_client(
+            proxy,
+            shadow.timeout_secs,
+            &TransportOptions::from_config(config),
+        )
+        .inspect_err(|e| tracing::warn!("创建影子请求客户端失败，影子复制未启用: {}", e))
+        .ok()?;
+        tracing::info!(
+            "影子复制已启用: {}% 的非流式请求 -> {}",
+            shadow.percent,
+            shadow.url
+        );
+        Some(Self {
+            config: shadow.clone(),
+            client,
+        })
+    }
+
+    fn sampled(&self) -> bool {
+        fastrand::u8(0..100) < self.config.percent
+    }
+
+    /// 发送影子请求并与主响应比较
+    async fn compare(&self, headers: HeaderMap, body: Bytes, primary: ResponseSummary) {
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header("content-type", "application/json");
+        for name in FORWARDED_HEADERS {
+            if let Some(value) = headers.get(*name) {
+                request = request.header(*name, value);
+            }
+        }
+        if !headers.contains_key("anthropic-version") {
+            request = request.header("anthropic-version", DEFAULT_ANTHROPIC_VERSION);
+        }
+        if let Some(key) = &self.config.api_key {
+            request = request.header("x-api-key", key);
+        }
+
+        let shadow = match request.body(body).send().await {
+            Ok(response) => {
+                let status = response.status().as_u16();
+                match response.bytes().await {
+                    Ok(bytes) => ResponseSummary::new(status, &bytes),
+                    Err(e) => {
+                        tracing::warn!("读取影子响应失败: {}", e);
+                        return;
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::warn!("影子请求失败: {}", e);
+                return;
+            }
+        };
+
+        let diffs = primary.diff(&shadow);
+        if diffs.is_empty() {
+            tracing::debug!("影子响应与主响应一致");
+        } else {
+            tracing::info!("影子响应与主响应存在差异: {}", diffs.join("; "));
+        }
+    }
+}
+
+/// 影子复制中间件（仅作用于 Messages 路由）
+pub async fn shadow_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(target) = state.shadow.clone().filter(|t| t.sampled()) else {
+        return next.run(request).await;
+    };
+
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::warn!("读取请求体失败: {}", e);
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+    let is_stream = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|v| v.get("stream").and_then(Value::as_bool))
+        .unwrap_or(false);
+    let headers = parts.headers.clone();
+    let response = next
+        .run(Request::from_parts(parts, Body::from(body.clone())))
+        .await;
+    if is_stream {
+        return response;
+    }
+
+    // 边转发边复制主响应，完整读取后再发送影子请求比较
+    tee_response(response, move |status, response_bytes| {
+        let primary = ResponseSummary::new(status.as_u16(), &response_bytes);
+        tokio::spawn(async move { target.compare(headers, body, primary).await });
+    })
+}
+
+/// 边转发边复制主响应体，完整读取后以状态码与响应体调用 `on_complete`
+///
+/// 响应体超过 [`MAX_RESPONSE_BYTES`] 或读取失败时只放弃比较，返回给客户端的响应不受影响
+fn tee_response<F>(response: Response, on_complete: F) -> Response
+where
+    F: FnOnce(StatusCode, Bytes) + Send + 'static,
+{
+    let (parts, body) = response.into_parts();
+    let status = parts.status;
+
+    let state = (body.into_data_stream(), Vec::new(), Some(on_complete));
+    let body = stream::unfold(
+        state,
+        move |(mut data, mut buffer, mut pending)| async move {
+            match data.next().await {
+                Some(Ok(chunk)) => {
+                    if pending.is_some() {
+                        if buffer.len() + chunk.len() > MAX_RESPONSE_BYTES {
+                            tracing::warn!("主响应超过 {} 字节，跳过影子比较", MAX_RESPONSE_BYTES);
+                            pending = None;
+                            buffer = Vec::new();
+                        } else {
+                            buffer.extend_from_slice(&chunk);
+                        }
+                    }
+                    Some((Ok(chunk), (data, buffer, pending)))
+                }
+                Some(Err(e)) => {
+                    tracing::warn!("读取主响应失败，跳过影子比较: {}", e);
+                    Some((Err(e), (data, Vec::new(), None)))
+                }
+                None => {
+                    if let Some(on_complete) = pending.take() {
+                        on_complete(status, Bytes::from(buffer));
+                    }
+                    None
+                }
+            }
+        },
+    );
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// 响应摘要（只比较结构，不比较具体文本）
+#[derive(Debug, Clone, PartialEq)]
+struct ResponseSummary {
+    /// 状态码（非流式保活已开始时固定为 200，视为未知）
+    status: Option<u16>,
+    stop_reason: Option<String>,
+    block_types: Vec<String>,
+    tool_names: Vec<String>,
+    text_chars: usize,
+    error_type: Option<String>,
+}
+
+impl ResponseSummary {
+    fn new(status: u16, body: &[u8]) -> Self {
+        let value: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+        let blocks = value
+            .get("content")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let block_type = |b: &Value| b.get("type").and_then(Value::as_str).map(str::to_string);
+        // 响应体以保活空白字符开头时，状态码在保活开始时已固定为 200，不代表实际结果
+        let kept_alive = body.first().is_some_and(u8::is_ascii_whitespace);
+        Self {
+            status: (!kept_alive).then_some(status),
+            stop_reason: value
+                .get("stop_reason")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            block_types: blocks.iter().filter_map(block_type).collect(),
+            tool_names: blocks
+                .iter()
+                .filter(|b| block_type(b).as_deref() == Some("tool_use"))
+                .filter_map(|b| b.get("name").and_then(Value::as_str).map(str::to_string))
+                .collect(),
+            text_chars: blocks
+                .iter()
+                .filter_map(|b| b.get("text").and_then(Value::as_str))
+                .map(|t| t.chars().count())
+                .sum(),
+            error_type: value
+                .pointer("/error/type")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        }
+    }
+
+    /// 列出与影子响应的差异（文本长度相差一倍以上才视为差异）
+    fn diff(&self, shadow: &Self) -> Vec<String> {
+        let mut diffs = Vec::new();
+        if let (Some(status), Some(shadow_status)) = (self.status, shadow.status)
+            && status != shadow_status
+        {
+            diffs.push(format!("status {} != {}", status, shadow_status));
+        }
+        if self.error_type != shadow.error_type {
+            diffs.push(format!(
+                "error {:?} != {:?}",
+                self.error_type, shadow.error_type
+            ));
+        }
+        if self.stop_reason != shadow.stop_reason {
+            diffs.push(format!(
+                "stop_reason {:?} != {:?}",
+                self.stop_reason, shadow.stop_reason
+            ));
+        }
+        if self.block_types != shadow.block_types {
+            diffs.push(format!(
+                "content {:?} != {:?}",
+                self.block_types, shadow.block_types
+            ));
+        }
+        if self.tool_names != shadow.tool_names {
+            diffs.push(format!(
+                "tool_use {:?} != {:?}",
+                self.tool_names, shadow.tool_names
+            ));
+        }
+        let (short, long) = if self.text_chars <= shadow.text_chars {
+            (self.text_chars, shadow.text_chars)
+        } else {
+            (shadow.text_chars, self.text_chars)
+        };
+        if long > short.max(1) * 2 {
+            diffs.push(format!(
+                "text_chars {} != {}",
+                self.text_chars, shadow.text_chars
+            ));
+        }
+        diffs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use super::*;
+
+    fn summary(status: u16, body: Value) -> ResponseSummary {
+        ResponseSummary::new(status, body.to_string().as_bytes())
+    }
+
+    #[test]
+    fn test_response_summary_diff() {
+        let primary = summary(
+            200,
+            serde_json::json!({
+                "content": [
+                    {"type": "text", "text": "Let me read it."},
+                    {"type": "tool_use", "id": "toolu_1", "name": "Read", "input": {}}
+                ],
+                "stop_reason": "tool_use"
+            }),
+        );
+        assert_eq!(primary.block_types, ["text", "tool_use"]);
+        assert_eq!(primary.tool_names, ["Read"]);
+
+        let same = summary(
+            200,
+            serde_json::json!({
+                "content": [
+                    {"type": "text", "text": "I'll read the file."},
+                    {"type": "tool_use", "id": "toolu_9", "name": "Read", "input": {"a": 1}}
+                ],
+                "stop_reason": "tool_use"
+            }),
+        );
+        assert!(primary.diff(&same).is_empty());
+
+        let different = summary(
+            200,
+            serde_json::json!({
+                "content": [{"type": "text", "text": "x".repeat(200)}],
+                "stop_reason": "end_turn"
+            }),
+        );
+        let diffs = primary.diff(&different);
+        assert_eq!(diffs.len(), 4);
+        assert!(diffs[0].starts_with("stop_reason"));
+
+        let error = summary(
+            400,
+            serde_json::json!({"type": "error", "error": {"type": "invalid_request_error"}}),
+        );
+        assert!(primary.diff(&error)[0].starts_with("status 200 != 400"));
+
+        // 保活后以 200 返回的错误只比较错误类型
+        let kept_alive = ResponseSummary::new(
+            200,
+            br#"  {"type":"error","error":{"type":"invalid_request_error"}}"#,
+        );
+        assert_eq!(kept_alive.status, None);
+        assert!(kept_alive.diff(&error).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tee_response_forwards_body() {
+        let copied = Arc::new(Mutex::new(None));
+        let sink = copied.clone();
+        let response = tee_response(
+            (StatusCode::CREATED, r#"{"content":[]}"#).into_response(),
+            move |status, bytes| *sink.lock() = Some((status, bytes)),
+        );
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], br#"{"content":[]}"#);
+        let (status, bytes) = copied.lock().take().unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(bytes, body);
+
+        // 超过复制上限时客户端仍收到完整响应，只跳过比较
+        let large = vec![b'x'; MAX_RESPONSE_BYTES + 1];
+        let sink = copied.clone();
+        let response = tee_response(Response::new(Body::from(large.clone())), move |s, b| {
+            *sink.lock() = Some((s, b))
+        });
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.len(), large.len());
+        assert!(copied.lock().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tee_response_passes_keep_alive_through() {
+        let (tx, rx) = futures::channel::mpsc::unbounded::<Result<Bytes, std::io::Error>>();
+        let body = Body::from_stream(rx);
+        let copied = Arc::new(Mutex::new(None));
+        let sink = copied.clone();
+        let response = tee_response(Response::new(body), move |_, bytes| {
+            *sink.lock() = Some(bytes)
+        });
+        let mut data = response.into_body().into_data_stream();
+
+        // 上游完成前保活空白字符即到达客户端
+        tx.unbounded_send(Ok(Bytes::from_static(b" "))).unwrap();
+        assert_eq!(&data.next().await.unwrap().unwrap()[..], b" ");
+        assert!(copied.lock().is_none());
+
+        tx.unbounded_send(Ok(Bytes::from_static(br#"{"content":[]}"#)))
+            .unwrap();
+        drop(tx);
+        assert_eq!(
+            &data.next().await.unwrap().unwrap()[..],
+            br#"{"content":[]}"#
+        );
+        assert!(data.next().await.is_none());
+        assert_eq!(copied.lock().as_deref(), Some(&br#" {"content":[]}"#[..]));
+    }
+}
@@ -9,6 +9,8 @@ use uuid::Uuid;
 
 use crate::kiro::model::events::Event;
 
+use super::converter::{ToolUseIdMap, citations_from_event};
+use super::prefill::PrefillEchoFilter;
 use super::types::get_context_window_size;
 
 /// 找到小于等于目标位置的最近有效UTF-8字符边界
@@ -455,6 +457,38 @@ impl SseStateManager {
     }
 }
 
+/// 上游在响应流中注入的错误（error / exception 事件）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamStreamError {
+    /// 错误代码或异常类型
+    pub code: String,
+    /// 错误消息
+    pub message: String,
+}
+
+impl UpstreamStreamError {
+    /// 对应的 Anthropic 错误类型
+    pub fn error_type(&self) -> &'static str {
+        let code = self.code.to_ascii_lowercase();
+        if code.contains("throttl") || code.contains("toomanyrequests") {
+            "rate_limit_error"
+        } else if code.contains("accessdenied") || code.contains("forbidden") {
+            "permission_error"
+        } else if code.contains("unauthorized")
+            || code.contains("expiredtoken")
+            || code.contains("invalidtoken")
+        {
+            "authentication_error"
+        } else if code.contains("validation") || code.contains("badrequest") {
+            "invalid_request_error"
+        } else if code.contains("unavailable") || code.contains("overloaded") {
+            "overloaded_error"
+        } else {
+            "api_error"
+        }
+    }
+}
+
 /// 流处理上下文
 pub struct StreamContext {
     /// SSE 状态管理器
@@ -486,6 +520,16 @@ pub struct StreamContext {
     /// 是否需要剥离 thinking 内容开头的换行符
     /// 模型输出 `<thinking>\n` 时，`\n` 可能与标签在同一 chunk 或下一 chunk
     strip_thinking_leading_newline: bool,
+    /// 待输出的 assistant 预填充文本（作为文本块的第一个 delta）
+    prefill: Option<String>,
+    /// 剥离上游对预填充内容的复述
+    prefill_filter: Option<PrefillEchoFilter>,
+    /// 上游在流中返回的错误（由调用方取出处理）
+    upstream_error: Option<UpstreamStreamError>,
+    /// 规范化后的 tool_use id → 客户端原始 id
+    tool_use_ids: ToolUseIdMap,
+    /// 尚未发送的引用（thinking 结束前收到的引用随下一个文本 delta 发送）
+    pending_citations: Vec<serde_json::Value>,
 }
 
 impl StreamContext {
@@ -510,7 +554,29 @@ impl StreamContext {
             thinking_block_index: None,
             text_block_index: None,
             strip_thinking_leading_newline: false,
+            prefill: None,
+            prefill_filter: None,
+            upstream_error: None,
+            tool_use_ids: ToolUseIdMap::default(),
+            pending_citations: Vec::new(),
+        }
+    }
+
+    /// 设置 assistant 预填充文本：文本块以它开头，上游复述的部分会被剥离
+    ///
+    /// 转换时已拒绝同时启用 thinking 的预填充请求，这里只处理非 thinking 模式
+    pub fn with_prefill(mut self, prefill: Option<String>) -> Self {
+        if let Some(prefill) = prefill {
+            self.prefill_filter = Some(PrefillEchoFilter::new(prefill.clone()));
+            self.prefill = Some(prefill);
         }
+        self
+    }
+
+    /// 设置 tool_use id 对照表：返回的 tool_use id 还原为客户端原始 id
+    pub fn with_tool_use_ids(mut self, tool_use_ids: ToolUseIdMap) -> Self {
+        self.tool_use_ids = tool_use_ids;
+        self
     }
 
     /// 生成 message_start 事件
@@ -569,6 +635,10 @@ impl StreamContext {
         );
         events.extend(text_block_events);
 
+        if let Some(prefill) = self.prefill.take() {
+            events.extend(self.create_text_delta_events(&prefill));
+        }
+
         events
     }
 
@@ -577,6 +647,14 @@ impl StreamContext {
         match event {
             Event::AssistantResponse(resp) => self.process_assistant_response(&resp.content),
             Event::ToolUse(tool_use) => self.process_tool_use(tool_use),
+            Event::Citation(_) | Event::SupplementaryWebLinks(_) => {
+                self.pending_citations.extend(citations_from_event(event));
+                // thinking 可能尚未结束，等到下一个文本 delta 再发送，保证文本块位于 thinking 块之后
+                if self.thinking_enabled && !self.thinking_extracted {
+                    return Vec::new();
+                }
+                self.create_citation_delta_events()
+            }
             Event::ContextUsage(context_usage) => {
                 // 从上下文使用百分比计算实际的 input_tokens
                 let context_window = get_context_window_size(&self.model);
@@ -602,17 +680,26 @@ impl StreamContext {
                 error_message,
             } => {
                 tracing::error!("收到错误事件: {} - {}", error_code, error_message);
+                self.upstream_error = Some(UpstreamStreamError {
+                    code: error_code.clone(),
+                    message: error_message.clone(),
+                });
                 Vec::new()
             }
             Event::Exception {
                 exception_type,
                 message,
             } => {
-                // 处理 ContentLengthExceededException
+                tracing::warn!("收到异常事件: {} - {}", exception_type, message);
+                // ContentLengthExceededException 是正常的截断结束，其余异常视为上游错误
                 if exception_type == "ContentLengthExceededException" {
                     self.state_manager.set_stop_reason("max_tokens");
+                } else {
+                    self.upstream_error = Some(UpstreamStreamError {
+                        code: exception_type.clone(),
+                        message: message.clone(),
+                    });
                 }
-                tracing::warn!("收到异常事件: {} - {}", exception_type, message);
                 Vec::new()
             }
             _ => Vec::new(),
@@ -621,6 +708,14 @@ impl StreamContext {
 
     /// 处理助手响应事件
     fn process_assistant_response(&mut self, content: &str) -> Vec<SseEvent> {
+        let filtered;
+        let content = match &mut self.prefill_filter {
+            Some(filter) => {
+                filtered = filter.push(content);
+                filtered.as_str()
+            }
+            None => content,
+        };
         if content.is_empty() {
             return Vec::new();
         }
@@ -792,7 +887,56 @@ impl StreamContext {
     /// 返回值包含可能的 content_block_start 事件和 content_block_delta 事件。
     fn create_text_delta_events(&mut self, text: &str) -> Vec<SseEvent> {
         let mut events = Vec::new();
+        let text_index = self.ensure_text_block(&mut events);
+        self.push_citation_deltas(text_index, &mut events);
 
+        // 发送 content_block_delta 事件
+        if let Some(delta_event) = self.state_manager.handle_content_block_delta(
+            text_index,
+            json!({
+                "type": "content_block_delta",
+                "index": text_index,
+                "delta": {
+                    "type": "text_delta",
+                    "text": text
+                }
+            }),
+        ) {
+            events.push(delta_event);
+        }
+
+        events
+    }
+
+    /// 发送待发送的引用（文本块尚未创建时先创建）
+    fn create_citation_delta_events(&mut self) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        let text_index = self.ensure_text_block(&mut events);
+        self.push_citation_deltas(text_index, &mut events);
+        events
+    }
+
+    /// 将待发送的引用作为 citations_delta 加入事件列表
+    fn push_citation_deltas(&mut self, text_index: i32, events: &mut Vec<SseEvent>) {
+        for citation in std::mem::take(&mut self.pending_citations) {
+            if let Some(delta_event) = self.state_manager.handle_content_block_delta(
+                text_index,
+                json!({
+                    "type": "content_block_delta",
+                    "index": text_index,
+                    "delta": {
+                        "type": "citations_delta",
+                        "citation": citation
+                    }
+                }),
+            ) {
+                events.push(delta_event);
+            }
+        }
+    }
+
+    /// 获取当前文本块索引，文本块不存在或已关闭时创建新的文本块
+    fn ensure_text_block(&mut self, events: &mut Vec<SseEvent>) -> i32 {
         // 如果当前 text_block_index 指向的块已经被关闭（例如 tool_use 开始时自动 stop），
         // 则丢弃该索引并创建新的文本块继续输出，避免 delta 被状态机拒绝导致“吞字”。
         if let Some(idx) = self.text_block_index {
@@ -802,7 +946,7 @@ impl StreamContext {
         }
 
         // 获取或创建文本块索引
-        let text_index = if let Some(idx) = self.text_block_index {
+        if let Some(idx) = self.text_block_index {
             idx
         } else {
             // 文本块尚未创建，需要先创建
@@ -824,24 +968,7 @@ impl StreamContext {
             );
             events.extend(start_events);
             idx
-        };
-
-        // 发送 content_block_delta 事件
-        if let Some(delta_event) = self.state_manager.handle_content_block_delta(
-            text_index,
-            json!({
-                "type": "content_block_delta",
-                "index": text_index,
-                "delta": {
-                    "type": "text_delta",
-                    "text": text
-                }
-            }),
-        ) {
-            events.push(delta_event);
         }
-
-        events
     }
 
     /// 创建 thinking_delta 事件
@@ -939,7 +1066,7 @@ impl StreamContext {
                 "index": block_index,
                 "content_block": {
                     "type": "tool_use",
-                    "id": tool_use.tool_use_id,
+                    "id": self.tool_use_ids.restore(&tool_use.tool_use_id),
                     "name": tool_use.name,
                     "input": {}
                 }
@@ -1053,6 +1180,11 @@ impl StreamContext {
             events.extend(self.create_text_delta_events(" "));
         }
 
+        // 发送尚未发送的引用
+        if !self.pending_citations.is_empty() {
+            events.extend(self.create_citation_delta_events());
+        }
+
         // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则使用估算值
         let final_input_tokens = self.context_input_tokens.unwrap_or(self.input_tokens);
 
@@ -1063,6 +1195,45 @@ impl StreamContext {
         );
         events
     }
+
+    /// 取出上游在流中返回的错误
+    pub fn take_upstream_error(&mut self) -> Option<UpstreamStreamError> {
+        self.upstream_error.take()
+    }
+
+    /// 生成流中途失败时的结束事件：Anthropic error 事件，随后是 message_stop
+    ///
+    /// 已打开的内容块不再补发 stop，客户端应以 error 事件为准丢弃不完整的响应
+    pub fn generate_error_events(&mut self, error_type: &str, message: &str) -> Vec<SseEvent> {
+        let mut events = vec![SseEvent::new(
+            "error",
+            json!({
+                "type": "error",
+                "error": {
+                    "type": error_type,
+                    "message": message
+                }
+            }),
+        )];
+        if !self.state_manager.message_ended {
+            self.state_manager.message_ended = true;
+            events.push(SseEvent::new(
+                "message_stop",
+                json!({ "type": "message_stop" }),
+            ));
+        }
+        events
+    }
+
+    /// 最终的 (input_tokens, output_tokens)
+    ///
+    /// input_tokens 优先使用从 contextUsageEvent 计算的值
+    pub fn usage(&self) -> (i32, i32) {
+        (
+            self.context_input_tokens.unwrap_or(self.input_tokens),
+            self.output_tokens,
+        )
+    }
 }
 
 /// 缓冲流处理上下文 - 用于 /cc/v1/messages 流式请求
@@ -1103,6 +1274,18 @@ impl BufferedStreamContext {
         }
     }
 
+    /// 设置 assistant 预填充文本，见 [`StreamContext::with_prefill`]
+    pub fn with_prefill(mut self, prefill: Option<String>) -> Self {
+        self.inner = self.inner.with_prefill(prefill);
+        self
+    }
+
+    /// 设置 tool_use id 对照表，见 [`StreamContext::with_tool_use_ids`]
+    pub fn with_tool_use_ids(mut self, tool_use_ids: ToolUseIdMap) -> Self {
+        self.inner = self.inner.with_tool_use_ids(tool_use_ids);
+        self
+    }
+
     /// 处理 Kiro 事件并缓冲结果
     ///
     /// 复用 StreamContext 的事件处理逻辑，但把结果缓存而不是立即发送。
@@ -1156,6 +1339,33 @@ impl BufferedStreamContext {
 
         std::mem::take(&mut self.event_buffer)
     }
+
+    /// 取出上游在流中返回的错误，见 [`StreamContext::take_upstream_error`]
+    pub fn take_upstream_error(&mut self) -> Option<UpstreamStreamError> {
+        self.inner.take_upstream_error()
+    }
+
+    /// 流中途失败：返回已缓冲的事件，随后是 error 与 message_stop 事件
+    pub fn finish_with_error(&mut self, error_type: &str, message: &str) -> Vec<SseEvent> {
+        if !self.initial_events_generated {
+            let initial_events = self.inner.generate_initial_events();
+            self.event_buffer.extend(initial_events);
+            self.initial_events_generated = true;
+        }
+        let error_events = self.inner.generate_error_events(error_type, message);
+        self.event_buffer.extend(error_events);
+        std::mem::take(&mut self.event_buffer)
+    }
+
+    /// 最终的 (input_tokens, output_tokens)
+    pub fn usage(&self) -> (i32, i32) {
+        (
+            self.inner
+                .context_input_tokens
+                .unwrap_or(self.estimated_input_tokens),
+            self.inner.output_tokens,
+        )
+    }
 }
 
 /// 简单的 token 估算
@@ -1183,6 +1393,51 @@ fn estimate_tokens(text: &str) -> i32 {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_upstream_error_event_ends_stream_with_error() {
+        let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4", 1, false);
+        let _ = ctx.generate_initial_events();
+
+        let events = ctx.process_kiro_event(&Event::Exception {
+            exception_type: "ThrottlingException".to_string(),
+            message: "Too many requests".to_string(),
+        });
+        assert!(events.is_empty());
+        let error = ctx.take_upstream_error().unwrap();
+        assert_eq!(error.error_type(), "rate_limit_error");
+        assert!(ctx.take_upstream_error().is_none());
+
+        let events = ctx.generate_error_events(error.error_type(), &error.message);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "error");
+        assert_eq!(events[0].data["error"]["type"], "rate_limit_error");
+        assert_eq!(events[0].data["error"]["message"], "Too many requests");
+        assert_eq!(events[1].event, "message_stop");
+        // message_stop 只发送一次
+        assert!(
+            ctx.generate_final_events()
+                .iter()
+                .all(|e| e.event != "message_stop")
+        );
+    }
+
+    #[test]
+    fn test_content_length_exception_is_not_an_error() {
+        let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4", 1, false);
+        ctx.process_kiro_event(&Event::Exception {
+            exception_type: "ContentLengthExceededException".to_string(),
+            message: String::new(),
+        });
+        assert!(ctx.take_upstream_error().is_none());
+        assert_eq!(ctx.state_manager.get_stop_reason(), "max_tokens");
+
+        let error = UpstreamStreamError {
+            code: "InternalServerException".to_string(),
+            message: String::new(),
+        };
+        assert_eq!(error.error_type(), "api_error");
+    }
+
     #[test]
     fn test_sse_event_format() {
         let event = SseEvent::new("message_start", json!({"type": "message_start"}));
@@ -1227,6 +1482,56 @@ mod tests {
         assert!(event.is_none());
     }
 
+    #[test]
+    fn test_citation_emits_citations_delta_on_text_block() {
+        let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4", 1, false);
+        let initial = ctx.generate_initial_events();
+        let text_index = initial[1].data["index"].as_i64().unwrap();
+
+        ctx.process_kiro_event(&Event::AssistantResponse(
+            serde_json::from_value(json!({"content": "Rust 1.0 shipped in 2015."})).unwrap(),
+        ));
+        let events = ctx.process_kiro_event(&Event::Citation(
+            serde_json::from_value(json!({
+                "citationText": "Rust 1.0 shipped in 2015",
+                "citationLink": "https://blog.rust-lang.org"
+            }))
+            .unwrap(),
+        ));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data["index"], text_index);
+        assert_eq!(events[0].data["delta"]["type"], "citations_delta");
+        assert_eq!(
+            events[0].data["delta"]["citation"]["url"],
+            "https://blog.rust-lang.org"
+        );
+    }
+
+    #[test]
+    fn test_citation_during_thinking_waits_for_text_block() {
+        let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4", 1, true);
+        ctx.generate_initial_events();
+        ctx.process_kiro_event(&Event::AssistantResponse(
+            serde_json::from_value(json!({"content": "<thinking>searching"})).unwrap(),
+        ));
+        let links = Event::SupplementaryWebLinks(
+            serde_json::from_value(json!({
+                "supplementaryWebLinks": [{"url": "https://www.rust-lang.org", "title": "Rust"}]
+            }))
+            .unwrap(),
+        );
+        assert!(ctx.process_kiro_event(&links).is_empty());
+
+        // thinking 块结束后，引用在文本块中先于文本发送
+        let final_events = ctx.generate_final_events();
+        let citation_delta = final_events
+            .iter()
+            .find(|e| e.data["delta"]["type"] == "citations_delta")
+            .unwrap();
+        let thinking_index = ctx.thinking_block_index.unwrap();
+        assert!(citation_delta.data["index"].as_i64().unwrap() > thinking_index as i64);
+    }
+
     #[test]
     fn test_text_delta_after_tool_use_restarts_text_block() {
         let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
@@ -1703,6 +2008,18 @@ mod tests {
             .collect()
     }
 
+    #[test]
+    fn test_prefill_is_first_text_delta_and_echo_is_stripped() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+            .with_prefill(Some("Once upon".to_string()));
+        let mut events = ctx.generate_initial_events();
+        assert_eq!(collect_text_content(&events), "Once upon");
+
+        events.extend(ctx.process_assistant_response("Once "));
+        events.extend(ctx.process_assistant_response("upon a time"));
+        assert_eq!(collect_text_content(&events), "Once upon a time");
+    }
+
     #[test]
     fn test_end_tag_newlines_split_across_events() {
         // `</thinking>\n` 在 chunk 1，`\n` 在 chunk 2，`text` 在 chunk 3
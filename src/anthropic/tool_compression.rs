@@ -4,8 +4,23 @@
 //! 压缩策略：
 //! 1. 简化 input_schema（仅保留 type/enum/required）
 //! 2. 按比例压缩 description（最小 50 字符）
+//!
+//! 每个实际执行的压缩步骤都返回耗时与节省的字节数（[`CompressionPass`]），用于指标统计。
+//! [`dry_run`] 对捕获的真实请求执行同样的压缩并只返回统计（`POST /api/admin/debug/compress`），用于调整压缩参数。
+
+use std::time::Instant;
+
+use serde::Serialize;
+use serde_json::Value;
 
+use crate::kiro::debug_dump::CompressionPassStats;
+use crate::kiro::model::requests::conversation::ConversationState;
+use crate::kiro::model::requests::kiro::KiroRequest;
 use crate::kiro::model::requests::tool::{InputSchema, Tool, ToolSpecification};
+use crate::model::config::Config;
+
+use super::converter::{CompressionPass, ConversionOptions, convert_request};
+use super::types::MessagesRequest;
 
 /// 工具压缩目标大小（20KB）
 const TOOL_COMPRESSION_TARGET_SIZE: usize = 20 * 1024;
@@ -14,7 +29,7 @@ const TOOL_COMPRESSION_TARGET_SIZE: usize = 20 * 1024;
 const MIN_TOOL_DESCRIPTION_LENGTH: usize = 50;
 
 /// 计算工具列表的 JSON 序列化大小
-fn calculate_tools_size(tools: &[Tool]) -> usize {
+pub fn calculate_tools_size(tools: &[Tool]) -> usize {
     serde_json::to_string(tools).map(|s| s.len()).unwrap_or(0)
 }
 
@@ -99,12 +114,18 @@ fn compress_description(description: &str, target_length: usize) -> String {
     format!("{}...", &description[..safe_len])
 }
 
+/// 工具总大小是否超过压缩阈值
+pub fn needs_compression(tools: &[Tool]) -> bool {
+    calculate_tools_size(tools) > TOOL_COMPRESSION_TARGET_SIZE
+}
+
 /// 如果工具总大小超过阈值则压缩
 ///
-/// 返回压缩后的工具列表（如果不需要压缩则返回原列表的克隆）
-pub fn compress_tools_if_needed(tools: &[Tool]) -> Vec<Tool> {
+/// 返回压缩后的工具列表（如果不需要压缩则返回原列表的克隆）以及实际执行的压缩步骤
+pub fn compress_tools_if_needed(tools: &[Tool]) -> (Vec<Tool>, Vec<CompressionPass>) {
+    let mut passes = Vec::new();
     if tools.is_empty() {
-        return tools.to_vec();
+        return (tools.to_vec(), passes);
     }
 
     let original_size = calculate_tools_size(tools);
@@ -114,7 +135,7 @@ pub fn compress_tools_if_needed(tools: &[Tool]) -> Vec<Tool> {
             original_size,
             TOOL_COMPRESSION_TARGET_SIZE
         );
-        return tools.to_vec();
+        return (tools.to_vec(), passes);
     }
 
     tracing::info!(
@@ -124,6 +145,7 @@ pub fn compress_tools_if_needed(tools: &[Tool]) -> Vec<Tool> {
     );
 
     // 第一步：简化 input_schema
+    let started = Instant::now();
     let mut compressed: Vec<Tool> = tools
         .iter()
         .map(|t| {
@@ -141,6 +163,12 @@ pub fn compress_tools_if_needed(tools: &[Tool]) -> Vec<Tool> {
         .collect();
 
     let size_after_schema = calculate_tools_size(&compressed);
+    passes.push(CompressionPass {
+        name: "schema",
+        elapsed: started.elapsed(),
+        bytes_before: original_size,
+        bytes_after: size_after_schema,
+    });
     tracing::debug!(
         "schema 简化后大小: {} 字节 (减少 {} 字节)",
         size_after_schema,
@@ -149,10 +177,11 @@ pub fn compress_tools_if_needed(tools: &[Tool]) -> Vec<Tool> {
 
     if size_after_schema <= TOOL_COMPRESSION_TARGET_SIZE {
         tracing::info!("schema 简化后已达标，最终大小: {} 字节", size_after_schema);
-        return compressed;
+        return (compressed, passes);
     }
 
     // 第二步：按比例压缩 description
+    let started = Instant::now();
     let size_to_reduce = size_after_schema - TOOL_COMPRESSION_TARGET_SIZE;
     let total_desc_len: usize = compressed
         .iter()
@@ -171,6 +200,12 @@ pub fn compress_tools_if_needed(tools: &[Tool]) -> Vec<Tool> {
     }
 
     let final_size = calculate_tools_size(&compressed);
+    passes.push(CompressionPass {
+        name: "description",
+        elapsed: started.elapsed(),
+        bytes_before: size_after_schema,
+        bytes_after: final_size,
+    });
     tracing::info!(
         "压缩完成，原始: {} 字节, 最终: {} 字节 ({:.1}% 减少)",
         original_size,
@@ -178,5 +213,137 @@ pub fn compress_tools_if_needed(tools: &[Tool]) -> Vec<Tool> {
         (original_size - final_size) as f64 / original_size as f64 * 100.0
     );
 
-    compressed
+    (compressed, passes)
+}
+
+/// 压缩试运行的结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressionReport {
+    /// 输入格式（`anthropic`：Anthropic Messages 请求，按当前配置转换；`kiro`：已转换的 Kiro 请求或 ConversationState）
+    pub input: &'static str,
+    /// 工具数量
+    pub tool_count: usize,
+    /// 压缩前工具定义的序列化大小（字节）
+    pub tools_bytes_before: usize,
+    /// 压缩后工具定义的序列化大小（字节）
+    pub tools_bytes_after: usize,
+    /// 实际执行的压缩步骤（未超过阈值时为空）
+    pub passes: Vec<CompressionPassStats>,
+    /// 最终发送给上游的 Kiro 请求体大小（字节）
+    pub request_bytes: usize,
+}
+
+/// 对一个请求执行工具压缩并返回统计（不发送请求）
+///
+/// 输入可以是 Anthropic Messages 请求、Kiro 请求（`{"conversationState": ...}`，如调试转储中的 `request`）
+/// 或单独的 ConversationState。Kiro 请求中的工具可能已经压缩过，此时统计的是再次压缩的效果。
+pub fn dry_run(input: Value, config: &Config) -> Result<CompressionReport, String> {
+    let (kind, state, tools_before, passes) = if input.get("conversationState").is_some()
+        || input.get("currentMessage").is_some()
+    {
+        let mut state = match input.get("conversationState") {
+            Some(_) => serde_json::from_value::<KiroRequest>(input).map(|r| r.conversation_state),
+            None => serde_json::from_value::<ConversationState>(input),
+        }
+        .map_err(|e| e.to_string())?;
+        let context = &mut state
+            .current_message
+            .user_input_message
+            .user_input_message_context;
+        let tools_before = calculate_tools_size(&context.tools);
+        let (compressed, passes) = compress_tools_if_needed(&context.tools);
+        context.tools = compressed;
+        ("kiro", state, tools_before, passes)
+    } else {
+        let request: MessagesRequest = serde_json::from_value(input).map_err(|e| e.to_string())?;
+        let result = convert_request(&request, &ConversionOptions::from_config(config))
+            .map_err(|e| e.to_string())?;
+        let state = result.conversation_state;
+        let tools_before = match result.compression_passes.first() {
+            Some(pass) => pass.bytes_before,
+            None => calculate_tools_size(
+                &state
+                    .current_message
+                    .user_input_message
+                    .user_input_message_context
+                    .tools,
+            ),
+        };
+        ("anthropic", state, tools_before, result.compression_passes)
+    };
+
+    let tools = &state
+        .current_message
+        .user_input_message
+        .user_input_message_context
+        .tools;
+    let (tool_count, tools_bytes_after) = (tools.len(), calculate_tools_size(tools));
+    let request = KiroRequest {
+        conversation_state: state,
+        profile_arn: None,
+    };
+    Ok(CompressionReport {
+        input: kind,
+        tool_count,
+        tools_bytes_before: tools_before,
+        tools_bytes_after,
+        passes: passes.iter().map(CompressionPassStats::from).collect(),
+        request_bytes: serde_json::to_string(&request).map_or(0, |s| s.len()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn large_tools(count: usize) -> Vec<Value> {
+        (0..count)
+            .map(|i| {
+                serde_json::json!({
+                    "name": format!("tool_{i}"),
+                    "description": "d".repeat(2000),
+                    "input_schema": {
+                        "type": "object",
+                        "properties": {"path": {"type": "string", "description": "p".repeat(500)}}
+                    }
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dry_run_reports_passes() {
+        let config = Config::default();
+        let request = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": large_tools(20)
+        });
+        let report = dry_run(request, &config).unwrap();
+        assert_eq!(report.input, "anthropic");
+        assert_eq!(report.tool_count, 20);
+        assert_eq!(report.passes.len(), 2);
+        assert_eq!(report.passes[0].name, "schema");
+        assert!(report.tools_bytes_after <= TOOL_COMPRESSION_TARGET_SIZE);
+        assert!(report.tools_bytes_before > report.tools_bytes_after);
+        assert!(report.request_bytes > report.tools_bytes_after);
+
+        // 已转换的 Kiro 请求：工具已压缩到阈值内，不再执行压缩步骤
+        let kiro = serde_json::json!({
+            "conversationState": {
+                "conversationId": "c",
+                "currentMessage": {
+                    "userInputMessage": {"userInputMessageContext": {}, "content": "hi", "modelId": "m"}
+                },
+                "history": []
+            }
+        });
+        let report = dry_run(kiro, &config).unwrap();
+        assert_eq!(report.input, "kiro");
+        assert!(report.passes.is_empty());
+
+        assert!(dry_run(serde_json::json!({"messages": 1}), &config).is_err());
+    }
 }
@@ -0,0 +1,139 @@
+//! 工具 Schema 学习缓存
+//!
+//! Kiro API 要求历史消息中引用的工具必须在 tools 列表中有定义。
+//! 客户端在后续请求中移除某个工具定义时，转换器需要为其生成占位符；
+//! 空的宽松 schema 偶尔会让模型以错误的参数再次调用该工具。
+//!
+//! 本模块按 conversationId + 工具名（忽略大小写）缓存同一会话中
+//! 先前请求出现过的真实工具定义，用于替代空占位符。
+//! 只有来自真实会话（metadata.user_id 中的 session 或 X-Kiro-Session 映射）的
+//! conversationId 才会写入缓存；随机生成的 ID 不会被再次读取，缓存只会白白占用内存。
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::kiro::model::requests::tool::Tool;
+
+/// 最多缓存的会话数量
+const MAX_CONVERSATIONS: usize = 1024;
+
+/// 会话缓存过期时间（2 小时无访问即过期）
+const CONVERSATION_TTL: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// 单个会话的工具定义缓存
+struct ConversationTools {
+    /// 工具名（小写）-> 工具定义
+    tools: HashMap<String, Tool>,
+    /// 最近一次访问时间
+    touched_at: Instant,
+}
+
+/// 全局缓存存储
+static TOOL_SCHEMA_CACHE: OnceLock<Mutex<HashMap<String, ConversationTools>>> = OnceLock::new();
+
+fn cache() -> &'static Mutex<HashMap<String, ConversationTools>> {
+    TOOL_SCHEMA_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// 记录会话中出现的工具定义
+pub fn remember_tools(conversation_id: &str, tools: &[Tool]) {
+    if tools.is_empty() {
+        return;
+    }
+
+    let mut cache = cache().lock();
+    let now = Instant::now();
+
+    if !cache.contains_key(conversation_id) && cache.len() >= MAX_CONVERSATIONS {
+        evict(&mut cache, now);
+    }
+
+    let entry = cache
+        .entry(conversation_id.to_string())
+        .or_insert_with(|| ConversationTools {
+            tools: HashMap::new(),
+            touched_at: now,
+        });
+    entry.touched_at = now;
+    for tool in tools {
+        entry
+            .tools
+            .insert(tool.tool_specification.name.to_lowercase(), tool.clone());
+    }
+}
+
+/// 查找会话中先前出现过的工具定义
+pub fn lookup_tool(conversation_id: &str, name: &str) -> Option<Tool> {
+    let mut cache = cache().lock();
+    let entry = cache.get_mut(conversation_id)?;
+    if entry.touched_at.elapsed() >= CONVERSATION_TTL {
+        cache.remove(conversation_id);
+        return None;
+    }
+    entry.touched_at = Instant::now();
+
+    let mut tool = entry.tools.get(&name.to_lowercase())?.clone();
+    // 保持历史中使用的名称（Kiro 匹配时忽略大小写，但保留原样更直观）
+    tool.tool_specification.name = name.to_string();
+    Some(tool)
+}
+
+/// 淘汰过期会话；若仍超出上限，再淘汰最久未访问的会话
+fn evict(cache: &mut HashMap<String, ConversationTools>, now: Instant) {
+    cache.retain(|_, v| now.duration_since(v.touched_at) < CONVERSATION_TTL);
+
+    if cache.len() >= MAX_CONVERSATIONS
+        && let Some(oldest) = cache
+            .iter()
+            .min_by_key(|(_, v)| v.touched_at)
+            .map(|(k, _)| k.clone())
+    {
+        cache.remove(&oldest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::requests::tool::{InputSchema, ToolSpecification};
+
+    fn make_tool(name: &str) -> Tool {
+        Tool {
+            tool_specification: ToolSpecification {
+                name: name.to_string(),
+                description: format!("{} tool", name),
+                input_schema: InputSchema::from_json(serde_json::json!({
+                    "type": "object",
+                    "properties": {"path": {"type": "string"}},
+                    "required": ["path"]
+                })),
+            },
+        }
+    }
+
+    #[test]
+    fn test_remember_and_lookup_case_insensitive() {
+        let conversation_id = uuid::Uuid::new_v4().to_string();
+        remember_tools(&conversation_id, &[make_tool("Read")]);
+
+        let tool = lookup_tool(&conversation_id, "read").expect("应命中缓存");
+        assert_eq!(tool.tool_specification.name, "read");
+        assert_eq!(tool.tool_specification.description, "Read tool");
+        assert_eq!(
+            tool.tool_specification.input_schema.json["required"][0],
+            "path"
+        );
+    }
+
+    #[test]
+    fn test_lookup_is_scoped_to_conversation() {
+        let conversation_a = uuid::Uuid::new_v4().to_string();
+        let conversation_b = uuid::Uuid::new_v4().to_string();
+        remember_tools(&conversation_a, &[make_tool("Write")]);
+
+        assert!(lookup_tool(&conversation_b, "Write").is_none());
+    }
+}
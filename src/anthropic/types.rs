@@ -1,7 +1,11 @@
 //! Anthropic API 类型定义
 
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+use crate::token::TokenBreakdown;
 
 // === 错误响应 ===
 
@@ -136,6 +140,30 @@ pub struct MessagesRequest {
     pub output_config: Option<OutputConfig>,
     /// Claude Code 请求中的 metadata，包含 session 信息
     pub metadata: Option<Metadata>,
+    /// 未识别的顶层字段（原样保留，用于跟踪客户端新增的功能）
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// 已记录过日志的未识别字段名
+static LOGGED_UNKNOWN_FIELDS: LazyLock<Mutex<HashSet<String>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+impl MessagesRequest {
+    /// 记录未识别的顶层字段：每个字段名首次出现时记录日志，返回本次首次出现的字段名
+    pub fn log_unknown_fields(&self) -> Vec<&str> {
+        let mut logged = LOGGED_UNKNOWN_FIELDS.lock();
+        let new_fields: Vec<&str> = self
+            .extra
+            .keys()
+            .filter(|key| logged.insert(key.to_string()))
+            .map(String::as_str)
+            .collect();
+        if !new_fields.is_empty() {
+            tracing::info!("请求包含暂不支持的字段，已忽略: {}", new_fields.join(", "));
+        }
+        new_fields
+    }
 }
 
 /// 反序列化 system 字段，支持字符串或数组格式
@@ -299,6 +327,16 @@ pub struct CountTokensRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CountTokensResponse {
     pub input_tokens: i32,
+    /// 按来源拆分的本地估算（仅在请求 `?breakdown=true` 或 beta 头含 `token-breakdown` 时返回）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<TokenBreakdown>,
+}
+
+/// Token 计数查询参数
+#[derive(Debug, Default, Deserialize)]
+pub struct CountTokensQuery {
+    #[serde(default)]
+    pub breakdown: bool,
 }
 
 /// 根据模型名获取上下文窗口大小
@@ -313,3 +351,29 @@ pub(crate) fn get_context_window_size(model: &str) -> i32 {
         200_000
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_request_preserves_unknown_fields() {
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": [],
+            "typesTestField": {"enabled": true},
+            "typesTestOther": 1
+        }))
+        .unwrap();
+        assert_eq!(req.extra["typesTestField"]["enabled"], true);
+        assert!(!req.extra.contains_key("model"));
+
+        // 每个字段名只记录一次
+        assert_eq!(
+            req.log_unknown_fields(),
+            ["typesTestField", "typesTestOther"]
+        );
+        assert!(req.log_unknown_fields().is_empty());
+    }
+}
@@ -15,6 +15,8 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use uuid::Uuid;
 
+use crate::common::i18n;
+
 use super::stream::SseEvent;
 use super::types::{ErrorResponse, MessagesRequest};
 
@@ -98,6 +100,57 @@ pub struct WebSearchResult {
     pub public_domain: Option<bool>,
 }
 
+/// 直接搜索请求（POST /v1/tools/web_search）
+#[derive(Debug, Deserialize)]
+pub struct WebSearchToolRequest {
+    pub query: String,
+}
+
+/// 直接搜索响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSearchToolResponse {
+    pub query: String,
+    pub results: Vec<NormalizedSearchResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_results: Option<i32>,
+}
+
+/// 规范化的单个搜索结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalizedSearchResult {
+    pub title: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    /// 发布时间（上游原样返回的时间戳）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published_date: Option<i64>,
+}
+
+impl WebSearchToolResponse {
+    pub fn new(query: String, results: &WebSearchResults) -> Self {
+        Self {
+            query,
+            results: results
+                .results
+                .iter()
+                .map(|r| NormalizedSearchResult {
+                    title: r.title.clone(),
+                    url: r.url.clone(),
+                    snippet: r.snippet.clone().filter(|s| !s.is_empty()),
+                    domain: r.domain.clone(),
+                    published_date: r.published_date,
+                })
+                .collect(),
+            total_results: results.total_results,
+        }
+    }
+}
+
 /// 检查请求是否为纯 WebSearch 请求
 ///
 /// 条件：tools 有且只有一个，且 name 为 web_search
@@ -452,7 +505,10 @@ pub async fn handle_websearch_request(
                 StatusCode::BAD_REQUEST,
                 Json(ErrorResponse::new(
                     "invalid_request_error",
-                    "无法从消息中提取搜索查询",
+                    i18n::pick(
+                        "无法从消息中提取搜索查询",
+                        "Unable to extract a search query from the messages",
+                    ),
                 )),
             )
                 .into_response();
@@ -487,6 +543,23 @@ pub async fn handle_websearch_request(
         .unwrap()
 }
 
+/// 直接执行一次搜索，返回解析后的结果
+///
+/// 与 [`handle_websearch_request`] 不同，MCP 调用失败或结果无法解析时返回错误
+pub async fn search(
+    provider: &crate::kiro::provider::KiroProvider,
+    query: &str,
+) -> anyhow::Result<WebSearchResults> {
+    let (_, mcp_request) = create_mcp_request(query);
+    let response = call_mcp_api(provider, &mcp_request).await?;
+    let results = parse_search_results(&response)
+        .ok_or_else(|| anyhow::anyhow!("无法解析 WebSearch 结果"))?;
+    if let Some(error) = &results.error {
+        anyhow::bail!("WebSearch error: {}", error);
+    }
+    Ok(results)
+}
+
 /// 调用 Kiro MCP API
 async fn call_mcp_api(
     provider: &crate::kiro::provider::KiroProvider,
@@ -542,6 +615,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            extra: Default::default(),
         };
 
         assert!(has_web_search_tool(&req));
@@ -580,6 +654,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            extra: Default::default(),
         };
 
         // 多个工具时不应该被识别为纯 websearch 请求
@@ -607,6 +682,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            extra: Default::default(),
         };
 
         let query = extract_search_query(&req);
@@ -632,6 +708,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            extra: Default::default(),
         };
 
         let query = extract_search_query(&req);
@@ -726,4 +803,28 @@ mod tests {
         assert!(summary.contains("https://example.com"));
         assert!(summary.contains("This is a test snippet"));
     }
+
+    #[test]
+    fn test_web_search_tool_response_normalizes_results() {
+        let results: WebSearchResults = serde_json::from_str(
+            r#"{"results":[{"title":"Rust","url":"https://www.rust-lang.org","snippet":"","domain":"rust-lang.org","publishedDate":1700000000000,"maxVerbatimWordLimit":30}],"totalResults":1}"#,
+        )
+        .unwrap();
+
+        let response =
+            serde_json::to_value(WebSearchToolResponse::new("rust".to_string(), &results)).unwrap();
+        assert_eq!(
+            response,
+            json!({
+                "query": "rust",
+                "results": [{
+                    "title": "Rust",
+                    "url": "https://www.rust-lang.org",
+                    "domain": "rust-lang.org",
+                    "publishedDate": 1700000000000i64
+                }],
+                "totalResults": 1
+            })
+        );
+    }
 }
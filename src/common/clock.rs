@@ -0,0 +1,122 @@
+//! 上游时钟偏差校正
+//!
+//! Token 过期判断依赖本机时钟：时钟偏差较大的机器会在每次请求时刷新 Token，或继续使用已过期的 Token。
+//! 每次收到上游响应时按 `Date` 响应头估算偏差（上游时间 - 本机时间）；
+//! 启用 `clockSkewCorrection`（默认）时，Token 过期判断与刷新后写入的过期时间都使用校正后的当前时间。
+//! 偏差超过 `clockSkewWarnSecs` 时记录一次警告，回到阈值内后重新计数。
+//! `Date` 头只精确到秒，2 秒内的偏差视为没有偏差。
+
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+
+use chrono::{DateTime, Duration, Utc};
+use reqwest::header::{DATE, HeaderMap};
+
+/// 视为没有偏差的误差范围（毫秒）
+const TOLERANCE_MS: i64 = 2000;
+
+/// 未初始化时的警告阈值（秒）
+const DEFAULT_WARN_SECS: u64 = 30;
+
+/// 全局设置：(是否校正, 警告阈值秒数)，启动时初始化
+static SETTINGS: OnceLock<(bool, u64)> = OnceLock::new();
+
+/// 最近一次估算的偏差（毫秒）
+static OFFSET_MS: AtomicI64 = AtomicI64::new(0);
+
+/// 当前偏差是否已记录过警告
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// 初始化时钟偏差校正
+pub fn init(correction: bool, warn_secs: u64) {
+    let _ = SETTINGS.set((correction, warn_secs));
+}
+
+fn settings() -> (bool, u64) {
+    SETTINGS.get().copied().unwrap_or((true, DEFAULT_WARN_SECS))
+}
+
+/// 按上游响应的 `Date` 头更新偏差估算
+pub fn observe(headers: &HeaderMap) {
+    let Some(server) = headers
+        .get(DATE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+    else {
+        return;
+    };
+    let offset = estimate(server.with_timezone(&Utc), Utc::now());
+    OFFSET_MS.store(offset.num_milliseconds(), Ordering::Relaxed);
+
+    let (correction, warn_secs) = settings();
+    if offset.num_seconds().unsigned_abs() <= warn_secs {
+        WARNED.store(false, Ordering::Relaxed);
+    } else if !WARNED.swap(true, Ordering::Relaxed) {
+        tracing::warn!(
+            "本机时钟比上游{} {} 秒，{}",
+            if offset.num_seconds() < 0 {
+                "快"
+            } else {
+                "慢"
+            },
+            offset.num_seconds().abs(),
+            if correction {
+                "已在 Token 过期判断中校正，建议同步系统时间"
+            } else {
+                "Token 过期判断可能出错，请同步系统时间或启用 clockSkewCorrection"
+            }
+        );
+    }
+}
+
+/// 估算偏差（上游时间 - 本机时间），误差范围内视为 0
+fn estimate(server: DateTime<Utc>, local: DateTime<Utc>) -> Duration {
+    let offset = server - local;
+    if offset.num_milliseconds().abs() <= TOLERANCE_MS {
+        Duration::zero()
+    } else {
+        offset
+    }
+}
+
+/// 最近一次估算的偏差（上游时间 - 本机时间，不论是否启用校正）
+pub fn observed_offset() -> Duration {
+    Duration::milliseconds(OFFSET_MS.load(Ordering::Relaxed))
+}
+
+/// 当前使用的偏差（未启用校正时为 0）
+pub fn offset() -> Duration {
+    if settings().0 {
+        observed_offset()
+    } else {
+        Duration::zero()
+    }
+}
+
+/// 校正后的当前时间
+pub fn now() -> DateTime<Utc> {
+    Utc::now() + offset()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_offset() {
+        let local = Utc::now();
+        // Date 头精确到秒，误差范围内视为没有偏差
+        assert_eq!(
+            estimate(local + Duration::milliseconds(1500), local),
+            Duration::zero()
+        );
+        assert_eq!(
+            estimate(local - Duration::minutes(7), local),
+            Duration::minutes(-7)
+        );
+        assert_eq!(
+            estimate(local + Duration::seconds(40), local),
+            Duration::seconds(40)
+        );
+    }
+}
@@ -0,0 +1,41 @@
+//! 面向客户端与 Admin API 的多语言文本
+//!
+//! 日志仍使用中文；返回给客户端的错误信息与 Admin API 响应文本
+//! 根据配置 `language`（zh | en）选择语言。
+
+use std::sync::OnceLock;
+
+use crate::model::config::Language;
+
+/// 全局语言设置（启动时初始化，未初始化时默认中文）
+static LANGUAGE: OnceLock<Language> = OnceLock::new();
+
+/// 初始化全局语言设置
+pub fn init(language: Language) {
+    let _ = LANGUAGE.set(language);
+}
+
+/// 获取当前语言
+pub fn language() -> Language {
+    LANGUAGE.get().copied().unwrap_or_default()
+}
+
+/// 按当前语言在中英文文本之间选择
+pub fn pick<T>(zh: T, en: T) -> T {
+    match language() {
+        Language::Zh => zh,
+        Language::En => en,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_defaults_to_chinese_when_uninitialized() {
+        // 测试进程中不调用 init，保持默认中文
+        assert_eq!(pick("中文", "English"), "中文");
+        assert_eq!(language(), Language::Zh);
+    }
+}
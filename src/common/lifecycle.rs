@@ -0,0 +1,86 @@
+//! 实例生命周期状态（滚动部署时的连接排空）
+//!
+//! 进入排空模式后：就绪检查返回未就绪，新请求被拒绝（503），
+//! 已建立的流式响应继续完成。活跃流数量归零后即可安全停止实例。
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// 实例生命周期状态（克隆后共享同一份状态）
+#[derive(Clone, Default)]
+pub struct Lifecycle {
+    inner: Arc<LifecycleInner>,
+}
+
+#[derive(Default)]
+struct LifecycleInner {
+    draining: AtomicBool,
+    active_streams: AtomicUsize,
+}
+
+impl Lifecycle {
+    /// 是否处于排空模式
+    pub fn is_draining(&self) -> bool {
+        self.inner.draining.load(Ordering::Relaxed)
+    }
+
+    /// 进入或退出排空模式
+    pub fn set_draining(&self, draining: bool) {
+        self.inner.draining.store(draining, Ordering::Relaxed);
+        if draining {
+            tracing::warn!(
+                "实例进入排空模式：拒绝新请求，等待 {} 个活跃流结束",
+                self.active_streams()
+            );
+        } else {
+            tracing::info!("实例退出排空模式，恢复接收请求");
+        }
+    }
+
+    /// 当前活跃的流式响应数量
+    pub fn active_streams(&self) -> usize {
+        self.inner.active_streams.load(Ordering::Relaxed)
+    }
+
+    /// 登记一个活跃流，返回的守卫 Drop 时注销
+    pub fn track_stream(&self) -> StreamGuard {
+        self.inner.active_streams.fetch_add(1, Ordering::Relaxed);
+        StreamGuard {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// 活跃流守卫，流结束（或客户端断开）时 Drop
+pub struct StreamGuard {
+    inner: Arc<LifecycleInner>,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.inner.active_streams.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_tracking_and_draining() {
+        let lifecycle = Lifecycle::default();
+        let shared = lifecycle.clone();
+        let first = lifecycle.track_stream();
+        let second = shared.track_stream();
+        assert_eq!(lifecycle.active_streams(), 2);
+        drop(first);
+        assert_eq!(shared.active_streams(), 1);
+        drop(second);
+        assert_eq!(lifecycle.active_streams(), 0);
+
+        shared.set_draining(true);
+        assert!(lifecycle.is_draining());
+        shared.set_draining(false);
+        assert!(!lifecycle.is_draining());
+    }
+}
@@ -1,3 +1,7 @@
 //! 公共工具模块
 
 pub mod auth;
+pub mod clock;
+pub mod i18n;
+pub mod lifecycle;
+pub mod tasks;
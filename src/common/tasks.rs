@@ -0,0 +1,128 @@
+//! 后台任务
+//!
+//! 定期落盘、凭据文件同步、再平衡分析、余额预热与审计日志等后台任务统一在这里登记，
+//! 共享同一个 [`CancellationToken`]。优雅停机时先取消并等待所有任务退出（超时则中止），
+//! 再由调用方执行一次最终落盘，避免后台任务与最终落盘同时写入文件。
+
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// 已登记的后台任务
+#[derive(Default)]
+pub struct BackgroundTasks {
+    cancel: CancellationToken,
+    handles: Vec<(&'static str, JoinHandle<()>)>,
+}
+
+impl BackgroundTasks {
+    /// 启动后台任务，任务应在 CancellationToken 被取消后尽快退出
+    pub fn spawn<F, Fut>(&mut self, name: &'static str, task: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(task(self.cancel.child_token()));
+        self.handles.push((name, handle));
+    }
+
+    /// 启动按间隔执行的后台任务（`skip_first` 为 true 时跳过立即触发的第一次）
+    ///
+    /// 每次等待的间隔在 `period` 上下随机浮动 `jitter_percent`%，
+    /// 避免多个实例或多个凭据池的定时任务在同一时刻集中访问上游与磁盘
+    pub fn spawn_interval<F>(
+        &mut self,
+        name: &'static str,
+        period: Duration,
+        jitter_percent: u8,
+        skip_first: bool,
+        mut tick: F,
+    ) where
+        F: FnMut() + Send + 'static,
+    {
+        self.spawn(name, move |cancel| async move {
+            if !skip_first {
+                tick();
+            }
+            loop {
+                tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    _ = tokio::time::sleep(jittered(period, jitter_percent)) => tick(),
+                }
+            }
+        });
+    }
+
+    /// 取消所有任务并等待退出，超过 `timeout` 仍未退出的任务被中止
+    pub async fn shutdown(self, timeout: Duration) {
+        self.cancel.cancel();
+        let deadline = tokio::time::Instant::now() + timeout;
+        for (name, mut handle) in self.handles {
+            if tokio::time::timeout_at(deadline, &mut handle)
+                .await
+                .is_err()
+            {
+                tracing::warn!("后台任务 {} 未在停机超时内退出，已中止", name);
+                handle.abort();
+            }
+        }
+    }
+}
+
+/// 在 `period` 上下随机浮动 `jitter_percent`%（最多 100%）
+fn jittered(period: Duration, jitter_percent: u8) -> Duration {
+    let spread = period.mul_f64(f64::from(jitter_percent.min(100)) / 100.0);
+    if spread.is_zero() {
+        return period;
+    }
+    period - spread + spread.mul_f64(fastrand::f64() * 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_and_joins() {
+        let mut tasks = BackgroundTasks::default();
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let counter = ticks.clone();
+        tasks.spawn_interval("ticker", Duration::from_millis(5), 20, false, move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        let exited = Arc::new(AtomicBool::new(false));
+        let flag = exited.clone();
+        tasks.spawn("waiter", move |cancel| async move {
+            cancel.cancelled().await;
+            flag.store(true, Ordering::Relaxed);
+        });
+        // 忽略取消信号的任务在超时后被中止，不会阻塞停机
+        tasks.spawn("stuck", |_| std::future::pending());
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        tasks.shutdown(Duration::from_millis(50)).await;
+        assert!(exited.load(Ordering::Relaxed));
+
+        // 停机后不再执行
+        let after = ticks.load(Ordering::Relaxed);
+        assert!(after > 0);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(ticks.load(Ordering::Relaxed), after);
+    }
+
+    #[test]
+    fn test_jittered_period() {
+        let period = Duration::from_secs(10);
+        assert_eq!(jittered(period, 0), period);
+        for _ in 0..100 {
+            let delay = jittered(period, 20);
+            assert!(delay >= Duration::from_secs(8) && delay <= Duration::from_secs(12));
+        }
+        // 超过 100% 时按 100% 处理，不会出现负数
+        assert!(jittered(period, 200) <= Duration::from_secs(20));
+    }
+}
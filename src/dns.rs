@@ -0,0 +1,274 @@
+//! DNS-over-HTTPS 解析器
+//!
+//! 部分受限网络中，系统 DNS 对 AWS 端点的解析被污染或很慢。
+//! 配置 `dohUrl` 后，所有 HTTP Client 改用 DoH（JSON 格式，`application/dns-json`）解析域名：
+//! - 同时查询 A 与 AAAA 记录，任一成功即可
+//! - 结果按记录 TTL 缓存（至少 30 秒，至多 1 小时），同一 DoH 地址的所有 Client 共用缓存
+//! - DoH 服务器本身使用系统 DNS 解析，建议直接使用 IP 地址（如 `https://1.1.1.1/dns-query`）
+//!   或通过 `hostOverrides` 固定其地址
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use reqwest::Client;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::Deserialize;
+
+use crate::model::config::TlsBackend;
+
+/// DoH 查询超时
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 缓存时间下限（秒）
+const MIN_TTL_SECS: u32 = 30;
+
+/// 缓存时间上限（秒）
+const MAX_TTL_SECS: u32 = 3600;
+
+/// DNS 记录类型：A
+const RECORD_A: u16 = 1;
+
+/// DNS 记录类型：AAAA
+const RECORD_AAAA: u16 = 28;
+
+/// DoH JSON 响应
+#[derive(Debug, Deserialize)]
+struct DohResponse {
+    /// 响应码（0 = NOERROR）
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+/// DoH JSON 响应中的单条记录
+#[derive(Debug, Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    #[serde(rename = "TTL", default)]
+    ttl: u32,
+    data: String,
+}
+
+/// 缓存的解析结果
+struct CachedAddrs {
+    addrs: Vec<IpAddr>,
+    expires_at: Instant,
+}
+
+/// DNS-over-HTTPS 解析器
+///
+/// 克隆开销很小，所有克隆共用同一个查询 Client 与缓存
+#[derive(Clone)]
+pub struct DohResolver {
+    inner: Arc<DohInner>,
+}
+
+struct DohInner {
+    url: String,
+    client: Client,
+    cache: Mutex<HashMap<String, CachedAddrs>>,
+}
+
+impl DohResolver {
+    /// 获取指定 DoH 地址的共享解析器（首次调用时创建）
+    ///
+    /// # Arguments
+    /// * `url` - DoH 服务地址
+    /// * `tls_backend` - TLS 后端
+    /// * `host_overrides` - 静态域名映射（用于解析 DoH 服务器自身）
+    pub fn shared(
+        url: &str,
+        tls_backend: TlsBackend,
+        host_overrides: &BTreeMap<String, Vec<IpAddr>>,
+    ) -> anyhow::Result<Self> {
+        static RESOLVERS: OnceLock<Mutex<HashMap<String, DohResolver>>> = OnceLock::new();
+
+        let mut resolvers = RESOLVERS.get_or_init(Default::default).lock();
+        if let Some(resolver) = resolvers.get(url) {
+            return Ok(resolver.clone());
+        }
+
+        let mut builder = Client::builder().timeout(QUERY_TIMEOUT);
+        if tls_backend == TlsBackend::Rustls {
+            builder = builder.use_rustls_tls();
+        }
+        for (host, addrs) in host_overrides {
+            let addrs: Vec<SocketAddr> = addrs.iter().map(|ip| SocketAddr::new(*ip, 0)).collect();
+            builder = builder.resolve_to_addrs(host, &addrs);
+        }
+
+        let resolver = Self {
+            inner: Arc::new(DohInner {
+                url: url.to_string(),
+                client: builder.build()?,
+                cache: Mutex::new(HashMap::new()),
+            }),
+        };
+        resolvers.insert(url.to_string(), resolver.clone());
+        Ok(resolver)
+    }
+
+    /// 解析域名（优先返回缓存）
+    pub async fn lookup_host(&self, host: &str) -> anyhow::Result<Vec<IpAddr>> {
+        self.inner.lookup(&host.to_ascii_lowercase()).await
+    }
+}
+
+impl DohInner {
+    /// 解析域名（优先返回缓存）
+    async fn lookup(&self, host: &str) -> anyhow::Result<Vec<IpAddr>> {
+        if let Some(cached) = self.cache.lock().get(host)
+            && cached.expires_at > Instant::now()
+        {
+            return Ok(cached.addrs.clone());
+        }
+
+        let (v4, v6) = futures::join!(self.query(host, RECORD_A), self.query(host, RECORD_AAAA));
+        let mut addrs = Vec::new();
+        let mut ttl = MAX_TTL_SECS;
+        let mut last_error = None;
+        for result in [v4, v6] {
+            match result {
+                Ok((records, record_ttl)) => {
+                    addrs.extend(records);
+                    ttl = ttl.min(record_ttl);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        if addrs.is_empty() {
+            return Err(
+                last_error.unwrap_or_else(|| anyhow::anyhow!("DoH 未返回 {} 的任何地址", host))
+            );
+        }
+
+        tracing::debug!("DoH 解析 {} -> {:?}（TTL {}秒）", host, addrs, ttl);
+        self.cache.lock().insert(
+            host.to_string(),
+            CachedAddrs {
+                addrs: addrs.clone(),
+                expires_at: Instant::now() + Duration::from_secs(ttl.max(MIN_TTL_SECS) as u64),
+            },
+        );
+        Ok(addrs)
+    }
+
+    /// 查询单一类型的记录，返回地址与最小 TTL
+    async fn query(&self, host: &str, record_type: u16) -> anyhow::Result<(Vec<IpAddr>, u32)> {
+        let response: DohResponse = self
+            .client
+            .get(&self.url)
+            .query(&[("name", host), ("type", &record_type.to_string())])
+            .header("accept", "application/dns-json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        parse_response(response, record_type)
+    }
+}
+
+impl Resolve for DohResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_ascii_lowercase();
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let addrs = inner.lookup(&host).await.map_err(|e| {
+                tracing::warn!("DoH 解析 {} 失败: {}", host, e);
+                Box::<dyn std::error::Error + Send + Sync>::from(e.to_string())
+            })?;
+            let addrs: Addrs = Box::new(addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+/// 从 DoH 响应中提取指定类型的地址与最小 TTL
+fn parse_response(response: DohResponse, record_type: u16) -> anyhow::Result<(Vec<IpAddr>, u32)> {
+    if response.status != 0 {
+        anyhow::bail!("DoH 查询失败（Status {}）", response.status);
+    }
+    let mut ttl = MAX_TTL_SECS;
+    let addrs = response
+        .answer
+        .into_iter()
+        // CNAME 等其他记录只用于跳转，这里只取最终的地址记录
+        .filter(|answer| answer.record_type == record_type)
+        .filter_map(|answer| {
+            let ip = answer.data.parse::<IpAddr>().ok()?;
+            ttl = ttl.min(answer.ttl);
+            Some(ip)
+        })
+        .collect();
+    Ok((addrs, ttl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(json: serde_json::Value) -> DohResponse {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_parse_response_skips_cname_and_takes_min_ttl() {
+        let response = response(serde_json::json!({
+            "Status": 0,
+            "Answer": [
+                { "name": "q.us-east-1.amazonaws.com", "type": 5, "TTL": 10, "data": "alias.amazonaws.com." },
+                { "name": "alias.amazonaws.com", "type": 1, "TTL": 120, "data": "52.94.0.1" },
+                { "name": "alias.amazonaws.com", "type": 1, "TTL": 60, "data": "52.94.0.2" }
+            ]
+        }));
+
+        let (addrs, ttl) = parse_response(response, RECORD_A).unwrap();
+        assert_eq!(
+            addrs,
+            vec![
+                "52.94.0.1".parse::<IpAddr>().unwrap(),
+                "52.94.0.2".parse::<IpAddr>().unwrap()
+            ]
+        );
+        assert_eq!(ttl, 60);
+    }
+
+    #[test]
+    fn test_parse_response_rejects_error_status() {
+        let response = response(serde_json::json!({ "Status": 3 }));
+        assert!(parse_response(response, RECORD_A).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lookup_uses_cache() {
+        let resolver = DohResolver::shared(
+            "https://127.0.0.1:1/dns-query",
+            TlsBackend::Rustls,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        resolver.inner.cache.lock().insert(
+            "example.com".to_string(),
+            CachedAddrs {
+                addrs: vec![ip],
+                expires_at: Instant::now() + Duration::from_secs(60),
+            },
+        );
+
+        // 命中缓存时不访问 DoH 服务器（该地址不可达）
+        let addrs: Vec<_> = resolver
+            .resolve("example.com".parse().unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert_eq!(addrs, vec![SocketAddr::new(ip, 0)]);
+    }
+}
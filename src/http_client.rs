@@ -1,11 +1,15 @@
 //! HTTP Client 构建模块
 //!
-//! 提供统一的 HTTP Client 构建功能，支持代理配置
+//! 提供统一的 HTTP Client 构建功能，支持代理配置与传输层参数
 
 use reqwest::{Client, Proxy};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
 use std::time::Duration;
 
-use crate::model::config::TlsBackend;
+use crate::dns::DohResolver;
+use crate::model::config::{Config, TlsBackend};
 
 /// 代理配置
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
@@ -36,24 +40,147 @@ impl ProxyConfig {
     }
 }
 
+/// 传输层参数（所有 HTTP Client 共用）
+///
+/// 部分企业网络的 TLS 中间人代理不支持 HTTP/2 或会主动断开空闲连接，
+/// 需要关闭 HTTP/2、缩短连接复用时间或只使用 IPv4
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportOptions {
+    /// TLS 后端
+    pub tls_backend: TlsBackend,
+    /// 是否允许 HTTP/2（false 时只使用 HTTP/1.1）
+    ///
+    /// 仅 rustls 后端会通过 ALPN 协商 HTTP/2，native-tls 后端始终使用 HTTP/1.1
+    pub http2: bool,
+    /// 空闲连接保留时间（None 表示不过期）
+    pub pool_idle_timeout: Option<Duration>,
+    /// 每个主机保留的最大空闲连接数（None 表示不限制，0 表示不复用连接）
+    pub pool_max_idle_per_host: Option<usize>,
+    /// 是否在 IPv6/IPv4 之间并行尝试连接（Happy Eyeballs）
+    ///
+    /// false 时只通过 IPv4 建立连接
+    pub happy_eyeballs: bool,
+    /// 静态域名映射：域名 -> IP 列表（优先于 DNS 解析）
+    pub host_overrides: BTreeMap<String, Vec<IpAddr>>,
+    /// DNS-over-HTTPS 服务地址（None 使用系统 DNS）
+    pub doh_url: Option<String>,
+}
+
+impl Default for TransportOptions {
+    fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
+
+impl TransportOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            tls_backend: config.tls_backend,
+            http2: config.http2,
+            pool_idle_timeout: (config.pool_idle_timeout_secs > 0)
+                .then(|| Duration::from_secs(config.pool_idle_timeout_secs)),
+            pool_max_idle_per_host: config.pool_max_idle_per_host,
+            happy_eyeballs: config.happy_eyeballs,
+            host_overrides: config.host_overrides.clone(),
+            doh_url: config.doh_url.clone(),
+        }
+    }
+
+    fn apply(&self, mut builder: reqwest::ClientBuilder) -> anyhow::Result<reqwest::ClientBuilder> {
+        if self.tls_backend == TlsBackend::Rustls {
+            builder = builder.use_rustls_tls();
+        }
+        if !self.http2 {
+            builder = builder.http1_only();
+        }
+        builder = builder.pool_idle_timeout(self.pool_idle_timeout);
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if !self.happy_eyeballs {
+            // 只绑定 IPv4 本地地址时，连接器会跳过所有 IPv6 目标地址
+            builder = builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        }
+
+        // 端口为 0 表示使用 URL 中的端口（或协议默认端口）
+        for (host, addrs) in &self.host_overrides {
+            let addrs: Vec<SocketAddr> = addrs.iter().map(|ip| SocketAddr::new(*ip, 0)).collect();
+            builder = builder.resolve_to_addrs(host, &addrs);
+        }
+        if let Some(url) = &self.doh_url {
+            let resolver = DohResolver::shared(url, self.tls_backend, &self.host_overrides)?;
+            builder = builder.dns_resolver(Arc::new(resolver));
+        }
+        Ok(builder)
+    }
+}
+
+/// HTTP Client 构建选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientOptions {
+    /// 传输层参数
+    pub transport: TransportOptions,
+    /// 整体超时（从发送请求到读完响应体）
+    pub timeout: Duration,
+    /// 建立连接超时（None 表示不单独限制）
+    pub connect_timeout: Option<Duration>,
+    /// 读取超时：两次读取之间的最长间隔（None 表示不限制）
+    pub read_timeout: Option<Duration>,
+}
+
+impl ClientOptions {
+    /// 仅设置整体超时的选项
+    pub fn new(timeout_secs: u64, transport: &TransportOptions) -> Self {
+        Self {
+            transport: transport.clone(),
+            timeout: Duration::from_secs(timeout_secs),
+            connect_timeout: None,
+            read_timeout: None,
+        }
+    }
+
+    /// 上游 API 请求使用的选项（超时取自配置）
+    pub fn for_api(config: &Config) -> Self {
+        let secs = |s: u64| (s > 0).then(|| Duration::from_secs(s));
+        Self {
+            transport: TransportOptions::from_config(config),
+            timeout: Duration::from_secs(config.request_timeout_secs),
+            connect_timeout: secs(config.connect_timeout_secs),
+            read_timeout: secs(config.read_timeout_secs),
+        }
+    }
+}
+
 /// 构建 HTTP Client
 ///
 /// # Arguments
 /// * `proxy` - 可选的代理配置
 /// * `timeout_secs` - 超时时间（秒）
+/// * `transport` - 传输层参数
 ///
 /// # Returns
 /// 配置好的 reqwest::Client
 pub fn build_client(
     proxy: Option<&ProxyConfig>,
     timeout_secs: u64,
-    tls_backend: TlsBackend,
+    transport: &TransportOptions,
 ) -> anyhow::Result<Client> {
-    let mut builder = Client::builder().timeout(Duration::from_secs(timeout_secs));
+    build_client_with_options(proxy, &ClientOptions::new(timeout_secs, transport))
+}
 
-    if tls_backend == TlsBackend::Rustls {
-        builder = builder.use_rustls_tls();
+/// 按构建选项构建 HTTP Client
+pub fn build_client_with_options(
+    proxy: Option<&ProxyConfig>,
+    options: &ClientOptions,
+) -> anyhow::Result<Client> {
+    let mut builder = Client::builder().timeout(options.timeout);
+    if let Some(timeout) = options.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(timeout) = options.read_timeout {
+        builder = builder.read_timeout(timeout);
     }
+    builder = options.transport.apply(builder)?;
 
     if let Some(proxy_config) = proxy {
         let mut proxy = Proxy::all(&proxy_config.url)?;
@@ -92,14 +219,80 @@ mod tests {
 
     #[test]
     fn test_build_client_without_proxy() {
-        let client = build_client(None, 30, TlsBackend::Rustls);
+        let client = build_client(None, 30, &TransportOptions::default());
         assert!(client.is_ok());
     }
 
+    #[test]
+    fn test_client_options_for_api() {
+        let mut config = Config::default();
+        config.request_timeout_secs = 600;
+        config.connect_timeout_secs = 10;
+        let options = ClientOptions::for_api(&config);
+        assert_eq!(options.timeout, Duration::from_secs(600));
+        assert_eq!(options.connect_timeout, Some(Duration::from_secs(10)));
+        assert_eq!(options.read_timeout, None);
+        assert!(build_client_with_options(None, &options).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_host_overrides_bypass_dns() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = socket.read(&mut buf).await;
+            let _ = socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+                .await;
+        });
+
+        let transport = TransportOptions {
+            host_overrides: BTreeMap::from([(
+                "kiro.invalid".to_string(),
+                vec!["127.0.0.1".parse().unwrap()],
+            )]),
+            ..TransportOptions::default()
+        };
+        let client = build_client(None, 5, &transport).unwrap();
+        let body = client
+            .get(format!("http://kiro.invalid:{}/", port))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(body, "ok");
+    }
+
+    #[test]
+    fn test_transport_options_from_config() {
+        let mut config = Config::default();
+        config.http2 = false;
+        config.pool_idle_timeout_secs = 0;
+        config.pool_max_idle_per_host = Some(0);
+        config.happy_eyeballs = false;
+
+        let transport = TransportOptions::from_config(&config);
+        assert!(!transport.http2);
+        assert_eq!(transport.pool_idle_timeout, None);
+        assert_eq!(transport.pool_max_idle_per_host, Some(0));
+        assert!(build_client(None, 30, &transport).is_ok());
+
+        let defaults = TransportOptions::default();
+        assert!(defaults.http2);
+        assert!(defaults.happy_eyeballs);
+        assert_eq!(defaults.pool_idle_timeout, Some(Duration::from_secs(90)));
+    }
+
     #[test]
     fn test_build_client_with_proxy() {
         let config = ProxyConfig::new("http://127.0.0.1:7890");
-        let client = build_client(Some(&config), 30, TlsBackend::Rustls);
+        let client = build_client(Some(&config), 30, &TransportOptions::default());
         assert!(client.is_ok());
     }
 }
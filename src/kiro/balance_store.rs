@@ -0,0 +1,375 @@
+//! 凭据余额缓存
+//!
+//! 缓存 getUsageLimits 的结果，由 MultiTokenManager 持有，
+//! Admin API 与 Token 管理器共用同一份数据、同一套 TTL 策略和同一个持久化文件，
+//! 避免重复请求上游以及不同入口显示的余额不一致。
+//!
+//! 缓存有效期按凭据状态分级（均可在 config.json 中配置）：
+//! - 余额低于阈值：额度在重置前基本不会变化，使用最长的 TTL
+//! - 近期被使用（高频）：余额变化快，使用较短的 TTL
+//! - 其他（低频）：使用中等 TTL
+//!
+//! 任何缓存都不会跨越额度重置时间（`nextResetAt`），重置后立即重新查询。
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::kiro::model::usage_limits::UsageLimitsResponse;
+use crate::model::config::Config;
+
+/// 余额缓存 TTL 策略
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceTtlPolicy {
+    /// 高频使用凭据的 TTL（秒）
+    pub high_freq_ttl_secs: u64,
+    /// 低频使用凭据的 TTL（秒）
+    pub low_freq_ttl_secs: u64,
+    /// 低余额凭据的 TTL（秒）
+    pub low_balance_ttl_secs: u64,
+    /// 剩余额度低于该值视为低余额
+    pub low_balance_threshold: f64,
+    /// 在该时间窗口（秒）内被使用过的凭据视为高频
+    pub high_freq_window_secs: u64,
+}
+
+impl Default for BalanceTtlPolicy {
+    fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
+
+impl BalanceTtlPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            high_freq_ttl_secs: config.balance_ttl_high_freq_secs,
+            low_freq_ttl_secs: config.balance_ttl_low_freq_secs,
+            low_balance_ttl_secs: config.balance_ttl_low_balance_secs,
+            low_balance_threshold: config.low_balance_threshold,
+            high_freq_window_secs: config.high_freq_window_secs,
+        }
+    }
+
+    /// 计算某条余额数据的 TTL（秒）
+    fn ttl_secs(&self, data: &BalanceSnapshot, recently_used: bool) -> u64 {
+        if data.usage_limit - data.current_usage <= self.low_balance_threshold {
+            self.low_balance_ttl_secs
+        } else if recently_used {
+            self.high_freq_ttl_secs
+        } else {
+            self.low_freq_ttl_secs
+        }
+    }
+
+    /// 所有分级中最长的 TTL（用于启动加载时的初步过滤）
+    fn max_ttl_secs(&self) -> u64 {
+        self.high_freq_ttl_secs
+            .max(self.low_freq_ttl_secs)
+            .max(self.low_balance_ttl_secs)
+    }
+}
+
+/// 余额快照
+///
+/// 字段与旧版 Admin 余额缓存兼容，升级后可直接读取原有的缓存文件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceSnapshot {
+    /// 订阅类型
+    pub subscription_title: Option<String>,
+    /// 当前使用量
+    pub current_usage: f64,
+    /// 使用限额
+    pub usage_limit: f64,
+    /// 下次重置时间（Unix 时间戳）
+    pub next_reset_at: Option<f64>,
+}
+
+impl From<&UsageLimitsResponse> for BalanceSnapshot {
+    fn from(usage: &UsageLimitsResponse) -> Self {
+        Self {
+            subscription_title: usage.subscription_title().map(|s| s.to_string()),
+            current_usage: usage.current_usage(),
+            usage_limit: usage.usage_limit(),
+            next_reset_at: usage.next_date_reset,
+        }
+    }
+}
+
+/// 缓存的余额条目（含时间戳）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedBalance {
+    /// 缓存时间（Unix 秒）
+    cached_at: f64,
+    /// 缓存的余额数据
+    data: BalanceSnapshot,
+}
+
+impl CachedBalance {
+    fn is_fresh(&self, now: f64, ttl_secs: u64) -> bool {
+        // 额度已重置：缓存的用量必然过时
+        if let Some(reset_at) = self.data.next_reset_at
+            && now >= reset_at
+        {
+            return false;
+        }
+        (now - self.cached_at) < ttl_secs as f64
+    }
+}
+
+/// 余额缓存存储
+pub struct BalanceStore {
+    entries: Mutex<HashMap<u64, CachedBalance>>,
+    policy: BalanceTtlPolicy,
+    /// 持久化文件路径（None 表示不持久化）
+    path: Option<PathBuf>,
+}
+
+impl BalanceStore {
+    /// 创建余额缓存，并从持久化文件加载未过期的条目
+    pub fn new(path: Option<PathBuf>, policy: BalanceTtlPolicy) -> Self {
+        let entries = Self::load_from(&path, &policy);
+        Self {
+            entries: Mutex::new(entries),
+            policy,
+            path,
+        }
+    }
+
+    /// 获取未过期的余额快照
+    ///
+    /// # Arguments
+    /// * `id` - 凭据 ID
+    /// * `last_used_secs_ago` - 凭据最近一次被使用距今的秒数（从未使用为 None）
+    pub fn get(&self, id: u64, last_used_secs_ago: Option<u64>) -> Option<BalanceSnapshot> {
+        let now = Utc::now().timestamp() as f64;
+        let recently_used =
+            last_used_secs_ago.is_some_and(|secs| secs < self.policy.high_freq_window_secs);
+        self.entries
+            .lock()
+            .get(&id)
+            .filter(|cached| {
+                cached.is_fresh(now, self.policy.ttl_secs(&cached.data, recently_used))
+            })
+            .map(|cached| cached.data.clone())
+    }
+
+    /// 获取最近一次已知的余额快照（不检查 TTL）
+    ///
+    /// 仅用于选择凭据等允许数据略微过时的场景；额度已重置的快照视为未知
+    pub fn latest(&self, id: u64) -> Option<BalanceSnapshot> {
+        let now = Utc::now().timestamp() as f64;
+        self.entries
+            .lock()
+            .get(&id)
+            .filter(|cached| {
+                cached
+                    .data
+                    .next_reset_at
+                    .is_none_or(|reset_at| now < reset_at)
+            })
+            .map(|cached| cached.data.clone())
+    }
+
+    /// 写入余额快照并持久化
+    pub fn insert(&self, id: u64, data: BalanceSnapshot) {
+        self.entries.lock().insert(
+            id,
+            CachedBalance {
+                cached_at: Utc::now().timestamp() as f64,
+                data,
+            },
+        );
+        self.save();
+    }
+
+    /// 移除指定凭据的缓存并持久化
+    pub fn remove(&self, id: u64) {
+        if self.entries.lock().remove(&id).is_some() {
+            self.save();
+        }
+    }
+
+    /// 只保留 `keep` 返回 true 的凭据的缓存，返回移除的条目数（有移除时持久化）
+    pub fn retain(&self, keep: impl Fn(u64) -> bool) -> usize {
+        let removed = {
+            let mut entries = self.entries.lock();
+            let before = entries.len();
+            entries.retain(|id, _| keep(*id));
+            before - entries.len()
+        };
+        if removed > 0 {
+            self.save();
+        }
+        removed
+    }
+
+    fn load_from(path: &Option<PathBuf>, policy: &BalanceTtlPolicy) -> HashMap<u64, CachedBalance> {
+        let path = match path {
+            Some(p) => p,
+            None => return HashMap::new(),
+        };
+
+        let content = match std::fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(_) => return HashMap::new(),
+        };
+
+        // 文件中使用字符串 key 以兼容 JSON 格式
+        let map: HashMap<String, CachedBalance> = match serde_json::from_str(&content) {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::warn!("解析余额缓存失败，将忽略: {}", e);
+                return HashMap::new();
+            }
+        };
+
+        let now = Utc::now().timestamp() as f64;
+        map.into_iter()
+            .filter_map(|(k, v)| {
+                let id = k.parse::<u64>().ok()?;
+                // 丢弃超过最长 TTL 的条目
+                v.is_fresh(now, policy.max_ttl_secs()).then_some((id, v))
+            })
+            .collect()
+    }
+
+    fn save(&self) {
+        let path = match &self.path {
+            Some(p) => p,
+            None => return,
+        };
+
+        // 持有锁期间完成序列化和写入，防止并发损坏
+        let entries = self.entries.lock();
+        let map: HashMap<String, &CachedBalance> =
+            entries.iter().map(|(k, v)| (k.to_string(), v)).collect();
+
+        match serde_json::to_string_pretty(&map) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(path, json) {
+                    tracing::warn!("保存余额缓存失败: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("序列化余额缓存失败: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(current_usage: f64) -> BalanceSnapshot {
+        BalanceSnapshot {
+            subscription_title: Some("KIRO PRO+".to_string()),
+            current_usage,
+            usage_limit: 1000.0,
+            next_reset_at: None,
+        }
+    }
+
+    #[test]
+    fn test_insert_persists_and_reloads() {
+        let path = std::env::temp_dir().join(format!("kiro-balance-{}.json", uuid::Uuid::new_v4()));
+
+        let store = BalanceStore::new(Some(path.clone()), BalanceTtlPolicy::default());
+        store.insert(1, snapshot(10.0));
+        store.insert(2, snapshot(20.0));
+        store.remove(2);
+
+        let reloaded = BalanceStore::new(Some(path.clone()), BalanceTtlPolicy::default());
+        assert_eq!(reloaded.get(1, None), Some(snapshot(10.0)));
+        assert_eq!(reloaded.get(2, None), None);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_loads_legacy_admin_cache_format() {
+        let path = std::env::temp_dir().join(format!("kiro-balance-{}.json", uuid::Uuid::new_v4()));
+        let now = Utc::now().timestamp() as f64;
+        let legacy = serde_json::json!({
+            "3": {
+                "cached_at": now,
+                "data": {
+                    "id": 3,
+                    "subscriptionTitle": "KIRO FREE",
+                    "currentUsage": 5.0,
+                    "usageLimit": 50.0,
+                    "remaining": 45.0,
+                    "usagePercentage": 10.0,
+                    "nextResetAt": null
+                }
+            },
+            "4": {
+                "cached_at": now - 3600.0,
+                "data": {
+                    "subscriptionTitle": null,
+                    "currentUsage": 0.0,
+                    "usageLimit": 0.0,
+                    "nextResetAt": null
+                }
+            }
+        });
+        std::fs::write(&path, legacy.to_string()).unwrap();
+
+        let policy = BalanceTtlPolicy {
+            high_freq_ttl_secs: 60,
+            low_freq_ttl_secs: 60,
+            low_balance_ttl_secs: 60,
+            ..BalanceTtlPolicy::default()
+        };
+        let store = BalanceStore::new(Some(path.clone()), policy);
+        let balance = store.get(3, None).expect("应加载旧格式缓存");
+        assert_eq!(balance.subscription_title.as_deref(), Some("KIRO FREE"));
+        assert_eq!(balance.usage_limit, 50.0);
+        // 过期条目在加载时被丢弃
+        assert!(store.get(4, None).is_none());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_ttl_tiers() {
+        let policy = BalanceTtlPolicy {
+            high_freq_ttl_secs: 10,
+            low_freq_ttl_secs: 100,
+            low_balance_ttl_secs: 1000,
+            low_balance_threshold: 5.0,
+            high_freq_window_secs: 60,
+        };
+
+        assert_eq!(policy.ttl_secs(&snapshot(10.0), true), 10);
+        assert_eq!(policy.ttl_secs(&snapshot(10.0), false), 100);
+        assert_eq!(policy.ttl_secs(&snapshot(996.0), true), 1000);
+    }
+
+    #[test]
+    fn test_cache_expires_at_reset_time() {
+        let store = BalanceStore::new(None, BalanceTtlPolicy::default());
+        let mut data = snapshot(1000.0);
+        data.next_reset_at = Some(Utc::now().timestamp() as f64 - 1.0);
+        store.insert(1, data);
+
+        // 低余额 TTL 很长，但已过重置时间，必须重新查询
+        assert!(store.get(1, None).is_none());
+    }
+
+    #[test]
+    fn test_recent_use_selects_high_freq_ttl() {
+        let policy = BalanceTtlPolicy {
+            high_freq_ttl_secs: 0,
+            low_freq_ttl_secs: 3600,
+            ..BalanceTtlPolicy::default()
+        };
+        let store = BalanceStore::new(None, policy);
+        store.insert(1, snapshot(10.0));
+
+        assert!(store.get(1, None).is_some());
+        assert!(store.get(1, Some(5)).is_none());
+    }
+}
@@ -0,0 +1,91 @@
+//! 会话级 token 预算
+//!
+//! 按 conversationId 累计每次请求的输入与输出 token。
+//! 超过 `conversationTokenBudget` 后，转换器对该会话后续请求的较早历史做摘要压缩
+//! （见 `anthropic::converter` 的 history 摘要），减少每次请求重复发送的上下文。
+//!
+//! 只保存在内存中：重启后重新累计。
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// 最多跟踪的会话数量
+const MAX_CONVERSATIONS: usize = 4096;
+
+/// 会话过期时间（2 小时无请求即过期）
+const CONVERSATION_TTL: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// 单个会话的累计用量
+struct ConversationUsage {
+    tokens: u64,
+    touched_at: Instant,
+}
+
+static USAGE: OnceLock<Mutex<HashMap<String, ConversationUsage>>> = OnceLock::new();
+
+fn usage() -> &'static Mutex<HashMap<String, ConversationUsage>> {
+    USAGE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// 累计会话的 token 用量，返回累计值
+pub fn record(conversation_id: &str, tokens: u64) -> u64 {
+    let mut usage = usage().lock();
+    let now = Instant::now();
+    if !usage.contains_key(conversation_id) && usage.len() >= MAX_CONVERSATIONS {
+        evict(&mut usage, now);
+    }
+    let entry = usage
+        .entry(conversation_id.to_string())
+        .or_insert(ConversationUsage {
+            tokens: 0,
+            touched_at: now,
+        });
+    if now.duration_since(entry.touched_at) >= CONVERSATION_TTL {
+        entry.tokens = 0;
+    }
+    entry.tokens = entry.tokens.saturating_add(tokens);
+    entry.touched_at = now;
+    entry.tokens
+}
+
+/// 会话的累计 token 用量（未记录或已过期时为 0）
+pub fn used(conversation_id: &str) -> u64 {
+    usage()
+        .lock()
+        .get(conversation_id)
+        .filter(|entry| entry.touched_at.elapsed() < CONVERSATION_TTL)
+        .map_or(0, |entry| entry.tokens)
+}
+
+/// 淘汰过期会话；若仍超出上限，再淘汰最久未使用的会话
+fn evict(usage: &mut HashMap<String, ConversationUsage>, now: Instant) {
+    usage.retain(|_, v| now.duration_since(v.touched_at) < CONVERSATION_TTL);
+
+    if usage.len() >= MAX_CONVERSATIONS
+        && let Some(oldest) = usage
+            .iter()
+            .min_by_key(|(_, v)| v.touched_at)
+            .map(|(k, _)| k.clone())
+    {
+        usage.remove(&oldest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_accumulates_per_conversation() {
+        let a = uuid::Uuid::new_v4().to_string();
+        let b = uuid::Uuid::new_v4().to_string();
+        assert_eq!(used(&a), 0);
+        assert_eq!(record(&a, 100), 100);
+        assert_eq!(record(&a, 50), 150);
+        assert_eq!(used(&a), 150);
+        assert_eq!(used(&b), 0);
+    }
+}
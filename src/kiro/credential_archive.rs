@@ -0,0 +1,203 @@
+//! 已删除凭据归档
+//!
+//! Admin 删除凭据时不直接丢弃，而是移入缓存目录下的归档文件，
+//! 在保留期（`archiveRetentionDays`）内可通过 Admin API 恢复，超过保留期后自动清除。
+//! 保留期为 0 时不归档，删除即永久删除。
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::kiro::model::credentials::KiroCredentials;
+
+/// 归档文件名（位于缓存目录）
+pub const ARCHIVE_FILE: &str = "kiro_credentials_archive.json";
+
+/// 单个已归档的凭据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedCredential {
+    /// 凭据内容（含删除时的 ID）
+    pub credentials: KiroCredentials,
+    /// 删除前所在的来源文件索引
+    pub source: usize,
+    /// 删除时间
+    pub deleted_at: DateTime<Utc>,
+}
+
+impl ArchivedCredential {
+    /// 凭据 ID
+    pub fn id(&self) -> u64 {
+        self.credentials.id.unwrap_or(0)
+    }
+}
+
+/// 已删除凭据的归档存储
+pub struct CredentialArchive {
+    path: Option<PathBuf>,
+    retention_days: u64,
+    entries: Mutex<Vec<ArchivedCredential>>,
+}
+
+impl CredentialArchive {
+    /// 创建归档并加载未过期的条目
+    ///
+    /// # Arguments
+    /// * `path` - 持久化文件路径（None 时仅保存在内存中）
+    /// * `retention_days` - 保留天数（0 表示不归档）
+    pub fn new(path: Option<PathBuf>, retention_days: u64) -> Self {
+        let archive = Self {
+            entries: Mutex::new(Self::load_from(&path)),
+            path,
+            retention_days,
+        };
+        let purged = archive.purge_expired();
+        if purged > 0 {
+            tracing::info!("已清除 {} 个超过保留期的已删除凭据", purged);
+        }
+        archive
+    }
+
+    /// 是否启用归档
+    pub fn is_enabled(&self) -> bool {
+        self.retention_days > 0
+    }
+
+    /// 归档条目的自动清除时间
+    pub fn purge_at(&self, entry: &ArchivedCredential) -> DateTime<Utc> {
+        entry.deleted_at + Duration::days(self.retention_days as i64)
+    }
+
+    /// 归档一个已删除的凭据（未启用归档时忽略）
+    pub fn archive(&self, source: usize, credentials: KiroCredentials) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut entries = self.entries.lock();
+        entries.retain(|e| e.credentials.id != credentials.id);
+        entries.push(ArchivedCredential {
+            credentials,
+            source,
+            deleted_at: Utc::now(),
+        });
+        self.save(&entries);
+    }
+
+    /// 列出所有未过期的归档条目（按删除时间倒序）
+    pub fn list(&self) -> Vec<ArchivedCredential> {
+        self.purge_expired();
+        let mut entries = self.entries.lock().clone();
+        entries.sort_by_key(|e| std::cmp::Reverse(e.deleted_at));
+        entries
+    }
+
+    /// 取出（并从归档中移除）指定 ID 的条目
+    pub fn take(&self, id: u64) -> Option<ArchivedCredential> {
+        let mut entries = self.entries.lock();
+        let index = entries.iter().position(|e| e.id() == id)?;
+        let entry = entries.remove(index);
+        self.save(&entries);
+        Some(entry)
+    }
+
+    /// 归档中最大的凭据 ID（分配新 ID 时避开，保证恢复时 ID 不冲突）
+    pub fn max_id(&self) -> Option<u64> {
+        self.entries.lock().iter().map(|e| e.id()).max()
+    }
+
+    /// 清除超过保留期的条目，返回清除数量
+    pub fn purge_expired(&self) -> usize {
+        let now = Utc::now();
+        let mut entries = self.entries.lock();
+        let before = entries.len();
+        entries.retain(|e| self.is_enabled() && self.purge_at(e) > now);
+        let purged = before - entries.len();
+        if purged > 0 {
+            self.save(&entries);
+        }
+        purged
+    }
+
+    fn load_from(path: &Option<PathBuf>) -> Vec<ArchivedCredential> {
+        let Some(path) = path else {
+            return Vec::new();
+        };
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return Vec::new();
+        };
+
+        match serde_json::from_str(&content) {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::warn!("解析已删除凭据归档失败，将忽略: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn save(&self, entries: &[ArchivedCredential]) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        match serde_json::to_string_pretty(entries) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(path, json) {
+                    tracing::warn!("保存已删除凭据归档失败: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("序列化已删除凭据归档失败: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials(id: u64) -> KiroCredentials {
+        KiroCredentials {
+            id: Some(id),
+            refresh_token: Some(format!("token-{}", id)),
+            ..KiroCredentials::default()
+        }
+    }
+
+    #[test]
+    fn test_archive_take_and_persist() {
+        let path = std::env::temp_dir().join(format!("kiro-archive-{}.json", uuid::Uuid::new_v4()));
+
+        let archive = CredentialArchive::new(Some(path.clone()), 30);
+        archive.archive(0, credentials(3));
+        archive.archive(1, credentials(5));
+        assert_eq!(archive.max_id(), Some(5));
+
+        let reloaded = CredentialArchive::new(Some(path.clone()), 30);
+        assert_eq!(reloaded.list().len(), 2);
+        let entry = reloaded.take(5).unwrap();
+        assert_eq!(entry.source, 1);
+        assert!(reloaded.take(5).is_none());
+
+        let reloaded = CredentialArchive::new(Some(path.clone()), 30);
+        assert_eq!(reloaded.list().len(), 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_purges_expired_and_disabled() {
+        let archive = CredentialArchive::new(None, 7);
+        archive.archive(0, credentials(1));
+        archive.entries.lock()[0].deleted_at -= Duration::days(8);
+        archive.archive(0, credentials(2));
+        assert_eq!(archive.purge_expired(), 1);
+        assert_eq!(archive.list()[0].id(), 2);
+
+        // 保留期为 0 时不归档
+        let disabled = CredentialArchive::new(None, 0);
+        disabled.archive(0, credentials(1));
+        assert!(disabled.list().is_empty());
+    }
+}
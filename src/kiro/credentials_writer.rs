@@ -0,0 +1,494 @@
+//! 凭据文件回写器
+//!
+//! 凭据回写可能同时由多处触发（Token 刷新、Admin 操作、订阅等级更新等）。
+//! 所有写入统一交给一个专用线程串行执行：
+//! - 每次回写请求携带单调递增的版本号，线程只落盘最新版本，突发的多次请求合并为一次写入
+//! - 先写临时文件再 rename，避免进程中断时留下半截文件
+//! - 调用方等待本次（或更新版本的）写入结果，保持原有的错误语义；
+//!   异步调用方通过 [`CredentialsWriter::write_async`] 等待，不会占用 Tokio worker
+//! - 记录每次回写后文件的修改时间；若落盘前发现文件已被其他程序修改，
+//!   拒绝覆盖，由调用方合并外部修改后再回写
+//!
+//! 多个实例共享同一个凭据文件时，Token 刷新会相互竞争：一方刷新后旧的 refreshToken 被作废，
+//! 另一方再用旧 Token 刷新就会失败。[`CredentialsWriter::lock_for_refresh`] 通过凭据文件旁的
+//! 锁文件（`.<文件名>.lock`）在进程间串行化"刷新 + 回写"。
+
+use std::fs::{File, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Context;
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+/// 单次回写请求
+struct WriteRequest {
+    /// 快照版本号（越大越新）
+    generation: u64,
+    /// 序列化后的凭据 JSON
+    json: String,
+    /// 写入结果回传
+    reply: oneshot::Sender<Result<(), String>>,
+}
+
+/// 等待进程间刷新锁时的轮询间隔
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 进程间刷新锁，Drop 时释放
+pub struct RefreshLock {
+    _file: File,
+}
+
+/// 外部修改检测结果（记录检测时文件的修改时间）
+#[derive(Debug, Clone, Copy)]
+pub struct ExternalChange {
+    mtime: Option<SystemTime>,
+}
+
+/// 凭据文件回写器
+pub struct CredentialsWriter {
+    tx: mpsc::Sender<WriteRequest>,
+    path: PathBuf,
+    /// 最近一次由本进程确认的文件修改时间（文件不存在为 None）
+    known_mtime: Arc<Mutex<Option<SystemTime>>>,
+    /// 是否已提示过其他进程在使用同一凭据文件（只提示一次）
+    shared_warned: AtomicBool,
+}
+
+impl CredentialsWriter {
+    /// 启动回写线程
+    ///
+    /// 以启动时文件的修改时间作为基准（调用方应已读取过该文件）
+    pub fn spawn(path: PathBuf) -> anyhow::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let known_mtime = Arc::new(Mutex::new(file_mtime(&path)));
+        std::thread::Builder::new()
+            .name("kiro-credentials-writer".to_string())
+            .spawn({
+                let path = path.clone();
+                let known_mtime = known_mtime.clone();
+                move || run(path, known_mtime, rx)
+            })
+            .context("启动凭据回写线程失败")?;
+        Ok(Self {
+            tx,
+            path,
+            known_mtime,
+            shared_warned: AtomicBool::new(false),
+        })
+    }
+
+    /// 检测文件是否在本进程最近一次读写之后被其他程序修改
+    pub fn check_external_change(&self) -> Option<ExternalChange> {
+        let mtime = file_mtime(&self.path);
+        (mtime != *self.known_mtime.lock()).then_some(ExternalChange { mtime })
+    }
+
+    /// 确认已读取并合并外部修改，之后的回写不再被拒绝
+    ///
+    /// 若确认前文件再次被修改，新的修改时间不会被确认，下次检测仍能发现
+    pub fn acknowledge(&self, change: ExternalChange) {
+        *self.known_mtime.lock() = change.mtime;
+    }
+
+    /// 获取进程间刷新锁，用于串行化多个实例对同一凭据文件的"刷新 + 回写"
+    ///
+    /// 锁被其他进程持有时轮询等待，超过 `timeout` 或锁文件无法创建时返回 None，
+    /// 调用方退化为不加锁刷新
+    pub async fn lock_for_refresh(&self, timeout: Duration) -> Option<RefreshLock> {
+        let lock_path = lock_path(&self.path);
+        let mut file = match std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+        {
+            Ok(file) => file,
+            Err(e) => {
+                tracing::debug!("无法创建刷新锁文件 {:?}，跳过进程间加锁: {}", lock_path, e);
+                return None;
+            }
+        };
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {
+                    if !self.shared_warned.swap(true, Ordering::Relaxed) {
+                        tracing::warn!(
+                            "凭据文件 {:?} 正被另一个进程（{}）使用，Token 刷新将在进程间串行执行。\
+                             多个实例共享同一凭据文件时，请确认这是预期行为；\
+                             否则请为每个实例使用独立的凭据文件",
+                            self.path,
+                            lock_holder(&mut file)
+                        );
+                    }
+                    if Instant::now() >= deadline {
+                        tracing::warn!(
+                            "等待凭据文件 {:?} 的刷新锁超时（{}秒），将不加锁刷新",
+                            self.path,
+                            timeout.as_secs()
+                        );
+                        return None;
+                    }
+                    tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+                }
+                Err(TryLockError::Error(e)) => {
+                    tracing::debug!("获取刷新锁 {:?} 失败，跳过进程间加锁: {}", lock_path, e);
+                    return None;
+                }
+            }
+        }
+
+        // 记录持有者 PID，便于其他进程提示
+        let _ = file
+            .set_len(0)
+            .and_then(|_| file.rewind())
+            .and_then(|_| write!(file, "{}", std::process::id()));
+        Some(RefreshLock { _file: file })
+    }
+
+    /// 提交一次回写并同步等待结果（用于非异步上下文）
+    ///
+    /// 若在排队期间已有更新版本的快照落盘，本次请求直接视为成功
+    pub fn write(&self, generation: u64, json: String) -> anyhow::Result<()> {
+        let reply_rx = self.submit(generation, json)?;
+
+        let wait = || futures::executor::block_on(reply_rx);
+        // 在 Tokio 多线程 runtime 内使用 block_in_place 避免阻塞 worker
+        let result = match tokio::runtime::Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(wait)
+            }
+            _ => wait(),
+        };
+        Self::into_result(result)
+    }
+
+    /// 提交一次回写并异步等待结果
+    pub async fn write_async(&self, generation: u64, json: String) -> anyhow::Result<()> {
+        let reply_rx = self.submit(generation, json)?;
+        Self::into_result(reply_rx.await)
+    }
+
+    fn submit(
+        &self,
+        generation: u64,
+        json: String,
+    ) -> anyhow::Result<oneshot::Receiver<Result<(), String>>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.tx
+            .send(WriteRequest {
+                generation,
+                json,
+                reply: reply_tx,
+            })
+            .map_err(|_| anyhow::anyhow!("凭据回写线程已退出"))?;
+        Ok(reply_rx)
+    }
+
+    fn into_result(
+        result: Result<Result<(), String>, oneshot::error::RecvError>,
+    ) -> anyhow::Result<()> {
+        match result {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => Err(anyhow::anyhow!(e)),
+            Err(_) => anyhow::bail!("凭据回写线程已退出"),
+        }
+    }
+}
+
+/// 回写线程主循环
+fn run(
+    path: PathBuf,
+    known_mtime: Arc<Mutex<Option<SystemTime>>>,
+    rx: mpsc::Receiver<WriteRequest>,
+) {
+    let mut written_generation = 0u64;
+
+    while let Ok(first) = rx.recv() {
+        // 合并排队中的所有请求，只写入版本最新的快照
+        let mut batch = vec![first];
+        batch.extend(rx.try_iter());
+
+        let latest = batch
+            .iter()
+            .max_by_key(|r| r.generation)
+            .expect("batch 不为空");
+
+        let result = if latest.generation > written_generation {
+            let mut known = known_mtime.lock();
+            if file_mtime(&path) != *known {
+                // 外部修改尚未合并：覆盖会丢失其他程序写入的内容
+                Err(format!(
+                    "凭据文件 {:?} 已被其他程序修改，拒绝覆盖（等待合并外部修改）",
+                    path
+                ))
+            } else {
+                match write_atomic(&path, &latest.json) {
+                    Ok(()) => {
+                        *known = file_mtime(&path);
+                        written_generation = latest.generation;
+                        if batch.len() > 1 {
+                            tracing::debug!("已合并 {} 次凭据回写请求", batch.len());
+                        }
+                        Ok(())
+                    }
+                    Err(e) => Err(format!("{:#}", e)),
+                }
+            }
+        } else {
+            // 更新版本已落盘
+            Ok(())
+        };
+
+        for request in batch {
+            let _ = request.reply.send(result.clone());
+        }
+    }
+}
+
+/// 凭据文件对应的刷新锁文件路径
+fn lock_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "credentials.json".to_string());
+    path.with_file_name(format!(".{}.lock", file_name))
+}
+
+/// 读取锁文件中记录的持有者 PID
+fn lock_holder(file: &mut File) -> String {
+    let mut pid = String::new();
+    match file.rewind().and_then(|_| file.read_to_string(&mut pid)) {
+        Ok(_) if !pid.trim().is_empty() => format!("pid {}", pid.trim()),
+        _ => "pid 未知".to_string(),
+    }
+}
+
+/// 读取文件修改时间（文件不存在或无法读取时为 None）
+fn file_mtime(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// 先写临时文件再 rename，保证文件内容完整
+///
+/// 凭据文件为符号链接时（常见于 Docker / secret 挂载）写入链接指向的真实文件，
+/// 不替换链接本身；临时文件沿用原文件的权限，避免包含 Token 的文件被放宽为所有人可读
+fn write_atomic(path: &Path, content: &str) -> anyhow::Result<()> {
+    let target = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let file_name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "credentials.json".to_string());
+    let tmp_path = target.with_file_name(format!(".{}.tmp", file_name));
+    let permissions = std::fs::metadata(&target).map(|m| m.permissions()).ok();
+
+    // 清理上次中断留下的临时文件，确保新建时的权限生效
+    let _ = std::fs::remove_file(&tmp_path);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let write_tmp = || -> std::io::Result<()> {
+        let mut file = options.open(&tmp_path)?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        file.write_all(content.as_bytes())?;
+        file.sync_all()
+    };
+    if let Err(e) = write_tmp() {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e).with_context(|| format!("回写凭据文件失败: {:?}", tmp_path));
+    }
+    std::fs::rename(&tmp_path, &target)
+        .with_context(|| format!("回写凭据文件失败: {:?}", target))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn temp_path() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kiro-writer-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("credentials.json")
+    }
+
+    #[test]
+    fn test_write_replaces_file_atomically() {
+        let path = temp_path();
+        let writer = CredentialsWriter::spawn(path.clone()).unwrap();
+
+        writer.write(1, "[1]".to_string()).unwrap();
+        writer.write(2, "[2]".to_string()).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[2]");
+        let leftovers: Vec<_> = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_preserves_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path();
+        std::fs::write(&path, "[]").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+        write_atomic(&path, "[1]").unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[1]");
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_follows_symlink() {
+        let path = temp_path();
+        let dir = path.parent().unwrap();
+        let real = dir.join("secrets").join("credentials.json");
+        std::fs::create_dir_all(real.parent().unwrap()).unwrap();
+        std::fs::write(&real, "[]").unwrap();
+        std::os::unix::fs::symlink(&real, &path).unwrap();
+
+        write_atomic(&path, "[1]").unwrap();
+        write_atomic(&path, "[2]").unwrap();
+
+        // 链接保持不变，内容写入链接指向的真实文件
+        assert!(
+            std::fs::symlink_metadata(&path)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(std::fs::read_to_string(&real).unwrap(), "[2]");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_stale_generation_does_not_overwrite_newer() {
+        let path = temp_path();
+        let writer = CredentialsWriter::spawn(path.clone()).unwrap();
+
+        writer.write(5, "[5]".to_string()).unwrap();
+        // 较旧的快照晚到：视为成功，但不覆盖文件
+        writer.write(3, "[3]".to_string()).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[5]");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_concurrent_writes_keep_latest() {
+        let path = temp_path();
+        let writer = Arc::new(CredentialsWriter::spawn(path.clone()).unwrap());
+
+        let handles: Vec<_> = (1..=16u64)
+            .map(|generation| {
+                let writer = writer.clone();
+                std::thread::spawn(move || writer.write(generation, format!("[{}]", generation)))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap().unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[16]");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_refuses_to_overwrite_external_modification() {
+        let path = temp_path();
+        std::fs::write(&path, "[0]").unwrap();
+        let writer = CredentialsWriter::spawn(path.clone()).unwrap();
+        writer.write(1, "[1]".to_string()).unwrap();
+        assert!(writer.check_external_change().is_none());
+
+        // 模拟其他程序改写文件（保证修改时间与上次回写不同）
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        std::fs::write(&path, "[\"external\"]").unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(10))
+            .unwrap();
+        drop(file);
+
+        let change = writer.check_external_change().expect("应检测到外部修改");
+        assert!(writer.write(2, "[2]".to_string()).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[\"external\"]");
+
+        // 确认合并后允许回写
+        writer.acknowledge(change);
+        writer.write(3, "[3]".to_string()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[3]");
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_lock_is_exclusive_across_handles() {
+        let path = temp_path();
+        let first = CredentialsWriter::spawn(path.clone()).unwrap();
+        let second = CredentialsWriter::spawn(path.clone()).unwrap();
+
+        let guard = first
+            .lock_for_refresh(Duration::from_secs(1))
+            .await
+            .expect("应获取到刷新锁");
+        // 另一个句柄（模拟另一个进程）在锁释放前等待超时
+        assert!(
+            second
+                .lock_for_refresh(Duration::from_millis(200))
+                .await
+                .is_none()
+        );
+
+        drop(guard);
+        assert!(
+            second
+                .lock_for_refresh(Duration::from_secs(1))
+                .await
+                .is_some()
+        );
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_write_async_and_sync_inside_current_thread_runtime() {
+        let path = temp_path();
+        let writer = CredentialsWriter::spawn(path.clone()).unwrap();
+
+        writer.write_async(1, "[1]".to_string()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[1]");
+
+        // 同步写入在单线程 runtime 中也不应 panic
+        writer.write(2, "[2]".to_string()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[2]");
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
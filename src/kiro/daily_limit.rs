@@ -0,0 +1,447 @@
+//! 每日请求上限
+//!
+//! 运营方可以配置全局每日请求上限（`dailyRequestLimit`）、每个凭据文件（凭据池）的
+//! 每日请求上限（`credentialsFiles[].dailyRequestLimit`）以及单个凭据的每日请求上限
+//! （凭据的 `dailyRequestLimit`）：
+//! - 全局上限达到后，新请求直接返回 overloaded_error，不再消耗上游额度
+//! - 凭据池或凭据的上限达到后，对应凭据当天不再被选用
+//!
+//! 计数按 `dailyResetTimezone` 指定时区的日期划分（默认本地时区，也可设为 `utc`
+//! 与上游对齐，或固定偏移如 `+08:00`），跨天自动清零；Admin API 可临时解除当天的限制。
+//! 计数随统计数据一起持久化（`kiro_daily_usage.json`），重启后不会清零。
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::{DateTime, Days, FixedOffset, Local, NaiveDate, NaiveTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// 全局每日请求上限已达到
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DailyLimitExceeded {
+    /// 每日上限
+    pub limit: u64,
+}
+
+impl std::fmt::Display for DailyLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "已达到每日请求上限（{}）", self.limit)
+    }
+}
+
+impl std::error::Error for DailyLimitExceeded {}
+
+/// 每日计数的清零时区
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DailyResetZone {
+    /// 服务器本地时区
+    Local,
+    /// 固定偏移（`utc` 即偏移为 0）
+    Fixed(FixedOffset),
+}
+
+impl DailyResetZone {
+    /// 解析配置值：`local`、`utc` 或固定偏移（如 `+08:00`、`-05:00`）
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("local") {
+            Some(Self::Local)
+        } else if value.eq_ignore_ascii_case("utc") {
+            Some(Self::Fixed(FixedOffset::east_opt(0)?))
+        } else {
+            value.parse().ok().map(Self::Fixed)
+        }
+    }
+
+    /// 指定时刻在该时区的日期
+    fn date_of(&self, now: DateTime<Utc>) -> NaiveDate {
+        match self {
+            Self::Local => now.with_timezone(&Local).date_naive(),
+            Self::Fixed(offset) => now.with_timezone(offset).date_naive(),
+        }
+    }
+
+    /// 指定时刻之后的下一次清零时间（该时区的次日零点）
+    pub fn next_reset(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let fallback = now + chrono::Duration::days(1);
+        let Some(midnight) = self
+            .date_of(now)
+            .checked_add_days(Days::new(1))
+            .map(|date| date.and_time(NaiveTime::MIN))
+        else {
+            return fallback;
+        };
+        let reset = match self {
+            Self::Local => midnight
+                .and_local_timezone(Local)
+                .earliest()
+                .map(|reset| reset.with_timezone(&Utc)),
+            Self::Fixed(offset) => midnight
+                .and_local_timezone(*offset)
+                .earliest()
+                .map(|reset| reset.with_timezone(&Utc)),
+        };
+        reset.unwrap_or(fallback)
+    }
+
+    fn today(&self) -> NaiveDate {
+        self.date_of(Utc::now())
+    }
+}
+
+/// 当天的计数状态（同时是持久化格式）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DailyState {
+    /// 计数所属日期
+    date: NaiveDate,
+    /// 全局请求数
+    requests: u64,
+    /// 各凭据池的请求数（下标对应凭据文件）
+    pool_requests: Vec<u64>,
+    /// 各凭据的请求数（凭据 ID -> 请求数）
+    #[serde(default)]
+    credential_requests: BTreeMap<u64, u64>,
+    /// 当天是否已由管理员解除限制
+    overridden: bool,
+}
+
+impl DailyState {
+    fn new(date: NaiveDate, pools: usize) -> Self {
+        Self {
+            date,
+            requests: 0,
+            pool_requests: vec![0; pools],
+            credential_requests: BTreeMap::new(),
+            overridden: false,
+        }
+    }
+
+    /// 跨天时清零
+    fn roll_over(&mut self, today: NaiveDate) {
+        if self.date != today {
+            *self = Self::new(today, self.pool_requests.len());
+        }
+    }
+}
+
+/// 每日限额状态快照（用于 Admin API）
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyLimitStatus {
+    /// 计数所属日期（YYYY-MM-DD）
+    pub date: String,
+    /// 全局请求数
+    pub requests: u64,
+    /// 全局每日上限（None 表示不限制）
+    pub limit: Option<u64>,
+    /// 各凭据池的 (请求数, 每日上限)
+    pub pools: Vec<(u64, Option<u64>)>,
+    /// 当天是否已由管理员解除限制
+    pub overridden: bool,
+    /// 下一次清零时间
+    pub reset_at: DateTime<Utc>,
+}
+
+/// 每日请求限额
+pub struct DailyLimiter {
+    /// 全局每日上限（None 表示不限制）
+    limit: Option<u64>,
+    /// 各凭据池的每日上限
+    pool_limits: Vec<Option<u64>>,
+    /// 计数清零时区
+    zone: DailyResetZone,
+    state: Mutex<DailyState>,
+    /// 持久化文件路径（None 表示不持久化）
+    path: Option<PathBuf>,
+    /// 自上次落盘后是否有更新
+    dirty: AtomicBool,
+}
+
+impl DailyLimiter {
+    /// 创建每日限额，并加载当天已持久化的计数
+    ///
+    /// # Arguments
+    /// * `limit` - 全局每日上限，0 表示不限制
+    /// * `pool_limits` - 各凭据池的每日上限，0 表示不限制
+    /// * `zone` - 计数清零时区
+    /// * `path` - 持久化文件路径
+    pub fn new(
+        limit: u64,
+        pool_limits: Vec<u64>,
+        zone: DailyResetZone,
+        path: Option<PathBuf>,
+    ) -> Self {
+        let today = zone.today();
+        let mut state = DailyState::new(today, pool_limits.len());
+        if let Some(saved) = path.as_deref().and_then(Self::load_from)
+            && saved.date == today
+        {
+            state.requests = saved.requests;
+            state.credential_requests = saved.credential_requests;
+            state.overridden = saved.overridden;
+            for (count, saved) in state.pool_requests.iter_mut().zip(saved.pool_requests) {
+                *count = saved;
+            }
+        }
+
+        Self {
+            limit: (limit > 0).then_some(limit),
+            pool_limits: pool_limits
+                .into_iter()
+                .map(|l| (l > 0).then_some(l))
+                .collect(),
+            zone,
+            state: Mutex::new(state),
+            path,
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// 占用一次全局请求额度，达到上限时返回错误
+    pub fn try_acquire(&self) -> Result<(), DailyLimitExceeded> {
+        let mut state = self.state.lock();
+        state.roll_over(self.zone.today());
+        if let Some(limit) = self.limit
+            && !state.overridden
+            && state.requests >= limit
+        {
+            return Err(DailyLimitExceeded { limit });
+        }
+        state.requests += 1;
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// 凭据池当天是否仍有额度
+    pub fn pool_available(&self, pool: usize) -> bool {
+        let Some(limit) = self.pool_limits.get(pool).copied().flatten() else {
+            return true;
+        };
+        let mut state = self.state.lock();
+        state.roll_over(self.zone.today());
+        state.overridden || state.pool_requests.get(pool).copied().unwrap_or(0) < limit
+    }
+
+    /// 凭据当天是否仍有额度
+    ///
+    /// # Arguments
+    /// * `id` - 凭据 ID
+    /// * `limit` - 凭据的每日上限（None 或 0 表示不限制）
+    pub fn credential_available(&self, id: u64, limit: Option<u64>) -> bool {
+        let Some(limit) = limit.filter(|l| *l > 0) else {
+            return true;
+        };
+        let mut state = self.state.lock();
+        state.roll_over(self.zone.today());
+        state.overridden || state.credential_requests.get(&id).copied().unwrap_or(0) < limit
+    }
+
+    /// 凭据当天的请求数
+    pub fn credential_requests(&self, id: u64) -> u64 {
+        let mut state = self.state.lock();
+        state.roll_over(self.zone.today());
+        state.credential_requests.get(&id).copied().unwrap_or(0)
+    }
+
+    /// 记录凭据（及其所在凭据池）的一次上游请求
+    pub fn record(&self, pool: usize, id: u64) {
+        let mut state = self.state.lock();
+        state.roll_over(self.zone.today());
+        if let Some(count) = state.pool_requests.get_mut(pool) {
+            *count += 1;
+        }
+        *state.credential_requests.entry(id).or_default() += 1;
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// 只保留 `keep` 返回 true 的凭据的当天计数，返回移除的条目数
+    pub fn retain_credentials(&self, keep: impl Fn(u64) -> bool) -> usize {
+        let mut state = self.state.lock();
+        let before = state.credential_requests.len();
+        state.credential_requests.retain(|id, _| keep(*id));
+        let removed = before - state.credential_requests.len();
+        if removed > 0 {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        removed
+    }
+
+    /// 解除（或恢复）当天的限制，次日自动恢复
+    pub fn set_overridden(&self, overridden: bool) {
+        let mut state = self.state.lock();
+        state.roll_over(self.zone.today());
+        state.overridden = overridden;
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// 获取当前状态
+    pub fn status(&self) -> DailyLimitStatus {
+        let mut state = self.state.lock();
+        state.roll_over(self.zone.today());
+        DailyLimitStatus {
+            date: state.date.to_string(),
+            requests: state.requests,
+            limit: self.limit,
+            pools: state
+                .pool_requests
+                .iter()
+                .copied()
+                .zip(self.pool_limits.iter().copied())
+                .collect(),
+            overridden: state.overridden,
+            reset_at: self.next_reset(),
+        }
+    }
+
+    /// 下一次清零时间
+    pub fn next_reset(&self) -> DateTime<Utc> {
+        self.zone.next_reset(Utc::now())
+    }
+
+    /// 将计数写入持久化文件（仅在有更新时）
+    pub fn flush(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+
+        let state = self.state.lock().clone();
+        match serde_json::to_string_pretty(&state) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(path, json) {
+                    tracing::warn!("保存每日请求计数失败: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("序列化每日请求计数失败: {}", e),
+        }
+    }
+
+    fn load_from(path: &Path) -> Option<DailyState> {
+        let content = std::fs::read_to_string(path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(state) => Some(state),
+            Err(e) => {
+                tracing::warn!("解析每日请求计数失败，将忽略: {}", e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_global_limit_and_override() {
+        let limiter = DailyLimiter::new(2, vec![], DailyResetZone::Local, None);
+        assert!(limiter.try_acquire().is_ok());
+        assert!(limiter.try_acquire().is_ok());
+        assert_eq!(limiter.try_acquire(), Err(DailyLimitExceeded { limit: 2 }));
+
+        limiter.set_overridden(true);
+        assert!(limiter.try_acquire().is_ok());
+        assert_eq!(limiter.status().requests, 3);
+    }
+
+    #[test]
+    fn test_zero_means_unlimited() {
+        let limiter = DailyLimiter::new(0, vec![0], DailyResetZone::Local, None);
+        for _ in 0..100 {
+            assert!(limiter.try_acquire().is_ok());
+            limiter.record(0, 1);
+        }
+        assert!(limiter.pool_available(0));
+    }
+
+    #[test]
+    fn test_pool_limit() {
+        let limiter = DailyLimiter::new(0, vec![1, 0], DailyResetZone::Local, None);
+        assert!(limiter.pool_available(0));
+        limiter.record(0, 1);
+        assert!(!limiter.pool_available(0));
+        assert!(limiter.pool_available(1));
+
+        let status = limiter.status();
+        assert_eq!(status.pools, vec![(1, Some(1)), (0, None)]);
+    }
+
+    #[test]
+    fn test_counts_persist_within_same_day() {
+        let path = std::env::temp_dir().join(format!("kiro-daily-{}.json", uuid::Uuid::new_v4()));
+        let limiter = DailyLimiter::new(5, vec![3], DailyResetZone::Local, Some(path.clone()));
+        limiter.try_acquire().unwrap();
+        limiter.record(0, 1);
+        limiter.flush();
+
+        let reloaded = DailyLimiter::new(5, vec![3], DailyResetZone::Local, Some(path.clone()));
+        let status = reloaded.status();
+        assert_eq!(status.requests, 1);
+        assert_eq!(status.pools, vec![(1, Some(3))]);
+        assert_eq!(reloaded.credential_requests(1), 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_credential_limit() {
+        let limiter = DailyLimiter::new(0, vec![0], DailyResetZone::Local, None);
+        limiter.record(0, 1);
+        limiter.record(0, 1);
+        assert!(!limiter.credential_available(1, Some(2)));
+        assert!(limiter.credential_available(1, Some(3)));
+        assert!(limiter.credential_available(1, None));
+        assert!(limiter.credential_available(2, Some(1)));
+        assert_eq!(limiter.credential_requests(1), 2);
+
+        limiter.set_overridden(true);
+        assert!(limiter.credential_available(1, Some(2)));
+    }
+
+    #[test]
+    fn test_reset_zone() {
+        assert_eq!(DailyResetZone::parse("Local"), Some(DailyResetZone::Local));
+        assert_eq!(
+            DailyResetZone::parse("utc"),
+            Some(DailyResetZone::Fixed(FixedOffset::east_opt(0).unwrap()))
+        );
+        let east8 = DailyResetZone::parse("+08:00").unwrap();
+        assert_eq!(
+            east8,
+            DailyResetZone::Fixed(FixedOffset::east_opt(8 * 3600).unwrap())
+        );
+        assert_eq!(DailyResetZone::parse("Asia/Shanghai"), None);
+
+        // UTC 20:00 在东八区已是次日 04:00，下一次清零为东八区再下一天零点（UTC 16:00）
+        let now = "2024-01-01T20:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(
+            east8.date_of(now),
+            NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()
+        );
+        assert_eq!(
+            east8.next_reset(now),
+            "2024-01-02T16:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert_eq!(
+            DailyResetZone::parse("utc").unwrap().next_reset(now),
+            "2024-01-02T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_roll_over_resets_counts() {
+        let mut state = DailyState::new(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(), 2);
+        state.requests = 10;
+        state.pool_requests = vec![4, 5];
+        state.credential_requests.insert(1, 3);
+        state.overridden = true;
+
+        let next_day = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        state.roll_over(next_day);
+        assert_eq!(state, DailyState::new(next_day, 2));
+    }
+}
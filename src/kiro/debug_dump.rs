@@ -0,0 +1,380 @@
+//! 上游错误调试转储
+//!
+//! 上游返回 400 或 5xx 时，将结构化的现场信息写入 `debugDumpDir`：
+//! Kiro 请求体、客户端请求头（敏感头已脱敏）、上游响应与请求转换统计。
+//! 响应流中出现未知事件类型时，同样写入该事件的原始 payload（每种类型每个进程只写一次）。
+//! 每次写入后按保留时长（`debugDumpRetentionHours`）与目录总大小（`debugDumpMaxBytes`）轮转，
+//! 超出时从最旧的文件开始删除。转储可通过 Admin API 列出与下载。
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Utc};
+use http::HeaderMap;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::model::config::Config;
+
+/// 转储文件名前缀
+const FILE_PREFIX: &str = "dump-";
+/// 转储文件扩展名
+const FILE_EXTENSION: &str = ".json";
+
+/// 未知事件转储中 payload 的最大字节数
+const MAX_EVENT_PAYLOAD_BYTES: usize = 64 * 1024;
+
+/// 写入转储时替换为 `<redacted>` 的请求头
+const REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "x-kiro-admin-key",
+    "cookie",
+];
+
+/// 请求转换统计
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionStats {
+    /// 转换耗时（毫秒）
+    pub elapsed_ms: f64,
+    /// 转换应用的修正（`Fixup::code`）
+    pub fixups: Vec<String>,
+    /// 工具压缩各阶段统计
+    pub compression_passes: Vec<CompressionPassStats>,
+}
+
+/// 单个工具压缩阶段的统计
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressionPassStats {
+    pub name: String,
+    pub elapsed_ms: f64,
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+}
+
+/// 一次上游错误的现场信息
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamDump {
+    pub created_at: DateTime<Utc>,
+    /// 上游 HTTP 状态码
+    pub status: u16,
+    /// 客户端请求头（敏感头已脱敏）
+    pub request_headers: BTreeMap<String, String>,
+    /// 发送给上游的 Kiro 请求体（非 JSON 时为原始字符串）
+    pub request: Value,
+    /// 上游响应体
+    pub response: String,
+    pub conversion: ConversionStats,
+}
+
+impl UpstreamDump {
+    pub fn new(
+        status: u16,
+        headers: &HeaderMap,
+        request_body: &str,
+        response: &str,
+        conversion: ConversionStats,
+    ) -> Self {
+        Self {
+            created_at: Utc::now(),
+            status,
+            request_headers: redact_headers(headers),
+            request: serde_json::from_str(request_body)
+                .unwrap_or_else(|_| Value::String(request_body.to_string())),
+            response: response.to_string(),
+            conversion,
+        }
+    }
+}
+
+/// 响应流中的未知事件
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnknownEventDump {
+    pub created_at: DateTime<Utc>,
+    /// 事件类型（`:event-type` 头）
+    pub event_type: String,
+    /// 原始 payload（非 JSON 或超过大小上限时为截断后的字符串）
+    pub payload: Value,
+    /// 原始 payload 字节数
+    pub payload_bytes: usize,
+}
+
+impl UnknownEventDump {
+    pub fn new(event_type: &str, payload: &[u8]) -> Self {
+        let value = if payload.len() <= MAX_EVENT_PAYLOAD_BYTES {
+            serde_json::from_slice(payload).ok()
+        } else {
+            None
+        };
+        let payload_value = value.unwrap_or_else(|| {
+            let end = payload.len().min(MAX_EVENT_PAYLOAD_BYTES);
+            Value::String(String::from_utf8_lossy(&payload[..end]).into_owned())
+        });
+        Self {
+            created_at: Utc::now(),
+            event_type: event_type.to_string(),
+            payload: payload_value,
+            payload_bytes: payload.len(),
+        }
+    }
+}
+
+/// 转储文件信息（Admin API）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpInfo {
+    pub name: String,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 调试转储目录
+pub struct DumpStore {
+    dir: PathBuf,
+    max_bytes: u64,
+    retention: Duration,
+}
+
+impl DumpStore {
+    /// 按配置创建（未配置 `debugDumpDir` 时返回 None）
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let dir = config.debug_dump_dir.as_ref()?;
+        Some(Self {
+            dir: PathBuf::from(dir),
+            max_bytes: config.debug_dump_max_bytes,
+            retention: Duration::from_secs(config.debug_dump_retention_hours * 3600),
+        })
+    }
+
+    /// 写入转储并轮转，返回文件路径
+    pub fn write(&self, dump: &UpstreamDump) -> std::io::Result<PathBuf> {
+        self.write_json(dump.created_at, &dump.status.to_string(), dump)
+    }
+
+    /// 写入未知事件转储并轮转，返回文件路径
+    pub fn write_unknown_event(&self, dump: &UnknownEventDump) -> std::io::Result<PathBuf> {
+        self.write_json(dump.created_at, "event", dump)
+    }
+
+    fn write_json<T: Serialize>(
+        &self,
+        created_at: DateTime<Utc>,
+        label: &str,
+        dump: &T,
+    ) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(&self.dir)?;
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let path = self.dir.join(format!(
+            "{}{}-{}-{}{}",
+            FILE_PREFIX,
+            created_at.format("%Y%m%dT%H%M%S"),
+            label,
+            &id[..8],
+            FILE_EXTENSION
+        ));
+        let json = serde_json::to_vec_pretty(dump).map_err(std::io::Error::other)?;
+        std::fs::write(&path, json)?;
+        self.rotate();
+        Ok(path)
+    }
+
+    /// 列出转储文件（最新的在前）
+    pub fn list(&self) -> Vec<DumpInfo> {
+        let mut dumps: Vec<(DumpInfo, SystemTime)> = self
+            .entries()
+            .into_iter()
+            .map(|(name, size, modified)| {
+                let info = DumpInfo {
+                    name,
+                    size,
+                    created_at: modified.into(),
+                };
+                (info, modified)
+            })
+            .collect();
+        dumps.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.0.name.cmp(&a.0.name)));
+        dumps.into_iter().map(|(info, _)| info).collect()
+    }
+
+    /// 读取转储文件内容（名称不合法或不存在时返回 None）
+    pub fn read(&self, name: &str) -> Option<Vec<u8>> {
+        if !is_dump_name(name) || name.contains(['/', '\\']) || name.contains("..") {
+            return None;
+        }
+        std::fs::read(self.dir.join(name)).ok()
+    }
+
+    /// 删除超过保留时长的转储，再从最旧的开始删除直到总大小不超过上限（始终保留最新的一个）
+    fn rotate(&self) {
+        let now = SystemTime::now();
+        let mut entries = self.entries();
+        entries.sort_by(|a, b| a.2.cmp(&b.2).then_with(|| a.0.cmp(&b.0)));
+
+        let newest = entries.len().saturating_sub(1);
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        for (i, (name, size, modified)) in entries.into_iter().enumerate() {
+            let expired = !self.retention.is_zero()
+                && now.duration_since(modified).unwrap_or_default() > self.retention;
+            let oversized = i < newest && self.max_bytes > 0 && total > self.max_bytes;
+            if !expired && !oversized {
+                continue;
+            }
+            match std::fs::remove_file(self.dir.join(&name)) {
+                Ok(()) => {
+                    total = total.saturating_sub(size);
+                    tracing::debug!("已轮转调试转储 {}", name);
+                }
+                Err(e) => tracing::warn!("删除调试转储 {} 失败: {}", name, e),
+            }
+        }
+    }
+
+    /// 目录中的转储文件：(名称, 大小, 修改时间)
+    fn entries(&self) -> Vec<(String, u64, SystemTime)> {
+        let Ok(read_dir) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        read_dir
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                if !is_dump_name(&name) {
+                    return None;
+                }
+                let metadata = entry.metadata().ok()?;
+                if !metadata.is_file() {
+                    return None;
+                }
+                Some((name, metadata.len(), metadata.modified().ok()?))
+            })
+            .collect()
+    }
+}
+
+fn is_dump_name(name: &str) -> bool {
+    name.starts_with(FILE_PREFIX) && name.ends_with(FILE_EXTENSION)
+}
+
+fn redact_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+                "<redacted>".to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            (name.as_str().to_string(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(max_bytes: u64) -> DumpStore {
+        DumpStore {
+            dir: std::env::temp_dir().join(format!("kiro-dumps-{}", uuid::Uuid::new_v4())),
+            max_bytes,
+            retention: Duration::from_secs(3600),
+        }
+    }
+
+    fn dump(status: u16) -> UpstreamDump {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", "sk-secret".parse().unwrap());
+        headers.insert("anthropic-version", "2023-06-01".parse().unwrap());
+        UpstreamDump::new(
+            status,
+            &headers,
+            r#"{"conversationState":{}}"#,
+            "Improperly formed request",
+            ConversionStats::default(),
+        )
+    }
+
+    #[test]
+    fn test_write_list_and_read() {
+        let store = store(0);
+        let path = store.write(&dump(400)).unwrap();
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+
+        let list = store.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].name, name);
+
+        let content = String::from_utf8(store.read(&name).unwrap()).unwrap();
+        let value: Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(value["status"], 400);
+        assert_eq!(value["requestHeaders"]["x-api-key"], "<redacted>");
+        assert_eq!(value["requestHeaders"]["anthropic-version"], "2023-06-01");
+        assert!(value["request"]["conversationState"].is_object());
+
+        // 只允许读取目录中的转储文件
+        assert!(store.read("../etc/passwd").is_none());
+        assert!(store.read("dump-../../x.json").is_none());
+        std::fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_unknown_event() {
+        let store = store(0);
+        let path = store
+            .write_unknown_event(&UnknownEventDump::new(
+                "citationEvent",
+                br#"{"citations":[{"url":"https://example.com"}]}"#,
+            ))
+            .unwrap();
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        assert!(name.contains("-event-"));
+
+        let value: Value = serde_json::from_slice(&store.read(&name).unwrap()).unwrap();
+        assert_eq!(value["eventType"], "citationEvent");
+        assert_eq!(
+            value["payload"]["citations"][0]["url"],
+            "https://example.com"
+        );
+
+        // 超过大小上限的 payload 截断为字符串
+        let large = vec![b'a'; MAX_EVENT_PAYLOAD_BYTES + 1];
+        let dump = UnknownEventDump::new("citationEvent", &large);
+        assert_eq!(
+            dump.payload.as_str().unwrap().len(),
+            MAX_EVENT_PAYLOAD_BYTES
+        );
+        assert_eq!(dump.payload_bytes, MAX_EVENT_PAYLOAD_BYTES + 1);
+        std::fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_by_size() {
+        let store = store(1);
+        store.write(&dump(400)).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        let newest = store.write(&dump(500)).unwrap();
+
+        // 超过总大小上限时从最旧的开始删除，保留最新的一个
+        let list = store.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(
+            newest.file_name().unwrap().to_str(),
+            Some(list[0].name.as_str())
+        );
+
+        let store = DumpStore {
+            max_bytes: 1024 * 1024,
+            ..store
+        };
+        store.write(&dump(400)).unwrap();
+        store.write(&dump(500)).unwrap();
+        assert_eq!(store.list().len(), 3);
+        std::fs::remove_dir_all(&store.dir).unwrap();
+    }
+}
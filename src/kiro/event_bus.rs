@@ -0,0 +1,194 @@
+//! 内部事件总线
+//!
+//! MultiTokenManager 在凭据状态变化（禁用/启用、熔断、额度冷却、余额更新等）与请求结果
+//! （Provider 上报的成功/失败、额度用尽，后台刷新的 Token）时向事件总线广播事件。
+//! 管理面板推送（`GET /api/admin/events`）、审计日志等功能作为订阅者接入，不再侵入核心逻辑。
+//! 没有订阅者时发送事件没有开销；订阅者处理过慢时丢弃最旧的事件。
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use crate::kiro::token_manager::FailureKind;
+
+/// 事件总线容量（每个订阅者最多积压的事件数）
+const EVENT_CAPACITY: usize = 256;
+
+/// 审计日志的 tracing 目标（可在 RUST_LOG 中单独调整级别，如 `kiro_rs::audit=debug`）
+const AUDIT_TARGET: &str = "kiro_rs::audit";
+
+/// 事件
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BusEvent {
+    /// 凭据被手动禁用
+    #[serde(rename_all = "camelCase")]
+    CredentialDisabled { id: u64 },
+    /// 凭据被重新启用（手动启用或重置）
+    #[serde(rename_all = "camelCase")]
+    CredentialEnabled { id: u64 },
+    /// 连续失败达到阈值，凭据被熔断禁用
+    #[serde(rename_all = "camelCase")]
+    CircuitBreakerTripped { id: u64, failure_count: u32 },
+    /// 额度用尽，凭据进入冷却（`until` 为额度重置时间，未知时为空）
+    #[serde(rename_all = "camelCase")]
+    CooldownSet {
+        id: u64,
+        until: Option<DateTime<Utc>>,
+    },
+    /// 额度冷却被解除
+    #[serde(rename_all = "camelCase")]
+    CooldownCleared { id: u64 },
+    /// 凭据余额已更新
+    #[serde(rename_all = "camelCase")]
+    BalanceUpdated {
+        id: u64,
+        current_usage: f64,
+        usage_limit: f64,
+    },
+    /// 新增凭据（Admin API、恢复归档或凭据文件被外部修改）
+    #[serde(rename_all = "camelCase")]
+    CredentialAdded { id: u64 },
+    /// 凭据被删除（Admin API 或凭据文件被外部修改）
+    #[serde(rename_all = "camelCase")]
+    CredentialDeleted { id: u64 },
+    /// 凭据的 Token 已刷新
+    #[serde(rename_all = "camelCase")]
+    TokenRefreshed { id: u64 },
+    /// 上游请求成功
+    #[serde(rename_all = "camelCase")]
+    RequestSucceeded { id: u64 },
+    /// 上游请求失败
+    #[serde(rename_all = "camelCase")]
+    RequestFailed { id: u64, kind: FailureKind },
+    /// 上游返回额度用尽
+    #[serde(rename_all = "camelCase")]
+    QuotaExhausted { id: u64 },
+    /// refreshToken 即将过期（`expires_at` 为按签发时间估算的过期时间），需要重新登录
+    #[serde(rename_all = "camelCase")]
+    RefreshTokenExpiring { id: u64, expires_at: DateTime<Utc> },
+}
+
+impl BusEvent {
+    /// 事件名（与序列化后的 `type` 一致）
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CredentialDisabled { .. } => "credential_disabled",
+            Self::CredentialEnabled { .. } => "credential_enabled",
+            Self::CircuitBreakerTripped { .. } => "circuit_breaker_tripped",
+            Self::CooldownSet { .. } => "cooldown_set",
+            Self::CooldownCleared { .. } => "cooldown_cleared",
+            Self::BalanceUpdated { .. } => "balance_updated",
+            Self::CredentialAdded { .. } => "credential_added",
+            Self::CredentialDeleted { .. } => "credential_deleted",
+            Self::TokenRefreshed { .. } => "token_refreshed",
+            Self::RequestSucceeded { .. } => "request_succeeded",
+            Self::RequestFailed { .. } => "request_failed",
+            Self::QuotaExhausted { .. } => "quota_exhausted",
+            Self::RefreshTokenExpiring { .. } => "refresh_token_expiring",
+        }
+    }
+
+    /// 是否为凭据状态变化（请求级事件频率高，不推送给管理面板）
+    pub fn is_state_change(&self) -> bool {
+        !matches!(
+            self,
+            Self::RequestSucceeded { .. }
+                | Self::RequestFailed { .. }
+                | Self::QuotaExhausted { .. }
+        )
+    }
+}
+
+/// 事件总线
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<BusEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+}
+
+impl EventBus {
+    /// 广播事件（没有订阅者时直接丢弃）
+    pub fn publish(&self, event: BusEvent) {
+        if self.sender.receiver_count() > 0 {
+            let _ = self.sender.send(event);
+        }
+    }
+
+    /// 订阅之后发生的事件
+    pub fn subscribe(&self) -> broadcast::Receiver<BusEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// 审计日志订阅者：凭据状态变化记录为 info，请求级事件记录为 debug（作为后台任务运行，取消后退出）
+pub async fn run_audit_logger(
+    mut receiver: broadcast::Receiver<BusEvent>,
+    cancel: CancellationToken,
+) {
+    loop {
+        let received = tokio::select! {
+            _ = cancel.cancelled() => break,
+            received = receiver.recv() => received,
+        };
+        match received {
+            Ok(event) => {
+                let data = serde_json::to_string(&event).unwrap_or_default();
+                if event.is_state_change() {
+                    tracing::info!(target: AUDIT_TARGET, event = event.name(), "{}", data);
+                } else {
+                    tracing::debug!(target: AUDIT_TARGET, event = event.name(), "{}", data);
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!(target: AUDIT_TARGET, "审计日志丢失 {} 个事件", skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_publish_and_subscribe() {
+        let bus = EventBus::default();
+        // 没有订阅者时不报错
+        bus.publish(BusEvent::CredentialAdded { id: 1 });
+
+        let mut receiver = bus.subscribe();
+        bus.publish(BusEvent::CircuitBreakerTripped {
+            id: 2,
+            failure_count: 3,
+        });
+        bus.publish(BusEvent::RequestFailed {
+            id: 2,
+            kind: FailureKind::Throttled,
+        });
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.name(), "circuit_breaker_tripped");
+        assert!(event.is_state_change());
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"type": "circuit_breaker_tripped", "id": 2, "failureCount": 3})
+        );
+
+        let event = receiver.recv().await.unwrap();
+        assert!(!event.is_state_change());
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"type": "request_failed", "id": 2, "kind": "throttled"})
+        );
+    }
+}
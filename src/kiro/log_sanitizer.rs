@@ -0,0 +1,127 @@
+//! 请求体日志脱敏
+//!
+//! 上游拒绝请求时需要记录请求体以便排查，但请求体可能包含大段 base64 图片和用户隐私：
+//! - 图片数据（`bytes` / `data` 字段）替换为长度说明
+//! - 用户内容（`content` / `text` / `input` 字段）替换为 SHA-256 摘要，相同内容可对照
+//! - 结果按 `logBodyMaxBytes` 截断
+//!
+//! 完整请求体见 [`crate::kiro::debug_dump`]（需配置 `debugDumpDir`）。
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// 视为图片数据的字段名
+const IMAGE_KEYS: &[&str] = &["bytes", "data"];
+/// 视为用户内容的字段名
+const CONTENT_KEYS: &[&str] = &["content", "text", "input"];
+
+/// 生成可写入日志的脱敏请求体
+pub fn sanitize_body(body: &str, max_bytes: usize) -> String {
+    let sanitized = match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            sanitize_value(&mut value);
+            value.to_string()
+        }
+        Err(_) => format!("<非 JSON 请求体 {}>", digest(body)),
+    };
+    truncate(sanitized, max_bytes)
+}
+
+fn sanitize_value(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                if IMAGE_KEYS.contains(&key.as_str()) && child.is_string() {
+                    let len = child.as_str().map_or(0, str::len);
+                    *child = Value::String(format!("<图片数据 {} 字节已移除>", len));
+                } else if CONTENT_KEYS.contains(&key.as_str()) && !is_container_of_objects(child) {
+                    let raw = match &*child {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    *child = Value::String(digest(&raw));
+                } else {
+                    sanitize_value(child);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(sanitize_value),
+        _ => {}
+    }
+}
+
+/// 内容块数组（如 `[{"text": ...}, {"image": ...}]`）继续向下脱敏，保留结构便于排查
+fn is_container_of_objects(value: &Value) -> bool {
+    value
+        .as_array()
+        .is_some_and(|items| items.iter().all(Value::is_object))
+}
+
+/// 内容摘要：`sha256:<前 12 位>（N 字符）`
+fn digest(content: &str) -> String {
+    let hash = hex::encode(Sha256::digest(content.as_bytes()));
+    format!("sha256:{}（{} 字符）", &hash[..12], content.chars().count())
+}
+
+fn truncate(mut s: String, max_bytes: usize) -> String {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let total = s.len();
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s.truncate(end);
+    s.push_str(&format!("...（已截断，共 {} 字节）", total));
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_strips_images_and_hashes_content() {
+        let body = serde_json::json!({
+            "conversationState": {
+                "currentMessage": {
+                    "userInputMessage": {
+                        "content": "my password is hunter2",
+                        "modelId": "claude-sonnet-4",
+                        "images": [{"format": "png", "source": {"bytes": "iVBORw0KGgo".repeat(1000)}}],
+                        "userInputMessageContext": {
+                            "toolResults": [{
+                                "toolUseId": "toolu_1",
+                                "content": [{"text": "secret output"}],
+                                "status": "success"
+                            }]
+                        }
+                    }
+                }
+            }
+        })
+        .to_string();
+
+        let sanitized = sanitize_body(&body, 4096);
+        assert!(!sanitized.contains("hunter2"));
+        assert!(!sanitized.contains("secret output"));
+        assert!(!sanitized.contains("iVBORw0KGgo"));
+        assert!(sanitized.contains("<图片数据 11000 字节已移除>"));
+        assert!(sanitized.contains("claude-sonnet-4"));
+        assert!(sanitized.contains("toolu_1"));
+        assert!(sanitized.contains(&digest("my password is hunter2")));
+    }
+
+    #[test]
+    fn test_sanitize_truncates_and_handles_non_json() {
+        let body = serde_json::json!({ "modelId": "中".repeat(100) }).to_string();
+        let sanitized = sanitize_body(&body, 20);
+        assert!(sanitized.contains("已截断"));
+        assert!(sanitized.len() < body.len());
+
+        let sanitized = sanitize_body("not json secret", 4096);
+        assert!(!sanitized.contains("secret"));
+        assert!(sanitized.starts_with("<非 JSON 请求体 sha256:"));
+    }
+}
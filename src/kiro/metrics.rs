@@ -0,0 +1,924 @@
+//! 累计运行指标
+//!
+//! 记录进程生命周期内的累计请求数、失败数与 token 用量，
+//! 并由 MultiTokenManager 随统计数据一起定期快照到缓存目录（`kiro_metrics.json`），
+//! 启动时重新加载，使长期统计不因重启而清零。
+//!
+//! token 用量同时按调用方 API Key 分别累计（见 [`UsageRecorder`]），用于内部分摊费用。
+//!
+//! 请求转换耗时与各压缩步骤的耗时、节省字节数以直方图记录（见 [`ConversionMetrics`]），
+//! 选择凭据的等待耗时按凭据与总体分别记录（见 [`WaitMetrics`]），
+//! 发往上游的请求体与上游响应体的字节数按模型与凭据分别记录（见 [`SizeMetrics`]），
+//! 响应流中未知事件类型的出现次数按类型记录，
+//! 只反映当前进程，不随快照持久化。
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use chrono::Utc;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::kiro::conversation_budget;
+
+/// 指标快照（持久化格式，同时用于 Admin API）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsSnapshot {
+    /// 累计成功请求数
+    pub requests_total: u64,
+    /// 累计失败请求数（上游调用失败）
+    pub failures_total: u64,
+    /// 累计输入 token 数
+    pub input_tokens_total: u64,
+    /// 累计输出 token 数
+    pub output_tokens_total: u64,
+    /// 累计背压事件数（流式响应缓冲达到高水位）
+    #[serde(default)]
+    pub backpressure_events_total: u64,
+    /// 开始累计的时间（RFC3339 格式）
+    #[serde(default)]
+    pub since: Option<String>,
+    /// 按调用方 API Key 名称累计的用量
+    #[serde(default)]
+    pub keys: BTreeMap<String, KeyUsage>,
+}
+
+/// 单个调用方 API Key 的累计用量
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyUsage {
+    /// 累计完成的请求数
+    pub requests_total: u64,
+    /// 累计输入 token 数（估算值）
+    pub input_tokens_total: u64,
+    /// 累计输出 token 数（估算值）
+    pub output_tokens_total: u64,
+    /// 最后一次请求时间（RFC3339 格式）
+    #[serde(default)]
+    pub last_used_at: Option<String>,
+}
+
+/// 耗时直方图的桶上界（微秒）
+const LATENCY_BUCKETS_US: &[u64] = &[
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 1_000_000,
+];
+
+/// 等待耗时直方图的桶上界（毫秒）
+const WAIT_BUCKETS_MS: &[u64] = &[
+    1, 5, 10, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000, 60_000,
+];
+
+/// 节省字节数直方图的桶上界（字节）
+const SAVINGS_BUCKETS_BYTES: &[u64] =
+    &[1_024, 4_096, 16_384, 65_536, 262_144, 1_048_576, 4_194_304];
+
+/// 请求体 / 响应体字节数直方图的桶上界（字节），包含上游常见的 400KB 请求体上限
+const SIZE_BUCKETS_BYTES: &[u64] = &[
+    1_024, 4_096, 16_384, 65_536, 131_072, 262_144, 409_600, 1_048_576, 4_194_304,
+];
+
+/// 固定桶直方图
+#[derive(Debug)]
+pub struct Histogram {
+    /// 各桶上界（最后隐含一个 +Inf 桶）
+    bounds: &'static [u64],
+    /// 各桶计数（非累计），长度为 bounds.len() + 1
+    counts: Vec<AtomicU64>,
+    sum: AtomicU64,
+    max: AtomicU64,
+}
+
+/// 直方图快照
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistogramSnapshot {
+    /// 样本数
+    pub count: u64,
+    /// 样本值之和
+    pub sum: u64,
+    /// 最大样本值
+    pub max: u64,
+    /// 中位数估计（所在桶的上界，落在 +Inf 桶时为最大值）
+    pub p50: u64,
+    /// p99 估计（同上）
+    pub p99: u64,
+    /// 累计分桶计数（`le` 为 null 表示 +Inf）
+    pub buckets: Vec<HistogramBucket>,
+}
+
+/// 直方图的一个累计分桶
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistogramBucket {
+    /// 桶上界（含）
+    pub le: Option<u64>,
+    /// 小于等于上界的样本数
+    pub count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [u64]) -> Self {
+        Self {
+            bounds,
+            counts: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    /// 记录一个样本
+    pub fn observe(&self, value: u64) {
+        let index = self.bounds.partition_point(|&bound| bound < value);
+        self.counts[index].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.max.fetch_max(value, Ordering::Relaxed);
+    }
+
+    /// 获取当前快照
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let counts: Vec<u64> = self
+            .counts
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect();
+        let count: u64 = counts.iter().sum();
+        let max = self.max.load(Ordering::Relaxed);
+
+        let mut cumulative = 0;
+        let buckets: Vec<HistogramBucket> = counts
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| {
+                cumulative += c;
+                HistogramBucket {
+                    le: self.bounds.get(i).copied(),
+                    count: cumulative,
+                }
+            })
+            .collect();
+        let quantile = |q: f64| {
+            let rank = ((count as f64) * q).ceil().max(1.0) as u64;
+            buckets
+                .iter()
+                .find(|b| b.count >= rank)
+                .and_then(|b| b.le)
+                .map_or(max, |le| le.min(max))
+        };
+
+        HistogramSnapshot {
+            count,
+            sum: self.sum.load(Ordering::Relaxed),
+            max,
+            p50: if count == 0 { 0 } else { quantile(0.5) },
+            p99: if count == 0 { 0 } else { quantile(0.99) },
+            buckets,
+        }
+    }
+}
+
+/// 单个压缩步骤的直方图
+#[derive(Debug)]
+struct PassMetrics {
+    latency_us: Histogram,
+    saved_bytes: Histogram,
+}
+
+/// 请求转换指标
+#[derive(Debug)]
+pub struct ConversionMetrics {
+    /// 整个请求转换的耗时
+    latency_us: Histogram,
+    /// 按压缩步骤名称分别记录
+    passes: Mutex<BTreeMap<String, Arc<PassMetrics>>>,
+}
+
+impl Default for ConversionMetrics {
+    fn default() -> Self {
+        Self {
+            latency_us: Histogram::new(LATENCY_BUCKETS_US),
+            passes: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+/// 单个压缩步骤的指标快照
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PassMetricsSnapshot {
+    /// 耗时（微秒）
+    pub latency_us: HistogramSnapshot,
+    /// 节省的字节数
+    pub saved_bytes: HistogramSnapshot,
+}
+
+/// 请求转换指标快照
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionMetricsSnapshot {
+    /// 整个请求转换的耗时（微秒）
+    pub latency_us: HistogramSnapshot,
+    /// 各压缩步骤的耗时与节省字节数
+    pub passes: BTreeMap<String, PassMetricsSnapshot>,
+}
+
+impl ConversionMetrics {
+    /// 记录一次请求转换的耗时
+    pub fn record_conversion(&self, elapsed: Duration) {
+        self.latency_us.observe(elapsed.as_micros() as u64);
+    }
+
+    /// 记录一个压缩步骤的耗时与节省字节数
+    pub fn record_pass(&self, name: &str, elapsed: Duration, saved_bytes: usize) {
+        let pass = self
+            .passes
+            .lock()
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                Arc::new(PassMetrics {
+                    latency_us: Histogram::new(LATENCY_BUCKETS_US),
+                    saved_bytes: Histogram::new(SAVINGS_BUCKETS_BYTES),
+                })
+            })
+            .clone();
+        pass.latency_us.observe(elapsed.as_micros() as u64);
+        pass.saved_bytes.observe(saved_bytes as u64);
+    }
+
+    /// 获取当前快照
+    pub fn snapshot(&self) -> ConversionMetricsSnapshot {
+        ConversionMetricsSnapshot {
+            latency_us: self.latency_us.snapshot(),
+            passes: self
+                .passes
+                .lock()
+                .iter()
+                .map(|(name, pass)| {
+                    (
+                        name.clone(),
+                        PassMetricsSnapshot {
+                            latency_us: pass.latency_us.snapshot(),
+                            saved_bytes: pass.saved_bytes.snapshot(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// 选择凭据的等待耗时指标
+///
+/// 覆盖 `acquire_context` 内的全部等待（Token 刷新、刷新锁排队、凭据切换），
+/// 用于评估当前流量下需要多少账号
+#[derive(Debug)]
+pub struct WaitMetrics {
+    /// 所有请求的等待耗时
+    overall: Histogram,
+    /// 按最终选中的凭据分别记录
+    credentials: Mutex<BTreeMap<u64, Arc<Histogram>>>,
+}
+
+impl Default for WaitMetrics {
+    fn default() -> Self {
+        Self {
+            overall: Histogram::new(WAIT_BUCKETS_MS),
+            credentials: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+/// 等待耗时指标快照
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WaitMetricsSnapshot {
+    /// 所有请求的等待耗时（毫秒）
+    pub overall_ms: HistogramSnapshot,
+    /// 各凭据的等待耗时（毫秒，按凭据 ID）
+    pub credentials_ms: BTreeMap<u64, HistogramSnapshot>,
+}
+
+impl WaitMetrics {
+    /// 记录一次选择凭据的等待耗时
+    pub fn record(&self, credential_id: u64, elapsed: Duration) {
+        let ms = elapsed.as_millis() as u64;
+        self.overall.observe(ms);
+        self.credentials
+            .lock()
+            .entry(credential_id)
+            .or_insert_with(|| Arc::new(Histogram::new(WAIT_BUCKETS_MS)))
+            .observe(ms);
+    }
+
+    /// 只保留 `keep` 返回 true 的凭据的直方图，返回移除的数量
+    fn retain_credentials(&self, keep: &impl Fn(u64) -> bool) -> usize {
+        retain_histograms(&self.credentials, keep)
+    }
+
+    /// 获取当前快照
+    pub fn snapshot(&self) -> WaitMetricsSnapshot {
+        WaitMetricsSnapshot {
+            overall_ms: self.overall.snapshot(),
+            credentials_ms: self
+                .credentials
+                .lock()
+                .iter()
+                .map(|(id, histogram)| (*id, histogram.snapshot()))
+                .collect(),
+        }
+    }
+}
+
+/// 单独记录字节数的模型数量上限（超出的模型计入 `other`）
+const MAX_SIZE_MODELS: usize = 64;
+
+/// 超出上限的模型计入的键
+const OTHER_MODELS: &str = "other";
+
+/// 未能识别模型时使用的键
+const UNKNOWN_MODEL: &str = "unknown";
+
+/// 一类字节数的直方图（总体、按模型、按凭据）
+#[derive(Debug)]
+struct SizeHistograms {
+    overall: Histogram,
+    models: Mutex<BTreeMap<String, Arc<Histogram>>>,
+    credentials: Mutex<BTreeMap<u64, Arc<Histogram>>>,
+}
+
+impl Default for SizeHistograms {
+    fn default() -> Self {
+        Self {
+            overall: Histogram::new(SIZE_BUCKETS_BYTES),
+            models: Mutex::new(BTreeMap::new()),
+            credentials: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+/// 一类字节数的快照
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SizeHistogramsSnapshot {
+    /// 所有请求
+    pub overall: HistogramSnapshot,
+    /// 按模型（上游 modelId）
+    pub models: BTreeMap<String, HistogramSnapshot>,
+    /// 按凭据 ID
+    pub credentials: BTreeMap<u64, HistogramSnapshot>,
+}
+
+impl SizeHistograms {
+    fn record(&self, model: Option<&str>, credential_id: u64, bytes: u64) {
+        self.overall.observe(bytes);
+        let model = model.unwrap_or(UNKNOWN_MODEL);
+        let histogram = {
+            let mut models = self.models.lock();
+            let key = if models.contains_key(model) || models.len() < MAX_SIZE_MODELS {
+                model
+            } else {
+                OTHER_MODELS
+            };
+            models
+                .entry(key.to_string())
+                .or_insert_with(|| Arc::new(Histogram::new(SIZE_BUCKETS_BYTES)))
+                .clone()
+        };
+        histogram.observe(bytes);
+        self.credentials
+            .lock()
+            .entry(credential_id)
+            .or_insert_with(|| Arc::new(Histogram::new(SIZE_BUCKETS_BYTES)))
+            .observe(bytes);
+    }
+
+    fn retain_credentials(&self, keep: &impl Fn(u64) -> bool) -> usize {
+        retain_histograms(&self.credentials, keep)
+    }
+
+    fn snapshot(&self) -> SizeHistogramsSnapshot {
+        SizeHistogramsSnapshot {
+            overall: self.overall.snapshot(),
+            models: self
+                .models
+                .lock()
+                .iter()
+                .map(|(model, histogram)| (model.clone(), histogram.snapshot()))
+                .collect(),
+            credentials: self
+                .credentials
+                .lock()
+                .iter()
+                .map(|(id, histogram)| (*id, histogram.snapshot()))
+                .collect(),
+        }
+    }
+}
+
+/// 只保留 `keep` 返回 true 的凭据的直方图，返回移除的数量
+fn retain_histograms(
+    histograms: &Mutex<BTreeMap<u64, Arc<Histogram>>>,
+    keep: &impl Fn(u64) -> bool,
+) -> usize {
+    let mut histograms = histograms.lock();
+    let before = histograms.len();
+    histograms.retain(|id, _| keep(*id));
+    before - histograms.len()
+}
+
+/// 上游流量字节数指标
+///
+/// 请求体按每次发往上游的尝试记录（包括失败与重试），用于对照上游请求体上限导致的失败；
+/// 响应体按实际读取的字节数记录，用于容量规划
+#[derive(Debug, Default)]
+pub struct SizeMetrics {
+    request: SizeHistograms,
+    response: SizeHistograms,
+}
+
+/// 上游流量字节数快照
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SizeMetricsSnapshot {
+    /// 发往上游的请求体字节数
+    pub request_bytes: SizeHistogramsSnapshot,
+    /// 从上游读取的响应体字节数
+    pub response_bytes: SizeHistogramsSnapshot,
+}
+
+impl SizeMetrics {
+    /// 记录一次发往上游的请求体字节数
+    pub fn record_request(&self, model: Option<&str>, credential_id: u64, bytes: usize) {
+        self.request.record(model, credential_id, bytes as u64);
+    }
+
+    /// 记录一次上游响应体的字节数
+    pub fn record_response(&self, model: Option<&str>, credential_id: u64, bytes: u64) {
+        self.response.record(model, credential_id, bytes);
+    }
+
+    fn retain_credentials(&self, keep: &impl Fn(u64) -> bool) -> usize {
+        self.request.retain_credentials(keep) + self.response.retain_credentials(keep)
+    }
+
+    /// 获取当前快照
+    pub fn snapshot(&self) -> SizeMetricsSnapshot {
+        SizeMetricsSnapshot {
+            request_bytes: self.request.snapshot(),
+            response_bytes: self.response.snapshot(),
+        }
+    }
+}
+
+/// 上游响应体字节计数器
+///
+/// 读取响应流时累加字节数，drop 时（或故障转移换用其他凭据时）计入 [`SizeMetrics`]
+#[derive(Debug)]
+pub struct ResponseBytes {
+    metrics: Arc<Metrics>,
+    model: Option<String>,
+    credential_id: u64,
+    bytes: u64,
+}
+
+impl ResponseBytes {
+    pub fn new(metrics: Arc<Metrics>, model: Option<String>, credential_id: u64) -> Self {
+        Self {
+            metrics,
+            model,
+            credential_id,
+            bytes: 0,
+        }
+    }
+
+    /// 累加读取到的字节数
+    pub fn add(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+
+    /// 故障转移：记录已读取的字节数，之后的字节计入新凭据
+    pub fn switch_credential(&mut self, credential_id: u64) {
+        self.flush();
+        self.credential_id = credential_id;
+    }
+
+    fn flush(&mut self) {
+        if self.bytes > 0 {
+            self.metrics.sizes().record_response(
+                self.model.as_deref(),
+                self.credential_id,
+                self.bytes,
+            );
+            self.bytes = 0;
+        }
+    }
+}
+
+impl Drop for ResponseBytes {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// 单独计数的未知事件类型上限（防止上游异常时无限增长）
+const MAX_UNKNOWN_EVENT_TYPES: usize = 64;
+
+/// 超出上限的未知事件类型计入的键
+const UNKNOWN_EVENT_OTHER: &str = "other";
+
+/// 累计运行指标
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests_total: AtomicU64,
+    failures_total: AtomicU64,
+    input_tokens_total: AtomicU64,
+    output_tokens_total: AtomicU64,
+    backpressure_events_total: AtomicU64,
+    since: Mutex<Option<String>>,
+    keys: Mutex<BTreeMap<String, KeyUsage>>,
+    /// 请求转换指标（不持久化）
+    conversion: ConversionMetrics,
+    /// 选择凭据的等待耗时（不持久化）
+    wait: WaitMetrics,
+    /// 上游请求体与响应体字节数（不持久化）
+    sizes: SizeMetrics,
+    /// 各未知事件类型的出现次数（不持久化）
+    unknown_events: Mutex<BTreeMap<String, u64>>,
+    /// 自上次落盘后是否有更新
+    dirty: AtomicBool,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let metrics = Self::default();
+        *metrics.since.lock() = Some(Utc::now().to_rfc3339());
+        metrics
+    }
+
+    /// 记录一次成功请求
+    pub fn record_success(&self) {
+        self.requests_total.fetch_add(1, Ordering::Relaxed);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// 记录一次失败请求
+    pub fn record_failure(&self) {
+        self.failures_total.fetch_add(1, Ordering::Relaxed);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// 记录一次背压事件（客户端读取过慢，流式响应缓冲达到高水位）
+    pub fn record_backpressure(&self) {
+        self.backpressure_events_total
+            .fetch_add(1, Ordering::Relaxed);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// 记录一次请求的 token 用量
+    pub fn record_tokens(&self, input_tokens: i32, output_tokens: i32) {
+        self.input_tokens_total
+            .fetch_add(input_tokens.max(0) as u64, Ordering::Relaxed);
+        self.output_tokens_total
+            .fetch_add(output_tokens.max(0) as u64, Ordering::Relaxed);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// 记录调用方 API Key 完成的一次请求及其 token 用量
+    pub fn record_key_usage(&self, key_name: &str, input_tokens: i32, output_tokens: i32) {
+        let mut keys = self.keys.lock();
+        let usage = keys.entry(key_name.to_string()).or_default();
+        usage.requests_total += 1;
+        usage.input_tokens_total += input_tokens.max(0) as u64;
+        usage.output_tokens_total += output_tokens.max(0) as u64;
+        usage.last_used_at = Some(Utc::now().to_rfc3339());
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// 记录一次未知事件，返回该类型的累计出现次数
+    ///
+    /// 记录的类型数达到上限后，新的类型计入 `other`
+    pub fn record_unknown_event(&self, event_type: &str) -> u64 {
+        let mut events = self.unknown_events.lock();
+        let key = if events.contains_key(event_type) || events.len() < MAX_UNKNOWN_EVENT_TYPES {
+            event_type
+        } else {
+            UNKNOWN_EVENT_OTHER
+        };
+        let count = events.entry(key.to_string()).or_insert(0);
+        *count += 1;
+        *count
+    }
+
+    /// 各未知事件类型的出现次数
+    pub fn unknown_events(&self) -> BTreeMap<String, u64> {
+        self.unknown_events.lock().clone()
+    }
+
+    /// 请求转换指标
+    pub fn conversion(&self) -> &ConversionMetrics {
+        &self.conversion
+    }
+
+    /// 选择凭据的等待耗时指标
+    pub fn wait(&self) -> &WaitMetrics {
+        &self.wait
+    }
+
+    /// 上游请求体与响应体字节数指标
+    pub fn sizes(&self) -> &SizeMetrics {
+        &self.sizes
+    }
+
+    /// 只保留 `keep` 返回 true 的凭据的分凭据指标（等待耗时、字节数），返回移除的数量
+    pub fn retain_credentials(&self, keep: impl Fn(u64) -> bool) -> usize {
+        self.wait.retain_credentials(&keep) + self.sizes.retain_credentials(&keep)
+    }
+
+    /// 自上次落盘后是否有更新
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Relaxed)
+    }
+
+    /// 获取当前快照
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            requests_total: self.requests_total.load(Ordering::Relaxed),
+            failures_total: self.failures_total.load(Ordering::Relaxed),
+            input_tokens_total: self.input_tokens_total.load(Ordering::Relaxed),
+            output_tokens_total: self.output_tokens_total.load(Ordering::Relaxed),
+            backpressure_events_total: self.backpressure_events_total.load(Ordering::Relaxed),
+            since: self.since.lock().clone(),
+            keys: self.keys.lock().clone(),
+        }
+    }
+
+    /// 将持久化的快照累加到当前计数上
+    ///
+    /// 使用累加而非覆盖：加载前已产生的计数不会丢失
+    pub fn restore(&self, snapshot: &MetricsSnapshot) {
+        self.requests_total
+            .fetch_add(snapshot.requests_total, Ordering::Relaxed);
+        self.failures_total
+            .fetch_add(snapshot.failures_total, Ordering::Relaxed);
+        self.input_tokens_total
+            .fetch_add(snapshot.input_tokens_total, Ordering::Relaxed);
+        self.output_tokens_total
+            .fetch_add(snapshot.output_tokens_total, Ordering::Relaxed);
+        self.backpressure_events_total
+            .fetch_add(snapshot.backpressure_events_total, Ordering::Relaxed);
+        if snapshot.since.is_some() {
+            *self.since.lock() = snapshot.since.clone();
+        }
+        let mut keys = self.keys.lock();
+        for (name, saved) in &snapshot.keys {
+            let usage = keys.entry(name.clone()).or_default();
+            usage.requests_total += saved.requests_total;
+            usage.input_tokens_total += saved.input_tokens_total;
+            usage.output_tokens_total += saved.output_tokens_total;
+            if usage.last_used_at.is_none() {
+                usage.last_used_at = saved.last_used_at.clone();
+            }
+        }
+    }
+
+    /// 从文件加载快照，文件不存在或损坏时返回 None
+    pub fn load_snapshot(path: &Path) -> Option<MetricsSnapshot> {
+        let content = std::fs::read_to_string(path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                tracing::warn!("解析指标快照失败，将忽略: {}", e);
+                None
+            }
+        }
+    }
+
+    /// 将当前快照写入文件
+    pub fn save_snapshot(&self, path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(&self.snapshot())?;
+        std::fs::write(path, json)?;
+        self.dirty.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// 绑定调用方 API Key 的用量记录器
+///
+/// 在请求处理链路中传递，记录的用量同时计入全局指标与该 Key 的累计用量
+#[derive(Debug, Clone)]
+pub struct UsageRecorder {
+    metrics: Arc<Metrics>,
+    key_name: String,
+    /// 同时累计用量的会话（启用 conversationTokenBudget 时设置）
+    conversation_id: Option<String>,
+}
+
+impl UsageRecorder {
+    pub fn new(metrics: Arc<Metrics>, key_name: impl Into<String>) -> Self {
+        Self {
+            metrics,
+            key_name: key_name.into(),
+            conversation_id: None,
+        }
+    }
+
+    /// 同时把用量累计到会话的 token 预算
+    pub fn with_conversation(mut self, conversation_id: impl Into<String>) -> Self {
+        self.conversation_id = Some(conversation_id.into());
+        self
+    }
+
+    /// 记录一次请求的 token 用量
+    pub fn record_tokens(&self, input_tokens: i32, output_tokens: i32) {
+        self.metrics.record_tokens(input_tokens, output_tokens);
+        self.metrics
+            .record_key_usage(&self.key_name, input_tokens, output_tokens);
+        if let Some(conversation_id) = &self.conversation_id {
+            let tokens = input_tokens.max(0) as u64 + output_tokens.max(0) as u64;
+            conversation_budget::record(conversation_id, tokens);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_roundtrip_accumulates() {
+        let dir = std::env::temp_dir().join(format!("kiro-metrics-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("kiro_metrics.json");
+
+        let before = Metrics::new();
+        before.record_success();
+        before.record_success();
+        before.record_failure();
+        before.record_tokens(100, 20);
+        before.record_key_usage("team-a", 100, 20);
+        assert!(before.is_dirty());
+        before.save_snapshot(&path).unwrap();
+        assert!(!before.is_dirty());
+
+        // 模拟重启：新进程在加载前已处理了一个请求
+        let after = Metrics::new();
+        after.record_success();
+        after.record_key_usage("team-a", 1, 2);
+        after.restore(&Metrics::load_snapshot(&path).unwrap());
+
+        let snapshot = after.snapshot();
+        assert_eq!(snapshot.requests_total, 3);
+        assert_eq!(snapshot.failures_total, 1);
+        assert_eq!(snapshot.input_tokens_total, 100);
+        assert_eq!(snapshot.output_tokens_total, 20);
+        assert_eq!(snapshot.since, before.snapshot().since);
+        let team = &snapshot.keys["team-a"];
+        assert_eq!(team.requests_total, 2);
+        assert_eq!(team.input_tokens_total, 101);
+        assert_eq!(team.output_tokens_total, 22);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_record_tokens_ignores_negative() {
+        let metrics = Metrics::new();
+        metrics.record_tokens(-5, 7);
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.input_tokens_total, 0);
+        assert_eq!(snapshot.output_tokens_total, 7);
+    }
+
+    #[test]
+    fn test_usage_recorder_attributes_to_key() {
+        let metrics = Arc::new(Metrics::new());
+        let alice = UsageRecorder::new(metrics.clone(), "alice");
+        let bob = UsageRecorder::new(metrics.clone(), "bob");
+        alice.record_tokens(10, 5);
+        alice.record_tokens(20, 5);
+        bob.record_tokens(1, 1);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.input_tokens_total, 31);
+        assert_eq!(snapshot.keys["alice"].requests_total, 2);
+        assert_eq!(snapshot.keys["alice"].output_tokens_total, 10);
+        assert_eq!(snapshot.keys["bob"].input_tokens_total, 1);
+        assert!(snapshot.keys["bob"].last_used_at.is_some());
+    }
+
+    #[test]
+    fn test_conversion_histograms() {
+        let metrics = Metrics::new();
+        let conversion = metrics.conversion();
+        for ms in [1, 1, 3, 40] {
+            conversion.record_conversion(Duration::from_millis(ms));
+        }
+        conversion.record_pass("schema", Duration::from_micros(80), 3_000);
+        conversion.record_pass("schema", Duration::from_micros(90), 10_000_000);
+
+        let snapshot = conversion.snapshot();
+        let latency = &snapshot.latency_us;
+        assert_eq!(latency.count, 4);
+        assert_eq!(latency.sum, 45_000);
+        assert_eq!(latency.max, 40_000);
+        assert_eq!(latency.p50, 1_000);
+        assert_eq!(latency.p99, 40_000);
+        assert_eq!(
+            latency
+                .buckets
+                .iter()
+                .find(|b| b.le == Some(5_000))
+                .unwrap()
+                .count,
+            3
+        );
+        assert_eq!(latency.buckets.last().unwrap().count, 4);
+
+        let schema = &snapshot.passes["schema"];
+        assert_eq!(schema.latency_us.count, 2);
+        assert_eq!(schema.latency_us.p99, 90);
+        // 超过最大桶上界的样本落在 +Inf 桶，分位数取最大值
+        assert_eq!(schema.saved_bytes.p99, 10_000_000);
+        assert_eq!(schema.saved_bytes.buckets[1].count, 1);
+
+        // 不随快照持久化
+        assert!(!metrics.is_dirty());
+    }
+
+    #[test]
+    fn test_wait_histograms() {
+        let metrics = Metrics::new();
+        let wait = metrics.wait();
+        wait.record(1, Duration::from_millis(0));
+        wait.record(1, Duration::from_millis(3));
+        wait.record(2, Duration::from_secs(2));
+
+        let snapshot = wait.snapshot();
+        assert_eq!(snapshot.overall_ms.count, 3);
+        assert_eq!(snapshot.overall_ms.p99, 2_000);
+        assert_eq!(snapshot.credentials_ms[&1].count, 2);
+        assert_eq!(snapshot.credentials_ms[&1].p50, 1);
+        assert_eq!(snapshot.credentials_ms[&2].max, 2_000);
+        assert!(!metrics.is_dirty());
+    }
+
+    #[test]
+    fn test_size_histograms() {
+        let metrics = Arc::new(Metrics::new());
+        let sizes = metrics.sizes();
+        sizes.record_request(Some("claude-sonnet-4"), 1, 2_000);
+        sizes.record_request(Some("claude-sonnet-4"), 2, 500_000);
+        sizes.record_request(None, 2, 100);
+
+        // 故障转移前读取的字节数计入原凭据，之后计入新凭据
+        let mut response = ResponseBytes::new(metrics.clone(), Some("claude-sonnet-4".into()), 1);
+        response.add(300);
+        response.switch_credential(2);
+        response.add(5_000);
+        response.add(5_000);
+        drop(response);
+        // 未读取任何字节时不记录
+        drop(ResponseBytes::new(metrics.clone(), None, 3));
+
+        let snapshot = metrics.sizes().snapshot();
+        let request = &snapshot.request_bytes;
+        assert_eq!(request.overall.count, 3);
+        assert_eq!(request.models["claude-sonnet-4"].count, 2);
+        assert_eq!(request.models[UNKNOWN_MODEL].count, 1);
+        assert_eq!(request.credentials[&2].p99, 500_000);
+        assert_eq!(request.credentials[&2].p50, 1_024);
+
+        let response = &snapshot.response_bytes;
+        assert_eq!(response.overall.count, 2);
+        assert_eq!(response.credentials[&1].sum, 300);
+        assert_eq!(response.credentials[&2].sum, 10_000);
+        assert!(!response.credentials.contains_key(&3));
+        assert!(!metrics.is_dirty());
+    }
+
+    #[test]
+    fn test_unknown_events_bounded() {
+        let metrics = Metrics::new();
+        assert_eq!(metrics.record_unknown_event("citationEvent"), 1);
+        assert_eq!(metrics.record_unknown_event("citationEvent"), 2);
+        for i in 1..MAX_UNKNOWN_EVENT_TYPES {
+            metrics.record_unknown_event(&format!("event{}", i));
+        }
+        // 达到类型上限后新的类型计入 other，已有类型继续单独计数
+        assert_eq!(metrics.record_unknown_event("serverToolEvent"), 1);
+        assert_eq!(metrics.record_unknown_event("citationEvent"), 3);
+        let events = metrics.unknown_events();
+        assert_eq!(events.len(), MAX_UNKNOWN_EVENT_TYPES + 1);
+        assert_eq!(events[UNKNOWN_EVENT_OTHER], 1);
+        assert!(!metrics.is_dirty());
+    }
+
+    #[test]
+    fn test_load_snapshot_missing_file() {
+        let path = std::env::temp_dir().join(format!("kiro-missing-{}.json", uuid::Uuid::new_v4()));
+        assert!(Metrics::load_snapshot(&path).is_none());
+    }
+}
@@ -1,7 +1,17 @@
 //! Kiro API 客户端模块
 
+pub mod balance_store;
+pub mod conversation_budget;
+pub mod credential_archive;
+pub mod credentials_writer;
+pub mod daily_limit;
+pub mod debug_dump;
+pub mod event_bus;
+pub mod log_sanitizer;
 pub mod machine_id;
+pub mod metrics;
 pub mod model;
 pub mod parser;
 pub mod provider;
+pub mod rebalance;
 pub mod token_manager;
@@ -0,0 +1,421 @@
+//! 凭据构建与校验
+//!
+//! Admin 添加、批量导入与配对上传的凭据都通过 [`KiroCredentialsBuilder`] 构建：
+//! 统一去除复制粘贴引入的不可见字符，校验 refreshToken 形态、认证方式、区域名称与 machineId，
+//! 各入口返回一致的错误信息（按配置语言输出）。
+
+use std::fmt;
+
+use crate::common::i18n;
+use crate::kiro::machine_id::normalize_machine_id;
+
+use super::credentials::{AuthMethod, KiroCredentials, invalid_token_char};
+
+/// 凭据校验错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialValidationError {
+    /// 缺少 refreshToken 或为空
+    MissingRefreshToken,
+    /// refreshToken 已被截断
+    TruncatedRefreshToken { len: usize },
+    /// Token 中包含非法字符（位置按字符计，从 1 开始）
+    InvalidTokenChar {
+        field: &'static str,
+        position: usize,
+        ch: char,
+    },
+    /// 不支持的认证方式
+    UnknownAuthMethod(String),
+    /// IdC 认证缺少 clientId / clientSecret
+    MissingClientCredentials,
+    /// 区域名称格式无效
+    InvalidRegion { field: &'static str, value: String },
+    /// machineId 格式无效
+    InvalidMachineId,
+    /// 金丝雀流量百分比超出 0-100
+    InvalidCanaryPercent(u8),
+}
+
+impl fmt::Display for CredentialValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Self::MissingRefreshToken => {
+                i18n::pick("缺少 refreshToken", "refreshToken is missing").to_string()
+            }
+            Self::TruncatedRefreshToken { len } => i18n::pick(
+                format!(
+                    "refreshToken 已被截断（长度: {} 字符）。\n\
+                     这通常是 Kiro IDE 为了防止凭证被第三方工具使用而故意截断的。",
+                    len
+                ),
+                format!(
+                    "refreshToken is truncated ({} chars).\n\
+                     Kiro IDE deliberately truncates tokens it displays to keep third-party tools from using them.",
+                    len
+                ),
+            ),
+            Self::InvalidTokenChar {
+                field,
+                position,
+                ch,
+            } => i18n::pick(
+                format!(
+                    "{} 第 {} 个字符 {:?}（U+{:04X}）不是合法的 Token 字符，\
+                     可能是复制粘贴时被聊天软件替换或插入的，请从原始 Token 缓存文件中重新复制",
+                    field, position, ch, *ch as u32
+                ),
+                format!(
+                    "character {} of {} {:?} (U+{:04X}) is not a valid token character; \
+                     it was probably replaced or inserted by a chat app while copying, \
+                     copy the token again from the original token cache file",
+                    position, field, ch, *ch as u32
+                ),
+            ),
+            Self::UnknownAuthMethod(method) => i18n::pick(
+                format!("不支持的认证方式: {}（可选 social、idc）", method),
+                format!(
+                    "unsupported auth method: {} (expected social or idc)",
+                    method
+                ),
+            ),
+            Self::MissingClientCredentials => i18n::pick(
+                "IdC 认证需要同时提供 clientId 和 clientSecret",
+                "IdC authentication requires both clientId and clientSecret",
+            )
+            .to_string(),
+            Self::InvalidRegion { field, value } => i18n::pick(
+                format!(
+                    "{} 格式无效: {}（应为类似 us-east-1 的区域名称）",
+                    field, value
+                ),
+                format!(
+                    "invalid {}: {} (expected a region name like us-east-1)",
+                    field, value
+                ),
+            ),
+            Self::InvalidMachineId => i18n::pick(
+                "machineId 格式无效（应为 64 位十六进制字符串或 UUID）",
+                "invalid machineId (expected a 64-character hex string or a UUID)",
+            )
+            .to_string(),
+            Self::InvalidCanaryPercent(_) => i18n::pick(
+                "金丝雀流量百分比必须在 0-100 之间",
+                "canaryPercent must be between 0 and 100",
+            )
+            .to_string(),
+        };
+        f.write_str(&message)
+    }
+}
+
+impl std::error::Error for CredentialValidationError {}
+
+/// refreshToken 是否已被截断（过短或包含 "..." / "…"）
+///
+/// Kiro IDE 显示或导出的 refreshToken 会被截断，这类 Token 无法用于刷新；
+/// 经聊天软件转发时 "..." 还可能被替换为省略号字符
+pub fn is_truncated_refresh_token(refresh_token: &str) -> bool {
+    refresh_token.len() < 100 || refresh_token.contains("...") || refresh_token.contains('…')
+}
+
+/// 校验 refreshToken 的形态：非空、未被截断、只包含合法字符
+pub fn check_refresh_token(refresh_token: Option<&str>) -> Result<(), CredentialValidationError> {
+    let refresh_token = refresh_token
+        .filter(|t| !t.is_empty())
+        .ok_or(CredentialValidationError::MissingRefreshToken)?;
+    if is_truncated_refresh_token(refresh_token) {
+        return Err(CredentialValidationError::TruncatedRefreshToken {
+            len: refresh_token.len(),
+        });
+    }
+    check_token_chars("refreshToken", Some(refresh_token))
+}
+
+fn check_token_chars(
+    field: &'static str,
+    token: Option<&str>,
+) -> Result<(), CredentialValidationError> {
+    match token.and_then(invalid_token_char) {
+        Some((position, ch)) => Err(CredentialValidationError::InvalidTokenChar {
+            field,
+            position,
+            ch,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// 区域名称是否有效（如 `us-east-1`、`us-gov-west-1`）
+pub fn is_valid_region(region: &str) -> bool {
+    let parts: Vec<&str> = region.split('-').collect();
+    let Some((number, names)) = parts.split_last() else {
+        return false;
+    };
+    names.len() >= 2
+        && names[0].len() == 2
+        && names
+            .iter()
+            .all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_lowercase()))
+        && !number.is_empty()
+        && number.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// 凭据构建器
+///
+/// 可选字段传入 `None` 或空白字符串时视为未配置；`build` 时统一校验
+#[derive(Debug, Clone, Default)]
+pub struct KiroCredentialsBuilder {
+    credentials: KiroCredentials,
+    auth_method: Option<String>,
+}
+
+impl KiroCredentials {
+    /// 创建凭据构建器
+    pub fn builder(refresh_token: impl Into<String>) -> KiroCredentialsBuilder {
+        KiroCredentialsBuilder {
+            credentials: KiroCredentials {
+                refresh_token: Some(refresh_token.into()),
+                ..Default::default()
+            },
+            auth_method: None,
+        }
+    }
+}
+
+impl KiroCredentialsBuilder {
+    /// 认证方式（`social` / `idc`，兼容 `builder-id`、`iam` 等旧写法）；未设置时按凭据内容推断
+    pub fn auth_method(mut self, auth_method: impl Into<String>) -> Self {
+        self.auth_method = Some(auth_method.into());
+        self
+    }
+
+    /// OIDC 客户端（IdC 认证需要）
+    pub fn client(
+        mut self,
+        client_id: impl Into<Option<String>>,
+        client_secret: impl Into<Option<String>>,
+    ) -> Self {
+        self.credentials.client_id = client_id.into();
+        self.credentials.client_secret = client_secret.into();
+        self
+    }
+
+    pub fn priority(mut self, priority: u32) -> Self {
+        self.credentials.priority = priority;
+        self
+    }
+
+    /// 凭据级 Region（Auth Region / API Region 未配置时的回退）
+    pub fn region(mut self, region: impl Into<Option<String>>) -> Self {
+        self.credentials.region = region.into();
+        self
+    }
+
+    pub fn auth_region(mut self, auth_region: impl Into<Option<String>>) -> Self {
+        self.credentials.auth_region = auth_region.into();
+        self
+    }
+
+    pub fn api_region(mut self, api_region: impl Into<Option<String>>) -> Self {
+        self.credentials.api_region = api_region.into();
+        self
+    }
+
+    pub fn machine_id(mut self, machine_id: impl Into<Option<String>>) -> Self {
+        self.credentials.machine_id = machine_id.into();
+        self
+    }
+
+    pub fn email(mut self, email: impl Into<Option<String>>) -> Self {
+        self.credentials.email = email.into();
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<Option<String>>) -> Self {
+        self.credentials.label = label.into();
+        self
+    }
+
+    pub fn notes(mut self, notes: impl Into<Option<String>>) -> Self {
+        self.credentials.notes = notes.into();
+        self
+    }
+
+    /// 凭据级代理（URL 为 `direct` 时显式不使用代理）
+    pub fn proxy(
+        mut self,
+        url: impl Into<Option<String>>,
+        username: impl Into<Option<String>>,
+        password: impl Into<Option<String>>,
+    ) -> Self {
+        self.credentials.proxy_url = url.into();
+        self.credentials.proxy_username = username.into();
+        self.credentials.proxy_password = password.into();
+        self
+    }
+
+    pub fn daily_request_limit(mut self, limit: Option<u64>) -> Self {
+        self.credentials.daily_request_limit = limit;
+        self
+    }
+
+    pub fn canary_percent(mut self, percent: Option<u8>) -> Self {
+        self.credentials.canary_percent = percent;
+        self
+    }
+
+    /// 规范化并校验，返回凭据
+    pub fn build(self) -> Result<KiroCredentials, CredentialValidationError> {
+        let mut credentials = self.credentials;
+        credentials.normalize_tokens();
+        for value in [
+            &mut credentials.client_id,
+            &mut credentials.client_secret,
+            &mut credentials.region,
+            &mut credentials.auth_region,
+            &mut credentials.api_region,
+            &mut credentials.machine_id,
+            &mut credentials.email,
+            &mut credentials.label,
+            &mut credentials.notes,
+            &mut credentials.proxy_url,
+            &mut credentials.proxy_username,
+            &mut credentials.proxy_password,
+        ] {
+            *value = value
+                .take()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty());
+        }
+
+        check_refresh_token(credentials.refresh_token.as_deref())?;
+        check_token_chars("clientId", credentials.client_id.as_deref())?;
+        check_token_chars("clientSecret", credentials.client_secret.as_deref())?;
+
+        if let Some(method) = self.auth_method {
+            let parsed = AuthMethod::parse(&method)
+                .ok_or(CredentialValidationError::UnknownAuthMethod(method))?;
+            credentials.auth_method = Some(parsed.as_str().to_string());
+        }
+        if credentials.effective_auth_method() == AuthMethod::Idc.as_str()
+            && (credentials.client_id.is_none() || credentials.client_secret.is_none())
+        {
+            return Err(CredentialValidationError::MissingClientCredentials);
+        }
+
+        for (field, value) in [
+            ("region", &credentials.region),
+            ("authRegion", &credentials.auth_region),
+            ("apiRegion", &credentials.api_region),
+        ] {
+            if let Some(value) = value.as_deref().filter(|v| !is_valid_region(v)) {
+                return Err(CredentialValidationError::InvalidRegion {
+                    field,
+                    value: value.to_string(),
+                });
+            }
+        }
+
+        if credentials
+            .machine_id
+            .as_deref()
+            .is_some_and(|id| normalize_machine_id(id).is_none())
+        {
+            return Err(CredentialValidationError::InvalidMachineId);
+        }
+        if let Some(percent) = credentials.canary_percent.filter(|&p| p > 100) {
+            return Err(CredentialValidationError::InvalidCanaryPercent(percent));
+        }
+
+        Ok(credentials)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token() -> String {
+        "a".repeat(150)
+    }
+
+    #[test]
+    fn test_is_valid_region() {
+        for region in [
+            "us-east-1",
+            "eu-central-1",
+            "us-gov-west-1",
+            "ap-southeast-2",
+        ] {
+            assert!(is_valid_region(region), "{}", region);
+        }
+        for region in [
+            "",
+            "us-east",
+            "US-EAST-1",
+            "us_east_1",
+            "useast-1",
+            "us-east-1a",
+        ] {
+            assert!(!is_valid_region(region), "{}", region);
+        }
+    }
+
+    #[test]
+    fn test_builder_normalizes_and_validates() {
+        let credentials = KiroCredentials::builder(format!(" {}\u{200B}\n", token()))
+            .auth_method("Builder-ID")
+            .client(Some("id".to_string()), Some("secret".to_string()))
+            .region(Some(" eu-central-1 ".to_string()))
+            .label(Some("  ".to_string()))
+            .priority(2)
+            .build()
+            .unwrap();
+        assert_eq!(credentials.refresh_token, Some(token()));
+        assert_eq!(credentials.auth_method.as_deref(), Some("idc"));
+        assert_eq!(credentials.region.as_deref(), Some("eu-central-1"));
+        assert_eq!(credentials.label, None);
+        assert_eq!(credentials.priority, 2);
+
+        let error = |builder: KiroCredentialsBuilder| builder.build().unwrap_err();
+        assert_eq!(
+            error(KiroCredentials::builder("")),
+            CredentialValidationError::MissingRefreshToken
+        );
+        assert_eq!(
+            error(KiroCredentials::builder(format!("{}...", token()))),
+            CredentialValidationError::TruncatedRefreshToken { len: 153 }
+        );
+        assert!(matches!(
+            error(KiroCredentials::builder(format!("{}“", token()))),
+            CredentialValidationError::InvalidTokenChar {
+                field: "refreshToken",
+                position: 151,
+                ..
+            }
+        ));
+        assert_eq!(
+            error(KiroCredentials::builder(token()).auth_method("github")),
+            CredentialValidationError::UnknownAuthMethod("github".to_string())
+        );
+        assert_eq!(
+            error(KiroCredentials::builder(token()).auth_method("idc")),
+            CredentialValidationError::MissingClientCredentials
+        );
+        assert!(matches!(
+            error(KiroCredentials::builder(token()).api_region(Some("us-east".to_string()))),
+            CredentialValidationError::InvalidRegion {
+                field: "apiRegion",
+                ..
+            }
+        ));
+        assert_eq!(
+            error(KiroCredentials::builder(token()).machine_id(Some("abc".to_string()))),
+            CredentialValidationError::InvalidMachineId
+        );
+        assert_eq!(
+            error(KiroCredentials::builder(token()).canary_percent(Some(120))),
+            CredentialValidationError::InvalidCanaryPercent(120)
+        );
+    }
+}
@@ -3,6 +3,7 @@
 //! 支持从 Kiro IDE 的凭证文件加载，使用 Social 认证方式
 //! 支持单凭据和多凭据配置格式
 
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
@@ -34,6 +35,17 @@ pub struct KiroCredentials {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expires_at: Option<String>,
 
+    /// refreshToken 的签发时间（RFC3339 格式；添加、导入或首次加载时记录，
+    /// 凭据文件中换成新的 refreshToken 时更新）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub refresh_token_issued_at: Option<String>,
+
+    /// refreshToken 最近一次被上游轮换的时间（RFC3339 格式）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub refresh_token_rotated_at: Option<String>,
+
     /// 认证方式 (social / idc)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub auth_method: Option<String>,
@@ -73,6 +85,27 @@ pub struct KiroCredentials {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub email: Option<String>,
 
+    /// 标签（运营方自定义的显示名称，如 "work account, eu-west-1"）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub label: Option<String>,
+
+    /// 备注（运营方自由填写，仅用于展示）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub notes: Option<String>,
+
+    /// 该凭据的每日上游请求上限（未配置或 0 表示不限制），达到后当天不再选用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub daily_request_limit: Option<u64>,
+
+    /// 金丝雀流量百分比（0-100）：配置后该凭据不参与常规轮换，只按该比例抽样承担请求；
+    /// 未配置表示正常参与轮换
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub canary_percent: Option<u8>,
+
     /// 订阅等级（KIRO PRO+ / KIRO FREE 等）
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
@@ -99,12 +132,93 @@ fn is_zero(value: &u32) -> bool {
     *value == 0
 }
 
+/// 认证方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    Social,
+    Idc,
+}
+
+impl AuthMethod {
+    /// 解析认证方式（不区分大小写）：builder-id / iam 等旧值视为 idc
+    pub fn parse(value: &str) -> Option<Self> {
+        if ["idc", "builder-id", "iam"]
+            .iter()
+            .any(|m| value.eq_ignore_ascii_case(m))
+        {
+            Some(Self::Idc)
+        } else if value.eq_ignore_ascii_case("social") {
+            Some(Self::Social)
+        } else {
+            None
+        }
+    }
+
+    /// 规范写法
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Social => "social",
+            Self::Idc => "idc",
+        }
+    }
+}
+
+/// 认证方式的规范写法：builder-id / iam / IdC 等旧值统一为 idc，Social 统一为 social
 fn canonicalize_auth_method_value(value: &str) -> &str {
-    if value.eq_ignore_ascii_case("builder-id") || value.eq_ignore_ascii_case("iam") {
-        "idc"
-    } else {
-        value
+    AuthMethod::parse(value).map_or(value, |m| m.as_str())
+}
+
+/// 从 JWT 格式的 refreshToken 中读取签发者（iss），不是 JWT 时返回 None
+fn refresh_token_issuer(refresh_token: &str) -> Option<String> {
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    let mut parts = refresh_token.split('.');
+    let (Some(header), Some(payload), Some(_), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    if !header.starts_with("eyJ") {
+        return None;
+    }
+    let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&payload).ok()?;
+    claims.get("iss")?.as_str().map(str::to_string)
+}
+
+/// 复制粘贴时常被引入、且不会出现在 Token 中的字符：空白、控制字符与零宽 / 方向控制字符
+fn is_invisible(c: char) -> bool {
+    c.is_whitespace()
+        || c.is_control()
+        || matches!(
+            c,
+            '\u{00AD}'
+                | '\u{200B}'..='\u{200F}'
+                | '\u{202A}'..='\u{202E}'
+                | '\u{2060}'..='\u{2064}'
+                | '\u{FEFF}'
+        )
+}
+
+/// 去除 Token 中的空白与不可见字符，返回是否有修改
+pub fn normalize_token(token: &mut String) -> bool {
+    if !token.chars().any(is_invisible) {
+        return false;
     }
+    token.retain(|c| !is_invisible(c));
+    true
+}
+
+/// Token 中第一个非法字符（非 ASCII 可见字符）的位置（按字符计，从 1 开始）与字符
+///
+/// 规范化后仍残留的非法字符通常是聊天软件替换的全角标点或引号
+pub fn invalid_token_char(token: &str) -> Option<(usize, char)> {
+    token
+        .chars()
+        .enumerate()
+        .find(|(_, c)| !c.is_ascii_graphic())
+        .map(|(i, c)| (i + 1, c))
 }
 
 /// 凭据配置（支持单对象或数组格式）
@@ -147,21 +261,27 @@ impl CredentialsConfig {
     }
 
     /// 转换为按优先级排序的凭据列表
+    ///
+    /// 同时规范化 authMethod 写法，并去除 Token 中复制粘贴引入的空白与不可见字符
     pub fn into_sorted_credentials(self) -> Vec<KiroCredentials> {
-        match self {
-            CredentialsConfig::Single(mut cred) => {
-                cred.canonicalize_auth_method();
-                vec![cred]
-            }
+        let mut creds = match self {
+            CredentialsConfig::Single(cred) => vec![cred],
             CredentialsConfig::Multiple(mut creds) => {
                 // 按优先级排序（数字越小优先级越高）
                 creds.sort_by_key(|c| c.priority);
-                for cred in &mut creds {
-                    cred.canonicalize_auth_method();
-                }
                 creds
             }
+        };
+        for cred in &mut creds {
+            cred.canonicalize_auth_method();
+            if cred.normalize_tokens() {
+                tracing::warn!(
+                    "凭据 {} 的 Token 中包含空白或不可见字符（可能由复制粘贴引入），已自动去除",
+                    cred.describe(cred.id.unwrap_or_default())
+                );
+            }
         }
+        creds
     }
 
     /// 获取凭据数量
@@ -212,6 +332,20 @@ impl KiroCredentials {
             .unwrap_or(config.effective_api_region())
     }
 
+    /// 获取额度查询（getUsageLimits）使用的 Region
+    /// IdC 凭据：凭据.api_region > 凭据.region > 凭据.auth_region > config.api_region > config.region
+    /// Social 凭据或关闭 usageLimitsCredentialRegion 时与 API Region 相同
+    pub fn effective_usage_limits_region<'a>(&'a self, config: &'a Config) -> &'a str {
+        if !config.usage_limits_credential_region || self.effective_auth_method() != "idc" {
+            return self.effective_api_region(config);
+        }
+        self.api_region
+            .as_deref()
+            .or(self.region.as_deref())
+            .or(self.auth_region.as_deref())
+            .unwrap_or(config.effective_api_region())
+    }
+
     /// 获取有效的代理配置
     /// 优先级：凭据代理 > 全局代理 > 无代理
     /// 特殊值 "direct" 表示显式不使用代理（即使全局配置了代理）
@@ -231,6 +365,23 @@ impl KiroCredentials {
         }
     }
 
+    /// 显示名称：标签优先，其次为邮箱
+    pub fn display_name(&self) -> Option<&str> {
+        [self.label.as_deref(), self.email.as_deref()]
+            .into_iter()
+            .flatten()
+            .map(str::trim)
+            .find(|name| !name.is_empty())
+    }
+
+    /// 日志中的凭据描述：`#ID` 或 `#ID（显示名称）`
+    pub fn describe(&self, id: u64) -> String {
+        match self.display_name() {
+            Some(name) => format!("#{}（{}）", id, name),
+            None => format!("#{}", id),
+        }
+    }
+
     /// 从 JSON 字符串解析凭证
     pub fn from_json(json_string: &str) -> Result<Self, serde_json::Error> {
         serde_json::from_str(json_string)
@@ -251,16 +402,94 @@ impl KiroCredentials {
         serde_json::to_string_pretty(self)
     }
 
-    pub fn canonicalize_auth_method(&mut self) {
+    /// 把 authMethod 的旧写法改为规范写法，返回是否有修改
+    pub fn canonicalize_auth_method(&mut self) -> bool {
         let auth_method = match &self.auth_method {
             Some(m) => m,
-            None => return,
+            None => return false,
         };
 
         let canonical = canonicalize_auth_method_value(auth_method);
         if canonical != auth_method {
             self.auth_method = Some(canonical.to_string());
+            return true;
+        }
+        false
+    }
+
+    /// 去除 refreshToken、accessToken、clientId、clientSecret 中的空白与不可见字符，返回是否有修改
+    ///
+    /// 通过聊天软件复制粘贴的 Token 常被插入换行或零宽字符，原样发送给上游只会得到 invalid_grant
+    pub fn normalize_tokens(&mut self) -> bool {
+        let mut changed = false;
+        for token in [
+            &mut self.refresh_token,
+            &mut self.access_token,
+            &mut self.client_id,
+            &mut self.client_secret,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            changed |= normalize_token(token);
         }
+        changed
+    }
+
+    /// 根据凭据内容推断认证方式（无法判断时返回 None）
+    ///
+    /// 依次参考：clientId/clientSecret（IdC 刷新必需）、JWT 格式 refreshToken 的签发者、
+    /// profileArn（只有 Social 登录会返回）
+    pub fn detect_auth_method(&self) -> Option<&'static str> {
+        if self.client_id.is_some() && self.client_secret.is_some() {
+            return Some("idc");
+        }
+        if let Some(issuer) = self.refresh_token.as_deref().and_then(refresh_token_issuer) {
+            let issuer = issuer.to_ascii_lowercase();
+            if issuer.contains("oidc") || issuer.contains("sso") || issuer.contains("amazonaws") {
+                return Some("idc");
+            }
+            if issuer.contains("kiro") {
+                return Some("social");
+            }
+        }
+        if self.profile_arn.is_some() {
+            return Some("social");
+        }
+        None
+    }
+
+    /// 实际使用的认证方式：配置的 authMethod（规范写法），未配置时按凭据内容推断，都没有时为 social
+    pub fn effective_auth_method(&self) -> &str {
+        match self.auth_method.as_deref() {
+            Some(m) => canonicalize_auth_method_value(m),
+            None => self.detect_auth_method().unwrap_or("social"),
+        }
+    }
+
+    /// 按签发时间估算的 refreshToken 过期时间
+    ///
+    /// 只有 IdC 的 refreshToken 有固定有效期（上游轮换不会延长）；
+    /// 其他认证方式、未记录签发时间或 `lifetime_days` 为 0 时返回 None
+    pub fn refresh_token_expires_at(&self, lifetime_days: u64) -> Option<DateTime<Utc>> {
+        if lifetime_days == 0 || self.effective_auth_method() != "idc" {
+            return None;
+        }
+        let issued_at =
+            DateTime::parse_from_rfc3339(self.refresh_token_issued_at.as_deref()?).ok()?;
+        Some(issued_at.with_timezone(&Utc) + Duration::days(lifetime_days as i64))
+    }
+
+    /// 配置的 authMethod 与按凭据内容推断的结果不一致时，返回推断的认证方式
+    pub fn auth_method_conflict(&self) -> Option<&'static str> {
+        let stored = canonicalize_auth_method_value(self.auth_method.as_deref()?);
+        self.detect_auth_method()
+            .filter(|detected| *detected != stored)
+    }
+
+    /// 是否为金丝雀凭据（不参与常规轮换）
+    pub fn is_canary(&self) -> bool {
+        self.canary_percent.is_some()
     }
 
     /// 检查凭据是否支持 Opus 模型
@@ -284,6 +513,58 @@ mod tests {
     use super::*;
     use crate::model::config::Config;
 
+    #[test]
+    fn test_normalize_tokens() {
+        let mut creds = KiroCredentials {
+            refresh_token: Some(" aor\u{200B}AAAA\r\nBBBB\u{FEFF}\t".to_string()),
+            client_secret: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert!(creds.normalize_tokens());
+        assert_eq!(creds.refresh_token.as_deref(), Some("aorAAAABBBB"));
+        assert_eq!(creds.client_secret.as_deref(), Some("secret"));
+        assert!(!creds.normalize_tokens());
+
+        assert_eq!(invalid_token_char("aorAAAA:BB-_=/+"), None);
+        assert_eq!(invalid_token_char("aor“AA"), Some((4, '“')));
+    }
+
+    #[test]
+    fn test_auth_method_detection_and_canonicalization() {
+        let mut creds = KiroCredentials {
+            auth_method: Some("IdC".to_string()),
+            ..Default::default()
+        };
+        assert!(creds.canonicalize_auth_method());
+        assert_eq!(creds.auth_method.as_deref(), Some("idc"));
+        assert!(!creds.canonicalize_auth_method());
+
+        // 没有 clientId/clientSecret 的 IdC 凭据无法刷新，推断结果与配置冲突
+        creds.profile_arn = Some("arn:aws:codewhisperer:us-east-1:1:profile/X".to_string());
+        assert_eq!(creds.auth_method_conflict(), Some("social"));
+        creds.client_id = Some("cid".to_string());
+        creds.client_secret = Some("secret".to_string());
+        assert_eq!(creds.auth_method_conflict(), None);
+
+        // authMethod 未配置时按凭据内容推断
+        let creds = KiroCredentials {
+            profile_arn: Some("arn".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(creds.effective_auth_method(), "social");
+        assert_eq!(KiroCredentials::default().effective_auth_method(), "social");
+
+        use base64::Engine;
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(r#"{"iss":"https://oidc.us-east-1.amazonaws.com"}"#);
+        let creds = KiroCredentials {
+            refresh_token: Some(format!("eyJhbGciOiJub25lIn0.{}.sig", payload)),
+            ..Default::default()
+        };
+        assert_eq!(creds.detect_auth_method(), Some("idc"));
+        assert_eq!(creds.effective_auth_method(), "idc");
+    }
+
     #[test]
     fn test_from_json() {
         let json = r#"{
@@ -321,6 +602,8 @@ mod tests {
             refresh_token: None,
             profile_arn: None,
             expires_at: None,
+            refresh_token_issued_at: None,
+            refresh_token_rotated_at: None,
             auth_method: Some("social".to_string()),
             client_id: None,
             client_secret: None,
@@ -330,6 +613,10 @@ mod tests {
             api_region: None,
             machine_id: None,
             email: None,
+            label: None,
+            notes: None,
+            daily_request_limit: None,
+            canary_percent: None,
             subscription_title: None,
             proxy_url: None,
             proxy_username: None,
@@ -344,6 +631,31 @@ mod tests {
         assert!(!json.contains("priority"));
     }
 
+    #[test]
+    fn test_label_and_notes_fields() {
+        let json = r#"{"refreshToken": "x", "label": "work account, eu-west-1", "notes": "shared with ops"}"#;
+        let creds = KiroCredentials::from_json(json).unwrap();
+        assert_eq!(creds.label.as_deref(), Some("work account, eu-west-1"));
+        assert_eq!(creds.notes.as_deref(), Some("shared with ops"));
+        assert_eq!(creds.describe(3), "#3（work account, eu-west-1）");
+
+        let json = creds.to_pretty_json().unwrap();
+        assert!(json.contains("\"label\""));
+        assert!(json.contains("\"notes\""));
+    }
+
+    #[test]
+    fn test_display_name_falls_back_to_email() {
+        let mut creds = KiroCredentials {
+            email: Some("user@example.com".to_string()),
+            label: Some("  ".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(creds.display_name(), Some("user@example.com"));
+        creds.email = None;
+        assert_eq!(creds.describe(7), "#7");
+    }
+
     #[test]
     fn test_default_credentials_path() {
         assert_eq!(
@@ -438,6 +750,8 @@ mod tests {
             refresh_token: Some("test".to_string()),
             profile_arn: None,
             expires_at: None,
+            refresh_token_issued_at: None,
+            refresh_token_rotated_at: None,
             auth_method: None,
             client_id: None,
             client_secret: None,
@@ -447,6 +761,10 @@ mod tests {
             api_region: None,
             machine_id: None,
             email: None,
+            label: None,
+            notes: None,
+            daily_request_limit: None,
+            canary_percent: None,
             subscription_title: None,
             proxy_url: None,
             proxy_username: None,
@@ -467,6 +785,8 @@ mod tests {
             refresh_token: Some("test".to_string()),
             profile_arn: None,
             expires_at: None,
+            refresh_token_issued_at: None,
+            refresh_token_rotated_at: None,
             auth_method: None,
             client_id: None,
             client_secret: None,
@@ -476,6 +796,10 @@ mod tests {
             api_region: None,
             machine_id: None,
             email: None,
+            label: None,
+            notes: None,
+            daily_request_limit: None,
+            canary_percent: None,
             subscription_title: None,
             proxy_url: None,
             proxy_username: None,
@@ -578,6 +902,8 @@ mod tests {
             refresh_token: Some("refresh".to_string()),
             profile_arn: None,
             expires_at: None,
+            refresh_token_issued_at: None,
+            refresh_token_rotated_at: None,
             auth_method: Some("social".to_string()),
             client_id: None,
             client_secret: None,
@@ -587,6 +913,10 @@ mod tests {
             api_region: None,
             machine_id: Some("c".repeat(64)),
             email: None,
+            label: None,
+            notes: None,
+            daily_request_limit: Some(500),
+            canary_percent: Some(5),
             subscription_title: None,
             proxy_url: None,
             proxy_username: None,
@@ -602,6 +932,8 @@ mod tests {
         assert_eq!(parsed.priority, original.priority);
         assert_eq!(parsed.region, original.region);
         assert_eq!(parsed.machine_id, original.machine_id);
+        assert_eq!(parsed.daily_request_limit, Some(500));
+        assert_eq!(parsed.canary_percent, Some(5));
     }
 
     // ============ auth_region / api_region 字段测试 ============
@@ -795,6 +1127,34 @@ mod tests {
         assert_eq!(creds.effective_api_region(&config), "api-only");
     }
 
+    #[test]
+    fn test_effective_usage_limits_region() {
+        let mut config = Config::default();
+        config.region = "config-region".to_string();
+
+        let idc = KiroCredentials {
+            auth_method: Some("idc".to_string()),
+            region: Some("cred-region".to_string()),
+            ..Default::default()
+        };
+        let social = KiroCredentials {
+            auth_method: Some("social".to_string()),
+            region: Some("cred-region".to_string()),
+            ..Default::default()
+        };
+
+        // IdC 凭据按凭据自身的区域查询额度，Social 凭据与 API Region 相同
+        assert_eq!(idc.effective_usage_limits_region(&config), "cred-region");
+        assert_eq!(
+            social.effective_usage_limits_region(&config),
+            "config-region"
+        );
+
+        // 关闭后恢复原有行为
+        config.usage_limits_credential_region = false;
+        assert_eq!(idc.effective_usage_limits_region(&config), "config-region");
+    }
+
     // ============ 凭据级代理优先级测试 ============
 
     #[test]
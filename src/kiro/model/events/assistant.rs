@@ -7,7 +7,7 @@ use serde::{Deserialize, Serialize};
 use crate::kiro::parser::error::ParseResult;
 use crate::kiro::parser::frame::Frame;
 
-use super::base::EventPayload;
+use super::base::{EventPayload, EventType};
 
 /// 助手响应事件
 ///
@@ -43,7 +43,7 @@ pub struct AssistantResponseEvent {
 
 impl EventPayload for AssistantResponseEvent {
     fn from_frame(frame: &Frame) -> ParseResult<Self> {
-        frame.payload_as_json()
+        super::protocol::decode_payload(frame, EventType::AssistantResponse.as_str())
     }
 }
 
@@ -16,6 +16,10 @@ pub enum EventType {
     Metering,
     /// 上下文使用率事件
     ContextUsage,
+    /// 引用事件
+    Citation,
+    /// 补充网页链接事件
+    SupplementaryWebLinks,
     /// 未知事件类型
     Unknown,
 }
@@ -28,6 +32,8 @@ impl EventType {
             "toolUseEvent" => Self::ToolUse,
             "meteringEvent" => Self::Metering,
             "contextUsageEvent" => Self::ContextUsage,
+            "citationEvent" => Self::Citation,
+            "supplementaryWebLinksEvent" => Self::SupplementaryWebLinks,
             _ => Self::Unknown,
         }
     }
@@ -39,6 +45,8 @@ impl EventType {
             Self::ToolUse => "toolUseEvent",
             Self::Metering => "meteringEvent",
             Self::ContextUsage => "contextUsageEvent",
+            Self::Citation => "citationEvent",
+            Self::SupplementaryWebLinks => "supplementaryWebLinksEvent",
             Self::Unknown => "unknown",
         }
     }
@@ -71,8 +79,17 @@ pub enum Event {
     Metering(()),
     /// 上下文使用率
     ContextUsage(super::ContextUsageEvent),
-    /// 未知事件 (保留原始帧数据)
-    Unknown {},
+    /// 引用
+    Citation(super::CitationEvent),
+    /// 补充网页链接
+    SupplementaryWebLinks(super::SupplementaryWebLinksEvent),
+    /// 未知事件（解析时已记录警告，保留原始帧数据）
+    Unknown {
+        /// 事件类型
+        event_type: String,
+        /// 原始 payload
+        payload: Vec<u8>,
+    },
     /// 服务端错误
     Error {
         /// 错误代码
@@ -94,12 +111,14 @@ impl Event {
     pub fn from_frame(frame: Frame) -> ParseResult<Self> {
         let message_type = frame.message_type().unwrap_or("event");
 
+        let event_type = frame.event_type().unwrap_or("unknown").to_string();
         match message_type {
             "event" => Self::parse_event(frame),
             "error" => Self::parse_error(frame),
             "exception" => Self::parse_exception(frame),
             other => Err(ParseError::InvalidMessageType(other.to_string())),
         }
+        .inspect_err(|e| tracing::warn!("解析上游事件 {} 失败，已忽略: {}", event_type, e))
     }
 
     /// 解析事件类型消息
@@ -121,7 +140,21 @@ impl Event {
                 let payload = super::ContextUsageEvent::from_frame(&frame)?;
                 Ok(Self::ContextUsage(payload))
             }
-            EventType::Unknown => Ok(Self::Unknown {}),
+            EventType::Citation => {
+                let payload = super::CitationEvent::from_frame(&frame)?;
+                Ok(Self::Citation(payload))
+            }
+            EventType::SupplementaryWebLinks => {
+                let payload = super::SupplementaryWebLinksEvent::from_frame(&frame)?;
+                Ok(Self::SupplementaryWebLinks(payload))
+            }
+            EventType::Unknown => {
+                super::protocol::report_unknown_event(event_type_str, &frame.payload);
+                Ok(Self::Unknown {
+                    event_type: event_type_str.to_string(),
+                    payload: frame.payload,
+                })
+            }
         }
     }
 
@@ -183,4 +216,27 @@ mod tests {
         );
         assert_eq!(EventType::ToolUse.as_str(), "toolUseEvent");
     }
+
+    #[test]
+    fn test_unknown_event_keeps_raw_payload() {
+        let mut headers = crate::kiro::parser::header::Headers::new();
+        headers.insert(
+            ":event-type".to_string(),
+            crate::kiro::parser::header::HeaderValue::String("futureEvent".to_string()),
+        );
+        let frame = Frame {
+            headers,
+            payload: b"{}".to_vec(),
+        };
+        match Event::from_frame(frame).unwrap() {
+            Event::Unknown {
+                event_type,
+                payload,
+            } => {
+                assert_eq!(event_type, "futureEvent");
+                assert_eq!(payload, b"{}");
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
 }
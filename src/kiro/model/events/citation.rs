@@ -0,0 +1,86 @@
+//! 引用事件
+//!
+//! 处理 citationEvent 与 supplementaryWebLinksEvent 类型的事件：
+//! 联网搜索或文档问答时上游返回的来源信息
+
+use serde::Deserialize;
+
+use crate::kiro::parser::error::ParseResult;
+use crate::kiro::parser::frame::Frame;
+
+use super::base::{EventPayload, EventType};
+
+/// 引用事件
+///
+/// 回答中引用的一段来源内容
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CitationEvent {
+    /// 被引用的原文
+    #[serde(default)]
+    pub citation_text: Option<String>,
+    /// 来源链接
+    #[serde(default)]
+    pub citation_link: Option<String>,
+}
+
+impl EventPayload for CitationEvent {
+    fn from_frame(frame: &Frame) -> ParseResult<Self> {
+        super::protocol::decode_payload(frame, EventType::Citation.as_str())
+    }
+}
+
+/// 补充网页链接事件
+///
+/// 回答参考的网页列表
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupplementaryWebLinksEvent {
+    #[serde(default)]
+    pub supplementary_web_links: Vec<WebLink>,
+}
+
+/// 网页链接
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebLink {
+    pub url: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// 网页摘要
+    #[serde(default)]
+    pub snippet: Option<String>,
+}
+
+impl EventPayload for SupplementaryWebLinksEvent {
+    fn from_frame(frame: &Frame) -> ParseResult<Self> {
+        super::protocol::decode_payload(frame, EventType::SupplementaryWebLinks.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_citation_events() {
+        let citation: CitationEvent = serde_json::from_str(
+            r#"{"target":{"location":12},"citationText":"Rust 1.0 was released in 2015","citationLink":"https://blog.rust-lang.org/2015/05/15/Rust-1.0.html"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            citation.citation_link.as_deref(),
+            Some("https://blog.rust-lang.org/2015/05/15/Rust-1.0.html")
+        );
+
+        let links: SupplementaryWebLinksEvent = serde_json::from_str(
+            r#"{"supplementaryWebLinks":[{"url":"https://www.rust-lang.org","title":"Rust","snippet":"A language empowering everyone","score":0.9}]}"#,
+        )
+        .unwrap();
+        assert_eq!(links.supplementary_web_links.len(), 1);
+        assert_eq!(
+            links.supplementary_web_links[0].title.as_deref(),
+            Some("Rust")
+        );
+    }
+}
@@ -7,7 +7,7 @@ use serde::Deserialize;
 use crate::kiro::parser::error::ParseResult;
 use crate::kiro::parser::frame::Frame;
 
-use super::base::EventPayload;
+use super::base::{EventPayload, EventType};
 
 /// 上下文使用率事件
 ///
@@ -22,7 +22,7 @@ pub struct ContextUsageEvent {
 
 impl EventPayload for ContextUsageEvent {
     fn from_frame(frame: &Frame) -> ParseResult<Self> {
-        frame.payload_as_json()
+        super::protocol::decode_payload(frame, EventType::ContextUsage.as_str())
     }
 }
 
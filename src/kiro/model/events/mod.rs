@@ -4,10 +4,13 @@
 
 mod assistant;
 mod base;
+mod citation;
 mod context_usage;
+pub mod protocol;
 mod tool_use;
 
 pub use assistant::AssistantResponseEvent;
 pub use base::Event;
+pub use citation::{CitationEvent, SupplementaryWebLinksEvent};
 pub use context_usage::ContextUsageEvent;
 pub use tool_use::ToolUseEvent;
@@ -0,0 +1,164 @@
+//! 上游事件流协议版本处理
+//!
+//! AWS 偶尔会调整事件流的 payload 形态。目前已知两种格式：
+//! - flat：字段直接位于 payload 顶层（`{"content":"..."}`）
+//! - wrapped：字段包裹在与事件类型同名的键下（`{"assistantResponseEvent":{"content":"..."}}`）
+//!
+//! 默认（`upstreamEventFormat = auto`）按每个事件的 payload 自动识别，识别结果发生变化时记录警告；
+//! 固定为 flat / wrapped 时，payload 与固定格式不符会记录警告，仍按实际格式解析。
+//! 未知事件类型不再静默丢弃，每种类型首次出现时记录警告（含 payload 摘要）。
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{LazyLock, OnceLock};
+
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::kiro::parser::error::{ParseError, ParseResult};
+use crate::kiro::parser::frame::Frame;
+use crate::model::config::UpstreamEventFormat;
+
+/// 全局固定格式（启动时初始化，未初始化时自动识别）
+static FORMAT: OnceLock<UpstreamEventFormat> = OnceLock::new();
+
+/// 最近一次识别出的格式（0 表示尚未识别）
+static DETECTED: AtomicU8 = AtomicU8::new(0);
+
+/// 已记录过警告的未知事件类型
+static WARNED_UNKNOWN: LazyLock<Mutex<HashSet<String>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// 警告日志中 payload 摘要的最大字符数
+const PAYLOAD_PREVIEW_CHARS: usize = 256;
+
+/// 初始化上游事件格式
+pub fn init(format: UpstreamEventFormat) {
+    let _ = FORMAT.set(format);
+}
+
+fn format() -> UpstreamEventFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// 识别 payload 格式：只有一个与事件类型同名的对象字段时视为 wrapped
+fn detect(event_type: &str, value: &Value) -> UpstreamEventFormat {
+    match value.as_object() {
+        Some(object)
+            if object.len() == 1 && object.get(event_type).is_some_and(Value::is_object) =>
+        {
+            UpstreamEventFormat::Wrapped
+        }
+        _ => UpstreamEventFormat::Flat,
+    }
+}
+
+/// 记录自动识别结果，格式与上次不同时记录日志
+fn record_detected(format: UpstreamEventFormat) {
+    let code = match format {
+        UpstreamEventFormat::Auto => return,
+        UpstreamEventFormat::Flat => 1,
+        UpstreamEventFormat::Wrapped => 2,
+    };
+    match DETECTED.swap(code, Ordering::Relaxed) {
+        0 => tracing::info!("识别到上游事件格式: {:?}", format),
+        previous if previous != code => {
+            tracing::warn!(
+                "上游事件格式发生变化，当前为 {:?}，上游协议可能已更新",
+                format
+            )
+        }
+        _ => {}
+    }
+}
+
+/// 按配置的格式解析事件 payload
+pub(super) fn decode_payload<T: DeserializeOwned>(
+    frame: &Frame,
+    event_type: &str,
+) -> ParseResult<T> {
+    let pinned = format();
+    let mut value: Value = frame.payload_as_json()?;
+    let actual = detect(event_type, &value);
+    match pinned {
+        UpstreamEventFormat::Auto => record_detected(actual),
+        pinned if pinned != actual => tracing::warn!(
+            "{} 事件的 payload 格式为 {:?}，与配置的 upstreamEventFormat = {:?} 不符，按实际格式解析",
+            event_type,
+            actual,
+            pinned
+        ),
+        _ => {}
+    }
+    if actual == UpstreamEventFormat::Wrapped
+        && let Some(inner) = value.get_mut(event_type)
+    {
+        value = inner.take();
+    }
+    serde_json::from_value(value).map_err(ParseError::PayloadDeserialize)
+}
+
+/// 报告未知事件类型：每种类型首次出现时记录警告，之后记录为 debug
+///
+/// 返回是否为首次出现
+pub(super) fn report_unknown_event(event_type: &str, payload: &[u8]) -> bool {
+    let first = WARNED_UNKNOWN.lock().insert(event_type.to_string());
+    let preview: String = String::from_utf8_lossy(payload)
+        .chars()
+        .take(PAYLOAD_PREVIEW_CHARS)
+        .collect();
+    if first {
+        tracing::warn!(
+            "收到未知的上游事件类型 {}（{} 字节），已忽略，上游协议可能已更新: {}",
+            event_type,
+            payload.len(),
+            preview
+        );
+    } else {
+        tracing::debug!("忽略未知的上游事件类型 {}: {}", event_type, preview);
+    }
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::events::AssistantResponseEvent;
+    use crate::kiro::parser::header::Headers;
+
+    fn frame(payload: &str) -> Frame {
+        Frame {
+            headers: Headers::new(),
+            payload: payload.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_decode_flat_and_wrapped_payloads() {
+        let flat: AssistantResponseEvent =
+            decode_payload(&frame(r#"{"content":"Hi"}"#), "assistantResponseEvent").unwrap();
+        assert_eq!(flat.content, "Hi");
+
+        let wrapped: AssistantResponseEvent = decode_payload(
+            &frame(r#"{"assistantResponseEvent":{"content":"Hi"}}"#),
+            "assistantResponseEvent",
+        )
+        .unwrap();
+        assert_eq!(wrapped.content, "Hi");
+
+        // 与事件类型同名但不是对象的字段按 flat 处理
+        let value = serde_json::json!({"assistantResponseEvent": "x"});
+        assert_eq!(
+            detect("assistantResponseEvent", &value),
+            UpstreamEventFormat::Flat
+        );
+    }
+
+    #[test]
+    fn test_report_unknown_event_once() {
+        assert!(report_unknown_event("protocolTestEvent", b"{\"a\":1}"));
+        assert!(!report_unknown_event("protocolTestEvent", b"{\"a\":2}"));
+        assert!(report_unknown_event("anotherFutureEvent", b""));
+    }
+}
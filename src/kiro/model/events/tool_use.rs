@@ -7,7 +7,7 @@ use serde::Deserialize;
 use crate::kiro::parser::error::ParseResult;
 use crate::kiro::parser::frame::Frame;
 
-use super::base::EventPayload;
+use super::base::{EventPayload, EventType};
 
 /// 工具使用事件
 ///
@@ -29,7 +29,7 @@ pub struct ToolUseEvent {
 
 impl EventPayload for ToolUseEvent {
     fn from_frame(frame: &Frame) -> ParseResult<Self> {
-        frame.payload_as_json()
+        super::protocol::decode_payload(frame, EventType::ToolUse.as_str())
     }
 }
 
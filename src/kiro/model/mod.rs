@@ -5,10 +5,12 @@
 //! - `events`: 响应事件类型
 //! - `requests`: 请求类型
 //! - `credentials`: OAuth 凭证
+//! - `credential_builder`: 凭据构建与校验
 //! - `token_refresh`: Token 刷新
 //! - `usage_limits`: 使用额度查询
 
 pub mod common;
+pub mod credential_builder;
 pub mod credentials;
 pub mod events;
 pub mod requests;
@@ -1,83 +1,143 @@
 //! 使用额度查询数据模型
 //!
 //! 包含 getUsageLimits API 的响应类型定义
+//!
+//! AWS 会不定期在响应中增加字段或调整字段类型。为避免整个余额查询因此失败：
+//! - 所有字段均可缺省，类型不符的字段按缺省处理并记录警告（部分数据仍可使用）
+//! - 未知字段保存在各结构的 `extra` 中，便于排查上游变化
+
+use serde::{Deserialize, Deserializer, de::DeserializeOwned};
+use serde_json::{Map, Value};
+
+/// 宽松解析：字段为 null 或类型不符时返回缺省值（类型不符时记录警告）
+fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default,
+{
+    let value = Value::deserialize(deserializer)?;
+    if value.is_null() {
+        return Ok(T::default());
+    }
+    Ok(serde_json::from_value(value.clone()).unwrap_or_else(|e| {
+        tracing::warn!("getUsageLimits 响应字段类型不符，已忽略: {} ({})", value, e);
+        T::default()
+    }))
+}
 
-use serde::Deserialize;
+/// 宽松解析列表：无法解析的元素被跳过
+fn lenient_list<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let items: Vec<Value> = lenient(deserializer)?;
+    Ok(items
+        .into_iter()
+        .filter_map(|item| {
+            serde_json::from_value(item.clone())
+                .inspect_err(|e| {
+                    tracing::warn!(
+                        "getUsageLimits 响应列表元素无法解析，已跳过: {} ({})",
+                        item,
+                        e
+                    )
+                })
+                .ok()
+        })
+        .collect())
+}
 
 /// 使用额度查询响应
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UsageLimitsResponse {
     /// 下次重置日期 (Unix 时间戳)
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient")]
     pub next_date_reset: Option<f64>,
 
     /// 订阅信息
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient")]
     pub subscription_info: Option<SubscriptionInfo>,
 
     /// 使用量明细列表
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_list")]
     pub usage_breakdown_list: Vec<UsageBreakdown>,
+
+    /// 未知字段
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// 订阅信息
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SubscriptionInfo {
     /// 订阅标题 (KIRO PRO+ / KIRO FREE 等)
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient")]
     pub subscription_title: Option<String>,
+
+    /// 未知字段
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// 使用量明细
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UsageBreakdown {
     /// 当前使用量
-    #[serde(default)]
-    pub current_usage: i64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub current_usage: Option<f64>,
 
     /// 当前使用量（精确值）
-    #[serde(default)]
-    pub current_usage_with_precision: f64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub current_usage_with_precision: Option<f64>,
 
     /// 奖励额度列表
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient_list")]
     pub bonuses: Vec<Bonus>,
 
     /// 免费试用信息
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient")]
     pub free_trial_info: Option<FreeTrialInfo>,
 
     /// 下次重置日期 (Unix 时间戳)
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient")]
     pub next_date_reset: Option<f64>,
 
     /// 使用限额
-    #[serde(default)]
-    pub usage_limit: i64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub usage_limit: Option<f64>,
 
     /// 使用限额（精确值）
-    #[serde(default)]
-    pub usage_limit_with_precision: f64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub usage_limit_with_precision: Option<f64>,
+
+    /// 未知字段
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// 奖励额度
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Bonus {
     /// 当前使用量
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient")]
     pub current_usage: f64,
 
     /// 使用限额
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient")]
     pub usage_limit: f64,
 
     /// 状态 (ACTIVE / EXPIRED)
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient")]
     pub status: Option<String>,
+
+    /// 未知字段
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 impl Bonus {
@@ -91,32 +151,36 @@ impl Bonus {
 }
 
 /// 免费试用信息
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FreeTrialInfo {
     /// 当前使用量
-    #[serde(default)]
-    pub current_usage: i64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub current_usage: Option<f64>,
 
     /// 当前使用量（精确值）
-    #[serde(default)]
-    pub current_usage_with_precision: f64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub current_usage_with_precision: Option<f64>,
 
     /// 免费试用过期时间 (Unix 时间戳)
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient")]
     pub free_trial_expiry: Option<f64>,
 
     /// 免费试用状态 (ACTIVE / EXPIRED)
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lenient")]
     pub free_trial_status: Option<String>,
 
     /// 使用限额
-    #[serde(default)]
-    pub usage_limit: i64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub usage_limit: Option<f64>,
 
     /// 使用限额（精确值）
-    #[serde(default)]
-    pub usage_limit_with_precision: f64,
+    #[serde(default, deserialize_with = "lenient")]
+    pub usage_limit_with_precision: Option<f64>,
+
+    /// 未知字段
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 // ============ 便捷方法实现 ============
@@ -129,6 +193,36 @@ impl FreeTrialInfo {
             .map(|s| s == "ACTIVE")
             .unwrap_or(false)
     }
+
+    /// 使用限额（优先使用精确值）
+    fn limit(&self) -> f64 {
+        self.usage_limit_with_precision
+            .or(self.usage_limit)
+            .unwrap_or_default()
+    }
+
+    /// 当前使用量（优先使用精确值）
+    fn usage(&self) -> f64 {
+        self.current_usage_with_precision
+            .or(self.current_usage)
+            .unwrap_or_default()
+    }
+}
+
+impl UsageBreakdown {
+    /// 使用限额（优先使用精确值）
+    fn limit(&self) -> f64 {
+        self.usage_limit_with_precision
+            .or(self.usage_limit)
+            .unwrap_or_default()
+    }
+
+    /// 当前使用量（优先使用精确值）
+    fn usage(&self) -> f64 {
+        self.current_usage_with_precision
+            .or(self.current_usage)
+            .unwrap_or_default()
+    }
 }
 
 impl UsageLimitsResponse {
@@ -139,6 +233,35 @@ impl UsageLimitsResponse {
             .and_then(|info| info.subscription_title.as_deref())
     }
 
+    /// 列出响应中的未知字段（含嵌套结构，形如 `usageBreakdownList[0].newField`）
+    pub fn unknown_fields(&self) -> Vec<String> {
+        let mut fields: Vec<String> = self.extra.keys().cloned().collect();
+        if let Some(info) = &self.subscription_info {
+            fields.extend(info.extra.keys().map(|k| format!("subscriptionInfo.{}", k)));
+        }
+        for (i, breakdown) in self.usage_breakdown_list.iter().enumerate() {
+            let prefix = format!("usageBreakdownList[{}]", i);
+            fields.extend(breakdown.extra.keys().map(|k| format!("{}.{}", prefix, k)));
+            if let Some(trial) = &breakdown.free_trial_info {
+                fields.extend(
+                    trial
+                        .extra
+                        .keys()
+                        .map(|k| format!("{}.freeTrialInfo.{}", prefix, k)),
+                );
+            }
+            for (j, bonus) in breakdown.bonuses.iter().enumerate() {
+                fields.extend(
+                    bonus
+                        .extra
+                        .keys()
+                        .map(|k| format!("{}.bonuses[{}].{}", prefix, j, k)),
+                );
+            }
+        }
+        fields
+    }
+
     /// 获取第一个使用量明细
     fn primary_breakdown(&self) -> Option<&UsageBreakdown> {
         self.usage_breakdown_list.first()
@@ -152,12 +275,12 @@ impl UsageLimitsResponse {
             return 0.0;
         };
 
-        let mut total = breakdown.usage_limit_with_precision;
+        let mut total = breakdown.limit();
 
         // 累加激活的 free trial 额度
         if let Some(trial) = &breakdown.free_trial_info {
             if trial.is_active() {
-                total += trial.usage_limit_with_precision;
+                total += trial.limit();
             }
         }
 
@@ -179,12 +302,12 @@ impl UsageLimitsResponse {
             return 0.0;
         };
 
-        let mut total = breakdown.current_usage_with_precision;
+        let mut total = breakdown.usage();
 
         // 累加激活的 free trial 使用量
         if let Some(trial) = &breakdown.free_trial_info {
             if trial.is_active() {
-                total += trial.current_usage_with_precision;
+                total += trial.usage();
             }
         }
 
@@ -198,3 +321,44 @@ impl UsageLimitsResponse {
         total
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_partial_and_unknown_fields() {
+        let usage: UsageLimitsResponse = serde_json::from_value(serde_json::json!({
+            "subscriptionInfo": {"subscriptionTitle": "KIRO PRO", "tier": "PRO"},
+            "usageBreakdownList": [{
+                "currentUsage": 12.5,
+                "usageLimit": 100,
+                "bonuses": null,
+                "freeTrialInfo": {
+                    "freeTrialStatus": "ACTIVE",
+                    "usageLimitWithPrecision": 50.0,
+                    "currentUsageWithPrecision": "n/a"
+                },
+                "overageConfiguration": {"enabled": false}
+            }, "unexpected"],
+            "nextDateReset": "soon",
+            "userInfo": {"userId": "u-1"}
+        }))
+        .unwrap();
+
+        // 类型不符的字段按缺省处理，精确值缺失时回退到整数字段
+        assert_eq!(usage.subscription_title(), Some("KIRO PRO"));
+        assert_eq!(usage.next_date_reset, None);
+        assert_eq!(usage.usage_breakdown_list.len(), 1);
+        assert_eq!(usage.usage_limit(), 150.0);
+        assert_eq!(usage.current_usage(), 12.5);
+        assert_eq!(
+            usage.unknown_fields(),
+            [
+                "userInfo",
+                "subscriptionInfo.tier",
+                "usageBreakdownList[0].overageConfiguration"
+            ]
+        );
+    }
+}
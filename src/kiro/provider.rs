@@ -4,26 +4,199 @@
 //! 支持流式和非流式请求
 //! 支持多凭据故障转移和重试
 
+use bytes::Bytes;
 use reqwest::Client;
 use reqwest::header::{AUTHORIZATION, CONNECTION, CONTENT_TYPE, HOST, HeaderMap, HeaderValue};
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::time::sleep;
 use uuid::Uuid;
 
-use crate::http_client::{ProxyConfig, build_client};
+use crate::common::clock;
+use crate::http_client::{ClientOptions, ProxyConfig, build_client_with_options};
+use crate::kiro::log_sanitizer;
 use crate::kiro::machine_id;
 use crate::kiro::model::credentials::KiroCredentials;
-use crate::kiro::token_manager::{CallContext, MultiTokenManager};
-use crate::model::config::TlsBackend;
+use crate::kiro::token_manager::{CallContext, FailureKind, MultiTokenManager};
+use crate::model::config::endpoint_host;
 use parking_lot::Mutex;
 
 /// 每个凭据的最大重试次数
 const MAX_RETRIES_PER_CREDENTIAL: usize = 3;
 
-/// 总重试次数硬上限（避免无限重试）
-const MAX_TOTAL_RETRIES: usize = 9;
+/// 预热备用凭据时 TLS 预连接的超时
+const PRECONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 上游请求超时
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpstreamTimeout {
+    /// 本次请求的超时时间
+    pub timeout: Duration,
+}
+
+impl std::fmt::Display for UpstreamTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "上游请求超时（{}秒）", self.timeout.as_secs())
+    }
+}
+
+impl std::error::Error for UpstreamTimeout {}
+
+/// 上游调用成功的响应
+pub struct UpstreamResponse {
+    /// 原始 HTTP 响应（不做解析）
+    pub response: reqwest::Response,
+    /// 实际重试次数
+    pub retries: usize,
+    /// 本次响应使用的凭据 ID
+    pub credential_id: u64,
+    /// 请求的上游模型（modelId），用于按模型记录响应字节数
+    pub model: Option<String>,
+}
+
+/// 上游返回错误状态码（400 或重试耗尽后的 5xx），保留响应体供调试转储使用
+#[derive(Debug, Clone)]
+pub struct UpstreamStatusError {
+    /// 流式 / 非流式
+    pub api_type: &'static str,
+    pub status: reqwest::StatusCode,
+    pub body: String,
+}
+
+impl std::fmt::Display for UpstreamStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} API 请求失败: {} {}",
+            self.api_type, self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for UpstreamStatusError {}
+
+/// 一次失败的上游尝试
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attempt {
+    /// 使用的凭据 ID（未能获取凭据时为空）
+    pub credential_id: Option<u64>,
+    /// 上游状态码（请求未送达时为空）
+    pub status: Option<u16>,
+    /// 失败分类（如 `network`、`throttled`、`auth`）
+    pub error: &'static str,
+    /// 下一次尝试前的等待时间
+    pub delay: Duration,
+}
+
+/// 上游调用失败时的逐次尝试记录
+///
+/// 作为 context 附加在最终错误上（不影响对 [`UpstreamTimeout`] 等类型的 downcast），
+/// 显示为最终错误信息加上尝试记录
+#[derive(Debug, Clone)]
+pub struct AttemptTrace {
+    /// 最终错误信息
+    message: String,
+    /// 按顺序排列的失败尝试
+    pub attempts: Vec<Attempt>,
+}
+
+impl AttemptTrace {
+    /// 在错误上附加尝试记录（没有记录时原样返回）
+    pub fn attach(e: anyhow::Error, attempts: Vec<Attempt>) -> anyhow::Error {
+        if attempts.is_empty() {
+            return e;
+        }
+        let message = e.to_string();
+        e.context(Self { message, attempts })
+    }
+
+    /// `x-kiro-attempts` 响应头的值：逗号分隔的尝试，每项为 `;` 分隔的 `键=值`
+    /// （如 `cred=1;status=429;error=throttled;delay_ms=230, cred=2;error=network;delay_ms=0`）
+    pub fn header_value(&self) -> String {
+        self.attempts
+            .iter()
+            .map(|a| {
+                let mut item = String::new();
+                if let Some(id) = a.credential_id {
+                    item.push_str(&format!("cred={};", id));
+                }
+                if let Some(status) = a.status {
+                    item.push_str(&format!("status={};", status));
+                }
+                format!("{}error={};delay_ms={}", item, a.error, a.delay.as_millis())
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl std::fmt::Display for AttemptTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}（尝试记录：", self.message)?;
+        for (i, a) in self.attempts.iter().enumerate() {
+            if i > 0 {
+                write!(f, "；")?;
+            }
+            write!(f, "#{}", i + 1)?;
+            if let Some(id) = a.credential_id {
+                write!(f, " 凭据 {}", id)?;
+            }
+            if let Some(status) = a.status {
+                write!(f, " {}", status)?;
+            }
+            write!(f, " {}", a.error)?;
+            if !a.delay.is_zero() {
+                write!(f, "，等待 {}ms", a.delay.as_millis())?;
+            }
+        }
+        write!(f, "）")
+    }
+}
+
+/// 一次请求的请求体
+///
+/// 重试与故障转移期间只解析一次；仅当所选凭据的 profileArn 与请求体中的不同时，
+/// 替换该字段并重新序列化，否则复用原始字节（克隆 [`Bytes`] 不复制数据）。
+struct RequestBody {
+    /// 原始请求体
+    raw: Bytes,
+    /// 解析后的 JSON（解析失败时原样发送）
+    value: Option<serde_json::Value>,
+}
+
+impl RequestBody {
+    fn new(request_body: &str) -> Self {
+        Self {
+            raw: Bytes::copy_from_slice(request_body.as_bytes()),
+            value: serde_json::from_str(request_body).ok(),
+        }
+    }
+
+    /// 提取 conversationState.currentMessage.userInputMessage.modelId
+    fn model(&self) -> Option<String> {
+        self.value
+            .as_ref()?
+            .pointer("/conversationState/currentMessage/userInputMessage/modelId")?
+            .as_str()
+            .map(|s| s.to_string())
+    }
+
+    /// 生成发送给指定凭据的请求体（凭据未配置 profileArn 时保留请求体中的值）
+    fn for_credentials(&self, credentials: &KiroCredentials) -> Bytes {
+        let (Some(value), Some(arn)) = (&self.value, &credentials.profile_arn) else {
+            return self.raw.clone();
+        };
+        if value.get("profileArn").and_then(|v| v.as_str()) == Some(arn.as_str()) {
+            return self.raw.clone();
+        }
+        let mut value = value.clone();
+        value["profileArn"] = serde_json::Value::String(arn.clone());
+        serde_json::to_vec(&value)
+            .map(Bytes::from)
+            .unwrap_or_else(|_| self.raw.clone())
+    }
+}
 
 /// Kiro API Provider
 ///
@@ -36,8 +209,10 @@ pub struct KiroProvider {
     /// Client 缓存：key = effective proxy config, value = reqwest::Client
     /// 不同代理配置的凭据使用不同的 Client，共享相同代理的凭据复用 Client
     client_cache: Mutex<HashMap<Option<ProxyConfig>, Client>>,
-    /// TLS 后端配置
-    tls_backend: TlsBackend,
+    /// HTTP Client 构建选项（TLS 后端与超时）
+    client_options: ClientOptions,
+    /// 各备用凭据最近一次预热的时间（warmStandbySecs）
+    warmed_standby: Mutex<HashMap<u64, Instant>>,
 }
 
 impl KiroProvider {
@@ -48,9 +223,9 @@ impl KiroProvider {
 
     /// 创建带代理配置的 KiroProvider 实例
     pub fn with_proxy(token_manager: Arc<MultiTokenManager>, proxy: Option<ProxyConfig>) -> Self {
-        let tls_backend = token_manager.config().tls_backend;
+        let client_options = ClientOptions::for_api(token_manager.config());
         // 预热：构建全局代理对应的 Client
-        let initial_client = build_client(proxy.as_ref(), 720, tls_backend)
+        let initial_client = build_client_with_options(proxy.as_ref(), &client_options)
             .expect("创建 HTTP 客户端失败");
         let mut cache = HashMap::new();
         cache.insert(proxy.clone(), initial_client);
@@ -59,7 +234,8 @@ impl KiroProvider {
             token_manager,
             global_proxy: proxy,
             client_cache: Mutex::new(cache),
-            tls_backend,
+            client_options,
+            warmed_standby: Mutex::new(HashMap::new()),
         }
     }
 
@@ -70,11 +246,16 @@ impl KiroProvider {
         if let Some(client) = cache.get(&effective) {
             return Ok(client.clone());
         }
-        let client = build_client(effective.as_ref(), 720, self.tls_backend)?;
+        let client = build_client_with_options(effective.as_ref(), &self.client_options)?;
         cache.insert(effective, client.clone());
         Ok(client)
     }
 
+    /// 获取全局代理配置
+    pub fn global_proxy(&self) -> Option<&ProxyConfig> {
+        self.global_proxy.as_ref()
+    }
+
     /// 获取 token_manager 的引用
     pub fn token_manager(&self) -> &MultiTokenManager {
         &self.token_manager
@@ -82,64 +263,97 @@ impl KiroProvider {
 
     /// 获取 API 基础 URL（使用 config 级 api_region）
     pub fn base_url(&self) -> String {
-        format!(
-            "https://q.{}.amazonaws.com/generateAssistantResponse",
-            self.token_manager.config().effective_api_region()
-        )
+        let config = self.token_manager.config();
+        config.endpoints.api_url(config.effective_api_region())
     }
 
     /// 获取 MCP API URL（使用 config 级 api_region）
     pub fn mcp_url(&self) -> String {
-        format!(
-            "https://q.{}.amazonaws.com/mcp",
-            self.token_manager.config().effective_api_region()
-        )
+        let config = self.token_manager.config();
+        config.endpoints.mcp_url(config.effective_api_region())
     }
 
     /// 获取 API 基础域名（使用 config 级 api_region）
     pub fn base_domain(&self) -> String {
-        format!("q.{}.amazonaws.com", self.token_manager.config().effective_api_region())
+        endpoint_host(&self.base_url())
     }
 
     /// 获取凭据级 API 基础 URL
     fn base_url_for(&self, credentials: &KiroCredentials) -> String {
-        format!(
-            "https://q.{}.amazonaws.com/generateAssistantResponse",
-            credentials.effective_api_region(self.token_manager.config())
-        )
+        let config = self.token_manager.config();
+        config
+            .endpoints
+            .api_url(credentials.effective_api_region(config))
     }
 
     /// 获取凭据级 MCP API URL
+    #[cfg(feature = "mcp")]
     fn mcp_url_for(&self, credentials: &KiroCredentials) -> String {
-        format!(
-            "https://q.{}.amazonaws.com/mcp",
-            credentials.effective_api_region(self.token_manager.config())
-        )
+        let config = self.token_manager.config();
+        config
+            .endpoints
+            .mcp_url(credentials.effective_api_region(config))
     }
 
     /// 获取凭据级 API 基础域名
     fn base_domain_for(&self, credentials: &KiroCredentials) -> String {
-        format!(
-            "q.{}.amazonaws.com",
-            credentials.effective_api_region(self.token_manager.config())
-        )
+        endpoint_host(&self.base_url_for(credentials))
     }
 
-    /// 从请求体中提取模型信息
+    /// 在后台预热故障转移时的备用凭据（warmStandbySecs，实验性）
     ///
-    /// 尝试解析 JSON 请求体，提取 conversationState.currentMessage.userInputMessage.modelId
-    fn extract_model_from_request(request_body: &str) -> Option<String> {
-        use serde_json::Value;
-
-        let json: Value = serde_json::from_str(request_body).ok()?;
+    /// 确认备用凭据的 Token 有效（即将过期时提前刷新）；启用 warmStandbyPreconnect 时
+    /// 再向其上游域名发送 HEAD 请求建立 TLS 连接。备用凭据与流中断后重试实际选中的凭据一致
+    /// （见 [`MultiTokenManager::standby_candidate`]），换用该凭据时可直接发送请求
+    fn warm_standby(&self, model: Option<&str>, affinity_key: Option<&str>, primary_id: u64) {
+        let config = self.token_manager.config();
+        if config.warm_standby_secs == 0 {
+            return;
+        }
+        let Some((id, credentials)) =
+            self.token_manager
+                .standby_candidate(model, affinity_key, primary_id)
+        else {
+            return;
+        };
+        {
+            let mut warmed = self.warmed_standby.lock();
+            let now = Instant::now();
+            let interval = Duration::from_secs(config.warm_standby_secs);
+            if warmed
+                .get(&id)
+                .is_some_and(|at| now.duration_since(*at) < interval)
+            {
+                return;
+            }
+            warmed.insert(id, now);
+        }
 
-        // 尝试提取 conversationState.currentMessage.userInputMessage.modelId
-        json.get("conversationState")?
-            .get("currentMessage")?
-            .get("userInputMessage")?
-            .get("modelId")?
-            .as_str()
-            .map(|s| s.to_string())
+        let preconnect = if config.warm_standby_preconnect {
+            let origin = reqwest::Url::parse(&self.base_url_for(&credentials))
+                .map(|url| url.origin().ascii_serialization());
+            self.client_for(&credentials).ok().zip(origin.ok())
+        } else {
+            None
+        };
+        let token_manager = self.token_manager.clone();
+        tokio::spawn(async move {
+            if let Err(e) = token_manager.warm_token(id).await {
+                tracing::debug!("预热备用凭据 #{} 失败: {}", id, e);
+                return;
+            }
+            if let Some((client, origin)) = preconnect
+                && let Err(e) = client
+                    .head(&origin)
+                    .timeout(PRECONNECT_TIMEOUT)
+                    .send()
+                    .await
+            {
+                tracing::debug!("备用凭据 #{} 预连接 {} 失败: {}", id, origin, e);
+                return;
+            }
+            tracing::debug!("已预热备用凭据 #{}", id);
+        });
     }
 
     /// 构建请求头
@@ -198,6 +412,7 @@ impl KiroProvider {
     }
 
     /// 构建 MCP 请求头
+    #[cfg(feature = "mcp")]
     fn build_mcp_headers(&self, ctx: &CallContext) -> anyhow::Result<HeaderMap> {
         let config = self.token_manager.config();
 
@@ -252,11 +467,31 @@ impl KiroProvider {
     ///
     /// # Arguments
     /// * `request_body` - JSON 格式的请求体字符串
+    /// * `affinity_key` - 可选的凭据亲和键（balanced 模式下生效）
+    /// * `pinned_id` - 可选的固定凭据 ID（跳过负载均衡，凭据失败时不切换）
+    /// * `timeout` - 可选的本次请求超时（覆盖 requestTimeoutSecs，包含重试与读取响应体）
+    /// * `max_retries` - 可选的本次请求重试次数上限（覆盖 maxRetries，0 表示不重试）
     ///
     /// # Returns
-    /// 返回原始的 HTTP Response，不做解析
-    pub async fn call_api(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        self.call_api_with_retry(request_body, false).await
+    /// 返回原始的 HTTP Response（不做解析）、实际重试次数与所用凭据；超时返回 [`UpstreamTimeout`] 错误
+    pub async fn call_api(
+        &self,
+        request_body: &str,
+        affinity_key: Option<&str>,
+        pinned_id: Option<u64>,
+        timeout: Option<Duration>,
+        max_retries: Option<usize>,
+    ) -> anyhow::Result<UpstreamResponse> {
+        self.call_api_with_retry(
+            request_body,
+            false,
+            affinity_key,
+            pinned_id,
+            None,
+            timeout,
+            max_retries,
+        )
+        .await
     }
 
     /// 发送流式 API 请求
@@ -269,11 +504,65 @@ impl KiroProvider {
     ///
     /// # Arguments
     /// * `request_body` - JSON 格式的请求体字符串
+    /// * `affinity_key` - 可选的凭据亲和键（balanced 模式下生效）
+    /// * `pinned_id` - 可选的固定凭据 ID（跳过负载均衡，凭据失败时不切换）
+    /// * `timeout` - 可选的本次请求超时（覆盖 requestTimeoutSecs，包含重试与读取响应体）
+    /// * `max_retries` - 可选的本次请求重试次数上限（覆盖 maxRetries，0 表示不重试）
     ///
     /// # Returns
-    /// 返回原始的 HTTP Response，调用方负责处理流式数据
-    pub async fn call_api_stream(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        self.call_api_with_retry(request_body, true).await
+    /// 返回原始的 HTTP Response（调用方负责处理流式数据）、实际重试次数与所用凭据；
+    /// 超时返回 [`UpstreamTimeout`] 错误
+    pub async fn call_api_stream(
+        &self,
+        request_body: &str,
+        affinity_key: Option<&str>,
+        pinned_id: Option<u64>,
+        timeout: Option<Duration>,
+        max_retries: Option<usize>,
+    ) -> anyhow::Result<UpstreamResponse> {
+        self.call_api_with_retry(
+            request_body,
+            true,
+            affinity_key,
+            pinned_id,
+            None,
+            timeout,
+            max_retries,
+        )
+        .await
+    }
+
+    /// 流式响应在发送内容前中断后重新发送请求
+    ///
+    /// 与 [`Self::call_api_stream`] 相同，但不选择刚失败的凭据 `failed_id`
+    /// （即使它是当前凭据或亲和绑定的凭据，亲和键会改绑到新选中的凭据）；
+    /// 没有其他可用凭据时仍使用该凭据。固定凭据时只使用固定的凭据
+    pub async fn call_api_stream_failover(
+        &self,
+        request_body: &str,
+        affinity_key: Option<&str>,
+        pinned_id: Option<u64>,
+        failed_id: u64,
+        timeout: Option<Duration>,
+        max_retries: Option<usize>,
+    ) -> anyhow::Result<UpstreamResponse> {
+        self.call_api_with_retry(
+            request_body,
+            true,
+            affinity_key,
+            pinned_id,
+            Some(failed_id),
+            timeout,
+            max_retries,
+        )
+        .await
+    }
+
+    /// 总尝试次数：min(凭据数量 × 每凭据重试次数, 重试次数上限 + 1)
+    fn max_attempts(&self, max_retries: Option<usize>) -> usize {
+        let total_credentials = self.token_manager.total_count();
+        let max_retries = max_retries.unwrap_or(self.token_manager.config().max_retries);
+        (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(max_retries.saturating_add(1))
     }
 
     /// 发送 MCP API 请求
@@ -285,20 +574,21 @@ impl KiroProvider {
     ///
     /// # Returns
     /// 返回原始的 HTTP Response
+    #[cfg(feature = "mcp")]
     pub async fn call_mcp(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
         self.call_mcp_with_retry(request_body).await
     }
 
     /// 内部方法：带重试逻辑的 MCP API 调用
+    #[cfg(feature = "mcp")]
     async fn call_mcp_with_retry(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        let total_credentials = self.token_manager.total_count();
-        let max_retries = (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES);
+        let max_retries = self.max_attempts(None);
         let mut last_error: Option<anyhow::Error> = None;
 
         for attempt in 0..max_retries {
             // 获取调用上下文
             // MCP 调用（WebSearch 等工具）不涉及模型选择，无需按模型过滤凭据
-            let ctx = match self.token_manager.acquire_context(None).await {
+            let ctx = match self.token_manager.acquire_context(None, None).await {
                 Ok(c) => c,
                 Err(e) => {
                     last_error = Some(e);
@@ -332,6 +622,8 @@ impl KiroProvider {
                         max_retries,
                         e
                     );
+                    self.token_manager
+                        .report_transient_failure(ctx.id, FailureKind::Network);
                     last_error = Some(e.into());
                     if attempt + 1 < max_retries {
                         sleep(Self::retry_delay(attempt)).await;
@@ -339,6 +631,7 @@ impl KiroProvider {
                     continue;
                 }
             };
+            clock::observe(response.headers());
 
             let status = response.status();
 
@@ -378,6 +671,8 @@ impl KiroProvider {
 
             // 瞬态错误
             if matches!(status.as_u16(), 408 | 429) || status.is_server_error() {
+                self.token_manager
+                    .report_transient_failure(ctx.id, Self::transient_failure_kind(status));
                 tracing::warn!(
                     "MCP 请求失败（上游瞬态错误，尝试 {}/{}）: {} {}",
                     attempt + 1,
@@ -398,6 +693,8 @@ impl KiroProvider {
             }
 
             // 兜底
+            self.token_manager
+                .report_transient_failure(ctx.id, FailureKind::Upstream);
             last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
             if attempt + 1 < max_retries {
                 sleep(Self::retry_delay(attempt)).await;
@@ -409,50 +706,149 @@ impl KiroProvider {
         }))
     }
 
+    /// 记录被上游拒绝（400）的请求体（日志中只写脱敏内容）
+    fn log_rejected_request(&self, request_body: &[u8]) {
+        tracing::error!(
+            "上游拒绝请求（400），请求体（已脱敏）: {}",
+            log_sanitizer::sanitize_body(
+                &String::from_utf8_lossy(request_body),
+                self.token_manager.config().log_body_max_bytes
+            )
+        );
+    }
+
     /// 内部方法：带重试逻辑的 API 调用
     ///
     /// 重试策略：
     /// - 每个凭据最多重试 MAX_RETRIES_PER_CREDENTIAL 次
-    /// - 总重试次数 = min(凭据数量 × 每凭据重试次数, MAX_TOTAL_RETRIES)
-    /// - 硬上限 9 次，避免无限重试
+    /// - 总尝试次数 = min(凭据数量 × 每凭据重试次数, 重试次数上限 + 1)，
+    ///   重试次数上限默认为 maxRetries，可被 API Key 配置或请求头 x-kiro-max-retries 缩小
+    /// - 所有重试共享同一个超时截止时间，超过后返回 [`UpstreamTimeout`]
+    /// - 固定凭据时只使用该凭据：凭据不可用或返回 401/402/403 时直接返回错误
+    /// - 指定 `exclude` 时不选择该凭据（没有其他可用凭据时除外）
+    /// - 失败时在错误上附加 [`AttemptTrace`]
+    #[allow(clippy::too_many_arguments)]
     async fn call_api_with_retry(
         &self,
         request_body: &str,
         is_stream: bool,
-    ) -> anyhow::Result<reqwest::Response> {
-        let total_credentials = self.token_manager.total_count();
-        let max_retries = (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES);
+        affinity_key: Option<&str>,
+        pinned_id: Option<u64>,
+        exclude: Option<u64>,
+        timeout: Option<Duration>,
+        max_retries: Option<usize>,
+    ) -> anyhow::Result<UpstreamResponse> {
+        let mut attempts = Vec::new();
+        self.call_api_attempts(
+            request_body,
+            is_stream,
+            affinity_key,
+            pinned_id,
+            exclude,
+            timeout,
+            max_retries,
+            &mut attempts,
+        )
+        .await
+        .map_err(|e| AttemptTrace::attach(e, attempts))
+    }
+
+    /// 重试循环，每次失败的尝试记录到 `attempts`
+    #[allow(clippy::too_many_arguments)]
+    async fn call_api_attempts(
+        &self,
+        request_body: &str,
+        is_stream: bool,
+        affinity_key: Option<&str>,
+        pinned_id: Option<u64>,
+        exclude: Option<u64>,
+        timeout: Option<Duration>,
+        max_retries: Option<usize>,
+        attempts: &mut Vec<Attempt>,
+    ) -> anyhow::Result<UpstreamResponse> {
+        let max_retries = self.max_attempts(max_retries);
         let mut last_error: Option<anyhow::Error> = None;
         let api_type = if is_stream { "流式" } else { "非流式" };
+        let timeout = timeout.unwrap_or(self.client_options.timeout);
+        let deadline = Instant::now() + timeout;
 
-        // 尝试从请求体中提取模型信息
-        let model = Self::extract_model_from_request(request_body);
+        // 请求体只解析一次，同时提取模型信息
+        let request_body = RequestBody::new(request_body);
+        let model = request_body.model();
 
         for attempt in 0..max_retries {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                tracing::warn!(
+                    "{} API 请求超时（{}秒），停止重试",
+                    api_type,
+                    timeout.as_secs()
+                );
+                return Err(UpstreamTimeout { timeout }.into());
+            }
+
             // 获取调用上下文（绑定 index、credentials、token）
-            let ctx = match self.token_manager.acquire_context(model.as_deref()).await {
+            let acquired = match pinned_id {
+                Some(id) => self.token_manager.acquire_pinned_context(id).await,
+                None => {
+                    self.token_manager
+                        .acquire_context_excluding(model.as_deref(), affinity_key, exclude)
+                        .await
+                }
+            };
+            let ctx = match acquired {
                 Ok(c) => c,
                 Err(e) => {
+                    attempts.push(Attempt {
+                        credential_id: pinned_id,
+                        status: None,
+                        error: "no_credential",
+                        delay: Duration::ZERO,
+                    });
+                    if pinned_id.is_some() {
+                        return Err(e);
+                    }
                     last_error = Some(e);
                     continue;
                 }
             };
+            if is_stream && pinned_id.is_none() {
+                self.warm_standby(model.as_deref(), affinity_key, ctx.id);
+            }
+            // 记录本次失败的尝试
+            let mut record = |status: Option<u16>, error: &'static str, delay: Duration| {
+                attempts.push(Attempt {
+                    credential_id: Some(ctx.id),
+                    status,
+                    error,
+                    delay,
+                });
+            };
 
             let url = self.base_url_for(&ctx.credentials);
+            let payload = request_body.for_credentials(&ctx.credentials);
             let headers = match self.build_headers(&ctx) {
                 Ok(h) => h,
                 Err(e) => {
+                    record(None, "headers", Duration::ZERO);
                     last_error = Some(e);
                     continue;
                 }
             };
 
-            // 发送请求
+            self.token_manager.metrics().sizes().record_request(
+                model.as_deref(),
+                ctx.id,
+                payload.len(),
+            );
+
+            // 发送请求（超时覆盖到读完响应体为止）
             let response = match self
                 .client_for(&ctx.credentials)?
                 .post(&url)
                 .headers(headers)
-                .body(request_body.to_string())
+                .timeout(remaining)
+                .body(payload.clone())
                 .send()
                 .await
             {
@@ -464,22 +860,36 @@ impl KiroProvider {
                         max_retries,
                         e
                     );
+                    if e.is_timeout() && Instant::now() >= deadline {
+                        record(None, "timeout", Duration::ZERO);
+                        return Err(UpstreamTimeout { timeout }.into());
+                    }
                     // 网络错误通常是上游/链路瞬态问题，不应导致"禁用凭据"或"切换凭据"
                     // （否则一段时间网络抖动会把所有凭据都误禁用，需要重启才能恢复）
+                    self.token_manager
+                        .report_transient_failure(ctx.id, FailureKind::Network);
+                    let delay = Self::backoff(attempt, max_retries);
+                    record(None, FailureKind::Network.as_str(), delay);
                     last_error = Some(e.into());
-                    if attempt + 1 < max_retries {
-                        sleep(Self::retry_delay(attempt)).await;
+                    if !delay.is_zero() {
+                        sleep(delay).await;
                     }
                     continue;
                 }
             };
+            clock::observe(response.headers());
 
             let status = response.status();
 
             // 成功响应
             if status.is_success() {
                 self.token_manager.report_success(ctx.id);
-                return Ok(response);
+                return Ok(UpstreamResponse {
+                    response,
+                    retries: attempt,
+                    credential_id: ctx.id,
+                    model,
+                });
             }
 
             // 失败响应：读取 body 用于日志/错误信息
@@ -495,7 +905,15 @@ impl KiroProvider {
                     body
                 );
 
+                record(
+                    Some(status.as_u16()),
+                    FailureKind::QuotaExhausted.as_str(),
+                    Duration::ZERO,
+                );
                 let has_available = self.token_manager.report_quota_exhausted(ctx.id);
+                if pinned_id.is_some() {
+                    anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
+                }
                 if !has_available {
                     anyhow::bail!(
                         "{} API 请求失败（所有凭据已用尽）: {} {}",
@@ -516,7 +934,14 @@ impl KiroProvider {
 
             // 400 Bad Request - 请求问题，重试/切换凭据无意义
             if status.as_u16() == 400 {
-                anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
+                record(Some(400), "bad_request", Duration::ZERO);
+                self.log_rejected_request(&payload);
+                return Err(UpstreamStatusError {
+                    api_type,
+                    status,
+                    body,
+                }
+                .into());
             }
 
             // 401/403 - 更可能是凭据/权限问题：计入失败并允许故障转移
@@ -529,7 +954,15 @@ impl KiroProvider {
                     body
                 );
 
+                record(
+                    Some(status.as_u16()),
+                    FailureKind::Auth.as_str(),
+                    Duration::ZERO,
+                );
                 let has_available = self.token_manager.report_failure(ctx.id);
+                if pinned_id.is_some() {
+                    anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
+                }
                 if !has_available {
                     anyhow::bail!(
                         "{} API 请求失败（所有凭据已用尽）: {} {}",
@@ -551,6 +984,8 @@ impl KiroProvider {
             // 429/408/5xx - 瞬态上游错误：重试但不禁用或切换凭据
             // （避免 429 high traffic / 502 high load 等瞬态错误把所有凭据锁死）
             if matches!(status.as_u16(), 408 | 429) || status.is_server_error() {
+                let kind = Self::transient_failure_kind(status);
+                self.token_manager.report_transient_failure(ctx.id, kind);
                 tracing::warn!(
                     "API 请求失败（上游瞬态错误，尝试 {}/{}）: {} {}",
                     attempt + 1,
@@ -558,24 +993,31 @@ impl KiroProvider {
                     status,
                     body
                 );
-                last_error = Some(anyhow::anyhow!(
-                    "{} API 请求失败: {} {}",
-                    api_type,
-                    status,
-                    body
-                ));
-                if attempt + 1 < max_retries {
-                    sleep(Self::retry_delay(attempt)).await;
+                let delay = Self::backoff(attempt, max_retries);
+                record(Some(status.as_u16()), kind.as_str(), delay);
+                last_error = Some(
+                    UpstreamStatusError {
+                        api_type,
+                        status,
+                        body,
+                    }
+                    .into(),
+                );
+                if !delay.is_zero() {
+                    sleep(delay).await;
                 }
                 continue;
             }
 
             // 其他 4xx - 通常为请求/配置问题：直接返回，不计入凭据失败
             if status.is_client_error() {
+                record(Some(status.as_u16()), "client_error", Duration::ZERO);
                 anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
             }
 
             // 兜底：当作可重试的瞬态错误处理（不切换凭据）
+            self.token_manager
+                .report_transient_failure(ctx.id, FailureKind::Upstream);
             tracing::warn!(
                 "API 请求失败（未知错误，尝试 {}/{}）: {} {}",
                 attempt + 1,
@@ -583,14 +1025,16 @@ impl KiroProvider {
                 status,
                 body
             );
+            let delay = Self::backoff(attempt, max_retries);
+            record(Some(status.as_u16()), FailureKind::Upstream.as_str(), delay);
             last_error = Some(anyhow::anyhow!(
                 "{} API 请求失败: {} {}",
                 api_type,
                 status,
                 body
             ));
-            if attempt + 1 < max_retries {
-                sleep(Self::retry_delay(attempt)).await;
+            if !delay.is_zero() {
+                sleep(delay).await;
             }
         }
 
@@ -604,6 +1048,24 @@ impl KiroProvider {
         }))
     }
 
+    /// 瞬态错误状态码对应的失败分类
+    fn transient_failure_kind(status: reqwest::StatusCode) -> FailureKind {
+        if status.as_u16() == 429 {
+            FailureKind::Throttled
+        } else {
+            FailureKind::Upstream
+        }
+    }
+
+    /// 第 `attempt` 次尝试失败后的等待时间（最后一次尝试后不等待）
+    fn backoff(attempt: usize, max_retries: usize) -> Duration {
+        if attempt + 1 < max_retries {
+            Self::retry_delay(attempt)
+        } else {
+            Duration::ZERO
+        }
+    }
+
     fn retry_delay(attempt: usize) -> Duration {
         // 指数退避 + 少量抖动，避免上游抖动时放大故障
         const BASE_MS: u64 = 200;
@@ -668,6 +1130,31 @@ mod tests {
         assert_eq!(provider.base_domain(), "q.us-east-1.amazonaws.com");
     }
 
+    #[test]
+    fn test_endpoint_overrides() {
+        let mut config = Config::default();
+        config.region = "eu-central-1".to_string();
+        config.endpoints.api = Some("http://127.0.0.1:9000/{region}/generate".to_string());
+        config.endpoints.mcp = Some("https://mcp.staging.example/mcp".to_string());
+        config.validate().unwrap();
+
+        let provider = create_test_provider(config.clone(), KiroCredentials::default());
+        assert_eq!(
+            provider.base_url(),
+            "http://127.0.0.1:9000/eu-central-1/generate"
+        );
+        assert_eq!(provider.base_domain(), "127.0.0.1:9000");
+        assert_eq!(provider.mcp_url(), "https://mcp.staging.example/mcp");
+        // 未覆盖的端点仍使用内置地址
+        assert_eq!(
+            config.endpoints.idc_refresh_url("eu-central-1"),
+            "https://oidc.eu-central-1.amazonaws.com/token"
+        );
+
+        config.endpoints.social_refresh = Some("not a url".to_string());
+        assert!(config.validate().is_err());
+    }
+
     #[test]
     fn test_build_headers() {
         let mut config = Config::default();
@@ -717,4 +1204,71 @@ mod tests {
         let body = r#"{"message":"nope","reason":"DAILY_REQUEST_COUNT"}"#;
         assert!(!KiroProvider::is_monthly_request_limit(body));
     }
+
+    #[test]
+    fn test_attempt_trace() {
+        let attempts = vec![
+            Attempt {
+                credential_id: Some(1),
+                status: Some(429),
+                error: "throttled",
+                delay: Duration::from_millis(230),
+            },
+            Attempt {
+                credential_id: None,
+                status: None,
+                error: "no_credential",
+                delay: Duration::ZERO,
+            },
+        ];
+        let e = AttemptTrace::attach(
+            UpstreamTimeout {
+                timeout: Duration::from_secs(5),
+            }
+            .into(),
+            attempts,
+        );
+        // 附加记录后仍可取得原始错误
+        assert!(e.downcast_ref::<UpstreamTimeout>().is_some());
+        let trace = e.downcast_ref::<AttemptTrace>().unwrap();
+        assert_eq!(
+            trace.header_value(),
+            "cred=1;status=429;error=throttled;delay_ms=230, error=no_credential;delay_ms=0"
+        );
+        assert_eq!(
+            e.to_string(),
+            "上游请求超时（5秒）（尝试记录：#1 凭据 1 429 throttled，等待 230ms；#2 no_credential）"
+        );
+
+        // 没有记录时原样返回
+        let e = AttemptTrace::attach(anyhow::anyhow!("boom"), Vec::new());
+        assert!(e.downcast_ref::<AttemptTrace>().is_none());
+    }
+
+    #[test]
+    fn test_request_body_for_credentials() {
+        let raw = r#"{"conversationState":{"currentMessage":{"userInputMessage":{"modelId":"claude-sonnet-4"}}},"profileArn":"arn:a"}"#;
+        let body = RequestBody::new(raw);
+        assert_eq!(body.model().as_deref(), Some("claude-sonnet-4"));
+
+        // 未配置或与请求体相同的 profileArn 复用原始字节
+        let mut credentials = KiroCredentials::default();
+        assert_eq!(body.for_credentials(&credentials), raw.as_bytes());
+        credentials.profile_arn = Some("arn:a".to_string());
+        assert_eq!(body.for_credentials(&credentials), raw.as_bytes());
+
+        credentials.profile_arn = Some("arn:b".to_string());
+        let patched: serde_json::Value =
+            serde_json::from_slice(&body.for_credentials(&credentials)).unwrap();
+        assert_eq!(patched["profileArn"], "arn:b");
+        assert_eq!(
+            patched["conversationState"]["currentMessage"]["userInputMessage"]["modelId"],
+            "claude-sonnet-4"
+        );
+
+        // 无法解析的请求体原样发送
+        let body = RequestBody::new("not json");
+        assert_eq!(body.model(), None);
+        assert_eq!(body.for_credentials(&credentials), "not json".as_bytes());
+    }
 }
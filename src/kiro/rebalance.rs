@@ -0,0 +1,283 @@
+//! 凭据池再平衡建议
+//!
+//! 定期对比各凭据的流量占比与剩余额度占比（以及两次分析之间的失败率），
+//! 给出优先级调整建议，使流量大致与剩余额度成比例：
+//! - 流量占比明显高于额度占比，或失败率过高：降到下一个优先级层
+//! - 流量占比明显低于额度占比：升到上一个优先级层
+//!
+//! 每次只移动一层，且只移动到已有的优先级层，避免优先级无限漂移。
+//! 建议默认只记录日志并通过 Admin API 展示；开启 `rebalanceAutopilot` 后自动应用。
+
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// 判断流量分布所需的最少请求数（两次分析之间）
+const MIN_WINDOW_REQUESTS: u64 = 20;
+/// 判断失败率所需的最少调用次数（单个凭据，两次分析之间）
+const MIN_FAILURE_SAMPLES: u64 = 5;
+/// 失败率达到该值时建议降级
+const HIGH_FAILURE_RATE: f64 = 0.5;
+/// 流量占比超过额度占比的该倍数时建议降级
+const OVERUSE_RATIO: f64 = 1.5;
+/// 流量占比低于额度占比的该倍数时建议升级
+const UNDERUSE_RATIO: f64 = 0.5;
+
+/// 单个凭据的分析输入
+#[derive(Debug, Clone)]
+pub struct CredentialSample {
+    pub id: u64,
+    pub priority: u32,
+    /// 当前是否可被选中
+    pub available: bool,
+    /// 已知剩余额度（没有余额数据时为 None）
+    pub remaining: Option<f64>,
+    /// 累计成功次数
+    pub success_count: u64,
+    /// 累计失败次数
+    pub failure_total: u64,
+}
+
+/// 建议原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RebalanceReason {
+    /// 流量占比明显高于剩余额度占比
+    Overused,
+    /// 流量占比明显低于剩余额度占比
+    Underused,
+    /// 失败率过高
+    HighFailureRate,
+}
+
+impl RebalanceReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RebalanceReason::Overused => "overused",
+            RebalanceReason::Underused => "underused",
+            RebalanceReason::HighFailureRate => "high_failure_rate",
+        }
+    }
+}
+
+/// 单个凭据的优先级调整建议
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RebalanceSuggestion {
+    pub id: u64,
+    pub current_priority: u32,
+    pub suggested_priority: u32,
+    pub reason: RebalanceReason,
+    /// 分析窗口内的流量占比
+    pub traffic_share: f64,
+    /// 剩余额度占比
+    pub quota_share: f64,
+    /// 分析窗口内的失败率
+    pub failure_rate: f64,
+}
+
+/// 一次分析的结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RebalanceReport {
+    pub generated_at: DateTime<Utc>,
+    /// 分析窗口内的请求总数
+    pub window_requests: u64,
+    pub suggestions: Vec<RebalanceSuggestion>,
+    /// 建议是否已自动应用
+    pub applied: bool,
+}
+
+/// 再平衡分析器
+///
+/// 保存上一次分析时的累计计数，以两次分析之间的增量作为分析窗口
+#[derive(Default)]
+pub struct RebalanceAnalyzer {
+    /// 凭据 ID -> (成功次数, 失败次数)
+    previous: Mutex<HashMap<u64, (u64, u64)>>,
+    last_report: Mutex<Option<RebalanceReport>>,
+}
+
+impl RebalanceAnalyzer {
+    /// 分析当前凭据池并记录为最近一次结果
+    pub fn analyze(&self, samples: &[CredentialSample]) -> RebalanceReport {
+        let deltas: HashMap<u64, (u64, u64)> = {
+            let mut previous = self.previous.lock();
+            let deltas = samples
+                .iter()
+                .map(|s| {
+                    let (success, failure) = previous.get(&s.id).copied().unwrap_or((0, 0));
+                    (
+                        s.id,
+                        (
+                            s.success_count.saturating_sub(success),
+                            s.failure_total.saturating_sub(failure),
+                        ),
+                    )
+                })
+                .collect();
+            *previous = samples
+                .iter()
+                .map(|s| (s.id, (s.success_count, s.failure_total)))
+                .collect();
+            deltas
+        };
+
+        let available: Vec<&CredentialSample> = samples.iter().filter(|s| s.available).collect();
+        let tiers: BTreeSet<u32> = available.iter().map(|s| s.priority).collect();
+        let window_requests: u64 = available.iter().map(|s| deltas[&s.id].0).sum();
+        let total_remaining: f64 = available.iter().filter_map(|s| s.remaining).sum();
+        // 有凭据余额未知（尚未初始化）时无法得出可靠的额度占比，只按失败率判断
+        let balances_known = available.iter().all(|s| s.remaining.is_some());
+
+        let mut suggestions = Vec::new();
+        for sample in &available {
+            let (success, failure) = deltas[&sample.id];
+            let traffic_share = if window_requests > 0 {
+                success as f64 / window_requests as f64
+            } else {
+                0.0
+            };
+            let quota_share = match sample.remaining {
+                Some(remaining) if total_remaining > 0.0 => remaining / total_remaining,
+                _ => 0.0,
+            };
+            let failure_rate = if success + failure > 0 {
+                failure as f64 / (success + failure) as f64
+            } else {
+                0.0
+            };
+
+            let lower_tier = tiers.range(sample.priority + 1..).next().copied();
+            let higher_tier = tiers.range(..sample.priority).next_back().copied();
+            let has_distribution =
+                window_requests >= MIN_WINDOW_REQUESTS && balances_known && total_remaining > 0.0;
+
+            let suggestion =
+                if success + failure >= MIN_FAILURE_SAMPLES && failure_rate >= HIGH_FAILURE_RATE {
+                    lower_tier.map(|p| (p, RebalanceReason::HighFailureRate))
+                } else if has_distribution && traffic_share > quota_share * OVERUSE_RATIO {
+                    lower_tier.map(|p| (p, RebalanceReason::Overused))
+                } else if has_distribution && traffic_share < quota_share * UNDERUSE_RATIO {
+                    higher_tier.map(|p| (p, RebalanceReason::Underused))
+                } else {
+                    None
+                };
+
+            if let Some((suggested_priority, reason)) = suggestion {
+                suggestions.push(RebalanceSuggestion {
+                    id: sample.id,
+                    current_priority: sample.priority,
+                    suggested_priority,
+                    reason,
+                    traffic_share,
+                    quota_share,
+                    failure_rate,
+                });
+            }
+        }
+
+        let report = RebalanceReport {
+            generated_at: Utc::now(),
+            window_requests,
+            suggestions,
+            applied: false,
+        };
+        *self.last_report.lock() = Some(report.clone());
+        report
+    }
+
+    /// 最近一次分析结果
+    pub fn last_report(&self) -> Option<RebalanceReport> {
+        self.last_report.lock().clone()
+    }
+
+    /// 标记最近一次分析的建议已应用
+    pub fn mark_applied(&self) {
+        if let Some(report) = self.last_report.lock().as_mut() {
+            report.applied = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(
+        id: u64,
+        priority: u32,
+        remaining: f64,
+        success: u64,
+        failure: u64,
+    ) -> CredentialSample {
+        CredentialSample {
+            id,
+            priority,
+            available: true,
+            remaining: Some(remaining),
+            success_count: success,
+            failure_total: failure,
+        }
+    }
+
+    #[test]
+    fn test_suggests_moving_traffic_towards_quota() {
+        let analyzer = RebalanceAnalyzer::default();
+        // #1 承担全部流量但剩余额度很少，#2 额度充足却没有流量
+        let report = analyzer.analyze(&[sample(1, 0, 10.0, 100, 0), sample(2, 1, 90.0, 0, 0)]);
+        assert_eq!(report.window_requests, 100);
+        assert_eq!(report.suggestions.len(), 2);
+        assert_eq!(report.suggestions[0].reason, RebalanceReason::Overused);
+        assert_eq!(report.suggestions[0].suggested_priority, 1);
+        assert_eq!(report.suggestions[1].reason, RebalanceReason::Underused);
+        assert_eq!(report.suggestions[1].suggested_priority, 0);
+
+        // 第二次分析只看增量：流量太少不足以判断分布
+        let report = analyzer.analyze(&[sample(1, 0, 10.0, 105, 0), sample(2, 1, 90.0, 0, 0)]);
+        assert_eq!(report.window_requests, 5);
+        assert!(report.suggestions.is_empty());
+        assert!(analyzer.last_report().is_some());
+    }
+
+    #[test]
+    fn test_high_failure_rate_and_tier_bounds() {
+        let analyzer = RebalanceAnalyzer::default();
+        let report = analyzer.analyze(&[
+            sample(1, 0, 50.0, 2, 8),
+            sample(2, 3, 50.0, 0, 0),
+            // 不可用的凭据不参与分析，也不构成优先级层
+            CredentialSample {
+                available: false,
+                ..sample(3, 1, 50.0, 0, 0)
+            },
+        ]);
+        assert_eq!(report.suggestions.len(), 1);
+        assert_eq!(
+            report.suggestions[0].reason,
+            RebalanceReason::HighFailureRate
+        );
+        assert_eq!(report.suggestions[0].suggested_priority, 3);
+
+        // 只有一个优先级层时无处可移
+        let analyzer = RebalanceAnalyzer::default();
+        let report = analyzer.analyze(&[sample(1, 0, 10.0, 100, 0), sample(2, 0, 90.0, 0, 0)]);
+        assert!(report.suggestions.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_balance_skips_distribution() {
+        let analyzer = RebalanceAnalyzer::default();
+        // #2 余额尚未初始化：不把 #1 的额度占比当作 100%
+        let report = analyzer.analyze(&[
+            sample(1, 1, 10.0, 0, 0),
+            CredentialSample {
+                remaining: None,
+                ..sample(2, 0, 0.0, 100, 0)
+            },
+        ]);
+        assert!(report.suggestions.is_empty());
+    }
+}
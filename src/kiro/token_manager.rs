@@ -3,26 +3,40 @@
 //! 负责 Token 过期检测和刷新，支持 Social 和 IdC 认证方式
 //! 支持单凭据 (TokenManager) 和多凭据 (MultiTokenManager) 管理
 
-use anyhow::bail;
+use anyhow::{Context, bail};
 use chrono::{DateTime, Duration, Utc};
+use futures::StreamExt;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use tokio::sync::Mutex as TokioMutex;
 
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{Duration as StdDuration, Instant};
 
-use crate::http_client::{ProxyConfig, build_client};
+use crate::common::clock;
+use crate::http_client::{ProxyConfig, TransportOptions, build_client};
+use crate::kiro::balance_store::{BalanceSnapshot, BalanceStore, BalanceTtlPolicy};
+use crate::kiro::credential_archive::{ARCHIVE_FILE, ArchivedCredential, CredentialArchive};
+use crate::kiro::credentials_writer::CredentialsWriter;
+use crate::kiro::daily_limit::{
+    DailyLimitExceeded, DailyLimitStatus, DailyLimiter, DailyResetZone,
+};
+use crate::kiro::debug_dump::DumpStore;
+use crate::kiro::event_bus::{BusEvent, EventBus};
 use crate::kiro::machine_id;
-use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::metrics::Metrics;
+use crate::kiro::model::credential_builder::{CredentialValidationError, check_refresh_token};
+use crate::kiro::model::credentials::{CredentialsConfig, KiroCredentials};
 use crate::kiro::model::token_refresh::{
     IdcRefreshRequest, IdcRefreshResponse, RefreshRequest, RefreshResponse,
 };
 use crate::kiro::model::usage_limits::UsageLimitsResponse;
-use crate::model::config::Config;
+use crate::kiro::rebalance::{CredentialSample, RebalanceAnalyzer, RebalanceReport};
+use crate::model::config::{Config, CredentialsFileConfig, endpoint_host};
 
 /// Token 管理器
 ///
@@ -82,7 +96,7 @@ impl TokenManager {
     }
 }
 
-/// 检查 Token 是否在指定时间内过期
+/// 检查 Token 是否在指定时间内过期（使用校正时钟偏差后的当前时间）
 pub(crate) fn is_token_expiring_within(
     credentials: &KiroCredentials,
     minutes: i64,
@@ -91,7 +105,7 @@ pub(crate) fn is_token_expiring_within(
         .expires_at
         .as_ref()
         .and_then(|expires_at| DateTime::parse_from_rfc3339(expires_at).ok())
-        .map(|expires| expires <= Utc::now() + Duration::minutes(minutes))
+        .map(|expires| expires <= clock::now() + Duration::minutes(minutes))
 }
 
 /// 检查 Token 是否已过期（提前 5 分钟判断）
@@ -111,27 +125,30 @@ fn sha256_hex(input: &str) -> String {
     format!("{:x}", result)
 }
 
-/// 验证 refreshToken 的基本有效性
-pub(crate) fn validate_refresh_token(credentials: &KiroCredentials) -> anyhow::Result<()> {
-    let refresh_token = credentials
+/// Admin API 中展示的 refreshToken 哈希长度（十六进制字符数）
+const REFRESH_TOKEN_HASH_DISPLAY_LEN: usize = 16;
+
+/// 加盐的 refreshToken 完整 SHA-256 哈希（用于重复检测与跨系统匹配账号）
+fn refresh_token_hash(salt: &str, credentials: &KiroCredentials) -> Option<String> {
+    credentials
         .refresh_token
-        .as_ref()
-        .ok_or_else(|| anyhow::anyhow!("缺少 refreshToken"))?;
+        .as_deref()
+        .map(|token| sha256_hex(&format!("{}:{}", salt, token)))
+}
 
-    if refresh_token.is_empty() {
-        bail!("refreshToken 为空");
-    }
+/// 验证 refreshToken 的基本有效性
+pub(crate) fn validate_refresh_token(credentials: &KiroCredentials) -> anyhow::Result<()> {
+    check_refresh_token(credentials.refresh_token.as_deref())?;
+    Ok(())
+}
 
-    if refresh_token.len() < 100 || refresh_token.ends_with("...") || refresh_token.contains("...")
-    {
-        bail!(
-            "refreshToken 已被截断（长度: {} 字符）。\n\
-             这通常是 Kiro IDE 为了防止凭证被第三方工具使用而故意截断的。",
-            refresh_token.len()
-        );
+/// 为没有记录签发时间的 refreshToken 记录当前时间，返回是否有修改
+fn record_refresh_token_issued_at(credentials: &mut KiroCredentials) -> bool {
+    if credentials.refresh_token.is_none() || credentials.refresh_token_issued_at.is_some() {
+        return false;
     }
-
-    Ok(())
+    credentials.refresh_token_issued_at = Some(Utc::now().to_rfc3339());
+    true
 }
 
 /// 刷新 Token
@@ -142,20 +159,8 @@ pub(crate) async fn refresh_token(
 ) -> anyhow::Result<KiroCredentials> {
     validate_refresh_token(credentials)?;
 
-    // 根据 auth_method 选择刷新方式
-    // 如果未指定 auth_method，根据是否有 clientId/clientSecret 自动判断
-    let auth_method = credentials.auth_method.as_deref().unwrap_or_else(|| {
-        if credentials.client_id.is_some() && credentials.client_secret.is_some() {
-            "idc"
-        } else {
-            "social"
-        }
-    });
-
-    if auth_method.eq_ignore_ascii_case("idc")
-        || auth_method.eq_ignore_ascii_case("builder-id")
-        || auth_method.eq_ignore_ascii_case("iam")
-    {
+    // 根据 auth_method 选择刷新方式，未指定时按凭据内容推断
+    if credentials.effective_auth_method() == "idc" {
         refresh_idc_token(credentials, config, proxy).await
     } else {
         refresh_social_token(credentials, config, proxy).await
@@ -174,13 +179,13 @@ async fn refresh_social_token(
     // 优先级：凭据.auth_region > 凭据.region > config.auth_region > config.region
     let region = credentials.effective_auth_region(config);
 
-    let refresh_url = format!("https://prod.{}.auth.desktop.kiro.dev/refreshToken", region);
-    let refresh_domain = format!("prod.{}.auth.desktop.kiro.dev", region);
+    let refresh_url = config.endpoints.social_refresh_url(region);
+    let refresh_domain = endpoint_host(&refresh_url);
     let machine_id = machine_id::generate_from_credentials(credentials, config)
         .ok_or_else(|| anyhow::anyhow!("无法生成 machineId"))?;
     let kiro_version = &config.kiro_version;
 
-    let client = build_client(proxy, 60, config.tls_backend)?;
+    let client = build_client(proxy, 60, &TransportOptions::from_config(config))?;
     let body = RefreshRequest {
         refresh_token: refresh_token.to_string(),
     };
@@ -199,6 +204,7 @@ async fn refresh_social_token(
         .json(&body)
         .send()
         .await?;
+    clock::observe(response.headers());
 
     let status = response.status();
     if !status.is_success() {
@@ -219,6 +225,9 @@ async fn refresh_social_token(
     new_credentials.access_token = Some(data.access_token);
 
     if let Some(new_refresh_token) = data.refresh_token {
+        if credentials.refresh_token.as_ref() != Some(&new_refresh_token) {
+            new_credentials.refresh_token_rotated_at = Some(Utc::now().to_rfc3339());
+        }
         new_credentials.refresh_token = Some(new_refresh_token);
     }
 
@@ -227,7 +236,7 @@ async fn refresh_social_token(
     }
 
     if let Some(expires_in) = data.expires_in {
-        let expires_at = Utc::now() + Duration::seconds(expires_in);
+        let expires_at = clock::now() + Duration::seconds(expires_in);
         new_credentials.expires_at = Some(expires_at.to_rfc3339());
     }
 
@@ -237,11 +246,112 @@ async fn refresh_social_token(
 /// IdC Token 刷新所需的 x-amz-user-agent header
 const IDC_AMZ_USER_AGENT: &str = "aws-sdk-js/3.738.0 ua/2.1 os/other lang/js md/browser#unknown_unknown api/sso-oidc#3.738.0 m/E KiroIDE";
 
+/// OIDC 错误响应中提示区域可能配置错误的关键字（小写）
+const IDC_WRONG_REGION_HINTS: &[&str] = &[
+    "invalid_client",
+    "invalidclient",
+    "invalid_grant",
+    "invalidgrant",
+    "region",
+];
+
+/// IdC Token 刷新被 OIDC 服务拒绝
+#[derive(Debug)]
+struct IdcRefreshRejected {
+    status: reqwest::StatusCode,
+    body: String,
+}
+
+impl IdcRefreshRejected {
+    /// 错误是否可能由区域配置错误导致
+    ///
+    /// OIDC 客户端与 refreshToken 只在注册时的区域有效，其他区域返回 invalid_client / invalid_grant
+    fn suggests_wrong_region(&self) -> bool {
+        let body = self.body.to_ascii_lowercase();
+        matches!(self.status.as_u16(), 400 | 401 | 403)
+            && IDC_WRONG_REGION_HINTS
+                .iter()
+                .any(|hint| body.contains(hint))
+    }
+}
+
+impl std::fmt::Display for IdcRefreshRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let error_msg = match self.status.as_u16() {
+            401 => "IdC 凭证已过期或无效，需要重新认证",
+            403 => "权限不足，无法刷新 Token",
+            429 => "请求过于频繁，已被限流",
+            500..=599 => "服务器错误，AWS OIDC 服务暂时不可用",
+            _ => "IdC Token 刷新失败",
+        };
+        write!(f, "{}: {} {}", error_msg, self.status, self.body)
+    }
+}
+
+impl std::error::Error for IdcRefreshRejected {}
+
+/// 区域探测时依次尝试的区域（去掉已失败的区域与重复项）
+fn probe_regions<'a>(config: &'a Config, failed: &str) -> Vec<&'a str> {
+    let mut regions: Vec<&str> = Vec::new();
+    for region in &config.auth_region_probe_list {
+        let region = region.trim();
+        if !region.is_empty() && region != failed && !regions.contains(&region) {
+            regions.push(region);
+        }
+    }
+    regions
+}
+
 /// 刷新 IdC Token (AWS SSO OIDC)
+///
+/// 配置的区域因区域错误被拒绝时，按 `authRegionProbeList` 依次尝试其他区域，
+/// 成功后把可用的区域写入凭据的 authRegion（随刷新结果持久化）
 async fn refresh_idc_token(
     credentials: &KiroCredentials,
     config: &Config,
     proxy: Option<&ProxyConfig>,
+) -> anyhow::Result<KiroCredentials> {
+    // 优先级：凭据.auth_region > 凭据.region > config.auth_region > config.region
+    let region = credentials.effective_auth_region(config);
+    let error = match refresh_idc_token_in(credentials, config, proxy, region).await {
+        Ok(new_credentials) => return Ok(new_credentials),
+        Err(e) => e,
+    };
+    let wrong_region = error
+        .downcast_ref::<IdcRefreshRejected>()
+        .is_some_and(IdcRefreshRejected::suggests_wrong_region);
+    if !wrong_region {
+        return Err(error);
+    }
+
+    for candidate in probe_regions(config, region) {
+        tracing::info!(
+            "IdC Token 在区域 {} 刷新失败，尝试区域 {}",
+            region,
+            candidate
+        );
+        match refresh_idc_token_in(credentials, config, proxy, candidate).await {
+            Ok(mut new_credentials) => {
+                tracing::warn!(
+                    "凭据的 authRegion 配置有误：{} 无法刷新 IdC Token，已自动更正为 {}",
+                    region,
+                    candidate
+                );
+                new_credentials.auth_region = Some(candidate.to_string());
+                return Ok(new_credentials);
+            }
+            Err(e) => tracing::debug!("区域 {} 刷新 IdC Token 失败: {}", candidate, e),
+        }
+    }
+    Err(error)
+}
+
+/// 在指定区域刷新 IdC Token
+async fn refresh_idc_token_in(
+    credentials: &KiroCredentials,
+    config: &Config,
+    proxy: Option<&ProxyConfig>,
+    region: &str,
 ) -> anyhow::Result<KiroCredentials> {
     tracing::info!("正在刷新 IdC Token...");
 
@@ -255,11 +365,9 @@ async fn refresh_idc_token(
         .as_ref()
         .ok_or_else(|| anyhow::anyhow!("IdC 刷新需要 clientSecret"))?;
 
-    // 优先级：凭据.auth_region > 凭据.region > config.auth_region > config.region
-    let region = credentials.effective_auth_region(config);
-    let refresh_url = format!("https://oidc.{}.amazonaws.com/token", region);
+    let refresh_url = config.endpoints.idc_refresh_url(region);
 
-    let client = build_client(proxy, 60, config.tls_backend)?;
+    let client = build_client(proxy, 60, &TransportOptions::from_config(config))?;
     let body = IdcRefreshRequest {
         client_id: client_id.to_string(),
         client_secret: client_secret.to_string(),
@@ -270,7 +378,7 @@ async fn refresh_idc_token(
     let response = client
         .post(&refresh_url)
         .header("Content-Type", "application/json")
-        .header("Host", format!("oidc.{}.amazonaws.com", region))
+        .header("Host", endpoint_host(&refresh_url))
         .header("Connection", "keep-alive")
         .header("x-amz-user-agent", IDC_AMZ_USER_AGENT)
         .header("Accept", "*/*")
@@ -281,18 +389,12 @@ async fn refresh_idc_token(
         .json(&body)
         .send()
         .await?;
+    clock::observe(response.headers());
 
     let status = response.status();
     if !status.is_success() {
-        let body_text = response.text().await.unwrap_or_default();
-        let error_msg = match status.as_u16() {
-            401 => "IdC 凭证已过期或无效，需要重新认证",
-            403 => "权限不足，无法刷新 Token",
-            429 => "请求过于频繁，已被限流",
-            500..=599 => "服务器错误，AWS OIDC 服务暂时不可用",
-            _ => "IdC Token 刷新失败",
-        };
-        bail!("{}: {} {}", error_msg, status, body_text);
+        let body = response.text().await.unwrap_or_default();
+        return Err(IdcRefreshRejected { status, body }.into());
     }
 
     let data: IdcRefreshResponse = response.json().await?;
@@ -301,11 +403,14 @@ async fn refresh_idc_token(
     new_credentials.access_token = Some(data.access_token);
 
     if let Some(new_refresh_token) = data.refresh_token {
+        if credentials.refresh_token.as_ref() != Some(&new_refresh_token) {
+            new_credentials.refresh_token_rotated_at = Some(Utc::now().to_rfc3339());
+        }
         new_credentials.refresh_token = Some(new_refresh_token);
     }
 
     if let Some(expires_in) = data.expires_in {
-        let expires_at = Utc::now() + Duration::seconds(expires_in);
+        let expires_at = clock::now() + Duration::seconds(expires_in);
         new_credentials.expires_at = Some(expires_at.to_rfc3339());
     }
 
@@ -324,18 +429,16 @@ pub(crate) async fn get_usage_limits(
 ) -> anyhow::Result<UsageLimitsResponse> {
     tracing::debug!("正在获取使用额度信息...");
 
-    // 优先级：凭据.api_region > config.api_region > config.region
-    let region = credentials.effective_api_region(config);
-    let host = format!("q.{}.amazonaws.com", region);
+    // IdC 凭据优先使用凭据自身的区域，其余与 API 请求相同
+    let region = credentials.effective_usage_limits_region(config);
+    let base_url = config.endpoints.usage_limits_url(region);
+    let host = endpoint_host(&base_url);
     let machine_id = machine_id::generate_from_credentials(credentials, config)
         .ok_or_else(|| anyhow::anyhow!("无法生成 machineId"))?;
     let kiro_version = &config.kiro_version;
 
     // 构建 URL
-    let mut url = format!(
-        "https://{}/getUsageLimits?origin=AI_EDITOR&resourceType=AGENTIC_REQUEST",
-        host
-    );
+    let mut url = format!("{}?origin=AI_EDITOR&resourceType=AGENTIC_REQUEST", base_url);
 
     // profileArn 是可选的
     if let Some(profile_arn) = &credentials.profile_arn {
@@ -353,7 +456,7 @@ pub(crate) async fn get_usage_limits(
         USAGE_LIMITS_AMZ_USER_AGENT_PREFIX, kiro_version, machine_id
     );
 
-    let client = build_client(proxy, 60, config.tls_backend)?;
+    let client = build_client(proxy, 60, &TransportOptions::from_config(config))?;
 
     let response = client
         .get(&url)
@@ -366,6 +469,7 @@ pub(crate) async fn get_usage_limits(
         .header("Connection", "close")
         .send()
         .await?;
+    clock::observe(response.headers());
 
     let status = response.status();
     if !status.is_success() {
@@ -380,34 +484,103 @@ pub(crate) async fn get_usage_limits(
         bail!("{}: {} {}", error_msg, status, body_text);
     }
 
-    let data: UsageLimitsResponse = response.json().await?;
+    let body = response.text().await?;
+    let data: UsageLimitsResponse = serde_json::from_str(&body).with_context(|| {
+        let preview: String = body.chars().take(256).collect();
+        format!("解析使用额度响应失败: {}", preview)
+    })?;
+    let unknown = data.unknown_fields();
+    if !unknown.is_empty() {
+        tracing::debug!("getUsageLimits 响应包含未知字段: {}", unknown.join(", "));
+    }
     Ok(data)
 }
 
+/// `current` 的 Token 过期时间是否晚于 `other`（用于合并外部修改时保留更新的 Token）
+fn token_expires_later(current: &KiroCredentials, other: &KiroCredentials) -> bool {
+    let parse = |c: &KiroCredentials| {
+        c.expires_at
+            .as_deref()
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+    };
+    match (parse(current), parse(other)) {
+        (Some(current), Some(other)) => current > other,
+        _ => false,
+    }
+}
+
 // ============================================================================
 // 多凭据 Token 管理器
 // ============================================================================
 
+/// 凭据来源文件
+#[derive(Debug, Clone, Default)]
+pub struct CredentialSource {
+    /// 凭据文件路径（None 表示不回写）
+    pub path: Option<PathBuf>,
+    /// 是否为多凭据格式（数组格式才回写）
+    pub is_multiple_format: bool,
+    /// 是否只读（只读文件永不回写，其中的凭据也不能通过 Admin API 删除）
+    pub read_only: bool,
+    /// 该文件中凭据的每日上游请求上限（0 表示不限制）
+    pub daily_request_limit: u64,
+}
+
+impl CredentialSource {
+    /// 是否需要回写到文件
+    fn is_writable(&self) -> bool {
+        self.path.is_some() && self.is_multiple_format && !self.read_only
+    }
+}
+
+/// 凭据来源文件及其回写器
+struct SourceState {
+    source: CredentialSource,
+    /// 凭据回写器（仅可回写的来源才有）
+    writer: Option<CredentialsWriter>,
+}
+
 /// 单个凭据条目的状态
 struct CredentialEntry {
     /// 凭据唯一 ID
     id: u64,
+    /// 来源文件索引（对应 MultiTokenManager::sources）
+    source: usize,
     /// 凭据信息
     credentials: KiroCredentials,
+    /// 加盐的 refreshToken 哈希（随凭据更新）
+    refresh_token_hash: Option<String>,
     /// API 调用连续失败次数
     failure_count: u32,
     /// 是否已禁用
     disabled: bool,
     /// 禁用原因（用于区分手动禁用 vs 自动禁用，便于自愈）
     disabled_reason: Option<DisabledReason>,
+    /// 禁用的自动解除时间（仅额度用尽时已知额度重置时间才有，重启加载时生效）
+    disabled_until: Option<DateTime<Utc>>,
+    /// 退避级别：自上次成功以来连续的瞬态失败次数（不会导致禁用）
+    backoff_level: u32,
+    /// 最近一次失败的分类与时间
+    last_failure: Option<(FailureKind, DateTime<Utc>)>,
     /// API 调用成功次数
     success_count: u64,
+    /// API 调用失败次数（含瞬态失败，仅进程内累计，用于再平衡分析）
+    failure_total: u64,
     /// 最后一次 API 调用时间（RFC3339 格式）
     last_used_at: Option<String>,
 }
 
+impl CredentialEntry {
+    /// 替换凭据信息并重新计算 refreshToken 哈希
+    fn set_credentials(&mut self, credentials: KiroCredentials, salt: &str) {
+        self.refresh_token_hash = refresh_token_hash(salt, &credentials);
+        self.credentials = credentials;
+    }
+}
+
 /// 禁用原因
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 enum DisabledReason {
     /// Admin API 手动禁用
     Manual,
@@ -417,17 +590,177 @@ enum DisabledReason {
     QuotaExceeded,
 }
 
+/// 凭据失败分类（用于诊断）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// 网络错误（请求未送达上游）
+    Network,
+    /// 上游限流（429）
+    Throttled,
+    /// 上游瞬态错误（408 / 5xx 等）
+    Upstream,
+    /// 凭据或权限错误（401 / 403），计入连续失败
+    Auth,
+    /// 额度用尽（402 MONTHLY_REQUEST_COUNT）
+    QuotaExhausted,
+}
+
+impl FailureKind {
+    /// Admin API 中使用的标识
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Network => "network",
+            Self::Throttled => "throttled",
+            Self::Upstream => "upstream",
+            Self::Auth => "auth",
+            Self::QuotaExhausted => "quota_exhausted",
+        }
+    }
+}
+
+/// 凭据当前不会被选中的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnavailableReason {
+    /// 手动禁用
+    Disabled,
+    /// 连续失败达到阈值后自动禁用
+    TooManyFailures,
+    /// 额度已用尽
+    QuotaExceeded,
+    /// 凭据自身的每日请求上限已用完
+    DailyLimit,
+    /// 所在凭据池的每日请求上限已用完
+    PoolDailyLimit,
+}
+
+impl UnavailableReason {
+    /// Admin API 中使用的标识
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Disabled => "disabled",
+            Self::TooManyFailures => "too_many_failures",
+            Self::QuotaExceeded => "quota_exceeded",
+            Self::DailyLimit => "daily_limit",
+            Self::PoolDailyLimit => "pool_daily_limit",
+        }
+    }
+}
+
+/// 请求固定的凭据无法使用（不会切换到其他凭据）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinnedCredentialUnavailable {
+    pub id: u64,
+    /// 不可用原因（凭据不存在时为 None）
+    pub reason: Option<UnavailableReason>,
+}
+
+impl std::fmt::Display for PinnedCredentialUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.reason {
+            Some(reason) => write!(f, "凭据 #{} 当前不可用（{}）", self.id, reason.as_str()),
+            None => write!(f, "凭据 #{} 不存在", self.id),
+        }
+    }
+}
+
+impl std::error::Error for PinnedCredentialUnavailable {}
+
+/// 同一优先级的可用凭据汇总（用于分层溢出）
+#[derive(Debug, Default)]
+struct PriorityTier {
+    /// 可用凭据数量
+    available: usize,
+    /// 剩余额度合计（该层有凭据尚无余额数据时为 None，视为未知而非耗尽）
+    remaining: Option<f64>,
+}
+
+/// 计算分层溢出后参与选择的最低优先级
+///
+/// 从优先级最高的一层开始累计，可用凭据数低于 `min_available`，
+/// 或已知剩余额度合计低于 `min_balance` 时纳入下一层（阈值为 0 表示不按该项判断）。
+/// 只需使用最高优先级层时返回 None
+fn spillover_cutoff(
+    tiers: &BTreeMap<u32, PriorityTier>,
+    min_available: usize,
+    min_balance: f64,
+) -> Option<u32> {
+    let mut available = 0;
+    let mut remaining: Option<f64> = None;
+    let mut cutoff = None;
+    for (i, (priority, tier)) in tiers.iter().enumerate() {
+        let short_of_available = available < min_available;
+        let short_of_balance = min_balance > 0.0 && remaining.is_some_and(|r| r < min_balance);
+        if i > 0 && !short_of_available && !short_of_balance {
+            break;
+        }
+        available += tier.available;
+        if let Some(r) = tier.remaining {
+            *remaining.get_or_insert(0.0) += r;
+        }
+        if i > 0 {
+            cutoff = Some(*priority);
+        }
+    }
+    cutoff
+}
+
+/// 突发溢出：在常规溢出的基础上再纳入下一优先级层
+///
+/// `cutoff` 为 None（只使用最高优先级层）时纳入第二层；已纳入全部层时保持不变
+fn widen_cutoff(tiers: &BTreeMap<u32, PriorityTier>, cutoff: Option<u32>) -> Option<u32> {
+    let from = cutoff.or_else(|| tiers.keys().next().copied())?;
+    tiers.keys().copied().find(|p| *p > from).or(cutoff)
+}
+
 /// 统计数据持久化条目
 #[derive(Serialize, Deserialize)]
 struct StatsEntry {
     success_count: u64,
     last_used_at: Option<String>,
+    /// 禁用状态（未禁用时不写入）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    disabled: Option<DisabledState>,
+}
+
+/// 持久化的禁用状态
+///
+/// 重启后恢复，避免重启立即解除自动禁用（如额度用尽）后又被上游判定异常
+#[derive(Serialize, Deserialize)]
+struct DisabledState {
+    reason: DisabledReason,
+    failure_count: u32,
+    /// 自动解除时间（None 表示需手动启用或等待自愈）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    until: Option<DateTime<Utc>>,
 }
 
 // ============================================================================
 // Admin API 公开结构
 // ============================================================================
 
+/// 亲和绑定
+#[derive(Debug, Clone)]
+struct AffinityBinding {
+    credential_id: u64,
+    bound_at: DateTime<Utc>,
+    last_used_at: DateTime<Utc>,
+}
+
+/// 亲和绑定快照（用于 Admin API 读取）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AffinityBindingSnapshot {
+    /// 亲和键（metadata.user_id，子代理带 `#subagent-` 后缀，IP 亲和为 `ip:` 前缀）
+    pub key: String,
+    pub credential_id: u64,
+    /// 绑定时间（RFC3339 格式）
+    pub bound_at: String,
+    /// 最后使用时间（RFC3339 格式）
+    pub last_used_at: String,
+}
+
 /// 凭据条目快照（用于 Admin API 读取）
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -442,14 +775,28 @@ pub struct CredentialEntrySnapshot {
     pub failure_count: u32,
     /// 认证方式
     pub auth_method: Option<String>,
+    /// 按凭据内容推断的认证方式（仅在与配置的 authMethod 不一致时设置）
+    pub detected_auth_method: Option<String>,
     /// 是否有 Profile ARN
     pub has_profile_arn: bool,
     /// Token 过期时间
     pub expires_at: Option<String>,
-    /// refreshToken 的 SHA-256 哈希（用于前端重复检测）
+    /// 加盐的 refreshToken SHA-256 哈希前缀（用于跨系统匹配账号）
     pub refresh_token_hash: Option<String>,
+    /// refreshToken 的签发时间（RFC3339 格式）
+    pub refresh_token_issued_at: Option<String>,
+    /// refreshToken 最近一次被上游轮换的时间（RFC3339 格式）
+    pub refresh_token_rotated_at: Option<String>,
+    /// 估算的 refreshToken 过期时间（RFC3339 格式，仅 IdC 凭据）
+    pub refresh_token_expires_at: Option<String>,
+    /// refreshToken 是否已进入过期告警窗口
+    pub refresh_token_expiring: bool,
     /// 用户邮箱（用于前端显示）
     pub email: Option<String>,
+    /// 标签（运营方自定义的显示名称）
+    pub label: Option<String>,
+    /// 备注
+    pub notes: Option<String>,
     /// API 调用成功次数
     pub success_count: u64,
     /// 最后一次 API 调用时间（RFC3339 格式）
@@ -459,6 +806,24 @@ pub struct CredentialEntrySnapshot {
     /// 代理 URL（用于前端展示）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_url: Option<String>,
+    /// 是否来自只读凭据文件
+    pub read_only: bool,
+    /// 今日已计入的请求数
+    pub requests_today: u64,
+    /// 凭据级每日请求上限（None 表示不限制）
+    pub daily_request_limit: Option<u64>,
+    /// 金丝雀流量百分比（None 表示正常参与轮换）
+    pub canary_percent: Option<u8>,
+    /// 退避级别（自上次成功以来连续的瞬态失败次数）
+    pub backoff_level: u32,
+    /// 最近一次失败的分类
+    pub last_failure: Option<FailureKind>,
+    /// 最近一次失败的时间（RFC3339 格式）
+    pub last_failure_at: Option<String>,
+    /// 当前不会被选中的原因（None 表示可被选中）
+    pub unavailable_reason: Option<UnavailableReason>,
+    /// 预计恢复可选的时间（RFC3339 格式；需手动处理时为 None）
+    pub available_at: Option<String>,
 }
 
 /// 凭据管理器状态快照
@@ -475,6 +840,61 @@ pub struct ManagerSnapshot {
     pub available: usize,
 }
 
+/// 凭据校验状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationStatus {
+    /// 刷新成功
+    Valid,
+    /// 刷新失败（或缺少 refreshToken）
+    Invalid(String),
+    /// refreshToken 已被截断，无需请求上游即可判定无效
+    Truncated(String),
+}
+
+impl ValidationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValidationStatus::Valid => "valid",
+            ValidationStatus::Invalid(_) => "invalid",
+            ValidationStatus::Truncated(_) => "truncated",
+        }
+    }
+
+    /// 失败原因
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            ValidationStatus::Valid => None,
+            ValidationStatus::Invalid(e) | ValidationStatus::Truncated(e) => Some(e),
+        }
+    }
+}
+
+/// 单个凭据的校验结果
+#[derive(Debug, Clone)]
+pub struct CredentialValidation {
+    /// 凭据 ID
+    pub id: u64,
+    /// 用户邮箱
+    pub email: Option<String>,
+    /// 认证方式
+    pub auth_method: Option<String>,
+    /// 校验状态
+    pub status: ValidationStatus,
+    /// 刷新后的 Token 过期时间（仅校验成功时有值）
+    pub expires_at: Option<String>,
+}
+
+/// 单个凭据的主动刷新结果
+#[derive(Debug, Clone)]
+pub struct RefreshResult {
+    /// 凭据 ID
+    pub id: u64,
+    /// 刷新后的 Token 过期时间（仅刷新成功时有值）
+    pub expires_at: Option<String>,
+    /// 失败原因
+    pub error: Option<String>,
+}
+
 /// 多凭据 Token 管理器
 ///
 /// 支持多个凭据的管理，实现固定优先级 + 故障转移策略
@@ -488,18 +908,67 @@ pub struct MultiTokenManager {
     current_id: Mutex<u64>,
     /// Token 刷新锁，确保同一时间只有一个刷新操作
     refresh_lock: TokioMutex<()>,
-    /// 凭据文件路径（用于回写）
-    credentials_path: Option<PathBuf>,
-    /// 是否为多凭据格式（数组格式才回写）
-    is_multiple_format: bool,
+    /// 正在刷新 Token 的凭据 ID
+    refreshing: Mutex<HashSet<u64>>,
+    /// 凭据来源文件（多个文件合并为同一个凭据池，按来源分别回写）
+    sources: Vec<SourceState>,
+    /// 凭据快照版本号（每次回写递增）
+    persist_generation: AtomicU64,
+    /// 凭据状态版本号（凭据列表、状态或当前凭据变化时递增，用于 Admin API 的 ETag）
+    state_version: AtomicU64,
     /// 负载均衡模式（运行时可修改）
     load_balancing_mode: Mutex<String>,
+    /// 亲和绑定：亲和键 -> 绑定信息（仅 balanced 模式生效）
+    affinity: Mutex<HashMap<String, AffinityBinding>>,
     /// 最近一次统计持久化时间（用于 debounce）
     last_stats_save_at: Mutex<Option<Instant>>,
     /// 统计数据是否有未落盘更新
     stats_dirty: AtomicBool,
+    /// 累计运行指标（随统计数据一起持久化）
+    metrics: Arc<Metrics>,
+    /// 余额缓存（Admin API 与额度查询共用）
+    balance_store: BalanceStore,
+    /// 已删除凭据归档（保留期内可恢复）
+    archive: CredentialArchive,
+    /// 每日请求上限（全局与各凭据池）
+    daily_limit: DailyLimiter,
+    /// 凭据池再平衡分析器
+    rebalancer: RebalanceAnalyzer,
+    /// 上游错误调试转储（未配置 debugDumpDir 时为 None）
+    debug_dumps: Option<DumpStore>,
+    /// 凭据状态变化事件总线
+    events: EventBus,
+    /// 突发溢出的截止时间（准入排队超过 spilloverQueueDelayMs 时延长）
+    burst_until: Mutex<Option<Instant>>,
+    /// 已发出 refreshToken 即将过期告警的凭据（过期时间重新超出告警窗口后移除）
+    refresh_token_warned: Mutex<HashSet<u64>>,
+}
+
+/// 刷新中凭据的登记守卫，Drop 时移除
+struct RefreshingGuard<'a> {
+    refreshing: &'a Mutex<HashSet<u64>>,
+    id: u64,
 }
 
+impl<'a> RefreshingGuard<'a> {
+    fn new(refreshing: &'a Mutex<HashSet<u64>>, id: u64) -> Self {
+        refreshing.lock().insert(id);
+        Self { refreshing, id }
+    }
+}
+
+impl Drop for RefreshingGuard<'_> {
+    fn drop(&mut self) {
+        self.refreshing.lock().remove(&self.id);
+    }
+}
+
+/// 凭据校验的默认并发数
+pub const VALIDATION_CONCURRENCY: usize = 8;
+
+/// 等待进程间刷新锁的最长时间
+const REFRESH_LOCK_TIMEOUT: StdDuration = StdDuration::from_secs(30);
+
 /// 每个凭据最大 API 调用失败次数
 const MAX_FAILURES_PER_CREDENTIAL: u32 = 3;
 /// 统计数据持久化防抖间隔
@@ -528,24 +997,63 @@ impl MultiTokenManager {
     /// * `proxy` - 可选的代理配置
     /// * `credentials_path` - 凭据文件路径（用于回写）
     /// * `is_multiple_format` - 是否为多凭据格式（数组格式才回写）
+    #[allow(dead_code)]
     pub fn new(
         config: Config,
         credentials: Vec<KiroCredentials>,
         proxy: Option<ProxyConfig>,
         credentials_path: Option<PathBuf>,
         is_multiple_format: bool,
+    ) -> anyhow::Result<Self> {
+        let source = CredentialSource {
+            path: credentials_path,
+            is_multiple_format,
+            read_only: false,
+            daily_request_limit: 0,
+        };
+        Self::with_sources(config, vec![(source, credentials)], proxy)
+    }
+
+    /// 从多个凭据文件创建 Token 管理器
+    ///
+    /// 所有文件中的凭据合并为同一个凭据池（ID 全局唯一），
+    /// 回写时按来源文件分别写回，只读文件不回写。
+    /// 缓存文件（统计、余额等）存放在第一个文件所在目录。
+    ///
+    /// # Arguments
+    /// * `config` - 应用配置
+    /// * `sources` - (来源文件, 该文件中的凭据列表)
+    /// * `proxy` - 可选的代理配置
+    pub fn with_sources(
+        config: Config,
+        sources: Vec<(CredentialSource, Vec<KiroCredentials>)>,
+        proxy: Option<ProxyConfig>,
     ) -> anyhow::Result<Self> {
         // 计算当前最大 ID，为没有 ID 的凭据分配新 ID
-        let max_existing_id = credentials.iter().filter_map(|c| c.id).max().unwrap_or(0);
+        let max_existing_id = sources
+            .iter()
+            .flat_map(|(_, creds)| creds.iter())
+            .filter_map(|c| c.id)
+            .max()
+            .unwrap_or(0);
         let mut next_id = max_existing_id + 1;
         let mut has_new_ids = false;
         let mut has_new_machine_ids = false;
+        let mut has_migrated_auth_methods = false;
+        let mut has_new_issued_at = false;
         let config_ref = &config;
 
+        let mut source_list = Vec::with_capacity(sources.len());
+        let mut credentials = Vec::new();
+        for (index, (source, creds)) in sources.into_iter().enumerate() {
+            source_list.push(source);
+            credentials.extend(creds.into_iter().map(|cred| (index, cred)));
+        }
+
         let entries: Vec<CredentialEntry> = credentials
             .into_iter()
-            .map(|mut cred| {
-                cred.canonicalize_auth_method();
+            .map(|(source, mut cred)| {
+                has_migrated_auth_methods |= cred.canonicalize_auth_method();
                 let id = cred.id.unwrap_or_else(|| {
                     let id = next_id;
                     next_id += 1;
@@ -561,13 +1069,24 @@ impl MultiTokenManager {
                         has_new_machine_ids = true;
                     }
                 }
+                // 没有记录签发时间的 refreshToken 以首次加载时间作为签发时间
+                has_new_issued_at |= record_refresh_token_issued_at(&mut cred);
                 CredentialEntry {
                     id,
+                    source,
+                    refresh_token_hash: refresh_token_hash(
+                        &config_ref.refresh_token_hash_salt,
+                        &cred,
+                    ),
                     credentials: cred,
                     failure_count: 0,
                     disabled: false,
                     disabled_reason: None,
+                    disabled_until: None,
+                    backoff_level: 0,
+                    last_failure: None,
                     success_count: 0,
+                    failure_total: 0,
                     last_used_at: None,
                 }
             })
@@ -593,21 +1112,93 @@ impl MultiTokenManager {
             .unwrap_or(0);
 
         let load_balancing_mode = config.load_balancing_mode.clone();
+        let balance_store = BalanceStore::new(
+            source_list
+                .first()
+                .and_then(|s| s.path.as_ref())
+                .and_then(|p| p.parent())
+                .map(|d| d.join("kiro_balance_cache.json")),
+            BalanceTtlPolicy::from_config(&config),
+        );
+        let archive = CredentialArchive::new(
+            source_list
+                .first()
+                .and_then(|s| s.path.as_ref())
+                .and_then(|p| p.parent())
+                .map(|d| d.join(ARCHIVE_FILE)),
+            config.archive_retention_days,
+        );
+        let daily_limit = DailyLimiter::new(
+            config.daily_request_limit,
+            source_list.iter().map(|s| s.daily_request_limit).collect(),
+            DailyResetZone::parse(&config.daily_reset_timezone).unwrap_or(DailyResetZone::Local),
+            source_list
+                .first()
+                .and_then(|s| s.path.as_ref())
+                .and_then(|p| p.parent())
+                .map(|d| d.join("kiro_daily_usage.json")),
+        );
+        let debug_dumps = DumpStore::from_config(&config);
+        let sources = source_list
+            .into_iter()
+            .map(|source| {
+                let writer = match &source.path {
+                    Some(path) if source.is_writable() => {
+                        Some(CredentialsWriter::spawn(path.clone())?)
+                    }
+                    _ => None,
+                };
+                Ok(SourceState { source, writer })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
         let manager = Self {
             config,
             proxy,
             entries: Mutex::new(entries),
             current_id: Mutex::new(initial_id),
             refresh_lock: TokioMutex::new(()),
-            credentials_path,
-            is_multiple_format,
+            refreshing: Mutex::new(HashSet::new()),
+            sources,
+            persist_generation: AtomicU64::new(0),
+            state_version: AtomicU64::new(0),
             load_balancing_mode: Mutex::new(load_balancing_mode),
+            affinity: Mutex::new(HashMap::new()),
             last_stats_save_at: Mutex::new(None),
             stats_dirty: AtomicBool::new(false),
+            metrics: Arc::new(Metrics::new()),
+            balance_store,
+            archive,
+            daily_limit,
+            rebalancer: RebalanceAnalyzer::default(),
+            debug_dumps,
+            events: EventBus::default(),
+            burst_until: Mutex::new(None),
+            refresh_token_warned: Mutex::new(HashSet::new()),
         };
 
-        // 如果有新分配的 ID 或新生成的 machineId，立即持久化到配置文件
-        if has_new_ids || has_new_machine_ids {
+        // 推断的认证方式与配置不一致、或 refreshToken 明显无效时提示（Admin API 中同样会返回警告）
+        for entry in manager.entries.lock().iter() {
+            if entry.credentials.refresh_token.is_some()
+                && let Err(e) = validate_refresh_token(&entry.credentials)
+            {
+                tracing::warn!(
+                    "凭据 {} 的 refreshToken 无效，Token 刷新将失败: {}",
+                    entry.credentials.describe(entry.id),
+                    e
+                );
+            }
+            if let Some(detected) = entry.credentials.auth_method_conflict() {
+                tracing::warn!(
+                    "凭据 {} 配置的 authMethod 为 {}，但凭据内容更像 {}，Token 刷新可能失败",
+                    entry.credentials.describe(entry.id),
+                    entry.credentials.auth_method.as_deref().unwrap_or_default(),
+                    detected
+                );
+            }
+        }
+
+        // 如果有新分配的 ID、新生成的 machineId、新记录的签发时间或迁移了旧的 authMethod 写法，立即持久化到配置文件
+        if has_new_ids || has_new_machine_ids || has_new_issued_at || has_migrated_auth_methods {
             if let Err(e) = manager.persist_credentials() {
                 tracing::warn!("补全凭据 ID/machineId 后持久化失败: {}", e);
             } else {
@@ -647,6 +1238,45 @@ impl MultiTokenManager {
         self.entries.lock().iter().filter(|e| !e.disabled).count()
     }
 
+    /// 锁定凭据列表用于修改，并递增状态版本号
+    fn entries_mut(&self) -> parking_lot::MutexGuard<'_, Vec<CredentialEntry>> {
+        self.bump_state_version();
+        self.entries.lock()
+    }
+
+    /// 锁定当前凭据 ID 用于修改，并递增状态版本号
+    fn current_id_mut(&self) -> parking_lot::MutexGuard<'_, u64> {
+        self.bump_state_version();
+        self.current_id.lock()
+    }
+
+    fn bump_state_version(&self) {
+        self.state_version.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录凭据的一次上游请求（计入每日请求数）
+    fn record_daily(&self, source: usize, id: u64) {
+        self.daily_limit.record(source, id);
+        self.bump_state_version();
+    }
+
+    /// 凭据状态的 ETag（Admin API 轮询用）
+    ///
+    /// 由状态版本号与每日计数周期组成：凭据状态变化或每日计数清零后即改变，
+    /// 判断状态是否变化无需获取凭据列表锁
+    pub fn status_etag(&self) -> String {
+        format!(
+            "W/\"{}-{}\"",
+            self.state_version.load(Ordering::Relaxed),
+            self.daily_limit.next_reset().timestamp()
+        )
+    }
+
+    /// 订阅凭据状态变化事件（Admin API）
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<BusEvent> {
+        self.events.subscribe()
+    }
+
     /// 根据负载均衡模式选择下一个凭据
     ///
     /// - priority 模式：选择优先级最高（priority 最小）的可用凭据
@@ -654,27 +1284,19 @@ impl MultiTokenManager {
     ///
     /// # 参数
     /// - `model`: 可选的模型名称，用于过滤支持该模型的凭据（如 opus 模型需要付费订阅）
-    fn select_next_credential(&self, model: Option<&str>) -> Option<(u64, KiroCredentials)> {
+    /// - `exclude`: 可选的排除凭据（流式响应中断后重试时排除刚失败的凭据）
+    fn select_next_credential(
+        &self,
+        model: Option<&str>,
+        exclude: Option<u64>,
+    ) -> Option<(u64, KiroCredentials)> {
         let entries = self.entries.lock();
 
-        // 检查是否是 opus 模型
-        let is_opus = model
-            .map(|m| m.to_lowercase().contains("opus"))
-            .unwrap_or(false);
-
         // 过滤可用凭据
-        let available: Vec<_> = entries
-            .iter()
-            .filter(|e| {
-                if e.disabled {
-                    return false;
-                }
-                // 如果是 opus 模型，需要检查订阅等级
-                if is_opus && !e.credentials.supports_opus() {
-                    return false;
-                }
-                true
-            })
+        let available: Vec<&CredentialEntry> = self
+            .selectable_entries(&entries, model)
+            .into_iter()
+            .filter(|e| Some(e.id) != exclude)
             .collect();
 
         if available.is_empty() {
@@ -696,65 +1318,517 @@ impl MultiTokenManager {
             }
             _ => {
                 // priority 模式（默认）：选择优先级最高的
-                let entry = available.iter().min_by_key(|e| e.credentials.priority)?;
+                // 启用溢出且需要纳入后续层时，在已纳入的各层中选择成功次数最少的凭据
+                let entry = match self.spillover_cutoff(&available) {
+                    Some(cutoff) => available
+                        .iter()
+                        .filter(|e| e.credentials.priority <= cutoff)
+                        .min_by_key(|e| (e.success_count, e.credentials.priority))?,
+                    None => available.iter().min_by_key(|e| e.credentials.priority)?,
+                };
                 Some((entry.id, entry.credentials.clone()))
             }
         }
     }
 
-    /// 获取 API 调用上下文
-    ///
-    /// 返回绑定了 id、credentials 和 token 的调用上下文
-    /// 确保整个 API 调用过程中使用一致的凭据信息
+    /// 按负载均衡策略选择凭据（不含金丝雀抽样），不选择 `exclude`
     ///
-    /// 如果 Token 过期或即将过期，会自动刷新
-    /// Token 刷新失败时会尝试下一个可用凭据（不计入失败次数）
+    /// - priority 模式：优先使用 current_id 指向的凭据；溢出到多个优先级层时每次请求重新选择，使负载分散到各层
+    /// - balanced 模式：每次请求都重新选择；有亲和键时按亲和绑定选择（亲和选择不考虑金丝雀凭据，只剩金丝雀凭据时退回常规选择）
     ///
-    /// # 参数
-    /// - `model`: 可选的模型名称，用于过滤支持该模型的凭据（如 opus 模型需要付费订阅）
-    pub async fn acquire_context(&self, model: Option<&str>) -> anyhow::Result<CallContext> {
-        let total = self.total_count();
-        let mut tried_count = 0;
+    /// `bind` 为 false 时只预测选择结果，不修改亲和绑定（用于预热备用凭据）
+    fn select_by_strategy(
+        &self,
+        model: Option<&str>,
+        affinity_key: Option<&str>,
+        exclude: Option<u64>,
+        bind: bool,
+    ) -> Option<(u64, KiroCredentials)> {
+        let is_balanced = self.load_balancing_mode.lock().as_str() == "balanced";
+        if !is_balanced
+            && !self.spillover_engaged(model)
+            && let Some(hit) = self.current_credential(exclude)
+        {
+            return Some(hit);
+        }
+        self.select_fresh(model, affinity_key, exclude, bind)
+    }
 
-        loop {
-            if tried_count >= total {
-                anyhow::bail!(
-                    "所有凭据均无法获取有效 Token（可用: {}/{}）",
-                    self.available_count(),
-                    total
-                );
+    /// 不沿用当前凭据，按负载均衡策略重新选择（balanced 模式有亲和键时按亲和绑定选择）
+    fn select_fresh(
+        &self,
+        model: Option<&str>,
+        affinity_key: Option<&str>,
+        exclude: Option<u64>,
+        bind: bool,
+    ) -> Option<(u64, KiroCredentials)> {
+        let is_balanced = self.load_balancing_mode.lock().as_str() == "balanced";
+        let affinity_hit = match affinity_key {
+            Some(key) if is_balanced && bind => {
+                self.select_affinity_credential(model, key, exclude)
             }
+            Some(key) if is_balanced => self.affinity_candidate(model, key, exclude),
+            _ => None,
+        };
+        affinity_hit.or_else(|| self.select_next_credential(model, exclude))
+    }
 
-            let (id, credentials) = {
-                let is_balanced = self.load_balancing_mode.lock().as_str() == "balanced";
-
-                // balanced 模式：每次请求都轮询选择，不固定 current_id
-                // priority 模式：优先使用 current_id 指向的凭据
-                let current_hit = if is_balanced {
-                    None
-                } else {
-                    let entries = self.entries.lock();
-                    let current_id = *self.current_id.lock();
-                    entries
-                        .iter()
-                        .find(|e| e.id == current_id && !e.disabled)
-                        .map(|e| (e.id, e.credentials.clone()))
-                };
+    /// priority 模式下的当前凭据（不可用或为 `exclude` 时返回 None）
+    fn current_credential(&self, exclude: Option<u64>) -> Option<(u64, KiroCredentials)> {
+        let entries = self.entries.lock();
+        let current_id = *self.current_id.lock();
+        entries
+            .iter()
+            .find(|e| {
+                e.id == current_id
+                    && Some(e.id) != exclude
+                    && !e.disabled
+                    && !e.credentials.is_canary()
+                    && self.within_daily_limit(e)
+            })
+            .map(|e| (e.id, e.credentials.clone()))
+    }
+
+    /// `exclude` 的流式响应中断时故障转移将选中的备用凭据（不改变当前凭据与亲和绑定）
+    ///
+    /// 与故障转移时的选择共用 [`Self::select_by_strategy`]，只是不进行金丝雀抽样
+    pub fn standby_candidate(
+        &self,
+        model: Option<&str>,
+        affinity_key: Option<&str>,
+        exclude: u64,
+    ) -> Option<(u64, KiroCredentials)> {
+        self.select_by_strategy(model, affinity_key, Some(exclude), false)
+    }
+
+    /// 预先确认凭据的 Token 有效（过期或即将过期时刷新），不计入请求统计与每日请求数
+    pub async fn warm_token(&self, id: u64) -> anyhow::Result<()> {
+        let credentials = self.entry_credentials(id)?;
+        self.try_ensure_token(id, &credentials).await.map(|_| ())
+    }
+
+    /// 凭据及其所在凭据池当天是否仍有请求额度
+    fn within_daily_limit(&self, entry: &CredentialEntry) -> bool {
+        self.daily_limit.pool_available(entry.source)
+            && self
+                .daily_limit
+                .credential_available(entry.id, entry.credentials.daily_request_limit)
+    }
+
+    /// 凭据当前不会被选中的原因，以及预计恢复可选的时间（需手动处理时为 None）
+    fn unavailability(
+        &self,
+        entry: &CredentialEntry,
+    ) -> Option<(UnavailableReason, Option<DateTime<Utc>>)> {
+        if entry.disabled {
+            return Some(match entry.disabled_reason {
+                Some(DisabledReason::TooManyFailures) => (UnavailableReason::TooManyFailures, None),
+                Some(DisabledReason::QuotaExceeded) => {
+                    (UnavailableReason::QuotaExceeded, entry.disabled_until)
+                }
+                _ => (UnavailableReason::Disabled, None),
+            });
+        }
+        if !self.daily_limit.pool_available(entry.source) {
+            return Some((
+                UnavailableReason::PoolDailyLimit,
+                Some(self.daily_limit.next_reset()),
+            ));
+        }
+        if !self
+            .daily_limit
+            .credential_available(entry.id, entry.credentials.daily_request_limit)
+        {
+            return Some((
+                UnavailableReason::DailyLimit,
+                Some(self.daily_limit.next_reset()),
+            ));
+        }
+        None
+    }
+
+    /// 过滤出参与常规轮换、可用于指定模型的凭据
+    ///
+    /// 金丝雀凭据只通过抽样承担流量；没有其他可用凭据时才参与常规轮换
+    fn selectable_entries<'a>(
+        &self,
+        entries: &'a [CredentialEntry],
+        model: Option<&str>,
+    ) -> Vec<&'a CredentialEntry> {
+        let eligible = self.eligible_entries(entries, model);
+        if eligible.iter().all(|e| e.credentials.is_canary()) {
+            return eligible;
+        }
+        eligible
+            .into_iter()
+            .filter(|e| !e.credentials.is_canary())
+            .collect()
+    }
+
+    /// 按金丝雀凭据的流量百分比抽样，命中时返回该凭据
+    fn select_canary(&self, model: Option<&str>) -> Option<(u64, KiroCredentials)> {
+        let entries = self.entries.lock();
+        let roll = fastrand::u32(0..100);
+        let mut threshold = 0u32;
+        for entry in self.eligible_entries(&entries, model) {
+            let Some(percent) = entry.credentials.canary_percent else {
+                continue;
+            };
+            threshold += u32::from(percent);
+            if roll < threshold {
+                tracing::debug!(
+                    "金丝雀抽样命中凭据 {}",
+                    entry.credentials.describe(entry.id)
+                );
+                return Some((entry.id, entry.credentials.clone()));
+            }
+        }
+        None
+    }
+
+    /// 过滤出可用于指定模型的凭据（含金丝雀凭据）
+    fn eligible_entries<'a>(
+        &self,
+        entries: &'a [CredentialEntry],
+        model: Option<&str>,
+    ) -> Vec<&'a CredentialEntry> {
+        // 检查是否是 opus 模型
+        let is_opus = model
+            .map(|m| m.to_lowercase().contains("opus"))
+            .unwrap_or(false);
+
+        entries
+            .iter()
+            .filter(|e| {
+                if let Some((reason, _)) = self.unavailability(e) {
+                    tracing::debug!(
+                        "跳过凭据 {}：{}",
+                        e.credentials.describe(e.id),
+                        reason.as_str()
+                    );
+                    return false;
+                }
+                // 如果是 opus 模型，需要检查订阅等级
+                if is_opus && !e.credentials.supports_opus() {
+                    tracing::debug!(
+                        "跳过凭据 {}：订阅等级不支持 Opus",
+                        e.credentials.describe(e.id)
+                    );
+                    return false;
+                }
+                true
+            })
+            .collect()
+    }
+
+    /// priority 模式下当前是否溢出到多个优先级层
+    fn spillover_engaged(&self, model: Option<&str>) -> bool {
+        let entries = self.entries.lock();
+        let available = self.selectable_entries(&entries, model);
+        self.spillover_cutoff(&available).is_some()
+    }
+
+    /// priority 模式下的优先级分层溢出
+    ///
+    /// 返回参与选择的最低优先级（priority 数值上限）；未启用溢出或只需使用最高优先级层时返回 None
+    fn spillover_cutoff(&self, available: &[&CredentialEntry]) -> Option<u32> {
+        let min_available = self.config.spillover_min_available;
+        let min_balance = self.config.spillover_min_balance;
+        let burst = self.burst_active();
+        if min_available == 0 && min_balance <= 0.0 && !burst {
+            return None;
+        }
+
+        let mut tiers: BTreeMap<u32, PriorityTier> = BTreeMap::new();
+        let mut unknown_tiers = HashSet::new();
+        for entry in available {
+            let tier = tiers.entry(entry.credentials.priority).or_default();
+            tier.available += 1;
+            match self.balance_store.latest(entry.id) {
+                Some(balance) => {
+                    let remaining = (balance.usage_limit - balance.current_usage).max(0.0);
+                    *tier.remaining.get_or_insert(0.0) += remaining;
+                }
+                // 余额尚未初始化（如延迟启动时预热未完成）
+                None => {
+                    unknown_tiers.insert(entry.credentials.priority);
+                }
+            }
+        }
+        for priority in unknown_tiers {
+            if let Some(tier) = tiers.get_mut(&priority) {
+                tier.remaining = None;
+            }
+        }
+
+        let mut cutoff = spillover_cutoff(&tiers, min_available, min_balance);
+        if burst {
+            cutoff = widen_cutoff(&tiers, cutoff);
+        }
+        let cutoff = cutoff?;
+        tracing::debug!(
+            "优先级最高的凭据层余量不足{}，溢出到 priority <= {} 的凭据",
+            if burst { "（突发流量）" } else { "" },
+            cutoff
+        );
+        Some(cutoff)
+    }
+
+    /// 记录请求在准入队列中的等待时间
+    ///
+    /// 超过 `spilloverQueueDelayMs` 时进入突发溢出（或延长保持时间），
+    /// `spilloverBurstHoldSecs` 内没有再超过阈值时自动恢复
+    pub fn record_queue_delay(&self, delay: StdDuration) {
+        let threshold = self.config.spillover_queue_delay_ms;
+        if threshold == 0 || delay < StdDuration::from_millis(threshold) {
+            return;
+        }
+        let hold = StdDuration::from_secs(self.config.spillover_burst_hold_secs);
+        let mut burst_until = self.burst_until.lock();
+        if burst_until.is_none() {
+            tracing::info!(
+                "准入排队 {}ms 超过 spilloverQueueDelayMs ({})，临时纳入下一优先级层",
+                delay.as_millis(),
+                threshold
+            );
+        }
+        *burst_until = Some(Instant::now() + hold);
+    }
+
+    /// 是否处于突发溢出（保持时间结束后恢复）
+    fn burst_active(&self) -> bool {
+        let mut burst_until = self.burst_until.lock();
+        match *burst_until {
+            Some(until) if Instant::now() < until => true,
+            Some(_) => {
+                *burst_until = None;
+                tracing::info!("突发流量已平息，恢复常规凭据选择");
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// 亲和绑定是否已超过空闲过期时间
+    fn affinity_expired(&self, binding: &AffinityBinding, now: DateTime<Utc>) -> bool {
+        let ttl = self.config.affinity_ttl_secs;
+        ttl > 0 && now - binding.last_used_at > Duration::seconds(ttl as i64)
+    }
+
+    /// 移除过期的亲和绑定，并在超出数量上限时淘汰最久未使用的绑定（为新绑定预留一个位置）
+    fn prune_affinity(&self, affinity: &mut HashMap<String, AffinityBinding>, now: DateTime<Utc>) {
+        affinity.retain(|_, binding| !self.affinity_expired(binding, now));
+
+        let max = self.config.affinity_max_entries;
+        if max == 0 || affinity.len() < max {
+            return;
+        }
+        let mut by_last_used: Vec<(DateTime<Utc>, String)> = affinity
+            .iter()
+            .map(|(key, binding)| (binding.last_used_at, key.clone()))
+            .collect();
+        by_last_used.sort();
+        let evict = affinity.len() + 1 - max;
+        for (_, key) in by_last_used.into_iter().take(evict) {
+            affinity.remove(&key);
+        }
+        tracing::debug!("亲和绑定数量达到上限 {}，已淘汰 {} 个", max, evict);
+    }
+
+    /// balanced 模式下按亲和键选择凭据
+    ///
+    /// 已绑定且仍可用的凭据直接复用；否则选择当前绑定数最少的可用凭据并重新绑定，
+    /// 使并行的会话（如 Claude Code 子代理）分散到不同凭据上。
+    /// 绑定超过 `affinityTtlSecs` 未使用即过期，数量超过 `affinityMaxEntries` 时淘汰最久未使用的绑定。
+    /// 绑定的凭据为 `exclude` 时视为不可用，亲和键改绑到其他凭据
+    fn select_affinity_credential(
+        &self,
+        model: Option<&str>,
+        affinity_key: &str,
+        exclude: Option<u64>,
+    ) -> Option<(u64, KiroCredentials)> {
+        let entries = self.entries.lock();
+        let mut affinity = self.affinity.lock();
+        let now = Utc::now();
+
+        if let Some(entry) =
+            self.affinity_bound_entry(&entries, &affinity, model, affinity_key, exclude, now)
+        {
+            if let Some(binding) = affinity.get_mut(affinity_key) {
+                binding.last_used_at = now;
+            }
+            return Some((entry.id, entry.credentials.clone()));
+        }
+
+        let candidate =
+            self.affinity_rebind_entry(&entries, &affinity, model, affinity_key, exclude, now);
+        affinity.remove(affinity_key);
+        self.prune_affinity(&mut affinity, now);
+        let entry = candidate?;
+
+        affinity.insert(
+            affinity_key.to_string(),
+            AffinityBinding {
+                credential_id: entry.id,
+                bound_at: now,
+                last_used_at: now,
+            },
+        );
+        tracing::debug!("亲和键 {} 绑定到凭据 #{}", affinity_key, entry.id);
+
+        Some((entry.id, entry.credentials.clone()))
+    }
+
+    /// 预测 [`Self::select_affinity_credential`] 的选择结果，不修改亲和绑定
+    fn affinity_candidate(
+        &self,
+        model: Option<&str>,
+        affinity_key: &str,
+        exclude: Option<u64>,
+    ) -> Option<(u64, KiroCredentials)> {
+        let entries = self.entries.lock();
+        let affinity = self.affinity.lock();
+        let now = Utc::now();
+        self.affinity_bound_entry(&entries, &affinity, model, affinity_key, exclude, now)
+            .or_else(|| {
+                self.affinity_rebind_entry(&entries, &affinity, model, affinity_key, exclude, now)
+            })
+            .map(|e| (e.id, e.credentials.clone()))
+    }
+
+    /// 凭据能否用于亲和绑定（不考虑金丝雀凭据）
+    fn affinity_selectable(
+        &self,
+        entry: &CredentialEntry,
+        model: Option<&str>,
+        exclude: Option<u64>,
+    ) -> bool {
+        let is_opus = model
+            .map(|m| m.to_lowercase().contains("opus"))
+            .unwrap_or(false);
+        !entry.disabled
+            && Some(entry.id) != exclude
+            && !entry.credentials.is_canary()
+            && (!is_opus || entry.credentials.supports_opus())
+            && self.within_daily_limit(entry)
+    }
+
+    /// 亲和键已绑定、未过期且仍可用的凭据
+    fn affinity_bound_entry<'a>(
+        &self,
+        entries: &'a [CredentialEntry],
+        affinity: &HashMap<String, AffinityBinding>,
+        model: Option<&str>,
+        affinity_key: &str,
+        exclude: Option<u64>,
+        now: DateTime<Utc>,
+    ) -> Option<&'a CredentialEntry> {
+        let binding = affinity
+            .get(affinity_key)
+            .filter(|binding| !self.affinity_expired(binding, now))?;
+        entries
+            .iter()
+            .find(|e| e.id == binding.credential_id && self.affinity_selectable(e, model, exclude))
+    }
+
+    /// 亲和键需要重新绑定时选择的凭据：当前绑定数最少的可用凭据
+    ///
+    /// 不计该亲和键自身与已过期的绑定
+    fn affinity_rebind_entry<'a>(
+        &self,
+        entries: &'a [CredentialEntry],
+        affinity: &HashMap<String, AffinityBinding>,
+        model: Option<&str>,
+        affinity_key: &str,
+        exclude: Option<u64>,
+        now: DateTime<Utc>,
+    ) -> Option<&'a CredentialEntry> {
+        // 统计每个凭据当前的绑定数量
+        let mut bindings: HashMap<u64, usize> = HashMap::new();
+        for (key, binding) in affinity {
+            if key != affinity_key && !self.affinity_expired(binding, now) {
+                *bindings.entry(binding.credential_id).or_default() += 1;
+            }
+        }
+
+        entries
+            .iter()
+            .filter(|e| self.affinity_selectable(e, model, exclude))
+            .min_by_key(|e| {
+                (
+                    bindings.get(&e.id).copied().unwrap_or(0),
+                    e.success_count,
+                    e.credentials.priority,
+                )
+            })
+    }
+
+    /// 获取 API 调用上下文
+    ///
+    /// 返回绑定了 id、credentials 和 token 的调用上下文
+    /// 确保整个 API 调用过程中使用一致的凭据信息
+    ///
+    /// 如果 Token 过期或即将过期，会自动刷新
+    /// Token 刷新失败时会尝试下一个可用凭据（不计入失败次数）
+    ///
+    /// # 参数
+    /// - `model`: 可选的模型名称，用于过滤支持该模型的凭据（如 opus 模型需要付费订阅）
+    /// - `affinity_key`: 可选的亲和键，balanced 模式下同一亲和键固定使用同一凭据
+    pub async fn acquire_context(
+        &self,
+        model: Option<&str>,
+        affinity_key: Option<&str>,
+    ) -> anyhow::Result<CallContext> {
+        self.acquire_context_excluding(model, affinity_key, None)
+            .await
+    }
+
+    /// 获取 API 调用上下文，不选择 `exclude` 指定的凭据
+    ///
+    /// 用于流式响应中断后的重试：刚失败的凭据即使是当前凭据或亲和绑定的凭据也不会被选中，
+    /// 亲和键改绑到实际选中的凭据
+    pub async fn acquire_context_excluding(
+        &self,
+        model: Option<&str>,
+        affinity_key: Option<&str>,
+        exclude: Option<u64>,
+    ) -> anyhow::Result<CallContext> {
+        let started = Instant::now();
+        let total = self.total_count();
+        let mut tried_count = 0;
+
+        loop {
+            if tried_count >= total {
+                anyhow::bail!(
+                    "所有凭据均无法获取有效 Token（可用: {}/{}）",
+                    self.available_count(),
+                    total
+                );
+            }
 
-                if let Some(hit) = current_hit {
+            let (id, credentials) = {
+                // 金丝雀凭据按流量百分比抽样，命中时不改变 current_id
+                let canary = self
+                    .select_canary(model)
+                    .filter(|(id, _)| Some(*id) != exclude);
+                if let Some(hit) = canary {
                     hit
                 } else {
-                    // 当前凭据不可用或 balanced 模式，根据负载均衡策略选择
-                    let mut best = self.select_next_credential(model);
+                    // 除被排除的凭据外没有其他可用凭据时，仍使用被排除的凭据
+                    let fallback =
+                        || exclude.and_then(|_| self.select_fresh(model, affinity_key, None, true));
+                    let mut best = self
+                        .select_by_strategy(model, affinity_key, exclude, true)
+                        .or_else(fallback);
 
                     // 没有可用凭据：如果是"自动禁用导致全灭"，做一次类似重启的自愈
                     if best.is_none() {
-                        let mut entries = self.entries.lock();
+                        let mut entries = self.entries_mut();
                         if entries.iter().any(|e| {
                             e.disabled && e.disabled_reason == Some(DisabledReason::TooManyFailures)
                         }) {
                             tracing::warn!(
-                                "所有凭据均已被自动禁用，执行自愈：重置失败计数并重新启用（等价于重启）"
+                                "所有凭据均已被自动禁用，执行自愈：重置失败计数并重新启用"
                             );
                             for e in entries.iter_mut() {
                                 if e.disabled_reason == Some(DisabledReason::TooManyFailures) {
@@ -764,14 +1838,18 @@ impl MultiTokenManager {
                                 }
                             }
                             drop(entries);
-                            best = self.select_next_credential(model);
+                            self.save_stats_debounced();
+                            best = self
+                                .select_fresh(model, affinity_key, exclude, true)
+                                .or_else(fallback);
                         }
                     }
 
                     if let Some((new_id, new_creds)) = best {
                         // 更新 current_id
-                        let mut current_id = self.current_id.lock();
-                        *current_id = new_id;
+                        if *self.current_id.lock() != new_id {
+                            *self.current_id_mut() = new_id;
+                        }
                         (new_id, new_creds)
                     } else {
                         let entries = self.entries.lock();
@@ -779,6 +1857,16 @@ impl MultiTokenManager {
                         // 因为 available_count() 会尝试获取 entries 锁，
                         // 而此时我们已经持有该锁，会导致死锁
                         let available = entries.iter().filter(|e| !e.disabled).count();
+                        if entries
+                            .iter()
+                            .any(|e| !e.disabled && !self.within_daily_limit(e))
+                        {
+                            anyhow::bail!(
+                                "可用凭据（或其所在的凭据池）均已达到今日请求上限（{}/{}）",
+                                available,
+                                total
+                            );
+                        }
                         anyhow::bail!("所有凭据均已禁用（{}/{}）", available, total);
                     }
                 }
@@ -787,10 +1875,24 @@ impl MultiTokenManager {
             // 尝试获取/刷新 Token
             match self.try_ensure_token(id, &credentials).await {
                 Ok(ctx) => {
+                    let source = self
+                        .entries
+                        .lock()
+                        .iter()
+                        .find(|e| e.id == id)
+                        .map(|e| e.source);
+                    if let Some(source) = source {
+                        self.record_daily(source, id);
+                    }
+                    self.metrics.wait().record(id, started.elapsed());
                     return Ok(ctx);
                 }
                 Err(e) => {
-                    tracing::warn!("凭据 #{} Token 刷新失败，尝试下一个凭据: {}", id, e);
+                    tracing::warn!(
+                        "凭据 {} Token 刷新失败，尝试下一个凭据: {}",
+                        credentials.describe(id),
+                        e
+                    );
 
                     // Token 刷新失败，切换到下一个优先级的凭据（不计入失败次数）
                     self.switch_to_next_by_priority();
@@ -800,21 +1902,47 @@ impl MultiTokenManager {
         }
     }
 
+    /// 获取指定凭据的 API 调用上下文（调试用的凭据固定）
+    ///
+    /// 跳过负载均衡选择，但仍遵守禁用、额度冷却与每日请求上限；
+    /// 凭据不可用时返回 [`PinnedCredentialUnavailable`]，不会切换到其他凭据
+    pub async fn acquire_pinned_context(&self, id: u64) -> anyhow::Result<CallContext> {
+        let (credentials, source) = {
+            let entries = self.entries.lock();
+            let entry = entries
+                .iter()
+                .find(|e| e.id == id)
+                .ok_or(PinnedCredentialUnavailable { id, reason: None })?;
+            if let Some((reason, _)) = self.unavailability(entry) {
+                return Err(PinnedCredentialUnavailable {
+                    id,
+                    reason: Some(reason),
+                }
+                .into());
+            }
+            (entry.credentials.clone(), entry.source)
+        };
+
+        let ctx = self.try_ensure_token(id, &credentials).await?;
+        self.record_daily(source, id);
+        Ok(ctx)
+    }
+
     /// 切换到下一个优先级最高的可用凭据（内部方法）
     fn switch_to_next_by_priority(&self) {
         let entries = self.entries.lock();
-        let mut current_id = self.current_id.lock();
+        let mut current_id = self.current_id_mut();
 
-        // 选择优先级最高的未禁用凭据（排除当前凭据）
+        // 选择优先级最高的未禁用凭据（排除当前凭据与金丝雀凭据）
         if let Some(entry) = entries
             .iter()
-            .filter(|e| !e.disabled && e.id != *current_id)
+            .filter(|e| !e.disabled && !e.credentials.is_canary() && e.id != *current_id)
             .min_by_key(|e| e.credentials.priority)
         {
             *current_id = entry.id;
             tracing::info!(
-                "已切换到凭据 #{}（优先级 {}）",
-                entry.id,
+                "已切换到凭据 {}（优先级 {}）",
+                entry.credentials.describe(entry.id),
                 entry.credentials.priority
             );
         }
@@ -826,12 +1954,12 @@ impl MultiTokenManager {
     /// 纯粹按优先级选择，用于优先级变更后立即生效
     fn select_highest_priority(&self) {
         let entries = self.entries.lock();
-        let mut current_id = self.current_id.lock();
+        let mut current_id = self.current_id_mut();
 
-        // 选择优先级最高的未禁用凭据（不排除当前凭据）
+        // 选择优先级最高的未禁用凭据（不排除当前凭据，排除金丝雀凭据）
         if let Some(best) = entries
             .iter()
-            .filter(|e| !e.disabled)
+            .filter(|e| !e.disabled && !e.credentials.is_canary())
             .min_by_key(|e| e.credentials.priority)
         {
             if best.id != *current_id {
@@ -865,45 +1993,11 @@ impl MultiTokenManager {
             // 获取刷新锁，确保同一时间只有一个刷新操作
             let _guard = self.refresh_lock.lock().await;
 
-            // 第二次检查：获取锁后重新读取凭据，因为其他请求可能已经完成刷新
-            let current_creds = {
-                let entries = self.entries.lock();
-                entries
-                    .iter()
-                    .find(|e| e.id == id)
-                    .map(|e| e.credentials.clone())
-                    .ok_or_else(|| anyhow::anyhow!("凭据 #{} 不存在", id))?
-            };
-
-            if is_token_expired(&current_creds) || is_token_expiring_soon(&current_creds) {
-                // 确实需要刷新
-                let effective_proxy = current_creds.effective_proxy(self.proxy.as_ref());
-                let new_creds =
-                    refresh_token(&current_creds, &self.config, effective_proxy.as_ref()).await?;
-
-                if is_token_expired(&new_creds) {
-                    anyhow::bail!("刷新后的 Token 仍然无效或已过期");
-                }
-
-                // 更新凭据
-                {
-                    let mut entries = self.entries.lock();
-                    if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
-                        entry.credentials = new_creds.clone();
-                    }
-                }
-
-                // 回写凭据到文件（仅多凭据格式），失败只记录警告
-                if let Err(e) = self.persist_credentials() {
-                    tracing::warn!("Token 刷新后持久化失败（不影响本次请求）: {}", e);
-                }
-
-                new_creds
-            } else {
-                // 其他请求已经完成刷新，直接使用新凭据
-                tracing::debug!("Token 已被其他请求刷新，跳过刷新");
-                current_creds
+            let new_creds = self.refresh_credential(id, false).await?;
+            if is_token_expired(&new_creds) {
+                anyhow::bail!("刷新后的 Token 仍然无效或已过期");
             }
+            new_creds
         } else {
             credentials.clone()
         };
@@ -920,6 +2014,87 @@ impl MultiTokenManager {
         })
     }
 
+    /// 刷新指定凭据的 Token 并回写（调用方需持有 `refresh_lock`）
+    ///
+    /// 可回写的凭据文件可能被多个实例共享：刷新前先获取进程间刷新锁并合并文件中的最新凭据，
+    /// 若其他实例已经完成刷新则直接使用（`force` 为 true 时仍然刷新）；锁一直持有到回写完成。
+    /// 刷新失败时若发现文件中的 refreshToken 已被其他进程轮换，改用新的 Token 重试一次。
+    async fn refresh_credential(&self, id: u64, force: bool) -> anyhow::Result<KiroCredentials> {
+        let source = {
+            let entries = self.entries.lock();
+            entries
+                .iter()
+                .find(|e| e.id == id)
+                .map(|e| e.source)
+                .ok_or_else(|| anyhow::anyhow!("凭据 #{} 不存在", id))?
+        };
+        let _file_lock = match self.sources.get(source).and_then(|s| s.writer.as_ref()) {
+            Some(writer) => {
+                let lock = writer.lock_for_refresh(REFRESH_LOCK_TIMEOUT).await;
+                // 其他进程可能在等待期间完成了刷新
+                self.merge_external_changes();
+                lock
+            }
+            None => None,
+        };
+
+        // 第二次检查：获取锁后重新读取凭据，因为其他请求（或进程）可能已经完成刷新
+        let current_creds = self.entry_credentials(id)?;
+        if !force && !is_token_expired(&current_creds) && !is_token_expiring_soon(&current_creds) {
+            tracing::debug!("Token 已被其他请求刷新，跳过刷新");
+            return Ok(current_creds);
+        }
+
+        let _refreshing = RefreshingGuard::new(&self.refreshing, id);
+        let effective_proxy = current_creds.effective_proxy(self.proxy.as_ref());
+        let refreshed = refresh_token(&current_creds, &self.config, effective_proxy.as_ref()).await;
+        let new_creds = match refreshed {
+            Ok(new_creds) => new_creds,
+            Err(e) => {
+                self.merge_external_changes();
+                let disk_creds = self.entry_credentials(id)?;
+                if disk_creds.refresh_token == current_creds.refresh_token {
+                    return Err(e);
+                }
+                tracing::error!(
+                    "凭据 #{} 的 refreshToken 已被其他进程轮换（多个实例共享同一凭据文件且未能协调刷新），\
+                     已改用文件中的新 Token。请为每个实例使用独立的凭据文件",
+                    id
+                );
+                if !is_token_expired(&disk_creds) && !is_token_expiring_soon(&disk_creds) {
+                    return Ok(disk_creds);
+                }
+                refresh_token(&disk_creds, &self.config, effective_proxy.as_ref()).await?
+            }
+        };
+
+        // 更新凭据
+        {
+            let mut entries = self.entries_mut();
+            if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+                entry.set_credentials(new_creds.clone(), &self.config.refresh_token_hash_salt);
+            }
+        }
+        self.events.publish(BusEvent::TokenRefreshed { id });
+
+        // 回写凭据到文件（仅多凭据格式），失败只记录警告
+        if let Err(e) = self.persist_credentials_async().await {
+            tracing::warn!("Token 刷新后持久化失败（不影响本次请求）: {}", e);
+        }
+
+        Ok(new_creds)
+    }
+
+    /// 读取指定凭据的当前内容
+    fn entry_credentials(&self, id: u64) -> anyhow::Result<KiroCredentials> {
+        let entries = self.entries.lock();
+        entries
+            .iter()
+            .find(|e| e.id == id)
+            .map(|e| e.credentials.clone())
+            .ok_or_else(|| anyhow::anyhow!("凭据 #{} 不存在", id))
+    }
+
     /// 将凭据列表回写到源文件
     ///
     /// 仅在以下条件满足时回写：
@@ -931,60 +2106,319 @@ impl MultiTokenManager {
     /// - `Ok(false)` - 跳过写入（非多凭据格式或无路径配置）
     /// - `Err(_)` - 写入失败
     fn persist_credentials(&self) -> anyhow::Result<bool> {
-        use anyhow::Context;
+        self.merge_external_changes();
+        let pending = self.prepare_persist()?;
+        for (writer, path, generation, json) in &pending {
+            writer.write(*generation, json.clone())?;
+            tracing::debug!("已回写凭据到文件: {:?}", path);
+        }
+        Ok(!pending.is_empty())
+    }
 
-        // 仅多凭据格式才回写
-        if !self.is_multiple_format {
-            return Ok(false);
+    /// 异步回写凭据（等待期间不占用 Tokio worker，用于请求路径上的 Token 刷新等场景）
+    async fn persist_credentials_async(&self) -> anyhow::Result<bool> {
+        self.merge_external_changes();
+        let pending = self.prepare_persist()?;
+        for (writer, path, generation, json) in &pending {
+            writer.write_async(*generation, json.clone()).await?;
+            tracing::debug!("已回写凭据到文件: {:?}", path);
         }
+        Ok(!pending.is_empty())
+    }
 
-        let path = match &self.credentials_path {
-            Some(p) => p,
-            None => return Ok(false),
-        };
+    /// 生成各可回写来源文件的凭据快照：(回写器, 文件路径, 版本号, JSON)
+    ///
+    /// 单凭据格式、只读或无路径的来源不回写
+    fn prepare_persist(&self) -> anyhow::Result<Vec<(&CredentialsWriter, &Path, u64, String)>> {
+        use anyhow::Context;
+
+        if self.sources.iter().all(|s| s.writer.is_none()) {
+            return Ok(Vec::new());
+        }
 
-        // 收集所有凭据
-        let credentials: Vec<KiroCredentials> = {
+        // 收集所有凭据（持锁分配版本号，保证版本号顺序与快照顺序一致）
+        let (generation, snapshots) = {
             let entries = self.entries.lock();
-            entries
+            let generation = self.persist_generation.fetch_add(1, Ordering::SeqCst) + 1;
+            let snapshots: Vec<(usize, Vec<KiroCredentials>)> = self
+                .sources
                 .iter()
-                .map(|e| {
-                    let mut cred = e.credentials.clone();
-                    cred.canonicalize_auth_method();
-                    cred
+                .enumerate()
+                .filter(|(_, s)| s.writer.is_some())
+                .map(|(index, _)| {
+                    let credentials = entries
+                        .iter()
+                        .filter(|e| e.source == index)
+                        .map(|e| {
+                            let mut cred = e.credentials.clone();
+                            cred.canonicalize_auth_method();
+                            cred
+                        })
+                        .collect();
+                    (index, credentials)
                 })
-                .collect()
+                .collect();
+            (generation, snapshots)
         };
 
-        // 序列化为 pretty JSON
-        let json = serde_json::to_string_pretty(&credentials).context("序列化凭据失败")?;
+        snapshots
+            .into_iter()
+            .filter_map(|(index, credentials)| {
+                let state = &self.sources[index];
+                let writer = state.writer.as_ref()?;
+                let path = state.source.path.as_deref()?;
+                // 序列化为 pretty JSON
+                let json = serde_json::to_string_pretty(&credentials)
+                    .context("序列化凭据失败")
+                    .map(|json| (writer, path, generation, json));
+                Some(json)
+            })
+            .collect()
+    }
 
-        // 写入文件（在 Tokio runtime 内使用 block_in_place 避免阻塞 worker）
-        if tokio::runtime::Handle::try_current().is_ok() {
-            tokio::task::block_in_place(|| std::fs::write(path, &json))
-                .with_context(|| format!("回写凭据文件失败: {:?}", path))?;
-        } else {
-            std::fs::write(path, &json).with_context(|| format!("回写凭据文件失败: {:?}", path))?;
+    /// 检测并合并凭据文件的外部修改，必要时回写
+    ///
+    /// 由后台任务定期调用；合并时补全了 ID/machineId 才需要回写
+    pub fn sync_external_changes(&self) {
+        if self.merge_external_changes()
+            && let Err(e) = self.persist_credentials()
+        {
+            tracing::warn!("合并外部修改后持久化失败: {}", e);
+        }
+    }
+
+    /// 合并被其他程序修改过的可回写凭据文件（按凭据 ID 合并）
+    ///
+    /// - 文件中存在、内存中也存在的 ID：以文件内容为准，保留运行时状态（失败计数、统计等）；
+    ///   若内存中的 Token 过期时间更晚（本进程刚刷新过），保留内存中的 Token
+    /// - 文件中新出现的凭据：加入凭据池（缺少 ID 时分配新 ID）
+    /// - 文件中已不存在的 ID：从凭据池移除
+    ///
+    /// # Returns
+    /// 是否需要回写（为文件中的凭据补全了 ID 或 machineId）
+    fn merge_external_changes(&self) -> bool {
+        let mut needs_persist = false;
+        let mut removed_current = false;
+
+        for (index, state) in self.sources.iter().enumerate() {
+            let (Some(writer), Some(path)) = (&state.writer, &state.source.path) else {
+                continue;
+            };
+            let Some(change) = writer.check_external_change() else {
+                continue;
+            };
+
+            let disk_creds = match CredentialsConfig::load(path) {
+                Ok(config) => config.into_sorted_credentials(),
+                Err(e) => {
+                    // 可能是其他程序写入到一半，下次再试；期间回写会被拒绝
+                    tracing::warn!("凭据文件 {:?} 已被外部修改但无法解析: {}", path, e);
+                    continue;
+                }
+            };
+
+            let (mut updated, mut added, mut removed) = (0, 0, Vec::new());
+            {
+                let mut entries = self.entries_mut();
+                let mut next_id = entries
+                    .iter()
+                    .map(|e| e.id)
+                    .chain(disk_creds.iter().filter_map(|c| c.id))
+                    .max()
+                    .unwrap_or(0)
+                    + 1;
+                let mut disk_ids = std::collections::HashSet::new();
+
+                for mut cred in disk_creds {
+                    let id = match cred.id {
+                        Some(id) => id,
+                        None => {
+                            let id = next_id;
+                            next_id += 1;
+                            cred.id = Some(id);
+                            needs_persist = true;
+                            id
+                        }
+                    };
+                    if cred.machine_id.is_none()
+                        && let Some(machine_id) =
+                            machine_id::generate_from_credentials(&cred, &self.config)
+                    {
+                        cred.machine_id = Some(machine_id);
+                        needs_persist = true;
+                    }
+                    disk_ids.insert(id);
+
+                    match entries.iter_mut().find(|e| e.id == id) {
+                        Some(entry) if entry.source != index => {
+                            tracing::warn!(
+                                "凭据文件 {:?} 中的凭据 #{} 与其他文件的 ID 冲突，已忽略",
+                                path,
+                                id
+                            );
+                        }
+                        Some(entry) => {
+                            if token_expires_later(&entry.credentials, &cred) {
+                                cred.access_token = entry.credentials.access_token.take();
+                                cred.refresh_token = entry.credentials.refresh_token.take();
+                                cred.expires_at = entry.credentials.expires_at.take();
+                                cred.refresh_token_rotated_at =
+                                    entry.credentials.refresh_token_rotated_at.take();
+                            } else if cred.refresh_token != entry.credentials.refresh_token
+                                && cred.refresh_token_rotated_at
+                                    == entry.credentials.refresh_token_rotated_at
+                            {
+                                // 不是上游轮换（其他实例轮换时会同时更新轮换时间）：视为重新登录
+                                cred.refresh_token_issued_at = None;
+                            }
+                            needs_persist |= record_refresh_token_issued_at(&mut cred);
+                            if serde_json::to_value(&entry.credentials).ok()
+                                != serde_json::to_value(&cred).ok()
+                            {
+                                updated += 1;
+                            }
+                            entry.set_credentials(cred, &self.config.refresh_token_hash_salt);
+                        }
+                        None => {
+                            added += 1;
+                            needs_persist |= record_refresh_token_issued_at(&mut cred);
+                            self.events.publish(BusEvent::CredentialAdded { id });
+                            entries.push(CredentialEntry {
+                                id,
+                                source: index,
+                                refresh_token_hash: refresh_token_hash(
+                                    &self.config.refresh_token_hash_salt,
+                                    &cred,
+                                ),
+                                credentials: cred,
+                                failure_count: 0,
+                                disabled: false,
+                                disabled_reason: None,
+                                disabled_until: None,
+                                backoff_level: 0,
+                                last_failure: None,
+                                success_count: 0,
+                                failure_total: 0,
+                                last_used_at: None,
+                            });
+                        }
+                    }
+                }
+
+                entries.retain(|e| {
+                    let keep = e.source != index || disk_ids.contains(&e.id);
+                    if !keep {
+                        removed.push(e.id);
+                        self.events
+                            .publish(BusEvent::CredentialDeleted { id: e.id });
+                    }
+                    keep
+                });
+            }
+
+            writer.acknowledge(change);
+
+            for id in &removed {
+                self.balance_store.remove(*id);
+                removed_current |= *id == *self.current_id.lock();
+            }
+            tracing::info!(
+                "检测到凭据文件 {:?} 被外部修改，已合并：更新 {} 个，新增 {} 个，移除 {} 个",
+                path,
+                updated,
+                added,
+                removed.len()
+            );
+        }
+
+        if removed_current {
+            self.select_highest_priority();
+        }
+        if self.entries.lock().is_empty() {
+            *self.current_id_mut() = 0;
         }
 
-        tracing::debug!("已回写凭据到文件: {:?}", path);
-        Ok(true)
+        needs_persist
     }
 
     /// 获取缓存目录（凭据文件所在目录）
     pub fn cache_dir(&self) -> Option<PathBuf> {
-        self.credentials_path
-            .as_ref()
+        self.sources
+            .first()
+            .and_then(|s| s.source.path.as_ref())
             .and_then(|p| p.parent().map(|d| d.to_path_buf()))
     }
 
+    /// 正在刷新 Token 的凭据 ID（升序）
+    pub fn refreshing_credentials(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = self.refreshing.lock().iter().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// 各凭据来源文件路径（下标即凭据池编号）
+    pub fn source_paths(&self) -> Vec<Option<PathBuf>> {
+        self.sources.iter().map(|s| s.source.path.clone()).collect()
+    }
+
+    /// 全局代理配置
+    pub fn proxy(&self) -> Option<&ProxyConfig> {
+        self.proxy.as_ref()
+    }
+
+    /// 有路径的凭据来源文件配置（用于自检）
+    pub fn source_files(&self) -> Vec<CredentialsFileConfig> {
+        self.sources
+            .iter()
+            .filter_map(|s| {
+                Some(CredentialsFileConfig {
+                    path: s.source.path.as_ref()?.display().to_string(),
+                    read_only: s.source.read_only,
+                    daily_request_limit: s.source.daily_request_limit,
+                })
+            })
+            .collect()
+    }
+
+    /// 新增凭据写入的来源文件：第一个可回写的文件，没有时为第一个文件
+    fn default_source(&self) -> usize {
+        self.sources
+            .iter()
+            .position(|s| s.writer.is_some())
+            .unwrap_or(0)
+    }
+
     /// 统计数据文件路径
     fn stats_path(&self) -> Option<PathBuf> {
         self.cache_dir().map(|d| d.join("kiro_stats.json"))
     }
 
+    /// 指标快照文件路径
+    fn metrics_path(&self) -> Option<PathBuf> {
+        self.cache_dir().map(|d| d.join("kiro_metrics.json"))
+    }
+
+    /// 获取累计运行指标
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// 获取上游错误调试转储目录（未配置时为 None）
+    pub fn debug_dumps(&self) -> Option<&DumpStore> {
+        self.debug_dumps.as_ref()
+    }
+
     /// 从磁盘加载统计数据并应用到当前条目
     fn load_stats(&self) {
+        if let Some(snapshot) = self.metrics_path().and_then(|p| Metrics::load_snapshot(&p)) {
+            self.metrics.restore(&snapshot);
+            tracing::info!(
+                "已从缓存加载累计指标（请求 {} 次，自 {}）",
+                snapshot.requests_total,
+                snapshot.since.as_deref().unwrap_or("-")
+            );
+        }
+
         let path = match self.stats_path() {
             Some(p) => p,
             None => return,
@@ -1003,20 +2437,61 @@ impl MultiTokenManager {
             }
         };
 
-        let mut entries = self.entries.lock();
-        for entry in entries.iter_mut() {
-            if let Some(s) = stats.get(&entry.id.to_string()) {
+        let now = Utc::now();
+        let mut restored_disabled = 0;
+        {
+            let mut entries = self.entries_mut();
+            for entry in entries.iter_mut() {
+                let Some(s) = stats.get(&entry.id.to_string()) else {
+                    continue;
+                };
                 entry.success_count = s.success_count;
                 entry.last_used_at = s.last_used_at.clone();
+
+                // 恢复禁用状态；已过自动解除时间的不再恢复
+                if let Some(state) = &s.disabled {
+                    if state.until.is_some_and(|until| until <= now) {
+                        tracing::info!(
+                            "凭据 {} 的禁用已到期，重新启用",
+                            entry.credentials.describe(entry.id)
+                        );
+                        continue;
+                    }
+                    entry.disabled = true;
+                    entry.disabled_reason = Some(state.reason);
+                    entry.disabled_until = state.until;
+                    entry.failure_count = state.failure_count;
+                    restored_disabled += 1;
+                }
+            }
+
+            // 当前凭据被恢复为禁用时，改用优先级最高的可用凭据
+            let mut current_id = self.current_id_mut();
+            if entries.iter().any(|e| e.id == *current_id && e.disabled)
+                && let Some(best) = entries
+                    .iter()
+                    .filter(|e| !e.disabled)
+                    .min_by_key(|e| e.credentials.priority)
+            {
+                *current_id = best.id;
             }
         }
         *self.last_stats_save_at.lock() = Some(Instant::now());
         self.stats_dirty.store(false, Ordering::Relaxed);
         tracing::info!("已从缓存加载 {} 条统计数据", stats.len());
+        if restored_disabled > 0 {
+            tracing::warn!("已恢复 {} 个凭据的禁用状态", restored_disabled);
+        }
     }
 
     /// 将当前统计数据持久化到磁盘
     fn save_stats(&self) {
+        if let Some(path) = self.metrics_path()
+            && let Err(e) = self.metrics.save_snapshot(&path)
+        {
+            tracing::warn!("保存指标快照失败: {}", e);
+        }
+
         let path = match self.stats_path() {
             Some(p) => p,
             None => return,
@@ -1032,6 +2507,13 @@ impl MultiTokenManager {
                         StatsEntry {
                             success_count: e.success_count,
                             last_used_at: e.last_used_at.clone(),
+                            disabled: e.disabled_reason.filter(|_| e.disabled).map(|reason| {
+                                DisabledState {
+                                    reason,
+                                    failure_count: e.failure_count,
+                                    until: e.disabled_until,
+                                }
+                            }),
                         },
                     )
                 })
@@ -1051,6 +2533,42 @@ impl MultiTokenManager {
         }
     }
 
+    /// 若统计数据或指标有未落盘更新则立即持久化（供定期快照任务调用）
+    ///
+    /// 同时清除超过保留期的已删除凭据
+    pub fn flush_stats(&self) {
+        if self.stats_dirty.load(Ordering::Relaxed) || self.metrics.is_dirty() {
+            self.save_stats();
+        }
+        self.daily_limit.flush();
+        let purged = self.archive.purge_expired();
+        if purged > 0 {
+            tracing::info!("已清除 {} 个超过保留期的已删除凭据", purged);
+        }
+    }
+
+    /// 占用一次全局每日请求额度（每个客户端请求调用一次）
+    pub fn try_acquire_daily(&self) -> Result<(), DailyLimitExceeded> {
+        self.daily_limit.try_acquire()
+    }
+
+    /// 获取每日请求上限状态（Admin API）
+    pub fn daily_limit_status(&self) -> DailyLimitStatus {
+        self.daily_limit.status()
+    }
+
+    /// 解除或恢复当天的每日请求上限（Admin API）
+    pub fn set_daily_limit_overridden(&self, overridden: bool) {
+        self.daily_limit.set_overridden(overridden);
+        self.daily_limit.flush();
+        self.bump_state_version();
+        if overridden {
+            tracing::info!("已临时解除今日的每日请求上限（次日自动恢复）");
+        } else {
+            tracing::info!("已恢复每日请求上限");
+        }
+    }
+
     /// 标记统计数据已更新，并按 debounce 策略决定是否立即落盘
     fn save_stats_debounced(&self) {
         self.stats_dirty.store(true, Ordering::Relaxed);
@@ -1076,10 +2594,12 @@ impl MultiTokenManager {
     /// * `id` - 凭据 ID（来自 CallContext）
     pub fn report_success(&self, id: u64) {
         {
-            let mut entries = self.entries.lock();
+            let mut entries = self.entries_mut();
             if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
                 entry.failure_count = 0;
+                entry.backoff_level = 0;
                 entry.success_count += 1;
+                self.metrics.record_success();
                 entry.last_used_at = Some(Utc::now().to_rfc3339());
                 tracing::debug!(
                     "凭据 #{} API 调用成功（累计 {} 次）",
@@ -1088,9 +2608,29 @@ impl MultiTokenManager {
                 );
             }
         }
+        self.events.publish(BusEvent::RequestSucceeded { id });
         self.save_stats_debounced();
     }
 
+    /// 报告指定凭据遇到瞬态错误（网络错误、429、408、5xx 等）
+    ///
+    /// 只提升退避级别并记录失败分类，用于诊断；不计入连续失败，也不会禁用或切换凭据
+    pub fn report_transient_failure(&self, id: u64, kind: FailureKind) {
+        let mut entries = self.entries_mut();
+        if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+            entry.backoff_level += 1;
+            entry.failure_total += 1;
+            entry.last_failure = Some((kind, Utc::now()));
+            tracing::debug!(
+                "凭据 {} 瞬态失败（{}），退避级别 {}",
+                entry.credentials.describe(id),
+                kind.as_str(),
+                entry.backoff_level
+            );
+        }
+        self.events.publish(BusEvent::RequestFailed { id, kind });
+    }
+
     /// 报告指定凭据 API 调用失败
     ///
     /// 增加失败计数，达到阈值时禁用凭据并切换到优先级最高的可用凭据
@@ -1099,9 +2639,10 @@ impl MultiTokenManager {
     /// # Arguments
     /// * `id` - 凭据 ID（来自 CallContext）
     pub fn report_failure(&self, id: u64) -> bool {
+        let mut disabled_now = false;
         let result = {
-            let mut entries = self.entries.lock();
-            let mut current_id = self.current_id.lock();
+            let mut entries = self.entries_mut();
+            let mut current_id = self.current_id_mut();
 
             let entry = match entries.iter_mut().find(|e| e.id == id) {
                 Some(e) => e,
@@ -1109,12 +2650,19 @@ impl MultiTokenManager {
             };
 
             entry.failure_count += 1;
+            entry.failure_total += 1;
             entry.last_used_at = Some(Utc::now().to_rfc3339());
+            entry.last_failure = Some((FailureKind::Auth, Utc::now()));
             let failure_count = entry.failure_count;
+            self.metrics.record_failure();
+            self.events.publish(BusEvent::RequestFailed {
+                id,
+                kind: FailureKind::Auth,
+            });
 
             tracing::warn!(
-                "凭据 #{} API 调用失败（{}/{}）",
-                id,
+                "凭据 {} API 调用失败（{}/{}）",
+                entry.credentials.describe(id),
                 failure_count,
                 MAX_FAILURES_PER_CREDENTIAL
             );
@@ -1122,7 +2670,15 @@ impl MultiTokenManager {
             if failure_count >= MAX_FAILURES_PER_CREDENTIAL {
                 entry.disabled = true;
                 entry.disabled_reason = Some(DisabledReason::TooManyFailures);
-                tracing::error!("凭据 #{} 已连续失败 {} 次，已被禁用", id, failure_count);
+                entry.disabled_until = None;
+                disabled_now = true;
+                tracing::error!(
+                    "凭据 {} 已连续失败 {} 次，已被禁用",
+                    entry.credentials.describe(id),
+                    failure_count
+                );
+                self.events
+                    .publish(BusEvent::CircuitBreakerTripped { id, failure_count });
 
                 // 切换到优先级最高的可用凭据
                 if let Some(next) = entries
@@ -1132,8 +2688,8 @@ impl MultiTokenManager {
                 {
                     *current_id = next.id;
                     tracing::info!(
-                        "已切换到凭据 #{}（优先级 {}）",
-                        next.id,
+                        "已切换到凭据 {}（优先级 {}）",
+                        next.credentials.describe(next.id),
                         next.credentials.priority
                     );
                 } else {
@@ -1143,7 +2699,12 @@ impl MultiTokenManager {
 
             entries.iter().any(|e| !e.disabled)
         };
-        self.save_stats_debounced();
+        // 禁用状态立即落盘，避免重启后丢失
+        if disabled_now {
+            self.save_stats();
+        } else {
+            self.save_stats_debounced();
+        }
         result
     }
 
@@ -1154,9 +2715,10 @@ impl MultiTokenManager {
     /// - 切换到下一个可用凭据继续重试
     /// - 返回是否还有可用凭据
     pub fn report_quota_exhausted(&self, id: u64) -> bool {
+        self.events.publish(BusEvent::QuotaExhausted { id });
         let result = {
-            let mut entries = self.entries.lock();
-            let mut current_id = self.current_id.lock();
+            let mut entries = self.entries_mut();
+            let mut current_id = self.current_id_mut();
 
             let entry = match entries.iter_mut().find(|e| e.id == id) {
                 Some(e) => e,
@@ -1169,11 +2731,26 @@ impl MultiTokenManager {
 
             entry.disabled = true;
             entry.disabled_reason = Some(DisabledReason::QuotaExceeded);
+            entry.last_failure = Some((FailureKind::QuotaExhausted, Utc::now()));
+            // 已知额度重置时间时，重启后过了重置时间即不再保持禁用
+            entry.disabled_until = self
+                .balance_store
+                .latest(id)
+                .and_then(|b| b.next_reset_at)
+                .and_then(|reset_at| DateTime::from_timestamp(reset_at as i64, 0));
             entry.last_used_at = Some(Utc::now().to_rfc3339());
+            self.metrics.record_failure();
             // 设为阈值，便于在管理面板中直观看到该凭据已不可用
             entry.failure_count = MAX_FAILURES_PER_CREDENTIAL;
 
-            tracing::error!("凭据 #{} 额度已用尽（MONTHLY_REQUEST_COUNT），已被禁用", id);
+            tracing::error!(
+                "凭据 {} 额度已用尽（MONTHLY_REQUEST_COUNT），已被禁用",
+                entry.credentials.describe(id)
+            );
+            self.events.publish(BusEvent::CooldownSet {
+                id,
+                until: entry.disabled_until,
+            });
 
             // 切换到优先级最高的可用凭据
             if let Some(next) = entries
@@ -1183,8 +2760,8 @@ impl MultiTokenManager {
             {
                 *current_id = next.id;
                 tracing::info!(
-                    "已切换到凭据 #{}（优先级 {}）",
-                    next.id,
+                    "已切换到凭据 {}（优先级 {}）",
+                    next.credentials.describe(next.id),
                     next.credentials.priority
                 );
                 true
@@ -1193,7 +2770,7 @@ impl MultiTokenManager {
                 false
             }
         };
-        self.save_stats_debounced();
+        self.save_stats();
         result
     }
 
@@ -1202,7 +2779,7 @@ impl MultiTokenManager {
     /// 返回是否成功切换
     pub fn switch_to_next(&self) -> bool {
         let entries = self.entries.lock();
-        let mut current_id = self.current_id.lock();
+        let mut current_id = self.current_id_mut();
 
         // 选择优先级最高的未禁用凭据（排除当前凭据）
         if let Some(next) = entries
@@ -1212,8 +2789,8 @@ impl MultiTokenManager {
         {
             *current_id = next.id;
             tracing::info!(
-                "已切换到凭据 #{}（优先级 {}）",
-                next.id,
+                "已切换到凭据 {}（优先级 {}）",
+                next.credentials.describe(next.id),
                 next.credentials.priority
             );
             true
@@ -1225,7 +2802,7 @@ impl MultiTokenManager {
 
     /// 获取使用额度信息
     pub async fn get_usage_limits(&self) -> anyhow::Result<UsageLimitsResponse> {
-        let ctx = self.acquire_context(None).await?;
+        let ctx = self.acquire_context(None, None).await?;
         let effective_proxy = ctx.credentials.effective_proxy(self.proxy.as_ref());
         get_usage_limits(
             &ctx.credentials,
@@ -1236,6 +2813,114 @@ impl MultiTokenManager {
         .await
     }
 
+    /// 校验所有凭据：并发（最多 `concurrency` 个）刷新每个凭据的 Token
+    ///
+    /// 刷新成功的凭据会更新为新 Token 并回写（刷新后旧 refreshToken 可能失效，不能丢弃结果）；
+    /// 校验不会禁用失败的凭据，结果按凭据 ID 排序
+    pub async fn validate_credentials(&self, concurrency: usize) -> Vec<CredentialValidation> {
+        use futures::StreamExt;
+
+        let targets: Vec<(u64, KiroCredentials)> = self
+            .entries
+            .lock()
+            .iter()
+            .map(|e| (e.id, e.credentials.clone()))
+            .collect();
+
+        let mut results: Vec<CredentialValidation> = futures::stream::iter(targets)
+            .map(|(id, credentials)| self.validate_credential(id, credentials))
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+        results.sort_by_key(|r| r.id);
+
+        if results.iter().any(|r| r.status == ValidationStatus::Valid)
+            && let Err(e) = self.persist_credentials_async().await
+        {
+            tracing::warn!("凭据校验后持久化失败: {}", e);
+        }
+        results
+    }
+
+    /// 立即刷新指定凭据（`None` 表示全部凭据）的 Token 并回写，不论 Token 是否即将过期（Admin API）
+    ///
+    /// 逐个刷新，每个凭据单独获取 `refresh_lock`，不会长时间阻塞请求路径的刷新；
+    /// 刷新失败只返回原因，不计入失败次数。
+    pub async fn refresh_tokens(&self, ids: Option<&[u64]>) -> Vec<RefreshResult> {
+        let ids: Vec<u64> = match ids {
+            Some(ids) => ids.to_vec(),
+            None => self.entries.lock().iter().map(|e| e.id).collect(),
+        };
+
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            let refreshed = {
+                let _guard = self.refresh_lock.lock().await;
+                self.refresh_credential(id, true).await
+            };
+            results.push(match refreshed {
+                Ok(credentials) => RefreshResult {
+                    id,
+                    expires_at: credentials.expires_at,
+                    error: None,
+                },
+                Err(e) => {
+                    tracing::warn!("凭据 #{} 主动刷新失败: {:#}", id, e);
+                    RefreshResult {
+                        id,
+                        expires_at: None,
+                        error: Some(format!("{:#}", e)),
+                    }
+                }
+            });
+        }
+        results
+    }
+
+    async fn validate_credential(
+        &self,
+        id: u64,
+        credentials: KiroCredentials,
+    ) -> CredentialValidation {
+        let mut result = CredentialValidation {
+            id,
+            email: credentials.email.clone(),
+            auth_method: credentials.auth_method.clone(),
+            status: ValidationStatus::Valid,
+            expires_at: None,
+        };
+
+        if let Err(e) = validate_refresh_token(&credentials) {
+            let has_token = credentials
+                .refresh_token
+                .as_deref()
+                .is_some_and(|t| !t.is_empty());
+            result.status = if has_token {
+                ValidationStatus::Truncated(e.to_string())
+            } else {
+                ValidationStatus::Invalid(e.to_string())
+            };
+            return result;
+        }
+
+        let _refreshing = RefreshingGuard::new(&self.refreshing, id);
+        let effective_proxy = credentials.effective_proxy(self.proxy.as_ref());
+        match refresh_token(&credentials, &self.config, effective_proxy.as_ref()).await {
+            Ok(new_creds) => {
+                result.expires_at = new_creds.expires_at.clone();
+                // 校验期间该凭据可能已被请求路径刷新，此时保留已有的结果
+                let mut entries = self.entries_mut();
+                if let Some(entry) = entries.iter_mut().find(|e| e.id == id)
+                    && entry.credentials.refresh_token == credentials.refresh_token
+                {
+                    entry.set_credentials(new_creds, &self.config.refresh_token_hash_salt);
+                }
+            }
+            Err(e) => result.status = ValidationStatus::Invalid(format!("{:#}", e)),
+        }
+        result
+    }
+
     // ========================================================================
     // Admin API 方法
     // ========================================================================
@@ -1249,26 +2934,57 @@ impl MultiTokenManager {
         ManagerSnapshot {
             entries: entries
                 .iter()
-                .map(|e| CredentialEntrySnapshot {
-                    id: e.id,
-                    priority: e.credentials.priority,
-                    disabled: e.disabled,
-                    failure_count: e.failure_count,
-                    auth_method: e.credentials.auth_method.as_deref().map(|m| {
-                        if m.eq_ignore_ascii_case("builder-id") || m.eq_ignore_ascii_case("iam") {
-                            "idc".to_string()
-                        } else {
-                            m.to_string()
-                        }
-                    }),
-                    has_profile_arn: e.credentials.profile_arn.is_some(),
-                    expires_at: e.credentials.expires_at.clone(),
-                    refresh_token_hash: e.credentials.refresh_token.as_deref().map(sha256_hex),
-                    email: e.credentials.email.clone(),
-                    success_count: e.success_count,
-                    last_used_at: e.last_used_at.clone(),
-                    has_proxy: e.credentials.proxy_url.is_some(),
-                    proxy_url: e.credentials.proxy_url.clone(),
+                .map(|e| {
+                    let unavailable = self.unavailability(e);
+                    let refresh_token_expires_at = e
+                        .credentials
+                        .refresh_token_expires_at(self.config.refresh_token_lifetime_days);
+                    CredentialEntrySnapshot {
+                        id: e.id,
+                        priority: e.credentials.priority,
+                        disabled: e.disabled,
+                        failure_count: e.failure_count,
+                        auth_method: e
+                            .credentials
+                            .auth_method
+                            .as_ref()
+                            .map(|_| e.credentials.effective_auth_method().to_string()),
+                        detected_auth_method: e
+                            .credentials
+                            .auth_method_conflict()
+                            .map(str::to_string),
+                        has_profile_arn: e.credentials.profile_arn.is_some(),
+                        expires_at: e.credentials.expires_at.clone(),
+                        refresh_token_hash: e
+                            .refresh_token_hash
+                            .as_ref()
+                            .map(|h| h[..REFRESH_TOKEN_HASH_DISPLAY_LEN].to_string()),
+                        refresh_token_issued_at: e.credentials.refresh_token_issued_at.clone(),
+                        refresh_token_rotated_at: e.credentials.refresh_token_rotated_at.clone(),
+                        refresh_token_expires_at: refresh_token_expires_at
+                            .map(|at| at.to_rfc3339()),
+                        refresh_token_expiring: refresh_token_expires_at
+                            .is_some_and(|at| self.refresh_token_expiring(at)),
+                        email: e.credentials.email.clone(),
+                        label: e.credentials.label.clone(),
+                        notes: e.credentials.notes.clone(),
+                        success_count: e.success_count,
+                        last_used_at: e.last_used_at.clone(),
+                        has_proxy: e.credentials.proxy_url.is_some(),
+                        proxy_url: e.credentials.proxy_url.clone(),
+                        read_only: self
+                            .sources
+                            .get(e.source)
+                            .is_some_and(|s| s.source.read_only),
+                        requests_today: self.daily_limit.credential_requests(e.id),
+                        daily_request_limit: e.credentials.daily_request_limit,
+                        canary_percent: e.credentials.canary_percent,
+                        backoff_level: e.backoff_level,
+                        last_failure: e.last_failure.map(|(kind, _)| kind),
+                        last_failure_at: e.last_failure.map(|(_, at)| at.to_rfc3339()),
+                        unavailable_reason: unavailable.map(|(reason, _)| reason),
+                        available_at: unavailable.and_then(|(_, at)| at).map(|at| at.to_rfc3339()),
+                    }
                 })
                 .collect(),
             current_id,
@@ -1280,12 +2996,14 @@ impl MultiTokenManager {
     /// 设置凭据禁用状态（Admin API）
     pub fn set_disabled(&self, id: u64, disabled: bool) -> anyhow::Result<()> {
         {
-            let mut entries = self.entries.lock();
+            let mut entries = self.entries_mut();
             let entry = entries
                 .iter_mut()
                 .find(|e| e.id == id)
                 .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
+            let was_cooling = entry.disabled_reason == Some(DisabledReason::QuotaExceeded);
             entry.disabled = disabled;
+            entry.disabled_until = None;
             if !disabled {
                 // 启用时重置失败计数
                 entry.failure_count = 0;
@@ -1293,8 +3011,17 @@ impl MultiTokenManager {
             } else {
                 entry.disabled_reason = Some(DisabledReason::Manual);
             }
+            if was_cooling {
+                self.events.publish(BusEvent::CooldownCleared { id });
+            }
+            self.events.publish(if disabled {
+                BusEvent::CredentialDisabled { id }
+            } else {
+                BusEvent::CredentialEnabled { id }
+            });
         }
-        // 持久化更改
+        // 持久化更改（禁用状态保存在统计缓存中）
+        self.save_stats();
         self.persist_credentials()?;
         Ok(())
     }
@@ -1305,7 +3032,7 @@ impl MultiTokenManager {
     /// 即使持久化失败，内存中的优先级和当前凭据选择也会生效。
     pub fn set_priority(&self, id: u64, priority: u32) -> anyhow::Result<()> {
         {
-            let mut entries = self.entries.lock();
+            let mut entries = self.entries_mut();
             let entry = entries
                 .iter_mut()
                 .find(|e| e.id == id)
@@ -1319,19 +3046,188 @@ impl MultiTokenManager {
         Ok(())
     }
 
+    /// refreshToken 是否已进入过期告警窗口（refreshTokenWarnDays）
+    fn refresh_token_expiring(&self, expires_at: DateTime<Utc>) -> bool {
+        expires_at - clock::now() <= Duration::days(self.config.refresh_token_warn_days as i64)
+    }
+
+    /// 检查各凭据 refreshToken 的预计过期时间，进入告警窗口时记录警告并发送事件
+    ///
+    /// 每个凭据只告警一次；重新登录后过期时间移出告警窗口，之后会再次告警。返回新告警的凭据 ID
+    pub fn check_refresh_token_expiry(&self) -> Vec<u64> {
+        let lifetime_days = self.config.refresh_token_lifetime_days;
+        let expiring: Vec<(u64, String, DateTime<Utc>)> = self
+            .entries
+            .lock()
+            .iter()
+            .filter_map(|e| {
+                let expires_at = e.credentials.refresh_token_expires_at(lifetime_days)?;
+                Some((e.id, e.credentials.describe(e.id), expires_at))
+            })
+            .collect();
+
+        let mut warned = self.refresh_token_warned.lock();
+        let mut newly_warned = Vec::new();
+        for (id, name, expires_at) in expiring {
+            if !self.refresh_token_expiring(expires_at) {
+                warned.remove(&id);
+                continue;
+            }
+            if !warned.insert(id) {
+                continue;
+            }
+            let days_left = (expires_at - clock::now()).num_days();
+            if days_left < 0 {
+                tracing::warn!(
+                    "凭据 {} 的 refreshToken 预计已于 {} 过期，Token 刷新失败后将被移出凭据池，请重新登录",
+                    name,
+                    expires_at.to_rfc3339()
+                );
+            } else {
+                tracing::warn!(
+                    "凭据 {} 的 refreshToken 预计 {} 天后（{}）过期，请在此之前重新登录",
+                    name,
+                    days_left,
+                    expires_at.to_rfc3339()
+                );
+            }
+            self.events
+                .publish(BusEvent::RefreshTokenExpiring { id, expires_at });
+            newly_warned.push(id);
+        }
+        newly_warned
+    }
+
+    /// 分析凭据池并生成再平衡建议（开启 rebalanceAutopilot 时自动应用）
+    pub fn analyze_rebalance(&self) -> RebalanceReport {
+        let samples: Vec<CredentialSample> = {
+            let entries = self.entries.lock();
+            entries
+                .iter()
+                .map(|e| CredentialSample {
+                    id: e.id,
+                    priority: e.credentials.priority,
+                    available: self.unavailability(e).is_none(),
+                    remaining: self
+                        .balance_store
+                        .latest(e.id)
+                        .map(|b| (b.usage_limit - b.current_usage).max(0.0)),
+                    success_count: e.success_count,
+                    failure_total: e.failure_total,
+                })
+                .collect()
+        };
+
+        let report = self.rebalancer.analyze(&samples);
+        for s in &report.suggestions {
+            tracing::info!(
+                "再平衡建议：凭据 #{} 优先级 {} -> {}（{}，流量占比 {:.0}%，额度占比 {:.0}%，失败率 {:.0}%）",
+                s.id,
+                s.current_priority,
+                s.suggested_priority,
+                s.reason.as_str(),
+                s.traffic_share * 100.0,
+                s.quota_share * 100.0,
+                s.failure_rate * 100.0
+            );
+        }
+        if self.config.rebalance_autopilot && !report.suggestions.is_empty() {
+            return self.apply_rebalance().unwrap_or(report);
+        }
+        report
+    }
+
+    /// 最近一次再平衡分析结果
+    pub fn rebalance_report(&self) -> Option<RebalanceReport> {
+        self.rebalancer.last_report()
+    }
+
+    /// 应用最近一次再平衡分析的建议（Admin API 或 autopilot）
+    ///
+    /// 返回应用后的分析结果；没有分析结果时返回 None
+    pub fn apply_rebalance(&self) -> Option<RebalanceReport> {
+        let report = self.rebalancer.last_report()?;
+        if report.applied || report.suggestions.is_empty() {
+            return Some(report);
+        }
+        {
+            let mut entries = self.entries_mut();
+            for s in &report.suggestions {
+                if let Some(entry) = entries
+                    .iter_mut()
+                    .find(|e| e.id == s.id && e.credentials.priority == s.current_priority)
+                {
+                    entry.credentials.priority = s.suggested_priority;
+                }
+            }
+        }
+        self.rebalancer.mark_applied();
+        tracing::info!("已应用 {} 条再平衡建议", report.suggestions.len());
+        self.select_highest_priority();
+        if let Err(e) = self.persist_credentials() {
+            tracing::warn!("再平衡后持久化凭据失败: {}", e);
+        }
+        self.rebalancer.last_report()
+    }
+
+    /// 设置凭据级每日请求上限（Admin API，None 表示不限制）
+    pub fn set_daily_request_limit(&self, id: u64, limit: Option<u64>) -> anyhow::Result<()> {
+        {
+            let mut entries = self.entries_mut();
+            let entry = entries
+                .iter_mut()
+                .find(|e| e.id == id)
+                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
+            entry.credentials.daily_request_limit = limit;
+        }
+        // 持久化更改
+        self.persist_credentials()?;
+        Ok(())
+    }
+
+    /// 设置凭据的金丝雀流量百分比（Admin API，None 表示转正，正常参与轮换）
+    pub fn set_canary_percent(&self, id: u64, percent: Option<u8>) -> anyhow::Result<()> {
+        if percent.is_some_and(|p| p > 100) {
+            anyhow::bail!("金丝雀流量百分比必须在 0-100 之间");
+        }
+        {
+            let mut entries = self.entries_mut();
+            let entry = entries
+                .iter_mut()
+                .find(|e| e.id == id)
+                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
+            entry.credentials.canary_percent = percent;
+            match percent {
+                Some(p) => tracing::info!("凭据 #{} 设为金丝雀凭据（{}% 流量）", id, p),
+                None => tracing::info!("凭据 #{} 已转正，参与常规轮换", id),
+            }
+        }
+        // 当前凭据变为金丝雀后重新选择
+        self.select_highest_priority();
+        // 持久化更改
+        self.persist_credentials()?;
+        Ok(())
+    }
+
     /// 重置凭据失败计数并重新启用（Admin API）
     pub fn reset_and_enable(&self, id: u64) -> anyhow::Result<()> {
         {
-            let mut entries = self.entries.lock();
+            let mut entries = self.entries_mut();
             let entry = entries
                 .iter_mut()
                 .find(|e| e.id == id)
                 .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
+            if entry.disabled_reason == Some(DisabledReason::QuotaExceeded) {
+                self.events.publish(BusEvent::CooldownCleared { id });
+            }
             entry.failure_count = 0;
             entry.disabled = false;
             entry.disabled_reason = None;
+            entry.disabled_until = None;
+            self.events.publish(BusEvent::CredentialEnabled { id });
         }
-        // 持久化更改
+        // 持久化更改（禁用状态保存在统计缓存中）
+        self.save_stats();
         self.persist_credentials()?;
         Ok(())
     }
@@ -1352,37 +3248,10 @@ impl MultiTokenManager {
 
         let token = if needs_refresh {
             let _guard = self.refresh_lock.lock().await;
-            let current_creds = {
-                let entries = self.entries.lock();
-                entries
-                    .iter()
-                    .find(|e| e.id == id)
-                    .map(|e| e.credentials.clone())
-                    .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?
-            };
-
-            if is_token_expired(&current_creds) || is_token_expiring_soon(&current_creds) {
-                let effective_proxy = current_creds.effective_proxy(self.proxy.as_ref());
-                let new_creds =
-                    refresh_token(&current_creds, &self.config, effective_proxy.as_ref()).await?;
-                {
-                    let mut entries = self.entries.lock();
-                    if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
-                        entry.credentials = new_creds.clone();
-                    }
-                }
-                // 持久化失败只记录警告，不影响本次请求
-                if let Err(e) = self.persist_credentials() {
-                    tracing::warn!("Token 刷新后持久化失败（不影响本次请求）: {}", e);
-                }
-                new_creds
-                    .access_token
-                    .ok_or_else(|| anyhow::anyhow!("刷新后无 access_token"))?
-            } else {
-                current_creds
-                    .access_token
-                    .ok_or_else(|| anyhow::anyhow!("凭据无 access_token"))?
-            }
+            self.refresh_credential(id, false)
+                .await?
+                .access_token
+                .ok_or_else(|| anyhow::anyhow!("刷新后无 access_token"))?
         } else {
             credentials
                 .access_token
@@ -1400,11 +3269,18 @@ impl MultiTokenManager {
 
         let effective_proxy = credentials.effective_proxy(self.proxy.as_ref());
         let usage_limits = get_usage_limits(&credentials, &self.config, &token, effective_proxy.as_ref()).await?;
+        let balance = BalanceSnapshot::from(&usage_limits);
+        self.events.publish(BusEvent::BalanceUpdated {
+            id,
+            current_usage: balance.current_usage,
+            usage_limit: balance.usage_limit,
+        });
+        self.balance_store.insert(id, balance);
 
         // 更新订阅等级到凭据（仅在发生变化时持久化）
         if let Some(subscription_title) = usage_limits.subscription_title() {
             let changed = {
-                let mut entries = self.entries.lock();
+                let mut entries = self.entries_mut();
                 if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
                     let old_title = entry.credentials.subscription_title.clone();
                     if old_title.as_deref() != Some(subscription_title) {
@@ -1426,7 +3302,7 @@ impl MultiTokenManager {
             };
 
             if changed {
-                if let Err(e) = self.persist_credentials() {
+                if let Err(e) = self.persist_credentials_async().await {
                     tracing::warn!("订阅等级更新后持久化失败（不影响本次请求）: {}", e);
                 }
             }
@@ -1435,12 +3311,84 @@ impl MultiTokenManager {
         Ok(usage_limits)
     }
 
-    /// 添加新凭据（Admin API）
-    ///
-    /// # 流程
-    /// 1. 验证凭据基本字段（refresh_token 不为空）
-    /// 2. 基于 refreshToken 的 SHA-256 哈希检测重复
-    /// 3. 尝试刷新 Token 验证凭据有效性
+    /// 获取指定凭据的余额（优先使用缓存，Admin API）
+    pub async fn get_balance(&self, id: u64) -> anyhow::Result<BalanceSnapshot> {
+        let last_used_secs_ago = {
+            let entries = self.entries.lock();
+            entries
+                .iter()
+                .find(|e| e.id == id)
+                .and_then(|e| e.last_used_at.as_deref())
+                .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                .map(|t| (Utc::now() - t.with_timezone(&Utc)).num_seconds().max(0) as u64)
+        };
+
+        if let Some(balance) = self.balance_store.get(id, last_used_secs_ago) {
+            tracing::debug!("凭据 #{} 余额命中缓存", id);
+            return Ok(balance);
+        }
+
+        let usage_limits = self.get_usage_limits_for(id).await?;
+        Ok(BalanceSnapshot::from(&usage_limits))
+    }
+
+    /// 启动时预热凭据余额（供溢出与再平衡使用）
+    ///
+    /// 跳过不可用及缓存仍有效的凭据，按 `balanceInitConcurrency` 限制并发，
+    /// 每次查询前随机等待 0 ~ `balanceInitJitterMs` 毫秒，避免同一上游主机收到突发请求
+    pub async fn initialize_balances(&self) {
+        let concurrency = self.config.balance_init_concurrency;
+        if concurrency == 0 {
+            return;
+        }
+        let ids: Vec<u64> = {
+            let entries = self.entries.lock();
+            entries
+                .iter()
+                .filter(|e| self.unavailability(e).is_none())
+                .filter(|e| self.balance_store.get(e.id, None).is_none())
+                .map(|e| e.id)
+                .collect()
+        };
+        if ids.is_empty() {
+            return;
+        }
+
+        let started = Instant::now();
+        let jitter_ms = self.config.balance_init_jitter_ms;
+        let total = ids.len();
+        let failed = futures::stream::iter(ids)
+            .map(|id| async move {
+                if jitter_ms > 0 {
+                    tokio::time::sleep(StdDuration::from_millis(fastrand::u64(0..=jitter_ms)))
+                        .await;
+                }
+                match self.get_usage_limits_for(id).await {
+                    Ok(_) => false,
+                    Err(e) => {
+                        tracing::warn!("预热凭据 #{} 余额失败: {}", id, e);
+                        true
+                    }
+                }
+            })
+            .buffer_unordered(concurrency)
+            .filter(|failed| std::future::ready(*failed))
+            .count()
+            .await;
+        tracing::info!(
+            "凭据余额预热完成：{} 个凭据，失败 {} 个，耗时 {:.1}s",
+            total,
+            failed,
+            started.elapsed().as_secs_f64()
+        );
+    }
+
+    /// 添加新凭据（Admin API）
+    ///
+    /// # 流程
+    /// 1. 验证凭据基本字段（refresh_token 不为空）
+    /// 2. 基于加盐的 refreshToken 完整 SHA-256 哈希检测重复
+    /// 3. 尝试刷新 Token 验证凭据有效性
     /// 4. 分配新 ID（当前最大 ID + 1）
     /// 5. 添加到 entries 列表
     /// 6. 持久化到配置文件
@@ -1448,27 +3396,26 @@ impl MultiTokenManager {
     /// # 返回
     /// - `Ok(u64)` - 新凭据 ID
     /// - `Err(_)` - 验证失败或添加失败
-    pub async fn add_credential(&self, new_cred: KiroCredentials) -> anyhow::Result<u64> {
-        // 1. 基本验证
+    pub async fn add_credential(&self, mut new_cred: KiroCredentials) -> anyhow::Result<u64> {
+        // 1. 基本验证（先去除复制粘贴引入的空白与不可见字符）
+        if new_cred.normalize_tokens() {
+            tracing::info!("新凭据的 Token 中包含空白或不可见字符，已自动去除");
+        }
         validate_refresh_token(&new_cred)?;
+        record_refresh_token_issued_at(&mut new_cred);
+        if let Some(percent) = new_cred.canary_percent.filter(|&p| p > 100) {
+            return Err(CredentialValidationError::InvalidCanaryPercent(percent).into());
+        }
 
-        // 2. 基于 refreshToken 的 SHA-256 哈希检测重复
-        let new_refresh_token = new_cred
-            .refresh_token
-            .as_deref()
-            .ok_or_else(|| anyhow::anyhow!("缺少 refreshToken"))?;
-        let new_refresh_token_hash = sha256_hex(new_refresh_token);
+        // 2. 基于加盐的 refreshToken 完整 SHA-256 哈希检测重复
+        let new_refresh_token_hash =
+            refresh_token_hash(&self.config.refresh_token_hash_salt, &new_cred)
+                .ok_or_else(|| anyhow::anyhow!("缺少 refreshToken"))?;
         let duplicate_exists = {
             let entries = self.entries.lock();
-            entries.iter().any(|entry| {
-                entry
-                    .credentials
-                    .refresh_token
-                    .as_deref()
-                    .map(sha256_hex)
-                    .as_deref()
-                    == Some(new_refresh_token_hash.as_str())
-            })
+            entries
+                .iter()
+                .any(|entry| entry.refresh_token_hash.as_ref() == Some(&new_refresh_token_hash))
         };
         if duplicate_exists {
             anyhow::bail!("凭据已存在（refreshToken 重复）");
@@ -1479,11 +3426,8 @@ impl MultiTokenManager {
         let mut validated_cred =
             refresh_token(&new_cred, &self.config, effective_proxy.as_ref()).await?;
 
-        // 4. 分配新 ID
-        let new_id = {
-            let entries = self.entries.lock();
-            entries.iter().map(|e| e.id).max().unwrap_or(0) + 1
-        };
+        // 4. 分配新 ID（避开归档中的 ID，保证已删除凭据可按原 ID 恢复）
+        let new_id = self.next_credential_id(&self.entries.lock());
 
         // 5. 设置 ID 并保留用户输入的元数据
         validated_cred.id = Some(new_id);
@@ -1502,27 +3446,44 @@ impl MultiTokenManager {
         validated_cred.api_region = new_cred.api_region;
         validated_cred.machine_id = new_cred.machine_id;
         validated_cred.email = new_cred.email;
+        validated_cred.label = new_cred.label;
+        validated_cred.notes = new_cred.notes;
+        validated_cred.daily_request_limit = new_cred.daily_request_limit;
+        validated_cred.canary_percent = new_cred
+            .canary_percent
+            .or(Some(self.config.new_credential_canary_percent).filter(|&p| p > 0));
         validated_cred.proxy_url = new_cred.proxy_url;
         validated_cred.proxy_username = new_cred.proxy_username;
         validated_cred.proxy_password = new_cred.proxy_password;
 
         {
-            let mut entries = self.entries.lock();
+            let mut entries = self.entries_mut();
             entries.push(CredentialEntry {
                 id: new_id,
+                source: self.default_source(),
+                refresh_token_hash: refresh_token_hash(
+                    &self.config.refresh_token_hash_salt,
+                    &validated_cred,
+                ),
                 credentials: validated_cred,
                 failure_count: 0,
                 disabled: false,
                 disabled_reason: None,
+                disabled_until: None,
+                backoff_level: 0,
+                last_failure: None,
                 success_count: 0,
+                failure_total: 0,
                 last_used_at: None,
             });
         }
 
         // 6. 持久化
-        self.persist_credentials()?;
+        self.persist_credentials_async().await?;
 
         tracing::info!("成功添加凭据 #{}", new_id);
+        self.events
+            .publish(BusEvent::CredentialAdded { id: new_id });
         Ok(new_id)
     }
 
@@ -1534,7 +3495,7 @@ impl MultiTokenManager {
     /// # 行为
     /// 1. 验证凭据存在
     /// 2. 验证凭据已禁用
-    /// 3. 从 entries 移除
+    /// 3. 从 entries 移除并归档（保留期内可通过 `restore_credential` 恢复）
     /// 4. 如果删除的是当前凭据，切换到优先级最高的可用凭据
     /// 5. 如果删除后没有凭据，将 current_id 重置为 0
     /// 6. 持久化到文件
@@ -1544,7 +3505,7 @@ impl MultiTokenManager {
     /// - `Err(_)` - 凭据不存在、未禁用或持久化失败
     pub fn delete_credential(&self, id: u64) -> anyhow::Result<()> {
         let was_current = {
-            let mut entries = self.entries.lock();
+            let mut entries = self.entries_mut();
 
             // 查找凭据
             let entry = entries
@@ -1557,16 +3518,31 @@ impl MultiTokenManager {
                 anyhow::bail!("只能删除已禁用的凭据（请先禁用凭据 #{}）", id);
             }
 
+            // 只读文件中的凭据删除后重启会重新出现
+            if self
+                .sources
+                .get(entry.source)
+                .is_some_and(|s| s.source.read_only)
+            {
+                anyhow::bail!("凭据 #{} 来自只读凭据文件，无法删除", id);
+            }
+
             // 记录是否是当前凭据
             let current_id = *self.current_id.lock();
             let was_current = current_id == id;
 
-            // 删除凭据
+            // 删除凭据并归档（保留期内可恢复）
+            let (source, credentials) = (entry.source, entry.credentials.clone());
             entries.retain(|e| e.id != id);
+            self.archive.archive(source, credentials);
 
             was_current
         };
 
+        // 清理已删除凭据残留的余额缓存、每日计数、亲和绑定等状态
+        self.gc_credential_state();
+        self.events.publish(BusEvent::CredentialDeleted { id });
+
         // 如果删除的是当前凭据，切换到优先级最高的可用凭据
         if was_current {
             self.select_highest_priority();
@@ -1576,7 +3552,7 @@ impl MultiTokenManager {
         {
             let entries = self.entries.lock();
             if entries.is_empty() {
-                let mut current_id = self.current_id.lock();
+                let mut current_id = self.current_id_mut();
                 *current_id = 0;
                 tracing::info!("所有凭据已删除，current_id 已重置为 0");
             }
@@ -1585,10 +3561,194 @@ impl MultiTokenManager {
         // 持久化更改
         self.persist_credentials()?;
 
-        tracing::info!("已删除凭据 #{}", id);
+        if self.archive.is_enabled() {
+            tracing::info!(
+                "已删除凭据 #{}（已归档，{} 天内可恢复）",
+                id,
+                self.config.archive_retention_days
+            );
+        } else {
+            tracing::info!("已删除凭据 #{}", id);
+        }
         Ok(())
     }
 
+    /// 分配新的凭据 ID（大于现有凭据与归档中的所有 ID）
+    fn next_credential_id(&self, entries: &[CredentialEntry]) -> u64 {
+        let max_id = entries.iter().map(|e| e.id).max().unwrap_or(0);
+        max_id.max(self.archive.max_id().unwrap_or(0)) + 1
+    }
+
+    /// 列出保留期内的已删除凭据及其自动清除时间（Admin API）
+    pub fn archived_credentials(&self) -> Vec<(ArchivedCredential, DateTime<Utc>)> {
+        self.archive
+            .list()
+            .into_iter()
+            .map(|entry| {
+                let purge_at = self.archive.purge_at(&entry);
+                (entry, purge_at)
+            })
+            .collect()
+    }
+
+    /// 恢复已删除的凭据（Admin API）
+    ///
+    /// 恢复到原来源文件（该文件已只读或不存在时写入默认来源文件），
+    /// 恢复后保持禁用状态，需手动启用。原 ID 已被占用时分配新 ID。
+    ///
+    /// # Returns
+    /// 恢复后的凭据 ID
+    pub fn restore_credential(&self, id: u64) -> anyhow::Result<u64> {
+        let archived = self
+            .archive
+            .list()
+            .into_iter()
+            .find(|e| e.id() == id)
+            .ok_or_else(|| anyhow::anyhow!("已删除凭据不存在或已超过保留期: {}", id))?;
+
+        let restored_id = {
+            let mut entries = self.entries_mut();
+            let hash =
+                refresh_token_hash(&self.config.refresh_token_hash_salt, &archived.credentials);
+            let duplicate = hash.is_some() && entries.iter().any(|e| e.refresh_token_hash == hash);
+            if duplicate {
+                anyhow::bail!("凭据已存在（refreshToken 重复）");
+            }
+            self.archive.take(id);
+
+            let restored_id = if entries.iter().any(|e| e.id == id) {
+                self.next_credential_id(&entries)
+            } else {
+                id
+            };
+            let mut credentials = archived.credentials;
+            credentials.id = Some(restored_id);
+
+            let source = match self.sources.get(archived.source) {
+                Some(s) if s.writer.is_some() => archived.source,
+                _ => self.default_source(),
+            };
+            entries.push(CredentialEntry {
+                id: restored_id,
+                source,
+                refresh_token_hash: refresh_token_hash(
+                    &self.config.refresh_token_hash_salt,
+                    &credentials,
+                ),
+                credentials,
+                failure_count: 0,
+                disabled: true,
+                disabled_reason: Some(DisabledReason::Manual),
+                disabled_until: None,
+                backoff_level: 0,
+                last_failure: None,
+                success_count: 0,
+                failure_total: 0,
+                last_used_at: None,
+            });
+            restored_id
+        };
+
+        self.save_stats();
+        self.persist_credentials()?;
+        self.events
+            .publish(BusEvent::CredentialAdded { id: restored_id });
+
+        if restored_id == id {
+            tracing::info!("已恢复凭据 #{}（保持禁用，需手动启用）", id);
+        } else {
+            tracing::info!(
+                "已恢复凭据 #{}，原 ID 已被占用，分配为 #{}（保持禁用，需手动启用）",
+                id,
+                restored_id
+            );
+        }
+        Ok(restored_id)
+    }
+
+    /// 永久删除已归档的凭据（Admin API）
+    pub fn purge_archived_credential(&self, id: u64) -> anyhow::Result<()> {
+        self.archive
+            .take(id)
+            .ok_or_else(|| anyhow::anyhow!("已删除凭据不存在或已超过保留期: {}", id))?;
+        tracing::info!("已永久删除凭据 #{}", id);
+        Ok(())
+    }
+
+    /// 当前的亲和绑定（最近使用的在前，不含已过期的绑定）
+    pub fn affinity_bindings(&self) -> Vec<AffinityBindingSnapshot> {
+        let now = Utc::now();
+        let mut affinity = self.affinity.lock();
+        affinity.retain(|_, binding| !self.affinity_expired(binding, now));
+
+        let mut bindings: Vec<(&String, &AffinityBinding)> = affinity.iter().collect();
+        bindings.sort_by(|a, b| b.1.last_used_at.cmp(&a.1.last_used_at).then(a.0.cmp(b.0)));
+        bindings
+            .into_iter()
+            .map(|(key, binding)| AffinityBindingSnapshot {
+                key: key.clone(),
+                credential_id: binding.credential_id,
+                bound_at: binding.bound_at.to_rfc3339(),
+                last_used_at: binding.last_used_at.to_rfc3339(),
+            })
+            .collect()
+    }
+
+    /// 解除亲和绑定：按亲和键、按凭据，或两者同时匹配，返回解除的数量
+    pub fn unbind_affinity(&self, key: Option<&str>, credential_id: Option<u64>) -> usize {
+        if key.is_none() && credential_id.is_none() {
+            return 0;
+        }
+        let mut affinity = self.affinity.lock();
+        let before = affinity.len();
+        affinity.retain(|k, binding| {
+            let matches = key.is_none_or(|key| key == k)
+                && credential_id.is_none_or(|id| id == binding.credential_id);
+            !matches
+        });
+        let removed = before - affinity.len();
+        if removed > 0 {
+            tracing::info!(
+                "已解除 {} 个亲和绑定（亲和键: {:?}，凭据: {:?}）",
+                removed,
+                key,
+                credential_id
+            );
+        }
+        removed
+    }
+
+    /// 清理已不在凭据池中的凭据残留的状态，返回移除的条目数
+    ///
+    /// 包括余额缓存、当天的分凭据请求计数、亲和绑定、refreshToken 过期告警记录与分凭据指标。
+    /// 删除凭据时立即调用；外部修改凭据文件移除的凭据由后台任务定期清理
+    pub fn gc_credential_state(&self) -> usize {
+        let ids: HashSet<u64> = self.entries.lock().iter().map(|e| e.id).collect();
+        let keep = |id: u64| ids.contains(&id);
+
+        let affinity = {
+            let mut affinity = self.affinity.lock();
+            let before = affinity.len();
+            affinity.retain(|_, binding| keep(binding.credential_id));
+            before - affinity.len()
+        };
+        let warned = {
+            let mut warned = self.refresh_token_warned.lock();
+            let before = warned.len();
+            warned.retain(|id| keep(*id));
+            before - warned.len()
+        };
+        let removed = self.balance_store.retain(keep)
+            + self.daily_limit.retain_credentials(keep)
+            + self.metrics.retain_credentials(keep)
+            + affinity
+            + warned;
+        if removed > 0 {
+            tracing::info!("已清理 {} 条已删除凭据残留的状态", removed);
+        }
+        removed
+    }
+
     /// 获取负载均衡模式（Admin API）
     pub fn get_load_balancing_mode(&self) -> String {
         self.load_balancing_mode.lock().clone()
@@ -1641,15 +3801,14 @@ impl MultiTokenManager {
 
 impl Drop for MultiTokenManager {
     fn drop(&mut self) {
-        if self.stats_dirty.load(Ordering::Relaxed) {
-            self.save_stats();
-        }
+        self.flush_stats();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kiro::model::credential_builder::is_truncated_refresh_token;
 
     #[test]
     fn test_token_manager_new() {
@@ -1719,6 +3878,26 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_validate_refresh_token_pasted() {
+        // 省略号字符同样视为截断
+        let mut credentials = KiroCredentials {
+            refresh_token: Some(format!("{}…", "a".repeat(150))),
+            ..Default::default()
+        };
+        assert!(is_truncated_refresh_token(
+            credentials.refresh_token.as_deref().unwrap()
+        ));
+
+        // 规范化后仍残留的全角字符给出具体位置
+        credentials.refresh_token = Some(format!("aor{}“", "a".repeat(150)));
+        let error = validate_refresh_token(&credentials)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("第 154 个字符"));
+        assert!(error.contains("U+201C"));
+    }
+
     #[test]
     fn test_sha256_hex() {
         let result = sha256_hex("test");
@@ -1745,6 +3924,44 @@ mod tests {
         assert!(result.err().unwrap().to_string().contains("凭据已存在"));
     }
 
+    #[test]
+    fn test_refresh_token_hash_uses_whole_token() {
+        let first = KiroCredentials {
+            refresh_token: Some(format!("{}{}", "a".repeat(100), "x".repeat(50))),
+            ..Default::default()
+        };
+        let second = KiroCredentials {
+            refresh_token: Some(format!("{}{}", "a".repeat(100), "y".repeat(50))),
+            ..Default::default()
+        };
+
+        // 前缀相同的 Token 不会被视为重复，盐不同时哈希不同
+        assert_ne!(
+            refresh_token_hash("kiro-rs", &first),
+            refresh_token_hash("kiro-rs", &second)
+        );
+        assert_ne!(
+            refresh_token_hash("kiro-rs", &first),
+            refresh_token_hash("other", &first)
+        );
+        assert!(refresh_token_hash("kiro-rs", &KiroCredentials::default()).is_none());
+
+        // Admin API 只展示哈希前缀
+        let manager =
+            MultiTokenManager::new(Config::default(), vec![first.clone()], None, None, false)
+                .unwrap();
+        let shown = manager.snapshot().entries[0]
+            .refresh_token_hash
+            .clone()
+            .unwrap();
+        assert_eq!(shown.len(), REFRESH_TOKEN_HASH_DISPLAY_LEN);
+        assert!(
+            refresh_token_hash("kiro-rs", &first)
+                .unwrap()
+                .starts_with(&shown)
+        );
+    }
+
     // MultiTokenManager 测试
 
     #[test]
@@ -1762,161 +3979,1156 @@ mod tests {
     }
 
     #[test]
-    fn test_multi_token_manager_empty_credentials() {
-        let config = Config::default();
-        let result = MultiTokenManager::new(config, vec![], None, None, false);
-        // 支持 0 个凭据启动（可通过管理面板添加）
-        assert!(result.is_ok());
-        let manager = result.unwrap();
-        assert_eq!(manager.total_count(), 0);
-        assert_eq!(manager.available_count(), 0);
+    fn test_multi_token_manager_empty_credentials() {
+        let config = Config::default();
+        let result = MultiTokenManager::new(config, vec![], None, None, false);
+        // 支持 0 个凭据启动（可通过管理面板添加）
+        assert!(result.is_ok());
+        let manager = result.unwrap();
+        assert_eq!(manager.total_count(), 0);
+        assert_eq!(manager.available_count(), 0);
+    }
+
+    #[test]
+    fn test_multi_token_manager_duplicate_ids() {
+        let config = Config::default();
+        let mut cred1 = KiroCredentials::default();
+        cred1.id = Some(1);
+        let mut cred2 = KiroCredentials::default();
+        cred2.id = Some(1); // 重复 ID
+
+        let result = MultiTokenManager::new(config, vec![cred1, cred2], None, None, false);
+        assert!(result.is_err());
+        let err_msg = result.err().unwrap().to_string();
+        assert!(
+            err_msg.contains("重复的凭据 ID"),
+            "错误消息应包含 '重复的凭据 ID'，实际: {}",
+            err_msg
+        );
+    }
+
+    #[test]
+    fn test_multi_token_manager_report_failure() {
+        let config = Config::default();
+        let cred1 = KiroCredentials::default();
+        let cred2 = KiroCredentials::default();
+
+        let manager =
+            MultiTokenManager::new(config, vec![cred1, cred2], None, None, false).unwrap();
+
+        // 凭据会自动分配 ID（从 1 开始）
+        // 前两次失败不会禁用（使用 ID 1）
+        assert!(manager.report_failure(1));
+        assert!(manager.report_failure(1));
+        assert_eq!(manager.available_count(), 2);
+
+        // 第三次失败会禁用第一个凭据
+        assert!(manager.report_failure(1));
+        assert_eq!(manager.available_count(), 1);
+
+        // 继续失败第二个凭据（使用 ID 2）
+        assert!(manager.report_failure(2));
+        assert!(manager.report_failure(2));
+        assert!(!manager.report_failure(2)); // 所有凭据都禁用了
+        assert_eq!(manager.available_count(), 0);
+    }
+
+    #[test]
+    fn test_multi_token_manager_report_success() {
+        let config = Config::default();
+        let cred = KiroCredentials::default();
+
+        let manager = MultiTokenManager::new(config, vec![cred], None, None, false).unwrap();
+
+        // 失败两次（使用 ID 1）
+        manager.report_failure(1);
+        manager.report_failure(1);
+
+        // 成功后重置计数（使用 ID 1）
+        manager.report_success(1);
+
+        // 再失败两次不会禁用
+        manager.report_failure(1);
+        manager.report_failure(1);
+        assert_eq!(manager.available_count(), 1);
+    }
+
+    #[test]
+    fn test_multi_token_manager_failure_diagnostics() {
+        let cred = KiroCredentials {
+            daily_request_limit: Some(1),
+            ..Default::default()
+        };
+        let manager =
+            MultiTokenManager::new(Config::default(), vec![cred], None, None, false).unwrap();
+        manager.daily_limit.record(0, 1);
+
+        // 瞬态失败只提升退避级别，不计入连续失败
+        manager.report_transient_failure(1, FailureKind::Throttled);
+        manager.report_transient_failure(1, FailureKind::Network);
+        let entry = manager.snapshot().entries.remove(0);
+        assert_eq!(entry.backoff_level, 2);
+        assert_eq!(entry.failure_count, 0);
+        assert_eq!(entry.last_failure, Some(FailureKind::Network));
+        assert!(entry.last_failure_at.is_some());
+
+        // 今日额度用完时给出原因与恢复时间
+        assert_eq!(
+            entry.unavailable_reason,
+            Some(UnavailableReason::DailyLimit)
+        );
+        assert!(entry.available_at.is_some());
+
+        // 成功后退避级别清零，失败分类保留用于诊断
+        manager.report_success(1);
+        manager.set_disabled(1, true).unwrap();
+        let entry = manager.snapshot().entries.remove(0);
+        assert_eq!(entry.backoff_level, 0);
+        assert_eq!(entry.last_failure, Some(FailureKind::Network));
+        assert_eq!(entry.unavailable_reason, Some(UnavailableReason::Disabled));
+        assert_eq!(entry.available_at, None);
+    }
+
+    #[test]
+    fn test_multi_token_manager_rebalance() {
+        let creds = (0..2)
+            .map(|priority| KiroCredentials {
+                priority,
+                ..Default::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(Config::default(), creds, None, None, false).unwrap();
+        for (id, current_usage) in [(1, 90.0), (2, 10.0)] {
+            manager.balance_store.insert(
+                id,
+                BalanceSnapshot {
+                    subscription_title: None,
+                    current_usage,
+                    usage_limit: 100.0,
+                    next_reset_at: None,
+                },
+            );
+        }
+        assert!(manager.rebalance_report().is_none());
+
+        // #1 承担全部流量但只剩 10% 额度：建议与 #2 交换优先级层
+        for _ in 0..30 {
+            manager.report_success(1);
+        }
+        let report = manager.analyze_rebalance();
+        assert_eq!(report.suggestions.len(), 2);
+        assert!(!report.applied);
+
+        let report = manager.apply_rebalance().unwrap();
+        assert!(report.applied);
+        let priorities: Vec<u32> = manager
+            .snapshot()
+            .entries
+            .iter()
+            .map(|e| e.priority)
+            .collect();
+        assert_eq!(priorities, vec![1, 0]);
+        assert_eq!(manager.snapshot().current_id, 2);
+    }
+
+    #[test]
+    fn test_multi_token_manager_switch_to_next() {
+        let config = Config::default();
+        let mut cred1 = KiroCredentials::default();
+        cred1.refresh_token = Some("token1".to_string());
+        let mut cred2 = KiroCredentials::default();
+        cred2.refresh_token = Some("token2".to_string());
+
+        let manager =
+            MultiTokenManager::new(config, vec![cred1, cred2], None, None, false).unwrap();
+
+        // 初始是第一个凭据
+        assert_eq!(
+            manager.credentials().refresh_token,
+            Some("token1".to_string())
+        );
+
+        // 切换到下一个
+        assert!(manager.switch_to_next());
+        assert_eq!(
+            manager.credentials().refresh_token,
+            Some("token2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_set_load_balancing_mode_persists_to_config_file() {
+        let config_path = std::env::temp_dir().join(format!(
+            "kiro-load-balancing-{}.json",
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::write(&config_path, r#"{"loadBalancingMode":"priority"}"#).unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+        let manager = MultiTokenManager::new(
+            config,
+            vec![KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        manager
+            .set_load_balancing_mode("balanced".to_string())
+            .unwrap();
+
+        let persisted = Config::load(&config_path).unwrap();
+        assert_eq!(persisted.load_balancing_mode, "balanced");
+        assert_eq!(manager.get_load_balancing_mode(), "balanced");
+
+        std::fs::remove_file(&config_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_multi_token_manager_acquire_context_auto_recovers_all_disabled() {
+        let config = Config::default();
+        let mut cred1 = KiroCredentials::default();
+        cred1.access_token = Some("t1".to_string());
+        cred1.expires_at = Some((Utc::now() + Duration::hours(1)).to_rfc3339());
+        let mut cred2 = KiroCredentials::default();
+        cred2.access_token = Some("t2".to_string());
+        cred2.expires_at = Some((Utc::now() + Duration::hours(1)).to_rfc3339());
+
+        let manager =
+            MultiTokenManager::new(config, vec![cred1, cred2], None, None, false).unwrap();
+
+        // 凭据会自动分配 ID（从 1 开始）
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(1);
+        }
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(2);
+        }
+
+        assert_eq!(manager.available_count(), 0);
+
+        // 应触发自愈：重置失败计数并重新启用，避免必须重启进程
+        let ctx = manager.acquire_context(None, None).await.unwrap();
+        assert!(ctx.token == "t1" || ctx.token == "t2");
+        assert_eq!(manager.available_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_tokens_reports_per_credential() {
+        let truncated = KiroCredentials {
+            id: Some(1),
+            refresh_token: Some("a".repeat(50)),
+            access_token: Some("t1".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let missing = KiroCredentials {
+            id: Some(2),
+            ..Default::default()
+        };
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![truncated, missing],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        // 未过期的 Token 同样会刷新（此处因 refreshToken 已截断而失败，不请求上游）
+        let results = manager.refresh_tokens(None).await;
+        let ids: Vec<u64> = results.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert!(results[0].error.as_deref().unwrap().contains("截断"));
+        assert!(results[1].error.is_some());
+
+        let results = manager.refresh_tokens(Some(&[9])).await;
+        assert!(results[0].error.as_deref().unwrap().contains("不存在"));
+        // 刷新失败不计入失败次数
+        assert!(
+            manager
+                .snapshot()
+                .entries
+                .iter()
+                .all(|e| e.failure_count == 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_credentials_classifies_without_network() {
+        let truncated = KiroCredentials {
+            id: Some(1),
+            refresh_token: Some("a".repeat(50)),
+            ..Default::default()
+        };
+        let missing = KiroCredentials {
+            id: Some(2),
+            ..Default::default()
+        };
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![missing, truncated],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        let results = manager.validate_credentials(VALIDATION_CONCURRENCY).await;
+        let statuses: Vec<(u64, &str)> =
+            results.iter().map(|r| (r.id, r.status.as_str())).collect();
+        assert_eq!(statuses, vec![(1, "truncated"), (2, "invalid")]);
+        assert!(results[0].status.error().unwrap().contains("截断"));
+    }
+
+    #[tokio::test]
+    async fn test_multi_token_manager_pool_daily_limit() {
+        let valid = |id: u64, token: &str| KiroCredentials {
+            id: Some(id),
+            access_token: Some(token.to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let pool = |daily_request_limit: u64| CredentialSource {
+            daily_request_limit,
+            ..Default::default()
+        };
+
+        let manager = MultiTokenManager::with_sources(
+            Config::default(),
+            vec![
+                (pool(1), vec![valid(1, "t1")]),
+                (pool(0), vec![valid(2, "t2")]),
+            ],
+            None,
+        )
+        .unwrap();
+
+        // 第一个池用完今日额度后改用第二个池
+        assert_eq!(manager.acquire_context(None, None).await.unwrap().id, 1);
+        assert_eq!(manager.acquire_context(None, None).await.unwrap().id, 2);
+        assert_eq!(
+            manager.daily_limit_status().pools,
+            vec![(1, Some(1)), (1, None)]
+        );
+
+        // 管理员解除限制后恢复使用
+        manager.set_daily_limit_overridden(true);
+        manager.set_priority(2, 10).unwrap();
+        assert_eq!(manager.acquire_context(None, None).await.unwrap().id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_multi_token_manager_pinned_context() {
+        let valid = |id: u64, token: &str| KiroCredentials {
+            id: Some(id),
+            access_token: Some(token.to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            daily_request_limit: Some(1),
+            ..Default::default()
+        };
+
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![valid(1, "t1"), valid(2, "t2")],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        // 绕过优先级选择，直接使用指定凭据
+        assert_eq!(manager.acquire_pinned_context(2).await.unwrap().id, 2);
+
+        // 仍遵守每日上限与禁用状态，且不会切换到其他凭据
+        let err = manager.acquire_pinned_context(2).await.err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<PinnedCredentialUnavailable>(),
+            Some(&PinnedCredentialUnavailable {
+                id: 2,
+                reason: Some(UnavailableReason::DailyLimit),
+            })
+        );
+        manager.set_disabled(1, true).unwrap();
+        let err = manager.acquire_pinned_context(1).await.err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<PinnedCredentialUnavailable>()
+                .and_then(|e| e.reason),
+            Some(UnavailableReason::Disabled)
+        );
+        let err = manager.acquire_pinned_context(9).await.err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<PinnedCredentialUnavailable>()
+                .map(|e| e.reason),
+            Some(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_multi_token_manager_canary() {
+        let valid = |id: u64, priority: u32, canary_percent: Option<u8>| KiroCredentials {
+            id: Some(id),
+            access_token: Some(format!("t{}", id)),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            priority,
+            canary_percent,
+            ..Default::default()
+        };
+
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![valid(1, 0, Some(0)), valid(2, 1, None)],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        // 金丝雀凭据即使优先级最高也不参与常规轮换
+        for _ in 0..5 {
+            assert_eq!(manager.acquire_context(None, None).await.unwrap().id, 2);
+        }
+
+        // 100% 流量时每次都命中金丝雀凭据
+        manager.set_canary_percent(1, Some(100)).unwrap();
+        assert_eq!(manager.acquire_context(None, None).await.unwrap().id, 1);
+        assert!(manager.set_canary_percent(1, Some(101)).is_err());
+
+        // 没有其他可用凭据时金丝雀凭据参与常规轮换
+        manager.set_canary_percent(1, Some(0)).unwrap();
+        manager.set_disabled(2, true).unwrap();
+        assert_eq!(manager.acquire_context(None, None).await.unwrap().id, 1);
+
+        // 转正后按优先级正常选择
+        manager.set_disabled(2, false).unwrap();
+        manager.set_canary_percent(1, None).unwrap();
+        assert_eq!(manager.acquire_context(None, None).await.unwrap().id, 1);
+        let snapshot = manager.snapshot();
+        assert!(snapshot.entries.iter().all(|e| e.canary_percent.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_multi_token_manager_credential_daily_limit() {
+        let valid = |id: u64, token: &str, daily_request_limit: Option<u64>| KiroCredentials {
+            id: Some(id),
+            access_token: Some(token.to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            daily_request_limit,
+            ..Default::default()
+        };
+
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![valid(1, "t1", Some(1)), valid(2, "t2", None)],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        // 凭据 1 用完自己的今日额度后改用凭据 2
+        assert_eq!(manager.acquire_context(None, None).await.unwrap().id, 1);
+        assert_eq!(manager.acquire_context(None, None).await.unwrap().id, 2);
+        let snapshot = manager.snapshot();
+        let first = snapshot.entries.iter().find(|e| e.id == 1).unwrap();
+        assert_eq!(first.requests_today, 1);
+        assert_eq!(first.daily_request_limit, Some(1));
+
+        // 取消上限后恢复使用
+        manager.set_daily_request_limit(1, None).unwrap();
+        manager.set_priority(2, 10).unwrap();
+        assert_eq!(manager.acquire_context(None, None).await.unwrap().id, 1);
+    }
+
+    #[test]
+    fn test_multi_token_manager_persists_disabled_state() {
+        let dir = std::env::temp_dir().join(format!("kiro-disabled-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("credentials.json");
+        let credentials: Vec<KiroCredentials> = (1..=3)
+            .map(|id| KiroCredentials {
+                id: Some(id),
+                refresh_token: Some(format!("token-{}", id)),
+                priority: id as u32,
+                ..Default::default()
+            })
+            .collect();
+        std::fs::write(&path, serde_json::to_string(&credentials).unwrap()).unwrap();
+        let load = || {
+            let credentials: Vec<KiroCredentials> =
+                serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            MultiTokenManager::new(
+                Config::default(),
+                credentials,
+                None,
+                Some(path.clone()),
+                true,
+            )
+            .unwrap()
+        };
+
+        let manager = load();
+        assert!(manager.report_quota_exhausted(1));
+        manager.set_disabled(2, true).unwrap();
+        drop(manager);
+
+        // 重启后禁用状态保持，当前凭据切换到可用凭据
+        let manager = load();
+        let snapshot = manager.snapshot();
+        let disabled: Vec<u64> = snapshot
+            .entries
+            .iter()
+            .filter(|e| e.disabled)
+            .map(|e| e.id)
+            .collect();
+        assert_eq!(disabled, vec![1, 2]);
+        assert_eq!(snapshot.current_id, 3);
+        let first = snapshot.entries.iter().find(|e| e.id == 1).unwrap();
+        assert_eq!(first.failure_count, MAX_FAILURES_PER_CREDENTIAL);
+
+        // 已过自动解除时间的禁用不再恢复
+        manager.entries.lock()[0].disabled_until = Some(Utc::now() - Duration::minutes(1));
+        manager.save_stats();
+        drop(manager);
+        let manager = load();
+        let snapshot = manager.snapshot();
+        let first = snapshot.entries.iter().find(|e| e.id == 1).unwrap();
+        assert!(!first.disabled);
+        assert_eq!(snapshot.current_id, 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_multi_token_manager_multiple_sources() {
+        let dir = std::env::temp_dir().join(format!("kiro-sources-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let personal_path = dir.join("personal.json");
+        let team_path = dir.join("team.json");
+        std::fs::write(&team_path, "[]").unwrap();
+
+        let personal = KiroCredentials {
+            id: Some(1),
+            refresh_token: Some("personal".to_string()),
+            ..Default::default()
+        };
+        let team = KiroCredentials {
+            id: Some(2),
+            refresh_token: Some("team".to_string()),
+            ..Default::default()
+        };
+
+        let manager = MultiTokenManager::with_sources(
+            Config::default(),
+            vec![
+                (
+                    CredentialSource {
+                        path: Some(personal_path.clone()),
+                        is_multiple_format: true,
+                        read_only: false,
+                        daily_request_limit: 0,
+                    },
+                    vec![personal],
+                ),
+                (
+                    CredentialSource {
+                        path: Some(team_path.clone()),
+                        is_multiple_format: true,
+                        read_only: true,
+                        daily_request_limit: 0,
+                    },
+                    vec![team],
+                ),
+            ],
+            None,
+        )
+        .unwrap();
+        assert_eq!(manager.total_count(), 2);
+
+        // 回写只写入可写文件，且只包含该文件自己的凭据
+        manager.set_priority(2, 5).unwrap();
+        let written: Vec<KiroCredentials> =
+            serde_json::from_str(&std::fs::read_to_string(&personal_path).unwrap()).unwrap();
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0].id, Some(1));
+        assert_eq!(std::fs::read_to_string(&team_path).unwrap(), "[]");
+
+        // 只读文件中的凭据不能删除
+        manager.set_disabled(2, true).unwrap();
+        let err = manager.delete_credential(2).unwrap_err();
+        assert!(err.to_string().contains("只读"));
+
+        let snapshot = manager.snapshot();
+        let team_entry = snapshot.entries.iter().find(|e| e.id == 2).unwrap();
+        assert!(team_entry.read_only);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_multi_token_manager_merges_external_changes() {
+        let dir = std::env::temp_dir().join(format!("kiro-merge-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("credentials.json");
+
+        let first = KiroCredentials {
+            id: Some(1),
+            refresh_token: Some("first".to_string()),
+            access_token: Some("fresh".to_string()),
+            expires_at: Some("2099-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        let second = KiroCredentials {
+            id: Some(2),
+            refresh_token: Some("second".to_string()),
+            ..Default::default()
+        };
+        std::fs::write(&path, serde_json::to_string(&[&first, &second]).unwrap()).unwrap();
+
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![first, second],
+            None,
+            Some(path.clone()),
+            true,
+        )
+        .unwrap();
+
+        // 其他程序改写文件：修改 #1、删除 #2、新增一个没有 ID 的凭据
+        let external = serde_json::json!([
+            {"id": 1, "refreshToken": "first", "accessToken": "stale",
+             "expiresAt": "2020-01-01T00:00:00Z", "priority": 7},
+            {"refreshToken": "third"}
+        ]);
+        std::fs::write(&path, external.to_string()).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() + StdDuration::from_secs(10))
+            .unwrap();
+
+        manager.sync_external_changes();
+
+        let snapshot = manager.snapshot();
+        let mut ids: Vec<u64> = snapshot.entries.iter().map(|e| e.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 3]);
+        let updated = snapshot.entries.iter().find(|e| e.id == 1).unwrap();
+        assert_eq!(updated.priority, 7);
+        // 内存中的 Token 更新，不被文件中过期的 Token 覆盖
+        assert_eq!(manager.credentials().access_token.as_deref(), Some("fresh"));
+
+        // 新分配的 ID 已回写，且回写不再被拒绝
+        let written: Vec<KiroCredentials> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written.len(), 2);
+        assert!(written.iter().any(|c| c.id == Some(3)));
+        manager.set_priority(3, 1).unwrap();
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_multi_token_manager_affinity_spreads_keys_in_balanced_mode() {
+        let mut config = Config::default();
+        config.load_balancing_mode = "balanced".to_string();
+        let cred1 = KiroCredentials {
+            access_token: Some("t1".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let cred2 = KiroCredentials {
+            access_token: Some("t2".to_string()),
+            ..cred1.clone()
+        };
+
+        let manager =
+            MultiTokenManager::new(config, vec![cred1, cred2], None, None, false).unwrap();
+
+        // 不同亲和键分散到不同凭据
+        let main = manager.acquire_context(None, Some("user")).await.unwrap();
+        let sub = manager
+            .acquire_context(None, Some("user#subagent-1"))
+            .await
+            .unwrap();
+        assert_ne!(main.id, sub.id);
+
+        // 同一亲和键保持绑定（即使另一凭据成功次数更少）
+        manager.report_success(main.id);
+        manager.report_success(main.id);
+        let again = manager.acquire_context(None, Some("user")).await.unwrap();
+        assert_eq!(again.id, main.id);
+
+        // 绑定的凭据被禁用后重新绑定
+        manager.set_disabled(main.id, true).unwrap();
+        let rebound = manager.acquire_context(None, Some("user")).await.unwrap();
+        assert_eq!(rebound.id, sub.id);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_context_excluding_skips_failed_credential() {
+        let cred1 = KiroCredentials {
+            access_token: Some("t1".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let cred2 = KiroCredentials {
+            access_token: Some("t2".to_string()),
+            priority: 1,
+            ..cred1.clone()
+        };
+
+        // priority 模式：当前凭据中断后重试落到下一优先级的凭据
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![cred1.clone(), cred2.clone()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let first = manager.acquire_context(None, None).await.unwrap();
+        assert_eq!(first.id, 1);
+        manager.report_transient_failure(first.id, FailureKind::Upstream);
+        let retry = manager
+            .acquire_context_excluding(None, None, Some(first.id))
+            .await
+            .unwrap();
+        assert_eq!(retry.id, 2);
+
+        // balanced 模式：亲和绑定的凭据中断后重试落到其他凭据，亲和键改绑到该凭据
+        let mut config = Config::default();
+        config.load_balancing_mode = "balanced".to_string();
+        let manager =
+            MultiTokenManager::new(config, vec![cred1.clone(), cred2], None, None, false).unwrap();
+        let bound = manager.acquire_context(None, Some("user")).await.unwrap();
+        let retry = manager
+            .acquire_context_excluding(None, Some("user"), Some(bound.id))
+            .await
+            .unwrap();
+        assert_ne!(retry.id, bound.id);
+        let next = manager.acquire_context(None, Some("user")).await.unwrap();
+        assert_eq!(next.id, retry.id);
+
+        // 没有其他可用凭据时仍使用被排除的凭据
+        let manager =
+            MultiTokenManager::new(Config::default(), vec![cred1], None, None, false).unwrap();
+        let retry = manager
+            .acquire_context_excluding(None, None, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(retry.id, 1);
+    }
+
+    #[test]
+    fn test_status_etag_changes_with_state() {
+        let creds = vec![KiroCredentials::default(), KiroCredentials::default()];
+        let manager = MultiTokenManager::new(Config::default(), creds, None, None, false).unwrap();
+
+        // 只读操作不改变 ETag
+        let etag = manager.status_etag();
+        manager.snapshot();
+        manager.available_count();
+        assert_eq!(manager.status_etag(), etag);
+
+        manager.report_success(1);
+        let after_success = manager.status_etag();
+        assert_ne!(after_success, etag);
+
+        manager.set_priority(2, 5).unwrap();
+        assert_ne!(manager.status_etag(), after_success);
     }
 
     #[test]
-    fn test_multi_token_manager_duplicate_ids() {
-        let config = Config::default();
-        let mut cred1 = KiroCredentials::default();
-        cred1.id = Some(1);
-        let mut cred2 = KiroCredentials::default();
-        cred2.id = Some(1); // 重复 ID
+    fn test_state_change_events_published() {
+        let creds = vec![KiroCredentials::default(), KiroCredentials::default()];
+        let manager = MultiTokenManager::new(Config::default(), creds, None, None, false).unwrap();
+        let mut events = manager.subscribe_events();
 
-        let result = MultiTokenManager::new(config, vec![cred1, cred2], None, None, false);
-        assert!(result.is_err());
-        let err_msg = result.err().unwrap().to_string();
-        assert!(
-            err_msg.contains("重复的凭据 ID"),
-            "错误消息应包含 '重复的凭据 ID'，实际: {}",
-            err_msg
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(1);
+        }
+        manager.report_quota_exhausted(2);
+        manager.reset_and_enable(2).unwrap();
+
+        let received: Vec<BusEvent> = std::iter::from_fn(|| events.try_recv().ok())
+            .filter(BusEvent::is_state_change)
+            .collect();
+        assert_eq!(
+            received,
+            [
+                BusEvent::CircuitBreakerTripped {
+                    id: 1,
+                    failure_count: MAX_FAILURES_PER_CREDENTIAL,
+                },
+                BusEvent::CooldownSet { id: 2, until: None },
+                BusEvent::CooldownCleared { id: 2 },
+                BusEvent::CredentialEnabled { id: 2 },
+            ]
         );
     }
 
-    #[test]
-    fn test_multi_token_manager_report_failure() {
-        let config = Config::default();
-        let cred1 = KiroCredentials::default();
-        let cred2 = KiroCredentials::default();
+    #[tokio::test]
+    async fn test_affinity_expiry_eviction_and_unbind() {
+        let mut config = Config::default();
+        config.load_balancing_mode = "balanced".to_string();
+        config.affinity_max_entries = 2;
+        let creds = (0..2)
+            .map(|i| KiroCredentials {
+                access_token: Some(format!("t{}", i)),
+                expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+                ..Default::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(config, creds, None, None, false).unwrap();
 
-        let manager =
-            MultiTokenManager::new(config, vec![cred1, cred2], None, None, false).unwrap();
+        for key in ["a", "b", "c"] {
+            manager.acquire_context(None, Some(key)).await.unwrap();
+        }
+        // 超出上限时淘汰最久未使用的绑定
+        let keys: Vec<String> = manager
+            .affinity_bindings()
+            .into_iter()
+            .map(|b| b.key)
+            .collect();
+        assert_eq!(keys.len(), 2);
+        assert!(!keys.contains(&"a".to_string()));
 
-        // 凭据会自动分配 ID（从 1 开始）
-        // 前两次失败不会禁用（使用 ID 1）
-        assert!(manager.report_failure(1));
-        assert!(manager.report_failure(1));
-        assert_eq!(manager.available_count(), 2);
+        // 过期的绑定不再列出
+        manager.affinity.lock().get_mut("b").unwrap().last_used_at -= Duration::days(1);
+        let bindings = manager.affinity_bindings();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].key, "c");
 
-        // 第三次失败会禁用第一个凭据
-        assert!(manager.report_failure(1));
-        assert_eq!(manager.available_count(), 1);
+        // 按凭据解除绑定
+        assert_eq!(
+            manager.unbind_affinity(None, Some(bindings[0].credential_id + 100)),
+            0
+        );
+        assert_eq!(
+            manager.unbind_affinity(None, Some(bindings[0].credential_id)),
+            1
+        );
+        assert!(manager.affinity_bindings().is_empty());
+        assert_eq!(manager.unbind_affinity(None, None), 0);
+    }
 
-        // 继续失败第二个凭据（使用 ID 2）
-        assert!(manager.report_failure(2));
-        assert!(manager.report_failure(2));
-        assert!(!manager.report_failure(2)); // 所有凭据都禁用了
-        assert_eq!(manager.available_count(), 0);
+    #[test]
+    fn test_gc_credential_state() {
+        let creds = (0..2)
+            .map(|_| KiroCredentials {
+                refresh_token: Some("a".repeat(120)),
+                ..Default::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(Config::default(), creds, None, None, false).unwrap();
+
+        // 凭据 #1 仍在凭据池中，#99 已不存在
+        for id in [1, 99] {
+            manager.balance_store.insert(
+                id,
+                BalanceSnapshot {
+                    subscription_title: None,
+                    current_usage: 0.0,
+                    usage_limit: 100.0,
+                    next_reset_at: None,
+                },
+            );
+            manager.daily_limit.record(0, id);
+            manager
+                .metrics
+                .wait()
+                .record(id, StdDuration::from_millis(5));
+            manager.affinity.lock().insert(
+                format!("key-{}", id),
+                AffinityBinding {
+                    credential_id: id,
+                    bound_at: Utc::now(),
+                    last_used_at: Utc::now(),
+                },
+            );
+        }
+        manager.refresh_token_warned.lock().insert(99);
+
+        assert_eq!(manager.gc_credential_state(), 5);
+        assert!(manager.balance_store.latest(1).is_some());
+        assert!(manager.balance_store.latest(99).is_none());
+        assert_eq!(manager.daily_limit.credential_requests(1), 1);
+        assert_eq!(manager.daily_limit.credential_requests(99), 0);
+        let waits = manager.metrics.wait().snapshot().credentials_ms;
+        assert_eq!(waits.keys().copied().collect::<Vec<_>>(), vec![1]);
+        let bindings = manager.affinity_bindings();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].credential_id, 1);
+        assert!(manager.refresh_token_warned.lock().is_empty());
+
+        // 没有残留时不做任何事
+        assert_eq!(manager.gc_credential_state(), 0);
     }
 
     #[test]
-    fn test_multi_token_manager_report_success() {
-        let config = Config::default();
-        let cred = KiroCredentials::default();
+    fn test_multi_token_manager_delete_and_restore_credential() {
+        let cred1 = KiroCredentials {
+            refresh_token: Some("a".repeat(120)),
+            ..Default::default()
+        };
+        let cred2 = KiroCredentials {
+            refresh_token: Some("b".repeat(120)),
+            ..Default::default()
+        };
 
-        let manager = MultiTokenManager::new(config, vec![cred], None, None, false).unwrap();
+        let manager =
+            MultiTokenManager::new(Config::default(), vec![cred1, cred2], None, None, false)
+                .unwrap();
+        manager.set_disabled(2, true).unwrap();
+        manager.delete_credential(2).unwrap();
+        assert_eq!(manager.total_count(), 1);
 
-        // 失败两次（使用 ID 1）
-        manager.report_failure(1);
-        manager.report_failure(1);
+        let archived = manager.archived_credentials();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].0.id(), 2);
 
-        // 成功后重置计数（使用 ID 1）
-        manager.report_success(1);
+        // 新凭据的 ID 避开归档中的 ID
+        assert_eq!(manager.next_credential_id(&manager.entries.lock()), 3);
 
-        // 再失败两次不会禁用
-        manager.report_failure(1);
-        manager.report_failure(1);
+        // 恢复后保持禁用，并从归档中移除
+        assert_eq!(manager.restore_credential(2).unwrap(), 2);
+        assert_eq!(manager.total_count(), 2);
         assert_eq!(manager.available_count(), 1);
+        assert!(manager.archived_credentials().is_empty());
+        assert!(manager.restore_credential(2).is_err());
     }
 
     #[test]
-    fn test_multi_token_manager_switch_to_next() {
-        let config = Config::default();
-        let mut cred1 = KiroCredentials::default();
-        cred1.refresh_token = Some("token1".to_string());
-        let mut cred2 = KiroCredentials::default();
-        cred2.refresh_token = Some("token2".to_string());
-
-        let manager =
-            MultiTokenManager::new(config, vec![cred1, cred2], None, None, false).unwrap();
-
-        // 初始是第一个凭据
-        assert_eq!(
-            manager.credentials().refresh_token,
-            Some("token1".to_string())
-        );
+    fn test_spillover_cutoff() {
+        let tier = |available, remaining| PriorityTier {
+            available,
+            remaining,
+        };
+        let tiers = BTreeMap::from([
+            (0, tier(2, Some(5.0))),
+            (1, tier(1, None)),
+            (2, tier(3, Some(100.0))),
+        ]);
+
+        // 未配置阈值或最高优先级层充足时只使用第一层
+        assert_eq!(spillover_cutoff(&tiers, 0, 0.0), None);
+        assert_eq!(spillover_cutoff(&tiers, 2, 0.0), None);
+        assert_eq!(spillover_cutoff(&tiers, 0, 5.0), None);
+
+        // 可用数量不足：逐层纳入直到累计数量达到阈值
+        assert_eq!(spillover_cutoff(&tiers, 3, 0.0), Some(1));
+        assert_eq!(spillover_cutoff(&tiers, 4, 0.0), Some(2));
+
+        // 剩余额度不足：余额未知的层不计入额度
+        assert_eq!(spillover_cutoff(&tiers, 0, 10.0), Some(2));
+
+        // 没有任何余额数据时不按额度溢出
+        let unknown = BTreeMap::from([(0, tier(1, None)), (1, tier(1, None))]);
+        assert_eq!(spillover_cutoff(&unknown, 0, 10.0), None);
+    }
 
-        // 切换到下一个
-        assert!(manager.switch_to_next());
-        assert_eq!(
-            manager.credentials().refresh_token,
-            Some("token2".to_string())
-        );
+    #[test]
+    fn test_widen_cutoff() {
+        let tiers = BTreeMap::from([
+            (0, PriorityTier::default()),
+            (1, PriorityTier::default()),
+            (5, PriorityTier::default()),
+        ]);
+        assert_eq!(widen_cutoff(&tiers, None), Some(1));
+        assert_eq!(widen_cutoff(&tiers, Some(1)), Some(5));
+        assert_eq!(widen_cutoff(&tiers, Some(5)), Some(5));
+
+        let single = BTreeMap::from([(0, PriorityTier::default())]);
+        assert_eq!(widen_cutoff(&single, None), None);
     }
 
     #[test]
-    fn test_set_load_balancing_mode_persists_to_config_file() {
-        let config_path = std::env::temp_dir().join(format!(
-            "kiro-load-balancing-{}.json",
-            uuid::Uuid::new_v4()
-        ));
-        std::fs::write(&config_path, r#"{"loadBalancingMode":"priority"}"#).unwrap();
+    fn test_multi_token_manager_burst_spillover() {
+        let mut config = Config::default();
+        config.spillover_queue_delay_ms = 100;
+        config.spillover_burst_hold_secs = 60;
+        let creds = [0, 1, 2]
+            .into_iter()
+            .map(|priority| KiroCredentials {
+                priority,
+                ..Default::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(config, creds, None, None, false).unwrap();
+
+        // 排队时间未超过阈值时只使用最高优先级层
+        manager.record_queue_delay(StdDuration::from_millis(50));
+        assert!(!manager.spillover_engaged(None));
+
+        // 突发期间纳入下一层
+        manager.record_queue_delay(StdDuration::from_millis(150));
+        assert!(manager.spillover_engaged(None));
+        let entries = manager.entries.lock();
+        let available = manager.selectable_entries(&entries, None);
+        assert_eq!(manager.spillover_cutoff(&available), Some(1));
+        drop(entries);
+
+        // 保持时间结束后恢复
+        *manager.burst_until.lock() = Some(Instant::now());
+        assert!(!manager.spillover_engaged(None));
+        assert!(manager.burst_until.lock().is_none());
+    }
 
-        let config = Config::load(&config_path).unwrap();
+    #[test]
+    fn test_multi_token_manager_refresh_token_expiry() {
+        let idc = |issued_days_ago: Option<i64>| KiroCredentials {
+            refresh_token: Some("r".repeat(150)),
+            auth_method: Some("idc".to_string()),
+            client_id: Some("cid".to_string()),
+            client_secret: Some("secret".to_string()),
+            refresh_token_issued_at: issued_days_ago
+                .map(|days| (Utc::now() - Duration::days(days)).to_rfc3339()),
+            ..Default::default()
+        };
+        let social = KiroCredentials {
+            refresh_token: Some("s".repeat(150)),
+            auth_method: Some("social".to_string()),
+            refresh_token_issued_at: Some((Utc::now() - Duration::days(89)).to_rfc3339()),
+            ..Default::default()
+        };
         let manager = MultiTokenManager::new(
-            config,
-            vec![KiroCredentials::default()],
+            Config::default(),
+            vec![idc(Some(80)), idc(None), social],
             None,
             None,
             false,
         )
         .unwrap();
 
-        manager
-            .set_load_balancing_mode("balanced".to_string())
-            .unwrap();
+        // 未记录签发时间的凭据以加载时间作为签发时间
+        let snapshot = manager.snapshot();
+        assert!(snapshot.entries[1].refresh_token_issued_at.is_some());
+        assert!(snapshot.entries[0].refresh_token_expiring);
+        assert!(!snapshot.entries[1].refresh_token_expiring);
+        // 非 IdC 凭据不估算过期时间
+        assert!(snapshot.entries[2].refresh_token_expires_at.is_none());
+
+        // 每个凭据只告警一次
+        assert_eq!(manager.check_refresh_token_expiry(), vec![1]);
+        assert!(manager.check_refresh_token_expiry().is_empty());
+    }
 
-        let persisted = Config::load(&config_path).unwrap();
-        assert_eq!(persisted.load_balancing_mode, "balanced");
-        assert_eq!(manager.get_load_balancing_mode(), "balanced");
+    #[tokio::test]
+    async fn test_multi_token_manager_standby_candidate() {
+        let creds: Vec<KiroCredentials> = [0, 1, 2]
+            .into_iter()
+            .map(|priority| KiroCredentials {
+                priority,
+                access_token: Some("t1".to_string()),
+                expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+                ..Default::default()
+            })
+            .collect();
+        let manager =
+            MultiTokenManager::new(Config::default(), creds.clone(), None, None, false).unwrap();
+        let standby = |exclude| {
+            manager
+                .standby_candidate(None, None, exclude)
+                .map(|(id, _)| id)
+        };
 
-        std::fs::remove_file(&config_path).unwrap();
+        // 备用凭据按故障转移顺序选择，且不改变当前凭据
+        assert_eq!(standby(1), Some(2));
+        assert_eq!(standby(2), Some(1));
+        manager.set_disabled(2, true).unwrap();
+        assert_eq!(standby(1), Some(3));
+        assert_eq!(*manager.current_id.lock(), 1);
+
+        // Token 有效时预热不刷新
+        manager.warm_token(3).await.unwrap();
+        assert!(manager.warm_token(99).await.is_err());
+
+        // balanced 模式：备用凭据即流中断后重试实际选中的凭据（亲和键改绑的目标）
+        let mut config = Config::default();
+        config.load_balancing_mode = "balanced".to_string();
+        let manager = MultiTokenManager::new(config, creds, None, None, false).unwrap();
+        let other = manager.acquire_context(None, Some("other")).await.unwrap();
+        let bound = manager.acquire_context(None, Some("user")).await.unwrap();
+        let (standby, _) = manager
+            .standby_candidate(None, Some("user"), bound.id)
+            .unwrap();
+        assert_ne!(standby, bound.id);
+        assert_ne!(standby, other.id);
+        let retry = manager
+            .acquire_context_excluding(None, Some("user"), Some(bound.id))
+            .await
+            .unwrap();
+        assert_eq!(retry.id, standby);
     }
 
     #[tokio::test]
-    async fn test_multi_token_manager_acquire_context_auto_recovers_all_disabled() {
-        let config = Config::default();
-        let mut cred1 = KiroCredentials::default();
-        cred1.access_token = Some("t1".to_string());
-        cred1.expires_at = Some((Utc::now() + Duration::hours(1)).to_rfc3339());
-        let mut cred2 = KiroCredentials::default();
-        cred2.access_token = Some("t2".to_string());
-        cred2.expires_at = Some((Utc::now() + Duration::hours(1)).to_rfc3339());
+    async fn test_multi_token_manager_spillover_spreads_across_tiers() {
+        let mut config = Config::default();
+        config.spillover_min_available = 2;
+        let cred1 = KiroCredentials {
+            access_token: Some("t1".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let mut cred2 = cred1.clone();
+        cred2.priority = 1;
+        let mut cred3 = cred1.clone();
+        cred3.priority = 2;
 
         let manager =
-            MultiTokenManager::new(config, vec![cred1, cred2], None, None, false).unwrap();
+            MultiTokenManager::new(config, vec![cred1, cred2, cred3], None, None, false).unwrap();
+
+        // 第一层只有 1 个凭据，低于阈值 2，纳入第二层并按成功次数分配
+        let first = manager.acquire_context(None, None).await.unwrap();
+        assert_eq!(first.id, 1);
+        manager.report_success(first.id);
+        let second = manager.acquire_context(None, None).await.unwrap();
+        assert_eq!(second.id, 2);
+        manager.report_success(second.id);
+        let third = manager.acquire_context(None, None).await.unwrap();
+        assert_eq!(third.id, 1);
+
+        // 第一层失效后第二、三层补足阈值
+        manager.set_disabled(1, true).unwrap();
+        manager.report_success(2);
+        let next = manager.acquire_context(None, None).await.unwrap();
+        assert_eq!(next.id, 3);
+    }
 
-        // 凭据会自动分配 ID（从 1 开始）
-        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
-            manager.report_failure(1);
-        }
-        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
-            manager.report_failure(2);
-        }
+    #[test]
+    fn test_multi_token_manager_spillover_unknown_balance() {
+        let mut config = Config::default();
+        config.spillover_min_balance = 10.0;
+        let creds = [0, 0, 1]
+            .into_iter()
+            .map(|priority| KiroCredentials {
+                priority,
+                ..Default::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(config, creds, None, None, false).unwrap();
+        let insert = |id, current_usage| {
+            manager.balance_store.insert(
+                id,
+                BalanceSnapshot {
+                    subscription_title: None,
+                    current_usage,
+                    usage_limit: 100.0,
+                    next_reset_at: None,
+                },
+            )
+        };
 
-        assert_eq!(manager.available_count(), 0);
+        // 第一层只有 #1 余额已知且不足，#2 尚未初始化：视为未知，不溢出
+        insert(1, 95.0);
+        assert!(!manager.spillover_engaged(None));
 
-        // 应触发自愈：重置失败计数并重新启用，避免必须重启进程
-        let ctx = manager.acquire_context(None).await.unwrap();
-        assert!(ctx.token == "t1" || ctx.token == "t2");
-        assert_eq!(manager.available_count(), 2);
+        // 第一层余额全部已知且合计不足时溢出
+        insert(2, 99.0);
+        assert!(manager.spillover_engaged(None));
     }
 
     #[test]
@@ -1951,7 +5163,12 @@ mod tests {
         manager.report_quota_exhausted(2);
         assert_eq!(manager.available_count(), 0);
 
-        let err = manager.acquire_context(None).await.err().unwrap().to_string();
+        let err = manager
+            .acquire_context(None, None)
+            .await
+            .err()
+            .unwrap()
+            .to_string();
         assert!(
             err.contains("所有凭据均已禁用"),
             "错误应提示所有凭据禁用，实际: {}",
@@ -1960,6 +5177,242 @@ mod tests {
         assert_eq!(manager.available_count(), 0);
     }
 
+    // ============ 选择公平性模拟测试 ============
+    //
+    // 确定性模拟：以请求序号作为虚拟时钟，按脚本为每次请求返回结果并上报给 MultiTokenManager，
+    // 校验选择公平性、额度冷却、熔断自愈与混合优先级下不饿死等不变量
+
+    /// 模拟请求的结果
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Outcome {
+        Success,
+        Transient,
+        AuthFailure,
+        QuotaExhausted,
+    }
+
+    struct Simulation {
+        manager: MultiTokenManager,
+        /// 虚拟时钟（已执行的请求数）
+        clock: u64,
+        /// 每个凭据被选中的次数
+        picks: HashMap<u64, u64>,
+        /// 每次请求选中的凭据（无可用凭据时为 None）
+        trace: Vec<Option<u64>>,
+    }
+
+    impl Simulation {
+        fn new(configure: impl FnOnce(&mut Config), priorities: &[u32]) -> Self {
+            let mut config = Config::default();
+            configure(&mut config);
+            let creds = priorities
+                .iter()
+                .enumerate()
+                .map(|(i, &priority)| KiroCredentials {
+                    access_token: Some(format!("t{}", i + 1)),
+                    expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+                    priority,
+                    ..Default::default()
+                })
+                .collect();
+            Self {
+                manager: MultiTokenManager::new(config, creds, None, None, false).unwrap(),
+                clock: 0,
+                picks: HashMap::new(),
+                trace: Vec::new(),
+            }
+        }
+
+        /// 执行 `steps` 次请求，`script(时钟, 凭据 ID)` 决定每次请求的结果
+        async fn run(&mut self, steps: u64, script: impl Fn(u64, u64) -> Outcome) {
+            for _ in 0..steps {
+                let picked = self.manager.acquire_context(None, None).await.ok();
+                if let Some(ctx) = &picked {
+                    *self.picks.entry(ctx.id).or_default() += 1;
+                    match script(self.clock, ctx.id) {
+                        Outcome::Success => self.manager.report_success(ctx.id),
+                        Outcome::Transient => self
+                            .manager
+                            .report_transient_failure(ctx.id, FailureKind::Upstream),
+                        Outcome::AuthFailure => {
+                            self.manager.report_failure(ctx.id);
+                        }
+                        Outcome::QuotaExhausted => {
+                            self.manager.report_quota_exhausted(ctx.id);
+                        }
+                    }
+                }
+                self.trace.push(picked.map(|ctx| ctx.id));
+                self.clock += 1;
+            }
+        }
+
+        fn picks(&self, id: u64) -> u64 {
+            self.picks.get(&id).copied().unwrap_or(0)
+        }
+
+        /// 最近 `window` 次请求中被选中过的凭据
+        fn recent(&self, window: usize) -> HashSet<u64> {
+            self.trace
+                .iter()
+                .rev()
+                .take(window)
+                .flatten()
+                .copied()
+                .collect()
+        }
+
+        /// 记录凭据的额度重置时间（Unix 时间戳）
+        fn set_quota_reset(&self, id: u64, reset_at: DateTime<Utc>) {
+            self.manager.balance_store.insert(
+                id,
+                BalanceSnapshot {
+                    subscription_title: None,
+                    current_usage: 100.0,
+                    usage_limit: 100.0,
+                    next_reset_at: Some(reset_at.timestamp() as f64),
+                },
+            );
+        }
+    }
+
+    fn balanced(config: &mut Config) {
+        config.load_balancing_mode = "balanced".to_string();
+    }
+
+    #[tokio::test]
+    async fn test_simulation_balanced_fairness() {
+        let mut sim = Simulation::new(balanced, &[0, 0, 0, 0]);
+        sim.run(400, |_, _| Outcome::Success).await;
+
+        // 全部成功时各凭据的选中次数相差不超过 1
+        let counts: Vec<u64> = (1..=4).map(|id| sim.picks(id)).collect();
+        assert_eq!(counts.iter().sum::<u64>(), 400);
+        assert!(counts.iter().max().unwrap() - counts.iter().min().unwrap() <= 1);
+
+        // 瞬态失败不会禁用凭据，恢复后各凭据的成功次数重新趋于一致
+        sim.run(20, |_, id| {
+            if id == 2 {
+                Outcome::Transient
+            } else {
+                Outcome::Success
+            }
+        })
+        .await;
+        assert_eq!(sim.manager.available_count(), 4);
+        sim.run(100, |_, _| Outcome::Success).await;
+        let entries = sim.manager.entries.lock();
+        let successes: Vec<u64> = entries.iter().map(|e| e.success_count).collect();
+        assert!(successes.iter().max().unwrap() - successes.iter().min().unwrap() <= 1);
+    }
+
+    #[tokio::test]
+    async fn test_simulation_no_starvation_with_mixed_priorities() {
+        let mut sim = Simulation::new(balanced, &[0, 1, 2, 5]);
+        // 第 100-199 个请求期间 #1 持续瞬态失败
+        sim.run(300, |clock, id| {
+            if id == 1 && (100..200).contains(&clock) {
+                Outcome::Transient
+            } else {
+                Outcome::Success
+            }
+        })
+        .await;
+
+        // 瞬态失败不会禁用凭据，也不计入成功次数：失败期间 #1 仍是成功次数最少的凭据
+        assert_eq!(sim.manager.available_count(), 4);
+        assert!(sim.trace[100..200].iter().all(|id| *id == Some(1)));
+
+        // 失败前后，混合优先级下任意 8 个连续请求内每个可用凭据至少被选中一次
+        let windows = sim.trace[..100].windows(8);
+        for window in windows.chain(sim.trace[200..].windows(8)) {
+            let seen: HashSet<u64> = window.iter().flatten().copied().collect();
+            assert_eq!(seen.len(), 4, "存在被饿死的凭据: {:?}", window);
+        }
+        let entries = sim.manager.entries.lock();
+        let successes: Vec<u64> = entries.iter().map(|e| e.success_count).collect();
+        assert!(successes.iter().max().unwrap() - successes.iter().min().unwrap() <= 1);
+    }
+
+    #[tokio::test]
+    async fn test_simulation_priority_spillover_reaches_lower_tiers() {
+        let mut sim = Simulation::new(|config| config.spillover_min_available = 3, &[0, 0, 1, 2]);
+        sim.run(120, |_, _| Outcome::Success).await;
+
+        // 第一层只有 2 个凭据，低于阈值 3，纳入第二层；第三层不被使用
+        assert!(sim.picks(1) > 0 && sim.picks(2) > 0 && sim.picks(3) > 0);
+        assert_eq!(sim.picks(4), 0);
+        assert_eq!(sim.picks(1) + sim.picks(2) + sim.picks(3), 120);
+    }
+
+    #[tokio::test]
+    async fn test_simulation_quota_cooldown() {
+        let mut sim = Simulation::new(balanced, &[0, 0, 0]);
+        let reset_at = DateTime::from_timestamp(Utc::now().timestamp() + 3600, 0).unwrap();
+        sim.set_quota_reset(2, reset_at);
+        // 第 30 个请求之后 #2 额度用尽
+        sim.run(90, |clock, id| {
+            if id == 2 && clock >= 30 {
+                Outcome::QuotaExhausted
+            } else {
+                Outcome::Success
+            }
+        })
+        .await;
+
+        // 进入冷却后不再被选中，其余凭据继续均衡分配
+        let exhausted_at = sim.trace.iter().rposition(|id| *id == Some(2)).unwrap();
+        assert!((30..34).contains(&exhausted_at));
+        assert!(!sim.recent(90 - exhausted_at - 1).contains(&2));
+        assert_eq!(sim.manager.available_count(), 2);
+        assert!(sim.picks(1).abs_diff(sim.picks(3)) <= 1);
+
+        // 冷却期限为额度重置时间（重启时已过期的冷却不再恢复）
+        let (reason, until) = sim
+            .manager
+            .entries
+            .lock()
+            .iter()
+            .find(|e| e.id == 2)
+            .map(|e| (e.disabled_reason, e.disabled_until))
+            .unwrap();
+        assert_eq!(reason, Some(DisabledReason::QuotaExceeded));
+        assert_eq!(until, Some(reset_at));
+
+        // 全部额度用尽后请求直接失败
+        sim.run(10, |_, _| Outcome::QuotaExhausted).await;
+        assert_eq!(sim.manager.available_count(), 0);
+        assert!(sim.trace.last().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_simulation_circuit_breaker_auto_heal() {
+        let mut sim = Simulation::new(|_| {}, &[0, 1]);
+        let threshold = u64::from(MAX_FAILURES_PER_CREDENTIAL);
+        // 前 2 × 阈值个请求认证失败（两个凭据依次熔断），之后恢复
+        sim.run(20, |clock, _| {
+            if clock < threshold * 2 {
+                Outcome::AuthFailure
+            } else {
+                Outcome::Success
+            }
+        })
+        .await;
+
+        // 优先级模式下先熔断 #1 再熔断 #2
+        let threshold = threshold as usize;
+        assert!(sim.trace[..threshold].iter().all(|id| *id == Some(1)));
+        assert!(
+            sim.trace[threshold..threshold * 2]
+                .iter()
+                .all(|id| *id == Some(2))
+        );
+        // 全部熔断后自愈：每个请求都拿到了凭据，恢复后回到最高优先级的凭据
+        assert!(sim.trace.iter().all(Option::is_some));
+        assert!(sim.trace[threshold * 2..].iter().all(|id| *id == Some(1)));
+        assert_eq!(sim.manager.available_count(), 2);
+    }
+
     // ============ 凭据级 Region 优先级测试 ============
 
     #[test]
@@ -2021,6 +5474,33 @@ mod tests {
         assert_eq!(cred3.effective_auth_region(&config), "ap-northeast-1");
     }
 
+    #[test]
+    fn test_idc_refresh_region_probe() {
+        let rejected = |status: u16, body: &str| IdcRefreshRejected {
+            status: reqwest::StatusCode::from_u16(status).unwrap(),
+            body: body.to_string(),
+        };
+        assert!(rejected(400, r#"{"error":"invalid_grant"}"#).suggests_wrong_region());
+        assert!(rejected(401, "InvalidClientException").suggests_wrong_region());
+        assert!(!rejected(429, "invalid_client").suggests_wrong_region());
+        assert!(!rejected(400, r#"{"error":"unsupported_grant_type"}"#).suggests_wrong_region());
+
+        let mut config = Config::default();
+        config.auth_region_probe_list = vec![
+            "us-east-1".to_string(),
+            " eu-central-1 ".to_string(),
+            "us-east-1".to_string(),
+            String::new(),
+        ];
+        assert_eq!(
+            probe_regions(&config, "us-west-2"),
+            ["us-east-1", "eu-central-1"]
+        );
+        assert_eq!(probe_regions(&config, "us-east-1"), ["eu-central-1"]);
+        config.auth_region_probe_list.clear();
+        assert!(probe_regions(&config, "us-east-1").is_empty());
+    }
+
     #[test]
     fn test_idc_oidc_endpoint_uses_credential_auth_region() {
         // 验证 IdC OIDC endpoint URL 使用凭据 auth_region
@@ -2031,7 +5511,7 @@ mod tests {
         credentials.auth_region = Some("eu-central-1".to_string());
 
         let region = credentials.effective_auth_region(&config);
-        let refresh_url = format!("https://oidc.{}.amazonaws.com/token", region);
+        let refresh_url = config.endpoints.idc_refresh_url(region);
 
         assert_eq!(refresh_url, "https://oidc.eu-central-1.amazonaws.com/token");
     }
@@ -2046,7 +5526,7 @@ mod tests {
         credentials.auth_region = Some("ap-southeast-1".to_string());
 
         let region = credentials.effective_auth_region(&config);
-        let refresh_url = format!("https://prod.{}.auth.desktop.kiro.dev/refreshToken", region);
+        let refresh_url = config.endpoints.social_refresh_url(region);
 
         assert_eq!(
             refresh_url,
@@ -1,20 +1,43 @@
 mod admin;
+#[cfg(feature = "admin-ui")]
 mod admin_ui;
 mod anthropic;
 mod common;
+mod dns;
+mod doctor;
 mod http_client;
 mod kiro;
 mod model;
+mod tenant;
 pub mod token;
+mod update;
 
 use std::sync::Arc;
 
 use clap::Parser;
+use common::tasks::BackgroundTasks;
+use kiro::event_bus;
 use kiro::model::credentials::{CredentialsConfig, KiroCredentials};
 use kiro::provider::KiroProvider;
-use kiro::token_manager::MultiTokenManager;
-use model::arg::Args;
-use model::config::Config;
+use kiro::token_manager::{
+    CredentialSource, CredentialValidation, MultiTokenManager, VALIDATION_CONCURRENCY,
+};
+use model::arg::{Args, Command};
+use model::config::{Config, CredentialsFileConfig};
+use tokio_util::sync::CancellationToken;
+
+/// 停机时等待进行中的连接结束的最长时间
+const CONNECTION_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 停机时等待后台任务退出的最长时间
+const BACKGROUND_SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// refreshToken 过期检查的间隔
+const REFRESH_TOKEN_EXPIRY_CHECK_INTERVAL: std::time::Duration =
+    std::time::Duration::from_secs(60 * 60);
+
+/// 清理已删除凭据残留状态的间隔
+const CREDENTIAL_STATE_GC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
 
 #[tokio::main]
 async fn main() {
@@ -32,30 +55,70 @@ async fn main() {
     // 加载配置
     let config_path = args
         .config
+        .clone()
         .unwrap_or_else(|| Config::default_config_path().to_string());
-    let config = Config::load(&config_path).unwrap_or_else(|e| {
+    let config = Config::load(&config_path);
+
+    // 子命令：自检（配置无效时同样输出报告）
+    if let Some(Command::Doctor) = &args.command {
+        let (config, config_error) = match config {
+            Ok(config) => (config, None),
+            Err(e) => (Config::default(), Some(format!("{:#}", e))),
+        };
+        let report = doctor::run(
+            &config,
+            Some(std::path::Path::new(&config_path)),
+            config_error.as_deref(),
+            &credential_files(&args, &config),
+            build_proxy_config(&config).as_ref(),
+        )
+        .await;
+        report.print();
+        std::process::exit(if report.has_failures() { 1 } else { 0 });
+    }
+
+    let config = config.unwrap_or_else(|e| {
         tracing::error!("加载配置失败: {}", e);
         std::process::exit(1);
     });
 
-    // 加载凭证（支持单对象或数组格式）
-    let credentials_path = args
-        .credentials
-        .unwrap_or_else(|| KiroCredentials::default_credentials_path().to_string());
-    let credentials_config = CredentialsConfig::load(&credentials_path).unwrap_or_else(|e| {
-        tracing::error!("加载凭证失败: {}", e);
-        std::process::exit(1);
-    });
+    // 初始化客户端/Admin 响应语言
+    common::i18n::init(config.language);
+
+    // 初始化时钟偏差校正
+    common::clock::init(config.clock_skew_correction, config.clock_skew_warn_secs);
+
+    // 初始化上游事件流格式
+    kiro::model::events::protocol::init(config.upstream_event_format);
+
+    // 构建代理配置
+    let proxy_config = build_proxy_config(&config);
 
-    // 判断是否为多凭据格式（用于刷新后回写）
-    let is_multiple_format = credentials_config.is_multiple();
+    if proxy_config.is_some() {
+        tracing::info!("已配置 HTTP 代理: {}", config.proxy_url.as_ref().unwrap());
+    }
+
+    // 子命令：自更新
+    if let Some(Command::SelfUpdate { check, repo }) = &args.command {
+        if let Err(e) = update::self_update(
+            repo,
+            *check,
+            proxy_config.as_ref(),
+            &http_client::TransportOptions::from_config(&config),
+        )
+        .await
+        {
+            tracing::error!("自更新失败: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    // 转换为按优先级排序的凭据列表
-    let credentials_list = credentials_config.into_sorted_credentials();
-    tracing::info!("已加载 {} 个凭据配置", credentials_list.len());
+    // 加载凭证（每个文件支持单对象或数组格式）
+    let credential_sources = load_credential_sources(credential_files(&args, &config));
 
     // 获取第一个凭据用于日志显示
-    let first_credentials = credentials_list.first().cloned().unwrap_or_default();
+    let first_credentials = primary_credentials(&credential_sources);
     tracing::debug!("主凭证: {:?}", first_credentials);
 
     // 获取 API Key
@@ -64,32 +127,34 @@ async fn main() {
         std::process::exit(1);
     });
 
-    // 构建代理配置
-    let proxy_config = config.proxy_url.as_ref().map(|url| {
-        let mut proxy = http_client::ProxyConfig::new(url);
-        if let (Some(username), Some(password)) = (&config.proxy_username, &config.proxy_password) {
-            proxy = proxy.with_auth(username, password);
-        }
-        proxy
-    });
+    // 创建 MultiTokenManager 和 KiroProvider
+    let token_manager =
+        MultiTokenManager::with_sources(config.clone(), credential_sources, proxy_config.clone())
+            .unwrap_or_else(|e| {
+                tracing::error!("创建 Token 管理器失败: {}", e);
+                std::process::exit(1);
+            });
+    let token_manager = Arc::new(token_manager);
 
-    if proxy_config.is_some() {
-        tracing::info!("已配置 HTTP 代理: {}", config.proxy_url.as_ref().unwrap());
+    // 启动校验：并发刷新所有凭据并输出结果表
+    if args.validate_credentials {
+        tracing::info!("正在校验 {} 个凭据...", token_manager.total_count());
+        let results = token_manager
+            .validate_credentials(VALIDATION_CONCURRENCY)
+            .await;
+        print_validation_table(&results);
+        if args.exit_after_validation {
+            token_manager.flush_stats();
+            let all_valid = results.iter().all(|r| r.status.error().is_none());
+            std::process::exit(if all_valid { 0 } else { 1 });
+        }
     }
 
-    // 创建 MultiTokenManager 和 KiroProvider
-    let token_manager = MultiTokenManager::new(
-        config.clone(),
-        credentials_list,
-        proxy_config.clone(),
-        Some(credentials_path.into()),
-        is_multiple_format,
-    )
-    .unwrap_or_else(|e| {
-        tracing::error!("创建 Token 管理器失败: {}", e);
-        std::process::exit(1);
-    });
-    let token_manager = Arc::new(token_manager);
+    let mut tasks = BackgroundTasks::default();
+    initialize_balances(&token_manager, &mut tasks).await;
+    spawn_maintenance_tasks(&token_manager, &mut tasks);
+    // 停机时需要最终落盘的凭据池（含各租户）
+    let mut managers = vec![token_manager.clone()];
     let kiro_provider = KiroProvider::with_proxy(token_manager.clone(), proxy_config.clone());
 
     // 初始化 count_tokens 配置
@@ -98,14 +163,27 @@ async fn main() {
         api_key: config.count_tokens_api_key.clone(),
         auth_type: config.count_tokens_auth_type.clone(),
         proxy: proxy_config,
-        tls_backend: config.tls_backend,
+        transport: http_client::TransportOptions::from_config(&config),
     });
 
+    // 构建请求准入控制器（maxConcurrentPerCredential 为 0 时不限制）
+    let admission = anthropic::AdmissionController::new(
+        config.max_concurrent_per_credential,
+        config.max_queued_requests,
+        std::time::Duration::from_secs(config.queue_timeout_secs),
+    );
+
+    // 实例生命周期状态（所有租户共享，排空模式下拒绝新请求）
+    let lifecycle = common::lifecycle::Lifecycle::default();
+
     // 构建 Anthropic API 路由（从第一个凭据获取 profile_arn）
     let anthropic_app = anthropic::create_router_with_provider(
         &api_key,
+        config.api_keys.clone(),
         Some(kiro_provider),
         first_credentials.profile_arn.clone(),
+        admission.clone(),
+        lifecycle.clone(),
     );
 
     // 构建 Admin API 路由（如果配置了非空的 admin_api_key）
@@ -116,32 +194,123 @@ async fn main() {
         .map(|k| !k.trim().is_empty())
         .unwrap_or(false);
 
-    let app = if let Some(admin_key) = &config.admin_api_key {
+    let mut app = if let Some(admin_key) = &config.admin_api_key {
         if admin_key.trim().is_empty() {
             tracing::warn!("admin_api_key 配置为空，Admin API 未启用");
             anthropic_app
         } else {
-            let admin_service = admin::AdminService::new(token_manager.clone());
+            let admin_service = admin::AdminService::new(token_manager.clone())
+                .with_runtime(lifecycle.clone(), admission);
             let admin_state = admin::AdminState::new(admin_key, admin_service);
+            let pairing_app = admin::create_pairing_router(admin_state.clone());
             let admin_app = admin::create_admin_router(admin_state);
 
-            // 创建 Admin UI 路由
-            let admin_ui_app = admin_ui::create_admin_ui_router();
-
             tracing::info!("Admin API 已启用");
-            tracing::info!("Admin UI 已启用: /admin");
-            anthropic_app
+            let app = anthropic_app
                 .nest("/api/admin", admin_app)
-                .nest("/admin", admin_ui_app)
+                .nest("/api/pair", pairing_app);
+
+            // 创建 Admin UI 路由（admin-ui 特性）
+            #[cfg(feature = "admin-ui")]
+            let app = {
+                tracing::info!("Admin UI 已启用: /admin");
+                app.nest("/admin", admin_ui::create_admin_ui_router())
+            };
+            app
         }
     } else {
         anthropic_app
     };
 
+    // 多租户：每个租户使用独立的凭据池、准入控制与 API Key，挂载在各自的路径前缀下
+    let absolute_dir =
+        |dir: Option<std::path::PathBuf>| dir.map(|dir| std::path::absolute(&dir).unwrap_or(dir));
+    let mut cache_dirs = vec![absolute_dir(token_manager.cache_dir())];
+    for tenant in &config.tenants {
+        let tenant_config = config.for_tenant(tenant).unwrap_or_else(|e| {
+            tracing::error!("{:#}", e);
+            std::process::exit(1);
+        });
+        let tenant_proxy = build_proxy_config(&tenant_config);
+        let tenant_sources = load_credential_sources(tenant.credentials_files.clone());
+        let profile_arn = primary_credentials(&tenant_sources).profile_arn;
+        let tenant_manager = MultiTokenManager::with_sources(
+            tenant_config.clone(),
+            tenant_sources,
+            tenant_proxy.clone(),
+        )
+        .unwrap_or_else(|e| {
+            tracing::error!("创建租户 {} 的 Token 管理器失败: {}", tenant.name, e);
+            std::process::exit(1);
+        });
+        let tenant_manager = Arc::new(tenant_manager);
+
+        // 统计、余额与归档文件保存在凭据文件所在目录，租户之间不能共用
+        let cache_dir = absolute_dir(tenant_manager.cache_dir());
+        if cache_dirs.contains(&cache_dir) {
+            tracing::error!(
+                "租户 {} 的凭据文件目录与其他凭据池相同，请为每个租户使用独立目录",
+                tenant.name
+            );
+            std::process::exit(1);
+        }
+        cache_dirs.push(cache_dir);
+
+        initialize_balances(&tenant_manager, &mut tasks).await;
+        spawn_maintenance_tasks(&tenant_manager, &mut tasks);
+        managers.push(tenant_manager.clone());
+        let tenant_provider = KiroProvider::with_proxy(tenant_manager, tenant_proxy);
+        let tenant_admission = anthropic::AdmissionController::new(
+            tenant_config.max_concurrent_per_credential,
+            tenant_config.max_queued_requests,
+            std::time::Duration::from_secs(tenant_config.queue_timeout_secs),
+        );
+        let tenant_app = anthropic::create_router_with_provider(
+            &tenant.api_key,
+            tenant.api_keys.clone(),
+            Some(tenant_provider),
+            profile_arn,
+            tenant_admission,
+            lifecycle.clone(),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::<str>::from(tenant.name.as_str()),
+            tenant::tenant_span_middleware,
+        ));
+
+        let prefix = tenant::route_prefix(tenant);
+        tracing::info!(
+            "租户 {} 已启用: {}{}",
+            tenant.name,
+            prefix,
+            if tenant.hosts.is_empty() {
+                String::new()
+            } else {
+                format!("（Host: {}）", tenant.hosts.join(", "))
+            }
+        );
+        app = app.nest(&prefix, tenant_app);
+    }
+
+    // 按 Host 选择租户：在路由前改写路径，因此包在整个路由外层
+    let host_routing = tenant::HostRouting::new(&config.tenants);
+    if !host_routing.is_empty() {
+        app =
+            axum::Router::new()
+                .fallback_service(app)
+                .layer(axum::middleware::from_fn_with_state(
+                    host_routing,
+                    tenant::host_routing_middleware,
+                ));
+    }
+
     // 启动服务器
     let addr = format!("{}:{}", config.host, config.port);
     tracing::info!("启动 Anthropic API 端点: {}", addr);
     tracing::info!("API Key: {}***", &api_key[..(api_key.len() / 2)]);
+    if !config.api_keys.is_empty() {
+        tracing::info!("额外客户端 API Key: {} 个", config.api_keys.len());
+    }
     tracing::info!("可用 API:");
     tracing::info!("  GET  /v1/models");
     tracing::info!("  POST /v1/messages");
@@ -149,6 +318,7 @@ async fn main() {
     if admin_key_valid {
         tracing::info!("Admin API:");
         tracing::info!("  GET  /api/admin/credentials");
+        tracing::info!("  POST /api/admin/credentials/validate");
         tracing::info!("  POST /api/admin/credentials/:index/disabled");
         tracing::info!("  POST /api/admin/credentials/:index/priority");
         tracing::info!("  POST /api/admin/credentials/:index/reset");
@@ -158,5 +328,276 @@ async fn main() {
     }
 
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // 携带连接对端地址，用于识别客户端 IP（见 trustedProxies）
+    let shutdown = CancellationToken::new();
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .with_graceful_shutdown({
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown_signal().await;
+            shutdown.cancel();
+        }
+    });
+    // 管理面板事件推送等长连接不会自行结束，等待超时后不再等待
+    tokio::select! {
+        result = async { server.await } => result.unwrap(),
+        _ = async {
+            shutdown.cancelled().await;
+            tokio::time::sleep(CONNECTION_DRAIN_TIMEOUT).await;
+        } => tracing::warn!("等待进行中的连接结束超时，强制停止"),
+    }
+
+    // 先停止后台任务，再统一落盘一次，避免与后台任务同时写入
+    tasks.shutdown(BACKGROUND_SHUTDOWN_TIMEOUT).await;
+    for manager in &managers {
+        manager.flush_stats();
+    }
+    tracing::info!("已停止");
+}
+
+/// 根据配置构建上游代理
+fn build_proxy_config(config: &Config) -> Option<http_client::ProxyConfig> {
+    config.proxy_url.as_ref().map(|url| {
+        let mut proxy = http_client::ProxyConfig::new(url);
+        if let (Some(username), Some(password)) = (&config.proxy_username, &config.proxy_password) {
+            proxy = proxy.with_auth(username, password);
+        }
+        proxy
+    })
+}
+
+/// 确定凭据文件：命令行 > config.json 的 credentialsFiles > 默认 credentials.json
+fn credential_files(args: &Args, config: &Config) -> Vec<CredentialsFileConfig> {
+    if !args.credentials.is_empty() || !args.credentials_read_only.is_empty() {
+        args.credentials
+            .iter()
+            .map(|p| (p, false))
+            .chain(args.credentials_read_only.iter().map(|p| (p, true)))
+            .map(|(path, read_only)| CredentialsFileConfig {
+                path: path.clone(),
+                read_only,
+                daily_request_limit: 0,
+            })
+            .collect()
+    } else if !config.credentials_files.is_empty() {
+        config.credentials_files.clone()
+    } else {
+        vec![CredentialsFileConfig {
+            path: KiroCredentials::default_credentials_path().to_string(),
+            read_only: false,
+            daily_request_limit: 0,
+        }]
+    }
+}
+
+/// 加载凭据文件（每个文件支持单对象或数组格式），失败时退出进程
+fn load_credential_sources(
+    files: Vec<CredentialsFileConfig>,
+) -> Vec<(CredentialSource, Vec<KiroCredentials>)> {
+    let mut credential_sources = Vec::with_capacity(files.len());
+    for file in files {
+        let CredentialsFileConfig {
+            path,
+            read_only,
+            daily_request_limit,
+        } = file;
+        let credentials_config = CredentialsConfig::load(&path).unwrap_or_else(|e| {
+            tracing::error!("加载凭证失败 {}: {}", path, e);
+            std::process::exit(1);
+        });
+
+        // 判断是否为多凭据格式（用于刷新后回写）
+        let is_multiple_format = credentials_config.is_multiple();
+
+        // 转换为按优先级排序的凭据列表
+        let credentials_list = credentials_config.into_sorted_credentials();
+        tracing::info!(
+            "已从 {} 加载 {} 个凭据配置{}",
+            path,
+            credentials_list.len(),
+            if read_only { "（只读）" } else { "" }
+        );
+
+        let source = CredentialSource {
+            path: Some(path.into()),
+            is_multiple_format,
+            read_only,
+            daily_request_limit,
+        };
+        credential_sources.push((source, credentials_list));
+    }
+    credential_sources
+}
+
+/// 凭据池中的第一个凭据
+fn primary_credentials(sources: &[(CredentialSource, Vec<KiroCredentials>)]) -> KiroCredentials {
+    sources
+        .iter()
+        .flat_map(|(_, creds)| creds.first())
+        .next()
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// 预热凭据余额：延迟启动时在后台进行，否则等待完成后再开始服务
+async fn initialize_balances(token_manager: &Arc<MultiTokenManager>, tasks: &mut BackgroundTasks) {
+    if token_manager.config().lazy_startup {
+        let token_manager = token_manager.clone();
+        tasks.spawn("balance-init", move |cancel| async move {
+            tokio::select! {
+                _ = cancel.cancelled() => {}
+                _ = token_manager.initialize_balances() => {}
+            }
+        });
+    } else {
+        token_manager.initialize_balances().await;
+    }
+}
+
+/// 启动凭据池的后台维护任务
+fn spawn_maintenance_tasks(token_manager: &Arc<MultiTokenManager>, tasks: &mut BackgroundTasks) {
+    // 审计日志：订阅内部事件总线记录凭据状态变化与请求结果
+    let receiver = token_manager.subscribe_events();
+    tasks.spawn("audit-log", move |cancel| {
+        event_bus::run_audit_logger(receiver, cancel)
+    });
+
+    // 定期快照统计数据与累计指标，避免进程被直接终止时丢失
+    let config = token_manager.config();
+    let jitter = config.background_jitter_percent;
+    {
+        let token_manager = token_manager.clone();
+        tasks.spawn_interval(
+            "flush-stats",
+            std::time::Duration::from_secs(config.stats_flush_interval_secs),
+            jitter,
+            false,
+            move || token_manager.flush_stats(),
+        );
+    }
+
+    // 定期检测凭据文件是否被其他程序修改（如 Kiro IDE 导出脚本），按 ID 合并
+    {
+        let token_manager = token_manager.clone();
+        tasks.spawn_interval(
+            "sync-credentials",
+            std::time::Duration::from_secs(config.credentials_sync_interval_secs),
+            jitter,
+            false,
+            move || token_manager.sync_external_changes(),
+        );
+    }
+
+    // 定期清理已不在凭据池中的凭据残留的状态（如外部修改凭据文件移除的凭据）
+    {
+        let token_manager = token_manager.clone();
+        tasks.spawn_interval(
+            "credential-state-gc",
+            CREDENTIAL_STATE_GC_INTERVAL,
+            jitter,
+            true,
+            move || {
+                token_manager.gc_credential_state();
+            },
+        );
+    }
+
+    // 定期检查 refreshToken 的预计过期时间，提前提醒重新登录
+    if config.refresh_token_lifetime_days > 0 {
+        let token_manager = token_manager.clone();
+        tasks.spawn_interval(
+            "refresh-token-expiry",
+            REFRESH_TOKEN_EXPIRY_CHECK_INTERVAL,
+            jitter,
+            false,
+            move || {
+                token_manager.check_refresh_token_expiry();
+            },
+        );
+    }
+
+    // 定期分析凭据池，记录再平衡建议（开启 rebalanceAutopilot 时自动调整优先级）
+    // 跳过立即触发的第一次，积累一个完整窗口的流量后再分析
+    if config.rebalance_interval_secs > 0 {
+        let token_manager = token_manager.clone();
+        tasks.spawn_interval(
+            "rebalance",
+            std::time::Duration::from_secs(config.rebalance_interval_secs),
+            jitter,
+            true,
+            move || {
+                token_manager.analyze_rebalance();
+            },
+        );
+    }
+}
+
+/// 等待停机信号（Ctrl+C 或 SIGTERM）
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("监听 Ctrl+C 失败: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("监听 SIGTERM 失败: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!("收到停机信号，停止接收新连接并等待进行中的请求完成");
+}
+
+/// 输出凭据校验结果表
+fn print_validation_table(results: &[CredentialValidation]) {
+    println!(
+        "{:<6} {:<32} {:<10} {:<10} 详情",
+        "ID", "邮箱", "认证方式", "状态"
+    );
+    for r in results {
+        // 错误信息只取第一行，保持表格整齐
+        let detail = match r.status.error() {
+            Some(e) => e.lines().next().unwrap_or_default().to_string(),
+            None => format!("expiresAt: {}", r.expires_at.as_deref().unwrap_or("-")),
+        };
+        println!(
+            "{:<6} {:<32} {:<10} {:<10} {}",
+            r.id,
+            r.email.as_deref().unwrap_or("-"),
+            r.auth_method.as_deref().unwrap_or("-"),
+            r.status.as_str(),
+            detail
+        );
+    }
+
+    let count = |status: &str| {
+        results
+            .iter()
+            .filter(|r| r.status.as_str() == status)
+            .count()
+    };
+    println!(
+        "共 {} 个凭据：有效 {}，无效 {}，已截断 {}",
+        results.len(),
+        count("valid"),
+        count("invalid"),
+        count("truncated")
+    );
 }
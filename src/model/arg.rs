@@ -1,14 +1,66 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+
+use crate::update::DEFAULT_UPDATE_REPO;
+
+/// `--version` 输出：版本号、编译目标与可用的 TLS 后端
+#[cfg(feature = "native-tls")]
+const VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("KIRO_BUILD_TARGET"),
+    ", tls: rustls, native-tls)"
+);
+#[cfg(not(feature = "native-tls"))]
+const VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("KIRO_BUILD_TARGET"),
+    ", tls: rustls)"
+);
 
 /// Anthropic <-> Kiro API 客户端
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version = VERSION, about, long_about = None)]
 pub struct Args {
     /// 配置文件路径
     #[arg(short, long)]
     pub config: Option<String>,
 
-    /// 凭证文件路径
+    /// 凭证文件路径（可重复指定，多个文件合并为同一个凭据池）
+    #[arg(long)]
+    pub credentials: Vec<String>,
+
+    /// 只读凭证文件路径（可重复指定，不回写）
+    #[arg(long)]
+    pub credentials_read_only: Vec<String>,
+
+    /// 启动时并发刷新所有凭据的 Token，输出有效/无效/已截断的校验结果表
     #[arg(long)]
-    pub credentials: Option<String>,
+    pub validate_credentials: bool,
+
+    /// 校验完成后直接退出（存在无效凭据时退出码为 1）
+    #[arg(long, requires = "validate_credentials")]
+    pub exit_after_validation: bool,
+
+    /// 子命令（不指定时启动服务）
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// 子命令
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 从 GitHub Releases 下载最新版本，校验 SHA256 后替换当前可执行文件
+    SelfUpdate {
+        /// 仅检查是否有新版本，不下载安装
+        #[arg(long)]
+        check: bool,
+
+        /// Release 所在的 GitHub 仓库（owner/repo）
+        #[arg(long, default_value = DEFAULT_UPDATE_REPO)]
+        repo: String,
+    },
+
+    /// 自检：检查配置、凭据文件权限与符号链接、时钟偏差、上游 DNS/TLS 与代理连通性（存在失败项时退出码为 1）
+    Doctor,
 }
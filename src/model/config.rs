@@ -1,8 +1,13 @@
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 
+use crate::anthropic::TrustedProxies;
+use crate::kiro::daily_limit::DailyResetZone;
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub enum TlsBackend {
@@ -16,6 +21,134 @@ impl Default for TlsBackend {
     }
 }
 
+/// 客户端错误信息与 Admin API 响应使用的语言
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    Zh,
+    En,
+}
+
+/// 上游事件流 payload 格式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamEventFormat {
+    /// 按每个事件的 payload 自动识别
+    #[default]
+    Auto,
+    /// 字段直接位于 payload 顶层（如 `{"content":"..."}`）
+    Flat,
+    /// 字段包裹在与事件类型同名的键下（如 `{"assistantResponseEvent":{"content":"..."}}`）
+    Wrapped,
+}
+
+/// 配置预设：为重试、限流与后台任务等设置适合场景的默认值
+///
+/// 预设只填充配置文件中未显式设置的字段，显式设置的字段与 API Key 级设置（`apiKeys[].maxRetries` 等）优先
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConfigProfile {
+    /// 个人使用：少量凭据、单一客户端，延迟启动并降低后台任务频率
+    SingleUser,
+    /// 团队共享：均衡分配凭据，限制单个 Key 与单个 IP 的用量
+    Team,
+    /// 高吞吐：大凭据池，提高并发与队列长度，减少重试并分散后台任务
+    HighThroughput,
+}
+
+impl ConfigProfile {
+    /// 预设的字段取值（字段名同 config.json）
+    fn preset(self) -> serde_json::Value {
+        match self {
+            Self::SingleUser => serde_json::json!({
+                "loadBalancingMode": "priority",
+                "maxRetries": 3,
+                "maxQueuedRequests": 16,
+                "lazyStartup": true,
+                "balanceInitConcurrency": 1,
+                "statsFlushIntervalSecs": 300,
+            }),
+            Self::Team => serde_json::json!({
+                "loadBalancingMode": "balanced",
+                "maxRetries": 4,
+                "maxConcurrentStreamsPerKey": 8,
+                "perIpRequestsPerMinute": 120,
+                "rebalanceIntervalSecs": 3600,
+            }),
+            Self::HighThroughput => serde_json::json!({
+                "loadBalancingMode": "balanced",
+                "maxRetries": 2,
+                "maxConcurrentPerCredential": 4,
+                "maxQueuedRequests": 256,
+                "queueTimeoutSecs": 30,
+                "lazyStartup": true,
+                "balanceInitConcurrency": 8,
+                "statsFlushIntervalSecs": 120,
+                "credentialsSyncIntervalSecs": 30,
+                "backgroundJitterPercent": 20,
+                "rebalanceIntervalSecs": 900,
+            }),
+        }
+    }
+
+    /// 将预设填入配置中未显式设置的字段
+    fn apply(self, value: &mut serde_json::Value) {
+        let (Some(object), serde_json::Value::Object(preset)) =
+            (value.as_object_mut(), self.preset())
+        else {
+            return;
+        };
+        for (key, preset_value) in preset {
+            object.entry(key).or_insert(preset_value);
+        }
+    }
+}
+
+/// system 数组中多个文本块的发送方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SystemBlockMode {
+    /// 按 `systemBlockSeparator` 拼接为一条消息
+    #[default]
+    Merge,
+    /// 每个文本块作为独立的一轮历史消息，保留块边界
+    Separate,
+}
+
+/// 系统提示的发送方式（Kiro API 没有独立的 system 字段）
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SystemPromptMode {
+    /// 作为历史开头的一轮 user + assistant（"I will follow these instructions."）
+    #[default]
+    Pair,
+    /// 拼接到第一条 user 消息开头，不插入伪造的 assistant 回复
+    Prepend,
+}
+
+/// 结尾连续 user 消息（上一条 assistant 之后、最后一条之前）的处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingUserMode {
+    /// 放入历史并自动配对一条 "OK" assistant 回复
+    #[default]
+    Pair,
+    /// 与最后一条消息合并为 current_message，不插入伪造的 assistant 回复
+    Merge,
+}
+
+/// 流式响应缓冲达到高水位（客户端读取过慢）时的处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackpressurePolicy {
+    /// 暂停读取上游，待客户端读取后继续
+    #[default]
+    Pause,
+    /// 断开客户端连接并停止读取上游
+    Disconnect,
+}
+
 /// KNA 应用配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -26,6 +159,10 @@ pub struct Config {
     #[serde(default = "default_port")]
     pub port: u16,
 
+    /// 配置预设（single-user / team / high-throughput），只填充未显式设置的字段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<ConfigProfile>,
+
     #[serde(default = "default_region")]
     pub region: String,
 
@@ -39,6 +176,18 @@ pub struct Config {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub api_region: Option<String>,
 
+    /// IdC 凭据查询额度时按凭据自身的区域路由（关闭时与 API 请求使用相同的区域）
+    #[serde(default = "default_usage_limits_credential_region")]
+    pub usage_limits_credential_region: bool,
+
+    /// IdC Token 刷新因区域错误失败时依次尝试的区域（空列表表示不自动探测）
+    #[serde(default = "default_auth_region_probe_list")]
+    pub auth_region_probe_list: Vec<String>,
+
+    /// 上游端点地址模板覆盖（用于 mock、预发环境或上游地址变更）
+    #[serde(default, skip_serializing_if = "EndpointsConfig::is_empty")]
+    pub endpoints: EndpointsConfig,
+
     #[serde(default = "default_kiro_version")]
     pub kiro_version: String,
 
@@ -48,6 +197,10 @@ pub struct Config {
     #[serde(default)]
     pub api_key: Option<String>,
 
+    /// 额外的客户端 API Key（可选，每个 Key 单独统计用量，主 apiKey 统计为 "default"）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<ClientApiKeyConfig>,
+
     #[serde(default = "default_system_version")]
     pub system_version: String,
 
@@ -57,6 +210,30 @@ pub struct Config {
     #[serde(default = "default_tls_backend")]
     pub tls_backend: TlsBackend,
 
+    /// 是否允许 HTTP/2（false 时只使用 HTTP/1.1，仅对 rustls 后端有影响）
+    #[serde(default = "default_http2")]
+    pub http2: bool,
+
+    /// 空闲连接保留时间（秒，0 表示不过期）
+    #[serde(default = "default_pool_idle_timeout_secs")]
+    pub pool_idle_timeout_secs: u64,
+
+    /// 每个主机保留的最大空闲连接数（可选，不设置表示不限制，0 表示不复用连接）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// 是否在 IPv6/IPv4 之间并行尝试连接（false 时只使用 IPv4）
+    #[serde(default = "default_happy_eyeballs")]
+    pub happy_eyeballs: bool,
+
+    /// 静态域名映射：域名 -> IP 列表（跳过 DNS 解析）
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub host_overrides: BTreeMap<String, Vec<IpAddr>>,
+
+    /// DNS-over-HTTPS 服务地址（可选，JSON 格式，如 https://1.1.1.1/dns-query）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doh_url: Option<String>,
+
     /// 外部 count_tokens API 地址（可选）
     #[serde(default)]
     pub count_tokens_api_url: Option<String>,
@@ -90,11 +267,569 @@ pub struct Config {
     #[serde(default = "default_load_balancing_mode")]
     pub load_balancing_mode: String,
 
+    /// 亲和绑定的空闲过期时间（秒，0 表示不过期）
+    #[serde(default = "default_affinity_ttl_secs")]
+    pub affinity_ttl_secs: u64,
+
+    /// 亲和绑定的最大数量（0 表示不限制），超出时淘汰最久未使用的绑定
+    #[serde(default = "default_affinity_max_entries")]
+    pub affinity_max_entries: usize,
+
+    /// 每个可用凭据允许的并发请求数（0 表示不限制，不启用排队）
+    #[serde(default)]
+    pub max_concurrent_per_credential: usize,
+
+    /// 每个 API Key 允许的并发流式请求数（0 表示不限制，可被 API Key 的 maxConcurrentStreams 覆盖）
+    #[serde(default)]
+    pub max_concurrent_streams_per_key: usize,
+
+    /// 并发已满时的等待队列长度
+    #[serde(default = "default_max_queued_requests")]
+    pub max_queued_requests: usize,
+
+    /// 排队等待的超时时间（秒）
+    #[serde(default = "default_queue_timeout_secs")]
+    pub queue_timeout_secs: u64,
+
+    /// 可信反向代理的 IP 或 CIDR，来自这些地址的请求按 X-Forwarded-For / X-Real-IP 识别客户端 IP
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_proxies: Vec<String>,
+
+    /// 每个客户端 IP 每分钟允许的请求数（0 表示不限制）
+    #[serde(default)]
+    pub per_ip_requests_per_minute: u64,
+
+    /// 请求没有 metadata.user_id 时以客户端 IP 作为凭据亲和键
+    #[serde(default)]
+    pub client_ip_affinity: bool,
+
+    /// 客户端错误信息与 Admin API 响应的语言（"zh" 或 "en"）
+    #[serde(default)]
+    pub language: Language,
+
+    /// 凭据文件列表（可选，多个文件合并为同一个凭据池）
+    /// 命令行指定 --credentials 时以命令行为准；均未指定时使用 credentials.json
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub credentials_files: Vec<CredentialsFileConfig>,
+
+    /// 全局每日请求上限（0 表示不限制），达到后返回 overloaded_error 直到次日
+    #[serde(default)]
+    pub daily_request_limit: u64,
+
+    /// 每日请求计数的清零时区：`local`（本地时区）、`utc` 或固定偏移（如 `+08:00`）
+    #[serde(default = "default_daily_reset_timezone")]
+    pub daily_reset_timezone: String,
+
+    /// 严格转换模式：转换需要修正请求内容（丢弃孤立 tool_result、补充工具定义等）时
+    /// 返回 400 并列出所有修正，而不是静默修正（可被请求头 x-kiro-strict 覆盖）
+    #[serde(default)]
+    pub strict_conversion: bool,
+
+    /// system 数组中多个文本块的发送方式（merge | separate）
+    #[serde(default)]
+    pub system_block_mode: SystemBlockMode,
+
+    /// merge 模式下拼接 system 文本块使用的分隔符
+    #[serde(default = "default_system_block_separator")]
+    pub system_block_separator: String,
+
+    /// 是否去掉 system 数组中内容重复的文本块（如 Claude Code 重复发送的固定提示）
+    #[serde(default)]
+    pub dedupe_system_blocks: bool,
+
+    /// 系统提示的发送方式（pair | prepend）
+    #[serde(default)]
+    pub system_prompt_mode: SystemPromptMode,
+
+    /// 结尾连续 user 消息的处理方式（pair | merge）
+    #[serde(default)]
+    pub trailing_user_mode: TrailingUserMode,
+
+    /// systemPromptMode = pair 时与系统提示配对的 assistant 回复
+    #[serde(default = "default_system_ack_text")]
+    pub system_ack_text: String,
+
+    /// 与没有 assistant 回复的 user 消息自动配对的 assistant 回复
+    #[serde(default = "default_paired_assistant_text")]
+    pub paired_assistant_text: String,
+
+    /// 只有 tool_use 没有文本的 assistant 消息使用的占位文本（Kiro API 要求 content 非空）
+    #[serde(default = "default_empty_content_placeholder")]
+    pub empty_content_placeholder: String,
+
+    /// 单个请求的图片数量上限（0 表示不限制）
+    #[serde(default = "default_max_images_per_request")]
+    pub max_images_per_request: usize,
+
+    /// 单张图片的字节上限（0 表示不限制）
+    #[serde(default = "default_max_image_bytes")]
+    pub max_image_bytes: usize,
+
+    /// 图片宽高的像素上限（0 表示不限制）
+    #[serde(default = "default_max_image_dimension")]
+    pub max_image_dimension: u32,
+
+    /// 历史消息中最多保留的图片数量（较早的图片被丢弃，未配置时全部保留）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_history_images: Option<usize>,
+
+    /// 会话（conversationId）累计输入与输出 token 的预算，超过后较早的历史被压缩为摘要（0 表示不启用）
+    #[serde(default)]
+    pub conversation_token_budget: u64,
+
+    /// 历史摘要压缩时至少保留的最近历史消息数
+    #[serde(default = "default_compaction_keep_messages")]
+    pub compaction_keep_messages: usize,
+
+    /// 请求头 x-kiro-session 会话映射的过期时间（秒，0 表示不启用会话映射）
+    #[serde(default = "default_session_ttl_secs")]
+    pub session_ttl_secs: u64,
+
+    /// 请求头 Idempotency-Key 保存非流式响应的时间（秒，0 表示不启用）
+    #[serde(default = "default_idempotency_ttl_secs")]
+    pub idempotency_ttl_secs: u64,
+
+    /// 幂等响应保存的响应体总大小上限（字节），超出时淘汰最早的响应
+    #[serde(default = "default_idempotency_max_bytes")]
+    pub idempotency_max_bytes: usize,
+
+    /// 上游 API 请求的整体超时（秒），可被单个 API Key 或请求头覆盖
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+
+    /// 上游 API 建立连接的超时（秒，0 表示不单独限制）
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+
+    /// 上游 API 响应两次读取之间的最长间隔（秒，0 表示不限制）
+    #[serde(default)]
+    pub read_timeout_secs: u64,
+
+    /// 流式响应等待客户端读取的缓冲字节上限（高水位，0 表示不限制）
+    #[serde(default = "default_stream_high_water_bytes")]
+    pub stream_high_water_bytes: usize,
+
+    /// 流式响应缓冲达到高水位时的处理方式（pause | disconnect）
+    #[serde(default)]
+    pub stream_backpressure_policy: BackpressurePolicy,
+
+    /// 非流式响应聚合内容的字节上限，超过后截断并以 max_tokens 结束
+    #[serde(default = "default_max_non_stream_response_bytes")]
+    pub max_non_stream_response_bytes: usize,
+
+    /// 非流式请求的保活间隔（秒）：上游超过该时间未完成时先返回 200，之后周期性发送空白字符（0 表示关闭）
+    #[serde(default)]
+    pub non_stream_keep_alive_secs: u64,
+
+    /// 启用非流式保活的最小 max_tokens（低于该值的请求直接等待完整响应）
+    #[serde(default = "default_non_stream_keep_alive_min_tokens")]
+    pub non_stream_keep_alive_min_tokens: u32,
+
+    /// 上游请求体大小的参考上限（字节），用于 `x-kiro-request-*` 响应头中的占用百分比（0 表示不返回）
+    #[serde(default = "default_upstream_request_limit_bytes")]
+    pub upstream_request_limit_bytes: usize,
+
+    /// 高频使用凭据的余额缓存 TTL（秒）
+    #[serde(default = "default_balance_ttl_high_freq_secs")]
+    pub balance_ttl_high_freq_secs: u64,
+
+    /// 低频使用凭据的余额缓存 TTL（秒）
+    #[serde(default = "default_balance_ttl_low_freq_secs")]
+    pub balance_ttl_low_freq_secs: u64,
+
+    /// 低余额凭据的余额缓存 TTL（秒），缓存不会跨越额度重置时间
+    #[serde(default = "default_balance_ttl_low_balance_secs")]
+    pub balance_ttl_low_balance_secs: u64,
+
+    /// 剩余额度低于该值时视为低余额
+    #[serde(default = "default_low_balance_threshold")]
+    pub low_balance_threshold: f64,
+
+    /// priority 模式下，已纳入的优先级层可用凭据数低于该值时纳入下一层（0 表示不启用）
+    #[serde(default)]
+    pub spillover_min_available: usize,
+
+    /// priority 模式下，已纳入的优先级层剩余额度合计低于该值时纳入下一层（0 表示不启用）
+    #[serde(default)]
+    pub spillover_min_balance: f64,
+
+    /// priority 模式下，请求在准入队列中的等待时间超过该值（毫秒）时视为突发流量，
+    /// 临时多纳入一个优先级层（0 表示不启用）
+    #[serde(default)]
+    pub spillover_queue_delay_ms: u64,
+
+    /// 突发流量溢出的保持时间（秒）：该时间内没有再出现超过阈值的排队时恢复
+    #[serde(default = "default_spillover_burst_hold_secs")]
+    pub spillover_burst_hold_secs: u64,
+
+    /// 流式请求取得凭据后，在后台预热故障转移时的备用凭据（实验性）：
+    /// 同一备用凭据每隔该时间（秒）最多预热一次（0 表示不启用）
+    #[serde(default)]
+    pub warm_standby_secs: u64,
+
+    /// 预热备用凭据时同时建立到上游的 TLS 连接（仅在启用 warmStandbySecs 时生效）
+    #[serde(default)]
+    pub warm_standby_preconnect: bool,
+
+    /// 在该时间窗口（秒）内被使用过的凭据视为高频使用
+    #[serde(default = "default_high_freq_window_secs")]
+    pub high_freq_window_secs: u64,
+
+    /// 已删除凭据的保留天数，期间可通过 Admin API 恢复（0 表示删除即永久删除）
+    #[serde(default = "default_archive_retention_days")]
+    pub archive_retention_days: u64,
+
+    /// 凭据池再平衡分析间隔（秒，0 表示不启用），建议记录到日志并通过 Admin API 展示
+    #[serde(default)]
+    pub rebalance_interval_secs: u64,
+
+    /// 是否自动应用再平衡建议（调整凭据优先级）
+    #[serde(default)]
+    pub rebalance_autopilot: bool,
+
+    /// 统计数据与累计指标定期落盘的间隔（秒）
+    #[serde(default = "default_stats_flush_interval_secs")]
+    pub stats_flush_interval_secs: u64,
+
+    /// 检测凭据文件外部修改的间隔（秒）
+    #[serde(default = "default_credentials_sync_interval_secs")]
+    pub credentials_sync_interval_secs: u64,
+
+    /// 后台定时任务间隔的随机抖动百分比（0-100，0 表示固定间隔）
+    #[serde(default = "default_background_jitter_percent")]
+    pub background_jitter_percent: u8,
+
+    /// 新添加（含批量导入与配对导入）凭据的金丝雀流量百分比（0 表示直接参与常规轮换）
+    #[serde(default)]
+    pub new_credential_canary_percent: u8,
+
+    /// refreshToken 哈希的盐（用于重复检测与 Admin API 中的账号匹配，多个实例使用相同的盐时哈希可互相比对）
+    #[serde(default = "default_refresh_token_hash_salt")]
+    pub refresh_token_hash_salt: String,
+
+    /// IdC refreshToken 的有效期（天），用于按签发时间估算过期时间（0 表示不估算）
+    #[serde(default = "default_refresh_token_lifetime_days")]
+    pub refresh_token_lifetime_days: u64,
+
+    /// refreshToken 预计在该天数内过期时提前告警，提醒重新登录
+    #[serde(default = "default_refresh_token_warn_days")]
+    pub refresh_token_warn_days: u64,
+
+    /// 以 `x-kiro-upstream-*` 名称透传给客户端的上游响应头白名单（如请求 ID、AWS 追踪 ID）
+    #[serde(default = "default_upstream_response_headers")]
+    pub upstream_response_headers: Vec<String>,
+
+    /// 上游事件流 payload 格式（auto 自动识别，flat / wrapped 固定格式，与实际不符时记录警告）
+    #[serde(default)]
+    pub upstream_event_format: UpstreamEventFormat,
+
+    /// 是否按上游响应的 Date 头校正本机时钟偏差（用于 Token 过期判断）
+    #[serde(default = "default_clock_skew_correction")]
+    pub clock_skew_correction: bool,
+
+    /// 本机时钟与上游的偏差超过该秒数时记录警告
+    #[serde(default = "default_clock_skew_warn_secs")]
+    pub clock_skew_warn_secs: u64,
+
+    /// 单次请求的上游重试次数上限（不含首次尝试），API Key 配置与请求头 x-kiro-max-retries 只能在此基础上缩小
+    #[serde(default = "default_max_retries")]
+    pub max_retries: usize,
+
+    /// 日志中记录的（脱敏后）请求体最大字节数
+    #[serde(default = "default_log_body_max_bytes")]
+    pub log_body_max_bytes: usize,
+
+    /// 上游返回 400/5xx 时写入结构化调试转储的目录（可选，包含用户内容，仅用于排查）
+    #[serde(default)]
+    pub debug_dump_dir: Option<String>,
+
+    /// 调试转储目录的总大小上限（字节，0 表示不限），超出时从最旧的转储开始删除
+    #[serde(default = "default_debug_dump_max_bytes")]
+    pub debug_dump_max_bytes: u64,
+
+    /// 调试转储的保留时长（小时，0 表示不按时间删除）
+    #[serde(default = "default_debug_dump_retention_hours")]
+    pub debug_dump_retention_hours: u64,
+
+    /// 延迟启动：不等待余额预热完成即开始服务，预热在后台进行（未初始化的余额在选择时视为未知）
+    #[serde(default)]
+    pub lazy_startup: bool,
+
+    /// 启动时预热凭据余额的最大并发数（0 表示不预热，余额在首次查询时获取）
+    #[serde(default = "default_balance_init_concurrency")]
+    pub balance_init_concurrency: usize,
+
+    /// 启动预热时每次余额查询前的最大随机延迟（毫秒）
+    #[serde(default = "default_balance_init_jitter_ms")]
+    pub balance_init_jitter_ms: u64,
+
+    /// 影子复制（可选）：抽样复制非流式请求到另一个后端并记录响应差异
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<ShadowConfig>,
+
+    /// 租户列表（可选），每个租户拥有独立的 API Key、凭据池与配置覆盖
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tenants: Vec<TenantConfig>,
+
     /// 配置文件路径（运行时元数据，不写入 JSON）
     #[serde(skip)]
     config_path: Option<PathBuf>,
 }
 
+/// 上游端点地址模板，`{region}` 替换为实际使用的区域，未配置的端点使用内置地址
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointsConfig {
+    /// generateAssistantResponse 地址（使用 API Region）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api: Option<String>,
+
+    /// MCP 地址（使用 API Region）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mcp: Option<String>,
+
+    /// getUsageLimits 地址（使用 API Region，查询参数自动追加）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_limits: Option<String>,
+
+    /// Social Token 刷新地址（使用 Auth Region）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub social_refresh: Option<String>,
+
+    /// IdC（OIDC）Token 刷新地址（使用 Auth Region）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idc_refresh: Option<String>,
+}
+
+const DEFAULT_API_ENDPOINT: &str = "https://q.{region}.amazonaws.com/generateAssistantResponse";
+const DEFAULT_MCP_ENDPOINT: &str = "https://q.{region}.amazonaws.com/mcp";
+const DEFAULT_USAGE_LIMITS_ENDPOINT: &str = "https://q.{region}.amazonaws.com/getUsageLimits";
+const DEFAULT_SOCIAL_REFRESH_ENDPOINT: &str =
+    "https://prod.{region}.auth.desktop.kiro.dev/refreshToken";
+const DEFAULT_IDC_REFRESH_ENDPOINT: &str = "https://oidc.{region}.amazonaws.com/token";
+
+impl EndpointsConfig {
+    fn is_empty(&self) -> bool {
+        self.templates().iter().all(|(_, t, _)| t.is_none())
+    }
+
+    /// (字段名, 配置的模板, 内置模板)
+    fn templates(&self) -> [(&'static str, Option<&str>, &'static str); 5] {
+        [
+            ("api", self.api.as_deref(), DEFAULT_API_ENDPOINT),
+            ("mcp", self.mcp.as_deref(), DEFAULT_MCP_ENDPOINT),
+            (
+                "usageLimits",
+                self.usage_limits.as_deref(),
+                DEFAULT_USAGE_LIMITS_ENDPOINT,
+            ),
+            (
+                "socialRefresh",
+                self.social_refresh.as_deref(),
+                DEFAULT_SOCIAL_REFRESH_ENDPOINT,
+            ),
+            (
+                "idcRefresh",
+                self.idc_refresh.as_deref(),
+                DEFAULT_IDC_REFRESH_ENDPOINT,
+            ),
+        ]
+    }
+
+    pub fn api_url(&self, region: &str) -> String {
+        render_endpoint(self.api.as_deref(), DEFAULT_API_ENDPOINT, region)
+    }
+
+    pub fn mcp_url(&self, region: &str) -> String {
+        render_endpoint(self.mcp.as_deref(), DEFAULT_MCP_ENDPOINT, region)
+    }
+
+    pub fn usage_limits_url(&self, region: &str) -> String {
+        render_endpoint(
+            self.usage_limits.as_deref(),
+            DEFAULT_USAGE_LIMITS_ENDPOINT,
+            region,
+        )
+    }
+
+    pub fn social_refresh_url(&self, region: &str) -> String {
+        render_endpoint(
+            self.social_refresh.as_deref(),
+            DEFAULT_SOCIAL_REFRESH_ENDPOINT,
+            region,
+        )
+    }
+
+    pub fn idc_refresh_url(&self, region: &str) -> String {
+        render_endpoint(
+            self.idc_refresh.as_deref(),
+            DEFAULT_IDC_REFRESH_ENDPOINT,
+            region,
+        )
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        for (name, template, _) in self.templates() {
+            let Some(template) = template else {
+                continue;
+            };
+            let url = template.replace("{region}", "us-east-1");
+            let valid = reqwest::Url::parse(&url)
+                .is_ok_and(|u| matches!(u.scheme(), "http" | "https") && u.host().is_some());
+            if !valid {
+                anyhow::bail!("endpoints.{} 不是有效的 http(s) 地址: {}", name, template);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn render_endpoint(template: Option<&str>, default: &str, region: &str) -> String {
+    template.unwrap_or(default).replace("{region}", region)
+}
+
+/// 端点地址的 Host 头（含非默认端口）
+pub fn endpoint_host(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| {
+            let host = u.host_str()?.to_string();
+            Some(match u.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host,
+            })
+        })
+        .unwrap_or_default()
+}
+
+/// 影子复制配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowConfig {
+    /// Anthropic 兼容的 Messages 端点（如 `https://api.anthropic.com/v1/messages`）
+    pub url: String,
+
+    /// 发送给影子端点的 API Key（x-api-key，可选）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+
+    /// 抽样复制的非流式请求百分比（0-100）
+    #[serde(default)]
+    pub percent: u8,
+
+    /// 影子请求超时（秒）
+    #[serde(default = "default_shadow_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_shadow_timeout_secs() -> u64 {
+    300
+}
+
+/// 凭据文件配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialsFileConfig {
+    /// 文件路径
+    pub path: String,
+
+    /// 是否只读（不回写刷新后的 Token，其中的凭据也不能通过 Admin API 删除）
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// 该文件中凭据的每日上游请求上限（0 表示不限制），达到后当天不再选用这些凭据
+    #[serde(default)]
+    pub daily_request_limit: u64,
+}
+
+/// 客户端 API Key 配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientApiKeyConfig {
+    /// 名称（用于用量统计，需唯一）
+    pub name: String,
+
+    /// API Key
+    pub key: String,
+
+    /// 该 Key 的上游请求超时（秒，可选，覆盖 requestTimeoutSecs）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+
+    /// 该 Key 的默认重试次数上限（可选，不超过 maxRetries）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<usize>,
+
+    /// 该 Key 允许的并发流式请求数（可选，覆盖 maxConcurrentStreamsPerKey，0 表示不限制）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_streams: Option<usize>,
+}
+
+/// 租户配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantConfig {
+    /// 租户名称（用于日志，需唯一）
+    pub name: String,
+
+    /// 路径前缀（如 `/acme`，请求 `/acme/v1/messages` 归属该租户）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_prefix: Option<String>,
+
+    /// 归属该租户的 Host 列表（不含端口）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+
+    /// 租户的主 API Key
+    pub api_key: String,
+
+    /// 租户的额外客户端 API Key
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<ClientApiKeyConfig>,
+
+    /// 租户的凭据文件列表（不能与其他租户或默认凭据池共用缓存目录）
+    pub credentials_files: Vec<CredentialsFileConfig>,
+
+    /// 覆盖全局配置的字段（与 config.json 字段同名，如 `loadBalancingMode`）
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub overrides: serde_json::Map<String, serde_json::Value>,
+}
+
+/// 租户不能覆盖的全局配置字段（监听地址、Admin 与租户自身的定义）
+const TENANT_GLOBAL_FIELDS: &[&str] = &[
+    "host",
+    "port",
+    "apiKey",
+    "apiKeys",
+    "adminApiKey",
+    "credentialsFiles",
+    "language",
+    "trustedProxies",
+    "profile",
+    "tenants",
+];
+
+/// 不能用作租户路径前缀的路径（已被默认路由占用）
+const RESERVED_TENANT_PREFIXES: &[&str] = &["/v1", "/cc", "/api", "/admin"];
+
+/// 不能透传给客户端的上游响应头（逐跳头与 Cookie）
+const FORBIDDEN_UPSTREAM_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "set-cookie",
+    "cookie",
+];
+
+/// 主 apiKey 在用量统计中的名称
+pub const DEFAULT_API_KEY_NAME: &str = "default";
+
 fn default_host() -> String {
     "127.0.0.1".to_string()
 }
@@ -107,6 +842,31 @@ fn default_region() -> String {
     "us-east-1".to_string()
 }
 
+fn default_usage_limits_credential_region() -> bool {
+    true
+}
+
+fn default_clock_skew_correction() -> bool {
+    true
+}
+
+fn default_clock_skew_warn_secs() -> u64 {
+    30
+}
+
+fn default_auth_region_probe_list() -> Vec<String> {
+    [
+        "us-east-1",
+        "us-west-2",
+        "eu-central-1",
+        "eu-west-1",
+        "ap-northeast-1",
+        "ap-southeast-1",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
 fn default_kiro_version() -> String {
     "0.9.2".to_string()
 }
@@ -128,10 +888,193 @@ fn default_tls_backend() -> TlsBackend {
     TlsBackend::Rustls
 }
 
+fn default_http2() -> bool {
+    true
+}
+
+fn default_pool_idle_timeout_secs() -> u64 {
+    90
+}
+
+fn default_happy_eyeballs() -> bool {
+    true
+}
+
+fn default_affinity_ttl_secs() -> u64 {
+    6 * 3600
+}
+
+fn default_affinity_max_entries() -> usize {
+    10_000
+}
+
 fn default_load_balancing_mode() -> String {
     "priority".to_string()
 }
 
+fn default_max_queued_requests() -> usize {
+    64
+}
+
+fn default_queue_timeout_secs() -> u64 {
+    60
+}
+
+fn default_system_block_separator() -> String {
+    "\n".to_string()
+}
+
+fn default_system_ack_text() -> String {
+    "I will follow these instructions.".to_string()
+}
+
+fn default_paired_assistant_text() -> String {
+    "OK".to_string()
+}
+
+fn default_empty_content_placeholder() -> String {
+    ".".to_string()
+}
+
+fn default_max_images_per_request() -> usize {
+    20
+}
+
+fn default_max_image_bytes() -> usize {
+    5 * 1024 * 1024
+}
+
+fn default_max_image_dimension() -> u32 {
+    8000
+}
+
+fn default_compaction_keep_messages() -> usize {
+    6
+}
+
+fn default_session_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_idempotency_ttl_secs() -> u64 {
+    10 * 60
+}
+
+fn default_idempotency_max_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_request_timeout_secs() -> u64 {
+    720
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    30
+}
+
+fn default_stream_high_water_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_max_non_stream_response_bytes() -> usize {
+    8 * 1024 * 1024
+}
+
+fn default_non_stream_keep_alive_min_tokens() -> u32 {
+    16384
+}
+
+fn default_upstream_request_limit_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_balance_ttl_high_freq_secs() -> u64 {
+    300
+}
+
+fn default_balance_ttl_low_freq_secs() -> u64 {
+    1800
+}
+
+fn default_balance_ttl_low_balance_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_low_balance_threshold() -> f64 {
+    1.0
+}
+
+fn default_spillover_burst_hold_secs() -> u64 {
+    30
+}
+
+fn default_high_freq_window_secs() -> u64 {
+    600
+}
+
+fn default_daily_reset_timezone() -> String {
+    "local".to_string()
+}
+
+fn default_archive_retention_days() -> u64 {
+    30
+}
+
+fn default_upstream_response_headers() -> Vec<String> {
+    vec![
+        "x-amzn-requestid".to_string(),
+        "x-amzn-trace-id".to_string(),
+    ]
+}
+
+fn default_max_retries() -> usize {
+    8
+}
+
+fn default_log_body_max_bytes() -> usize {
+    4096
+}
+
+fn default_debug_dump_max_bytes() -> u64 {
+    50 * 1024 * 1024
+}
+
+fn default_debug_dump_retention_hours() -> u64 {
+    72
+}
+
+fn default_balance_init_concurrency() -> usize {
+    4
+}
+
+fn default_balance_init_jitter_ms() -> u64 {
+    500
+}
+
+fn default_refresh_token_lifetime_days() -> u64 {
+    90
+}
+
+fn default_refresh_token_warn_days() -> u64 {
+    14
+}
+
+fn default_refresh_token_hash_salt() -> String {
+    "kiro-rs".to_string()
+}
+
+fn default_stats_flush_interval_secs() -> u64 {
+    60
+}
+
+fn default_credentials_sync_interval_secs() -> u64 {
+    10
+}
+
+fn default_background_jitter_percent() -> u8 {
+    10
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -140,12 +1083,23 @@ impl Default for Config {
             region: default_region(),
             auth_region: None,
             api_region: None,
+            usage_limits_credential_region: default_usage_limits_credential_region(),
+            auth_region_probe_list: default_auth_region_probe_list(),
+            endpoints: EndpointsConfig::default(),
             kiro_version: default_kiro_version(),
             machine_id: None,
             api_key: None,
+            api_keys: Vec::new(),
+            profile: None,
             system_version: default_system_version(),
             node_version: default_node_version(),
             tls_backend: default_tls_backend(),
+            http2: default_http2(),
+            pool_idle_timeout_secs: default_pool_idle_timeout_secs(),
+            pool_max_idle_per_host: None,
+            happy_eyeballs: default_happy_eyeballs(),
+            host_overrides: BTreeMap::new(),
+            doh_url: None,
             count_tokens_api_url: None,
             count_tokens_api_key: None,
             count_tokens_auth_type: default_count_tokens_auth_type(),
@@ -154,6 +1108,81 @@ impl Default for Config {
             proxy_password: None,
             admin_api_key: None,
             load_balancing_mode: default_load_balancing_mode(),
+            affinity_ttl_secs: default_affinity_ttl_secs(),
+            affinity_max_entries: default_affinity_max_entries(),
+            max_concurrent_per_credential: 0,
+            max_concurrent_streams_per_key: 0,
+            max_queued_requests: default_max_queued_requests(),
+            queue_timeout_secs: default_queue_timeout_secs(),
+            trusted_proxies: Vec::new(),
+            per_ip_requests_per_minute: 0,
+            client_ip_affinity: false,
+            language: Language::default(),
+            credentials_files: Vec::new(),
+            daily_request_limit: 0,
+            daily_reset_timezone: default_daily_reset_timezone(),
+            strict_conversion: false,
+            system_block_mode: SystemBlockMode::default(),
+            system_block_separator: default_system_block_separator(),
+            dedupe_system_blocks: false,
+            system_prompt_mode: SystemPromptMode::default(),
+            trailing_user_mode: TrailingUserMode::default(),
+            system_ack_text: default_system_ack_text(),
+            paired_assistant_text: default_paired_assistant_text(),
+            empty_content_placeholder: default_empty_content_placeholder(),
+            max_images_per_request: default_max_images_per_request(),
+            max_image_bytes: default_max_image_bytes(),
+            max_image_dimension: default_max_image_dimension(),
+            max_history_images: None,
+            conversation_token_budget: 0,
+            compaction_keep_messages: default_compaction_keep_messages(),
+            session_ttl_secs: default_session_ttl_secs(),
+            idempotency_ttl_secs: default_idempotency_ttl_secs(),
+            idempotency_max_bytes: default_idempotency_max_bytes(),
+            request_timeout_secs: default_request_timeout_secs(),
+            connect_timeout_secs: default_connect_timeout_secs(),
+            read_timeout_secs: 0,
+            stream_high_water_bytes: default_stream_high_water_bytes(),
+            stream_backpressure_policy: BackpressurePolicy::default(),
+            max_non_stream_response_bytes: default_max_non_stream_response_bytes(),
+            non_stream_keep_alive_secs: 0,
+            non_stream_keep_alive_min_tokens: default_non_stream_keep_alive_min_tokens(),
+            upstream_request_limit_bytes: default_upstream_request_limit_bytes(),
+            balance_ttl_high_freq_secs: default_balance_ttl_high_freq_secs(),
+            balance_ttl_low_freq_secs: default_balance_ttl_low_freq_secs(),
+            balance_ttl_low_balance_secs: default_balance_ttl_low_balance_secs(),
+            low_balance_threshold: default_low_balance_threshold(),
+            spillover_min_available: 0,
+            spillover_min_balance: 0.0,
+            spillover_queue_delay_ms: 0,
+            spillover_burst_hold_secs: default_spillover_burst_hold_secs(),
+            warm_standby_secs: 0,
+            warm_standby_preconnect: false,
+            high_freq_window_secs: default_high_freq_window_secs(),
+            archive_retention_days: default_archive_retention_days(),
+            rebalance_interval_secs: 0,
+            rebalance_autopilot: false,
+            stats_flush_interval_secs: default_stats_flush_interval_secs(),
+            credentials_sync_interval_secs: default_credentials_sync_interval_secs(),
+            background_jitter_percent: default_background_jitter_percent(),
+            new_credential_canary_percent: 0,
+            refresh_token_hash_salt: default_refresh_token_hash_salt(),
+            refresh_token_lifetime_days: default_refresh_token_lifetime_days(),
+            refresh_token_warn_days: default_refresh_token_warn_days(),
+            upstream_response_headers: default_upstream_response_headers(),
+            upstream_event_format: UpstreamEventFormat::default(),
+            clock_skew_correction: default_clock_skew_correction(),
+            clock_skew_warn_secs: default_clock_skew_warn_secs(),
+            max_retries: default_max_retries(),
+            log_body_max_bytes: default_log_body_max_bytes(),
+            debug_dump_dir: None,
+            debug_dump_max_bytes: default_debug_dump_max_bytes(),
+            debug_dump_retention_hours: default_debug_dump_retention_hours(),
+            lazy_startup: false,
+            balance_init_concurrency: default_balance_init_concurrency(),
+            balance_init_jitter_ms: default_balance_init_jitter_ms(),
+            shadow: None,
+            tenants: Vec::new(),
             config_path: None,
         }
     }
@@ -188,11 +1217,217 @@ impl Config {
         }
 
         let content = fs::read_to_string(path)?;
-        let mut config: Config = serde_json::from_str(&content)?;
+        let mut config = Self::parse(&content)?;
+        config.validate()?;
         config.config_path = Some(path.to_path_buf());
         Ok(config)
     }
 
+    /// 解析配置文件内容并应用配置预设
+    fn parse(content: &str) -> anyhow::Result<Self> {
+        let config: Config = serde_json::from_str(content)?;
+        let Some(profile) = config.profile else {
+            return Ok(config);
+        };
+        let mut value: serde_json::Value = serde_json::from_str(content)?;
+        profile.apply(&mut value);
+        serde_json::from_value(value).context("应用配置预设失败")
+    }
+
+    /// 校验配置项取值
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, value) in [
+            ("balanceTtlHighFreqSecs", self.balance_ttl_high_freq_secs),
+            ("balanceTtlLowFreqSecs", self.balance_ttl_low_freq_secs),
+            (
+                "balanceTtlLowBalanceSecs",
+                self.balance_ttl_low_balance_secs,
+            ),
+            ("highFreqWindowSecs", self.high_freq_window_secs),
+            ("requestTimeoutSecs", self.request_timeout_secs),
+            ("statsFlushIntervalSecs", self.stats_flush_interval_secs),
+            (
+                "credentialsSyncIntervalSecs",
+                self.credentials_sync_interval_secs,
+            ),
+        ] {
+            if value == 0 {
+                anyhow::bail!("{} 必须大于 0", name);
+            }
+        }
+        for (host, addrs) in &self.host_overrides {
+            if host.trim().is_empty() || addrs.is_empty() {
+                anyhow::bail!("hostOverrides 中的域名和 IP 列表不能为空: {:?}", host);
+            }
+        }
+        self.endpoints.validate()?;
+        if let Some(shadow) = &self.shadow {
+            if !shadow.url.starts_with("http://") && !shadow.url.starts_with("https://") {
+                anyhow::bail!("shadow.url 必须是 http(s) 地址: {}", shadow.url);
+            }
+            if shadow.percent > 100 {
+                anyhow::bail!("shadow.percent 必须在 0-100 之间: {}", shadow.percent);
+            }
+        }
+        if let Err(entry) = TrustedProxies::parse(&self.trusted_proxies) {
+            anyhow::bail!("trustedProxies 中的地址无效（应为 IP 或 CIDR）: {}", entry);
+        }
+        if let Some(url) = &self.doh_url
+            && !url.starts_with("https://")
+        {
+            anyhow::bail!("dohUrl 必须是 https:// 地址: {}", url);
+        }
+        if DailyResetZone::parse(&self.daily_reset_timezone).is_none() {
+            anyhow::bail!(
+                "dailyResetTimezone 必须是 local、utc 或固定偏移（如 +08:00）: {}",
+                self.daily_reset_timezone
+            );
+        }
+        // 上游拒绝空白的消息内容
+        for (name, value) in [
+            ("systemAckText", &self.system_ack_text),
+            ("pairedAssistantText", &self.paired_assistant_text),
+            ("emptyContentPlaceholder", &self.empty_content_placeholder),
+        ] {
+            if value.trim().is_empty() {
+                anyhow::bail!("{} 不能为空白", name);
+            }
+        }
+        if self.max_non_stream_response_bytes == 0 {
+            anyhow::bail!("maxNonStreamResponseBytes 必须大于 0");
+        }
+        if !self.low_balance_threshold.is_finite() || self.low_balance_threshold < 0.0 {
+            anyhow::bail!("lowBalanceThreshold 必须是非负数");
+        }
+        if !self.spillover_min_balance.is_finite() || self.spillover_min_balance < 0.0 {
+            anyhow::bail!("spilloverMinBalance 必须是非负数");
+        }
+        if self.balance_ttl_high_freq_secs > self.balance_ttl_low_freq_secs {
+            anyhow::bail!("balanceTtlHighFreqSecs 不能大于 balanceTtlLowFreqSecs");
+        }
+        if self.background_jitter_percent > 100 {
+            anyhow::bail!("backgroundJitterPercent 必须在 0-100 之间");
+        }
+        if self.new_credential_canary_percent > 100 {
+            anyhow::bail!("newCredentialCanaryPercent 必须在 0-100 之间");
+        }
+        if self.tls_backend == TlsBackend::NativeTls && !cfg!(feature = "native-tls") {
+            anyhow::bail!(
+                "当前构建未启用 native-tls 特性（发布的 Linux 静态二进制只支持 rustls），请将 tlsBackend 设为 rustls"
+            );
+        }
+        for name in &self.upstream_response_headers {
+            let Ok(header) = http::HeaderName::try_from(name.as_str()) else {
+                anyhow::bail!("upstreamResponseHeaders 中的响应头名称无效: {}", name);
+            };
+            if FORBIDDEN_UPSTREAM_HEADERS.contains(&header.as_str()) {
+                anyhow::bail!("upstreamResponseHeaders 不能包含逐跳头或 Cookie: {}", name);
+            }
+        }
+
+        let mut names = std::collections::HashSet::from([DEFAULT_API_KEY_NAME]);
+        let mut keys: std::collections::HashSet<&str> =
+            self.api_key.as_deref().into_iter().collect();
+        for client in &self.api_keys {
+            if client.name.trim().is_empty() || client.key.trim().is_empty() {
+                anyhow::bail!("apiKeys 中的 name 和 key 不能为空");
+            }
+            if client.timeout_secs == Some(0) {
+                anyhow::bail!("apiKeys 中的 timeoutSecs 必须大于 0: {}", client.name);
+            }
+            if !names.insert(client.name.as_str()) {
+                anyhow::bail!("apiKeys 中的名称重复或与保留名称冲突: {}", client.name);
+            }
+            if !keys.insert(client.key.as_str()) {
+                anyhow::bail!("apiKeys 中的 Key 重复: {}", client.name);
+            }
+        }
+        self.validate_tenants()
+    }
+
+    /// 校验租户定义：名称、路径前缀与 Host 均不能重复，且租户配置本身有效
+    fn validate_tenants(&self) -> anyhow::Result<()> {
+        let mut names = std::collections::HashSet::new();
+        let mut prefixes = std::collections::HashSet::new();
+        let mut hosts = std::collections::HashSet::new();
+        for tenant in &self.tenants {
+            let name = tenant.name.as_str();
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                anyhow::bail!("tenants 中的 name 只能包含字母、数字、- 和 _: {:?}", name);
+            }
+            if !names.insert(name) {
+                anyhow::bail!("tenants 中的名称重复: {}", name);
+            }
+            if tenant.path_prefix.is_none() && tenant.hosts.is_empty() {
+                anyhow::bail!("租户 {} 必须配置 pathPrefix 或 hosts", name);
+            }
+            if let Some(prefix) = &tenant.path_prefix {
+                if !prefix.starts_with('/') || prefix.len() < 2 || prefix.ends_with('/') {
+                    anyhow::bail!("租户 {} 的 pathPrefix 必须形如 /name: {}", name, prefix);
+                }
+                if RESERVED_TENANT_PREFIXES.contains(&prefix.as_str())
+                    || prefix.starts_with("/_tenants")
+                {
+                    anyhow::bail!("租户 {} 的 pathPrefix 与内置路由冲突: {}", name, prefix);
+                }
+                if !prefixes.insert(prefix.as_str()) {
+                    anyhow::bail!("tenants 中的 pathPrefix 重复: {}", prefix);
+                }
+            }
+            for host in &tenant.hosts {
+                if host.trim().is_empty() || !hosts.insert(host.to_ascii_lowercase()) {
+                    anyhow::bail!("租户 {} 的 hosts 为空或与其他租户重复: {:?}", name, host);
+                }
+            }
+            if tenant.api_key.trim().is_empty() {
+                anyhow::bail!("租户 {} 的 apiKey 不能为空", name);
+            }
+            if tenant.credentials_files.is_empty() {
+                anyhow::bail!("租户 {} 必须配置 credentialsFiles", name);
+            }
+            self.for_tenant(tenant)?;
+        }
+        Ok(())
+    }
+
+    /// 生成租户的有效配置：以全局配置为基础，应用租户的覆盖字段、API Key 与凭据文件
+    ///
+    /// 返回的配置没有配置文件路径（租户配置不通过 Admin API 回写）
+    pub fn for_tenant(&self, tenant: &TenantConfig) -> anyhow::Result<Config> {
+        if let Some(field) = tenant
+            .overrides
+            .keys()
+            .find(|key| TENANT_GLOBAL_FIELDS.contains(&key.as_str()))
+        {
+            anyhow::bail!(
+                "租户 {} 的 overrides 不能包含全局字段 {}",
+                tenant.name,
+                field
+            );
+        }
+
+        let mut value = serde_json::to_value(self).context("序列化配置失败")?;
+        if let Some(object) = value.as_object_mut() {
+            object.remove("tenants");
+            for (key, override_value) in &tenant.overrides {
+                object.insert(key.clone(), override_value.clone());
+            }
+        }
+        let mut config: Config = serde_json::from_value(value)
+            .with_context(|| format!("租户 {} 的 overrides 无效", tenant.name))?;
+        config.api_key = Some(tenant.api_key.clone());
+        config.api_keys = tenant.api_keys.clone();
+        config.credentials_files = tenant.credentials_files.clone();
+        config
+            .validate()
+            .with_context(|| format!("租户 {} 的配置无效", tenant.name))?;
+        Ok(config)
+    }
+
     /// 获取配置文件路径（如果有）
     pub fn config_path(&self) -> Option<&Path> {
         self.config_path.as_deref()
@@ -210,3 +1445,47 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_fills_unset_fields() {
+        let config = Config::parse(r#"{"profile": "team", "maxRetries": 6}"#).unwrap();
+        assert_eq!(config.profile, Some(ConfigProfile::Team));
+        assert_eq!(config.load_balancing_mode, "balanced");
+        assert_eq!(config.per_ip_requests_per_minute, 120);
+        // 显式设置的字段优先于预设
+        assert_eq!(config.max_retries, 6);
+
+        let config = Config::parse(r#"{"profile": "high-throughput"}"#).unwrap();
+        assert_eq!(config.max_queued_requests, 256);
+        assert!(config.lazy_startup);
+        config.validate().unwrap();
+
+        // 未设置预设时保持原有默认值
+        let config = Config::parse("{}").unwrap();
+        assert_eq!(config.max_retries, default_max_retries());
+        assert!(Config::parse(r#"{"profile": "unknown"}"#).is_err());
+    }
+
+    #[test]
+    fn test_validate_upstream_response_headers() {
+        let mut config = Config {
+            upstream_response_headers: vec!["x-amzn-requestid".to_string()],
+            ..Config::default()
+        };
+        config.validate().unwrap();
+
+        for name in [
+            "Connection",
+            "transfer-encoding",
+            "set-cookie",
+            "bad header",
+        ] {
+            config.upstream_response_headers = vec![name.to_string()];
+            assert!(config.validate().is_err(), "{}", name);
+        }
+    }
+}
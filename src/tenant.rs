@@ -0,0 +1,229 @@
+//! 多租户路由
+//!
+//! 每个租户拥有独立的 API Key、凭据池（独立的 `MultiTokenManager`）与配置覆盖，
+//! 通过路径前缀（如 `/acme/v1/messages`）或 Host 选择：
+//! - 配置了 `pathPrefix` 的租户挂载在该前缀下
+//! - 只配置了 `hosts` 的租户挂载在内部前缀 `/_tenants/{name}` 下
+//! - 请求的 Host 匹配租户时，在路由前将路径改写到该租户的前缀下
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{Uri, header, uri::PathAndQuery},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+use crate::model::config::TenantConfig;
+
+/// 租户路由挂载的路径前缀
+pub fn route_prefix(tenant: &TenantConfig) -> String {
+    tenant
+        .path_prefix
+        .clone()
+        .unwrap_or_else(|| format!("/_tenants/{}", tenant.name))
+}
+
+/// Host 到租户路径前缀的映射
+#[derive(Clone, Default)]
+pub struct HostRouting {
+    prefixes: Arc<HashMap<String, String>>,
+}
+
+impl HostRouting {
+    pub fn new(tenants: &[TenantConfig]) -> Self {
+        let prefixes = tenants
+            .iter()
+            .flat_map(|tenant| {
+                let prefix = route_prefix(tenant);
+                tenant
+                    .hosts
+                    .iter()
+                    .map(move |host| (host.to_ascii_lowercase(), prefix.clone()))
+            })
+            .collect();
+        Self {
+            prefixes: Arc::new(prefixes),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    /// 按 Host 将请求路径改写到租户前缀下（已带前缀或 Host 未匹配时不变）
+    pub fn rewrite(&self, mut request: Request) -> Request {
+        let Some(prefix) = request
+            .headers()
+            .get(header::HOST)
+            .and_then(|v| v.to_str().ok())
+            .map(|host| {
+                host.split(':')
+                    .next()
+                    .unwrap_or_default()
+                    .to_ascii_lowercase()
+            })
+            .and_then(|host| self.prefixes.get(&host))
+        else {
+            return request;
+        };
+
+        let path = request.uri().path();
+        if path == prefix || path.starts_with(&format!("{}/", prefix)) {
+            return request;
+        }
+        let path_and_query = match request.uri().query() {
+            Some(query) => format!("{}{}?{}", prefix, path, query),
+            None => format!("{}{}", prefix, path),
+        };
+
+        let mut parts = request.uri().clone().into_parts();
+        parts.path_and_query = PathAndQuery::try_from(path_and_query).ok();
+        if let Ok(uri) = Uri::from_parts(parts) {
+            *request.uri_mut() = uri;
+        }
+        request
+    }
+}
+
+/// 按 Host 改写路径的中间件（需作用于路由之前）
+pub async fn host_routing_middleware(
+    State(routing): State<HostRouting>,
+    request: Request,
+    next: Next,
+) -> Response {
+    next.run(routing.rewrite(request)).await
+}
+
+/// 为租户请求的日志附加 `tenant` 字段
+pub async fn tenant_span_middleware(
+    State(tenant): State<Arc<str>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let span = tracing::info_span!("tenant", tenant = %tenant);
+    next.run(request).instrument(span).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::config::{Config, CredentialsFileConfig};
+
+    fn tenant(name: &str, path_prefix: Option<&str>, hosts: &[&str]) -> TenantConfig {
+        TenantConfig {
+            name: name.to_string(),
+            path_prefix: path_prefix.map(str::to_string),
+            hosts: hosts.iter().map(|h| h.to_string()).collect(),
+            api_key: format!("sk-{}", name),
+            api_keys: Vec::new(),
+            credentials_files: vec![CredentialsFileConfig {
+                path: format!("{}/credentials.json", name),
+                read_only: false,
+                daily_request_limit: 0,
+            }],
+            overrides: serde_json::Map::new(),
+        }
+    }
+
+    fn request(host: &str, uri: &str) -> Request {
+        Request::builder()
+            .uri(uri)
+            .header(header::HOST, host)
+            .body(axum::body::Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_host_rewrite() {
+        let routing = HostRouting::new(&[
+            tenant("acme", Some("/acme"), &["Acme.example.com"]),
+            tenant("beta", None, &["beta.example.com"]),
+        ]);
+
+        let rewritten = routing.rewrite(request("acme.example.com:8080", "/v1/messages?beta=true"));
+        assert_eq!(rewritten.uri(), "/acme/v1/messages?beta=true");
+
+        let rewritten = routing.rewrite(request("beta.example.com", "/v1/models"));
+        assert_eq!(rewritten.uri(), "/_tenants/beta/v1/models");
+
+        // 已带前缀或 Host 未匹配时不改写
+        let rewritten = routing.rewrite(request("acme.example.com", "/acme/v1/models"));
+        assert_eq!(rewritten.uri(), "/acme/v1/models");
+        let rewritten = routing.rewrite(request("other.example.com", "/v1/models"));
+        assert_eq!(rewritten.uri(), "/v1/models");
+    }
+
+    #[test]
+    fn test_tenant_config_overrides() {
+        let mut acme = tenant("acme", Some("/acme"), &[]);
+        acme.overrides
+            .insert("loadBalancingMode".to_string(), "balanced".into());
+        let mut config = Config::default();
+        config.api_key = Some("sk-global".to_string());
+        config.tenants = vec![acme.clone()];
+        config.validate().unwrap();
+
+        let tenant_config = config.for_tenant(&acme).unwrap();
+        assert_eq!(tenant_config.load_balancing_mode, "balanced");
+        assert_eq!(tenant_config.api_key.as_deref(), Some("sk-acme"));
+        assert_eq!(
+            tenant_config.credentials_files[0].path,
+            "acme/credentials.json"
+        );
+        assert!(tenant_config.tenants.is_empty());
+        assert!(tenant_config.config_path().is_none());
+        assert_eq!(config.load_balancing_mode, "priority");
+    }
+
+    #[test]
+    fn test_tenant_config_validation() {
+        let validate = |tenants: Vec<TenantConfig>| {
+            let mut config = Config::default();
+            config.tenants = tenants;
+            config.validate()
+        };
+
+        assert!(validate(vec![tenant("acme", Some("/acme"), &["a.example.com"])]).is_ok());
+        // 既无前缀也无 Host
+        assert!(validate(vec![tenant("acme", None, &[])]).is_err());
+        // 前缀格式错误或与内置路由冲突
+        assert!(validate(vec![tenant("acme", Some("acme"), &[])]).is_err());
+        assert!(validate(vec![tenant("acme", Some("/v1"), &[])]).is_err());
+        // 名称、前缀或 Host 重复
+        assert!(
+            validate(vec![
+                tenant("acme", Some("/a"), &[]),
+                tenant("acme", Some("/b"), &[])
+            ])
+            .is_err()
+        );
+        assert!(
+            validate(vec![
+                tenant("a", Some("/x"), &[]),
+                tenant("b", Some("/x"), &[])
+            ])
+            .is_err()
+        );
+        assert!(
+            validate(vec![
+                tenant("a", None, &["x.example.com"]),
+                tenant("b", None, &["X.example.com"])
+            ])
+            .is_err()
+        );
+
+        // 不能覆盖全局字段，覆盖值需通过校验
+        let mut invalid = tenant("acme", Some("/acme"), &[]);
+        invalid.overrides.insert("port".to_string(), 9000.into());
+        assert!(validate(vec![invalid]).is_err());
+        let mut invalid = tenant("acme", Some("/acme"), &[]);
+        invalid
+            .overrides
+            .insert("requestTimeoutSecs".to_string(), 0.into());
+        assert!(validate(vec![invalid]).is_err());
+    }
+}
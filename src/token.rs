@@ -6,12 +6,16 @@
 //! - 非西文字符：每个计 4.5 个字符单位
 //! - 西文字符：每个计 1 个字符单位
 //! - 4 个字符单位 = 1 token（四舍五入）
+//! - 图片：按 宽 × 高 / 750 估算（与 Anthropic 的计费方式一致，上限 1600），无法读取尺寸时按上限计
 
+use crate::anthropic::image_dimensions;
 use crate::anthropic::types::{
     CountTokensRequest, CountTokensResponse, Message, SystemMessage, Tool,
 };
-use crate::http_client::{ProxyConfig, build_client};
-use crate::model::config::TlsBackend;
+use crate::http_client::{ProxyConfig, TransportOptions, build_client};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
 use std::sync::OnceLock;
 
 /// Count Tokens API 配置
@@ -25,8 +29,8 @@ pub struct CountTokensConfig {
     pub auth_type: String,
     /// 代理配置
     pub proxy: Option<ProxyConfig>,
-
-    pub tls_backend: TlsBackend,
+    /// 传输层参数
+    pub transport: TransportOptions,
 }
 
 /// 全局配置存储
@@ -146,7 +150,7 @@ async fn call_remote_count_tokens(
     messages: &Vec<Message>,
     tools: &Option<Vec<Tool>>,
 ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
-    let client = build_client(config.proxy.as_ref(), 300, config.tls_backend)?;
+    let client = build_client(config.proxy.as_ref(), 300, &config.transport)?;
 
     // 构建请求体
     let request = CountTokensRequest {
@@ -183,45 +187,99 @@ async fn call_remote_count_tokens(
     Ok(result.input_tokens as u64)
 }
 
+/// 单张图片的 token 上限（Anthropic 会将更大的图片缩放到约 1.15 百万像素）
+const MAX_IMAGE_TOKENS: u64 = 1600;
+
+/// 输入 tokens 的组成（本地估算）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenBreakdown {
+    /// 系统提示词
+    pub system_tokens: u64,
+    /// 消息文本
+    pub message_tokens: u64,
+    /// 工具定义（名称、描述与 input_schema）
+    pub tool_schema_tokens: u64,
+    /// 消息中的图片（含 tool_result 中的图片）
+    pub image_tokens: u64,
+}
+
+impl TokenBreakdown {
+    pub fn total(&self) -> u64 {
+        self.system_tokens + self.message_tokens + self.tool_schema_tokens + self.image_tokens
+    }
+}
+
 /// 本地计算请求的输入 tokens
 fn count_all_tokens_local(
     system: Option<Vec<SystemMessage>>,
     messages: Vec<Message>,
     tools: Option<Vec<Tool>>,
 ) -> u64 {
-    let mut total = 0;
+    count_tokens_breakdown(system.as_deref(), &messages, tools.as_deref())
+        .total()
+        .max(1)
+}
+
+/// 按来源分别估算输入 tokens（始终在本地计算）
+pub(crate) fn count_tokens_breakdown(
+    system: Option<&[SystemMessage]>,
+    messages: &[Message],
+    tools: Option<&[Tool]>,
+) -> TokenBreakdown {
+    let mut breakdown = TokenBreakdown::default();
 
     // 系统消息
-    if let Some(ref system) = system {
-        for msg in system {
-            total += count_tokens(&msg.text);
-        }
+    for msg in system.unwrap_or_default() {
+        breakdown.system_tokens += count_tokens(&msg.text);
     }
 
     // 用户消息
-    for msg in &messages {
+    for msg in messages {
         if let serde_json::Value::String(s) = &msg.content {
-            total += count_tokens(s);
+            breakdown.message_tokens += count_tokens(s);
         } else if let serde_json::Value::Array(arr) = &msg.content {
             for item in arr {
                 if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
-                    total += count_tokens(text);
+                    breakdown.message_tokens += count_tokens(text);
                 }
+                breakdown.image_tokens += count_block_image_tokens(item);
             }
         }
     }
 
     // 工具定义
-    if let Some(ref tools) = tools {
-        for tool in tools {
-            total += count_tokens(&tool.name);
-            total += count_tokens(&tool.description);
-            let input_schema_json = serde_json::to_string(&tool.input_schema).unwrap_or_default();
-            total += count_tokens(&input_schema_json);
-        }
+    for tool in tools.unwrap_or_default() {
+        breakdown.tool_schema_tokens += count_tokens(&tool.name);
+        breakdown.tool_schema_tokens += count_tokens(&tool.description);
+        let input_schema_json = serde_json::to_string(&tool.input_schema).unwrap_or_default();
+        breakdown.tool_schema_tokens += count_tokens(&input_schema_json);
     }
 
-    total.max(1)
+    breakdown
+}
+
+/// 内容块中图片的 tokens（image 块本身，或 tool_result 内嵌的 image 块）
+fn count_block_image_tokens(block: &serde_json::Value) -> u64 {
+    match block.get("type").and_then(|v| v.as_str()) {
+        Some("image") => estimate_image_tokens(block.get("source")),
+        Some("tool_result") => block
+            .get("content")
+            .and_then(|v| v.as_array())
+            .map_or(0, |items| items.iter().map(count_block_image_tokens).sum()),
+        _ => 0,
+    }
+}
+
+/// 按图片尺寸估算 tokens：宽 × 高 / 750，上限 [`MAX_IMAGE_TOKENS`]
+fn estimate_image_tokens(source: Option<&serde_json::Value>) -> u64 {
+    source
+        .and_then(|s| s.get("data"))
+        .and_then(|v| v.as_str())
+        .and_then(|data| STANDARD.decode(data).ok())
+        .and_then(|bytes| image_dimensions(&bytes))
+        .map_or(MAX_IMAGE_TOKENS, |(width, height)| {
+            (width as u64 * height as u64 / 750).clamp(1, MAX_IMAGE_TOKENS)
+        })
 }
 
 /// 估算输出 tokens
@@ -243,3 +301,59 @@ pub(crate) fn estimate_output_tokens(content: &[serde_json::Value]) -> i32 {
 
     total.max(1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn png_base64(width: u32, height: u32) -> String {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        data.extend(width.to_be_bytes());
+        data.extend(height.to_be_bytes());
+        data.extend([8, 6, 0, 0, 0]);
+        STANDARD.encode(data)
+    }
+
+    #[test]
+    fn test_count_tokens_breakdown() {
+        let system = vec![SystemMessage {
+            text: "You are helpful.".to_string(),
+        }];
+        let image = |data: String| {
+            let source = json!({"type": "base64", "media_type": "image/png", "data": data});
+            json!({"type": "image", "source": source})
+        };
+        let messages = vec![
+            Message {
+                role: "user".to_string(),
+                content: json!([{"type": "text", "text": "What is this?"}, image(png_base64(300, 250))]),
+            },
+            Message {
+                role: "user".to_string(),
+                content: json!([{
+                    "type": "tool_result",
+                    "tool_use_id": "toolu_1",
+                    "content": [image("not an image".to_string())]
+                }]),
+            },
+        ];
+        let tools: Vec<Tool> = serde_json::from_value(json!([{
+            "name": "read",
+            "description": "Read a file",
+            "input_schema": {"type": "object"}
+        }]))
+        .unwrap();
+
+        let breakdown = count_tokens_breakdown(Some(&system), &messages, Some(&tools));
+        assert_eq!(breakdown.system_tokens, count_tokens("You are helpful."));
+        assert_eq!(breakdown.message_tokens, count_tokens("What is this?"));
+        assert!(breakdown.tool_schema_tokens > 0);
+        // 300 × 250 / 750 = 100；无法识别尺寸的图片按上限计
+        assert_eq!(breakdown.image_tokens, 100 + MAX_IMAGE_TOKENS);
+        assert_eq!(
+            count_all_tokens_local(Some(system), messages, Some(tools)),
+            breakdown.total()
+        );
+    }
+}
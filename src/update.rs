@@ -0,0 +1,318 @@
+//! 自更新模块
+//!
+//! `kiro-rs self-update` 从 GitHub Releases 检查最新版本，下载当前平台的二进制文件，
+//! 使用 release 中的 `SHA256SUMS` 校验完整性后原子替换当前可执行文件。
+//!
+//! Release 资产命名约定（由 `.github/workflows/build.yaml` 生成）：
+//! - `kiro-rs-<平台>`（Windows 为 `kiro-rs-<平台>.exe`），平台如 `Linux-x64`、`macOS-arm64`
+//! - `SHA256SUMS`：每行 `<sha256>  <文件名>`
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::http_client::{ProxyConfig, TransportOptions, build_client};
+
+/// 默认的 Release 仓库
+pub const DEFAULT_UPDATE_REPO: &str = "huazz233/kiro.rs";
+
+/// 校验和文件名
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+/// 下载超时（秒）
+const DOWNLOAD_TIMEOUT_SECS: u64 = 300;
+
+/// GitHub Release 信息（只解析需要的字段）
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    assets: Vec<ReleaseAsset>,
+}
+
+/// GitHub Release 资产
+#[derive(Debug, Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Option<&ReleaseAsset> {
+        self.assets.iter().find(|a| a.name == name)
+    }
+}
+
+/// 当前平台对应的 Release 资产名，不支持的平台返回 None
+fn platform_asset_name() -> Option<&'static str> {
+    let name = match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => "kiro-rs-Linux-x64",
+        ("linux", "aarch64") => "kiro-rs-Linux-arm64",
+        ("macos", "x86_64") => "kiro-rs-macOS-x64",
+        ("macos", "aarch64") => "kiro-rs-macOS-arm64",
+        ("windows", "x86_64") => "kiro-rs-Windows-x64.exe",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// 解析版本号为数字序列（忽略前缀 v 与非数字后缀）
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .trim()
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| {
+            part.chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect::<String>()
+                .parse()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// 判断 latest 是否比 current 更新
+fn is_newer(latest: &str, current: &str) -> bool {
+    let mut latest = parse_version(latest);
+    let mut current = parse_version(current);
+    let len = latest.len().max(current.len());
+    latest.resize(len, 0);
+    current.resize(len, 0);
+    latest > current
+}
+
+/// 从 SHA256SUMS 内容中查找指定文件的校验和
+fn find_checksum(checksums: &str, asset_name: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let hash = parts.next()?;
+        // sha256sum 二进制模式会在文件名前加 '*'
+        let name = parts.next()?.trim_start_matches('*');
+        (name == asset_name).then(|| hash.to_ascii_lowercase())
+    })
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// 将新二进制原子替换到当前可执行文件位置
+///
+/// 先写入同目录下的临时文件，再通过 rename 替换；
+/// Windows 无法覆盖运行中的可执行文件，先将其改名为 `.old`，替换失败时再改回原名
+fn replace_executable(exe: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let staged = exe.with_extension("new");
+    std::fs::write(&staged, data)
+        .with_context(|| format!("写入临时文件失败: {}", staged.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))
+            .context("设置可执行权限失败")?;
+    }
+
+    #[cfg(windows)]
+    let old = {
+        let old = exe.with_extension("old");
+        let _ = std::fs::remove_file(&old);
+        if let Err(e) = std::fs::rename(exe, &old) {
+            let _ = std::fs::remove_file(&staged);
+            return Err(e).context("重命名当前可执行文件失败");
+        }
+        old
+    };
+
+    if let Err(e) = std::fs::rename(&staged, exe) {
+        // 恢复原可执行文件，避免原路径下没有可执行文件
+        #[cfg(windows)]
+        if let Err(restore) = std::fs::rename(&old, exe) {
+            tracing::error!(
+                "恢复原可执行文件失败，原文件保留在 {}: {}",
+                old.display(),
+                restore
+            );
+        }
+        let _ = std::fs::remove_file(&staged);
+        return Err(e).with_context(|| format!("替换可执行文件失败: {}", exe.display()));
+    }
+    Ok(())
+}
+
+/// 执行自更新
+///
+/// # Arguments
+/// * `repo` - GitHub 仓库（owner/repo）
+/// * `check_only` - 仅检查是否有新版本，不下载安装
+/// * `proxy` - 可选的代理配置
+/// * `transport` - 传输层参数
+pub async fn self_update(
+    repo: &str,
+    check_only: bool,
+    proxy: Option<&ProxyConfig>,
+    transport: &TransportOptions,
+) -> anyhow::Result<()> {
+    let current_version = env!("CARGO_PKG_VERSION");
+    let client = build_client(proxy, DOWNLOAD_TIMEOUT_SECS, transport)?;
+
+    let url = format!("https://api.github.com/repos/{}/releases/latest", repo);
+    let release: Release = client
+        .get(&url)
+        .header("User-Agent", format!("kiro-rs/{}", current_version))
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await
+        .context("获取最新 Release 失败")?
+        .error_for_status()
+        .context("获取最新 Release 失败")?
+        .json()
+        .await
+        .context("解析 Release 信息失败")?;
+
+    if !is_newer(&release.tag_name, current_version) {
+        tracing::info!(
+            "当前已是最新版本: {}（最新 Release: {}）",
+            current_version,
+            release.tag_name
+        );
+        return Ok(());
+    }
+
+    tracing::info!("发现新版本: {} -> {}", current_version, release.tag_name);
+    if check_only {
+        return Ok(());
+    }
+
+    let asset_name = platform_asset_name().ok_or_else(|| {
+        anyhow::anyhow!(
+            "当前平台不支持自更新: {}-{}",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        )
+    })?;
+    let asset = release
+        .asset(asset_name)
+        .ok_or_else(|| anyhow::anyhow!("Release {} 中缺少资产 {}", release.tag_name, asset_name))?;
+    let checksums_asset = release.asset(CHECKSUMS_ASSET).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Release {} 中缺少 {}，拒绝安装未校验的二进制",
+            release.tag_name,
+            CHECKSUMS_ASSET
+        )
+    })?;
+
+    let checksums = client
+        .get(&checksums_asset.browser_download_url)
+        .send()
+        .await
+        .with_context(|| {
+            format!(
+                "下载校验和文件失败: {}",
+                checksums_asset.browser_download_url
+            )
+        })?
+        .error_for_status()
+        .with_context(|| {
+            format!(
+                "下载校验和文件失败: {}",
+                checksums_asset.browser_download_url
+            )
+        })?
+        .text()
+        .await
+        .context("下载校验和文件失败")?;
+    let expected = find_checksum(&checksums, asset_name)
+        .ok_or_else(|| anyhow::anyhow!("{} 中没有 {} 的校验和", CHECKSUMS_ASSET, asset_name))?;
+
+    tracing::info!("正在下载 {}", asset.browser_download_url);
+    let data = client
+        .get(&asset.browser_download_url)
+        .send()
+        .await
+        .with_context(|| format!("下载二进制文件失败: {}", asset.browser_download_url))?
+        .error_for_status()
+        .with_context(|| format!("下载二进制文件失败: {}", asset.browser_download_url))?
+        .bytes()
+        .await
+        .context("下载二进制文件失败")?;
+
+    let actual = sha256_hex(&data);
+    if actual != expected {
+        bail!("SHA256 校验失败: 期望 {}，实际 {}", expected, actual);
+    }
+    tracing::info!("SHA256 校验通过: {}", actual);
+
+    let exe: PathBuf = std::env::current_exe().context("无法获取当前可执行文件路径")?;
+    replace_executable(&exe, &data)?;
+
+    tracing::info!(
+        "已更新到 {}，请重启服务以生效: {}",
+        release.tag_name,
+        exe.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("v2026.2.5", "2026.2.4"));
+        assert!(is_newer("2026.10.1", "2026.9.30"));
+        assert!(is_newer("v2026.2.4.1", "2026.2.4"));
+        assert!(!is_newer("v2026.2.4", "2026.2.4"));
+        assert!(!is_newer("v2026.1.9", "2026.2.4"));
+    }
+
+    #[test]
+    fn test_find_checksum() {
+        let checksums = "\
+abc123  kiro-rs-Linux-x64
+DEF456 *kiro-rs-Windows-x64.exe
+";
+        assert_eq!(
+            find_checksum(checksums, "kiro-rs-Linux-x64"),
+            Some("abc123".to_string())
+        );
+        assert_eq!(
+            find_checksum(checksums, "kiro-rs-Windows-x64.exe"),
+            Some("def456".to_string())
+        );
+        assert_eq!(find_checksum(checksums, "kiro-rs-macOS-x64"), None);
+    }
+
+    #[test]
+    fn test_replace_executable() {
+        let dir = std::env::temp_dir().join(format!("kiro-update-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let exe = dir.join("kiro-rs");
+        std::fs::write(&exe, b"old").unwrap();
+
+        replace_executable(&exe, b"new").unwrap();
+
+        assert_eq!(std::fs::read(&exe).unwrap(), b"new");
+        assert!(!exe.with_extension("new").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_executable_failure_cleans_up() {
+        let dir = std::env::temp_dir().join(format!("kiro-update-{}", uuid::Uuid::new_v4()));
+        // 目标是非空目录，rename 必然失败
+        let exe = dir.join("kiro-rs");
+        std::fs::create_dir_all(exe.join("busy")).unwrap();
+
+        let err = replace_executable(&exe, b"new").unwrap_err();
+
+        assert!(err.to_string().contains("替换可执行文件失败"));
+        assert!(exe.join("busy").exists());
+        assert!(!exe.with_extension("new").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
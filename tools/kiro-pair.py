@@ -0,0 +1,97 @@
+#!/usr/bin/env python3
+"""kiro-pair：将本机 Kiro IDE 的凭据上传到 kiro-rs 的一次性配对地址
+
+在安装了 Kiro IDE 的机器上运行，读取 Kiro 的 Token 缓存文件（IdC 登录时同时读取
+OIDC 客户端注册信息），POST 到管理界面生成的配对地址，由服务端校验并导入。
+refreshToken 不经过聊天软件或剪贴板，也不会被截断。
+
+用法：
+    python3 kiro-pair.py <配对地址> [--file <kiro-auth-token.json 路径>]
+
+也可以直接从服务端获取本脚本：
+    curl -fsSL <服务地址>/api/pair/kiro-pair.py | python3 - <配对地址>
+
+仅依赖 Python 3 标准库。
+"""
+
+import json
+import os
+import sys
+import urllib.error
+import urllib.request
+
+CACHE_DIR = os.path.join(os.path.expanduser("~"), ".aws", "sso", "cache")
+TOKEN_FILE = os.path.join(CACHE_DIR, "kiro-auth-token.json")
+
+
+def load_credentials(path):
+    with open(path, encoding="utf-8") as f:
+        token = json.load(f)
+
+    refresh_token = token.get("refreshToken")
+    if not refresh_token:
+        sys.exit(f"{path} 中没有 refreshToken，请先在 Kiro IDE 中登录")
+
+    credentials = {
+        "refreshToken": refresh_token,
+        "authMethod": "social",
+    }
+    if token.get("authMethod", "").lower() == "idc":
+        # IdC 登录：clientId / clientSecret 保存在以 clientIdHash 命名的注册文件中
+        client_id_hash = token.get("clientIdHash")
+        if not client_id_hash:
+            sys.exit(f"{path} 中缺少 clientIdHash，无法定位 IdC 客户端注册信息")
+        registration_path = os.path.join(os.path.dirname(path), f"{client_id_hash}.json")
+        with open(registration_path, encoding="utf-8") as f:
+            registration = json.load(f)
+        credentials.update(
+            authMethod="idc",
+            clientId=registration.get("clientId"),
+            clientSecret=registration.get("clientSecret"),
+        )
+    if token.get("region"):
+        credentials["region"] = token["region"]
+    return credentials
+
+
+def upload(url, credentials):
+    request = urllib.request.Request(
+        url,
+        data=json.dumps(credentials).encode("utf-8"),
+        headers={"Content-Type": "application/json"},
+        method="POST",
+    )
+    try:
+        with urllib.request.urlopen(request, timeout=60) as response:
+            return json.load(response)
+    except urllib.error.HTTPError as e:
+        try:
+            message = json.load(e)["error"]["message"]
+        except Exception:
+            message = e.reason
+        sys.exit(f"上传失败（HTTP {e.code}）：{message}")
+    except urllib.error.URLError as e:
+        sys.exit(f"无法连接到 {url}：{e.reason}")
+
+
+def main():
+    args = sys.argv[1:]
+    path = TOKEN_FILE
+    if "--file" in args:
+        index = args.index("--file")
+        if index + 1 >= len(args):
+            sys.exit("--file 需要指定文件路径")
+        path = args[index + 1]
+        del args[index : index + 2]
+    if len(args) != 1:
+        sys.exit(__doc__)
+
+    if not os.path.exists(path):
+        sys.exit(f"未找到 Kiro Token 缓存文件：{path}（可通过 --file 指定）")
+
+    result = upload(args[0], load_credentials(path))
+    print(result.get("message", "上传成功"))
+
+
+if __name__ == "__main__":
+    main()
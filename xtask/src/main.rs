@@ -0,0 +1,381 @@
+//! 发布构建任务（`cargo xtask <命令>`）
+//!
+//! - `dist`：为各平台构建发布二进制，输出到 `dist/`，文件名与 `self-update` 查找的 Release 资产一致，
+//!   并生成 `SHA256SUMS`。Linux 为 musl 静态链接（只启用 rustls），macOS 额外用 `lipo` 合成 universal 二进制。
+//! - `docker`：把 `dist/` 中的 Linux 静态二进制打包为多架构容器镜像（`Dockerfile.release`）。
+//!
+//! 前置条件：已构建 Admin UI（`admin-ui/dist`），并通过 `rustup target add` 安装所需目标。
+
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+use sha2::{Digest, Sha256};
+
+/// 发布目标
+struct Target {
+    /// Rust 编译目标
+    triple: &'static str,
+    /// Release 资产名称（与 `src/update.rs` 的 `platform_asset_name` 一致）
+    asset: &'static str,
+    /// 是否只启用 rustls（不链接系统 OpenSSL，便于静态链接）
+    rustls_only: bool,
+}
+
+const TARGETS: &[Target] = &[
+    Target {
+        triple: "x86_64-unknown-linux-musl",
+        asset: "kiro-rs-Linux-x64",
+        rustls_only: true,
+    },
+    Target {
+        triple: "aarch64-unknown-linux-musl",
+        asset: "kiro-rs-Linux-arm64",
+        rustls_only: true,
+    },
+    Target {
+        triple: "x86_64-apple-darwin",
+        asset: "kiro-rs-macOS-x64",
+        rustls_only: false,
+    },
+    Target {
+        triple: "aarch64-apple-darwin",
+        asset: "kiro-rs-macOS-arm64",
+        rustls_only: false,
+    },
+    Target {
+        triple: "x86_64-pc-windows-msvc",
+        asset: "kiro-rs-Windows-x64.exe",
+        rustls_only: false,
+    },
+];
+
+/// 只启用 rustls 时保留的其他默认特性（与 Cargo.toml 的 default 特性保持一致，去掉 native-tls）
+const RUSTLS_ONLY_FEATURES: &str = "admin-ui,mcp,compression";
+
+/// macOS universal 二进制的伪目标名称（由两个 macOS 目标合成）
+const UNIVERSAL_DARWIN: &str = "universal-apple-darwin";
+
+/// macOS universal 二进制的资产名称
+const UNIVERSAL_ASSET: &str = "kiro-rs-macOS-universal";
+
+/// 容器镜像中各架构对应的 Linux 资产
+const DOCKER_PLATFORMS: &[(&str, &str)] = &[
+    ("amd64", "kiro-rs-Linux-x64"),
+    ("arm64", "kiro-rs-Linux-arm64"),
+];
+
+const USAGE: &str = "\
+用法:
+  cargo xtask dist [--target <triple>]... [--out <dir>] [--zigbuild]
+      构建发布二进制并生成 SHA256SUMS（默认构建当前系统对应的全部目标）
+      可用目标: x86_64-unknown-linux-musl, aarch64-unknown-linux-musl,
+                x86_64-apple-darwin, aarch64-apple-darwin, universal-apple-darwin,
+                x86_64-pc-windows-msvc
+      --zigbuild  使用 cargo-zigbuild 交叉编译（如在 x86_64 上构建 aarch64 musl）
+  cargo xtask docker --tag <image:tag>... [--out <dir>] [--push]
+      用 dist 目录中的 Linux 静态二进制构建 linux/amd64 + linux/arm64 镜像";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("dist") => dist(&args[1..]),
+        Some("docker") => docker(&args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("错误: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, String>;
+
+/// 解析后的命令行选项
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    targets: Vec<String>,
+    tags: Vec<String>,
+    out: Option<PathBuf>,
+    zigbuild: bool,
+    push: bool,
+}
+
+fn parse_options(args: &[String]) -> Result<Options> {
+    let mut options = Options::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("{} 缺少参数值", arg))
+        };
+        match arg.as_str() {
+            "--target" => options.targets.push(value()?),
+            "--tag" => options.tags.push(value()?),
+            "--out" => options.out = Some(PathBuf::from(value()?)),
+            "--zigbuild" => options.zigbuild = true,
+            "--push" => options.push = true,
+            _ => return Err(format!("未知参数: {}\n\n{}", arg, USAGE)),
+        }
+    }
+    Ok(options)
+}
+
+/// 仓库根目录（xtask 位于其下一级）
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask 应位于仓库根目录下")
+        .to_path_buf()
+}
+
+/// 当前系统默认构建的目标
+fn default_targets() -> Vec<String> {
+    let targets: &[&str] = match env::consts::OS {
+        "linux" => &["x86_64-unknown-linux-musl", "aarch64-unknown-linux-musl"],
+        "macos" => &[UNIVERSAL_DARWIN],
+        "windows" => &["x86_64-pc-windows-msvc"],
+        _ => &[],
+    };
+    targets.iter().map(|t| t.to_string()).collect()
+}
+
+fn find_target(triple: &str) -> Result<&'static Target> {
+    TARGETS
+        .iter()
+        .find(|t| t.triple == triple)
+        .ok_or_else(|| format!("不支持的目标: {}", triple))
+}
+
+fn dist(args: &[String]) -> Result<()> {
+    let options = parse_options(args)?;
+    let root = workspace_root();
+    if !root.join("admin-ui/dist").is_dir() {
+        return Err(
+            "未找到 admin-ui/dist，请先执行 cd admin-ui && pnpm install && pnpm build".into(),
+        );
+    }
+    let out = options.out.clone().unwrap_or_else(|| root.join("dist"));
+    fs::create_dir_all(&out).map_err(|e| format!("创建 {} 失败: {}", out.display(), e))?;
+
+    let targets = if options.targets.is_empty() {
+        default_targets()
+    } else {
+        options.targets.clone()
+    };
+    if targets.is_empty() {
+        return Err("当前系统没有默认目标，请通过 --target 指定".into());
+    }
+
+    for triple in &targets {
+        if triple == UNIVERSAL_DARWIN {
+            let x64 = build(&root, find_target("x86_64-apple-darwin")?, &options, &out)?;
+            let arm64 = build(&root, find_target("aarch64-apple-darwin")?, &options, &out)?;
+            let universal = out.join(UNIVERSAL_ASSET);
+            run(Command::new("lipo")
+                .arg("-create")
+                .arg("-output")
+                .arg(&universal)
+                .arg(&x64)
+                .arg(&arm64))?;
+            println!("已生成 {}", universal.display());
+        } else {
+            build(&root, find_target(triple)?, &options, &out)?;
+        }
+    }
+
+    let sums = checksums(&out)?;
+    fs::write(out.join("SHA256SUMS"), &sums).map_err(|e| format!("写入 SHA256SUMS 失败: {}", e))?;
+    print!("{}", sums);
+    Ok(())
+}
+
+/// 构建单个目标并复制到输出目录，返回复制后的路径
+fn build(root: &Path, target: &Target, options: &Options, out: &Path) -> Result<PathBuf> {
+    println!("构建 {} ...", target.triple);
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let mut cmd = Command::new(cargo);
+    cmd.current_dir(root)
+        .arg(if options.zigbuild {
+            "zigbuild"
+        } else {
+            "build"
+        })
+        .args([
+            "--release",
+            "--package",
+            "kiro-rs",
+            "--target",
+            target.triple,
+        ]);
+    if target.rustls_only {
+        cmd.args(["--no-default-features", "--features", RUSTLS_ONLY_FEATURES]);
+    }
+    if target.triple.contains("-musl") {
+        // musl 默认静态链接，这里显式声明，避免被外部 RUSTFLAGS 覆盖
+        let flags = env::var("RUSTFLAGS").unwrap_or_default();
+        cmd.env(
+            "RUSTFLAGS",
+            format!("{} -C target-feature=+crt-static", flags).trim(),
+        );
+    }
+    run(&mut cmd)?;
+
+    let binary = if target.triple.contains("windows") {
+        "kiro-rs.exe"
+    } else {
+        "kiro-rs"
+    };
+    let built = root
+        .join("target")
+        .join(target.triple)
+        .join("release")
+        .join(binary);
+    let dest = out.join(target.asset);
+    fs::copy(&built, &dest).map_err(|e| format!("复制 {} 失败: {}", built.display(), e))?;
+    println!("已生成 {}", dest.display());
+    Ok(dest)
+}
+
+/// 为输出目录中的所有 `kiro-rs-*` 资产生成 SHA256SUMS（`sha256sum` 格式，按文件名排序）
+fn checksums(dir: &Path) -> Result<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map_err(|e| format!("读取 {} 失败: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with("kiro-rs-"))
+        .collect();
+    names.sort();
+
+    let mut sums = String::new();
+    for name in names {
+        let digest = sha256_file(&dir.join(&name))?;
+        sums.push_str(&format!("{}  {}\n", digest, name));
+    }
+    Ok(sums)
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file =
+        fs::File::open(path).map_err(|e| format!("打开 {} 失败: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .map_err(|e| format!("读取 {} 失败: {}", path.display(), e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn docker(args: &[String]) -> Result<()> {
+    let options = parse_options(args)?;
+    if options.tags.is_empty() {
+        return Err("请通过 --tag 指定镜像名称，如 --tag ghcr.io/owner/kiro-rs:latest".into());
+    }
+    // docker buildx --load 只支持单平台镜像
+    if !options.push && DOCKER_PLATFORMS.len() > 1 {
+        return Err("多架构镜像需要 --push 推送到镜像仓库".into());
+    }
+    let root = workspace_root();
+    let out = options.out.clone().unwrap_or_else(|| root.join("dist"));
+
+    // 构建上下文只包含各架构的二进制：<context>/<arch>/kiro-rs
+    let context = root.join("target").join("docker-context");
+    let _ = fs::remove_dir_all(&context);
+    let mut platforms = Vec::new();
+    for (arch, asset) in DOCKER_PLATFORMS {
+        let binary = out.join(asset);
+        if !binary.is_file() {
+            return Err(format!(
+                "未找到 {}，请先执行 cargo xtask dist",
+                binary.display()
+            ));
+        }
+        let dir = context.join(arch);
+        fs::create_dir_all(&dir).map_err(|e| format!("创建 {} 失败: {}", dir.display(), e))?;
+        fs::copy(&binary, dir.join("kiro-rs"))
+            .map_err(|e| format!("复制 {} 失败: {}", binary.display(), e))?;
+        platforms.push(format!("linux/{}", arch));
+    }
+
+    let mut cmd = Command::new("docker");
+    cmd.args(["buildx", "build", "--platform", &platforms.join(",")])
+        .arg("--file")
+        .arg(root.join("Dockerfile.release"));
+    for tag in &options.tags {
+        cmd.args(["--tag", tag]);
+    }
+    cmd.arg(if options.push { "--push" } else { "--load" });
+    cmd.arg(&context);
+    run(&mut cmd)
+}
+
+fn run(cmd: &mut Command) -> Result<()> {
+    let status = cmd
+        .status()
+        .map_err(|e| format!("执行 {:?} 失败: {}", cmd.get_program(), e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{:?} 退出: {}", cmd.get_program(), status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_options() {
+        let args: Vec<String> = [
+            "--target",
+            "x86_64-unknown-linux-musl",
+            "--zigbuild",
+            "--out",
+            "out",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let options = parse_options(&args).unwrap();
+        assert_eq!(options.targets, vec!["x86_64-unknown-linux-musl"]);
+        assert_eq!(options.out, Some(PathBuf::from("out")));
+        assert!(options.zigbuild);
+        assert!(!options.push);
+
+        assert!(parse_options(&["--target".to_string()]).is_err());
+        assert!(parse_options(&["--bogus".to_string()]).is_err());
+        assert!(find_target("x86_64-unknown-linux-gnu").is_err());
+    }
+
+    #[test]
+    fn test_checksums() {
+        let dir = env::temp_dir().join(format!("kiro-xtask-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("kiro-rs-Linux-x64"), b"abc").unwrap();
+        fs::write(dir.join("SHA256SUMS"), b"stale").unwrap();
+
+        let sums = checksums(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        // 只包含发布资产，格式与 sha256sum 一致
+        assert_eq!(
+            sums,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  kiro-rs-Linux-x64\n"
+        );
+    }
+}